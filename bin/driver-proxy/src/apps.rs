@@ -0,0 +1,59 @@
+//! Per-application modes from `[apps]` of driver config, switched to when scene application
+//! changes
+//!
+//! Driver has no access to application keys of client API, so key is guessed the same way
+//! SteamVR makes it: `steam.app.<id>` for games started by Steam (including Proton ones, they
+//! inherit `SteamAppId`), `system.generated.<executable>` otherwise
+use std::{fs, path::Path, sync::Mutex};
+
+use tracing::info;
+
+use crate::{config, live::HMD_RESOLUTION};
+
+/// Mode from before the first per-app switch, restored once no profiled app is running
+static BASE_MODE: Mutex<Option<i32>> = Mutex::new(None);
+
+pub fn app_key(pid: u32) -> Option<String> {
+	let proc = Path::new("/proc").join(pid.to_string());
+	if let Ok(environ) = fs::read(proc.join("environ")) {
+		let app_id = environ
+			.split(|&b| b == 0)
+			.find_map(|var| var.strip_prefix(b"SteamAppId="))
+			.and_then(|id| std::str::from_utf8(id).ok())
+			.filter(|id| !id.is_empty() && id != &"0");
+		if let Some(id) = app_id {
+			return Some(format!("steam.app.{id}"));
+		}
+	}
+	let exe = fs::read_link(proc.join("exe")).ok()?;
+	Some(format!(
+		"system.generated.{}",
+		exe.file_name()?.to_string_lossy()
+	))
+}
+
+/// Called on `VREvent_SceneApplicationChanged`, `pid` is 0 when scene application has quit
+pub fn scene_changed(pid: u32) {
+	let key = (pid != 0).then(|| app_key(pid)).flatten();
+	let config = config::current();
+	let profile = key.as_ref().and_then(|key| config.apps.get(key));
+	let mut base = BASE_MODE.lock().expect("lock");
+	match profile {
+		Some(&mode) => {
+			info!(
+				"switching to mode {mode} for {}",
+				key.as_deref().unwrap_or("")
+			);
+			if base.is_none() {
+				*base = Some(HMD_RESOLUTION.get());
+			}
+			HMD_RESOLUTION.set(mode as i32);
+		}
+		None => {
+			if let Some(mode) = base.take() {
+				info!("restoring mode {mode}, {key:?} has no mode configured");
+				HMD_RESOLUTION.set(mode);
+			}
+		}
+	}
+}
@@ -0,0 +1,566 @@
+//! Driver's own config, `~/.config/vivepro2/driver.toml`, watched with inotify, so changes
+//! apply to running session where possible
+//!
+//! ```toml
+//! mode = "2448x1224@120"  # or mode index
+//! brightness = 100
+//! color = [1.0, 0.95, 0.9]
+//! color_temperature = 6000  # kelvin, applied on top of color
+//! lens_server = "/path/to/lens-server.exe"  # only read on start, as wine and wine_prefix
+//! wine = "/usr/bin/wine64"  # instead of proton, found by default
+//! proton = "GE-Proton9-5"  # name or path of build, from steamapps/common or compatibilitytools.d
+//! wine_prefix = "/path/to/prefix"  # see `vivectl setup lens-server`
+//! lens_server_address = "tcp:10.0.0.2:7878"  # or unix:<path>, lens server started elsewhere
+//! lens = "server"  # or "native"/"compare", with native-lens feature, read on start only
+//! serial = "LHR-XXXXXXXX"  # only drive this headset, by tracking board serial or devsn
+//! log_level = "debug"
+//! log_file_level = "debug"  # for vivepro2_driver.txt in SteamVR logs
+//!
+//! # Modes, switched to while application with this key is running
+//! [apps]
+//! "steam.app.620980" = "2448x1224@120"
+//!
+//! # Direct mode display matching, for GPU drivers which report EDID differently
+//! [direct_mode]
+//! vendor = "HVR"  # PNP id
+//! product = 0x0906
+//! edid_file = "/path/to/edid.bin"  # reported for connector instead of the read one, needs root
+//! connector = "card0-DP-1"
+//!
+//! # Dynamic resolution, see `dynamic` module, every key is optional
+//! [dynamic]
+//! down_threshold = 0.1
+//!
+//! # Simulated headset, for development without hardware, see `sim` module
+//! [simulate]
+//! output = "window"  # or "null", for no image at all
+//! window = [1224, 612]
+//! config = "/path/to/vive-config.json"  # instead of built-in one
+//! ```
+//!
+//! Mode, brightness and color are written to SteamVR settings, so they are applied by the same
+//! code which applies `steamvr.vrsettings` changes. Without color keys, calibration stored on the
+//! headset by `vivectl color` is used. TOML is read by [`vive_ipc::toml`], which vived config
+//! shares
+use std::{
+	collections::BTreeMap,
+	ffi::CString,
+	fs, io,
+	os::unix::ffi::OsStrExt,
+	path::{Path, PathBuf},
+	sync::Mutex,
+	thread,
+};
+
+use lens_protocol::Address;
+use once_cell::sync::OnceCell;
+use tracing::{info, level_filters::LevelFilter, warn};
+use vive_display::edid::{parse_pnp_id, EdidId};
+use vive_hid::{combined_gain, Resolution};
+use vive_ipc::{
+	headsets,
+	toml::{self, Value},
+};
+
+use crate::{
+	live::{BRIGHTNESS, HMD_RESOLUTION},
+	log::{set_file_log_level, set_log_level},
+	setting,
+	settings::Setting,
+	Error, Result,
+};
+
+const CONFIG_FILE: &str = "driver.toml";
+const COLOR_GAIN: [Setting<f32>; 3] = [
+	setting!("steamvr", "hmdDisplayColorGainR"),
+	setting!("steamvr", "hmdDisplayColorGainG"),
+	setting!("steamvr", "hmdDisplayColorGainB"),
+];
+
+fn parse_mode(value: &Value) -> Option<u8> {
+	let spec = match value {
+		Value::String(s) => s.clone(),
+		Value::Integer(v) => v.to_string(),
+		_ => return None,
+	};
+	let res: Resolution = spec.parse().ok()?;
+	Some(res as u8)
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct DriverConfig {
+	pub mode: Option<u8>,
+	pub brightness: Option<u8>,
+	/// Per-channel (r, g, b) display gain
+	pub color: Option<[f32; 3]>,
+	pub color_temperature: Option<u32>,
+	pub lens_server: Option<PathBuf>,
+	pub wine: Option<PathBuf>,
+	pub proton: Option<String>,
+	pub wine_prefix: Option<PathBuf>,
+	/// Lens server, which is already listening, instead of starting it
+	pub lens_server_address: Option<Address>,
+	pub lens: LensBackend,
+	/// Tracking board serial, or headset devsn, other headsets are not added to SteamVR
+	pub serial: Option<String>,
+	pub log_level: Option<LevelFilter>,
+	pub log_file_level: Option<LevelFilter>,
+	/// Mode per SteamVR application key
+	pub apps: BTreeMap<String, u8>,
+	pub dynamic: Option<Dynamic>,
+	pub direct_mode: DirectMode,
+	pub simulate: Option<Simulate>,
+}
+
+/// Where lens distortion comes from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LensBackend {
+	#[default]
+	Server,
+	/// Evaluated in driver, see `lens_geometry::NativeLens`
+	Native,
+	/// Lens server is used, native model is evaluated alongside, and the difference is logged
+	Compare,
+}
+
+/// Only read on start, as direct mode display is matched once
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DirectMode {
+	/// Manufacturer id in EDID byte order
+	pub vendor: Option<u16>,
+	pub product: Option<u16>,
+	pub edid_file: Option<PathBuf>,
+	pub connector: Option<String>,
+}
+impl DirectMode {
+	fn parse(table: &BTreeMap<String, Value>) -> Result<Self> {
+		let mut out = Self::default();
+		for (key, value) in table {
+			let invalid = || Error::Config(format!("invalid direct_mode.{key}: {value:?}"));
+			match key.as_str() {
+				"vendor" => {
+					out.vendor = Some(match value {
+						Value::String(s) => parse_pnp_id(s).ok_or_else(invalid)?,
+						Value::Integer(v) => u16::try_from(*v).map_err(|_| invalid())?,
+						_ => return Err(invalid()),
+					})
+				}
+				"product" => match value {
+					Value::Integer(v) => {
+						out.product = Some(u16::try_from(*v).map_err(|_| invalid())?)
+					}
+					_ => return Err(invalid()),
+				},
+				"edid_file" => match value {
+					Value::String(s) => out.edid_file = Some(PathBuf::from(s)),
+					_ => return Err(invalid()),
+				},
+				"connector" => match value {
+					Value::String(s) => out.connector = Some(s.clone()),
+					_ => return Err(invalid()),
+				},
+				_ => warn!("unknown config key: direct_mode.{key}"),
+			}
+		}
+		if out.edid_file.is_some() != out.connector.is_some() {
+			return Err(Error::Config(
+				"direct_mode.edid_file and direct_mode.connector should be set together".to_owned(),
+			));
+		}
+		Ok(out)
+	}
+	/// Display id with overrides applied over the one from tracking board config
+	pub fn edid(&self, config: Option<EdidId>) -> Option<EdidId> {
+		match (config, self.vendor, self.product) {
+			(Some(id), vendor, product) => Some(EdidId {
+				vendor: vendor.unwrap_or(id.vendor),
+				product: product.unwrap_or(id.product),
+			}),
+			(None, Some(vendor), Some(product)) => Some(EdidId { vendor, product }),
+			_ => None,
+		}
+	}
+	pub fn overrides_id(&self) -> bool {
+		self.vendor.is_some() || self.product.is_some()
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SimOutput {
+	/// Compositor renders to a desktop window
+	#[default]
+	Window,
+	/// HMD has no display component
+	Null,
+}
+
+/// Only read on start, as HMD is only added once
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simulate {
+	pub output: SimOutput,
+	pub window: (u32, u32),
+	/// Vive config JSON, i.e from `vivectl config dump`
+	pub config: Option<PathBuf>,
+}
+impl Default for Simulate {
+	fn default() -> Self {
+		Self {
+			output: SimOutput::default(),
+			window: (1224, 612),
+			config: None,
+		}
+	}
+}
+impl Simulate {
+	fn parse(table: &BTreeMap<String, Value>) -> Result<Self> {
+		let mut out = Self::default();
+		for (key, value) in table {
+			let invalid = || Error::Config(format!("invalid simulate.{key}: {value:?}"));
+			match key.as_str() {
+				"output" => match value {
+					Value::String(s) if s == "window" => out.output = SimOutput::Window,
+					Value::String(s) if s == "null" => out.output = SimOutput::Null,
+					_ => return Err(invalid()),
+				},
+				"window" => match value {
+					Value::Array(items) => match items.as_slice() {
+						[Value::Integer(w @ 1..), Value::Integer(h @ 1..)] => {
+							out.window = (*w as u32, *h as u32)
+						}
+						_ => return Err(invalid()),
+					},
+					_ => return Err(invalid()),
+				},
+				"config" => match value {
+					Value::String(s) => out.config = Some(PathBuf::from(s)),
+					_ => return Err(invalid()),
+				},
+				_ => warn!("unknown config key: simulate.{key}"),
+			}
+		}
+		Ok(out)
+	}
+}
+
+/// Thresholds are fractions of dropped or reprojected frames
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dynamic {
+	pub down_threshold: f32,
+	pub up_threshold: f32,
+	/// Seconds of missed frames before stepping down
+	pub down_after: u64,
+	/// Seconds of headroom before stepping back up
+	pub up_after: u64,
+	/// Lowest mode to step down to
+	pub min_mode: u8,
+}
+impl Default for Dynamic {
+	fn default() -> Self {
+		Self {
+			down_threshold: 0.1,
+			up_threshold: 0.02,
+			down_after: 5,
+			up_after: 30,
+			min_mode: 0,
+		}
+	}
+}
+impl Dynamic {
+	fn parse(table: &BTreeMap<String, Value>) -> Result<Self> {
+		let mut out = Self::default();
+		for (key, value) in table {
+			let invalid = || Error::Config(format!("invalid dynamic.{key}: {value:?}"));
+			let fraction = || {
+				value
+					.as_f64()
+					.filter(|v| (0.0..=1.0).contains(v))
+					.map(|v| v as f32)
+					.ok_or_else(invalid)
+			};
+			let seconds = || match value {
+				Value::Integer(v @ 0..) => Ok(*v as u64),
+				_ => Err(invalid()),
+			};
+			match key.as_str() {
+				"down_threshold" => out.down_threshold = fraction()?,
+				"up_threshold" => out.up_threshold = fraction()?,
+				"down_after" => out.down_after = seconds()?,
+				"up_after" => out.up_after = seconds()?,
+				"min_mode" => out.min_mode = parse_mode(value).ok_or_else(invalid)?,
+				_ => warn!("unknown config key: dynamic.{key}"),
+			}
+		}
+		if out.up_threshold >= out.down_threshold {
+			return Err(Error::Config(
+				"dynamic.up_threshold should be lower than dynamic.down_threshold".to_owned(),
+			));
+		}
+		Ok(out)
+	}
+}
+impl DriverConfig {
+	fn parse(data: &str) -> Result<Self> {
+		let mut out = Self::default();
+		for (key, value) in toml::parse(data).map_err(Error::Config)? {
+			let invalid = || Error::Config(format!("invalid {key}: {value:?}"));
+			match key.as_str() {
+				"mode" => out.mode = Some(parse_mode(&value).ok_or_else(invalid)?),
+				"brightness" => match value {
+					Value::Integer(v @ 1..=130) => out.brightness = Some(v as u8),
+					_ => return Err(invalid()),
+				},
+				"color" => {
+					let Value::Array(items) = &value else {
+						return Err(invalid());
+					};
+					let gains = items
+						.iter()
+						.map(|v| v.as_f64().map(|v| v as f32))
+						.collect::<Option<Vec<_>>>()
+						.ok_or_else(invalid)?;
+					out.color = Some(gains.try_into().map_err(|_| invalid())?);
+				}
+				"color_temperature" => match value {
+					Value::Integer(v @ 1000..=40000) => out.color_temperature = Some(v as u32),
+					_ => return Err(invalid()),
+				},
+				"lens_server" => match value {
+					Value::String(s) => out.lens_server = Some(PathBuf::from(s)),
+					_ => return Err(invalid()),
+				},
+				"wine" => match value {
+					Value::String(s) => out.wine = Some(PathBuf::from(s)),
+					_ => return Err(invalid()),
+				},
+				"proton" => match value {
+					Value::String(s) => out.proton = Some(s),
+					_ => return Err(invalid()),
+				},
+				"wine_prefix" => match value {
+					Value::String(s) => out.wine_prefix = Some(PathBuf::from(s)),
+					_ => return Err(invalid()),
+				},
+				"lens_server_address" => match &value {
+					Value::String(s) => {
+						out.lens_server_address = Some(s.parse().map_err(|_| invalid())?)
+					}
+					_ => return Err(invalid()),
+				},
+				"lens" => match &value {
+					Value::String(s) if s == "server" => out.lens = LensBackend::Server,
+					Value::String(s) if s == "native" => out.lens = LensBackend::Native,
+					Value::String(s) if s == "compare" => out.lens = LensBackend::Compare,
+					_ => return Err(invalid()),
+				},
+				"serial" => match value {
+					Value::String(s) => out.serial = Some(s),
+					_ => return Err(invalid()),
+				},
+				"log_level" => match &value {
+					Value::String(s) => out.log_level = Some(s.parse().map_err(|_| invalid())?),
+					_ => return Err(invalid()),
+				},
+				"log_file_level" => match &value {
+					Value::String(s) => {
+						out.log_file_level = Some(s.parse().map_err(|_| invalid())?)
+					}
+					_ => return Err(invalid()),
+				},
+				"apps" => {
+					let Value::Table(apps) = &value else {
+						return Err(invalid());
+					};
+					for (app, mode) in apps {
+						let mode = parse_mode(mode).ok_or_else(|| {
+							Error::Config(format!("invalid mode for {app}: {mode:?}"))
+						})?;
+						out.apps.insert(app.clone(), mode);
+					}
+				}
+				"direct_mode" => {
+					let Value::Table(table) = &value else {
+						return Err(invalid());
+					};
+					out.direct_mode = DirectMode::parse(table)?;
+				}
+				"dynamic" => {
+					let Value::Table(table) = &value else {
+						return Err(invalid());
+					};
+					out.dynamic = Some(Dynamic::parse(table)?);
+				}
+				"simulate" => {
+					let Value::Table(table) = &value else {
+						return Err(invalid());
+					};
+					out.simulate = Some(Simulate::parse(table)?);
+				}
+				_ => warn!("unknown config key: {key}"),
+			}
+		}
+		Ok(out)
+	}
+}
+
+pub fn config_path() -> PathBuf {
+	headsets::config_dir().join(CONFIG_FILE)
+}
+
+/// Missing config is the same as empty one
+fn load(path: &Path) -> Result<DriverConfig> {
+	match fs::read_to_string(path) {
+		Ok(data) => DriverConfig::parse(&data),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(DriverConfig::default()),
+		Err(e) => Err(Error::Config(format!("failed to read {path:?}: {e}"))),
+	}
+}
+
+static CURRENT: Mutex<Option<DriverConfig>> = Mutex::new(None);
+static WATCHING: OnceCell<()> = OnceCell::new();
+
+/// Calibration stored on the headset, used when config has no color of its own
+static DEVICE_COLOR: Mutex<Option<[f32; 3]>> = Mutex::new(None);
+
+fn set_color_gain(gain: [f32; 3]) {
+	for (setting, value) in COLOR_GAIN.iter().zip(gain) {
+		setting.set(value);
+	}
+}
+
+/// Called once headset user config is read
+pub fn set_device_color(gain: Option<[f32; 3]>) {
+	*DEVICE_COLOR.lock().expect("lock") = gain;
+	let config = current();
+	if config.color.is_some() || config.color_temperature.is_some() {
+		return;
+	}
+	if let Some(gain) = gain {
+		info!("applying color calibration stored on headset: {gain:?}");
+		set_color_gain(gain);
+	}
+}
+
+pub fn current() -> DriverConfig {
+	CURRENT.lock().expect("lock").clone().unwrap_or_default()
+}
+
+/// Applies only changed values, so setting changed by other means isn't reverted on unrelated edit
+fn apply(old: &DriverConfig, new: &DriverConfig) {
+	if new.mode != old.mode {
+		if let Some(mode) = new.mode {
+			HMD_RESOLUTION.set(mode as i32);
+		}
+	}
+	if new.brightness != old.brightness {
+		if let Some(brightness) = new.brightness {
+			BRIGHTNESS.set(brightness as i32);
+		}
+	}
+	if (new.color, new.color_temperature) != (old.color, old.color_temperature) {
+		let gain = combined_gain(new.color, new.color_temperature)
+			.or(*DEVICE_COLOR.lock().expect("lock"))
+			.unwrap_or([1.0; 3]);
+		set_color_gain(gain);
+	}
+	if new.log_level != old.log_level {
+		set_log_level(new.log_level.unwrap_or(LevelFilter::INFO));
+	}
+	if new.log_file_level != old.log_file_level {
+		set_file_log_level(new.log_file_level.unwrap_or(LevelFilter::INFO));
+	}
+}
+
+fn reload(path: &Path) {
+	let new = match load(path) {
+		Ok(c) => c,
+		Err(e) => {
+			warn!("keeping previous config: {e}");
+			return;
+		}
+	};
+	let mut current = CURRENT.lock().expect("lock");
+	let old = current.clone().unwrap_or_default();
+	if old != new {
+		info!("config changed: {new:?}");
+		apply(&old, &new);
+		if current.is_some()
+			&& (
+				&old.lens_server,
+				&old.wine,
+				&old.proton,
+				&old.wine_prefix,
+				&old.lens_server_address,
+				old.lens,
+			) != (
+				&new.lens_server,
+				&new.wine,
+				&new.proton,
+				&new.wine_prefix,
+				&new.lens_server_address,
+				new.lens,
+			) {
+			info!("lens server change will be applied on SteamVR restart");
+		}
+		if current.is_some() && old.serial != new.serial {
+			info!("headset serial change will be applied on SteamVR restart");
+		}
+	}
+	*current = Some(new);
+}
+
+/// Editors usually replace file instead of writing it, so the directory is watched
+fn watch(dir: &Path) -> io::Result<()> {
+	let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+	if fd < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	fs::create_dir_all(dir)?;
+	let dir_c = CString::new(dir.as_os_str().as_bytes()).expect("path has no \\0");
+	let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_DELETE | libc::IN_MOVED_FROM;
+	if unsafe { libc::inotify_add_watch(fd, dir_c.as_ptr(), mask) } < 0 {
+		let e = io::Error::last_os_error();
+		unsafe { libc::close(fd) };
+		return Err(e);
+	}
+	let path = dir.join(CONFIG_FILE);
+	thread::spawn(move || {
+		let mut buf = vec![0u8; 4096];
+		loop {
+			let len = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+			if len <= 0 {
+				warn!("config watch stopped: {}", io::Error::last_os_error());
+				return;
+			}
+			let mut offset = 0;
+			let mut changed = false;
+			while offset < len as usize {
+				let event = unsafe {
+					std::ptr::read_unaligned(buf.as_ptr().add(offset) as *const libc::inotify_event)
+				};
+				let name_start = offset + std::mem::size_of::<libc::inotify_event>();
+				let name = &buf[name_start..name_start + event.len as usize];
+				let name = &name[..name.iter().position(|&c| c == 0).unwrap_or(name.len())];
+				changed |= name == CONFIG_FILE.as_bytes();
+				offset = name_start + event.len as usize;
+			}
+			if changed {
+				reload(&path);
+			}
+		}
+	});
+	Ok(())
+}
+
+/// Loads config and starts watching it, only first call per process has effect
+pub fn init() {
+	if WATCHING.set(()).is_err() {
+		return;
+	}
+	let path = config_path();
+	reload(&path);
+	if let Some(dir) = path.parent() {
+		if let Err(e) = watch(dir) {
+			warn!("failed to watch {dir:?}, config changes need SteamVR restart: {e}");
+		}
+	}
+}
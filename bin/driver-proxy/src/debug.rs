@@ -0,0 +1,82 @@
+//! `DebugRequest` handler of the HMD, commands are described in [`vive_ipc::debug`]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use vive_hid::Resolution;
+use vive_ipc::debug::{self, DebugRequest, DebugResponse};
+
+use crate::{
+	driver_host::frame_timing,
+	live::{self, HMD_RESOLUTION},
+	setting,
+	settings::Setting,
+};
+
+/// SteamVR performance graph, drawn in headset
+pub const PERF_GRAPH: Setting<bool> = setting!("perfcheck", "perfGraphInHMD");
+
+pub struct Counters {
+	pub mode_switches: AtomicU64,
+	pub failed_mode_switches: AtomicU64,
+	pub standby_entries: AtomicU64,
+	pub ipd_changes: AtomicU64,
+	pub debug_requests: AtomicU64,
+}
+pub static COUNTERS: Counters = Counters {
+	mode_switches: AtomicU64::new(0),
+	failed_mode_switches: AtomicU64::new(0),
+	standby_entries: AtomicU64::new(0),
+	ipd_changes: AtomicU64::new(0),
+	debug_requests: AtomicU64::new(0),
+};
+
+pub fn count(counter: &AtomicU64) {
+	counter.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn handle(request: DebugRequest) -> DebugResponse {
+	count(&COUNTERS.debug_requests);
+	match request {
+		DebugRequest::State => match live::debug_state() {
+			Some(state) => DebugResponse::State(state),
+			None => DebugResponse::Error {
+				message: "headset is not initialized yet".to_owned(),
+			},
+		},
+		DebugRequest::Counters => {
+			let get = |c: &AtomicU64| c.load(Ordering::Relaxed);
+			DebugResponse::Counters(debug::Counters {
+				mode_switches: get(&COUNTERS.mode_switches),
+				failed_mode_switches: get(&COUNTERS.failed_mode_switches),
+				standby_entries: get(&COUNTERS.standby_entries),
+				ipd_changes: get(&COUNTERS.ipd_changes),
+				debug_requests: get(&COUNTERS.debug_requests),
+				frame_timing: frame_timing(),
+			})
+		}
+		DebugRequest::SetMode { mode } => {
+			if Resolution::try_from(mode).is_err() {
+				return DebugResponse::Error {
+					message: format!("unknown mode {mode}"),
+				};
+			}
+			// Switched by the setting watcher
+			HMD_RESOLUTION.set(mode as i32);
+			DebugResponse::Ok
+		}
+		DebugRequest::Hud { enabled } => {
+			let enabled = enabled.unwrap_or_else(|| !PERF_GRAPH.get());
+			PERF_GRAPH.set(enabled);
+			DebugResponse::Hud { enabled }
+		}
+		DebugRequest::RecentReports => match live::recent_reports() {
+			Ok(reports) => DebugResponse::RecentReports { reports },
+			Err(message) => DebugResponse::Error { message },
+		},
+	}
+}
+
+/// As received from SteamVR, `None` if request is not ours
+pub fn handle_raw(request: &str) -> Option<String> {
+	let request = serde_json::from_str(request).ok()?;
+	Some(serde_json::to_string(&handle(request)).expect("response is serializable"))
+}
@@ -0,0 +1,58 @@
+//! Messages for problems which leave headset unusable, nobody reads vrserver logs when HMD
+//! shows black screen, and notifications for expected interruptions
+//!
+//! Drivers have no access to SteamVR notifications (they are a client API), so desktop ones
+//! are used, they are visible in-headset through desktop view of the dashboard
+use std::{env::var_os, ffi::OsString, process::Command, thread};
+
+use tracing::{info, warn};
+
+#[derive(Clone, Copy)]
+pub enum Kind {
+	Error,
+	Warning,
+}
+
+/// Shown with zenity Steam ships, doesn't block the caller
+pub fn show(kind: Kind, text: String) {
+	let zenity = var_os("STEAM_ZENITY").unwrap_or_else(|| OsString::from("zenity"));
+	let mut cmd = Command::new(zenity);
+	cmd.arg("--no-wrap")
+		.arg(match kind {
+			Kind::Error => "--error",
+			Kind::Warning => "--warning",
+		})
+		.arg("--text")
+		.arg(text);
+	thread::spawn(
+		move || match cmd.spawn().and_then(|p| p.wait_with_output()) {
+			Ok(v) => {
+				info!(
+					"zenity finished: {}\n{:?}\n{:?}",
+					v.status, v.stdout, v.stderr
+				)
+			}
+			Err(e) => {
+				warn!("fatal error remains unnoticed: {e}")
+			}
+		},
+	);
+}
+
+/// Shown with notify-send, replaces previous notification with the same `tag`
+pub fn notify(tag: &str, summary: &str, body: String) {
+	let mut cmd = Command::new("notify-send");
+	cmd.arg("--app-name=SteamVR")
+		.arg("--icon=input-gaming")
+		.arg(format!(
+			"--hint=string:x-canonical-private-synchronous:{tag}"
+		))
+		.arg("--hint=string:x-dunst-stack-tag:vivepro2")
+		.arg(summary)
+		.arg(body);
+	thread::spawn(move || {
+		if let Err(e) = cmd.status() {
+			warn!("failed to show notification: {e}");
+		}
+	});
+}
@@ -0,0 +1,128 @@
+//! Display geometry and timings, reported by display component and HMD properties, derived from
+//! headset config and the active mode
+use openvr::ETrackedDeviceProperty;
+use vive_hid::{Mode, Resolution, ViveConfig};
+
+use crate::{
+	openvr::EVREye,
+	settings::{Property, PropertyValue},
+};
+
+/// User tuning of reprojection prediction, 0 keeps values derived from config
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct TimingOverride {
+	pub vsync_to_photons: f32,
+	pub photons_to_vblank: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct Display {
+	pub mode: Mode,
+	/// Factory recommended per-eye render size, for the native panel resolution
+	eye_target: (u32, u32),
+	/// Older configs have no value
+	photons_to_vblank: Option<f32>,
+	pub timing: TimingOverride,
+}
+impl Display {
+	pub fn new(config: &ViveConfig, mode: Mode) -> Self {
+		Self {
+			mode,
+			eye_target: (
+				config.device.eye_target_width_in_pixels,
+				config.device.eye_target_height_in_pixels,
+			),
+			photons_to_vblank: config.seconds_from_photons_to_vblank.map(|s| s as f32),
+			timing: TimingOverride::default(),
+		}
+	}
+	/// Same panel, other mode
+	pub fn with_mode(self, mode: Mode) -> Self {
+		Self { mode, ..self }
+	}
+	pub fn with_timing(self, timing: TimingOverride) -> Self {
+		Self { timing, ..self }
+	}
+
+	pub fn window_size(&self) -> (u32, u32) {
+		(self.mode.width, self.mode.height)
+	}
+	/// Both eyes are side by side, left first: x, y, width, height
+	pub fn eye_viewport(&self, eye: EVREye) -> (u32, u32, u32, u32) {
+		let eye_width = self.mode.width / 2;
+		let x = match eye {
+			EVREye::Eye_Left => 0,
+			EVREye::Eye_Right => eye_width,
+		};
+		(x, 0, eye_width, self.mode.height)
+	}
+	/// Per eye, factory target is scaled with the mode, as it is only right for the native one
+	pub fn render_target_size(&self) -> (u32, u32) {
+		let (width, height) = self.eye_target;
+		if width == 0 || height == 0 {
+			let (_, _, width, height) = self.eye_viewport(EVREye::Eye_Left);
+			return (width, height);
+		}
+		let native = Resolution::ALL
+			.iter()
+			.map(|r| r.mode().height)
+			.max()
+			.expect("there are modes");
+		let scale = self.mode.height as f32 / native as f32;
+		(
+			(width as f32 * scale).round() as u32,
+			(height as f32 * scale).round() as u32,
+		)
+	}
+	pub fn seconds_from_vsync_to_photons(&self) -> f32 {
+		if self.timing.vsync_to_photons > 0.0 {
+			return self.timing.vsync_to_photons;
+		}
+		1.0 / self.mode.frame_rate + self.mode.extra_photon_vsync
+	}
+	pub fn seconds_from_photons_to_vblank(&self) -> Option<f32> {
+		if self.timing.photons_to_vblank > 0.0 {
+			return Some(self.timing.photons_to_vblank);
+		}
+		self.photons_to_vblank
+	}
+	/// Rates of modes with the same resolution, current one first
+	pub fn available_frame_rates(&self) -> Vec<f32> {
+		let mut rates = vec![self.mode.frame_rate];
+		rates.extend(
+			Resolution::ALL
+				.map(Resolution::mode)
+				.into_iter()
+				.filter(|m| m.width == self.mode.width && m.height == self.mode.height)
+				.map(|m| m.frame_rate)
+				.filter(|&r| r != self.mode.frame_rate),
+		);
+		rates
+	}
+
+	/// Properties, which change with the mode
+	pub fn properties(&self) -> Vec<Property> {
+		let mut out = vec![
+			Property::new(
+				ETrackedDeviceProperty::Prop_DisplayFrequency_Float,
+				PropertyValue::Float(self.mode.frame_rate),
+			),
+			Property::new(
+				ETrackedDeviceProperty::Prop_SecondsFromVsyncToPhotons_Float,
+				PropertyValue::Float(self.seconds_from_vsync_to_photons()),
+			),
+			Property::new(
+				ETrackedDeviceProperty::Prop_DisplayAvailableFrameRates_Float_Array,
+				PropertyValue::FloatArray(self.available_frame_rates()),
+			),
+		];
+		// Older configs have no value, lighthouse driver default is kept then
+		if let Some(photons_to_vblank) = self.seconds_from_photons_to_vblank() {
+			out.push(Property::new(
+				ETrackedDeviceProperty::Prop_SecondsFromPhotonsToVblank_Float,
+				PropertyValue::Float(photons_to_vblank),
+			));
+		}
+		out
+	}
+}
@@ -1,18 +1,34 @@
+//! Camera component of the lighthouse driver, with intrinsics taken from tracking board config
+//!
+//! Frames are still delivered by the lighthouse driver itself, it reads them from UVC device
 use std::ffi::c_void;
 
-use cppvtbl::VtableRef;
+use cppvtbl::{impl_vtables, HasVtable, VtableRef, WithVtables};
 use openvr::{
-	ECameraCompatibilityMode, ECameraVideoStreamFormat, EVRDistortionFunctionType,
-	EVRTrackedCameraFrameType, HmdMatrix44_t, HmdVector2_t, ICameraVideoSinkCallbackVtable,
-	IVRCameraComponent, IVRCameraComponentVtable,
+	k_unMaxDistortionFunctionParameters, CameraVideoStreamFrame_t, ECameraCompatibilityMode,
+	ECameraVideoStreamFormat, EVRDistortionFunctionType, EVRTrackedCameraFrameType, HmdMatrix44_t,
+	HmdVector2_t, ICameraVideoSinkCallbackVtable, IVRCameraComponent, IVRCameraComponentVtable,
 };
 use vive_hid::{ConfigCamera, DistortType};
 
+#[impl_vtables(IVRCameraComponent)]
 struct HmdCamera {
 	real: &'static VtableRef<IVRCameraComponentVtable>,
 	cameras: Vec<ConfigCamera>,
 }
 
+/// `real` is the component returned by lighthouse driver, returned as is if it is missing
+pub fn wrap(real: *mut c_void, cameras: Vec<ConfigCamera>) -> *mut c_void {
+	if real.is_null() || cameras.is_empty() {
+		return real;
+	}
+	let camera = Box::leak(Box::new(WithVtables::new(HmdCamera {
+		real: unsafe { VtableRef::from_raw(real as *const _) },
+		cameras,
+	})));
+	VtableRef::into_raw_mut(HasVtable::<IVRCameraComponentVtable>::get_mut(camera)) as *mut _
+}
+
 impl IVRCameraComponent for HmdCamera {
 	fn GetCameraFrameDimensions(
 		&self,
@@ -156,18 +172,34 @@ impl IVRCameraComponent for HmdCamera {
 		peDistortionType: *mut EVRDistortionFunctionType,
 		rCoefficients: *mut f64,
 	) -> bool {
-		let camera = if let Some(camera) = self.cameras.get(nCameraIndex as usize) {
-			camera
-		} else {
-			tracing::warn!("unknown camera, fallback");
-			return self.real.GetCameraIntrinsics(
-				nCameraIndex,
-				eFrameType,
-				pFocalLength,
-				pCenter,
-				peDistortionType,
-				rCoefficients,
-			);
+		let camera = match self.cameras.get(nCameraIndex as usize) {
+			// Config describes raw sensor image only
+			Some(camera)
+				if eFrameType == EVRTrackedCameraFrameType::VRTrackedCameraFrameType_Distorted =>
+			{
+				camera
+			}
+			Some(_) => {
+				return self.real.GetCameraIntrinsics(
+					nCameraIndex,
+					eFrameType,
+					pFocalLength,
+					pCenter,
+					peDistortionType,
+					rCoefficients,
+				)
+			}
+			None => {
+				tracing::warn!("unknown camera, fallback");
+				return self.real.GetCameraIntrinsics(
+					nCameraIndex,
+					eFrameType,
+					pFocalLength,
+					pCenter,
+					peDistortionType,
+					rCoefficients,
+				);
+			}
 		};
 		unsafe {
 			*pFocalLength = HmdVector2_t {
@@ -184,11 +216,13 @@ impl IVRCameraComponent for HmdCamera {
 					EVRDistortionFunctionType::VRDistortionFunctionType_FTheta
 				}
 			};
-			let slice = std::slice::from_raw_parts_mut(
-				rCoefficients,
-				camera.intrinsics.distort.coeffs.len(),
-			);
-			slice.copy_from_slice(&camera.intrinsics.distort.coeffs);
+			// Buffer is sized by SteamVR
+			let coeffs = &camera.intrinsics.distort.coeffs;
+			let len = coeffs
+				.len()
+				.min(k_unMaxDistortionFunctionParameters as usize);
+			let slice = std::slice::from_raw_parts_mut(rCoefficients, len);
+			slice.copy_from_slice(&coeffs[..len]);
 		};
 		return true;
 	}
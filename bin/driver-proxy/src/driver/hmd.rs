@@ -1,29 +1,46 @@
 use std::{
+	cell::RefCell,
 	ffi::{c_void, CStr},
 	os::raw::c_char,
 	rc::Rc,
+	sync::{Arc, Mutex},
 };
 
 use crate::{
+	camera, debug, dialog,
+	display::Display,
 	driver_context::{self, DRIVER_CONTEXT},
-	settings::{set_properties, Property, PropertyValue, PROPERTIES},
+	input, live,
+	settings::{
+		get_bool_property, set_hidden_area, set_properties, Property, PropertyValue, PROPERTIES,
+	},
 	Result,
 };
 use cppvtbl::{impl_vtables, HasVtable, VtableRef, WithVtables};
-use lens_protocol::{Eye, LensClient};
+use lens_client::{AsyncClient, Pending};
+use lens_geometry::{hidden_area_mesh, DistortionMesh, LensIntrinsics};
+use lens_protocol::{Eye, LeftRightTopBottom, LensClient};
 use openvr::{
 	k_unFloatPropertyTag, EPropertyWriteType, ETrackedDeviceProperty, ETrackedPropertyError,
 	HmdVector2_t, IVRProperties, PropertyWrite_t,
 };
-use tracing::{error, info, instrument};
-use vive_hid::{Mode, ViveConfig, ViveDevice};
+use tracing::{error, info, instrument, warn};
+use vive_display::edid::EdidId;
+use vive_hid::{ConfigCamera, ViveConfig, ViveDevice};
 
 use crate::openvr::{
-	DistortionCoordinates_t, DriverPose_t, EVREye, EVRInitError, ITrackedDeviceServerDriver,
-	ITrackedDeviceServerDriverVtable, IVRDisplayComponent, IVRDisplayComponentVtable,
-	IVRDisplayComponent_Version,
+	DistortionCoordinates_t, DriverPose_t, EHiddenAreaMeshType, EVREye, EVRInitError,
+	ITrackedDeviceServerDriver, ITrackedDeviceServerDriverVtable, IVRCameraComponent_Version,
+	IVRDisplayComponent, IVRDisplayComponentVtable, IVRDisplayComponent_Version,
 };
 
+/// Cells per side of precomputed distortion, dense enough for interpolation to be invisible
+const DISTORTION_GRID: usize = 128;
+/// Smaller IPD changes don't move the image enough to be seen
+const DISTORTION_IPD_STEP: f32 = 0.002;
+/// Hidden area is a triangle ring, so it only needs enough segments for the circle to look round
+const HIDDEN_AREA_SEGMENTS: usize = 64;
+
 fn map_eye(eye: EVREye) -> Eye {
 	match eye {
 		EVREye::Eye_Left => Eye::Left,
@@ -31,19 +48,150 @@ fn map_eye(eye: EVREye) -> Eye {
 	}
 }
 
+/// Both eyes, when lens config carries precomputed values
+fn lens_intrinsics(config: &ViveConfig) -> Option<[LensIntrinsics; 2]> {
+	let correction = &config.inhouse_lens_correction;
+	Some([
+		LensIntrinsics::from_config(correction, Eye::Left)?,
+		LensIntrinsics::from_config(correction, Eye::Right)?,
+	])
+}
+
+/// Raw projection, as reported to SteamVR
+///
+/// Computed from lens config directly when possible, lens server is only asked for configs
+/// without precomputed intrinsics
+fn projection(
+	intrinsics: Option<&[LensIntrinsics; 2]>,
+	lens: &dyn LensClient,
+	eye: EVREye,
+) -> Result<LeftRightTopBottom> {
+	let (result, inverse) = match intrinsics {
+		Some(intrinsics) => {
+			let result = intrinsics[map_eye(eye) as usize].projection_raw();
+			// Same check lens server client does
+			let inverse = result.top > result.bottom;
+			(result, inverse)
+		}
+		None => (lens.project(map_eye(eye))?, lens.matrix_needs_inversion()?),
+	};
+	Ok(if inverse {
+		LeftRightTopBottom {
+			top: result.bottom,
+			bottom: result.top,
+			..result
+		}
+	} else {
+		result
+	})
+}
+
+struct Precomputed {
+	inverse: bool,
+	meshes: [DistortionMesh; 2],
+}
+
+struct MeshState {
+	/// `None` if that has failed, and every point is requested separately
+	ready: Option<Rc<Precomputed>>,
+	/// Only the first computation is waited for, the old mesh is used during later ones
+	waited: bool,
+	pending: Option<Pending<Precomputed>>,
+	/// Of the latest requested computation
+	ipd: Option<f32>,
+}
+
+/// Both eyes are requested from lens in one batch as soon as it is started, so it is computed
+/// while the rest of activation goes on, then interpolated
+///
+/// Mesh is requested again for the new IPD after the knob moves far enough, lens may have it
+/// cached. Compositor only sees it once it asks for distortion again
+pub struct Meshes {
+	lens: Rc<AsyncClient>,
+	state: RefCell<MeshState>,
+}
+impl Meshes {
+	pub fn request(lens: Rc<AsyncClient>, ipd: Option<f32>) -> Self {
+		let pending = Self::compute(&lens, None);
+		Self {
+			lens,
+			state: RefCell::new(MeshState {
+				ready: None,
+				waited: false,
+				pending: Some(pending),
+				ipd,
+			}),
+		}
+	}
+	fn compute(lens: &AsyncClient, ipd: Option<f32>) -> Pending<Precomputed> {
+		lens.request(move |lens| {
+			if let Some(ipd) = ipd {
+				lens.set_ipd(ipd)?;
+			}
+			Ok(Precomputed {
+				inverse: lens.matrix_needs_inversion()?,
+				meshes: DistortionMesh::compute_both(lens, DISTORTION_GRID)?,
+			})
+		})
+	}
+	fn get(&self) -> Option<Rc<Precomputed>> {
+		let mut state = self.state.borrow_mut();
+		let res = if !state.waited {
+			state.waited = true;
+			Some(state.pending.take().expect("pending until waited").wait())
+		} else {
+			state.pending.as_mut().and_then(|p| p.try_wait())
+		};
+		match res {
+			Some(Ok(precomputed)) => {
+				state.pending = None;
+				state.ready = Some(Rc::new(precomputed));
+			}
+			Some(Err(e)) => {
+				state.pending = None;
+				if state.ready.is_none() {
+					warn!("failed to compute distortion mesh, requesting every point: {e}");
+				} else {
+					warn!("failed to recompute distortion mesh, keeping the old one: {e}");
+				}
+			}
+			None => {}
+		}
+		if let (None, Some(ipd)) = (&state.pending, live::ipd()) {
+			if state
+				.ipd
+				.map(|old| (ipd - old).abs() >= DISTORTION_IPD_STEP)
+				.unwrap_or(true)
+			{
+				info!("recomputing distortion for {:.1}mm ipd", ipd * 1000.0);
+				state.ipd = Some(ipd);
+				state.pending = Some(Self::compute(&self.lens, Some(ipd)));
+			}
+		}
+		state.ready.clone()
+	}
+}
+
 #[impl_vtables(IVRDisplayComponent)]
 struct HmdDisplay {
 	// steam: Rc<SteamDevice>,
 	// vive: Rc<ViveDevice>,
-	lens: Rc<dyn LensClient>,
+	lens: Rc<AsyncClient>,
+	meshes: Rc<Meshes>,
+	intrinsics: Option<[LensIntrinsics; 2]>,
 	real: &'static VtableRef<IVRDisplayComponentVtable>,
-	mode: Mode,
+	/// Changes on live mode switch
+	display: Arc<Mutex<Display>>,
 }
 
 impl IVRDisplayComponent for HmdDisplay {
 	#[instrument(skip(self))]
 	fn GetWindowBounds(&self, pnX: *mut i32, pnY: *mut i32, pnWidth: *mut u32, pnHeight: *mut u32) {
-		let Mode { width, height, .. } = self.mode;
+		if vive_hid::sim::enabled() {
+			// Desktop window has its own size
+			return self.real.GetWindowBounds(pnX, pnY, pnWidth, pnHeight);
+		}
+		let (width, height) = self.display.lock().expect("lock").window_size();
 		unsafe {
 			*pnX = 0;
 			*pnY = 0;
@@ -61,7 +209,7 @@ impl IVRDisplayComponent for HmdDisplay {
 	}
 
 	fn GetRecommendedRenderTargetSize(&self, pnWidth: *mut u32, pnHeight: *mut u32) {
-		let Mode { width, height, .. } = self.mode;
+		let (width, height) = self.display.lock().expect("lock").render_target_size();
 		unsafe {
 			*pnWidth = width;
 			*pnHeight = height;
@@ -77,23 +225,18 @@ impl IVRDisplayComponent for HmdDisplay {
 		pnWidth: *mut u32,
 		pnHeight: *mut u32,
 	) {
-		// let err: Result<()> = try {
-		let Mode { width, height, .. } = self.mode;
+		if vive_hid::sim::enabled() {
+			return self
+				.real
+				.GetEyeOutputViewport(eEye, pnX, pnY, pnWidth, pnHeight);
+		}
+		let (x, y, width, height) = self.display.lock().expect("lock").eye_viewport(eEye);
 		unsafe {
-			*pnX = if eEye == EVREye::Eye_Left {
-				0
-			} else {
-				width / 2
-			};
-			*pnY = 0;
-			*pnWidth = width / 2;
+			*pnX = x;
+			*pnY = y;
+			*pnWidth = width;
 			*pnHeight = height;
 		}
-		// 	return;
-		// };
-		// error!("failed: {}", err.err().unwrap());
-		// self.real
-		// 	.GetEyeOutputViewport(eEye, pnX, pnY, pnWidth, pnHeight)
 	}
 
 	#[instrument(skip(self))]
@@ -106,17 +249,12 @@ impl IVRDisplayComponent for HmdDisplay {
 		pfBottom: *mut f32,
 	) {
 		let err: Result<()> = try {
-			let result = self.lens.project(map_eye(eEye))?;
+			let result = projection(self.intrinsics.as_ref(), &*self.lens, eEye)?;
 			unsafe {
 				*pfLeft = result.left;
 				*pfRight = result.right;
-				if self.lens.matrix_needs_inversion()? {
-					*pfTop = result.bottom;
-					*pfBottom = result.top;
-				} else {
-					*pfTop = result.top;
-					*pfBottom = result.bottom;
-				}
+				*pfTop = result.top;
+				*pfBottom = result.bottom;
 			}
 			return;
 		};
@@ -128,10 +266,17 @@ impl IVRDisplayComponent for HmdDisplay {
 	#[instrument(skip(self))]
 	fn ComputeDistortion(&self, eEye: EVREye, fU: f32, fV: f32) -> DistortionCoordinates_t {
 		let err: Result<()> = try {
-			let inverse = self.lens.matrix_needs_inversion()?;
-			let result = self
-				.lens
-				.distort(map_eye(eEye), [fU, if inverse { 1.0 - fV } else { fV }])?;
+			let precomputed = self.meshes.get();
+			let precomputed = precomputed.as_deref();
+			let inverse = match precomputed {
+				Some(p) => p.inverse,
+				None => self.lens.matrix_needs_inversion()?,
+			};
+			let uv = [fU, if inverse { 1.0 - fV } else { fV }];
+			let result = match precomputed {
+				Some(p) => p.meshes[map_eye(eEye) as usize].sample(uv),
+				None => self.lens.distort(map_eye(eEye), uv)?,
+			};
 			return DistortionCoordinates_t {
 				rfRed: result.red,
 				rfGreen: result.green,
@@ -157,11 +302,16 @@ impl IVRDisplayComponent for HmdDisplay {
 
 #[impl_vtables(ITrackedDeviceServerDriver)]
 pub struct HmdDriver {
-	pub vive: Rc<ViveDevice>,
-	pub vive_config: ViveConfig,
-	pub lens: Rc<dyn LensClient>,
+	pub vive: Arc<Mutex<ViveDevice>>,
+	pub vive_config: Rc<ViveConfig>,
+	/// From tracking board config
+	pub cameras: Vec<ConfigCamera>,
+	pub lens: Rc<AsyncClient>,
+	pub meshes: Rc<Meshes>,
 	pub real: &'static VtableRef<ITrackedDeviceServerDriverVtable>,
-	pub mode: Mode,
+	pub display: Arc<Mutex<Display>>,
+	/// Reported to compositor in place of tracking board config id, for direct mode matching
+	pub edid_override: Option<EdidId>,
 }
 
 impl ITrackedDeviceServerDriver for HmdDriver {
@@ -171,56 +321,93 @@ impl ITrackedDeviceServerDriver for HmdDriver {
 			return res;
 		}
 		let container = PROPERTIES.TrackedDeviceToPropertyContainer(unObjectId);
-
-		set_properties(
+		// Tracking board state, as seen by lighthouse driver. It keeps going with incompatible
+		// firmware, and headset just stays black
+		if get_bool_property(
 			container,
-			vec![
-				Property::new(
-					ETrackedDeviceProperty::Prop_DisplayFrequency_Float,
-					PropertyValue::Float(self.mode.frame_rate),
-				),
-				Property::new(
-					ETrackedDeviceProperty::Prop_DisplaySupportsMultipleFramerates_Bool,
-					PropertyValue::Bool(true),
-				),
-				Property::new(
-					ETrackedDeviceProperty::Prop_SecondsFromVsyncToPhotons_Float,
-					PropertyValue::Float(
-						(1.0 / self.mode.frame_rate) + self.mode.extra_photon_vsync,
-					),
-				),
-				// Property::new(
-				// 	ETrackedDeviceProperty::Prop_MinimumIpdStepMeters_Float,
-				// 	PropertyValue::Float(0.0005),
-				// ),
-				// Property::new(
-				// 	ETrackedDeviceProperty::Prop_UserIpdMeters_Float,
-				// 	// TODO
-				// 	PropertyValue::Float(0.0005),
-				// ),
-				Property::new(
-					ETrackedDeviceProperty::Prop_UserHeadToEyeDepthMeters_Float,
-					PropertyValue::Float(0.015),
-				),
+			ETrackedDeviceProperty::Prop_Firmware_ForceUpdateRequired_Bool,
+		) == Some(true)
+		{
+			error!("headset firmware requires update");
+			dialog::show(dialog::Kind::Error, "VIVE Pro 2 firmware is incompatible with this SteamVR version, and requires update.\n\nUpdate it from SteamVR (Devices > Update Device), or with VIVE Console on Windows.".to_owned());
+			return EVRInitError::VRInitError_Compositor_FirmwareRequiresUpdate;
+		}
+		if get_bool_property(
+			container,
+			ETrackedDeviceProperty::Prop_Firmware_UpdateAvailable_Bool,
+		) == Some(true)
+		{
+			warn!("headset firmware update is available");
+		}
+		live::activated(unObjectId);
+		input::activated(container);
+
+		let mut properties = self.display.lock().expect("lock").properties();
+		properties.extend([
+			Property::new(
+				ETrackedDeviceProperty::Prop_DisplaySupportsMultipleFramerates_Bool,
+				PropertyValue::Bool(true),
+			),
+			// Property::new(
+			// 	ETrackedDeviceProperty::Prop_MinimumIpdStepMeters_Float,
+			// 	PropertyValue::Float(0.0005),
+			// ),
+			Property::new(
+				ETrackedDeviceProperty::Prop_UserHeadToEyeDepthMeters_Float,
+				PropertyValue::Float(0.015),
+			),
+			Property::new(
+				ETrackedDeviceProperty::Prop_DisplaySupportsRuntimeFramerateChange_Bool,
+				PropertyValue::Bool(true),
+			),
+			// Reported by input module, from mainboard status
+			Property::new(
+				ETrackedDeviceProperty::Prop_ContainsProximitySensor_Bool,
+				PropertyValue::Bool(true),
+			),
+		]);
+		if let Some(edid) = self.edid_override {
+			let (vid, pid) = edid.to_config();
+			properties.extend([
 				Property::new(
-					ETrackedDeviceProperty::Prop_DisplayAvailableFrameRates_Float_Array,
-					PropertyValue::FloatArray(if self.mode.frame_rate == 90.0 {
-						vec![90.0, 120.0]
-					} else {
-						vec![120.0, 90.0]
-					}),
+					ETrackedDeviceProperty::Prop_EdidVendorID_Int32,
+					PropertyValue::Int32(vid as i32),
 				),
 				Property::new(
-					ETrackedDeviceProperty::Prop_DisplaySupportsRuntimeFramerateChange_Bool,
-					PropertyValue::Bool(true),
+					ETrackedDeviceProperty::Prop_EdidProductID_Int32,
+					PropertyValue::Int32(pid as i32),
 				),
-			],
-		);
+			]);
+		}
+		set_properties(container, properties);
+		let intrinsics = lens_intrinsics(&self.vive_config);
+		if intrinsics.is_none() {
+			info!("lens config has no intrinsics, projection comes from lens server");
+		}
+		for eye in [EVREye::Eye_Left, EVREye::Eye_Right] {
+			match projection(intrinsics.as_ref(), &*self.lens, eye) {
+				Ok(projection) => {
+					let mesh = hidden_area_mesh(&projection, None, HIDDEN_AREA_SEGMENTS);
+					let mut vertices: Vec<_> =
+						mesh.vertices().map(|v| HmdVector2_t { v }).collect();
+					set_hidden_area(
+						container,
+						eye,
+						EHiddenAreaMeshType::k_eHiddenAreaMesh_Standard,
+						&mut vertices,
+					);
+				}
+				Err(e) => error!("failed to build hidden area mesh: {e}"),
+			}
+		}
 
 		EVRInitError::VRInitError_None
 	}
 
+	/// Everything is kept, as the same HMD may be activated again, or added again by lighthouse
 	fn Deactivate(&self) {
+		live::deactivated();
+		input::deactivated();
 		self.real.Deactivate()
 	}
 
@@ -232,17 +419,26 @@ impl ITrackedDeviceServerDriver for HmdDriver {
 		let name = unsafe { CStr::from_ptr(pchComponentNameAndVersion) };
 		info!("getting {name:?} hmd component");
 		let real = self.real.GetComponent(pchComponentNameAndVersion);
+		if real.is_null() {
+			// Simulated headset without output, or without cameras
+			return real;
+		}
 		if name == unsafe { CStr::from_ptr(IVRDisplayComponent_Version) } {
 			info!("faking display");
 			let display = Box::leak(Box::new(WithVtables::new(HmdDisplay {
 				// steam: self.steam.clone(),
 				// vive: self.vive.clone(),
 				lens: self.lens.clone(),
+				meshes: self.meshes.clone(),
+				intrinsics: lens_intrinsics(&self.vive_config),
 				real: unsafe { VtableRef::from_raw(real as *const _) },
-				mode: self.mode,
+				display: self.display.clone(),
 			})));
 			VtableRef::into_raw_mut(HasVtable::<IVRDisplayComponentVtable>::get_mut(display))
 				as *mut _
+		} else if name == unsafe { CStr::from_ptr(IVRCameraComponent_Version) } {
+			info!("wrapping camera");
+			camera::wrap(real, self.cameras.clone())
 		} else {
 			real
 		}
@@ -254,8 +450,22 @@ impl ITrackedDeviceServerDriver for HmdDriver {
 		pchResponseBuffer: *mut c_char,
 		unResponseBufferSize: u32,
 	) {
-		self.real
-			.DebugRequest(pchRequest, pchResponseBuffer, unResponseBufferSize)
+		let request = unsafe { CStr::from_ptr(pchRequest) }.to_string_lossy();
+		let Some(response) = debug::handle_raw(&request) else {
+			// Lighthouse driver has its own commands
+			return self
+				.real
+				.DebugRequest(pchRequest, pchResponseBuffer, unResponseBufferSize);
+		};
+		if unResponseBufferSize == 0 {
+			return;
+		}
+		// Truncated to fit, with terminating nul
+		let len = response.len().min(unResponseBufferSize as usize - 1);
+		unsafe {
+			std::ptr::copy_nonoverlapping(response.as_ptr().cast(), pchResponseBuffer, len);
+			*pchResponseBuffer.add(len) = 0;
+		}
 	}
 
 	fn GetPose(&self) -> DriverPose_t {
@@ -1,19 +1,22 @@
 use std::{os::raw::c_char, sync::Mutex};
 
 use crate::{
+	config,
 	driver_context::{try_init_driver_context, DRIVER_CONTEXT},
 	factory::{get_hmd_driver_factory, TOKIO_RUNTIME},
+	ipc, live,
 	log::try_init_driver_log,
 	setting,
 	settings::Setting,
-	try_vr,
+	sim, try_vr,
 };
 use cppvtbl::{impl_vtables, HasVtable, VtableRef, WithVtables};
 use once_cell::sync::Lazy;
 use openvr::{IVRDriverLogVtable, IVRDriverLog_Version};
 use tokio::task::LocalSet;
-use tracing::info;
+use tracing::{info, warn};
 use valve_pm::{start_manager, StationCommand, StationControl, StationState};
+use vive_ipc::{Client, Request};
 
 use crate::openvr::{
 	EVRInitError, IServerTrackedDeviceProvider, IServerTrackedDeviceProviderVtable,
@@ -26,11 +29,14 @@ const BASE_STATIONS: Setting<String> = setting!("driver_lighthouse", "PowerManag
 // 1 - sleep
 // 2 - standby
 const POWER_MANAGEMENT: Setting<i32> = setting!("vivepro2", "basestationPowerManagement");
+/// Seconds after SteamVR exit, 0 - right away
+const SLEEP_DELAY: Setting<i32> = setting!("vivepro2", "basestationSleepDelay");
 
 #[impl_vtables(IServerTrackedDeviceProvider)]
 pub struct ServerTrackedProvider {
 	real: &'static VtableRef<IServerTrackedDeviceProviderVtable>,
 	stations: Mutex<Vec<StationControl>>,
+	station_names: Mutex<Vec<String>>,
 	standby_state: Mutex<StationState>,
 }
 impl IServerTrackedDeviceProvider for ServerTrackedProvider {
@@ -45,6 +51,12 @@ impl IServerTrackedDeviceProvider for ServerTrackedProvider {
 			.expect("always able to initialize driver log")
 			.cast();
 		try_init_driver_log(unsafe { &*logger });
+		config::init();
+		if let Some(simulate) = &config::current().simulate {
+			if let Err(e) = vive_hid::sim::enable(simulate.config.as_deref()) {
+				warn!("failed to read simulated headset config: {e}");
+			}
+		}
 
 		let power_management = POWER_MANAGEMENT.get();
 		*self.standby_state.lock().expect("lock") = match power_management {
@@ -65,7 +77,7 @@ impl IServerTrackedDeviceProvider for ServerTrackedProvider {
 				let Ok(manager) = TOKIO_RUNTIME.block_on(start_manager()) else {
 					break 'stations;
 				};
-				let stations: Vec<_> = stations
+				let names: Vec<_> = stations
 					.iter()
 					.filter_map(|line| {
 						let mut parts = line.split(":");
@@ -79,26 +91,64 @@ impl IServerTrackedDeviceProvider for ServerTrackedProvider {
 							None
 						}
 					})
+					.collect();
+				// Woken up with the session
+				let stations: Vec<_> = names
+					.iter()
 					.map(|name| {
-						StationControl::new(manager.clone(), name.to_owned(), StationState::On)
+						StationControl::new(manager.clone(), name.clone(), StationState::On)
 					})
 					.collect();
 				info!("enabled power management for {} stations", stations.len());
 				self.stations.lock().expect("lock").extend(stations);
+				self.station_names.lock().expect("lock").extend(names);
 			}
 		};
 
-		self.real.Init(
+		let res = self.real.Init(
 			VtableRef::into_raw(HasVtable::<IVRDriverContextVtable>::get(&context)) as *const _,
-		)
+		);
+		if res == EVRInitError::VRInitError_None && vive_hid::sim::enabled() {
+			// Only set with environment variable, then defaults are used
+			let simulate = config::current().simulate.clone().unwrap_or_default();
+			sim::add(&simulate);
+		}
+		res
 	}
 
 	fn Cleanup(&self) {
 		self.real.Cleanup();
+		// vived takes the headset over, and expects display to be lit
+		live::standby(false);
+		live::finish();
+		ipc::stop();
+		let mut stations = self.stations.lock().expect("lock");
+		if stations.is_empty() {
+			return;
+		}
+		let delay = SLEEP_DELAY.get();
+		if delay > 0 {
+			let names = self.station_names.lock().expect("lock").clone();
+			let request = Request::SleepBaseStations {
+				names,
+				after: delay as u64,
+			};
+			match Client::connect_default().and_then(|mut c| c.request(&request)) {
+				Ok(_) => {
+					info!("base stations are handed over to vived, to sleep in {delay}s");
+					let _runtime = TOKIO_RUNTIME.enter();
+					for station in stations.drain(..) {
+						station.release();
+					}
+					return;
+				}
+				Err(e) => warn!("vived can't put base stations to sleep later, doing it now: {e}"),
+			}
+		}
 		info!("disconnecting from base stations");
 		let _runtime = TOKIO_RUNTIME.enter();
 		let localset = LocalSet::new();
-		for station in self.stations.lock().expect("lock").drain(..) {
+		for station in stations.drain(..) {
 			localset.spawn_local(station.finish());
 		}
 		TOKIO_RUNTIME.block_on(localset);
@@ -109,7 +159,9 @@ impl IServerTrackedDeviceProvider for ServerTrackedProvider {
 	}
 
 	fn RunFrame(&self) {
-		self.real.RunFrame()
+		self.real.RunFrame();
+		sim::run_frame();
+		live::poll();
 	}
 
 	fn ShouldBlockStandbyMode(&self) -> bool {
@@ -118,6 +170,7 @@ impl IServerTrackedDeviceProvider for ServerTrackedProvider {
 
 	fn EnterStandby(&self) {
 		self.real.EnterStandby();
+		live::standby(true);
 		info!("making station standby");
 		for station in self.stations.lock().expect("lock").iter_mut() {
 			station.send(StationCommand::SetState(
@@ -128,6 +181,7 @@ impl IServerTrackedDeviceProvider for ServerTrackedProvider {
 
 	fn LeaveStandby(&self) {
 		self.real.LeaveStandby();
+		live::standby(false);
 		info!("waking up base stations");
 		for station in self.stations.lock().expect("lock").iter_mut() {
 			station.send(StationCommand::SetState(StationState::On))
@@ -147,6 +201,7 @@ pub static SERVER_TRACKED_DEVICE_PROVIDER: Lazy<WithVtables<ServerTrackedProvide
 		WithVtables::new(ServerTrackedProvider {
 			real: unsafe { VtableRef::from_raw(real as *const _) },
 			stations: Mutex::new(vec![]),
+			station_names: Mutex::new(vec![]),
 			standby_state: Mutex::new(StationState::Unknown),
 		})
 	});
@@ -0,0 +1,120 @@
+//! Dynamic resolution: steps headset down a mode when compositor keeps missing frames, and back
+//! up once there is headroom again, never above the mode which was picked before the first step
+//!
+//! Enabled by `[dynamic]` table of driver config. Down and up steps need the fraction of bad
+//! frames stay past its threshold for `down_after`/`up_after` seconds, and anything in between
+//! resets both timers, so mode doesn't flap on the edge
+use std::{
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use tracing::info;
+use vive_hid::{Mode, Resolution};
+
+use crate::{config, driver_host::frame_timing, live::HMD_RESOLUTION};
+
+/// Frames are bad while headset reconnects after switch, they shouldn't cause another one
+const SETTLE: Duration = Duration::from_secs(10);
+
+struct State {
+	bad_since: Option<Instant>,
+	good_since: Option<Instant>,
+	settle_until: Option<Instant>,
+	/// Mode picked by user (or per-app config), before the first step down
+	ceiling: Option<u8>,
+	/// Last mode set here, other value means mode was changed by user
+	stepped_to: Option<u8>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+	bad_since: None,
+	good_since: None,
+	settle_until: None,
+	ceiling: None,
+	stepped_to: None,
+});
+
+fn cost(mode: &Mode) -> f32 {
+	mode.width as f32 * mode.height as f32 * mode.frame_rate
+}
+
+/// Modes, cheapest to render first
+fn ladder() -> Vec<u8> {
+	let mut modes = Resolution::ALL.map(Resolution::mode);
+	modes.sort_by(|a, b| cost(a).total_cmp(&cost(b)));
+	modes.iter().map(|m| m.id).collect()
+}
+
+fn step(state: &mut State, to: u8, now: Instant) {
+	HMD_RESOLUTION.set(to as i32);
+	state.bad_since = None;
+	state.good_since = None;
+	state.settle_until = Some(now + SETTLE);
+	if state.ceiling == Some(to) {
+		state.ceiling = None;
+		state.stepped_to = None;
+	} else {
+		state.stepped_to = Some(to);
+	}
+}
+
+/// Called once per live settings check, with mode which is active right now
+pub fn poll(current: u8) {
+	let mut state = STATE.lock().expect("lock");
+	let Some(cfg) = config::current().dynamic else {
+		state.ceiling = None;
+		state.stepped_to = None;
+		return;
+	};
+	let now = Instant::now();
+	if state.stepped_to.is_some() && state.stepped_to.map(i32::from) != Some(HMD_RESOLUTION.get()) {
+		// User has picked another mode, it is the new ceiling
+		state.ceiling = None;
+		state.stepped_to = None;
+	}
+	if state.settle_until.map(|t| now < t) == Some(true) {
+		return;
+	}
+	let timing = frame_timing();
+	if timing.frames == 0 {
+		return;
+	}
+	let bad = (timing.dropped_frames + timing.reprojected_frames) as f32 / timing.frames as f32;
+
+	let ladder = ladder();
+	let Some(position) = ladder.iter().position(|&m| m == current) else {
+		return;
+	};
+	let min = ladder.iter().position(|&m| m == cfg.min_mode).unwrap_or(0);
+
+	if bad >= cfg.down_threshold {
+		state.good_since = None;
+		let since = *state.bad_since.get_or_insert(now);
+		if now - since >= Duration::from_secs(cfg.down_after) && position > min {
+			let to = ladder[position - 1];
+			info!(
+				"{:.0}% of frames missed, stepping down to mode {to}",
+				bad * 100.0
+			);
+			state.ceiling.get_or_insert(current);
+			step(&mut state, to, now);
+		}
+	} else if bad <= cfg.up_threshold {
+		state.bad_since = None;
+		let since = *state.good_since.get_or_insert(now);
+		let below_ceiling = state
+			.ceiling
+			.and_then(|c| ladder.iter().position(|&m| m == c))
+			.map(|c| position < c)
+			== Some(true);
+		if now - since >= Duration::from_secs(cfg.up_after) && below_ceiling {
+			let to = ladder[position + 1];
+			info!("frame timing has headroom, stepping up to mode {to}");
+			step(&mut state, to, now);
+		}
+	} else {
+		state.bad_since = None;
+		state.good_since = None;
+	}
+}
@@ -14,6 +14,12 @@ pub enum Error {
 	LibLoading(#[from] libloading::Error),
 	#[error("hid error: {0}")]
 	Hid(#[from] vive_hid::Error),
+	#[error("display error: {0}")]
+	Display(#[from] vive_display::Error),
+	#[error("headset display is not connected")]
+	LinkDown,
+	#[error("config error: {0}")]
+	Config(String),
 	#[error("internal error: {0}")]
 	Internal(&'static str),
 }
@@ -5,13 +5,16 @@ use std::{
 };
 
 use crate::{
-	log::LogWriter, server_tracked_provider::SERVER_TRACKED_DEVICE_PROVIDER, Error, Result,
+	log::{init_log_file, init_log_level, FileWriter, LogWriter},
+	server_tracked_provider::SERVER_TRACKED_DEVICE_PROVIDER,
+	Error, Result,
 };
 use cppvtbl::{HasVtable, VtableRef};
 use libloading::{Library, Symbol};
 use once_cell::sync::{Lazy, OnceCell};
 use tokio::runtime::Runtime;
-use tracing::info;
+use tracing::{info, level_filters::LevelFilter, warn};
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, Layer as _};
 
 use crate::openvr::{
 	EVRInitError, IServerTrackedDeviceProviderVtable, IServerTrackedDeviceProvider_Version,
@@ -37,15 +40,35 @@ pub static TOKIO_RUNTIME: Lazy<Runtime> =
 	Lazy::new(|| Runtime::new().expect("tokio init should not fail"));
 
 fn HmdDriverFactory_impl(iface: *const c_char) -> Result<*const c_void> {
+	// Levels may be changed by config later
+	let (level, handle) = reload::Layer::new(LevelFilter::INFO);
+	let (file_level, file_handle) = reload::Layer::new(LevelFilter::INFO);
 	// May be already installed
-	if tracing_subscriber::fmt()
-		.without_time()
-		.with_writer(LogWriter::default)
+	if tracing_subscriber::registry()
+		.with(
+			tracing_subscriber::fmt::layer()
+				.without_time()
+				.with_writer(LogWriter::default)
+				.with_filter(level),
+		)
+		.with(
+			tracing_subscriber::fmt::layer()
+				.with_ansi(false)
+				.with_thread_names(true)
+				.with_writer(|| FileWriter)
+				.with_filter(file_level),
+		)
 		.try_init()
 		.is_ok()
 	{
+		init_log_level(handle, file_handle);
 		// This magic string is also used for installation detection!
 		info!("https://patreon.com/0lach");
+		match init_log_file() {
+			Ok(Some(path)) => info!("logging to {}", path.display()),
+			Ok(None) => warn!("SteamVR logs directory not found, not logging to file"),
+			Err(e) => warn!("failed to open log file: {e}"),
+		}
 	}
 
 	let ifacen = unsafe { CStr::from_ptr(iface) };
@@ -0,0 +1,105 @@
+//! Headset button and proximity sensor, as HMD input components
+//!
+//! Button is reported as system button, so it opens dashboard the same way as on other headsets,
+//! and may be bound in SteamVR input binding UI. Proximity makes runtime pause and go to standby
+//! when headset is taken off
+use std::{ffi::c_char, sync::Mutex};
+
+use cppvtbl::VtableRef;
+use once_cell::sync::Lazy;
+use openvr::{
+	k_ulInvalidInputComponentHandle, EVRInputError, IVRDriverInput, IVRDriverInputVtable,
+	IVRDriverInput_Version, VRInputComponentHandle_t,
+};
+use real_c_string::real_c_string;
+use tracing::{error, info};
+
+use crate::driver_context::DRIVER_CONTEXT;
+
+pub static DRIVER_INPUT: Lazy<&'static VtableRef<IVRDriverInputVtable>> = Lazy::new(|| {
+	let ctx = DRIVER_CONTEXT
+		.get()
+		.expect("context should be initialized at this point");
+	let raw = ctx
+		.get_generic_interface(IVRDriverInput_Version)
+		.expect("there should be driver input interface");
+	unsafe { VtableRef::from_raw(raw as *const VtableRef<IVRDriverInputVtable>) }
+});
+
+/// Boolean component, only changes are sent
+struct Component {
+	/// Known once HMD is activated
+	handle: Option<VRInputComponentHandle_t>,
+	value: bool,
+}
+impl Component {
+	const fn new() -> Self {
+		Self {
+			handle: None,
+			value: false,
+		}
+	}
+	fn create(&mut self, container: u64, path: *const c_char) {
+		let mut handle = k_ulInvalidInputComponentHandle;
+		let res = DRIVER_INPUT.CreateBooleanComponent(container, path, &mut handle);
+		if res != EVRInputError::VRInputError_None {
+			error!("failed to create input component: {res:?}");
+			return;
+		}
+		*self = Self {
+			handle: Some(handle),
+			value: false,
+		};
+	}
+	/// Returns whether value has changed
+	fn update(&mut self, value: bool) -> bool {
+		let Some(handle) = self.handle else {
+			return false;
+		};
+		if self.value == value {
+			return false;
+		}
+		self.value = value;
+		let res = DRIVER_INPUT.UpdateBooleanComponent(handle, value, 0.0);
+		if res != EVRInputError::VRInputError_None {
+			error!("failed to update input component: {res:?}");
+		}
+		true
+	}
+}
+
+static BUTTON: Mutex<Component> = Mutex::new(Component::new());
+static PROXIMITY: Mutex<Component> = Mutex::new(Component::new());
+
+pub fn activated(container: u64) {
+	BUTTON
+		.lock()
+		.expect("lock")
+		.create(container, real_c_string!("/input/system/click"));
+	PROXIMITY
+		.lock()
+		.expect("lock")
+		.create(container, real_c_string!("/proximity"));
+}
+
+pub fn deactivated() {
+	BUTTON.lock().expect("lock").handle = None;
+	PROXIMITY.lock().expect("lock").handle = None;
+}
+
+/// Called for every status report
+pub fn button(pressed: bool) {
+	if BUTTON.lock().expect("lock").update(pressed) {
+		info!(
+			"headset button {}",
+			if pressed { "pressed" } else { "released" }
+		);
+	}
+}
+
+/// Called for every status report
+pub fn proximity(near: bool) {
+	if PROXIMITY.lock().expect("lock").update(near) {
+		info!("headset is {}", if near { "put on" } else { "taken off" });
+	}
+}
@@ -0,0 +1,204 @@
+//! Control socket, for tools which want to touch headset while SteamVR holds it
+//!
+//! Speaks vived protocol, so vivectl/vived don't need to care who owns the device
+//!
+//! Subscribed clients also get driver-side events, which are otherwise only visible in log
+use std::{
+	fs,
+	io::{BufReader, ErrorKind},
+	os::unix::net::{UnixListener, UnixStream},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		mpsc::{self, Sender},
+		Arc, Mutex,
+	},
+	thread,
+};
+
+use once_cell::sync::OnceCell;
+use tracing::{info, warn};
+use vive_hid::{ViveContext, ViveDevice};
+use vive_ipc::{
+	driver_socket_path, headsets, read_message, write_message, Event, Request, Response, Settings,
+	Status,
+};
+
+use crate::{
+	debug,
+	driver_host::frame_timing,
+	live::{self, BRIGHTNESS, HMD_RESOLUTION, NOISE_CANCEL},
+};
+
+pub struct IpcState {
+	pub vive: Arc<Mutex<ViveDevice>>,
+	pub serial: String,
+	/// Key of stored headset settings
+	pub devsn: Option<String>,
+	/// Values, applied at session start, and changed via socket since then
+	pub settings: Arc<Mutex<Settings>>,
+	/// Set once lens server fails to start, which may happen mid-session, when distortion wasn't
+	/// cached
+	pub lens_fallback: Arc<AtomicBool>,
+}
+
+static STARTED: OnceCell<()> = OnceCell::new();
+static SUBSCRIBERS: Mutex<Vec<Sender<Event>>> = Mutex::new(Vec::new());
+
+/// Sends event to every subscribed client, gone ones are dropped
+pub fn emit(event: Event) {
+	SUBSCRIBERS
+		.lock()
+		.expect("lock")
+		.retain(|tx| tx.send(event.clone()).is_ok());
+}
+
+fn handle(state: &IpcState, request: Request) -> Result<Response, vive_hid::Error> {
+	// Handler takes the same locks
+	let request = match request {
+		Request::Debug { request } => {
+			return Ok(Response::Debug {
+				response: debug::handle(request),
+			})
+		}
+		request => request,
+	};
+	let vive = state.vive.lock().expect("lock");
+	let mut settings = state.settings.lock().expect("lock");
+	match request {
+		Request::Status => {
+			let connection = ViveContext::global().connection().state();
+			return Ok(Response::Status(Status {
+				connected: connection.is_ready(),
+				serial: Some(state.serial.clone()),
+				settings: settings.clone(),
+				lens_fallback: state.lens_fallback.load(Ordering::Relaxed),
+				standby: live::in_standby(),
+				state: Some(connection.name().to_owned()),
+			}));
+		}
+		Request::SetResolution { resolution } => {
+			if !vive.query_modes().iter().any(|m| m.id == resolution) {
+				return Ok(Response::Error {
+					message: format!("unknown resolution {resolution}"),
+				});
+			}
+			// Switched by the setting watcher, which also stores it once headset is back
+			HMD_RESOLUTION.set(resolution as i32);
+			return Ok(Response::Ok);
+		}
+		Request::FrameTiming => return Ok(Response::FrameTiming(frame_timing())),
+		Request::Profiles | Request::SetProfile { .. } => {
+			return Ok(Response::Error {
+				message: "profiles are managed by vived".to_owned(),
+			})
+		}
+		Request::Debug { .. } | Request::Subscribe => unreachable!("handled above"),
+		Request::SetBaseStations { .. } | Request::SleepBaseStations { .. } => {
+			return Ok(Response::Error {
+				message: "base stations are managed by SteamVR during session".to_owned(),
+			})
+		}
+		Request::SetBrightness { brightness } => {
+			if !(1..=130).contains(&brightness) {
+				return Ok(Response::Error {
+					message: "brightness should be in 1-130 range".to_owned(),
+				});
+			}
+			// Restored on wake
+			if !live::in_standby() {
+				vive.set_brightness(brightness)?;
+			}
+			settings.brightness = Some(brightness);
+			// Otherwise watcher would revert it to the old setting value
+			BRIGHTNESS.set(brightness as i32);
+		}
+		Request::SetNoiseCancel { enabled } => {
+			vive.toggle_noise_canceling(enabled)?;
+			settings.noise_cancel = Some(enabled);
+			NOISE_CANCEL.set(enabled);
+		}
+	}
+	if let Some(devsn) = &state.devsn {
+		if let Err(e) = headsets::update(devsn, &settings) {
+			warn!("failed to store headset settings: {e}");
+		}
+	}
+	live::persist(&settings);
+	Ok(Response::Ok)
+}
+
+fn handle_client(state: &IpcState, stream: UnixStream) -> Result<(), vive_ipc::Error> {
+	let mut read = BufReader::new(stream.try_clone()?);
+	let mut write = stream;
+	loop {
+		let response = match read_message::<Request>(&mut read) {
+			Ok(Request::Subscribe) => {
+				let (tx, rx) = mpsc::channel();
+				SUBSCRIBERS.lock().expect("lock").push(tx);
+				write_message(&mut write, &Response::Ok)?;
+				for event in rx {
+					write_message(&mut write, &event)?;
+				}
+				return Ok(());
+			}
+			Ok(request) => handle(state, request).unwrap_or_else(|e| Response::Error {
+				message: e.to_string(),
+			}),
+			Err(vive_ipc::Error::Closed) => return Ok(()),
+			Err(vive_ipc::Error::Json(e)) => Response::Error {
+				message: format!("malformed request: {e}"),
+			},
+			Err(e) => return Err(e),
+		};
+		write_message(&mut write, &response)?;
+	}
+}
+
+/// Spawns socket server thread, only first call per process has effect
+pub fn start(state: IpcState) {
+	if STARTED.set(()).is_err() {
+		return;
+	}
+	let path = driver_socket_path();
+	match UnixStream::connect(&path) {
+		Ok(_) => {
+			warn!("control socket {path:?} is already served by another process");
+			return;
+		}
+		Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+			let _ = fs::remove_file(&path);
+		}
+		Err(_) => {}
+	}
+	let listener = match UnixListener::bind(&path) {
+		Ok(l) => l,
+		Err(e) => {
+			warn!("failed to bind control socket {path:?}: {e}");
+			return;
+		}
+	};
+	info!("control socket listening on {path:?}");
+	let state = Arc::new(state);
+	thread::spawn(move || {
+		for stream in listener.incoming() {
+			let Ok(stream) = stream else {
+				continue;
+			};
+			let state = state.clone();
+			thread::spawn(move || {
+				if let Err(e) = handle_client(&state, stream) {
+					warn!("control client failed: {e}");
+				}
+			});
+		}
+	});
+}
+
+/// Removes socket, so clients don't try to talk to exited driver
+pub fn stop() {
+	if STARTED.get().is_some() {
+		let _ = fs::remove_file(driver_socket_path());
+		// Ends event streams
+		SUBSCRIBERS.lock().expect("lock").clear();
+	}
+}
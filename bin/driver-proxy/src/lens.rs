@@ -0,0 +1,56 @@
+//! Lens distortion source, picked by `lens` config key
+use std::rc::Rc;
+
+use lens_protocol::LensClient;
+use serde_json::Value;
+use tracing::warn;
+
+use crate::config::LensBackend;
+
+/// `server` starts lens server, falling back to approximate distortion by itself
+#[cfg(feature = "native-lens")]
+pub fn start(
+	backend: LensBackend,
+	correction: &Value,
+	server: impl FnOnce() -> Rc<dyn LensClient>,
+) -> Rc<dyn LensClient> {
+	use lens_geometry::{Compare, NativeLens};
+	use tracing::info;
+
+	/// SteamVR samples distortion on a grid, a few thousands per eye
+	const REPORT_EVERY: u64 = 4096;
+
+	if backend == LensBackend::Server {
+		return server();
+	}
+	let native = match NativeLens::new(correction) {
+		Ok(native) => native,
+		Err(e) => {
+			warn!("native lens distortion can't be used with this headset, using lens server: {e}");
+			return server();
+		}
+	};
+	if backend == LensBackend::Native {
+		info!("using native lens distortion");
+		return Rc::new(native);
+	}
+	info!("comparing native lens distortion with lens server");
+	Rc::new(Compare::new(server(), native).on_report(REPORT_EVERY, |d| {
+		info!(
+				"native lens distortion is off by {:.5} uv at most ({:?}), {:.5} on average, over {} samples",
+				d.max, d.worst, d.mean(), d.samples
+			)
+	}))
+}
+
+#[cfg(not(feature = "native-lens"))]
+pub fn start(
+	backend: LensBackend,
+	_correction: &Value,
+	server: impl FnOnce() -> Rc<dyn LensClient>,
+) -> Rc<dyn LensClient> {
+	if backend != LensBackend::Server {
+		warn!("driver is built without native-lens feature, using lens server");
+	}
+	server()
+}
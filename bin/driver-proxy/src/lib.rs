@@ -12,9 +12,20 @@ pub use driver::{camera, hmd, server_tracked_provider};
 mod server;
 pub use server::{driver_context, driver_host};
 
+mod apps;
+mod config;
+mod debug;
+mod dialog;
+mod display;
+mod dynamic;
 #[macro_use]
 mod error;
 mod factory;
+mod input;
+mod ipc;
+mod lens;
+mod live;
+mod sim;
 #[macro_use]
 mod settings;
 mod log;
@@ -0,0 +1,681 @@
+//! Settings, which are watched during session, so changes made in `steamvr.vrsettings` (or in
+//! settings UI, see `resources/settings/vivepro2.schema.json`) apply without SteamVR restart
+use std::{
+	ops::RangeInclusive,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+	thread,
+	time::{Duration, Instant},
+};
+
+use once_cell::sync::OnceCell;
+use openvr::{
+	ETrackedDeviceProperty, EVREventType, IVRProperties, VREvent_Data_t, VREvent_Ipd_t,
+	VREvent_Property_t,
+};
+use tracing::{error, info, warn};
+use vive_display::{drm::find_connector, edid::EdidId};
+use vive_hid::{Mode, Resolution, SwitchStage, ViveContext, ViveDevice};
+use vive_ipc::{
+	debug::DebugState,
+	headsets,
+	session::{self, SessionState},
+	Event, Settings,
+};
+
+use crate::{
+	debug::{count, COUNTERS},
+	dialog,
+	display::{Display, TimingOverride},
+	driver_host::DRIVER_HOST,
+	dynamic, input, ipc,
+	openvr::IVRServerDriverHost,
+	setting,
+	settings::{set_properties, Property, PropertyValue, Setting, PROPERTIES},
+	Error, Result,
+};
+
+pub const HMD_RESOLUTION: Setting<i32> = setting!("vivepro2", "resolution");
+pub const BRIGHTNESS: Setting<i32> = setting!("vivepro2", "brightness");
+pub const NOISE_CANCEL: Setting<bool> = setting!("vivepro2", "noiseCancel");
+/// Seconds, 0 keeps value derived from headset config
+pub const VSYNC_TO_PHOTONS: Setting<f32> = setting!("vivepro2", "secondsFromVsyncToPhotons");
+pub const PHOTONS_TO_VBLANK: Setting<f32> = setting!("vivepro2", "secondsFromPhotonsToVblank");
+/// Written by SteamVR refresh rate picker, as display supports runtime frame rate change
+pub const PREFERRED_REFRESH_RATE: Setting<i32> = setting!("steamvr", "preferredRefreshRate");
+/// Inverted, as unset bool setting reads as false
+pub const DISABLE_CAMERAS: Setting<bool> = setting!("vivepro2", "disableCameras");
+/// Headset default, used when brightness was never set
+const DEFAULT_BRIGHTNESS: u8 = 130;
+/// Settings are checked from vrserver frame loop, no need to read them every frame
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Headset takes a few seconds to re-enumerate after switch
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+/// Display is plugged back a moment after USB side
+const LINK_TIMEOUT: Duration = Duration::from_secs(10);
+const LINK_POLL: Duration = Duration::from_millis(250);
+/// Fallback for modes, which leave headset or its display dead, when no mode is known to work
+/// on this setup, works on every known one
+const SAFE_MODE: Resolution = Resolution::R2448x1224f90;
+/// Every mode switch notification replaces the previous one
+const MODE_NOTIFICATION: &str = "vivepro2-mode";
+/// Anything outside is a garbage report, headset knob covers 57-70mm
+const IPD_RANGE: RangeInclusive<f32> = 0.05..=0.08;
+/// Status reports queued between checks, only the latest one matters
+const MAX_QUEUED_REPORTS: usize = 64;
+
+pub struct Live {
+	pub vive: Arc<Mutex<ViveDevice>>,
+	pub display: Arc<Mutex<Display>>,
+	/// Shared with control socket, reapplied after reconnect
+	pub settings: Arc<Mutex<Settings>>,
+	pub devsn: Option<String>,
+	/// Tracking board serial, headset is found by it after reconnect
+	serial: String,
+	/// For checking display link after switch, from tracking board config
+	edid: Option<EdidId>,
+	/// Known once HMD is activated
+	object_id: Mutex<Option<u32>>,
+	/// Last raw knob position reported to SteamVR
+	ipd: Mutex<Option<u16>>,
+	/// Same, in meters, only values which were in range
+	ipd_meters: Mutex<Option<f32>>,
+	switching: AtomicBool,
+	/// Last seen refresh rate picked in SteamVR, only its changes switch the mode
+	refresh_rate: Mutex<Option<i32>>,
+	/// Runtime standby, display is blanked and watched settings are held until wake
+	standby: AtomicBool,
+	last_check: Mutex<Option<Instant>>,
+}
+impl Live {
+	fn store(&self, settings: &Settings) {
+		if let Some(devsn) = &self.devsn {
+			if let Err(e) = headsets::update(devsn, settings) {
+				warn!("failed to store headset settings: {e}");
+			}
+		}
+	}
+	fn persist(&self, settings: &Settings) {
+		let state = SessionState {
+			serial: self.serial.clone(),
+			mode: self.display.lock().expect("lock").mode.id,
+			brightness: settings.brightness.unwrap_or(DEFAULT_BRIGHTNESS),
+			standby: self.standby.load(Ordering::Acquire),
+		};
+		if let Err(e) = session::save(&state) {
+			warn!("failed to persist session state: {e}");
+		}
+	}
+	/// Last mode headset and its display came back with, other than `failed`
+	fn fallback_mode(&self, failed: u8) -> u8 {
+		let good = self.devsn.as_deref().and_then(|devsn| {
+			headsets::last_good_mode(devsn)
+				.map_err(|e| warn!("failed to load last good mode: {e}"))
+				.ok()
+				.flatten()
+		});
+		good.filter(|&m| m != failed && Resolution::try_from(m).is_ok())
+			.unwrap_or(SAFE_MODE as u8)
+	}
+	fn mark_good(&self, mode: u8) {
+		if let Some(devsn) = &self.devsn {
+			if let Err(e) = headsets::mark_good_mode(devsn, mode) {
+				warn!("failed to store last good mode: {e}");
+			}
+		}
+	}
+}
+
+static LIVE: OnceCell<Arc<Live>> = OnceCell::new();
+
+/// Starts watching, only first call per process has effect
+pub fn init(
+	vive: Arc<Mutex<ViveDevice>>,
+	display: Arc<Mutex<Display>>,
+	settings: Arc<Mutex<Settings>>,
+	devsn: Option<String>,
+	serial: String,
+	edid: Option<EdidId>,
+) {
+	let live = Arc::new(Live {
+		vive,
+		display,
+		settings,
+		devsn,
+		serial,
+		edid,
+		object_id: Mutex::new(None),
+		ipd: Mutex::new(None),
+		ipd_meters: Mutex::new(None),
+		switching: AtomicBool::new(false),
+		refresh_rate: Mutex::new(None),
+		standby: AtomicBool::new(false),
+		last_check: Mutex::new(None),
+	});
+	live.persist(&live.settings.lock().expect("lock"));
+	// Picker would show the rate of previous session otherwise
+	let rate = live.display.lock().expect("lock").mode.frame_rate as i32;
+	PREFERRED_REFRESH_RATE.set(rate);
+	*live.refresh_rate.lock().expect("lock") = Some(rate);
+	let _ = LIVE.set(live);
+}
+
+/// Applied state has changed outside of this module
+pub fn persist(settings: &Settings) {
+	if let Some(live) = LIVE.get() {
+		live.persist(settings);
+	}
+}
+
+/// Called on clean shutdown, so the next start doesn't take it for crash
+pub fn finish() {
+	if let Err(e) = session::clear() {
+		warn!("failed to remove session state: {e}");
+	}
+}
+
+pub fn activated(object_id: u32) {
+	if let Some(live) = LIVE.get() {
+		*live.object_id.lock().expect("lock") = Some(object_id);
+	}
+}
+
+pub fn deactivated() {
+	if let Some(live) = LIVE.get() {
+		*live.object_id.lock().expect("lock") = None;
+	}
+}
+
+/// Called every frame, mode switch itself happens in background, as headset is gone for a while
+pub fn poll() {
+	let Some(live) = LIVE.get() else {
+		return;
+	};
+	// Device is held by the switch, and everything is reapplied after it anyway
+	if live.switching.load(Ordering::Acquire) {
+		return;
+	}
+	// Handle is dead, reads would fail every frame
+	if !ViveContext::global().connection().is_ready() {
+		return;
+	}
+	// Every frame, so short button presses aren't missed
+	poll_status(live);
+	{
+		let mut last = live.last_check.lock().expect("lock");
+		if last.map(|t| t.elapsed() < CHECK_INTERVAL) == Some(true) {
+			return;
+		}
+		*last = Some(Instant::now());
+	}
+	// Changes are picked up on wake
+	if live.standby.load(Ordering::Acquire) {
+		return;
+	}
+	poll_settings(live);
+	poll_timing(live);
+	poll_refresh_rate(live);
+	let current = live.display.lock().expect("lock").mode.id;
+	dynamic::poll(current);
+	poll_resolution(live);
+}
+
+pub fn debug_state() -> Option<DebugState> {
+	let live = LIVE.get()?;
+	let mode = live.display.lock().expect("lock").mode;
+	Some(DebugState {
+		serial: live.serial.clone(),
+		devsn: live.devsn.clone(),
+		mode: mode.id,
+		width: mode.width,
+		height: mode.height,
+		frame_rate: mode.frame_rate,
+		switching: live.switching.load(Ordering::Acquire),
+		state: ViveContext::global().connection().state().name().to_owned(),
+		standby: live.standby.load(Ordering::Acquire),
+		activated: live.object_id.lock().expect("lock").is_some(),
+		settings: live.settings.lock().expect("lock").clone(),
+	})
+}
+
+/// Handle is replaced on mode switch, so reports of the previous one are gone after it
+pub fn recent_reports() -> Result<String, String> {
+	let live = LIVE.get().ok_or("headset is not initialized yet")?;
+	// Held for the whole mode switch, which shouldn't block debug requests
+	let vive = live
+		.vive
+		.try_lock()
+		.map_err(|_| "headset is busy, mode switch is in progress")?;
+	Ok(vive.dump_recent())
+}
+
+/// Last IPD reported to SteamVR
+pub fn ipd() -> Option<f32> {
+	*LIVE.get()?.ipd_meters.lock().expect("lock")
+}
+
+pub fn in_standby() -> bool {
+	LIVE.get()
+		.map(|live| live.standby.load(Ordering::Acquire))
+		.unwrap_or(false)
+}
+
+/// Called on runtime standby, there is no known panel power command, so backlight is turned off
+/// instead, the same way vived does outside of session
+pub fn standby(on: bool) {
+	let Some(live) = LIVE.get() else {
+		return;
+	};
+	let vive = live.vive.lock().expect("lock");
+	if live.standby.swap(on, Ordering::AcqRel) == on {
+		return;
+	}
+	let settings = live.settings.lock().expect("lock");
+	let res = if on {
+		info!("entering standby, blanking display");
+		count(&COUNTERS.standby_entries);
+		vive.set_brightness(0)
+	} else {
+		info!("leaving standby");
+		let brightness = vive.set_brightness(settings.brightness.unwrap_or(DEFAULT_BRIGHTNESS));
+		// Headset may have been power cycled meanwhile, which resets codec state
+		match settings.noise_cancel {
+			Some(nc) => brightness.and_then(|()| vive.toggle_noise_canceling(nc)),
+			None => brightness,
+		}
+	};
+	if let Err(e) = res {
+		error!(
+			"failed to {} standby: {e}",
+			if on { "enter" } else { "leave" }
+		);
+	}
+	live.persist(&settings);
+	// Mode requested during standby is switched to by the next poll
+	*live.last_check.lock().expect("lock") = None;
+}
+
+fn poll_settings(live: &Live) {
+	let vive = live.vive.lock().expect("lock");
+	let mut settings = live.settings.lock().expect("lock");
+	let mut changed = false;
+
+	let brightness = BRIGHTNESS.get();
+	if Some(brightness) != settings.brightness.map(i32::from) {
+		match u8::try_from(brightness) {
+			Ok(value @ 1..=130) => match vive.set_brightness(value) {
+				Ok(()) => {
+					info!("brightness changed to {value}");
+					settings.brightness = Some(value);
+					changed = true;
+				}
+				Err(e) => error!("failed to set brightness: {e}"),
+			},
+			_ => {
+				warn!("brightness {brightness} is out of 1-130 range, reverting");
+				BRIGHTNESS.set(
+					settings
+						.brightness
+						.map(i32::from)
+						.unwrap_or(DEFAULT_BRIGHTNESS.into()),
+				);
+			}
+		}
+	}
+
+	let nc = NOISE_CANCEL.get();
+	if Some(nc) != settings.noise_cancel {
+		match vive.toggle_noise_canceling(nc) {
+			Ok(()) => {
+				info!("noise canceling changed to {nc}");
+				settings.noise_cancel = Some(nc);
+				changed = true;
+			}
+			Err(e) => error!("failed to toggle noise canceling: {e}"),
+		}
+	}
+
+	let cameras = !DISABLE_CAMERAS.get();
+	if Some(cameras) != settings.cameras {
+		match vive.set_cameras_enabled(cameras) {
+			Ok(()) => {
+				info!("cameras enabled changed to {cameras}");
+				settings.cameras = Some(cameras);
+				changed = true;
+			}
+			Err(e) => {
+				error!("failed to toggle cameras: {e}");
+				// Would be retried on every check otherwise
+				DISABLE_CAMERAS.set(settings.cameras.map(|c| !c).unwrap_or(false));
+			}
+		}
+	}
+
+	if changed {
+		live.store(&settings);
+		live.persist(&settings);
+	}
+}
+
+fn poll_status(live: &Live) {
+	let latest = {
+		let vive = live.vive.lock().expect("lock");
+		let mut latest = None;
+		for _ in 0..MAX_QUEUED_REPORTS {
+			match vive.read_status(0) {
+				Ok(Some(status)) => {
+					input::button(status.button != 0);
+					input::proximity(status.proximity_change != 0);
+					latest = Some(status);
+				}
+				Ok(None) => break,
+				Err(e) => {
+					error!("failed to read headset status: {e}");
+					break;
+				}
+			}
+		}
+		latest
+	};
+	let Some(status) = latest else {
+		return;
+	};
+	let Some(id) = *live.object_id.lock().expect("lock") else {
+		return;
+	};
+	let mut last = live.ipd.lock().expect("lock");
+	if *last == Some(status.ipd) {
+		return;
+	}
+	*last = Some(status.ipd);
+	let ipd = status.ipd_meters();
+	if !IPD_RANGE.contains(&ipd) {
+		warn!("ignoring ipd {ipd}m, raw value {}", status.ipd);
+		return;
+	}
+	info!("ipd changed to {:.1}mm", ipd * 1000.0);
+	*live.ipd_meters.lock().expect("lock") = Some(ipd);
+	count(&COUNTERS.ipd_changes);
+
+	let container = PROPERTIES.TrackedDeviceToPropertyContainer(id);
+	set_properties(
+		container,
+		vec![Property::new(
+			ETrackedDeviceProperty::Prop_UserIpdMeters_Float,
+			PropertyValue::Float(ipd),
+		)],
+	);
+	// Same event lighthouse driver sends for other headsets, makes compositor pick eye transforms up
+	let data = VREvent_Data_t {
+		ipd: VREvent_Ipd_t { ipdMeters: ipd },
+	};
+	DRIVER_HOST.VendorSpecificEvent(id, EVREventType::VREvent_IpdChanged, &data, 0.0);
+}
+
+/// Settings, created with current values if missing, so they can be found in `steamvr.vrsettings`
+pub fn timing_override() -> TimingOverride {
+	let timing = TimingOverride {
+		vsync_to_photons: VSYNC_TO_PHOTONS.get(),
+		photons_to_vblank: PHOTONS_TO_VBLANK.get(),
+	};
+	VSYNC_TO_PHOTONS.set(timing.vsync_to_photons);
+	PHOTONS_TO_VBLANK.set(timing.photons_to_vblank);
+	timing
+}
+
+fn poll_timing(live: &Live) {
+	let timing = TimingOverride {
+		vsync_to_photons: VSYNC_TO_PHOTONS.get(),
+		photons_to_vblank: PHOTONS_TO_VBLANK.get(),
+	};
+	let updated = {
+		let mut display = live.display.lock().expect("lock");
+		if display.timing == timing {
+			return;
+		}
+		display.timing = timing;
+		*display
+	};
+	info!(
+		"display timing changed: vsync to photons {}s, photons to vblank {}",
+		updated.seconds_from_vsync_to_photons(),
+		updated
+			.seconds_from_photons_to_vblank()
+			.map_or("not set".to_owned(), |s| format!("{s}s"))
+	);
+	if let Some(id) = *live.object_id.lock().expect("lock") {
+		let container = PROPERTIES.TrackedDeviceToPropertyContainer(id);
+		set_properties(container, updated.properties());
+	}
+}
+
+/// Same panel resolution is kept, only the rate is changed
+fn poll_refresh_rate(live: &Live) {
+	let requested = PREFERRED_REFRESH_RATE.get();
+	{
+		let mut last = live.refresh_rate.lock().expect("lock");
+		if *last == Some(requested) {
+			return;
+		}
+		*last = Some(requested);
+		if requested <= 0 {
+			return;
+		}
+	}
+	let current = live.display.lock().expect("lock").mode;
+	if current.frame_rate as i32 == requested {
+		return;
+	}
+	let modes = live.vive.lock().expect("lock").query_modes();
+	let Some(mode) = modes.into_iter().find(|m| {
+		m.width == current.width && m.height == current.height && m.frame_rate as i32 == requested
+	}) else {
+		warn!(
+			"refresh rate {requested} is not available for {}x{}",
+			current.width, current.height
+		);
+		return;
+	};
+	info!(
+		"refresh rate {requested} picked in SteamVR, switching to mode {}",
+		mode.id
+	);
+	HMD_RESOLUTION.set(mode.id as i32);
+}
+
+fn poll_resolution(live: &Arc<Live>) {
+	let requested = HMD_RESOLUTION.get();
+	let current = live.display.lock().expect("lock").mode.id;
+	if requested == current as i32 {
+		return;
+	}
+	let modes = live.vive.lock().expect("lock").query_modes();
+	let Some(mode) = modes.into_iter().find(|m| m.id as i32 == requested) else {
+		warn!("unknown resolution {requested}, keeping mode {current}");
+		HMD_RESOLUTION.set(current as i32);
+		return;
+	};
+	live.switching.store(true, Ordering::Release);
+	let live = live.clone();
+	thread::spawn(move || {
+		count(&COUNTERS.mode_switches);
+		if let Err(e) = switch(&live, mode) {
+			count(&COUNTERS.failed_mode_switches);
+			error!("failed to switch to mode {}: {e}", mode.id);
+			dialog::notify(
+				MODE_NOTIFICATION,
+				"Display mode switch failed",
+				e.to_string(),
+			);
+			// Would be retried on every check otherwise
+			HMD_RESOLUTION.set(live.display.lock().expect("lock").mode.id as i32);
+		}
+		live.switching.store(false, Ordering::Release);
+	});
+}
+
+/// Headset connector is found by EDID, and is connected, there is no way to see whether link is
+/// trained without DRM master, but failed training usually leaves it disconnected
+fn wait_link(live: &Live) -> Result<()> {
+	let Some(edid) = live.edid else {
+		return Ok(());
+	};
+	let start = Instant::now();
+	loop {
+		if find_connector(edid)?.map(|c| c.connected) == Some(true) {
+			return Ok(());
+		}
+		if start.elapsed() >= LINK_TIMEOUT {
+			return Err(Error::LinkDown);
+		}
+		thread::sleep(LINK_POLL);
+	}
+}
+
+/// Both USB and display parts should come back with the new mode, USB one within
+/// [`RECONNECT_TIMEOUT`] of the switch
+fn switch_and_come_back(live: &Live, vive: &mut ViveDevice, mode: u8) -> Result<()> {
+	match vive.set_resolution_and_wait_paired(mode, &live.serial, RECONNECT_TIMEOUT)? {
+		Some(back) => *vive = back,
+		// Same switch is in progress, whoever started it waits for it
+		None => return Ok(()),
+	}
+	wait_link(live)
+}
+
+/// Mode was written, but headset didn't come back with it, unlike switch which didn't start,
+/// i.e as another one is in progress, where there is nothing to fall back from
+fn failed_to_come_back(e: &Error) -> bool {
+	matches!(
+		e,
+		Error::LinkDown
+			| Error::Hid(vive_hid::Error::SwitchTimeout {
+				stage: SwitchStage::DropOff | SwitchStage::Reconnect,
+			})
+	)
+}
+
+fn switch(live: &Live, mode: Mode) -> Result<()> {
+	info!(
+		"switching to mode {} ({}x{}@{})",
+		mode.id, mode.width, mode.height, mode.frame_rate
+	);
+	// Before headset goes black, link is read every time, as cable may have been replaced
+	if let Some(connector) = live.edid.and_then(|id| find_connector(id).ok().flatten()) {
+		vive_hid::check_link(&connector.link_caps(), mode.id)?;
+	}
+	// Held until headset is back, so control socket doesn't talk to the gone device
+	let mut vive = live.vive.lock().expect("lock");
+	dialog::notify(
+		MODE_NOTIFICATION,
+		"Switching display mode",
+		format!(
+			"{}x{}@{}, headset display will be black for a few seconds",
+			mode.width, mode.height, mode.frame_rate
+		),
+	);
+	let fallback = live.fallback_mode(mode.id);
+	let (mode, failure) = match switch_and_come_back(live, &mut vive, mode.id) {
+		Ok(()) => (mode, None),
+		Err(e) if mode.id != fallback && failed_to_come_back(&e) => {
+			error!(
+				"mode {} has failed: {e}, falling back to mode {fallback}",
+				mode.id
+			);
+			count(&COUNTERS.failed_mode_switches);
+			// Headset may be still on its way back, and old handle is gone either way
+			*vive = ViveDevice::wait_reconnect_paired(&live.serial, RECONNECT_TIMEOUT)?;
+			switch_and_come_back(live, &mut vive, fallback)?;
+			let fallback = Resolution::try_from(fallback).expect("known mode").mode();
+			(fallback, Some((mode, e)))
+		}
+		Err(e) => return Err(e),
+	};
+	live.mark_good(mode.id);
+
+	let mut settings = live.settings.lock().expect("lock");
+	// Chip reset loses brightness and codec state
+	if let Some(brightness) = settings.brightness {
+		vive.set_brightness(brightness)?;
+	}
+	if let Some(nc) = settings.noise_cancel {
+		vive.toggle_noise_canceling(nc)?;
+	}
+	// Stored mode is the last working one, so failed mode isn't used on the next start either
+	if failure.is_none() {
+		settings.resolution = Some(mode.id);
+		live.store(&settings);
+	}
+	HMD_RESOLUTION.set(mode.id as i32);
+
+	let (display, previous_rate) = {
+		let mut display = live.display.lock().expect("lock");
+		let previous_rate = display.mode.frame_rate;
+		*display = display.with_mode(mode);
+		(*display, previous_rate)
+	};
+	if mode.frame_rate != previous_rate {
+		// Keeps SteamVR picker in sync, and isn't taken for a new request
+		PREFERRED_REFRESH_RATE.set(mode.frame_rate as i32);
+		*live.refresh_rate.lock().expect("lock") = Some(mode.frame_rate as i32);
+	}
+	// Fallback mode too, it is what headset runs now
+	live.persist(&settings);
+	if let Some(id) = *live.object_id.lock().expect("lock") {
+		let container = PROPERTIES.TrackedDeviceToPropertyContainer(id);
+		set_properties(container, display.properties());
+		let (width, height) = display.render_target_size();
+		DRIVER_HOST.SetRecommendedRenderTargetSize(id, width, height);
+		if mode.frame_rate != previous_rate {
+			// Compositor only re-reads frame pacing inputs once told about the change
+			for prop in [
+				ETrackedDeviceProperty::Prop_DisplayFrequency_Float,
+				ETrackedDeviceProperty::Prop_SecondsFromVsyncToPhotons_Float,
+			] {
+				let data = VREvent_Data_t {
+					property: VREvent_Property_t { container, prop },
+				};
+				DRIVER_HOST.VendorSpecificEvent(
+					id,
+					EVREventType::VREvent_PropertyChanged,
+					&data,
+					0.0,
+				);
+			}
+		}
+	}
+	info!("switched to mode {}", mode.id);
+	ipc::emit(match &failure {
+		Some((failed, e)) => Event::ModeFallback {
+			requested: failed.id,
+			resolution: mode.id,
+			reason: e.to_string(),
+		},
+		None => Event::ModeApplied {
+			resolution: mode.id,
+		},
+	});
+	match failure {
+		Some((failed, e)) => dialog::notify(
+			MODE_NOTIFICATION,
+			"Display mode switch failed",
+			format!(
+				"{}x{}@{} doesn't work with this setup ({e}), fell back to {}x{}@{}",
+				failed.width,
+				failed.height,
+				failed.frame_rate,
+				mode.width,
+				mode.height,
+				mode.frame_rate
+			),
+		),
+		None => dialog::notify(
+			MODE_NOTIFICATION,
+			"Display mode switched",
+			format!("{}x{}@{}", mode.width, mode.height, mode.frame_rate),
+		),
+	}
+	Ok(())
+}
@@ -1,11 +1,31 @@
-use std::{ffi::CString, io::Write};
+use std::{
+	ffi::CString,
+	fs::{self, File, OpenOptions},
+	io::{self, Write},
+	path::{Path, PathBuf},
+	sync::Mutex,
+};
 
 use cppvtbl::VtableRef;
 use once_cell::sync::OnceCell;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::reload;
 
 use crate::openvr::{IVRDriverLog, IVRDriverLogVtable};
 
+/// Next to `vrserver.txt`, so it is collected together with SteamVR logs
+pub const LOG_FILE: &str = "vivepro2_driver";
+/// Rotated once current file grows past this size, and on every session start
+const MAX_LOG_SIZE: u64 = 16 * 1024 * 1024;
+/// Previous files, `vivepro2_driver.1.txt` is the newest
+const KEPT_LOGS: u32 = 3;
+
+type ReloadLevel = Box<dyn Fn(LevelFilter) -> Result<(), reload::Error> + Send + Sync>;
+
 static DRIVER_LOG: OnceCell<&'static VtableRef<IVRDriverLogVtable>> = OnceCell::new();
+static LOG_LEVEL: OnceCell<ReloadLevel> = OnceCell::new();
+static FILE_LOG_LEVEL: OnceCell<ReloadLevel> = OnceCell::new();
+static LOG_FILE_STATE: Mutex<Option<RotatingFile>> = Mutex::new(None);
 
 #[derive(Default)]
 pub struct LogWriter(Vec<u8>);
@@ -41,3 +61,104 @@ impl Write for LogWriter {
 pub fn try_init_driver_log(log: &'static VtableRef<IVRDriverLogVtable>) {
 	let _ = DRIVER_LOG.set(log);
 }
+
+fn reload_level<S: 'static>(handle: reload::Handle<LevelFilter, S>) -> ReloadLevel {
+	Box::new(move |level| handle.reload(level))
+}
+
+/// Handles of subscriber filters, installed by this driver, there is nothing to change otherwise
+pub fn init_log_level<S: 'static, F: 'static>(
+	handle: reload::Handle<LevelFilter, S>,
+	file_handle: reload::Handle<LevelFilter, F>,
+) {
+	let _ = LOG_LEVEL.set(reload_level(handle));
+	let _ = FILE_LOG_LEVEL.set(reload_level(file_handle));
+}
+
+fn set_level(reload: &OnceCell<ReloadLevel>, level: LevelFilter) {
+	if let Some(reload) = reload.get() {
+		if let Err(e) = reload(level) {
+			tracing::warn!("failed to change log level: {e}");
+		}
+	}
+}
+pub fn set_log_level(level: LevelFilter) {
+	set_level(&LOG_LEVEL, level)
+}
+pub fn set_file_log_level(level: LevelFilter) {
+	set_level(&FILE_LOG_LEVEL, level)
+}
+
+struct RotatingFile {
+	dir: PathBuf,
+	file: File,
+	size: u64,
+}
+impl RotatingFile {
+	fn path(dir: &Path, index: u32) -> PathBuf {
+		if index == 0 {
+			dir.join(format!("{LOG_FILE}.txt"))
+		} else {
+			dir.join(format!("{LOG_FILE}.{index}.txt"))
+		}
+	}
+	/// Always starts a new file, older ones are shifted, and the oldest one is removed
+	fn open(dir: PathBuf) -> io::Result<Self> {
+		for index in (0..KEPT_LOGS).rev() {
+			let from = Self::path(&dir, index);
+			if from.exists() {
+				fs::rename(&from, Self::path(&dir, index + 1))?;
+			}
+		}
+		let file = OpenOptions::new()
+			.create(true)
+			.write(true)
+			.truncate(true)
+			.open(Self::path(&dir, 0))?;
+		Ok(Self { dir, file, size: 0 })
+	}
+	fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+		if self.size + buf.len() as u64 > MAX_LOG_SIZE {
+			*self = Self::open(self.dir.clone())?;
+		}
+		self.file.write_all(buf)?;
+		self.size += buf.len() as u64;
+		Ok(())
+	}
+}
+
+/// Steam root of SteamVR this driver is installed to, driver is at
+/// `<root>/steamapps/common/SteamVR/drivers/lighthouse/bin/linux64/driver_lighthouse.so`
+fn steam_logs_dir() -> Option<PathBuf> {
+	let lib = process_path::get_dylib_path()?;
+	Some(lib.ancestors().nth(8)?.join("logs"))
+}
+
+/// Starts per-session log file, logs are written nowhere if SteamVR logs directory is not found
+pub fn init_log_file() -> io::Result<Option<PathBuf>> {
+	let Some(dir) = steam_logs_dir().filter(|d| d.is_dir()) else {
+		return Ok(None);
+	};
+	let file = RotatingFile::open(dir)?;
+	let path = RotatingFile::path(&file.dir, 0);
+	*LOG_FILE_STATE.lock().expect("lock") = Some(file);
+	Ok(Some(path))
+}
+
+/// Writer for log file layer, formatter writes whole event at once
+pub struct FileWriter;
+impl Write for FileWriter {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		if let Some(file) = LOG_FILE_STATE.lock().expect("lock").as_mut() {
+			file.write(buf)?;
+		}
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		if let Some(file) = LOG_FILE_STATE.lock().expect("lock").as_mut() {
+			file.file.flush()?;
+		}
+		Ok(())
+	}
+}
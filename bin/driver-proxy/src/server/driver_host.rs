@@ -1,35 +1,91 @@
 use cppvtbl::{impl_vtables, HasVtable, VtableRef, WithVtables};
-use lens_client::start_lens_server;
-use lens_protocol::{LensClient, StubClient};
+use lens_client::{AsyncClient, CacheKey, CachedClient, SupervisedClient, ADDRESS_ENV};
+use lens_geometry::FallbackLens;
+use lens_protocol::LensClient;
 use once_cell::sync::Lazy;
-use std::env::var_os;
-use std::ffi::{CStr, OsString};
+use std::cell::RefCell;
+use std::env;
+use std::ffi::CStr;
 use std::os::raw::c_char;
-use std::process::Command;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use tracing::{error, info, warn};
-use vive_hid::{SteamDevice, ViveDevice};
+use vive_display::edid::EdidId;
+use vive_hid::{ConfigCamera, SteamDevice, ViveConfig, ViveDevice};
+use vive_ipc::{headsets, session, Event, FrameTiming, Settings};
 
+use crate::display::Display;
 use crate::driver_context::DRIVER_CONTEXT;
-use crate::hmd::HmdDriver;
+use crate::hmd::{HmdDriver, Meshes};
+use crate::ipc::{self, IpcState};
+use crate::live::{self, BRIGHTNESS, DISABLE_CAMERAS, HMD_RESOLUTION, NOISE_CANCEL};
 use crate::openvr::{
 	Compositor_FrameTiming, DriverPose_t, ETrackedDeviceClass, EVREventType, HmdMatrix34_t,
 	HmdRect2_t, ITrackedDeviceServerDriverVtable, IVRServerDriverHost_Version, TrackedDevicePose_t,
 	VREvent_t,
 };
 use crate::openvr::{IVRServerDriverHost, IVRServerDriverHostVtable, VREvent_Data_t};
-use crate::settings::Setting;
-use crate::{setting, Result};
+use crate::Result;
+use crate::{apps, config, dialog, lens};
+
+/// Mainboard sends status every 100ms
+const STATUS_TIMEOUT_MS: i32 = 500;
+
+/// Everything that is slow to set up (config read, lens server start), kept for when lighthouse
+/// adds the same HMD again, i.e after compositor restart
+struct Added {
+	serial: String,
+	vive: Arc<Mutex<ViveDevice>>,
+	vive_config: Rc<ViveConfig>,
+	cameras: Vec<ConfigCamera>,
+	lens: Rc<AsyncClient>,
+	meshes: Rc<Meshes>,
+	display: Arc<Mutex<Display>>,
+	edid_override: Option<EdidId>,
+}
+impl Added {
+	fn wrap(
+		&self,
+		real: &'static VtableRef<ITrackedDeviceServerDriverVtable>,
+	) -> &'static WithVtables<HmdDriver> {
+		Box::leak(Box::new(WithVtables::new(HmdDriver {
+			vive: self.vive.clone(),
+			vive_config: self.vive_config.clone(),
+			cameras: self.cameras.clone(),
+			lens: self.lens.clone(),
+			meshes: self.meshes.clone(),
+			real,
+			display: self.display.clone(),
+			edid_override: self.edid_override,
+		})))
+	}
+}
+
+thread_local! {
+	// Lens client is not Send, device is added again from the same thread anyway, and on miss
+	// everything is just set up again
+	static ADDED: RefCell<Option<Added>> = const { RefCell::new(None) };
+}
+
+/// `None` if this HMD wasn't seen yet
+fn readded(
+	sn: &str,
+	pDriver: *const VtableRef<ITrackedDeviceServerDriverVtable>,
+) -> Option<&'static WithVtables<HmdDriver>> {
+	ADDED.with(|added| {
+		let added = added.borrow();
+		let added = added.as_ref().filter(|a| a.serial == sn)?;
+		info!("hmd {sn} was added again, reusing opened device and lens server");
+		Some(added.wrap(unsafe { VtableRef::from_raw(pDriver) }))
+	})
+}
 
 #[impl_vtables(IVRServerDriverHost)]
 pub struct DriverHost {
 	real: &'static VtableRef<IVRServerDriverHostVtable>,
 }
 
-const HMD_RESOLUTION: Setting<i32> = setting!("vivepro2", "resolution");
-const BRIGHTNESS: Setting<i32> = setting!("vivepro2", "brightness");
-const NOISE_CANCEL: Setting<bool> = setting!("vivepro2", "noiseCancel");
-
 impl IVRServerDriverHost for DriverHost {
 	fn TrackedDeviceAdded(
 		&self,
@@ -42,14 +98,77 @@ impl IVRServerDriverHost for DriverHost {
 			.to_string();
 		info!("added tracked device: {sn:?} ({eDeviceClass:?})");
 		if eDeviceClass == ETrackedDeviceClass::TrackedDeviceClass_HMD {
+			if let Some(hmd) = readded(&sn, pDriver) {
+				return self.real.TrackedDeviceAdded(
+					pchDeviceSerialNumber,
+					eDeviceClass,
+					HasVtable::<ITrackedDeviceServerDriverVtable>::get(hmd),
+				);
+			}
 			let err: Result<()> = try {
 				// Steam part is opened for checking if this is really a needed HMD device
-				let _steam = Rc::new(SteamDevice::open(&sn)?);
-				// We don't know for sure this device serial
-				let vive = Rc::new(ViveDevice::open_first()?);
+				let steam = SteamDevice::open(&sn)?;
+				let vive = ViveDevice::open_paired(&sn);
+				// Settings changed via vivectl/vived follow the headset, they are stored by the same serial
+				let devsn = vive.as_ref().ok().and_then(|v| v.read_devsn().ok());
+				let config = config::current();
+				if let Some(serial) = &config.serial {
+					// Not added at all, SteamVR only drives one headset
+					if *serial != sn && devsn.as_ref() != Some(serial) {
+						info!("ignoring headset {sn}, driver is bound to {serial}");
+						return false;
+					}
+				}
+				let vive = vive?;
+				// Left by crashed session of this headset
+				let crashed = session::load()
+					.unwrap_or_else(|e| {
+						warn!("failed to load session state: {e}");
+						None
+					})
+					.filter(|s| s.serial == sn);
+				if let Some(crashed) = &crashed {
+					info!(
+						"previous session has crashed{}, restoring its state: mode {}, brightness {}",
+						if crashed.standby { " in standby" } else { "" },
+						crashed.mode,
+						crashed.brightness
+					);
+				}
+				let steam_config = steam
+					.read_config()
+					.map_err(|e| warn!("failed to read tracking board config, camera intrinsics and display EDID are not known: {e}"))
+					.ok();
+				let cameras = steam_config
+					.as_ref()
+					.map(|c| c.tracked_cameras.clone())
+					.unwrap_or_default();
+				let config_edid = steam_config
+					.as_ref()
+					.and_then(|c| {
+						let edid = c.direct_mode_edid();
+						if edid.is_none() {
+							warn!("tracking board config has no display EDID ids");
+						}
+						edid
+					})
+					.map(|(vid, pid)| EdidId::from_config(vid, pid));
+				let stored = devsn
+					.as_deref()
+					.map(headsets::load)
+					.transpose()
+					.unwrap_or_else(|e| {
+						warn!("failed to load stored headset settings: {e}");
+						None
+					})
+					.flatten()
+					.unwrap_or_default();
 
 				let mode = {
-					let res = HMD_RESOLUTION.get();
+					let res = crashed
+						.as_ref()
+						.map(|s| i32::from(s.mode))
+						.unwrap_or_else(|| HMD_RESOLUTION.get());
 					let modes = vive.query_modes();
 					let mode = *modes.iter().find(|m| m.id == res as u8).unwrap_or(
 						modes
@@ -61,51 +180,184 @@ impl IVRServerDriverHost for DriverHost {
 					vive.set_mode(mode.id)?;
 					mode
 				};
-				{
-					let nc = NOISE_CANCEL.get();
+				let nc = {
+					let nc = stored.noise_cancel.unwrap_or_else(|| NOISE_CANCEL.get());
 					NOISE_CANCEL.set(nc);
 
 					vive.toggle_noise_canceling(nc)?;
+					nc
+				};
+				// Simulated headset has no display to wait for, image goes to a desktop window
+				let simulated = vive_hid::sim::enabled();
+				let edid = config.direct_mode.edid(config_edid).filter(|_| !simulated);
+				if let Some(edid) = edid.filter(|_| config.direct_mode.overrides_id()) {
+					info!(
+						"direct mode display id is overridden: {} {:04x}",
+						edid.pnp_id(),
+						edid.product
+					);
 				}
+				if let (Some(path), Some(connector)) =
+					(&config.direct_mode.edid_file, &config.direct_mode.connector)
 				{
-					let mut brightness = BRIGHTNESS.get();
+					let res: Result<()> = try {
+						let blob = std::fs::read(path).map_err(vive_display::Error::Io)?;
+						vive_display::drm::override_edid(connector, &blob)?;
+					};
+					match res {
+						Ok(()) => info!("edid of {connector} is replaced with {}", path.display()),
+						Err(e) => warn!("failed to replace edid of {connector}, it needs root, drm.edid_firmware kernel parameter may be used instead: {e}"),
+					}
+				}
+				let brightness = {
+					// Config is explicit, so it wins over last used value, but not over the one
+					// applied right before crash
+					let mut brightness = crashed
+						.as_ref()
+						.map(|s| s.brightness)
+						.or(config.brightness)
+						.or(stored.brightness)
+						.map(i32::from)
+						.unwrap_or_else(|| BRIGHTNESS.get());
 					if brightness == 0 {
 						brightness = 130;
 					}
 					BRIGHTNESS.set(brightness);
 
 					vive.set_brightness(brightness as u8)?;
+					brightness as u8
+				};
+
+				let vive_config = Rc::new(vive.read_config()?);
+				match vive.read_user_config() {
+					Ok(user) => config::set_device_color(user.display_gain()),
+					Err(e) => warn!(
+						"failed to read headset user config, color calibration is not applied: {e}"
+					),
 				}
 
-				let vive_config = vive.read_config()?;
+				let cameras_enabled = {
+					let enabled = stored.cameras.unwrap_or_else(|| !DISABLE_CAMERAS.get());
+					DISABLE_CAMERAS.set(!enabled);
 
-				let lens = start_lens_server(vive_config.inhouse_lens_correction.clone())
-					.map(|v| Rc::new(v) as Rc<dyn LensClient>)
-					.unwrap_or_else(|e| {
-						let zenity = var_os("STEAM_ZENITY").unwrap_or_else(|| OsString::from("zenity"));
-						let mut cmd = Command::new(zenity);
-						cmd.arg("--no-wrap").arg("--error").arg("--text").arg(format!("Lens distortion helper is failed to launch, HMD image most probaly will be distorted and unusable.\nError: {e}\n\nMake sure you have any recent version of proton installed."));
-						match cmd.spawn().and_then(|p| p.wait_with_output()) {
-							Ok(v) => {
-								info!("zenity finished: {}\n{:?}\n{:?}", v.status, v.stdout, v.stderr)
-							},
-							Err(e) => {
-								warn!("fatal error remains unnoticed: {e}")
-							},
+					if let Err(e) = vive.set_cameras_enabled(enabled) {
+						warn!("failed to toggle cameras: {e}");
+					}
+					enabled
+				};
+
+				let settings = Settings {
+					resolution: Some(mode.id),
+					brightness: Some(brightness),
+					noise_cancel: Some(nc),
+					cameras: Some(cameras_enabled),
+				};
+				if let Some(devsn) = &devsn {
+					if let Err(e) = headsets::update(devsn, &settings) {
+						warn!("failed to store headset settings: {e}");
+					}
+				}
+
+				// Status is sent continuously, first one is enough to pick cached distortion
+				let ipd = match vive.read_status(STATUS_TIMEOUT_MS) {
+					Ok(status) => status.map(|s| s.ipd_meters()),
+					Err(e) => {
+						warn!("failed to read headset status: {e}");
+						None
+					}
+				};
+				let vive = Arc::new(Mutex::new(vive));
+				let settings = Arc::new(Mutex::new(settings));
+				let display = Arc::new(Mutex::new(
+					Display::new(&vive_config, mode).with_timing(live::timing_override()),
+				));
+				live::init(
+					vive.clone(),
+					display.clone(),
+					settings.clone(),
+					devsn.clone(),
+					sn.clone(),
+					edid,
+				);
+
+				if let Some(path) = &config.lens_server {
+					// Preferred by lens server lookup
+					env::set_var("LENS_SERVER_EXE", path);
+				}
+				if let Some(wine) = &config.wine {
+					env::set_var("WINE", wine);
+				}
+				if let Some(proton) = &config.proton {
+					env::set_var("PROTON", proton);
+				}
+				if let Some(prefix) = &config.wine_prefix {
+					env::set_var("WINEPREFIX", prefix);
+				}
+				if let Some(address) = &config.lens_server_address {
+					env::set_var(ADDRESS_ENV, address.to_string());
+				}
+				let lens_fallback = Arc::new(AtomicBool::new(simulated));
+				// Started on its own thread, so lens server startup and distortion computation
+				// overlap the rest of activation
+				let lens = Rc::new(AsyncClient::start({
+					let correction = vive_config.inhouse_lens_correction.clone();
+					let (backend, mode) = (config.lens, mode.id);
+					let lens_fallback = lens_fallback.clone();
+					move || {
+						if simulated {
+							// Lens server needs real calibration
+							info!("headset is simulated, using built-in lens distortion");
+							return Rc::new(FallbackLens::new(&correction)) as Rc<dyn LensClient>;
 						}
-						error!("lens server start failed: {e}");
-						Rc::new(StubClient)
-					});
+						lens::start(backend, &correction, || {
+							let key = CacheKey::new(&correction, mode, ipd);
+							let start = {
+								let correction = correction.clone();
+								move || {
+									SupervisedClient::start(correction).map(|v| {
+										let v =
+											v.on_restart(|| ipc::emit(Event::LensServerRestarted));
+										Rc::new(v) as Rc<dyn LensClient>
+									})
+								}
+							};
+							let fallback = {
+								let correction = correction.clone();
+								let lens_fallback = lens_fallback.clone();
+								move |e| {
+									lens_fallback.store(true, Ordering::Relaxed);
+									dialog::show(dialog::Kind::Warning, format!("Lens distortion helper is failed to launch, HMD image uses approximate built-in distortion, and will look warped near the edges.\nError: {e}\n\nMake sure you have any recent version of proton installed."));
+									error!("lens server start failed: {e}");
+									warn!("running in degraded mode, with approximate lens distortion");
+									Rc::new(FallbackLens::new(&correction)) as Rc<dyn LensClient>
+								}
+							};
+							Rc::new(CachedClient::new(key, start, fallback))
+						})
+					}
+				}));
+				let meshes = Rc::new(Meshes::request(lens.clone(), ipd));
+				ipc::start(IpcState {
+					vive: vive.clone(),
+					serial: sn.clone(),
+					devsn,
+					settings,
+					lens_fallback,
+				});
 				let real = unsafe { VtableRef::from_raw(pDriver) };
 
-				let hmd = Box::leak(Box::new(WithVtables::new(HmdDriver {
-					// steam,
+				let added = Added {
+					serial: sn.clone(),
 					vive,
 					vive_config,
+					cameras,
 					lens,
-					real,
-					mode,
-				})));
+					meshes,
+					display,
+					edid_override: edid.filter(|_| config.direct_mode.overrides_id()),
+				};
+				let hmd = added.wrap(real);
+				ADDED.with(|a| *a.borrow_mut() = Some(added));
 
 				return self.real.TrackedDeviceAdded(
 					pchDeviceSerialNumber,
@@ -113,7 +365,9 @@ impl IVRServerDriverHost for DriverHost {
 					HasVtable::<ITrackedDeviceServerDriverVtable>::get(hmd),
 				);
 			};
-			error!("failed to wrap hmd: {}", err.err().unwrap());
+			let err = err.err().unwrap();
+			error!("failed to wrap hmd: {err}");
+			dialog::show(dialog::Kind::Error, format!("VIVE Pro 2 headset failed to initialize, display will stay black.\nError: {err}\n\nIf it persists after reconnecting the headset, its firmware may be incompatible, update it with VIVE Console on Windows.\nRun vivectl doctor for details."));
 		}
 		self.real
 			.TrackedDeviceAdded(pchDeviceSerialNumber, eDeviceClass, pDriver)
@@ -149,7 +403,14 @@ impl IVRServerDriverHost for DriverHost {
 	}
 
 	fn PollNextEvent(&self, pEvent: *mut VREvent_t, uncbVREvent: u32) -> bool {
-		self.real.PollNextEvent(pEvent, uncbVREvent)
+		if !self.real.PollNextEvent(pEvent, uncbVREvent) {
+			return false;
+		}
+		let event = unsafe { &*pEvent };
+		if event.eventType == EVREventType::VREvent_SceneApplicationChanged as u32 {
+			apps::scene_changed(unsafe { event.data.process }.pid);
+		}
+		true
 	}
 
 	fn GetRawTrackedDevicePoses(
@@ -210,6 +471,39 @@ impl IVRServerDriverHost for DriverHost {
 	}
 }
 
+/// About a second of frames at 90Hz
+const FRAME_TIMING_FRAMES: usize = 90;
+
+/// Summary of recent compositor frames, for metrics
+pub fn frame_timing() -> FrameTiming {
+	let mut timings: Vec<Compositor_FrameTiming> =
+		vec![unsafe { std::mem::zeroed() }; FRAME_TIMING_FRAMES];
+	// Only the first element size is checked
+	timings[0].m_nSize = std::mem::size_of::<Compositor_FrameTiming>() as u32;
+	let count = DRIVER_HOST
+		.real
+		.GetFrameTimings(timings.as_mut_ptr(), FRAME_TIMING_FRAMES as u32) as usize;
+	let timings = &timings[..count.min(FRAME_TIMING_FRAMES)];
+	if timings.is_empty() {
+		return FrameTiming::default();
+	}
+	let avg = |f: fn(&Compositor_FrameTiming) -> f32| {
+		timings.iter().map(f).sum::<f32>() / timings.len() as f32
+	};
+	FrameTiming {
+		frames: timings.len() as u32,
+		dropped_frames: timings.iter().map(|t| t.m_nNumDroppedFrames).sum(),
+		mispresented_frames: timings.iter().map(|t| t.m_nNumMisPresented).sum(),
+		reprojected_frames: timings
+			.iter()
+			.filter(|t| t.m_nReprojectionFlags != 0)
+			.count() as u32,
+		gpu_ms: avg(|t| t.m_flTotalRenderGpuMs),
+		compositor_cpu_ms: avg(|t| t.m_flCompositorRenderCpuMs),
+		frame_interval_ms: avg(|t| t.m_flClientFrameIntervalMs),
+	}
+}
+
 pub static DRIVER_HOST: Lazy<WithVtables<DriverHost>> = Lazy::new(|| {
 	let context = DRIVER_CONTEXT
 		.get()
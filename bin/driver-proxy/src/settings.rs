@@ -4,9 +4,10 @@ use std::{marker::PhantomData, os::raw::c_char};
 use cppvtbl::VtableRef;
 use once_cell::sync::Lazy;
 use openvr::{
-	k_unBoolPropertyTag, k_unFloatPropertyTag, EPropertyWriteType, ETrackedDeviceProperty,
-	ETrackedPropertyError, IVRProperties, IVRPropertiesVtable, IVRProperties_Version,
-	PropertyWrite_t,
+	k_unBoolPropertyTag, k_unFloatPropertyTag, k_unHiddenAreaPropertyTag, k_unInt32PropertyTag,
+	k_unStringPropertyTag, EHiddenAreaMeshType, EPropertyWriteType, ETrackedDeviceProperty,
+	ETrackedPropertyError, EVREye, HmdVector2_t, IVRProperties, IVRPropertiesVtable,
+	IVRProperties_Version, PropertyRead_t, PropertyTypeTag_t, PropertyWrite_t,
 };
 use tracing::{error, instrument};
 
@@ -48,6 +49,7 @@ macro_rules! impl_setting {
 }
 impl_setting!(i32, GetInt32, SetInt32, 0);
 impl_setting!(bool, GetBool, SetBool, false);
+impl_setting!(f32, GetFloat, SetFloat, 0.0);
 
 const STRING_SIZE: usize = 65535;
 impl Setting<String> {
@@ -112,12 +114,16 @@ pub enum PropertyValue {
 	Float(f32),
 	FloatArray(Vec<f32>),
 	Bool(bool),
+	Int32(i32),
+	String(CString),
 }
 impl PropertyValue {
 	fn tag(&self) -> u32 {
 		match self {
 			Self::Float(_) | Self::FloatArray(_) => k_unFloatPropertyTag,
 			Self::Bool(_) => k_unBoolPropertyTag,
+			Self::Int32(_) => k_unInt32PropertyTag,
+			Self::String(_) => k_unStringPropertyTag,
 		}
 	}
 	fn size(&self) -> u32 {
@@ -125,6 +131,9 @@ impl PropertyValue {
 			PropertyValue::Float(_) => 4,
 			PropertyValue::FloatArray(v) => 4 * v.len() as u32,
 			PropertyValue::Bool(_) => 1,
+			PropertyValue::Int32(_) => 4,
+			// With terminating nul
+			PropertyValue::String(v) => v.as_bytes_with_nul().len() as u32,
 		}
 	}
 	fn buf(&mut self) -> *mut c_void {
@@ -132,6 +141,9 @@ impl PropertyValue {
 			PropertyValue::Float(f) => (f as *mut f32).cast(),
 			PropertyValue::FloatArray(f) => f.as_mut_ptr().cast(),
 			PropertyValue::Bool(v) => (v as *mut bool).cast(),
+			PropertyValue::Int32(v) => (v as *mut i32).cast(),
+			// Only read by vrserver
+			PropertyValue::String(v) => v.as_ptr() as *mut c_void,
 		}
 	}
 }
@@ -161,6 +173,59 @@ pub fn set_properties(container: u64, mut props: Vec<Property>) {
 	PROPERTIES.WritePropertyBatch(container, batch.as_mut_ptr(), batch.len() as u32);
 }
 
+/// `None` if property is not set, i.e by lighthouse driver
+pub fn get_bool_property(container: u64, name: ETrackedDeviceProperty) -> Option<bool> {
+	let mut value = false;
+	let mut read = PropertyRead_t {
+		prop: name,
+		pvBuffer: (&mut value as *mut bool).cast(),
+		unBufferSize: 1,
+		unTag: k_unBoolPropertyTag,
+		unRequiredBufferSize: 0,
+		eError: ETrackedPropertyError::TrackedProp_Success,
+	};
+	PROPERTIES.ReadPropertyBatch(container, &mut read, 1);
+	(read.eError == ETrackedPropertyError::TrackedProp_Success && read.unTag == k_unBoolPropertyTag)
+		.then_some(value)
+}
+
+/// [`PropertyWrite_t`], but with property as a plain number, as hidden area properties are in
+/// `Prop_DisplayHiddenArea_Binary_Start..End` range, and have no enum variants
+#[repr(C)]
+struct RawPropertyWrite {
+	prop: i32,
+	write_type: EPropertyWriteType,
+	set_error: ETrackedPropertyError,
+	buffer: *mut c_void,
+	buffer_size: u32,
+	tag: PropertyTypeTag_t,
+	error: ETrackedPropertyError,
+}
+
+/// Same as `CVRHiddenAreaHelpers::SetHiddenArea` of openvr_driver.h
+pub fn set_hidden_area(
+	container: u64,
+	eye: EVREye,
+	mesh_type: EHiddenAreaMeshType,
+	vertices: &mut [HmdVector2_t],
+) {
+	let mut write = RawPropertyWrite {
+		prop: ETrackedDeviceProperty::Prop_DisplayHiddenArea_Binary_Start as i32
+			+ mesh_type as i32 * 2
+			+ eye as i32,
+		write_type: EPropertyWriteType::PropertyWrite_Set,
+		set_error: ETrackedPropertyError::TrackedProp_Success,
+		buffer: vertices.as_mut_ptr().cast(),
+		buffer_size: std::mem::size_of_val(vertices) as u32,
+		tag: k_unHiddenAreaPropertyTag,
+		error: ETrackedPropertyError::TrackedProp_Success,
+	};
+	PROPERTIES.WritePropertyBatch(container, (&mut write as *mut RawPropertyWrite).cast(), 1);
+	if write.set_error != ETrackedPropertyError::TrackedProp_Success {
+		error!("failed to set hidden area: {:?}", write.set_error);
+	}
+}
+
 pub static PROPERTIES: Lazy<&'static VtableRef<IVRPropertiesVtable>> = Lazy::new(|| {
 	let ctx = DRIVER_CONTEXT
 		.get()
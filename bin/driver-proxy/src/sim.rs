@@ -0,0 +1,253 @@
+//! Simulated HMD, for development without hardware. Lighthouse driver doesn't add anything
+//! without tracking board, so this one is added in its place, and is wrapped the same way, with
+//! headset itself simulated by [`vive_hid::sim`]
+//!
+//! Pose is fixed, image goes to a desktop window, or nowhere with `output = "null"`
+use std::{
+	ffi::{c_void, CStr, CString},
+	os::raw::c_char,
+	sync::Mutex,
+};
+
+use cppvtbl::{impl_vtables, HasVtable, VtableRef, WithVtables};
+use once_cell::sync::OnceCell;
+use openvr::{ETrackedDeviceProperty, ETrackingResult, HmdQuaternion_t, HmdVector2_t};
+use tracing::info;
+use vive_hid::sim::STEAM_SERIAL;
+
+use crate::{
+	config::{SimOutput, Simulate},
+	driver_host::DRIVER_HOST,
+	openvr::{
+		DistortionCoordinates_t, DriverPose_t, ETrackedDeviceClass, EVREye, EVRInitError,
+		ITrackedDeviceServerDriver, ITrackedDeviceServerDriverVtable, IVRDisplayComponent,
+		IVRDisplayComponentVtable, IVRDisplayComponent_Version, IVRProperties, IVRServerDriverHost,
+	},
+	settings::{set_properties, Property, PropertyValue, PROPERTIES},
+};
+
+/// Standing user, at the origin of the play area
+const HEAD_POSITION: [f64; 3] = [0.0, 1.7, 0.0];
+const IPD: f32 = 0.063;
+
+#[impl_vtables(IVRDisplayComponent)]
+struct SimDisplay {
+	window: (u32, u32),
+}
+impl IVRDisplayComponent for SimDisplay {
+	fn GetWindowBounds(&self, pnX: *mut i32, pnY: *mut i32, pnWidth: *mut u32, pnHeight: *mut u32) {
+		unsafe {
+			*pnX = 0;
+			*pnY = 0;
+			*pnWidth = self.window.0;
+			*pnHeight = self.window.1;
+		}
+	}
+
+	fn IsDisplayOnDesktop(&self) -> bool {
+		true
+	}
+
+	fn IsDisplayRealDisplay(&self) -> bool {
+		false
+	}
+
+	fn GetRecommendedRenderTargetSize(&self, pnWidth: *mut u32, pnHeight: *mut u32) {
+		unsafe {
+			*pnWidth = self.window.0 / 2;
+			*pnHeight = self.window.1;
+		}
+	}
+
+	fn GetEyeOutputViewport(
+		&self,
+		eEye: EVREye,
+		pnX: *mut u32,
+		pnY: *mut u32,
+		pnWidth: *mut u32,
+		pnHeight: *mut u32,
+	) {
+		let width = self.window.0 / 2;
+		unsafe {
+			*pnX = match eEye {
+				EVREye::Eye_Left => 0,
+				EVREye::Eye_Right => width,
+			};
+			*pnY = 0;
+			*pnWidth = width;
+			*pnHeight = self.window.1;
+		}
+	}
+
+	fn GetProjectionRaw(
+		&self,
+		_eEye: EVREye,
+		pfLeft: *mut f32,
+		pfRight: *mut f32,
+		pfTop: *mut f32,
+		pfBottom: *mut f32,
+	) {
+		unsafe {
+			*pfLeft = -1.0;
+			*pfRight = 1.0;
+			*pfTop = -1.0;
+			*pfBottom = 1.0;
+		}
+	}
+
+	fn ComputeDistortion(&self, _eEye: EVREye, fU: f32, fV: f32) -> DistortionCoordinates_t {
+		DistortionCoordinates_t {
+			rfRed: [fU, fV],
+			rfGreen: [fU, fV],
+			rfBlue: [fU, fV],
+		}
+	}
+
+	fn ComputeInverseDistortion(
+		&self,
+		_idk1: *mut HmdVector2_t,
+		_eEye: EVREye,
+		_fU: f32,
+		_fV: f32,
+	) -> i32 {
+		0
+	}
+}
+
+#[impl_vtables(ITrackedDeviceServerDriver)]
+struct SimHmd {
+	display: Option<&'static WithVtables<SimDisplay>>,
+	object_id: Mutex<Option<u32>>,
+}
+impl ITrackedDeviceServerDriver for SimHmd {
+	fn Activate(&self, unObjectId: u32) -> EVRInitError {
+		*self.object_id.lock().expect("lock") = Some(unObjectId);
+		let container = PROPERTIES.TrackedDeviceToPropertyContainer(unObjectId);
+		let string = |v: &str| PropertyValue::String(CString::new(v).expect("no nul"));
+		set_properties(
+			container,
+			vec![
+				Property::new(
+					ETrackedDeviceProperty::Prop_TrackingSystemName_String,
+					string("lighthouse"),
+				),
+				Property::new(
+					ETrackedDeviceProperty::Prop_ModelNumber_String,
+					string("VIVE Pro 2 (simulated)"),
+				),
+				Property::new(
+					ETrackedDeviceProperty::Prop_ManufacturerName_String,
+					string("HTC"),
+				),
+				Property::new(
+					ETrackedDeviceProperty::Prop_SerialNumber_String,
+					string(STEAM_SERIAL),
+				),
+				Property::new(
+					ETrackedDeviceProperty::Prop_UserIpdMeters_Float,
+					PropertyValue::Float(IPD),
+				),
+				Property::new(
+					ETrackedDeviceProperty::Prop_IsOnDesktop_Bool,
+					PropertyValue::Bool(self.display.is_some()),
+				),
+				Property::new(
+					ETrackedDeviceProperty::Prop_HasDisplayComponent_Bool,
+					PropertyValue::Bool(self.display.is_some()),
+				),
+			],
+		);
+		EVRInitError::VRInitError_None
+	}
+
+	fn Deactivate(&self) {
+		*self.object_id.lock().expect("lock") = None;
+	}
+
+	fn EnterStandby(&self) {}
+
+	fn GetComponent(&self, pchComponentNameAndVersion: *const c_char) -> *mut c_void {
+		let name = unsafe { CStr::from_ptr(pchComponentNameAndVersion) };
+		match self.display {
+			Some(display) if name == unsafe { CStr::from_ptr(IVRDisplayComponent_Version) } => {
+				VtableRef::into_raw(HasVtable::<IVRDisplayComponentVtable>::get(display)) as *mut _
+			}
+			_ => std::ptr::null_mut(),
+		}
+	}
+
+	fn DebugRequest(
+		&self,
+		_pchRequest: *const c_char,
+		pchResponseBuffer: *mut c_char,
+		unResponseBufferSize: u32,
+	) {
+		if unResponseBufferSize != 0 {
+			unsafe { *pchResponseBuffer = 0 };
+		}
+	}
+
+	fn GetPose(&self) -> DriverPose_t {
+		pose()
+	}
+}
+
+fn pose() -> DriverPose_t {
+	let identity = HmdQuaternion_t {
+		w: 1.0,
+		x: 0.0,
+		y: 0.0,
+		z: 0.0,
+	};
+	// Everything else is zero, as the head doesn't move
+	let mut pose: DriverPose_t = unsafe { std::mem::zeroed() };
+	pose.qWorldFromDriverRotation = identity;
+	pose.qDriverFromHeadRotation = identity;
+	pose.qRotation = identity;
+	pose.vecPosition = HEAD_POSITION;
+	pose.result = ETrackingResult::TrackingResult_Running_OK;
+	pose.poseIsValid = true;
+	pose.deviceIsConnected = true;
+	pose
+}
+
+static HMD: OnceCell<&'static WithVtables<SimHmd>> = OnceCell::new();
+
+/// Adds HMD through the same driver host lighthouse driver uses, only first call has effect
+pub fn add(config: &Simulate) {
+	let mut added = false;
+	let hmd = HMD.get_or_init(|| {
+		added = true;
+		let display = (config.output == SimOutput::Window).then(|| {
+			&*Box::leak(Box::new(WithVtables::new(SimDisplay {
+				window: config.window,
+			})))
+		});
+		Box::leak(Box::new(WithVtables::new(SimHmd {
+			display,
+			object_id: Mutex::new(None),
+		})))
+	});
+	if !added {
+		return;
+	}
+	info!("adding simulated hmd {STEAM_SERIAL}");
+	let serial = CString::new(STEAM_SERIAL).expect("no nul");
+	DRIVER_HOST.TrackedDeviceAdded(
+		serial.as_ptr(),
+		ETrackedDeviceClass::TrackedDeviceClass_HMD,
+		HasVtable::<ITrackedDeviceServerDriverVtable>::get(*hmd),
+	);
+}
+
+/// Vrserver expects pose updates from driver, even if pose never changes
+pub fn run_frame() {
+	let Some(hmd) = HMD.get() else {
+		return;
+	};
+	let Some(id) = *hmd.object_id.lock().expect("lock") else {
+		return;
+	};
+	let pose = pose();
+	DRIVER_HOST.TrackedDevicePoseUpdated(id, &pose, std::mem::size_of::<DriverPose_t>() as u32);
+}
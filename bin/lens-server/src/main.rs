@@ -5,11 +5,12 @@ use std::{
 	sync::atomic::{AtomicBool, Ordering},
 };
 
-use anyhow::{ensure, Context, Result};
-use lens_protocol::{DistortOutput, Eye, LeftRightTopBottom, Request, Server};
+use anyhow::{bail, ensure, Context, Result};
+use lens_geometry::LensIntrinsics;
+use lens_protocol::{Address, DistortOutput, Eye, LeftRightTopBottom, Listener, Request, Server};
 use libloading::{Library, Symbol};
 use serde_json::Value;
-use tracing::info;
+use tracing::{info, warn};
 
 static CREATED: AtomicBool = AtomicBool::new(false);
 
@@ -112,17 +113,11 @@ impl LensLibrary {
 		})
 	}
 	fn projection_raw(&self, eye: Eye) -> Result<LeftRightTopBottom> {
-		let mut g = self.m.grow_for_undistort(eye)?;
-		for v in g.iter_mut() {
-			*v += 1.0;
+		Ok(LensIntrinsics {
+			intrinsic: self.m.intrinsic(eye)?,
+			grow_for_undistort: self.m.grow_for_undistort(eye)?,
 		}
-		let i = self.m.intrinsic(eye)?;
-		Ok(LeftRightTopBottom {
-			left: (-1.0 - i[2]) * g[0] / i[0],
-			right: (1.0 - i[2]) * g[1] / i[0],
-			top: (1.0 - i[4 + 1]) * g[2] / i[4],
-			bottom: (-1.0 - i[4 + 1]) * g[3] / i[4],
-		})
+		.projection_raw())
 	}
 }
 
@@ -145,15 +140,40 @@ fn main() -> Result<()> {
 	dll_path.push("LibLensDistortion.dll");
 	info!("dll path: {dll_path:?}");
 
-	let mut server = Server::listen();
-
+	// `--listen <address>` serves clients over a socket, one at a time, stdio is used otherwise
+	let listen = match args().skip(1).collect::<Vec<_>>().as_slice() {
+		[] => None,
+		[flag, address] if flag == "--listen" => Some(address.parse::<Address>()?),
+		_ => bail!("usage: lens-server [--listen unix:<path>|tcp:<host>:<port>]"),
+	};
+	let Some(address) = listen else {
+		let server = Server::listen();
+		let library = unsafe { LensLibrary::new(dll_path, (2448, 2448))? };
+		return serve(server, &library);
+	};
+	let listener =
+		Listener::bind(&address).with_context(|| format!("failed to listen on {address}"))?;
 	let library = unsafe { LensLibrary::new(dll_path, (2448, 2448))? };
+	info!("listening on {address}");
+	loop {
+		let server = Server::accept(&listener).context("failed to accept client")?;
+		info!("client connected");
+		if let Err(e) = serve(server, &library) {
+			warn!("client failed: {e:#}");
+		}
+	}
+}
 
+/// Until client sends exit
+fn serve(mut server: Server, library: &LensLibrary) -> Result<()> {
 	loop {
 		let req = server.recv().context("failed to read request")?;
 		match req {
 			Request::Init(config) => {
-				info!("set config");
+				info!(
+					"set config, protocol version {}",
+					server.negotiated().version
+				);
 				library.set_config(config)?;
 			}
 			Request::Distort(eye, uv) => {
@@ -169,6 +189,16 @@ fn main() -> Result<()> {
 				info!("received exit signal");
 				break;
 			}
+			Request::Hello(_) | Request::AttachShm(_) | Request::DistortBatchShm(_) => {
+				unreachable!("answered by server")
+			}
+			Request::DistortBatch(points) => {
+				let out = points
+					.into_iter()
+					.map(|(eye, uv)| library.distort(eye, uv))
+					.collect::<Result<Vec<_>>>()?;
+				server.send(&out)?;
+			}
 		}
 	}
 	Ok(())
@@ -0,0 +1,213 @@
+use eframe::egui;
+use vive_ipc::{Request, Settings};
+use vive_protocol::Resolution;
+
+use crate::{
+	backend::{Backend, Snapshot},
+	color,
+};
+
+/// Shown for unset brightness, same as daemon applies on wake
+const DEFAULT_BRIGHTNESS: u8 = 130;
+const WARNING: egui::Color32 = egui::Color32::from_rgb(0xd0, 0x80, 0x00);
+const ERROR: egui::Color32 = egui::Color32::from_rgb(0xc0, 0x30, 0x30);
+
+/// Sliders are applied once released, not on every step of drag
+fn committed(response: &egui::Response) -> bool {
+	response.drag_released() || (response.changed() && !response.dragged())
+}
+
+pub struct App {
+	backend: Backend,
+	/// Daemon settings edits were synced from, edits stay until daemon reports something else
+	seen: Option<Settings>,
+	brightness: u8,
+	noise_cancel: bool,
+	/// Driver config overrides calibration stored on the headset
+	custom_color: bool,
+	gain: [f32; 3],
+	color_error: Option<String>,
+}
+impl App {
+	pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+		let (gain, color_error) = match color::load() {
+			Ok(gain) => (gain, None),
+			Err(e) => (None, Some(format!("{e:#}"))),
+		};
+		Self {
+			backend: Backend::start(cc.egui_ctx.clone()),
+			seen: None,
+			brightness: DEFAULT_BRIGHTNESS,
+			noise_cancel: false,
+			custom_color: gain.is_some(),
+			gain: gain.unwrap_or([1.0; 3]),
+			color_error,
+		}
+	}
+
+	fn sync(&mut self, settings: &Settings) {
+		if self.seen.as_ref() == Some(settings) {
+			return;
+		}
+		self.brightness = settings.brightness.unwrap_or(DEFAULT_BRIGHTNESS);
+		self.noise_cancel = settings.noise_cancel.unwrap_or(false);
+		self.seen = Some(settings.clone());
+	}
+
+	fn status(&self, ui: &mut egui::Ui, snapshot: &Snapshot) {
+		let Some(status) = &snapshot.status else {
+			ui.colored_label(WARNING, "vived is not running");
+			if let Some(reason) = &snapshot.unreachable {
+				ui.small(reason);
+			}
+			return;
+		};
+		let state = status.state.as_deref().unwrap_or(if status.connected {
+			"ready"
+		} else {
+			"disconnected"
+		});
+		ui.horizontal(|ui| {
+			ui.strong("Headset");
+			ui.label(state);
+			if let Some(serial) = &status.serial {
+				ui.monospace(serial);
+			}
+		});
+		if status.standby {
+			ui.label("Display is in standby");
+		}
+		if status.lens_fallback {
+			ui.colored_label(
+				WARNING,
+				"Lens server has failed, driver uses approximate lens distortion",
+			);
+		}
+		if snapshot.busy {
+			ui.horizontal(|ui| {
+				ui.spinner();
+				ui.label("Applying");
+			});
+		}
+		if let Some(error) = &snapshot.error {
+			ui.colored_label(ERROR, error);
+		}
+	}
+
+	fn settings(&mut self, ui: &mut egui::Ui, snapshot: &Snapshot) {
+		let resolution = snapshot
+			.status
+			.as_ref()
+			.and_then(|s| s.settings.resolution)
+			.and_then(|r| Resolution::try_from(r).ok());
+		egui::Grid::new("settings").num_columns(2).show(ui, |ui| {
+			ui.label("Mode");
+			egui::ComboBox::from_id_source("mode")
+				.selected_text(resolution.map_or("not set".to_owned(), |r| r.to_string()))
+				.show_ui(ui, |ui| {
+					for option in Resolution::ALL {
+						let selected = resolution == Some(option);
+						if ui.selectable_label(selected, option.to_string()).clicked() && !selected
+						{
+							self.backend.send(Request::SetResolution {
+								resolution: option as u8,
+							});
+						}
+					}
+				});
+			ui.end_row();
+
+			ui.label("Brightness");
+			if committed(&ui.add(egui::Slider::new(&mut self.brightness, 1..=130))) {
+				self.backend.send(Request::SetBrightness {
+					brightness: self.brightness,
+				});
+			}
+			ui.end_row();
+
+			ui.label("Noise canceling");
+			if ui.checkbox(&mut self.noise_cancel, "").changed() {
+				self.backend.send(Request::SetNoiseCancel {
+					enabled: self.noise_cancel,
+				});
+			}
+			ui.end_row();
+
+			ui.label("Profile");
+			ui.horizontal_wrapped(|ui| {
+				for name in snapshot.profiles.keys() {
+					let active = snapshot.active_profile.as_ref() == Some(name);
+					if ui.selectable_label(active, name).clicked() {
+						self.backend
+							.send(Request::SetProfile { name: name.clone() });
+					}
+				}
+			});
+			ui.end_row();
+		});
+		ui.small(
+			"Headset reconnects after mode switch, which isn't possible during SteamVR session",
+		);
+	}
+
+	fn store_color(&mut self) {
+		self.color_error = color::store(self.custom_color.then_some(self.gain))
+			.err()
+			.map(|e| format!("{e:#}"));
+	}
+	fn color(&mut self, ui: &mut egui::Ui) {
+		ui.horizontal(|ui| {
+			ui.strong("Color");
+			if ui
+				.checkbox(&mut self.custom_color, "Override headset calibration")
+				.changed()
+			{
+				self.store_color();
+			}
+		});
+		let mut changed = false;
+		ui.add_enabled_ui(self.custom_color, |ui| {
+			for (name, gain) in ["Red", "Green", "Blue"].into_iter().zip(&mut self.gain) {
+				let slider = egui::Slider::new(gain, 0.0..=1.0).step_by(0.01).text(name);
+				changed |= committed(&ui.add(slider));
+			}
+		});
+		if changed {
+			self.store_color();
+		}
+		ui.small("Applied by driver, during SteamVR session or on its start");
+		if let Some(error) = &self.color_error {
+			ui.colored_label(ERROR, error);
+		}
+	}
+}
+
+impl eframe::App for App {
+	fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+		// Not held while drawing, backend threads update it meanwhile
+		let snapshot = self.backend.snapshot().clone();
+		if let Some(status) = &snapshot.status {
+			self.sync(&status.settings);
+		}
+		egui::CentralPanel::default().show(ctx, |ui| {
+			self.status(ui, &snapshot);
+			ui.separator();
+			ui.add_enabled_ui(snapshot.status.is_some() && !snapshot.busy, |ui| {
+				self.settings(ui, &snapshot);
+			});
+			ui.separator();
+			self.color(ui);
+			ui.separator();
+			egui::CollapsingHeader::new("Events").show(ui, |ui| {
+				egui::ScrollArea::vertical()
+					.max_height(160.0)
+					.stick_to_bottom(true)
+					.show(ui, |ui| {
+						for event in &snapshot.events {
+							ui.monospace(event);
+						}
+					});
+			});
+		});
+	}
+}
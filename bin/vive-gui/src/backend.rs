@@ -0,0 +1,152 @@
+//! Daemon connection, kept off UI thread, as mode switch request blocks until headset is back
+use std::{
+	collections::{BTreeMap, VecDeque},
+	sync::{
+		mpsc::{self, Receiver, RecvTimeoutError, Sender},
+		Arc, Mutex, MutexGuard,
+	},
+	thread,
+	time::Duration,
+};
+
+use eframe::egui;
+use vive_ipc::{Client, Error, Request, Response, Settings, Status};
+
+/// Status is polled this often, in addition to refresh on every daemon event
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_EVENTS: usize = 50;
+
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+	/// None while daemon is unreachable
+	pub status: Option<Status>,
+	pub profiles: BTreeMap<String, Settings>,
+	pub active_profile: Option<String>,
+	/// Request is in flight
+	pub busy: bool,
+	/// Why status is None
+	pub unreachable: Option<String>,
+	/// Daemon refused last request
+	pub error: Option<String>,
+	/// Latest daemon events as JSON, newest last
+	pub events: VecDeque<String>,
+}
+
+enum Command {
+	Refresh,
+	Request(Request),
+}
+
+pub struct Backend {
+	shared: Arc<Mutex<Snapshot>>,
+	tx: Sender<Command>,
+}
+impl Backend {
+	/// UI is repainted on every change
+	pub fn start(ctx: egui::Context) -> Self {
+		let shared = Arc::new(Mutex::new(Snapshot::default()));
+		let (tx, rx) = mpsc::channel();
+		{
+			let shared = shared.clone();
+			let ctx = ctx.clone();
+			thread::spawn(move || run(&shared, rx, &ctx));
+		}
+		{
+			let shared = shared.clone();
+			let tx = tx.clone();
+			thread::spawn(move || watch(&shared, tx, &ctx));
+		}
+		Self { shared, tx }
+	}
+	pub fn snapshot(&self) -> MutexGuard<'_, Snapshot> {
+		self.shared.lock().expect("lock")
+	}
+	pub fn send(&self, request: Request) {
+		self.snapshot().busy = true;
+		let _ = self.tx.send(Command::Request(request));
+	}
+}
+
+/// Sends `request` if any, then reads everything shown
+fn exchange(
+	client: &mut Option<Client>,
+	request: Option<&Request>,
+	shared: &Mutex<Snapshot>,
+) -> Result<(), Error> {
+	let client = match client {
+		Some(client) => client,
+		None => client.insert(Client::connect_default()?),
+	};
+	if let Some(request) = request {
+		client.request(request)?;
+	}
+	let status = client.status()?;
+	let Response::Profiles { active, profiles } = client.request(&Request::Profiles)? else {
+		return Err(Error::UnexpectedResponse);
+	};
+	let mut snapshot = shared.lock().expect("lock");
+	snapshot.status = Some(status);
+	snapshot.profiles = profiles;
+	snapshot.active_profile = active;
+	snapshot.unreachable = None;
+	if request.is_some() {
+		snapshot.error = None;
+	}
+	Ok(())
+}
+
+fn run(shared: &Mutex<Snapshot>, rx: Receiver<Command>, ctx: &egui::Context) {
+	let mut client = None;
+	loop {
+		let request = match rx.recv_timeout(POLL_INTERVAL) {
+			Ok(Command::Request(request)) => Some(request),
+			Ok(Command::Refresh) | Err(RecvTimeoutError::Timeout) => None,
+			Err(RecvTimeoutError::Disconnected) => return,
+		};
+		let res = exchange(&mut client, request.as_ref(), shared);
+		let mut snapshot = shared.lock().expect("lock");
+		match res {
+			Ok(()) => {}
+			// Connection is still fine
+			Err(Error::Remote(message)) => snapshot.error = Some(message),
+			Err(e) => {
+				client = None;
+				snapshot.status = None;
+				snapshot.unreachable = Some(e.to_string());
+			}
+		}
+		if request.is_some() {
+			snapshot.busy = false;
+		}
+		drop(snapshot);
+		ctx.request_repaint();
+	}
+}
+
+/// Follows daemon events, so changes made elsewhere are shown right away
+fn watch(shared: &Mutex<Snapshot>, tx: Sender<Command>, ctx: &egui::Context) {
+	loop {
+		if let Ok(events) = Client::connect_default().and_then(Client::subscribe) {
+			for event in events {
+				let Ok(event) = event else {
+					break;
+				};
+				{
+					let mut snapshot = shared.lock().expect("lock");
+					if snapshot.events.len() == MAX_EVENTS {
+						snapshot.events.pop_front();
+					}
+					snapshot
+						.events
+						.push_back(serde_json::to_string(&event).expect("event is serializable"));
+				}
+				if tx.send(Command::Refresh).is_err() {
+					return;
+				}
+				ctx.request_repaint();
+			}
+		}
+		thread::sleep(RECONNECT_INTERVAL);
+	}
+}
@@ -0,0 +1,53 @@
+//! Color gain has no daemon request, driver reads it from `color` key of its config, which it
+//! watches, so changes apply to running SteamVR session, and to the next one otherwise
+use std::{fs, io, path::PathBuf};
+
+use anyhow::{Context, Result};
+use vive_ipc::{
+	headsets,
+	toml::{self, Value},
+};
+
+fn config_path() -> PathBuf {
+	headsets::config_dir().join("driver.toml")
+}
+
+fn read() -> Result<String> {
+	let path = config_path();
+	match fs::read_to_string(&path) {
+		Ok(data) => Ok(data),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+		Err(e) => Err(e).with_context(|| format!("failed to read {path:?}")),
+	}
+}
+
+/// None if driver uses calibration stored on the headset
+pub fn load() -> Result<Option<[f32; 3]>> {
+	let data = read()?;
+	let config = toml::parse(&data)
+		.map_err(anyhow::Error::msg)
+		.with_context(|| format!("failed to parse {:?}", config_path()))?;
+	let Some(Value::Array(items)) = config.get("color") else {
+		return Ok(None);
+	};
+	let gain = items
+		.iter()
+		.map(|v| v.as_f64().map(|v| v as f32))
+		.collect::<Option<Vec<_>>>()
+		.and_then(|gain| <[f32; 3]>::try_from(gain).ok())
+		.context("color should be a list of three numbers")?;
+	Ok(Some(gain))
+}
+
+/// Rest of the config is kept as written, None removes the key
+pub fn store(gain: Option<[f32; 3]>) -> Result<()> {
+	let value = gain.map(|[r, g, b]| format!("[{r:?}, {g:?}, {b:?}]"));
+	let path = config_path();
+	let data = toml::set_keys(&read()?, &[("color", value)])
+		.map_err(anyhow::Error::msg)
+		.with_context(|| format!("failed to update {path:?}"))?;
+	if let Some(dir) = path.parent() {
+		fs::create_dir_all(dir)?;
+	}
+	fs::write(&path, data).with_context(|| format!("failed to write {path:?}"))
+}
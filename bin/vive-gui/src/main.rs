@@ -0,0 +1,32 @@
+//! Control panel for vived, for those who'd rather not use vivectl
+//!
+//! Everything goes through daemon socket, as with vivectl, except color, see [`color`]
+use std::{env::args, process::exit};
+
+use anyhow::{anyhow, Result};
+use eframe::egui;
+
+use crate::app::App;
+
+mod app;
+mod backend;
+mod color;
+
+const USAGE: &str = "\
+Usage: vive-gui
+
+Shows headset status and settings of vived, which should be running
+";
+
+fn main() -> Result<()> {
+	if args().len() > 1 {
+		eprint!("{USAGE}");
+		exit(2)
+	}
+	let options = eframe::NativeOptions {
+		viewport: egui::ViewportBuilder::default().with_inner_size([400.0, 560.0]),
+		..Default::default()
+	};
+	eframe::run_native("VIVE Pro 2", options, Box::new(|cc| Box::new(App::new(cc))))
+		.map_err(|e| anyhow!("{e}"))
+}
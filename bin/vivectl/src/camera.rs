@@ -0,0 +1,175 @@
+//! Headset cameras are plain UVC devices, frames are captured by kernel driver, and any V4L2
+//! consumer can read them. Here they are found by headset, and republished through
+//! v4l2loopback, with frame rate from tracking board config, so there is nothing to look up by hand
+use std::{
+	fs,
+	path::{Path, PathBuf},
+	process::Command,
+};
+
+use anyhow::{bail, Context, Result};
+use serde_json::json;
+use vive_hid::{ConfigCamera, SteamDevice};
+
+use crate::{device, usage, Opts};
+
+/// v4l2loopback devices are not backed by hardware, the rest of `/sys/class/video4linux` is
+const VIRTUAL_VIDEO: &str = "/sys/devices/virtual/video4linux";
+/// Most webcam consumers (browsers especially) only list loopback devices with
+/// `exclusive_caps=1`, which announce capture capability once there is a producer
+const LOOPBACK_HINT: &str =
+	"sudo modprobe v4l2loopback card_label=\"VIVE Pro 2 camera\" exclusive_caps=1";
+
+/// Capture node, with what config says about it
+struct Stream {
+	node: PathBuf,
+	/// Cameras, which share this stream (both, for stereo camera)
+	cameras: Vec<ConfigCamera>,
+	frame_rate: Option<u32>,
+}
+
+fn stream(node: Option<PathBuf>) -> Result<Stream> {
+	let node = match node {
+		Some(node) => node,
+		None => device::open()?
+			.camera_nodes()?
+			.into_iter()
+			.next()
+			.context("headset has no camera nodes, are cameras disabled?")?,
+	};
+	// Only for caps and info, stream works without it
+	let config = SteamDevice::open_first().and_then(|d| d.read_config()).ok();
+	Ok(Stream {
+		node,
+		cameras: config
+			.as_ref()
+			.map(|c| c.tracked_cameras.clone())
+			.unwrap_or_default(),
+		frame_rate: config.and_then(|c| c.camera?.frame_rate),
+	})
+}
+
+/// `gst-launch-1.0` syntax
+fn pipeline(stream: &Stream, sink: &str) -> String {
+	let caps = match stream.frame_rate {
+		Some(rate) => format!("video/x-raw,framerate={rate}/1"),
+		None => "video/x-raw".to_owned(),
+	};
+	format!(
+		"v4l2src device={} ! {caps} ! videoconvert ! {sink}",
+		stream.node.display()
+	)
+}
+
+fn info(opts: Opts, args: &[&str]) -> Result<()> {
+	if !args.is_empty() {
+		usage();
+	}
+	let nodes = device::open()?.camera_nodes()?;
+	let stream = stream(nodes.first().cloned()).ok();
+	let cameras = stream.as_ref().map_or(&[][..], |s| &s.cameras);
+	opts.print(
+		json!({
+			"nodes": nodes,
+			"frame_rate": stream.as_ref().and_then(|s| s.frame_rate),
+			"cameras": cameras
+				.iter()
+				.map(|c| json!({
+					"name": c.name,
+					"width": c.intrinsics.width,
+					"height": c.intrinsics.height,
+				}))
+				.collect::<Vec<_>>(),
+		}),
+		|| {
+			if nodes.is_empty() {
+				return "no camera nodes, cameras are disabled or not authorized".to_owned();
+			}
+			let mut out = nodes
+				.iter()
+				.map(|n| n.display().to_string())
+				.collect::<Vec<_>>()
+				.join("\n");
+			for camera in cameras {
+				let i = &camera.intrinsics;
+				out.push_str(&format!(
+					"\ncamera {}: {}x{}",
+					camera.name, i.width, i.height
+				));
+			}
+			if let Some(rate) = stream.as_ref().and_then(|s| s.frame_rate) {
+				out.push_str(&format!("\n{rate} fps"));
+			}
+			out
+		},
+	);
+	Ok(())
+}
+
+fn loopback_devices() -> Vec<PathBuf> {
+	let Ok(entries) = fs::read_dir(VIRTUAL_VIDEO) else {
+		return Vec::new();
+	};
+	let mut devices = entries
+		.flatten()
+		.map(|e| Path::new("/dev").join(e.file_name()))
+		.collect::<Vec<_>>();
+	devices.sort();
+	devices
+}
+
+/// Feeds cameras into v4l2loopback device, so webcam consumers (OBS, browsers, video calls) can
+/// use them, and more than one of them at the same time. Runs until interrupted
+fn loopback(opts: Opts, args: &[&str]) -> Result<()> {
+	let mut node = None;
+	let mut device = None;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		let mut value = || *args.next().unwrap_or_else(|| usage());
+		match *arg {
+			"--node" => node = Some(PathBuf::from(value())),
+			"--device" => device = Some(PathBuf::from(value())),
+			_ => usage(),
+		}
+	}
+	let device = match device {
+		Some(device) => device,
+		None => loopback_devices().into_iter().next().with_context(|| {
+			format!("no v4l2loopback devices, load the module with\n{LOOPBACK_HINT}")
+		})?,
+	};
+	let stream = stream(node)?;
+	// Raw YUYV is what every consumer of loopback understands
+	let sink = format!(
+		"video/x-raw,format=YUY2 ! v4l2sink device={} sync=false",
+		device.display()
+	);
+	let pipeline = pipeline(&stream, &sink);
+	opts.print(
+		json!({ "node": stream.node, "device": device, "pipeline": pipeline }),
+		|| {
+			format!(
+				"publishing {} to {}, interrupt to stop",
+				stream.node.display(),
+				device.display()
+			)
+		},
+	);
+	let status = Command::new("gst-launch-1.0")
+		.arg("-q")
+		.args(pipeline.split_whitespace())
+		.status()
+		.context("failed to run gst-launch-1.0, are GStreamer tools installed?")?;
+	if !status.success() {
+		bail!("gst-launch-1.0 failed: {status}");
+	}
+	Ok(())
+}
+
+pub fn run(opts: Opts, args: &[&str]) -> Result<()> {
+	match args {
+		["info", rest @ ..] => info(opts, rest),
+		["loopback", rest @ ..] => loopback(opts, rest),
+		_ => usage(),
+	}
+}
@@ -0,0 +1,694 @@
+//! Command tree, which USAGE and completion scripts are generated from
+use anyhow::Result;
+
+use crate::usage;
+
+/// Width of usage column, help text starts after it
+const USAGE_WIDTH: usize = 32;
+
+struct Command {
+	name: &'static str,
+	/// Shown by completion scripts
+	about: &'static str,
+	/// Usage lines, as arguments after command path and help for them. Line breaks of arguments are
+	/// aligned after command path, of help after usage column
+	usage: &'static [(&'static str, &'static str)],
+	/// Flags and fixed values
+	args: &'static [&'static str],
+	/// Accepts file paths
+	files: bool,
+	sub: &'static [Command],
+}
+const fn cmd(name: &'static str, about: &'static str) -> Command {
+	Command {
+		name,
+		about,
+		usage: &[],
+		args: &[],
+		files: false,
+		sub: &[],
+	}
+}
+
+const COMMANDS: &[Command] = &[
+	Command {
+		usage: &[(
+			"[--stats]",
+			"Show headset serial, panel and supported modes, with uptime and\n\
+			usage counters, unknown until their reads are verified",
+		)],
+		args: &["--stats"],
+		..cmd("info", "Show headset serial, panel and supported modes")
+	},
+	Command {
+		usage: &[(
+			"[<mode>]",
+			"List modes, or switch to mode (index, WxH or WxH@RATE)",
+		)],
+		sub: &[
+			Command {
+				usage: &[(
+					"<WxH[@RATE]> | [--width <w>] [--height <h>] [--rate <hz>]",
+					"Switch to the nearest supported mode",
+				)],
+				args: &["--width", "--height", "--rate"],
+				..cmd("set", "Switch to the nearest supported mode")
+			},
+			Command {
+				usage: &[(
+					"[--gpu <low|mid|high>]",
+					"Switch to the highest mode DisplayPort link and GPU can drive,\n\
+					GPU is guessed by video memory (amdgpu only) if not given",
+				)],
+				args: &["--gpu", "low", "mid", "high"],
+				..cmd("auto", "Switch to the highest mode link and GPU can drive")
+			},
+		],
+		..cmd("resolution", "List modes, or switch to mode")
+	},
+	Command {
+		usage: &[("<1-130>", "Set display brightness")],
+		..cmd("brightness", "Set display brightness")
+	},
+	Command {
+		usage: &[("<on|off>", "Toggle microphone noise cancelation")],
+		args: &["on", "off"],
+		..cmd("noise-cancel", "Toggle microphone noise cancelation")
+	},
+	Command {
+		usage: &[(
+			"[--temperature <K>] [--gain <r,g,b>] | color reset",
+			"Show or store color calibration on headset, driver applies it\n\
+			on SteamVR start, storing needs unverified-flash-write feature",
+		)],
+		args: &["--temperature", "--gain", "reset"],
+		..cmd("color", "Show or store color calibration on headset")
+	},
+	Command {
+		usage: &[(
+			"[<name>]",
+			"List settings profiles, or apply one (requires vived)",
+		)],
+		args: &["performance", "quality", "cinema"],
+		..cmd("profile", "List settings profiles, or apply one")
+	},
+	Command {
+		usage: &[("", "Stream IPD, proximity, button and connection events")],
+		..cmd(
+			"watch",
+			"Stream IPD, proximity, button and connection events",
+		)
+	},
+	Command {
+		usage: &[("", "Interactive control panel with live headset state")],
+		..cmd("tui", "Interactive control panel")
+	},
+	Command {
+		sub: &[
+			Command {
+				usage: &[(
+					"[--raw|--parsed] [--steam]",
+					"Print headset (or steam tracking) config, as stored,\n\
+					or as understood by driver",
+				)],
+				args: &["--raw", "--parsed", "--steam"],
+				..cmd("dump", "Print headset config")
+			},
+			Command {
+				usage: &[(
+					"[--steam] [<file>]",
+					"Check device (or dumped) config against known schema",
+				)],
+				args: &["--steam"],
+				files: true,
+				..cmd("validate", "Check config against known schema")
+			},
+			Command {
+				usage: &[("<a> <b>", "Compare two dumped configs field-by-field")],
+				files: true,
+				..cmd("diff", "Compare two dumped configs")
+			},
+		],
+		..cmd("config", "Inspect headset config")
+	},
+	Command {
+		sub: &[Command {
+			usage: &[(
+				"[--stations]",
+				"Show firmware versions of headset components\n\
+				(and base stations over bluetooth)",
+			)],
+			args: &["--stations"],
+			..cmd("info", "Show firmware versions")
+		}],
+		..cmd("firmware", "Inspect firmware of headset components")
+	},
+	Command {
+		usage: &[("", "Check setup, and suggest fixes for common problems")],
+		..cmd("doctor", "Check setup for common problems")
+	},
+	Command {
+		sub: &[
+			Command {
+				usage: &[(
+					"[--steamvr <path>] [<release dir>]",
+					"Install proxy driver from release (current directory by default)",
+				)],
+				args: &["--steamvr"],
+				files: true,
+				..cmd("install", "Install proxy driver from release directory")
+			},
+			Command {
+				usage: &[("[--steamvr <path>]", "Restore original lighthouse driver")],
+				args: &["--steamvr"],
+				files: true,
+				..cmd("uninstall", "Restore original lighthouse driver")
+			},
+			Command {
+				usage: &[("", "Show every found SteamVR, and driver state in it")],
+				..cmd("status", "Show found SteamVR installations")
+			},
+		],
+		..cmd("driver", "Manage SteamVR proxy driver")
+	},
+	Command {
+		sub: &[Command {
+			usage: &[(
+				"[--output <path>]",
+				"Collect device info, firmware versions, configs, SteamVR logs,\n\
+				display link and system info into a tarball for bug reports",
+			)],
+			args: &["--output"],
+			files: true,
+			..cmd("bundle", "Collect diagnostics into a tarball")
+		}],
+		..cmd("diag", "Diagnostics for bug reports")
+	},
+	Command {
+		usage: &[(
+			"[--samples <n>]",
+			"Measure HID round-trip, show vsync-to-photons per mode,\n\
+			and headset vblank period, for reprojection tuning",
+		)],
+		args: &["--samples"],
+		..cmd("latency", "Measure HID round-trip and display timing")
+	},
+	Command {
+		sub: &[
+			Command {
+				usage: &[(
+					"",
+					"Show V4L2 nodes of headset cameras, their frame size and rate",
+				)],
+				..cmd("info", "Show V4L2 nodes of headset cameras")
+			},
+			Command {
+				usage: &[(
+					"[--device <path>] [--node <path>]",
+					"Publish cameras to v4l2loopback device, for webcam consumers",
+				)],
+				args: &["--device", "--node"],
+				files: true,
+				..cmd("loopback", "Publish cameras to v4l2loopback device")
+			},
+		],
+		..cmd("camera", "Headset cameras")
+	},
+	Command {
+		sub: &[
+			Command {
+				usage: &[(
+					"[--format json|obj] [--grid <n>] [--output <path>] [--config <file>]\n\
+					[--server <exe>] [--approximate]",
+					"Write lens distortion meshes (per eye and color channel), for\n\
+					other compositors, from lens server or built-in approximation",
+				)],
+				args: &[
+					"--format",
+					"json",
+					"obj",
+					"--grid",
+					"--output",
+					"--config",
+					"--server",
+					"--approximate",
+				],
+				files: true,
+				..cmd("export", "Write lens distortion meshes")
+			},
+			Command {
+				usage: &[(
+					"--output <dir> [--grid <n>] [--config <file>] [--server <exe>] [--approximate]",
+					"Record lens server answers, and meshes computed from them",
+				)],
+				args: &[
+					"--output",
+					"--grid",
+					"--config",
+					"--server",
+					"--approximate",
+				],
+				files: true,
+				..cmd("record", "Record lens server answers and golden meshes")
+			},
+			Command {
+				usage: &[(
+					"[--in-process] <dir>",
+					"Replay recorded answers without wine, through lens protocol,\n\
+					and check that meshes match recorded ones bit-for-bit",
+				)],
+				args: &["--in-process"],
+				files: true,
+				..cmd("check", "Check meshes against recorded ones")
+			},
+		],
+		..cmd("lens", "Lens distortion of the headset")
+	},
+	Command {
+		sub: &[
+			Command {
+				usage: &[(
+					"[--output <path>] [--config <file>] [--mode <mode>] [--ipd <mm>]\n\
+					[--server <exe>] [--approximate]",
+					"Write OpenHMD profile: panel size, fov, and distortion fitted\n\
+					to its universal model, for mode (4896x2448@90 by default)",
+				)],
+				args: &[
+					"--output",
+					"--config",
+					"--mode",
+					"--ipd",
+					"--server",
+					"--approximate",
+				],
+				files: true,
+				..cmd("openhmd", "Write OpenHMD profile")
+			},
+			Command {
+				usage: &[(
+					"[--output <path>] [--config <file>]",
+					"Write tracking calibration (sensor positions, IMU) in the layout\n\
+					libsurvive expects, from headset or dumped steam config",
+				)],
+				args: &["--output", "--config"],
+				files: true,
+				..cmd("libsurvive", "Write tracking calibration for libsurvive")
+			},
+			Command {
+				usage: &[(
+					"[--steam <root>] [--output <path>] [--config <file>] [--force]",
+					"Seed SteamVR lighthouse config cache with tracking calibration,\n\
+					from headset or dumped steam config",
+				)],
+				args: &["--steam", "--output", "--config", "--force"],
+				files: true,
+				..cmd("steamvr", "Seed SteamVR lighthouse config cache")
+			},
+		],
+		..cmd("export", "Headset description for other VR stacks")
+	},
+	Command {
+		usage: &[(
+			"<state|counters>",
+			"Show live state of driver, or its counters and frame timing",
+		)],
+		sub: &[
+			cmd("state", "Show live state of driver"),
+			cmd("counters", "Show driver counters and frame timing"),
+			Command {
+				usage: &[(
+					"<mode>",
+					"Force mode through driver, same as changing it in SteamVR settings",
+				)],
+				..cmd("mode", "Force mode through driver")
+			},
+			Command {
+				usage: &[("[on|off]", "Toggle SteamVR performance graph in headset")],
+				args: &["on", "off"],
+				..cmd("hud", "Toggle SteamVR performance graph in headset")
+			},
+			Command {
+				usage: &[(
+					"",
+					"Show last HID reports of driver (or vived), as JSON capture lines",
+				)],
+				..cmd("recent", "Show last HID reports of driver")
+			},
+		],
+		..cmd("debug", "Driver debug commands")
+	},
+	Command {
+		usage: &[(
+			"--settings [--yes]",
+			"Back up, then forget settings vived and driver store for the headset,\n\
+			and restart it, nothing stored on the headset is changed",
+		)],
+		args: &["--settings", "--yes"],
+		..cmd("reset", "Forget stored headset settings, and restart it")
+	},
+	Command {
+		sub: &[
+			Command {
+				usage: &[(
+					"[--dry-run] [--output <path>]",
+					"Install hidraw access rules for the current user",
+				)],
+				args: &["--dry-run", "--output"],
+				files: true,
+				..cmd("udev", "Install hidraw access rules")
+			},
+			Command {
+				usage: &[(
+					"[--dry-run]",
+					"Keep headset USB devices from being autosuspended, until reconnect",
+				)],
+				args: &["--dry-run"],
+				..cmd("autosuspend", "Keep headset USB devices awake")
+			},
+			Command {
+				usage: &[(
+					"[--dry-run]",
+					"Check that compositor keeps headset out of the desktop,\n\
+					and disable its output if it doesn't",
+				)],
+				args: &["--dry-run"],
+				..cmd("wayland", "Keep headset out of Wayland desktop")
+			},
+			Command {
+				usage: &[(
+					"[--dry-run]",
+					"Mark headset RandR output as non-desktop, and turn it off",
+				)],
+				args: &["--dry-run"],
+				..cmd("x11", "Keep headset out of X11 desktop")
+			},
+			Command {
+				usage: &[(
+					"[--dry-run]",
+					"Apply overrides, which driver needs inside of Flatpak Steam",
+				)],
+				args: &["--dry-run"],
+				..cmd("flatpak", "Give Flatpak Steam access to driver settings")
+			},
+			Command {
+				usage: &[
+					(
+						"[--dry-run] [--from <dir> | --fetch [--yes]] [--server <exe>]\n\
+						[--wine <path> | --proton <name>]",
+						"Create Wine prefix for lens server (or use Proton build), copy\n\
+						VIVE Console libraries next to it, and point driver config to them.\n\
+						--fetch asks Steam to install VIVE Console, if it is missing",
+					),
+					(
+						"--list-protons",
+						"List Proton builds, lens server can run under",
+					),
+				],
+				args: &[
+					"--dry-run",
+					"--from",
+					"--fetch",
+					"--yes",
+					"--server",
+					"--wine",
+					"--proton",
+					"--list-protons",
+				],
+				files: true,
+				..cmd("lens-server", "Create Wine prefix for lens server")
+			},
+		],
+		..cmd("setup", "Install system integration")
+	},
+	Command {
+		usage: &[("<bash|zsh|fish>", "Print shell completion script")],
+		args: &["bash", "zsh", "fish"],
+		..cmd("completions", "Print shell completion script")
+	},
+	cmd("help", "Show usage"),
+];
+const GLOBAL_FLAGS: &[&str] = &["--json"];
+
+fn usage_lines(prefix: &str, commands: &[Command], out: &mut String) {
+	for c in commands {
+		let path = if prefix.is_empty() {
+			c.name.to_owned()
+		} else {
+			format!("{prefix} {}", c.name)
+		};
+		for (args, help) in c.usage {
+			let mut line = format!("  {path}");
+			for (i, args) in args.lines().enumerate() {
+				if i > 0 {
+					out.push_str(&line);
+					out.push('\n');
+					line = " ".repeat(path.len() + 2);
+				}
+				if !args.is_empty() {
+					line.push(' ');
+					line.push_str(args);
+				}
+			}
+			if line.len() < USAGE_WIDTH && !args.contains('\n') {
+				out.push_str(&format!("{line:USAGE_WIDTH$}"));
+			} else {
+				out.push_str(&line);
+				out.push('\n');
+				out.push_str(&" ".repeat(USAGE_WIDTH));
+			}
+			out.push_str(&help.replace('\n', &format!("\n{}", " ".repeat(USAGE_WIDTH))));
+			out.push('\n');
+		}
+		usage_lines(&path, c.sub, out);
+	}
+}
+
+/// Commands part of USAGE
+pub fn commands_usage() -> String {
+	let mut out = String::new();
+	usage_lines("", COMMANDS, &mut out);
+	out
+}
+
+/// Command path, with words completed after it
+struct Node {
+	path: String,
+	words: Vec<&'static str>,
+	files: bool,
+	leaf: bool,
+}
+impl Node {
+	/// Shell `case` pattern, leaf commands keep completing their flags after positional args
+	fn pattern(&self) -> String {
+		if self.leaf {
+			format!("\"{}\"*", self.path)
+		} else {
+			format!("\"{}\"", self.path)
+		}
+	}
+}
+
+fn walk(prefix: &str, commands: &[Command], out: &mut Vec<Node>) {
+	for c in commands {
+		let path = if prefix.is_empty() {
+			c.name.to_owned()
+		} else {
+			format!("{prefix} {}", c.name)
+		};
+		let mut words = c.sub.iter().map(|s| s.name).collect::<Vec<_>>();
+		words.extend(c.args);
+		out.push(Node {
+			path: path.clone(),
+			words,
+			files: c.files,
+			leaf: c.sub.is_empty(),
+		});
+		walk(&path, c.sub, out);
+	}
+}
+
+fn nodes() -> Vec<Node> {
+	let mut words = COMMANDS.iter().map(|c| c.name).collect::<Vec<_>>();
+	words.extend(GLOBAL_FLAGS);
+	let mut out = vec![Node {
+		path: String::new(),
+		words,
+		files: false,
+		leaf: false,
+	}];
+	walk("", COMMANDS, &mut out);
+	out
+}
+
+fn bash() -> String {
+	let mut out = String::from(
+		"_vivectl() {\n\
+		\tlocal cur=\"${COMP_WORDS[COMP_CWORD]}\" words=() opts=\"\" files=\"\"\n\
+		\tfor w in \"${COMP_WORDS[@]:1:COMP_CWORD-1}\"; do\n\
+		\t\t[[ $w == --json ]] || words+=(\"$w\")\n\
+		\tdone\n\
+		\tcase \"${words[*]}\" in\n",
+	);
+	for node in nodes() {
+		out.push_str(&format!(
+			"\t\t{}) opts=\"{}\"{} ;;\n",
+			node.pattern(),
+			node.words.join(" "),
+			if node.files { "; files=1" } else { "" }
+		));
+	}
+	out.push_str(
+		"\tesac\n\
+		\tCOMPREPLY=($(compgen -W \"$opts\" -- \"$cur\"))\n\
+		\t[[ -n $files ]] && COMPREPLY+=($(compgen -f -- \"$cur\"))\n\
+		}\n\
+		complete -F _vivectl vivectl\n",
+	);
+	out
+}
+
+fn zsh() -> String {
+	let mut out = String::from(
+		"#compdef vivectl\n\
+		_vivectl() {\n\
+		\tlocal -a args=(${words[2,CURRENT-1]:#--json})\n\
+		\tcase \"${args[*]}\" in\n",
+	);
+	for node in nodes() {
+		out.push_str(&format!("\t\t{})\n", node.pattern()));
+		if !node.words.is_empty() {
+			out.push_str(&format!("\t\t\tcompadd -- {}\n", node.words.join(" ")));
+		}
+		if node.files {
+			out.push_str("\t\t\t_files\n");
+		}
+		out.push_str("\t\t\t;;\n");
+	}
+	out.push_str("\tesac\n}\n_vivectl \"$@\"\n");
+	out
+}
+
+fn fish() -> String {
+	let mut out = String::from("complete -c vivectl -f\n");
+	for flag in GLOBAL_FLAGS {
+		out.push_str(&format!(
+			"complete -c vivectl -l {}\n",
+			flag.trim_start_matches('-')
+		));
+	}
+	fn emit(out: &mut String, parent: Option<&Command>, condition: &str, commands: &[Command]) {
+		let siblings = commands
+			.iter()
+			.map(|c| c.name)
+			.collect::<Vec<_>>()
+			.join(" ");
+		let here = match parent {
+			Some(_) => format!("{condition}; and not __fish_seen_subcommand_from {siblings}"),
+			None => "__fish_use_subcommand".to_owned(),
+		};
+		for c in commands {
+			out.push_str(&format!(
+				"complete -c vivectl -n '{here}' -a {} -d '{}'\n",
+				c.name, c.about
+			));
+			let condition = match parent {
+				Some(_) => format!("{condition}; and __fish_seen_subcommand_from {}", c.name),
+				None => format!("__fish_seen_subcommand_from {}", c.name),
+			};
+			for arg in c.args {
+				match arg.strip_prefix("--") {
+					Some(flag) => {
+						out.push_str(&format!("complete -c vivectl -n '{condition}' -l {flag}\n"))
+					}
+					None => {
+						out.push_str(&format!("complete -c vivectl -n '{condition}' -a {arg}\n"))
+					}
+				}
+			}
+			if c.files {
+				out.push_str(&format!("complete -c vivectl -n '{condition}' -F\n"));
+			}
+			emit(out, Some(c), &condition, c.sub);
+		}
+	}
+	emit(&mut out, None, "", COMMANDS);
+	out
+}
+
+pub fn run(args: &[&str]) -> Result<()> {
+	let script = match args {
+		["bash"] => bash(),
+		["zsh"] => zsh(),
+		["fish"] => fish(),
+		_ => usage(),
+	};
+	print!("{script}");
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Command path of each USAGE line, words up to the first argument
+	fn usage_paths() -> Vec<Vec<String>> {
+		let usage = commands_usage();
+		let paths = usage
+			.lines()
+			.filter(|l| l.starts_with("  ") && !l.starts_with("   "))
+			.map(|l| {
+				l.split_whitespace()
+					.take_while(|w| {
+						!w.starts_with('-')
+							&& w.chars()
+								.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+					})
+					.map(str::to_owned)
+					.collect::<Vec<_>>()
+			})
+			.collect::<Vec<_>>();
+		assert!(paths.len() > 20, "{usage}");
+		paths
+	}
+
+	#[test]
+	fn usage_commands_complete() {
+		let (bash, zsh, fish) = (bash(), zsh(), fish());
+		for path in usage_paths() {
+			let joined = path.join(" ");
+			assert!(
+				bash.contains(&format!("\"{joined}\"")),
+				"bash lacks {joined}"
+			);
+			assert!(zsh.contains(&format!("\"{joined}\"")), "zsh lacks {joined}");
+			let name = path.last().unwrap();
+			let parent = match path.len() {
+				1 => "__fish_use_subcommand".to_owned(),
+				_ => format!("__fish_seen_subcommand_from {}", path[path.len() - 2]),
+			};
+			assert!(
+				fish.lines()
+					.any(|l| l.contains(&parent) && l.contains(&format!(" -a {name} "))),
+				"fish lacks {joined}"
+			);
+		}
+	}
+
+	#[test]
+	fn usage_flags_complete() {
+		fn check(commands: &[Command]) {
+			for c in commands {
+				for (args, _) in c.usage {
+					for flag in args
+						.split(|c: char| c.is_whitespace() || "[]|".contains(c))
+						.filter(|w| w.starts_with("--"))
+					{
+						assert!(c.args.contains(&flag), "{} lacks {flag}", c.name);
+					}
+				}
+				check(c.sub);
+			}
+		}
+		check(COMMANDS);
+	}
+}
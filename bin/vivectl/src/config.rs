@@ -0,0 +1,156 @@
+use std::{
+	fs,
+	io::{self, Write},
+	path::Path,
+	process::exit,
+};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use vive_hid::{SteamDevice, ViveDevice};
+use vive_protocol::{
+	config_diff, parse_config_dump, parse_steam_config_json, parse_vive_config_json,
+	validate_steam_config, validate_vive_config, ConfigChange, RawConfig,
+};
+
+use crate::{usage, Opts};
+
+pub fn load(path: &Path) -> Result<Value> {
+	let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+	parse_config_dump(&bytes).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+fn change_json(change: &ConfigChange) -> Value {
+	match change {
+		ConfigChange::Added { path, value } => json!({"op": "added", "path": path, "value": value}),
+		ConfigChange::Removed { path, value } => {
+			json!({"op": "removed", "path": path, "value": value})
+		}
+		ConfigChange::Changed { path, from, to } => {
+			json!({"op": "changed", "path": path, "from": from, "to": to})
+		}
+	}
+}
+
+fn diff(opts: Opts, a: &str, b: &str) -> Result<()> {
+	let a = load(Path::new(a))?;
+	let b = load(Path::new(b))?;
+	let changes = config_diff(&a, &b);
+	if changes.is_empty() && !opts.json {
+		eprintln!("configs are identical");
+		return Ok(());
+	}
+	opts.print(changes.iter().map(change_json).collect(), || {
+		changes
+			.iter()
+			.map(ToString::to_string)
+			.collect::<Vec<_>>()
+			.join("\n")
+	});
+	Ok(())
+}
+
+fn read_raw(steam: bool) -> Result<RawConfig> {
+	Ok(if steam {
+		SteamDevice::open_first()
+			.context("failed to open steam device")?
+			.read_config_raw()?
+	} else {
+		ViveDevice::open_first()
+			.context("failed to open headset")?
+			.read_config_raw()?
+	})
+}
+
+fn dump(opts: Opts, args: &[&str]) -> Result<()> {
+	let mut raw = false;
+	let mut parsed = false;
+	let mut steam = false;
+	for arg in args {
+		match *arg {
+			"--raw" => raw = true,
+			"--parsed" => parsed = true,
+			"--steam" => steam = true,
+			_ => usage(),
+		}
+	}
+	let config = read_raw(steam)?;
+	if raw {
+		// Header is kept, so dump may be attached to bug reports, and diffed later
+		let mut out = config.header;
+		out.extend_from_slice(&config.blob);
+		io::stdout().write_all(&out)?;
+		return Ok(());
+	}
+	let json = config
+		.json
+		.context("config can't be decoded, use --raw to dump it as-is")?;
+	let value: Value = if parsed {
+		// How driver sees the config, unknown fields are kept in `extra`
+		if steam {
+			serde_json::to_value(parse_steam_config_json(&json)?)?
+		} else {
+			serde_json::to_value(parse_vive_config_json(&json)?)?
+		}
+	} else {
+		serde_json::from_str(&json).context("config is not valid JSON")?
+	};
+	if opts.json {
+		println!("{value}");
+	} else {
+		println!("{}", serde_json::to_string_pretty(&value)?);
+	}
+	Ok(())
+}
+
+fn validate(opts: Opts, args: &[&str]) -> Result<()> {
+	let mut steam = false;
+	let mut file = None;
+	for arg in args {
+		match *arg {
+			"--steam" => steam = true,
+			arg if !arg.starts_with('-') && file.is_none() => file = Some(arg),
+			_ => usage(),
+		}
+	}
+	let json = match file {
+		Some(file) => load(Path::new(file))?.to_string(),
+		None => read_raw(steam)?
+			.json
+			.context("config can't be decoded, use config dump --raw to inspect it")?,
+	};
+	let issues = if steam {
+		validate_steam_config(&json)
+	} else {
+		validate_vive_config(&json)
+	};
+	opts.print(
+		json!({
+			"valid": issues.is_empty(),
+			"issues": issues.iter().map(ToString::to_string).collect::<Vec<_>>(),
+		}),
+		|| {
+			if issues.is_empty() {
+				return "config is valid".to_owned();
+			}
+			issues
+				.iter()
+				.map(ToString::to_string)
+				.collect::<Vec<_>>()
+				.join("\n")
+		},
+	);
+	if !issues.is_empty() {
+		exit(1);
+	}
+	Ok(())
+}
+
+pub fn run(opts: Opts, args: &[&str]) -> Result<()> {
+	match args {
+		["dump", rest @ ..] => dump(opts, rest),
+		["diff", a, b] => diff(opts, a, b),
+		["validate", rest @ ..] => validate(opts, rest),
+		_ => usage(),
+	}
+}
@@ -0,0 +1,51 @@
+//! Driver debug commands, see [`vive_ipc::debug`]
+use anyhow::{Context, Result};
+use vive_hid::Resolution;
+use vive_ipc::{
+	debug::{DebugRequest, DebugResponse},
+	Client,
+};
+
+use crate::{usage, Opts};
+
+pub fn run(opts: Opts, args: &[&str]) -> Result<()> {
+	let request = match args {
+		["state"] => DebugRequest::State,
+		["counters"] => DebugRequest::Counters,
+		["mode", mode] => DebugRequest::SetMode {
+			mode: mode.parse::<Resolution>()? as u8,
+		},
+		["hud"] => DebugRequest::Hud { enabled: None },
+		["hud", "on"] => DebugRequest::Hud {
+			enabled: Some(true),
+		},
+		["hud", "off"] => DebugRequest::Hud {
+			enabled: Some(false),
+		},
+		["recent"] => DebugRequest::RecentReports,
+		_ => usage(),
+	};
+	let mut client = match request {
+		// Headset handle is in driver during session, and in vived otherwise
+		DebugRequest::RecentReports => Client::connect_driver()
+			.map(Ok)
+			.unwrap_or_else(Client::connect_default)
+			.context("neither SteamVR nor vived is running")?,
+		_ => Client::connect_driver().context("SteamVR is not running")?,
+	};
+	let response = client.debug(request)?;
+	let value = serde_json::to_value(&response)?;
+	opts.print(value.clone(), || match response {
+		DebugResponse::Ok => "ok".to_owned(),
+		DebugResponse::Hud { enabled } => {
+			format!(
+				"performance graph is {}",
+				if enabled { "on" } else { "off" }
+			)
+		}
+		// As is, so it can be saved as a trace
+		DebugResponse::RecentReports { reports } => reports.trim_end().to_owned(),
+		_ => serde_json::to_string_pretty(&value).expect("serializable"),
+	});
+	Ok(())
+}
@@ -0,0 +1,592 @@
+use std::{
+	thread,
+	time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use serde_json::json;
+use vive_display::{
+	aux::DeviceId,
+	drm::{find_connector, find_htc_connectors},
+	edid::EdidId,
+};
+use vive_hid::{
+	GpuTier, Mode, PanelInfo, Resolution, SwitchStage, UserConfig, ViveDevice,
+	NATIVE_COLOR_TEMPERATURE,
+};
+use vive_ipc::{headsets, Client, Request};
+
+use crate::{
+	setup::{headset_connector, headset_edid},
+	usage, Opts,
+};
+
+/// Chip reset, re-enumeration and link training, headset which isn't back with its display after
+/// this didn't take the mode
+const SWITCH_BUDGET: Duration = Duration::from_secs(20);
+const LINK_POLL: Duration = Duration::from_millis(250);
+/// Rollback target, when no mode is known to work, works on every known setup
+const SAFE_MODE: Resolution = Resolution::R2448x1224f90;
+
+pub fn open() -> Result<ViveDevice> {
+	match ViveDevice::open_first() {
+		// Controls are sent through driver-proxy when its socket is up, so this is either stock
+		// SteamVR driver, or driver-proxy which failed to start its socket
+		Err(e @ vive_hid::Error::HeldBySteamVr { .. }) => {
+			Err(e).context("driver-proxy is not reachable, stop SteamVR, or check its log")
+		}
+		res => res.context("failed to open headset, is it connected and accessible?"),
+	}
+}
+
+fn usb_unreachable(e: &vive_hid::Error) -> bool {
+	use vive_hid::Error as E;
+	matches!(
+		e.root(),
+		E::DeviceNotFound | E::Disconnected(_) | E::PermissionDenied(_) | E::HeldBySteamVr { .. }
+	)
+}
+
+/// What DisplayPort side tells, when USB one can't be opened, config and commands are USB only,
+/// this is still enough to tell whether headset is connected, and where
+fn info_from_display(opts: Opts, usb: vive_hid::Error) -> Result<()> {
+	let Some(connector) = find_htc_connectors()
+		.ok()
+		.and_then(|c| c.into_iter().next())
+	else {
+		return Err(usb).context("failed to open headset, and no HTC display is connected either");
+	};
+	eprintln!("headset is unreachable over USB ({usb}), showing what its display tells");
+	let edid = &connector.edid;
+	let aux = connector.aux_info();
+	let id_json = |id: &Option<DeviceId>| {
+		id.as_ref().map(|id| {
+			json!({
+				"oui": id.oui,
+				"device": id.device,
+				"hardware_rev": id.hardware_rev,
+				"firmware_rev": format!("{}.{}", id.firmware_rev.0, id.firmware_rev.1),
+			})
+		})
+	};
+	let mut value = json!({
+		"source": "display",
+		"usb_error": usb.to_string(),
+		"connector": format!("{}-{}", connector.card, connector.name),
+		"connected": connector.connected,
+		"edid": {
+			"vendor": edid.id.pnp_id(),
+			"product": edid.id.product,
+			"name": edid.name,
+			"serial": edid.serial_string,
+			"year": edid.year,
+		},
+	});
+	match &aux {
+		Ok(aux) => {
+			value["dpcd_rev"] = json!(aux.dpcd_rev);
+			value["sink"] = json!(id_json(&aux.sink));
+			value["branch"] = json!(id_json(&aux.branch));
+		}
+		Err(e) => value["aux_error"] = json!(e.to_string()),
+	}
+	opts.print(value, || {
+		let mut out = format!(
+			"connector: {}-{} ({})
+",
+			connector.card,
+			connector.name,
+			if connector.connected {
+				"connected"
+			} else {
+				"disconnected"
+			}
+		);
+		out.push_str(&format!(
+			"display: {} {:04x} {} (serial {}, made {})
+",
+			edid.id.pnp_id(),
+			edid.id.product,
+			edid.name.as_deref().unwrap_or("unnamed"),
+			edid.serial_string.as_deref().unwrap_or("unknown"),
+			edid.year,
+		));
+		let id_text = |id: &Option<DeviceId>| match id {
+			Some(id) => format!(
+				"{} {} (hw {}, fw {}.{})",
+				id.oui.as_deref().unwrap_or("unknown vendor"),
+				id.device,
+				id.hardware_rev,
+				id.firmware_rev.0,
+				id.firmware_rev.1
+			),
+			None => "not identified".to_owned(),
+		};
+		match &aux {
+			Ok(aux) => {
+				out.push_str(&format!(
+					"dpcd: {}.{}
+sink: {}",
+					aux.dpcd_rev >> 4,
+					aux.dpcd_rev & 0xf,
+					id_text(&aux.sink)
+				));
+				if aux.branch.is_some() {
+					out.push_str(&format!(
+						"
+branch: {}",
+						id_text(&aux.branch)
+					));
+				}
+			}
+			Err(e) => out.push_str(&format!("dpcd: unreadable ({e}), needs root")),
+		}
+		out
+	});
+	Ok(())
+}
+
+/// Sends request to driver-proxy, if SteamVR session is running, as it already holds the headset
+///
+/// Returns false if there is no session, and headset should be opened directly
+fn via_driver(request: Request) -> Result<bool> {
+	let Some(mut driver) = Client::connect_driver() else {
+		return Ok(false);
+	};
+	driver.request(&request)?;
+	Ok(true)
+}
+
+fn mode_json(mode: Mode) -> serde_json::Value {
+	json!({
+		"id": mode.id,
+		"width": mode.width,
+		"height": mode.height,
+		"frame_rate": mode.frame_rate,
+	})
+}
+
+pub fn info(opts: Opts, args: &[&str]) -> Result<()> {
+	let with_stats = match args {
+		[] => false,
+		["--stats"] => true,
+		_ => usage(),
+	};
+	let dev = match ViveDevice::open_first() {
+		Ok(dev) => dev,
+		Err(e) if usb_unreachable(&e) => return info_from_display(opts, e),
+		Err(e) => return Err(e).context("failed to open headset, is it connected and accessible?"),
+	};
+	let identity = dev.identity()?;
+	let stats = with_stats.then(|| dev.usage_stats()).transpose()?;
+	let serial = &identity.device_sn;
+	let config = dev.read_config()?;
+	// Panel mfg reads are guessed, what they answer is only shown as unverified in JSON, until
+	// a capture confirms them, factory config is what is trusted
+	let panel_field = |field: fn(&PanelInfo) -> &Option<String>| {
+		config.panel.as_ref().and_then(|p| field(p).clone())
+	};
+	let panel_serial = panel_field(|p| &p.serial_number);
+	let panel_date = panel_field(|p| &p.manufacture_date);
+	let modes = dev.query_modes();
+	// Missing sysfs (i.e in container) shouldn't fail the whole info
+	let audio = dev.audio_cards().unwrap_or_default();
+	let mut value = json!({
+		"serial": serial,
+		"board_serial": identity.board_sn,
+		"model": identity.model,
+		"region": identity.region,
+		"eye_target": {
+			"width": config.device.eye_target_width_in_pixels,
+			"height": config.device.eye_target_height_in_pixels,
+		},
+		"panel": config.panel,
+		"panel_serial": panel_serial,
+		"panel_manufacture_date": panel_date,
+		"unverified": {
+			"panel_serial": identity.panel_sn,
+			"panel_manufacture_date": identity.panel_date,
+		},
+		"modes": modes.iter().copied().map(mode_json).collect::<Vec<_>>(),
+		"audio": audio
+			.iter()
+			.map(|card| json!({
+				"index": card.index,
+				"id": card.id,
+				"alsa_device": card.alsa_device(),
+			}))
+			.collect::<Vec<_>>(),
+	});
+	// Counter reads are guessed, so there is nothing known to show until a capture confirms
+	// them, answers are kept apart, for whoever checks them against VIVE Console
+	if let Some(stats) = &stats {
+		value["stats"] = json!({
+			"uptime_secs": null,
+			"power_on_hours": null,
+			"power_cycles": null,
+		});
+		value["unverified"]["stats"] = json!({
+			"uptime_secs": stats.uptime.map(|d| d.as_secs()),
+			"power_on_hours": stats.power_on_hours,
+			"power_cycles": stats.power_cycles,
+		});
+	}
+	opts.print(value, || {
+		let mut out = format!("serial: {serial}\n");
+		for (name, value) in [
+			("board serial", &identity.board_sn),
+			("model", &identity.model),
+			("region", &identity.region),
+		] {
+			if let Some(value) = value {
+				out.push_str(&format!("{name}: {value}\n"));
+			}
+		}
+		out.push_str(&format!(
+			"eye target: {}x{}\n",
+			config.device.eye_target_width_in_pixels, config.device.eye_target_height_in_pixels
+		));
+		if config.panel.is_some() || panel_serial.is_some() || panel_date.is_some() {
+			let field = |v: Option<&String>| v.cloned().unwrap_or_else(|| "unknown".to_owned());
+			let panel = config.panel.as_ref();
+			out.push_str(&format!(
+				"panel: {} {} (serial {}, made {})\n",
+				field(panel.and_then(|p| p.vendor.as_ref())),
+				field(panel.and_then(|p| p.model.as_ref())),
+				field(panel_serial.as_ref()),
+				field(panel_date.as_ref()),
+			));
+		}
+		for card in &audio {
+			out.push_str(&format!(
+				"audio: card {} ({})\n",
+				card.index,
+				card.alsa_device()
+			));
+		}
+		if stats.is_some() {
+			out.push_str(
+				"uptime: unknown\npower on hours: unknown\npower cycles: unknown\n\
+				 (counter reads are unverified, see --json for what firmware answered)\n",
+			);
+		}
+		out.push_str("modes:");
+		for mode in &modes {
+			out.push_str(&format!("\n  {}: {}", mode.id, mode.display_mode()));
+		}
+		out
+	});
+	Ok(())
+}
+
+pub fn resolution(opts: Opts, args: &[&str]) -> Result<()> {
+	match args {
+		[] => {
+			let value = Resolution::ALL
+				.into_iter()
+				.map(|r| mode_json(r.mode()))
+				.collect::<Vec<_>>();
+			opts.print(value.into(), || {
+				Resolution::ALL
+					.into_iter()
+					.map(|res| format!("{}: {res}", res as u8))
+					.collect::<Vec<_>>()
+					.join("\n")
+			});
+			Ok(())
+		}
+		["auto", rest @ ..] => {
+			let link = headset_connector().ok();
+			let gpu = match rest {
+				[] => link
+					.as_ref()
+					.and_then(|c| c.vram_total())
+					.map_or(GpuTier::Mid, GpuTier::from_vram),
+				["--gpu", tier] => tier.parse()?,
+				_ => usage(),
+			};
+			let caps = link.map(|c| c.link_caps()).unwrap_or_default();
+			let res = vive_hid::recommend_resolution(&caps, gpu);
+			switch_mode(res)?;
+			let link = caps.max_link().map(|l| l.to_string());
+			opts.print(
+				json!({
+					"gpu": gpu.name(),
+					"link": link,
+					"mode": mode_json(res.mode()),
+				}),
+				|| {
+					let link = link.as_deref().unwrap_or("unknown link");
+					format!("switched to {res}, best for {gpu} tier GPU on {link}, headset will reconnect")
+				},
+			);
+			Ok(())
+		}
+		["set", rest @ ..] => {
+			let (res, requested, exact) = nearest(rest)?;
+			switch_mode(res)?;
+			opts.print(
+				json!({
+					"requested": requested,
+					"exact": exact,
+					"mode": mode_json(res.mode()),
+				}),
+				|| {
+					if exact {
+						format!("switched to {res}, headset will reconnect")
+					} else {
+						format!("{requested} is not supported, switched to nearest {res}, headset will reconnect")
+					}
+				},
+			);
+			Ok(())
+		}
+		[mode] => {
+			let res: Resolution = mode.parse()?;
+			switch_mode(res)?;
+			opts.print(json!({ "mode": mode_json(res.mode()) }), || {
+				format!("switched to {res}, headset will reconnect")
+			});
+			Ok(())
+		}
+		_ => usage(),
+	}
+}
+
+fn switch_mode(res: Resolution) -> Result<()> {
+	if !via_driver(Request::SetResolution {
+		resolution: res as u8,
+	})? {
+		let mut vive = open()?;
+		let devsn = vive.read_devsn().ok();
+		// Link is unknown without tracking board config
+		let edid = headset_edid().ok();
+		if let Some(connector) = edid.and_then(|id| find_connector(id).ok().flatten()) {
+			vive = vive.with_link_check(connector.link_caps());
+		}
+		match switch_and_come_back(&vive, res as u8, edid) {
+			Ok(()) => mark_good(devsn.as_deref(), res as u8),
+			Err(
+				e @ vive_hid::Error::SwitchTimeout {
+					stage: SwitchStage::DropOff | SwitchStage::Reconnect,
+				},
+			) => {
+				let good = devsn
+					.as_deref()
+					.and_then(|devsn| headsets::last_good_mode(devsn).ok().flatten())
+					.and_then(|m| Resolution::try_from(m).ok())
+					.unwrap_or(SAFE_MODE);
+				if good == res {
+					return Err(e.into());
+				}
+				eprintln!("{res} didn't come back ({e}), rolling back to {good}");
+				// Old handle may be gone, or may be still there with the failed mode
+				let vive = ViveDevice::wait_reconnect(SWITCH_BUDGET)
+					.context("headset didn't come back to roll back the mode")?;
+				switch_and_come_back(&vive, good as u8, edid)
+					.with_context(|| format!("failed to roll back to {good}"))?;
+				return Err(e).with_context(|| format!("{res} has failed, rolled back to {good}"));
+			}
+			Err(e) => return Err(e.into()),
+		}
+	}
+	Ok(())
+}
+
+/// Mode counts as applied once headset is reopened, and its display is connected, all of it
+/// within [`SWITCH_BUDGET`]
+fn switch_and_come_back(
+	vive: &ViveDevice,
+	res: u8,
+	edid: Option<EdidId>,
+) -> Result<(), vive_hid::Error> {
+	let deadline = Instant::now() + SWITCH_BUDGET;
+	// Nothing to wait for, if the same switch is already in progress
+	if vive.set_resolution_and_wait(res, SWITCH_BUDGET)?.is_none() {
+		return Ok(());
+	}
+	let Some(edid) = edid else {
+		return Ok(());
+	};
+	// Simulated headset has no display
+	if vive_hid::sim::enabled() {
+		return Ok(());
+	}
+	while find_connector(edid).ok().flatten().map(|c| c.connected) != Some(true) {
+		if Instant::now() >= deadline {
+			return Err(vive_hid::Error::SwitchTimeout {
+				stage: SwitchStage::Reconnect,
+			});
+		}
+		thread::sleep(LINK_POLL);
+	}
+	Ok(())
+}
+
+fn mark_good(devsn: Option<&str>, mode: u8) {
+	if let Some(devsn) = devsn {
+		if let Err(e) = headsets::mark_good_mode(devsn, mode) {
+			eprintln!("failed to store last good mode: {e}");
+		}
+	}
+}
+
+/// Accepts `WxH[@RATE]`, or any of `--width`, `--height`, `--rate`,
+/// returns picked resolution, request in the same format, and whether it matched exactly
+fn nearest(args: &[&str]) -> Result<(Resolution, String, bool)> {
+	if let [mode] = args {
+		if !mode.starts_with("--") {
+			let res = Resolution::nearest_to(mode)?;
+			let exact = mode.parse::<Resolution>().ok() == Some(res);
+			return Ok((res, (*mode).to_owned(), exact));
+		}
+	}
+	let (mut width, mut height, mut rate) = (None, None, None);
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		let value = args.next().unwrap_or_else(|| usage());
+		match *arg {
+			"--width" => width = Some(value.parse::<u32>().context("width should be a number")?),
+			"--height" => height = Some(value.parse::<u32>().context("height should be a number")?),
+			"--rate" => rate = Some(value.parse::<f32>().context("rate should be a number")?),
+			_ => usage(),
+		}
+	}
+	if width.is_none() && height.is_none() && rate.is_none() {
+		usage();
+	}
+	let part = |v: Option<String>| v.unwrap_or_else(|| "*".to_owned());
+	let requested = format!(
+		"{}x{}{}",
+		part(width.map(|w| w.to_string())),
+		part(height.map(|h| h.to_string())),
+		rate.map(|r| format!("@{r}")).unwrap_or_default()
+	);
+	let res = Resolution::nearest(width, height, rate);
+	let mode = res.mode();
+	let exact = width.map(|w| w == mode.width) != Some(false)
+		&& height.map(|h| h == mode.height) != Some(false)
+		&& rate.map(|r| r == mode.frame_rate) != Some(false);
+	Ok((res, requested, exact))
+}
+
+pub fn brightness(opts: Opts, args: &[&str]) -> Result<()> {
+	let [value] = args else { usage() };
+	let value: u8 = value.parse().context("brightness should be a number")?;
+	if !(1..=130).contains(&value) {
+		bail!("brightness should be in 1-130 range");
+	}
+	if !via_driver(Request::SetBrightness { brightness: value })? {
+		open()?.set_brightness(value)?;
+	}
+	opts.print(json!({ "brightness": value }), || {
+		format!("brightness set to {value}")
+	});
+	Ok(())
+}
+
+pub fn noise_cancel(opts: Opts, args: &[&str]) -> Result<()> {
+	let enabled = match args {
+		["on" | "true" | "1"] => true,
+		["off" | "false" | "0"] => false,
+		_ => usage(),
+	};
+	if !via_driver(Request::SetNoiseCancel { enabled })? {
+		open()?.toggle_noise_canceling(enabled)?;
+	}
+	opts.print(json!({ "noise_cancel": enabled }), || {
+		format!(
+			"noise cancelation {}",
+			if enabled { "enabled" } else { "disabled" }
+		)
+	});
+	Ok(())
+}
+
+fn parse_gain(value: &str) -> Result<[f32; 3]> {
+	let gain = value
+		.split(',')
+		.map(|v| v.trim().parse::<f32>())
+		.collect::<Result<Vec<_>, _>>()
+		.ok()
+		.and_then(|v| <[f32; 3]>::try_from(v).ok())
+		.filter(|g| g.iter().all(|v| (0.0..=1.0).contains(v)))
+		.context("gain should be three comma separated values in 0-1 range, i.e 1,0.95,0.9")?;
+	Ok(gain)
+}
+
+#[cfg(feature = "unverified-flash-write")]
+fn store_user_config(dev: &ViveDevice, config: &UserConfig) -> Result<()> {
+	Ok(dev.write_user_config(config)?)
+}
+/// Flash write protocol is not confirmed, so it is not in default builds
+#[cfg(not(feature = "unverified-flash-write"))]
+fn store_user_config(_: &ViveDevice, _: &UserConfig) -> Result<()> {
+	bail!(
+		"storing calibration on the headset needs vivectl built with unverified-flash-write feature, \
+		 its flash write commands are guessed, use color keys of driver.toml instead"
+	)
+}
+
+/// Calibration is stored in headset user config, driver applies it on SteamVR start, unless
+/// driver config has color of its own
+pub fn color(opts: Opts, args: &[&str]) -> Result<()> {
+	let mut temperature = None;
+	let mut gain = None;
+	let mut reset = false;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match *arg {
+			"--temperature" => {
+				let value = args.next().unwrap_or_else(|| usage());
+				let value: u32 = value.parse().context("temperature should be in kelvin")?;
+				if !(1000..=40000).contains(&value) {
+					bail!("temperature should be in 1000-40000K range");
+				}
+				temperature = Some(value);
+			}
+			"--gain" => gain = Some(parse_gain(args.next().unwrap_or_else(|| usage()))?),
+			"reset" => reset = true,
+			_ => usage(),
+		}
+	}
+	let writes = reset || temperature.is_some() || gain.is_some();
+	// Writes to config region would interleave with driver requests
+	if writes && Client::connect_driver().is_some() {
+		bail!("SteamVR is running, stop it first, calibration is applied on its next start");
+	}
+	let dev = open()?;
+	let mut config = dev.read_user_config()?;
+	if writes {
+		if reset {
+			config.color_gain = None;
+			config.color_temperature = None;
+		}
+		config.color_gain = gain.or(config.color_gain);
+		config.color_temperature = temperature.or(config.color_temperature);
+		store_user_config(&dev, &config)?;
+	}
+	let display_gain = config.display_gain();
+	opts.print(
+		json!({
+			"gain": config.color_gain,
+			"temperature": config.color_temperature,
+			"display_gain": display_gain,
+		}),
+		|| match display_gain {
+			Some([r, g, b]) => format!(
+				"gain: {}\ntemperature: {}\napplied gain: {r:.3}, {g:.3}, {b:.3}",
+				config
+					.color_gain
+					.map(|[r, g, b]| format!("{r}, {g}, {b}"))
+					.unwrap_or_else(|| "not set".to_owned()),
+				config
+					.color_temperature
+					.map(|t| format!("{t}K"))
+					.unwrap_or_else(|| format!("not set ({NATIVE_COLOR_TEMPERATURE}K)")),
+			),
+			None => "headset has no color calibration".to_owned(),
+		},
+	);
+	Ok(())
+}
@@ -0,0 +1,248 @@
+//! Diagnostics bundle, everything needed to look into issue report, in one tarball
+use std::{
+	env,
+	fs::{self, File},
+	io::{self, Read, Seek, SeekFrom, Write},
+	path::{Path, PathBuf},
+	process::Command,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use vive_ipc::{
+	debug::{DebugRequest, DebugResponse},
+	Client,
+};
+
+use crate::{
+	steam::{steam_root, steamvr_path},
+	usage, Opts,
+};
+
+const SYSFS_DRM: &str = "/sys/class/drm";
+/// Only recent part of SteamVR logs is interesting, and they may grow huge
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+const SYSTEM_FILES: &[(&str, &str)] = &[
+	("system/os-release", "/etc/os-release"),
+	("system/kernel", "/proc/version"),
+	("system/cmdline", "/proc/cmdline"),
+	("system/modules", "/proc/modules"),
+];
+const STEAMVR_LOGS: &[&str] = &[
+	"vrserver.txt",
+	"vrserver.previous.txt",
+	"vrcompositor.txt",
+	"vrmonitor.txt",
+	"vivepro2_driver.txt",
+	"vivepro2_driver.1.txt",
+];
+
+/// Minimal ustar writer, not worth a dependency
+struct Tar {
+	file: File,
+	prefix: String,
+	mtime: u64,
+}
+impl Tar {
+	fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+		let path = format!("{}/{name}", self.prefix);
+		let mut header = [0u8; 512];
+		let field = |header: &mut [u8; 512], offset: usize, value: &[u8]| {
+			header[offset..offset + value.len()].copy_from_slice(value);
+		};
+		field(&mut header, 0, &path.as_bytes()[..path.len().min(100)]);
+		field(&mut header, 100, b"0000644\0");
+		field(&mut header, 108, b"0000000\0");
+		field(&mut header, 116, b"0000000\0");
+		field(
+			&mut header,
+			124,
+			format!("{:011o}\0", data.len()).as_bytes(),
+		);
+		field(
+			&mut header,
+			136,
+			format!("{:011o}\0", self.mtime).as_bytes(),
+		);
+		field(&mut header, 156, b"0");
+		field(&mut header, 257, b"ustar\0");
+		field(&mut header, 263, b"00");
+		// Checksum is calculated with its own field filled with spaces
+		field(&mut header, 148, b"        ");
+		let sum = header.iter().map(|&b| b as u32).sum::<u32>();
+		field(&mut header, 148, format!("{sum:06o}\0 ").as_bytes());
+		self.file.write_all(&header)?;
+		self.file.write_all(data)?;
+		let padding = (512 - data.len() % 512) % 512;
+		self.file.write_all(&vec![0; padding])
+	}
+	fn finish(mut self) -> io::Result<()> {
+		self.file.write_all(&[0; 1024])?;
+		self.file.flush()
+	}
+}
+
+/// Runs this vivectl with other command, output of failed command is kept too, as it is
+/// diagnostics too
+fn vivectl(args: &[&str]) -> Vec<u8> {
+	let output = env::current_exe().and_then(|exe| Command::new(exe).args(args).output());
+	match output {
+		Ok(output) => {
+			let mut out = output.stdout;
+			if !output.stderr.is_empty() {
+				out.extend_from_slice(b"\n--- stderr ---\n");
+				out.extend_from_slice(&output.stderr);
+			}
+			out
+		}
+		Err(e) => format!("failed to run: {e}\n").into_bytes(),
+	}
+}
+
+fn read_tail(path: &Path) -> io::Result<Vec<u8>> {
+	let mut file = File::open(path)?;
+	let len = file.metadata()?.len();
+	file.seek(SeekFrom::Start(len.saturating_sub(MAX_LOG_SIZE)))?;
+	let mut out = Vec::new();
+	file.read_to_end(&mut out)?;
+	Ok(out)
+}
+
+/// Link state of every connector, headset one is recognizable by its EDID in doctor output
+fn drm_status() -> String {
+	let Ok(entries) = fs::read_dir(SYSFS_DRM) else {
+		return format!("{SYSFS_DRM} is not available\n");
+	};
+	let mut connectors = entries
+		.filter_map(|e| e.ok())
+		.map(|e| e.path())
+		.filter(|p| p.join("status").exists())
+		.collect::<Vec<_>>();
+	connectors.sort();
+	let mut out = String::new();
+	for connector in connectors {
+		let attr = |name: &str| {
+			fs::read_to_string(connector.join(name))
+				.map(|v| v.trim().replace('\n', ", "))
+				.unwrap_or_default()
+		};
+		let edid = fs::metadata(connector.join("edid"))
+			.map(|m| m.len())
+			.unwrap_or(0);
+		out.push_str(&format!(
+			"{}: status={} enabled={} dpms={} edid={edid}b modes=[{}]\n",
+			connector.file_name().unwrap_or_default().to_string_lossy(),
+			attr("status"),
+			attr("enabled"),
+			attr("dpms"),
+			attr("modes"),
+		));
+	}
+	out
+}
+
+fn daemon_status() -> String {
+	let status = |client: Option<Client>| match client {
+		Some(mut client) => match client.status() {
+			Ok(status) => serde_json::to_string_pretty(&status).expect("serializable"),
+			Err(e) => format!("failed to query: {e}"),
+		},
+		None => "not running".to_owned(),
+	};
+	format!(
+		"vived: {}\ndriver-proxy: {}\n",
+		status(Client::connect_default().ok()),
+		status(Client::connect_driver()),
+	)
+}
+
+/// Reports exchanged right before the issue, by whoever holds the headset
+fn recent_reports(client: Option<Client>) -> Option<String> {
+	match client?.debug(DebugRequest::RecentReports).ok()? {
+		DebugResponse::RecentReports { reports } => Some(reports),
+		_ => None,
+	}
+}
+
+fn bundle(opts: Opts, args: &[&str]) -> Result<()> {
+	let mtime = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or_default()
+		.as_secs();
+	let prefix = format!("vivepro2-diag-{mtime}");
+	let path = match args {
+		[] => PathBuf::from(format!("{prefix}.tar")),
+		["--output", path] => PathBuf::from(path),
+		_ => usage(),
+	};
+	let file = File::create(&path).with_context(|| format!("failed to create {path:?}"))?;
+	let mut tar = Tar {
+		file,
+		prefix,
+		mtime,
+	};
+
+	for (name, args) in [
+		("info.json", &["--json", "info"][..]),
+		("firmware.json", &["--json", "firmware", "info"]),
+		("doctor.json", &["--json", "doctor"]),
+		("config.json", &["config", "dump"]),
+		("config.bin", &["config", "dump", "--raw"]),
+		("steam-config.json", &["config", "dump", "--steam"]),
+		("steam-config.bin", &["config", "dump", "--steam", "--raw"]),
+	] {
+		tar.add(name, &vivectl(args))?;
+	}
+	tar.add("daemon.txt", daemon_status().as_bytes())?;
+	tar.add("drm.txt", drm_status().as_bytes())?;
+	let mut missing = Vec::new();
+	for (name, client) in [
+		("hid/vived.json", Client::connect_default().ok()),
+		("hid/driver.json", Client::connect_driver()),
+	] {
+		match recent_reports(client) {
+			Some(reports) => tar.add(name, reports.as_bytes())?,
+			None => missing.push(name),
+		}
+	}
+	for (name, source) in SYSTEM_FILES {
+		match fs::read(source) {
+			Ok(data) => tar.add(name, &data)?,
+			Err(_) => missing.push(*source),
+		}
+	}
+	let logs = steamvr_path().and_then(|p| steam_root(&p).map(|root| root.join("logs")));
+	if let Some(logs) = &logs {
+		for name in STEAMVR_LOGS {
+			match read_tail(&logs.join(name)) {
+				Ok(data) => tar.add(&format!("steamvr/{name}"), &data)?,
+				Err(_) => missing.push(name),
+			}
+		}
+	}
+	tar.finish()?;
+
+	opts.print(
+		json!({ "path": path, "missing": missing, "steamvr_logs": logs }),
+		|| {
+			let mut out = format!("written {}", path.display());
+			if logs.is_none() {
+				out.push_str("\nSteamVR not found, its logs are not included");
+			}
+			if !missing.is_empty() {
+				out.push_str(&format!("\nnot available: {}", missing.join(", ")));
+			}
+			out.push_str("\nreview it before attaching to a public issue, it contains headset serial numbers");
+			out
+		},
+	);
+	Ok(())
+}
+
+pub fn run(opts: Opts, args: &[&str]) -> Result<()> {
+	match args {
+		["bundle", rest @ ..] => bundle(opts, rest),
+		_ => usage(),
+	}
+}
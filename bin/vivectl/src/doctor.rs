@@ -0,0 +1,471 @@
+use std::{env, fs, path::Path, process::exit};
+
+use anyhow::Result;
+use serde_json::json;
+use vive_display::{
+	drm::{find_connectors, find_htc_connectors, Connector},
+	edid::EdidId,
+	link::{LinkCaps, ModeFit},
+};
+use vive_hid::{
+	power, Resolution, SteamDevice, ViveContext, ViveDevice, STEAM_PID, STEAM_VID, VIVE_PID,
+	VIVE_VID,
+};
+
+use crate::{
+	setup::{flatpak_overridden, UDEV_RULES_PATH},
+	steam::{is_flatpak, steamvr_path},
+	usage, Opts,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Status {
+	Ok,
+	Warn,
+	Fail,
+}
+impl Status {
+	fn name(self) -> &'static str {
+		match self {
+			Self::Ok => "ok",
+			Self::Warn => "warn",
+			Self::Fail => "fail",
+		}
+	}
+}
+
+struct Check {
+	name: &'static str,
+	status: Status,
+	detail: String,
+	fix: Option<String>,
+}
+impl Check {
+	fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+		Self {
+			name,
+			status: Status::Ok,
+			detail: detail.into(),
+			fix: None,
+		}
+	}
+	fn warn(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+		Self {
+			name,
+			status: Status::Warn,
+			detail: detail.into(),
+			fix: Some(fix.into()),
+		}
+	}
+	fn fail(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+		Self {
+			name,
+			status: Status::Fail,
+			..Self::warn(name, detail, fix)
+		}
+	}
+}
+
+fn check_udev() -> Check {
+	if Path::new(UDEV_RULES_PATH).exists() {
+		Check::ok("udev rules", UDEV_RULES_PATH)
+	} else {
+		Check::warn(
+			"udev rules",
+			"vivectl rules are not installed (distribution ones may still work)",
+			"sudo vivectl setup udev",
+		)
+	}
+}
+
+/// Returns whether headset and tracking board are present
+fn check_usb(out: &mut Vec<Check>) -> (bool, bool) {
+	let devices = match ViveContext::global().devices() {
+		Ok(devices) => devices
+			.iter()
+			.map(|d| (d.vendor_id(), d.product_id()))
+			.collect::<Vec<_>>(),
+		Err(e) => {
+			out.push(Check::fail(
+				"usb",
+				format!("hidapi is not available: {e}"),
+				"check that hidraw kernel module is loaded",
+			));
+			return (false, false);
+		}
+	};
+	let mut present = [false; 2];
+	for (i, (name, vid, pid)) in [
+		("headset on usb", VIVE_VID, VIVE_PID),
+		("tracking board on usb", STEAM_VID, STEAM_PID),
+	]
+	.into_iter()
+	.enumerate()
+	{
+		present[i] = devices.contains(&(vid, pid));
+		out.push(if present[i] {
+			Check::ok(name, format!("{vid:04x}:{pid:04x} found"))
+		} else {
+			Check::fail(
+				name,
+				format!("{vid:04x}:{pid:04x} not found"),
+				"connect link box USB to the PC, and power it on",
+			)
+		});
+	}
+	(present[0], present[1])
+}
+
+/// Only checked for present devices, otherwise open fails for unrelated reason
+fn check_access(
+	out: &mut Vec<Check>,
+	vive: bool,
+	steam: bool,
+) -> (Option<ViveDevice>, Option<SteamDevice>) {
+	let fix = "sudo vivectl setup udev, then reconnect headset";
+	let vive = if vive {
+		match ViveDevice::open_first() {
+			Ok(dev) => {
+				out.push(Check::ok("headset hidraw access", "opened"));
+				Some(dev)
+			}
+			Err(e) => {
+				out.push(Check::fail("headset hidraw access", e.to_string(), fix));
+				None
+			}
+		}
+	} else {
+		None
+	};
+	if !steam {
+		return (vive, None);
+	}
+	let steam = match SteamDevice::open_first() {
+		Ok(dev) => {
+			out.push(Check::ok("tracking board hidraw access", "opened"));
+			Some(dev)
+		}
+		Err(e) => {
+			out.push(Check::fail(
+				"tracking board hidraw access",
+				e.to_string(),
+				fix,
+			));
+			None
+		}
+	};
+	(vive, steam)
+}
+
+/// Firmware, which is incompatible with this driver (or SteamVR), shows up as unreadable
+/// configs, headset display stays black then
+fn check_firmware(out: &mut Vec<Check>, vive: Option<&ViveDevice>, steam: Option<&SteamDevice>) {
+	if let Some(vive) = vive {
+		out.push(match vive.read_config() {
+			Ok(config) if config.version.is_known() => {
+				Check::ok("headset firmware", "config is readable")
+			}
+			Ok(config) => Check::warn(
+				"headset firmware",
+				format!(
+					"config version {} is newer than supported",
+					config.version.0
+				),
+				"update the driver, some settings may be ignored",
+			),
+			Err(e) => Check::fail(
+				"headset firmware",
+				format!("config is unreadable: {e}"),
+				"reconnect headset, if it persists - update its firmware with VIVE Console on Windows",
+			),
+		});
+	}
+	if let Some(steam) = steam {
+		out.push(match steam.read_config() {
+			Ok(_) => Check::ok("tracking board firmware", "config is readable"),
+			Err(e) => Check::fail(
+				"tracking board firmware",
+				format!("config is unreadable: {e}"),
+				"update it from SteamVR (Devices > Update Device)",
+			),
+		});
+	}
+}
+
+fn check_display(out: &mut Vec<Check>, steam: Option<SteamDevice>) {
+	const NAME: &str = "headset on displayport";
+	// Display is only identifiable by EDID, which ids are stored in tracking board config
+	let edid = steam
+		.and_then(|s| s.read_config().ok())
+		.and_then(|c| c.direct_mode_edid());
+	let Some((vid, pid)) = edid else {
+		// Any HTC display is likely the headset, exact id is only needed to tell it apart
+		let htc = find_htc_connectors().unwrap_or_default();
+		out.push(match htc.iter().find(|c| c.connected) {
+			Some(c) => Check::warn(
+				NAME,
+				format!(
+					"{}-{} has HTC display, can't get EDID ids from tracking board config to confirm it is the headset",
+					c.card, c.name
+				),
+				"fix tracking board access first",
+			),
+			None => Check::warn(
+				NAME,
+				"can't get EDID ids from tracking board config to identify headset display",
+				"fix tracking board access first",
+			),
+		});
+		return;
+	};
+	let id = EdidId::from_config(vid, pid);
+	let check = match find_connectors(id) {
+		Ok(connectors) => match connectors.first() {
+			Some(c) if c.connected => {
+				out.push(Check::ok(NAME, format!("{}-{}", c.card, c.name)));
+				check_link(c)
+			}
+			Some(c) => Check::fail(
+				NAME,
+				format!("{}-{} is known, but disconnected", c.card, c.name),
+				"check DisplayPort cable, and power on link box",
+			),
+			None => Check::fail(
+				NAME,
+				format!("no connector with {} {:04x} EDID", id.pnp_id(), id.product),
+				"connect link box DisplayPort directly to GPU, adapters/docks are not supported",
+			),
+		},
+		Err(e) => Check::fail(
+			NAME,
+			format!("failed to scan DRM connectors: {e}"),
+			"is this a DRM/KMS system?",
+		),
+	};
+	out.push(check);
+}
+
+/// Whether the highest mode fits into link GPU, cable and headset agreed on
+fn check_link(connector: &Connector) -> Check {
+	const NAME: &str = "displayport link";
+	let caps = LinkCaps::read(connector);
+	if caps.link_good == Some(false) {
+		return Check::fail(
+			NAME,
+			"link training failed",
+			"reconnect DisplayPort cable, power cycle link box",
+		);
+	}
+	let fit = |res: Resolution| {
+		let mode = res.mode();
+		caps.fit(mode.width, mode.height, mode.frame_rate)
+	};
+	let Some(link) = caps.max_link() else {
+		return Check::warn(
+			NAME,
+			"link rate is unreadable",
+			"run doctor as root, to read it from DPCD and debugfs",
+		);
+	};
+	let link = match caps.verified {
+		Some(_) => format!("{link}"),
+		// Says nothing about GPU and cable
+		None => format!("{link} (headset maximum)"),
+	};
+	let highest = *Resolution::ALL.last().expect("modes");
+	match fit(highest) {
+		ModeFit::Fits | ModeFit::Unknown => Check::ok(NAME, format!("{link}, every mode fits")),
+		ModeFit::NeedsDsc if caps.dsc_enabled == Some(true) => {
+			Check::ok(NAME, format!("{link}, DSC is active"))
+		}
+		ModeFit::NeedsDsc => Check::ok(
+			NAME,
+			format!("{link}, {highest} needs DSC, which headset supports"),
+		),
+		ModeFit::TooFast => {
+			let fastest = Resolution::ALL
+				.into_iter()
+				.rev()
+				.find(|&res| fit(res) != ModeFit::TooFast);
+			let detail = match fastest {
+				Some(res) => format!("{link} only fits modes up to {res}"),
+				None => format!("{link} fits no mode"),
+			};
+			Check::warn(
+				NAME,
+				detail,
+				"use DisplayPort 1.4 (HBR3) capable GPU port and cable",
+			)
+		}
+	}
+}
+
+/// Sysfs only, so it is checked even when headset can't be opened
+fn check_autosuspend() -> Option<Check> {
+	const NAME: &str = "usb autosuspend";
+	let devices = match power::headset_usb_power() {
+		Ok(devices) if devices.is_empty() => return None,
+		Ok(devices) => devices,
+		Err(e) => {
+			return Some(Check::warn(
+				NAME,
+				format!("failed to read usb power state: {e}"),
+				"is sysfs mounted?",
+			))
+		}
+	};
+	let enabled = devices
+		.iter()
+		.filter(|d| d.autosuspend)
+		.map(|d| d.label())
+		.collect::<Vec<_>>();
+	Some(if enabled.is_empty() {
+		Check::ok(NAME, "disabled for every headset device")
+	} else {
+		Check::warn(
+			NAME,
+			format!(
+				"enabled for {}, suspended devices may drop headset off usb",
+				enabled.join(", ")
+			),
+			"sudo vivectl setup autosuspend, and sudo vivectl setup udev to keep it disabled",
+		)
+	})
+}
+
+fn check_steamvr(out: &mut Vec<Check>) {
+	let Some(steamvr) = steamvr_path() else {
+		out.push(Check::fail(
+			"steamvr",
+			"not found",
+			"install SteamVR, or set $STEAMVR to its location",
+		));
+		return;
+	};
+	out.push(Check::ok("steamvr", steamvr.display().to_string()));
+	if is_flatpak(&steamvr) {
+		out.push(match flatpak_overridden() {
+			Some(true) => Check::ok("flatpak", "Steam has access to driver settings"),
+			_ => Check::warn(
+				"flatpak",
+				"Steam is sandboxed, driver can't reach settings shared with vived",
+				"vivectl setup flatpak",
+			),
+		});
+	}
+
+	let lighthouse = steamvr.join("drivers/lighthouse/bin/linux64");
+	out.push(if lighthouse.join("driver_lighthouse_real.so").exists() {
+		Check::ok("proxy driver", "installed")
+	} else {
+		Check::fail(
+			"proxy driver",
+			"driver_lighthouse_real.so is missing",
+			"run vivectl driver install <release dir>, or install.sh from the driver release",
+		)
+	});
+
+	let server = lighthouse.join("lens-server/lens-server.exe");
+	out.push(if server.exists() {
+		Check::ok("lens server", server.display().to_string())
+	} else {
+		Check::fail(
+			"lens server",
+			format!("{} is missing", server.display()),
+			"rerun install.sh, or set LENS_SERVER_EXE",
+		)
+	});
+
+	let in_path = |name: &str| {
+		env::var_os("PATH")
+			.map(|path| env::split_paths(&path).any(|dir| dir.join(name).exists()))
+			.unwrap_or(false)
+	};
+	let protons = steamvr
+		.parent()
+		.and_then(|common| fs::read_dir(common).ok())
+		.map(|dir| {
+			dir.flatten()
+				.filter(|e| e.file_name().to_string_lossy().starts_with("Proton "))
+				.count()
+		})
+		.unwrap_or(0);
+	out.push(if env::var_os("WINE").is_some() {
+		Check::ok("wine", "$WINE is set")
+	} else if protons > 0 {
+		Check::ok("wine", format!("{protons} proton installation(s) found"))
+	} else if in_path("wine64") || in_path("wine") {
+		Check::ok("wine", "wine found in PATH")
+	} else {
+		Check::fail(
+			"wine",
+			"neither proton nor wine found, lens server can't run",
+			"install Proton through Steam, or wine from your distribution",
+		)
+	});
+}
+
+fn check_kernel() -> Check {
+	const NAME: &str = "kernel";
+	let Ok(release) = fs::read_to_string("/proc/sys/kernel/osrelease") else {
+		return Check::warn(NAME, "unknown version", "is this linux?");
+	};
+	let release = release.trim();
+	let version = release
+		.split(|c: char| !c.is_ascii_digit())
+		.take(2)
+		.map(|v| v.parse::<u32>().unwrap_or(0))
+		.collect::<Vec<_>>();
+	if version.as_slice() < [5, 18].as_slice() {
+		return Check::fail(
+			NAME,
+			format!("{release} lacks non-desktop and DisplayID fixes"),
+			"use kernel 5.18+, or apply patches from kernel-patches",
+		);
+	}
+	// DSC patch can't be detected from userspace
+	Check::ok(
+		NAME,
+		format!("{release}, 4896x2448 modes additionally need DSC patch from kernel-patches"),
+	)
+}
+
+pub fn run(opts: Opts, args: &[&str]) -> Result<()> {
+	if !args.is_empty() {
+		usage()
+	}
+	let mut checks = vec![check_udev()];
+	let (vive, steam) = check_usb(&mut checks);
+	let (vive, steam) = check_access(&mut checks, vive, steam);
+	check_firmware(&mut checks, vive.as_ref(), steam.as_ref());
+	check_display(&mut checks, steam);
+	checks.extend(check_autosuspend());
+	check_steamvr(&mut checks);
+	checks.push(check_kernel());
+
+	let value = checks
+		.iter()
+		.map(|c| {
+			json!({
+				"check": c.name,
+				"status": c.status.name(),
+				"detail": c.detail,
+				"fix": c.fix,
+			})
+		})
+		.collect();
+	opts.print(value, || {
+		let mut out = Vec::new();
+		for c in &checks {
+			out.push(format!("[{}] {}: {}", c.status.name(), c.name, c.detail));
+			if let Some(fix) = &c.fix {
+				out.push(format!("       fix: {fix}"));
+			}
+		}
+		out.join("\n")
+	});
+	if checks.iter().any(|c| c.status == Status::Fail) {
+		exit(1);
+	}
+	Ok(())
+}
@@ -0,0 +1,289 @@
+//! Proxy driver installation, same steps as `install.sh` from the release
+//!
+//! Proxy replaces lighthouse driver in place and loads the original one from
+//! `driver_lighthouse_real.so`, so it needs no `external_drivers` entry. Registered runtime still
+//! matters: with several SteamVR copies, clients start the one listed first in `openvrpaths.vrpath`
+use std::{
+	env, fs,
+	path::{Path, PathBuf},
+	process::Command,
+};
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+
+use crate::{
+	steam::{find_steamvr, openvrpaths_path, register_runtime, registered_runtimes},
+	usage, Opts,
+};
+
+/// Logged by driver-proxy on load, install.sh uses it to tell proxy from the original driver
+const PROXY_MARKER: &[u8] = b"https://patreon.com/0lach";
+pub const LIGHTHOUSE_BIN: &str = "drivers/lighthouse/bin/linux64";
+const LIGHTHOUSE_MANIFEST: &str = "drivers/lighthouse/driver.vrdrivermanifest";
+/// Relative to lighthouse driver, and to release directory
+const SETTINGS_SCHEMA: &str = "resources/settings/vivepro2.schema.json";
+
+struct Installation {
+	steamvr: PathBuf,
+	manifest: Result<(), String>,
+	proxy: bool,
+	real_driver: bool,
+	lens_server: bool,
+	/// Is first registered runtime
+	registered: bool,
+}
+impl Installation {
+	fn inspect(steamvr: PathBuf) -> Self {
+		let bin = steamvr.join(LIGHTHOUSE_BIN);
+		let proxy = fs::read(bin.join("driver_lighthouse.so"))
+			.map(|so| so.windows(PROXY_MARKER.len()).any(|w| w == PROXY_MARKER))
+			.unwrap_or(false);
+		let registered = registered_runtimes(&openvrpaths_path(&steamvr)).first() == Some(&steamvr);
+		Self {
+			manifest: check_manifest(&steamvr),
+			proxy,
+			real_driver: bin.join("driver_lighthouse_real.so").exists(),
+			lens_server: bin.join("lens-server/lens-server.exe").exists(),
+			registered,
+			steamvr,
+		}
+	}
+	fn json(&self) -> Value {
+		json!({
+			"steamvr": self.steamvr,
+			"manifest": match &self.manifest {
+				Ok(()) => json!({ "ok": true }),
+				Err(e) => json!({ "ok": false, "error": e }),
+			},
+			"proxy": self.proxy,
+			"real_driver": self.real_driver,
+			"lens_server": self.lens_server,
+			"registered": self.registered,
+		})
+	}
+}
+
+/// SteamVR only loads drivers with valid manifest, broken one means broken SteamVR installation
+fn check_manifest(steamvr: &Path) -> Result<(), String> {
+	let path = steamvr.join(LIGHTHOUSE_MANIFEST);
+	let data = fs::read_to_string(&path).map_err(|e| format!("failed to read {path:?}: {e}"))?;
+	let manifest: Value =
+		serde_json::from_str(&data).map_err(|e| format!("{path:?} is not valid JSON: {e}"))?;
+	match manifest.get("name").and_then(Value::as_str) {
+		Some("lighthouse") => Ok(()),
+		other => Err(format!("unexpected driver name in manifest: {other:?}")),
+	}
+}
+
+/// `--steamvr <path>` or the first found installation
+fn target(args: &[&str]) -> Result<(PathBuf, Vec<String>)> {
+	let mut steamvr = None;
+	let mut rest = Vec::new();
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match *arg {
+			"--steamvr" => steamvr = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+			arg if arg.starts_with("--") => usage(),
+			arg => rest.push(arg.to_owned()),
+		}
+	}
+	let steamvr = match steamvr {
+		Some(path) => path,
+		None => find_steamvr()
+			.into_iter()
+			.next()
+			.context("SteamVR not found, pass --steamvr <path>")?,
+	};
+	if let Err(e) = check_manifest(&steamvr) {
+		bail!("{e}, is {} a SteamVR installation?", steamvr.display());
+	}
+	Ok((steamvr, rest))
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+	fs::create_dir_all(to)?;
+	for entry in fs::read_dir(from)? {
+		let entry = entry?;
+		let dest = to.join(entry.file_name());
+		if entry.file_type()?.is_dir() {
+			copy_dir(&entry.path(), &dest)?;
+		} else {
+			fs::copy(entry.path(), &dest).with_context(|| format!("failed to copy to {dest:?}"))?;
+		}
+	}
+	Ok(())
+}
+
+/// Patches real driver with sewer from release, or from PATH, failure is not fatal, as it is
+/// in install.sh: patch only makes VIVE Console unnecessary
+fn patch_real_driver(release: &Path, real: &Path) -> Option<String> {
+	let bundled = release.join("bin/sewer");
+	let sewer = if bundled.exists() {
+		bundled
+	} else {
+		PathBuf::from("sewer")
+	};
+	let mut backup = real.as_os_str().to_owned();
+	backup.push(".bak");
+	let status = Command::new(sewer)
+		.arg("-v")
+		.arg("--backup")
+		.arg(&backup)
+		.arg(real)
+		.args(["patch-file", "--partial"])
+		.arg(release.join("driver_lighthouse_real.sew"))
+		.status();
+	match status {
+		Ok(status) if status.success() => None,
+		Ok(status) => Some(format!("sewer failed: {status}")),
+		Err(e) => Some(format!("failed to run sewer: {e}")),
+	}
+}
+
+fn install(opts: Opts, args: &[&str]) -> Result<()> {
+	let (steamvr, rest) = target(args)?;
+	let release = match rest.as_slice() {
+		[release] => PathBuf::from(release),
+		[] => env::current_dir()?,
+		_ => usage(),
+	};
+	let proxy_so = release.join("driver_lighthouse.so");
+	if !proxy_so.exists() {
+		bail!(
+			"{} doesn't look like driver release, driver_lighthouse.so is missing",
+			release.display()
+		);
+	}
+	let bin = steamvr.join(LIGHTHOUSE_BIN);
+	let current = bin.join("driver_lighthouse.so");
+	let real = bin.join("driver_lighthouse_real.so");
+	let before = Installation::inspect(steamvr.clone());
+
+	// Not the proxy means original driver, possibly updated by SteamVR since last install
+	if !before.proxy {
+		fs::copy(&current, &real).with_context(|| format!("failed to save {current:?}"))?;
+	}
+	let patch_warning = patch_real_driver(&release, &real);
+	fs::copy(&proxy_so, &current).with_context(|| format!("failed to write {current:?}"))?;
+	copy_dir(&release.join("lens-server"), &bin.join("lens-server"))?;
+	// Older releases have no schema, settings still work without it
+	let schema = release.join(SETTINGS_SCHEMA);
+	if schema.exists() {
+		let dest = steamvr.join("drivers/lighthouse").join(SETTINGS_SCHEMA);
+		if let Some(parent) = dest.parent() {
+			fs::create_dir_all(parent)?;
+		}
+		fs::copy(&schema, &dest).with_context(|| format!("failed to write {dest:?}"))?;
+	}
+	let openvrpaths = register_runtime(&steamvr)?;
+
+	let after = Installation::inspect(steamvr.clone());
+	opts.print(
+		json!({
+			"updated": before.proxy,
+			"patch_warning": patch_warning,
+			"openvrpaths": openvrpaths,
+			"installation": after.json(),
+		}),
+		|| {
+			let mut out = format!(
+				"{} proxy driver in {}",
+				if before.proxy { "updated" } else { "installed" },
+				steamvr.display()
+			);
+			if let Some(warning) = &patch_warning {
+				out.push_str(&format!(
+					"\nwarning: {warning}, VIVE Console may be required"
+				));
+			}
+			out.push_str(&format!(
+				"\nregistered as runtime in {}",
+				openvrpaths.display()
+			));
+			out.push_str("\nrestart SteamVR to apply");
+			out
+		},
+	);
+	Ok(())
+}
+
+fn uninstall(opts: Opts, args: &[&str]) -> Result<()> {
+	let (steamvr, rest) = target(args)?;
+	if !rest.is_empty() {
+		usage()
+	}
+	let bin = steamvr.join(LIGHTHOUSE_BIN);
+	let real = bin.join("driver_lighthouse_real.so");
+	let backup = bin.join("driver_lighthouse_real.so.bak");
+	if !real.exists() {
+		bail!("proxy driver is not installed in {}", steamvr.display());
+	}
+	// Backup is made by sewer before patching, so it is the original driver as shipped
+	let original = if backup.exists() { &backup } else { &real };
+	fs::copy(original, bin.join("driver_lighthouse.so"))
+		.context("failed to restore original driver")?;
+	fs::remove_file(&real)?;
+	if backup.exists() {
+		fs::remove_file(&backup)?;
+	}
+	let lens_server = bin.join("lens-server");
+	if lens_server.exists() {
+		fs::remove_dir_all(&lens_server)?;
+	}
+	let _ = fs::remove_file(steamvr.join("drivers/lighthouse").join(SETTINGS_SCHEMA));
+	opts.print(json!({ "steamvr": steamvr, "uninstalled": true }), || {
+		format!(
+			"original lighthouse driver restored in {}, restart SteamVR to apply",
+			steamvr.display()
+		)
+	});
+	Ok(())
+}
+
+fn status(opts: Opts, args: &[&str]) -> Result<()> {
+	if !args.is_empty() {
+		usage()
+	}
+	let installations = find_steamvr()
+		.into_iter()
+		.map(Installation::inspect)
+		.collect::<Vec<_>>();
+	opts.print(
+		installations.iter().map(Installation::json).collect(),
+		|| {
+			if installations.is_empty() {
+				return "SteamVR not found".to_owned();
+			}
+			let yes_no = |v: bool| if v { "yes" } else { "no" };
+			installations
+				.iter()
+				.map(|i| {
+					format!(
+						"{}\n  manifest: {}\n  proxy driver: {}\n  original driver saved: {}\n  lens server: {}\n  registered runtime: {}",
+						i.steamvr.display(),
+						match &i.manifest {
+							Ok(()) => "ok".to_owned(),
+							Err(e) => e.clone(),
+						},
+						yes_no(i.proxy),
+						yes_no(i.real_driver),
+						yes_no(i.lens_server),
+						yes_no(i.registered),
+					)
+				})
+				.collect::<Vec<_>>()
+				.join("\n")
+		},
+	);
+	Ok(())
+}
+
+pub fn run(opts: Opts, args: &[&str]) -> Result<()> {
+	match args {
+		["install", rest @ ..] => install(opts, rest),
+		["uninstall", rest @ ..] => uninstall(opts, rest),
+		["status", rest @ ..] => status(opts, rest),
+		_ => usage(),
+	}
+}
@@ -0,0 +1,187 @@
+//! Headset description for other VR stacks, which don't know how to read it from the headset,
+//! or can't do that while SteamVR holds it
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use lens_geometry::OpenHmdProfile;
+use serde_json::Value;
+use vive_hid::{Resolution, SteamConfig, SteamDevice};
+use vive_protocol::{libsurvive_config, Error as ProtocolError};
+
+use crate::{
+	config, device, lens,
+	steam::{steam_root, steamvr_path},
+	usage, Opts,
+};
+
+/// Average adult IPD, when headset is not there to be asked
+const DEFAULT_IPD_MM: f32 = 63.0;
+const STATUS_TIMEOUT_MS: i32 = 500;
+
+/// Lens correction and IPD in meters, from dumped config, or from the headset
+fn load(config_file: Option<&Path>, ipd_mm: Option<f32>) -> Result<(Value, f32)> {
+	let ipd = ipd_mm.map(|mm| mm / 1000.0);
+	Ok(match config_file {
+		Some(path) => (
+			config::load(path)?["inhouse_lens_correction"].clone(),
+			ipd.unwrap_or(DEFAULT_IPD_MM / 1000.0),
+		),
+		None => {
+			let device = device::open()?;
+			let correction = device.read_config()?.inhouse_lens_correction;
+			let ipd = match ipd {
+				Some(ipd) => ipd,
+				None => device
+					.read_status(STATUS_TIMEOUT_MS)?
+					.map_or(DEFAULT_IPD_MM / 1000.0, |s| s.ipd_meters()),
+			};
+			(correction, ipd)
+		}
+	})
+}
+
+fn write(opts: Opts, output: Option<&Path>, value: &Value) -> Result<()> {
+	let text = serde_json::to_string_pretty(value)?;
+	match output {
+		Some(path) => {
+			fs::write(path, text + "\n").with_context(|| format!("failed to write {path:?}"))?;
+			opts.print(serde_json::json!({ "output": path }), || {
+				format!("written to {}", path.display())
+			});
+		}
+		None => println!("{text}"),
+	}
+	Ok(())
+}
+
+/// Profile for OpenHMD based software, with distortion fitted to its universal model
+fn openhmd(opts: Opts, args: &[&str]) -> Result<()> {
+	let mut output = None;
+	let mut config_file = None;
+	let mut server = None;
+	let mut approximate = false;
+	let mut mode = Resolution::R4896x2448f90;
+	let mut ipd = None;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		let mut value = || *args.next().unwrap_or_else(|| usage());
+		match *arg {
+			"--output" => output = Some(PathBuf::from(value())),
+			"--config" => config_file = Some(PathBuf::from(value())),
+			"--server" => server = Some(PathBuf::from(value())),
+			"--approximate" => approximate = true,
+			"--mode" => mode = value().parse()?,
+			"--ipd" => ipd = Some(value().parse().context("ipd should be in millimeters")?),
+			_ => usage(),
+		}
+	}
+	let (correction, ipd) = load(config_file.as_deref(), ipd)?;
+	let lens = lens::open(server, correction, approximate)?;
+	let mode = mode.mode();
+	let profile = OpenHmdProfile::compute(&*lens, mode.width, mode.height, ipd)
+		.context("failed to compute distortion")?;
+	lens.exit()?;
+	write(opts, output.as_deref(), &profile.to_json())
+}
+
+/// Tracking calibration for libsurvive, from the steam (lighthouse) part of the headset
+fn libsurvive(opts: Opts, args: &[&str]) -> Result<()> {
+	let mut output = None;
+	let mut config_file = None;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		let mut value = || *args.next().unwrap_or_else(|| usage());
+		match *arg {
+			"--output" => output = Some(PathBuf::from(value())),
+			"--config" => config_file = Some(PathBuf::from(value())),
+			_ => usage(),
+		}
+	}
+	let config: SteamConfig = match &config_file {
+		Some(path) => serde_json::from_value(config::load(path)?)
+			.with_context(|| format!("{} is not a steam config", path.display()))?,
+		None => SteamDevice::open_first()
+			.context("failed to open steam device")?
+			.read_config()?,
+	};
+	write(opts, output.as_deref(), &libsurvive_config(&config)?)
+}
+
+/// Steam config as is, where SteamVR caches configs of lighthouse devices it has seen:
+/// `<Steam>/config/lighthouse/<serial>/config.json`. SteamVR reads it from there instead of asking
+/// the device, so it is enough for tracking, even before lighthouse driver managed to read it
+fn steamvr(opts: Opts, args: &[&str]) -> Result<()> {
+	let mut output = None;
+	let mut config_file = None;
+	let mut steam = None;
+	let mut force = false;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		let mut value = || *args.next().unwrap_or_else(|| usage());
+		match *arg {
+			"--output" => output = Some(PathBuf::from(value())),
+			"--config" => config_file = Some(PathBuf::from(value())),
+			"--steam" => steam = Some(PathBuf::from(value())),
+			"--force" => force = true,
+			_ => usage(),
+		}
+	}
+	let value = match &config_file {
+		Some(path) => config::load(path)?,
+		None => {
+			let raw = SteamDevice::open_first()
+				.context("failed to open steam device")?
+				.read_config_raw()?;
+			let json = raw.json.context("steam config can't be decompressed")?;
+			serde_json::from_str(&json).context("steam config is not JSON")?
+		}
+	};
+	// SteamVR takes whatever is cached, so broken config should not get there
+	let config: SteamConfig =
+		serde_json::from_value(value.clone()).context("not a steam config")?;
+	if config.lighthouse_config.is_none() {
+		return Err(ProtocolError::MissingCalibration("lighthouse_config").into());
+	}
+	let serial = config
+		.device_serial_number
+		.context("config has no device_serial_number")?;
+
+	let path = match output {
+		Some(path) => path,
+		None => {
+			let root = match steam {
+				Some(root) => root,
+				None => steamvr_path()
+					.as_deref()
+					.and_then(steam_root)
+					.context("SteamVR not found, pass Steam root with --steam")?,
+			};
+			root.join("config/lighthouse")
+				.join(serial.to_lowercase())
+				.join("config.json")
+		}
+	};
+	if path.exists() && !force {
+		bail!(
+			"{} already exists, pass --force to replace it",
+			path.display()
+		);
+	}
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent)
+			.with_context(|| format!("failed to create {}", parent.display()))?;
+	}
+	write(opts, Some(&path), &value)
+}
+
+pub fn run(opts: Opts, args: &[&str]) -> Result<()> {
+	match args {
+		["openhmd", rest @ ..] => openhmd(opts, rest),
+		["libsurvive", rest @ ..] => libsurvive(opts, rest),
+		["steamvr", rest @ ..] => steamvr(opts, rest),
+		_ => usage(),
+	}
+}
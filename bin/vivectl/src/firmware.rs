@@ -0,0 +1,134 @@
+use std::{fs, path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use vive_hid::{STEAM_VID, VIVE_VID};
+
+use crate::{usage, Opts};
+
+const SYSFS_USB: &str = "/sys/bus/usb/devices";
+const STATION_SCAN_TIME: Duration = Duration::from_secs(5);
+
+struct UsbFirmware {
+	vid: u16,
+	pid: u16,
+	product: Option<String>,
+	/// bcdDevice, which HTC and Valve use as firmware version
+	version: String,
+}
+
+fn read_attr(dev: &Path, name: &str) -> Option<String> {
+	fs::read_to_string(dev.join(name))
+		.ok()
+		.map(|v| v.trim().to_owned())
+}
+
+/// Every HTC and Valve device: headset mainboard, tracking board, camera, audio, watchman dongles
+fn usb_firmware() -> Result<Vec<UsbFirmware>> {
+	let mut out = Vec::new();
+	for entry in fs::read_dir(SYSFS_USB).context("failed to list usb devices")? {
+		let path = entry?.path();
+		let (Some(vid), Some(pid), Some(bcd)) = (
+			read_attr(&path, "idVendor").and_then(|v| u16::from_str_radix(&v, 16).ok()),
+			read_attr(&path, "idProduct").and_then(|v| u16::from_str_radix(&v, 16).ok()),
+			read_attr(&path, "bcdDevice"),
+		) else {
+			// Interfaces don't have ids
+			continue;
+		};
+		if vid != VIVE_VID && vid != STEAM_VID {
+			continue;
+		}
+		// BCD, i.e 0103 is 1.03
+		let version = match bcd.split_at(bcd.len().min(2)) {
+			(major, minor) if !minor.is_empty() => {
+				let major = major.trim_start_matches('0');
+				format!("{}.{minor}", if major.is_empty() { "0" } else { major })
+			}
+			_ => bcd,
+		};
+		out.push(UsbFirmware {
+			vid,
+			pid,
+			product: read_attr(&path, "product"),
+			version,
+		});
+	}
+	out.sort_by_key(|f| (f.vid, f.pid));
+	Ok(out)
+}
+
+fn station_firmware() -> Result<Vec<valve_pm::StationInfo>> {
+	let runtime = tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()?;
+	runtime.block_on(async {
+		let manager = valve_pm::start_manager()
+			.await
+			.map_err(|()| anyhow::anyhow!("bluetooth is not available"))?;
+		Ok(valve_pm::station_info(&manager.1, STATION_SCAN_TIME).await?)
+	})
+}
+
+fn info(opts: Opts, args: &[&str]) -> Result<()> {
+	let stations = match args {
+		[] => false,
+		["--stations"] => true,
+		_ => usage(),
+	};
+	let usb = usb_firmware()?;
+	let stations = if stations {
+		Some(station_firmware()?)
+	} else {
+		None
+	};
+	let value = json!({
+		"usb": usb.iter().map(|f| json!({
+			"vid": format!("{:04x}", f.vid),
+			"pid": format!("{:04x}", f.pid),
+			"product": f.product,
+			"version": f.version,
+		})).collect::<Vec<_>>(),
+		"base_stations": stations.as_ref().map(|s| s.iter().map(|s| json!({
+			"name": s.name,
+			"firmware": s.firmware,
+		})).collect::<Vec<_>>()),
+	});
+	opts.print(value, || {
+		let mut out = Vec::new();
+		if usb.is_empty() {
+			out.push("no HTC/Valve usb devices found".to_owned());
+		}
+		for f in &usb {
+			out.push(format!(
+				"{:04x}:{:04x} {}: {}",
+				f.vid,
+				f.pid,
+				f.product.as_deref().unwrap_or("unknown"),
+				f.version
+			));
+		}
+		match &stations {
+			Some(stations) if stations.is_empty() => out.push("no base stations found".to_owned()),
+			Some(stations) => {
+				for s in stations {
+					out.push(format!(
+						"{}: {}",
+						s.name,
+						s.firmware.as_deref().unwrap_or("unknown")
+					));
+				}
+			}
+			None => out.push("run with --stations to query base stations".to_owned()),
+		}
+		out.join("\n")
+	});
+	Ok(())
+}
+
+pub fn run(opts: Opts, args: &[&str]) -> Result<()> {
+	match args {
+		["info", rest @ ..] => info(opts, rest),
+		_ => usage(),
+	}
+}
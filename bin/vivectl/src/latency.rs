@@ -0,0 +1,143 @@
+//! Numbers, which matter for reprojection tuning: HID round-trip, vsync-to-photons as reported
+//! to SteamVR, and real vblank period of the headset crtc
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use vive_hid::Resolution;
+use vive_ipc::Client;
+
+use crate::{device, setup::headset_connector, usage, Opts};
+
+const DEFAULT_SAMPLES: usize = 20;
+
+#[derive(Clone, Copy)]
+struct Summary {
+	min: Duration,
+	avg: Duration,
+	max: Duration,
+}
+impl Summary {
+	fn new(samples: &[Duration]) -> Option<Self> {
+		Some(Self {
+			min: *samples.iter().min()?,
+			avg: samples.iter().sum::<Duration>() / samples.len() as u32,
+			max: *samples.iter().max()?,
+		})
+	}
+	fn json(self) -> Value {
+		json!({ "min_ms": ms(self.min), "avg_ms": ms(self.avg), "max_ms": ms(self.max) })
+	}
+	fn text(self) -> String {
+		format!(
+			"min {:.3}ms, avg {:.3}ms, max {:.3}ms",
+			ms(self.min),
+			ms(self.avg),
+			ms(self.max)
+		)
+	}
+}
+
+fn ms(d: Duration) -> f64 {
+	d.as_secs_f64() * 1000.0
+}
+/// Config timings are missing in older configs
+fn config_ms(seconds: Option<f64>) -> String {
+	seconds.map_or("not set".to_owned(), |s| format!("{:.3}ms", s * 1000.0))
+}
+
+/// Mode, which was applied by whoever owns the headset, device itself can't be asked
+fn active_mode() -> Option<Resolution> {
+	let mut client = Client::connect_driver().or_else(|| Client::connect_default().ok())?;
+	let resolution = client.status().ok()?.settings.resolution?;
+	Resolution::try_from(resolution).ok()
+}
+
+pub fn run(opts: Opts, args: &[&str]) -> Result<()> {
+	let samples = match args {
+		[] => DEFAULT_SAMPLES,
+		["--samples", n] => n.parse().context("samples should be a number")?,
+		_ => usage(),
+	}
+	.max(1);
+
+	let dev = device::open()?;
+	let mut round_trips = Vec::with_capacity(samples);
+	for _ in 0..samples {
+		let start = Instant::now();
+		dev.read_devsn()?;
+		round_trips.push(start.elapsed());
+	}
+	let hid = Summary::new(&round_trips).expect("at least one sample");
+	let config = dev.read_config()?;
+	let active = active_mode();
+
+	let modes = Resolution::ALL
+		.into_iter()
+		.map(|res| {
+			let mode = res.mode();
+			// Same value, as driver-proxy reports in Prop_SecondsFromVsyncToPhotons
+			let vsync_to_photons = 1.0 / mode.frame_rate + mode.extra_photon_vsync;
+			(res, vsync_to_photons as f64 * 1000.0)
+		})
+		.collect::<Vec<_>>();
+
+	// Needs headset display to be lit, i.e leased by SteamVR, so failure is only reported
+	let vblank = headset_connector().and_then(|connector| {
+		let timestamps = connector
+			.vblank_timestamps(samples + 1)
+			.with_context(|| format!("failed to wait for {} vblank", connector.name))?;
+		let intervals = timestamps
+			.windows(2)
+			.map(|w| w[1].saturating_sub(w[0]))
+			.collect::<Vec<_>>();
+		Ok(Summary::new(&intervals).expect("at least one interval"))
+	});
+	let expected_period = active.map(|res| 1000.0 / res.mode().frame_rate as f64);
+
+	let value = json!({
+		"hid_round_trip": hid.json(),
+		"config": {
+			"seconds_from_vsync_to_photons": config.seconds_from_vsync_to_photons,
+			"seconds_from_photons_to_vblank": config.seconds_from_photons_to_vblank,
+		},
+		"active_mode": active.map(|res| res as u8),
+		"modes": modes.iter().map(|(res, vsync_to_photons)| json!({
+			"id": *res as u8,
+			"mode": res.to_string(),
+			"vsync_to_photons_ms": vsync_to_photons,
+		})).collect::<Vec<_>>(),
+		"vblank": match &vblank {
+			Ok(period) => json!({ "period": period.json(), "expected_period_ms": expected_period }),
+			Err(e) => json!({ "error": format!("{e:#}") }),
+		},
+	});
+	opts.print(value, || {
+		let mut out = format!("hid round-trip: {}\n", hid.text());
+		out.push_str(&format!(
+			"config: vsync to photons {}, photons to vblank {}\n",
+			config_ms(config.seconds_from_vsync_to_photons),
+			config_ms(config.seconds_from_photons_to_vblank),
+		));
+		out.push_str("reported vsync to photons:");
+		for (res, vsync_to_photons) in &modes {
+			let marker = if Some(*res) == active {
+				" (active)"
+			} else {
+				""
+			};
+			out.push_str(&format!("\n  {res}: {vsync_to_photons:.3}ms{marker}"));
+		}
+		match &vblank {
+			Ok(period) => {
+				out.push_str(&format!("\nvblank period: {}", period.text()));
+				if let Some(expected) = expected_period {
+					out.push_str(&format!(", expected {expected:.3}ms"));
+				}
+			}
+			Err(e) => out.push_str(&format!("\nvblank period: unavailable, {e:#}")),
+		}
+		out
+	});
+	Ok(())
+}
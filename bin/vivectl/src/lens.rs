@@ -0,0 +1,312 @@
+//! Lens distortion of the headset, as meshes for other compositors (Monado, OpenHMD, custom ones)
+//!
+//! Distortion comes from lens server, same as in driver, or from built-in approximation
+//!
+//! `record` keeps lens server answers next to meshes computed from them, `check` replays them
+//! without wine, and fails if meshes are not the same bit-for-bit, to validate lens stack changes
+use std::{
+	env, fs,
+	fs::File,
+	io::{self, BufWriter, Write},
+	path::{Path, PathBuf},
+	process::{Command, Stdio},
+	rc::Rc,
+	time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use lens_client::{serve_replay, Recording, RecordingClient, ReplayClient};
+use lens_geometry::{write_obj, DistortionMesh, FallbackLens};
+use lens_protocol::{Eye, LensClient, ServerClient, Timeouts};
+use serde_json::{json, Value};
+use vive_protocol::config_diff;
+
+use crate::{config, device, lens_server, usage, Opts};
+
+/// Enough for distortion to be smooth when interpolated, SteamVR uses similar density
+const DEFAULT_GRID: usize = 64;
+const RESPONSES_FILE: &str = "responses.bin";
+const GOLDEN_FILE: &str = "golden.json";
+/// Replay server answers right away
+const REPLAY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Differences shown by `check`
+const SHOWN_DIFFERENCES: usize = 10;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+	Json,
+	Obj,
+}
+
+fn export(opts: Opts, args: &[&str]) -> Result<()> {
+	let mut format = Format::Json;
+	let mut grid = DEFAULT_GRID;
+	let mut output = None;
+	let mut config_file = None;
+	let mut server = None;
+	let mut approximate = false;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		let mut value = || *args.next().unwrap_or_else(|| usage());
+		match *arg {
+			"--format" => {
+				format = match value() {
+					"json" => Format::Json,
+					"obj" => Format::Obj,
+					_ => usage(),
+				}
+			}
+			"--grid" => {
+				grid = value()
+					.parse()
+					.ok()
+					.filter(|&g| g > 0)
+					.unwrap_or_else(|| usage())
+			}
+			"--output" => output = Some(PathBuf::from(value())),
+			"--config" => config_file = Some(PathBuf::from(value())),
+			"--server" => server = Some(PathBuf::from(value())),
+			"--approximate" => approximate = true,
+			_ => usage(),
+		}
+	}
+	let correction = load_correction(config_file.as_deref())?;
+	let lens = open(server, correction, approximate)?;
+	let meshes = compute(&*lens, grid)?;
+	lens.exit()?;
+
+	let mut out: Box<dyn Write> = match &output {
+		Some(path) => Box::new(BufWriter::new(
+			File::create(path).with_context(|| format!("failed to create {path:?}"))?,
+		)),
+		None => Box::new(BufWriter::new(io::stdout().lock())),
+	};
+	match format {
+		Format::Json => writeln!(out, "{}", to_json(&meshes, approximate))?,
+		Format::Obj => write_obj(&meshes, &mut out)?,
+	}
+	out.flush()?;
+	if let Some(path) = &output {
+		report(opts, path, format, grid);
+	}
+	Ok(())
+}
+
+fn load_correction(config_file: Option<&Path>) -> Result<Value> {
+	Ok(match config_file {
+		Some(path) => config::load(path)?["inhouse_lens_correction"].clone(),
+		None => device::open()?.read_config()?.inhouse_lens_correction,
+	})
+}
+
+pub fn open(
+	server: Option<PathBuf>,
+	correction: Value,
+	approximate: bool,
+) -> Result<Rc<dyn LensClient>> {
+	Ok(if approximate {
+		Rc::new(FallbackLens::new(&correction))
+	} else {
+		Rc::new(lens_server::start(server, correction)?)
+	})
+}
+
+fn compute(lens: &dyn LensClient, grid: usize) -> Result<Vec<DistortionMesh>> {
+	[Eye::Left, Eye::Right]
+		.into_iter()
+		.map(|eye| DistortionMesh::compute(lens, eye, grid))
+		.collect::<Result<Vec<_>, _>>()
+		.context("failed to compute distortion")
+}
+
+fn to_json(meshes: &[DistortionMesh], approximate: bool) -> Value {
+	json!({
+		"source": if approximate { "approximate" } else { "lens-server" },
+		"meshes": meshes.iter().map(DistortionMesh::to_json).collect::<Vec<_>>(),
+	})
+}
+
+/// Lens server answers and meshes computed from them, in `output` directory
+fn record(opts: Opts, args: &[&str]) -> Result<()> {
+	let mut grid = DEFAULT_GRID;
+	let mut output = None;
+	let mut config_file = None;
+	let mut server = None;
+	let mut approximate = false;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		let mut value = || *args.next().unwrap_or_else(|| usage());
+		match *arg {
+			"--grid" => {
+				grid = value()
+					.parse()
+					.ok()
+					.filter(|&g| g > 0)
+					.unwrap_or_else(|| usage())
+			}
+			"--output" => output = Some(PathBuf::from(value())),
+			"--config" => config_file = Some(PathBuf::from(value())),
+			"--server" => server = Some(PathBuf::from(value())),
+			"--approximate" => approximate = true,
+			_ => usage(),
+		}
+	}
+	let output = output.unwrap_or_else(|| usage());
+	let correction = load_correction(config_file.as_deref())?;
+	let lens = RecordingClient::new(open(server, correction.clone(), approximate)?, &correction);
+	let meshes = compute(&lens, grid)?;
+	lens.exit()?;
+
+	fs::create_dir_all(&output).with_context(|| format!("failed to create {output:?}"))?;
+	let recording = lens.recording();
+	recording
+		.store(&output.join(RESPONSES_FILE))
+		.context("failed to write recorded answers")?;
+	fs::write(
+		output.join(GOLDEN_FILE),
+		format!("{}\n", to_json(&meshes, approximate)),
+	)
+	.context("failed to write golden meshes")?;
+	opts.print(
+		json!({ "output": output, "grid": grid, "calls": recording.calls.len() }),
+		|| {
+			format!(
+				"{} lens server answers, and {grid}x{grid} golden meshes are written to {}",
+				recording.calls.len(),
+				output.display()
+			)
+		},
+	);
+	Ok(())
+}
+
+/// Recorded answers go through lens protocol, with this same binary as lens server
+fn start_replay(responses: &Path, config: Value) -> Result<ServerClient> {
+	let exe = env::current_exe().context("failed to find vivectl executable")?;
+	let child = Command::new(exe)
+		.args(["lens", "replay-server"])
+		.arg(responses)
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.spawn()
+		.context("failed to start replay server")?;
+	let timeouts = Timeouts {
+		handshake: Some(REPLAY_TIMEOUT),
+		request: Some(REPLAY_TIMEOUT),
+	};
+	ServerClient::open(child, config, timeouts).context("replay server has failed handshake")
+}
+
+fn check(opts: Opts, args: &[&str]) -> Result<()> {
+	let mut dir = None;
+	let mut in_process = false;
+	for arg in args {
+		match *arg {
+			"--in-process" => in_process = true,
+			arg if !arg.starts_with("--") && dir.is_none() => dir = Some(PathBuf::from(arg)),
+			_ => usage(),
+		}
+	}
+	let dir = dir.unwrap_or_else(|| usage());
+	let responses = dir.join(RESPONSES_FILE);
+	let recording = Recording::load(&responses)
+		.with_context(|| format!("failed to read {}", responses.display()))?;
+	let golden: Value = serde_json::from_slice(
+		&fs::read(dir.join(GOLDEN_FILE)).context("failed to read golden meshes")?,
+	)
+	.context("golden meshes are not valid JSON")?;
+	let grid = golden["meshes"][0]["grid"]
+		.as_u64()
+		.context("golden meshes have no grid")? as usize;
+
+	let config = recording.config();
+	let (lens, via): (Rc<dyn LensClient>, String) = if in_process {
+		(
+			Rc::new(ReplayClient::new(recording)),
+			"in-process".to_owned(),
+		)
+	} else {
+		let client = start_replay(&responses, config)?;
+		let via = format!(
+			"lens protocol {}{}",
+			client.negotiated().version,
+			if client.shared_memory() {
+				", shared memory"
+			} else {
+				""
+			}
+		);
+		(Rc::new(client), via)
+	};
+	let meshes = compute(&*lens, grid)?;
+	lens.exit()?;
+
+	let json = to_json(&meshes, false);
+	let differences = config_diff(&golden["meshes"], &json["meshes"]);
+	let shown = differences
+		.iter()
+		.take(SHOWN_DIFFERENCES)
+		.map(ToString::to_string)
+		.collect::<Vec<_>>();
+	opts.print(
+		json!({
+			"via": via,
+			"grid": grid,
+			"matches": differences.is_empty(),
+			"differences": differences.len(),
+			"first": shown,
+		}),
+		|| {
+			if differences.is_empty() {
+				return format!("{grid}x{grid} meshes match golden ones bit-for-bit ({via})");
+			}
+			let mut out = format!(
+				"{} values differ from golden meshes ({via}):",
+				differences.len()
+			);
+			for change in &shown {
+				out.push_str("\n  ");
+				out.push_str(change);
+			}
+			out
+		},
+	);
+	if !differences.is_empty() {
+		bail!("meshes differ from golden ones");
+	}
+	Ok(())
+}
+
+/// Stands in for lens server, for `check`
+fn replay_server(args: &[&str]) -> Result<()> {
+	let [path] = args else { usage() };
+	let recording = Recording::load(Path::new(path)).context("failed to read recorded answers")?;
+	serve_replay(&recording).context("replay has failed")?;
+	Ok(())
+}
+
+fn report(opts: Opts, path: &Path, format: Format, grid: usize) {
+	let format = match format {
+		Format::Json => "json",
+		Format::Obj => "obj",
+	};
+	opts.print(
+		json!({ "output": path, "format": format, "grid": grid }),
+		|| {
+			format!(
+				"{grid}x{grid} distortion meshes are written to {}",
+				path.display()
+			)
+		},
+	);
+}
+
+pub fn run(opts: Opts, args: &[&str]) -> Result<()> {
+	match args {
+		["export", rest @ ..] => export(opts, rest),
+		["record", rest @ ..] => record(opts, rest),
+		["check", rest @ ..] => check(opts, rest),
+		["replay-server", rest @ ..] => replay_server(rest),
+		_ => usage(),
+	}
+}
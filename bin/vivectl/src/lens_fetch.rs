@@ -0,0 +1,154 @@
+//! Vendor libraries for lens server, for `setup lens-server --fetch`
+//!
+//! They can't be redistributed, so missing VIVE Console is installed by Steam itself, from the
+//! user's own account, and only after the user agrees. Staged copies are recorded in
+//! `SHA256SUMS` next to them, and checked against it when they are reused
+use std::{
+	fs,
+	io::{self, BufRead, IsTerminal, Write},
+	path::{Path, PathBuf},
+	process::{Command, Stdio},
+	thread,
+	time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+	lens_server::{find_dlls, VENDOR_DLLS},
+	Opts,
+};
+
+/// VIVE Console for SteamVR
+const VIVE_CONSOLE_APP_ID: u32 = 1635730;
+/// Download is a few hundred megabytes
+const INSTALL_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+pub const MANIFEST: &str = "SHA256SUMS";
+
+fn consent(opts: Opts) -> Result<bool> {
+	// Prompt would be mixed with JSON output otherwise
+	if opts.json || !io::stdin().is_terminal() {
+		bail!("VIVE Console is not installed, pass --yes to let Steam install it");
+	}
+	eprint!(
+		"VIVE Console is not installed, lens server needs its libraries.\nAsk Steam to install it \
+		 (app {VIVE_CONSOLE_APP_ID}, from your Steam account)? [y/N] "
+	);
+	io::stderr().flush()?;
+	let mut line = String::new();
+	io::stdin().lock().read_line(&mut line)?;
+	Ok(matches!(line.trim(), "y" | "Y" | "yes"))
+}
+
+/// Running Steam gets the URL, whichever way it is installed
+fn request_install() -> Result<()> {
+	let url = format!("steam://install/{VIVE_CONSOLE_APP_ID}");
+	for opener in ["xdg-open", "steam"] {
+		let res = Command::new(opener)
+			.arg(&url)
+			.stdin(Stdio::null())
+			.stdout(Stdio::null())
+			.stderr(Stdio::null())
+			.spawn();
+		match res {
+			Ok(_) => return Ok(()),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+			Err(e) => return Err(e).with_context(|| format!("failed to run {opener}")),
+		}
+	}
+	bail!("neither xdg-open nor steam are found, install VIVE Console from Steam manually");
+}
+
+/// Directory with vendor libraries, waits for Steam to install them if needed
+pub fn acquire(opts: Opts, yes: bool, dry_run: bool) -> Result<PathBuf> {
+	if let Some(dir) = find_dlls() {
+		return Ok(dir);
+	}
+	if dry_run {
+		bail!("VIVE Console is not installed, would ask Steam to install it");
+	}
+	if !yes && !consent(opts)? {
+		bail!("VIVE Console is not installed, install it from Steam, or pass --from <dir>");
+	}
+	request_install()?;
+	eprintln!("waiting for Steam to install VIVE Console, confirm it in Steam window");
+	let until = Instant::now() + INSTALL_TIMEOUT;
+	while Instant::now() < until {
+		thread::sleep(POLL_INTERVAL);
+		if let Some(dir) = find_dlls() {
+			return Ok(dir);
+		}
+	}
+	bail!("VIVE Console is still not installed, rerun once Steam has finished")
+}
+
+fn sha256(path: &Path) -> Result<String> {
+	let out = Command::new("sha256sum")
+		.arg(path)
+		.stderr(Stdio::inherit())
+		.output()
+		.context("failed to run sha256sum")?;
+	if !out.status.success() {
+		bail!("sha256sum has failed for {}", path.display());
+	}
+	let out = String::from_utf8_lossy(&out.stdout);
+	match out.split_whitespace().next() {
+		Some(hash) if hash.len() == 64 => Ok(hash.to_owned()),
+		_ => bail!("unexpected sha256sum output: {out}"),
+	}
+}
+
+/// `sha256sum` format, name by hash
+fn read_manifest(dir: &Path) -> Result<Option<Vec<(String, String)>>> {
+	let data = match fs::read_to_string(dir.join(MANIFEST)) {
+		Ok(data) => data,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+		Err(e) => return Err(e).context("failed to read hash manifest"),
+	};
+	Ok(Some(
+		data.lines()
+			.filter_map(|line| {
+				let (hash, name) = line.split_once("  ")?;
+				Some((name.to_owned(), hash.to_owned()))
+			})
+			.collect(),
+	))
+}
+
+/// Copies vendor libraries to `target`, and checks that copies match. Libraries already in
+/// `target` are checked against recorded hashes instead. Returns name and hash of every library
+pub fn stage(from: &Path, target: &Path) -> Result<Vec<(String, String)>> {
+	let mut hashes = Vec::new();
+	if from == target {
+		let manifest = read_manifest(target)?;
+		for dll in VENDOR_DLLS {
+			let hash = sha256(&target.join(dll))?;
+			let recorded = manifest
+				.iter()
+				.flatten()
+				.find(|(name, _)| name == dll)
+				.map(|(_, hash)| hash);
+			if let Some(recorded) = recorded.filter(|r| **r != hash) {
+				bail!("{dll} doesn't match {MANIFEST} (recorded {recorded}, actual {hash}), copy it from VIVE Console again");
+			}
+			hashes.push((dll.to_string(), hash));
+		}
+	} else {
+		for dll in VENDOR_DLLS {
+			let (source, copy) = (from.join(dll), target.join(dll));
+			fs::copy(&source, &copy).with_context(|| format!("failed to copy {dll}"))?;
+			let hash = sha256(&source)?;
+			if sha256(&copy)? != hash {
+				bail!("copy of {dll} doesn't match the original");
+			}
+			hashes.push((dll.to_string(), hash));
+		}
+	}
+	let manifest = hashes
+		.iter()
+		.map(|(name, hash)| format!("{hash}  {name}\n"))
+		.collect::<String>();
+	fs::write(target.join(MANIFEST), manifest).context("failed to write hash manifest")?;
+	Ok(hashes)
+}
@@ -0,0 +1,375 @@
+//! Dedicated Wine prefix for lens server, with vendor libraries copied next to it
+//!
+//! Lens server needs `LibLensDistortion.dll` (and OpenCV it links to) from VIVE Console, which are
+//! looked up in Steam libraries, or in already installed driver. Everything is kept in
+//! `$XDG_DATA_HOME/vivepro2`, and resulting paths are written to driver config
+//!
+//! Lens server may run under Proton instead, then Proton manages its own prefix
+//!
+//! With `--fetch`, missing VIVE Console is installed through Steam, see [`crate::lens_fetch`]
+use std::{
+	env, fs,
+	path::{Path, PathBuf},
+	process::{Command, Stdio},
+};
+
+use anyhow::{bail, Context, Result};
+use lens_client::{find_protons, select_proton, start_lens_server, Proton};
+use lens_protocol::ServerClient;
+use serde_json::{json, Value};
+use vive_ipc::{headsets, toml};
+
+use crate::{driver::LIGHTHOUSE_BIN, lens_fetch, steam::find_steamvr, usage, Opts};
+
+pub const SERVER_EXE: &str = "lens-server.exe";
+/// Loaded by lens server from its own directory
+pub const VENDOR_DLLS: &[&str] = &["LibLensDistortion.dll", "opencv_world346.dll"];
+/// `lens-server.exe check` exits with this code, without loading anything
+const CHECK_EXIT_CODE: i32 = 42;
+/// VIVE Console keeps the library a few directories deep
+const SEARCH_DEPTH: usize = 4;
+
+/// `$XDG_DATA_HOME/vivepro2`
+pub fn data_dir() -> PathBuf {
+	let base = match env::var_os("XDG_DATA_HOME") {
+		Some(dir) => PathBuf::from(dir),
+		None => Path::new(&env::var_os("HOME").unwrap_or_default()).join(".local/share"),
+	};
+	base.join("vivepro2")
+}
+pub fn server_dir() -> PathBuf {
+	data_dir().join("lens-server")
+}
+pub fn prefix_dir() -> PathBuf {
+	data_dir().join("wine-prefix")
+}
+
+fn has_dlls(dir: &Path) -> bool {
+	VENDOR_DLLS.iter().all(|dll| dir.join(dll).is_file())
+}
+
+fn find_dlls_in(dir: &Path, depth: usize) -> Option<PathBuf> {
+	if has_dlls(dir) {
+		return Some(dir.to_owned());
+	}
+	if depth == 0 {
+		return None;
+	}
+	fs::read_dir(dir)
+		.ok()?
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| entry.file_type().map(|t| t.is_dir()).unwrap_or(false))
+		.find_map(|entry| find_dlls_in(&entry.path(), depth - 1))
+}
+
+/// VIVE Console depot in any Steam library, then lens server of installed driver
+pub fn find_dlls() -> Option<PathBuf> {
+	let steamvrs = find_steamvr();
+	let depots = steamvrs
+		.iter()
+		.filter_map(|steamvr| fs::read_dir(steamvr.parent()?).ok())
+		.flatten()
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| {
+			entry
+				.file_name()
+				.to_string_lossy()
+				.to_lowercase()
+				.contains("vive")
+		})
+		.find_map(|entry| find_dlls_in(&entry.path(), SEARCH_DEPTH));
+	depots.or_else(|| {
+		steamvrs
+			.iter()
+			.map(|steamvr| steamvr.join(LIGHTHOUSE_BIN).join("lens-server"))
+			.find(|dir| has_dlls(dir))
+	})
+}
+
+/// Lens server is built with the driver, so it comes from the release or installed driver
+fn find_server() -> Option<PathBuf> {
+	let release = env::current_dir().ok().map(|dir| dir.join("lens-server"));
+	let installed = find_steamvr()
+		.into_iter()
+		.map(|steamvr| steamvr.join(LIGHTHOUSE_BIN).join("lens-server"));
+	release
+		.into_iter()
+		.chain(installed)
+		.map(|dir| dir.join(SERVER_EXE))
+		.find(|exe| exe.is_file())
+}
+
+/// Starts lens server outside of SteamVR, for tools which need distortion. Same variables driver
+/// reads are respected, and prefix made by `setup lens-server` is used otherwise
+pub fn start(server: Option<PathBuf>, correction: Value) -> Result<ServerClient> {
+	let server = server
+		.or_else(|| env::var_os("LENS_SERVER_EXE").map(PathBuf::from))
+		.or_else(|| Some(server_dir().join(SERVER_EXE)).filter(|exe| exe.is_file()))
+		.or_else(find_server)
+		.context(
+			"lens-server.exe not found, pass --server <exe>, or run vivectl setup lens-server",
+		)?;
+	env::set_var("LENS_SERVER_EXE", &server);
+	let prefix = prefix_dir();
+	if env::var_os("WINE").is_none()
+		&& env::var_os("PROTON").is_none()
+		&& prefix.join("system.reg").is_file()
+	{
+		if let Some(wine) = find_wine() {
+			env::set_var("WINE", wine);
+			env::set_var("WINEPREFIX", prefix);
+		}
+	}
+	start_lens_server(correction)
+		.context("failed to start lens server, pass --approximate to use built-in distortion")
+}
+
+fn find_wine() -> Option<PathBuf> {
+	let path = env::var_os("PATH")?;
+	["wine64", "wine"].iter().find_map(|name| {
+		env::split_paths(&path)
+			.map(|dir| dir.join(name))
+			.find(|wine| wine.is_file())
+	})
+}
+
+fn wine_command(wine: &Path, prefix: &Path) -> Command {
+	let mut command = Command::new(wine);
+	command
+		.env("WINEPREFIX", prefix)
+		.env("WINEDEBUG", "-all")
+		.stdin(Stdio::null())
+		.stdout(Stdio::null())
+		.stderr(Stdio::null());
+	command
+}
+
+/// Prefix is created by wineboot, `system.reg` is there once it has finished
+fn ensure_prefix(wine: &Path, prefix: &Path) -> Result<bool> {
+	if prefix.join("system.reg").is_file() {
+		return Ok(false);
+	}
+	fs::create_dir_all(prefix)?;
+	let status = wine_command(wine, prefix)
+		.args(["wineboot", "--init"])
+		.status()
+		.with_context(|| format!("failed to run {}", wine.display()))?;
+	if !status.success() || !prefix.join("system.reg").is_file() {
+		bail!(
+			"wineboot has failed to create prefix at {}",
+			prefix.display()
+		);
+	}
+	Ok(true)
+}
+
+fn check_server(wine: &Path, prefix: &Path, exe: &Path) -> Result<()> {
+	let status = wine_command(wine, prefix)
+		.arg(exe)
+		.arg("check")
+		.status()
+		.with_context(|| format!("failed to run {}", wine.display()))?;
+	if status.code() != Some(CHECK_EXIT_CODE) {
+		bail!("lens server doesn't start in the prefix: {status}");
+	}
+	Ok(())
+}
+
+fn list_protons(opts: Opts) -> Result<()> {
+	let protons = find_protons();
+	opts.print(
+		json!(protons
+			.iter()
+			.map(|p| json!({ "name": p.name, "script": p.script, "custom": p.custom }))
+			.collect::<Vec<_>>()),
+		|| {
+			if protons.is_empty() {
+				return "no proton builds found".to_owned();
+			}
+			protons
+				.iter()
+				.map(|p| format!("{}\t{}", p.name, p.script.display()))
+				.collect::<Vec<_>>()
+				.join("\n")
+		},
+	);
+	Ok(())
+}
+
+/// What lens server is started with
+enum Runtime {
+	Wine(PathBuf),
+	Proton(Proton),
+}
+
+pub fn setup(opts: Opts, args: &[&str]) -> Result<()> {
+	let mut dry_run = false;
+	let mut from = None;
+	let mut server = None;
+	let mut wine = None;
+	let mut proton = None;
+	let mut fetch = false;
+	let mut yes = false;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		let mut value = || PathBuf::from(args.next().unwrap_or_else(|| usage()));
+		match *arg {
+			"--dry-run" => dry_run = true,
+			"--fetch" => fetch = true,
+			"--yes" => yes = true,
+			"--from" => from = Some(value()),
+			"--server" => server = Some(value()),
+			"--wine" => wine = Some(value()),
+			"--proton" => proton = Some(value()),
+			"--list-protons" => return list_protons(opts),
+			_ => usage(),
+		}
+	}
+	let dlls = match from {
+		Some(dir) if has_dlls(&dir) => dir,
+		Some(dir) => bail!(
+			"{} doesn't contain {}",
+			dir.display(),
+			VENDOR_DLLS.join(", ")
+		),
+		None if fetch => lens_fetch::acquire(opts, yes, dry_run)?,
+		None => find_dlls().with_context(|| {
+			format!(
+				"{} not found, install VIVE Console from Steam, pass --fetch to let Steam install it, or pass --from <dir>",
+				VENDOR_DLLS[0]
+			)
+		})?,
+	};
+	let server = match server {
+		Some(exe) => exe,
+		None => find_server().context(
+			"lens-server.exe not found, run from release directory, install driver, or pass --server <exe>",
+		)?,
+	};
+	let runtime = match (wine, proton) {
+		(Some(_), Some(_)) => usage(),
+		(_, Some(name)) => Runtime::Proton(select_proton(name.as_os_str()).with_context(|| {
+			format!(
+				"proton {} is not found, see vivectl setup lens-server --list-protons",
+				name.display()
+			)
+		})?),
+		(Some(wine), None) => Runtime::Wine(wine),
+		(None, None) => Runtime::Wine(
+			find_wine()
+				.context("wine is not found in PATH, pass --wine <path> or --proton <name>")?,
+		),
+	};
+	let target = server_dir();
+	let exe = target.join(SERVER_EXE);
+	let prefix = prefix_dir();
+	let config_path = headsets::config_dir().join("driver.toml");
+
+	let mut created = false;
+	let mut hashes = Vec::new();
+	if !dry_run {
+		fs::create_dir_all(&target)?;
+		// Server may already be the one in the target directory
+		if server != exe {
+			fs::copy(&server, &exe).with_context(|| format!("failed to copy {server:?}"))?;
+		}
+		hashes = lens_fetch::stage(&dlls, &target)?;
+		if let Runtime::Wine(wine) = &runtime {
+			created = ensure_prefix(wine, &prefix)?;
+			check_server(wine, &prefix, &exe)?;
+		}
+
+		let data = match fs::read_to_string(&config_path) {
+			Ok(data) => data,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+			Err(e) => return Err(e).with_context(|| format!("failed to read {config_path:?}")),
+		};
+		let path = |path: &Path| Some(toml::quote(&path.to_string_lossy()));
+		// Wine is preferred by driver when both are set
+		let keys = match &runtime {
+			Runtime::Wine(wine) => [
+				("wine", path(wine)),
+				("wine_prefix", path(&prefix)),
+				("proton", None),
+			],
+			Runtime::Proton(proton) => [
+				("wine", None),
+				("wine_prefix", None),
+				("proton", path(&proton.script)),
+			],
+		};
+		let data = toml::set_keys(&data, &keys)
+			.and_then(|data| toml::set_keys(&data, &[("lens_server", path(&exe))]))
+			.map_err(anyhow::Error::msg)
+			.with_context(|| format!("failed to update {config_path:?}"))?;
+		if let Some(dir) = config_path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		fs::write(&config_path, data)
+			.with_context(|| format!("failed to write {config_path:?}"))?;
+	}
+
+	let (wine, proton) = match &runtime {
+		Runtime::Wine(wine) => (Some(wine), None),
+		Runtime::Proton(proton) => (None, Some(proton)),
+	};
+	opts.print(
+		json!({
+			"dlls": dlls,
+			"server": server,
+			"wine": wine,
+			"proton": proton.map(|p| &p.script),
+			"target": target,
+			"prefix": wine.map(|_| &prefix),
+			"prefix_created": created,
+			"hashes": hashes.iter().map(|(name, hash)| (name.clone(), json!(hash))).collect::<serde_json::Map<_, _>>(),
+			"config": config_path,
+			"applied": !dry_run,
+		}),
+		|| {
+			let mut out = format!(
+				"vendor libraries: {}\nlens server: {}",
+				dlls.display(),
+				server.display(),
+			);
+			match (wine, proton) {
+				(Some(wine), _) => out.push_str(&format!("\nwine: {}", wine.display())),
+				(_, Some(proton)) => out.push_str(&format!(
+					"\nproton: {} ({})",
+					proton.name,
+					proton.script.display()
+				)),
+				_ => {}
+			}
+			if dry_run {
+				out.push_str(&format!(
+					"\nwould copy them to {}, and update {}",
+					target.display(),
+					config_path.display()
+				));
+				if wine.is_some() {
+					out.push_str(&format!("\nwould use prefix {}", prefix.display()));
+				}
+			} else {
+				out.push_str(&format!(
+					"\ncopied to {}, hashes are recorded in {}",
+					target.display(),
+					lens_fetch::MANIFEST
+				));
+				if wine.is_some() {
+					out.push_str(&format!(
+						"\nprefix {} ({}), lens server starts in it",
+						prefix.display(),
+						if created { "created" } else { "already exists" },
+					));
+				}
+				out.push_str(&format!(
+					"\n{} is updated, restart SteamVR to apply",
+					config_path.display()
+				));
+			}
+			out
+		},
+	);
+	Ok(())
+}
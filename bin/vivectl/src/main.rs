@@ -0,0 +1,157 @@
+use std::{
+	env::{self, args},
+	process::{exit, ExitCode},
+};
+
+use tracing_subscriber::filter::LevelFilter;
+
+mod camera;
+mod completions;
+mod config;
+mod debug;
+mod device;
+mod diag;
+mod doctor;
+mod driver;
+mod export;
+mod firmware;
+mod latency;
+mod lens;
+mod lens_fetch;
+mod lens_server;
+mod profile;
+mod reset;
+mod setup;
+mod steam;
+mod tui;
+mod watch;
+
+const USAGE_HEADER: &str = "\
+Usage: vivectl [--json] <command>
+
+Commands:
+";
+/// After commands, which come from the command tree of completions
+const USAGE_FOOTER: &str = "
+Options:
+  --json                        Print machine-readable output instead of text
+
+Exit codes:
+  1 other errors, 2 usage, 65 mode doesn't fit into DisplayPort link, 69 headset not found or disconnected, 74 HID/io error,
+  75 headset busy (held by SteamVR, switching modes, or mode switch timed out),
+  76 unexpected reply,
+  77 no permission to access headset, 130 cancelled
+";
+
+/// Global flags, available to every command
+#[derive(Clone, Copy)]
+pub struct Opts {
+	pub json: bool,
+}
+impl Opts {
+	/// Prints `value` as JSON, or `text` otherwise, so every command has the same switch
+	pub fn print(&self, value: serde_json::Value, text: impl FnOnce() -> String) {
+		if self.json {
+			println!("{value}");
+		} else {
+			println!("{}", text());
+		}
+	}
+}
+
+fn usage_text() -> String {
+	format!(
+		"{USAGE_HEADER}{}{USAGE_FOOTER}",
+		completions::commands_usage()
+	)
+}
+
+fn usage() -> ! {
+	eprint!("{}", usage_text());
+	exit(2)
+}
+
+/// sysexits.h codes, so scripts can tell headset problems apart without parsing messages
+fn exit_code(e: &anyhow::Error) -> u8 {
+	use vive_hid::Error as E;
+	let Some(e) = e.chain().find_map(|e| e.downcast_ref::<E>()) else {
+		return 1;
+	};
+	match e.root() {
+		E::DeviceNotFound | E::NotAVive | E::Disconnected(_) => 69,
+		E::Hid(_) | E::Io(_) => 74,
+		E::HeldBySteamVr { .. } | E::ModeSwitchInProgress | E::SwitchTimeout { .. } => 75,
+		E::ModeNotAchievable { .. } => 65,
+		E::Protocol(_) | E::ConfigReadFailed { .. } => 76,
+		E::PermissionDenied(_) => 77,
+		E::Cancelled => 130,
+		E::Context { .. } => 1,
+	}
+}
+
+/// `VIVEPRO2_LOG=debug`, `trace` also shows every HID report with `trace-hid` feature
+fn log_level() -> LevelFilter {
+	env::var("VIVEPRO2_LOG")
+		.ok()
+		.and_then(|level| level.parse().ok())
+		.unwrap_or(LevelFilter::INFO)
+}
+
+fn main() -> ExitCode {
+	tracing_subscriber::fmt()
+		.with_writer(std::io::stderr)
+		.without_time()
+		.with_max_level(log_level())
+		.init();
+	let args = args().skip(1).collect::<Vec<_>>();
+	let mut opts = Opts { json: false };
+	let args = args
+		.iter()
+		.map(String::as_str)
+		.filter(|arg| match *arg {
+			"--json" => {
+				opts.json = true;
+				false
+			}
+			_ => true,
+		})
+		.collect::<Vec<_>>();
+	let res = match args.as_slice() {
+		["info", rest @ ..] => device::info(opts, rest),
+		["resolution", rest @ ..] => device::resolution(opts, rest),
+		["brightness", rest @ ..] => device::brightness(opts, rest),
+		["noise-cancel", rest @ ..] => device::noise_cancel(opts, rest),
+		["color", rest @ ..] => device::color(opts, rest),
+		["profile", rest @ ..] => profile::run(opts, rest),
+		["watch", rest @ ..] => watch::run(opts, rest),
+		["tui", rest @ ..] => tui::run(rest),
+		["config", rest @ ..] => config::run(opts, rest),
+		["setup", rest @ ..] => setup::run(opts, rest),
+		["doctor", rest @ ..] => doctor::run(opts, rest),
+		["driver", rest @ ..] => driver::run(opts, rest),
+		["diag", rest @ ..] => diag::run(opts, rest),
+		["latency", rest @ ..] => latency::run(opts, rest),
+		["camera", rest @ ..] => camera::run(opts, rest),
+		["lens", rest @ ..] => lens::run(opts, rest),
+		["export", rest @ ..] => export::run(opts, rest),
+		["debug", rest @ ..] => debug::run(opts, rest),
+		["reset", rest @ ..] => reset::run(opts, rest),
+		["firmware", rest @ ..] => firmware::run(opts, rest),
+		["completions", rest @ ..] => completions::run(rest),
+		["help" | "--help" | "-h"] => {
+			print!("{}", usage_text());
+			Ok(())
+		}
+		_ => usage(),
+	};
+	let Err(e) = res else {
+		return ExitCode::SUCCESS;
+	};
+	// Scripts shouldn't need to parse stderr to find the failure reason
+	if opts.json {
+		println!("{}", serde_json::json!({ "error": format!("{e:#}") }));
+	} else {
+		eprintln!("Error: {e:?}");
+	}
+	ExitCode::from(exit_code(&e))
+}
@@ -0,0 +1,67 @@
+use anyhow::{Context, Result};
+use serde_json::json;
+use vive_hid::Resolution;
+use vive_ipc::{Client, Request, Response, Settings};
+
+use crate::{usage, Opts};
+
+fn describe(settings: &Settings) -> String {
+	let mut parts = Vec::new();
+	if let Some(resolution) = settings.resolution {
+		parts.push(match Resolution::try_from(resolution) {
+			Ok(res) => res.to_string(),
+			Err(_) => format!("mode {resolution}"),
+		});
+	}
+	if let Some(brightness) = settings.brightness {
+		parts.push(format!("brightness {brightness}"));
+	}
+	if let Some(enabled) = settings.noise_cancel {
+		parts.push(format!(
+			"noise cancel {}",
+			if enabled { "on" } else { "off" }
+		));
+	}
+	if let Some(enabled) = settings.cameras {
+		parts.push(format!("cameras {}", if enabled { "on" } else { "off" }));
+	}
+	parts.join(", ")
+}
+
+/// Profiles are stored by vived, so it should be running
+pub fn run(opts: Opts, args: &[&str]) -> Result<()> {
+	let mut daemon =
+		Client::connect_default().context("failed to connect to vived, is it running?")?;
+	match args {
+		[] => {
+			let Response::Profiles { active, profiles } = daemon.request(&Request::Profiles)?
+			else {
+				return Err(vive_ipc::Error::UnexpectedResponse.into());
+			};
+			opts.print(json!({ "active": active, "profiles": profiles }), || {
+				profiles
+					.iter()
+					.map(|(name, settings)| {
+						let mark = if active.as_ref() == Some(name) {
+							'*'
+						} else {
+							' '
+						};
+						format!("{mark} {name}: {}", describe(settings))
+					})
+					.collect::<Vec<_>>()
+					.join("\n")
+			});
+		}
+		[name] => {
+			daemon.request(&Request::SetProfile {
+				name: (*name).to_owned(),
+			})?;
+			opts.print(json!({ "active": name }), || {
+				format!("switched to {name} profile")
+			});
+		}
+		_ => usage(),
+	}
+	Ok(())
+}
@@ -0,0 +1,104 @@
+//! Settings reset, for headsets, which ended up in bad state because of what host software
+//! stores for them
+//!
+//! There is no known vendor command for factory reset, and writes to headset flash are not
+//! confirmed, so nothing stored on the headset is touched: settings vived and driver store for
+//! it on this machine are forgotten, and its chip is restarted
+use std::{
+	fs,
+	io::{self, BufRead, IsTerminal, Write},
+	path::PathBuf,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use serde_json::json;
+use vive_ipc::{headsets, Client};
+
+use crate::{device, usage, Opts};
+
+/// Settings, which are about to be forgotten, `None` if there were none
+fn backup(serial: &str) -> Result<Option<PathBuf>> {
+	let Some(settings) = headsets::load(serial)? else {
+		return Ok(None);
+	};
+	let time = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or_default()
+		.as_secs();
+	let dir = headsets::config_dir()
+		.join("backups")
+		.join(format!("{serial}-{time}"));
+	fs::create_dir_all(&dir).with_context(|| format!("failed to create {dir:?}"))?;
+	let good_mode = headsets::last_good_mode(serial)?;
+	fs::write(
+		dir.join("settings.json"),
+		serde_json::to_string_pretty(&json!({ "settings": settings, "good_mode": good_mode }))?,
+	)?;
+	Ok(Some(dir))
+}
+
+fn confirm(opts: Opts, serial: &str) -> Result<bool> {
+	// Prompt would be mixed with JSON output otherwise
+	if opts.json || !io::stdin().is_terminal() {
+		bail!("refusing to reset without confirmation, pass --yes to run non-interactively");
+	}
+	eprint!(
+		"This forgets settings stored for headset {serial} on this machine, and restarts it.\n\
+		 Type \"reset\" to continue: "
+	);
+	io::stderr().flush()?;
+	let mut line = String::new();
+	io::stdin().lock().read_line(&mut line)?;
+	Ok(line.trim() == "reset")
+}
+
+pub fn run(opts: Opts, args: &[&str]) -> Result<()> {
+	let mut settings = false;
+	let mut yes = false;
+	for arg in args {
+		match *arg {
+			"--settings" => settings = true,
+			"--yes" => yes = true,
+			// Asked for, but there is nothing to send, said explicitly rather than with usage
+			"--factory" => bail!(
+				"factory reset is not supported, no vendor command for it is known, \
+				 --settings forgets what this machine stores for the headset"
+			),
+			_ => usage(),
+		}
+	}
+	// Only kind of reset, flag is required so it is never triggered by accident
+	if !settings {
+		usage();
+	}
+	if Client::connect_driver().is_some() {
+		bail!("SteamVR is running, stop it before resetting the headset");
+	}
+	let dev = device::open()?;
+	let serial = dev.read_devsn()?;
+	let backup = backup(&serial).context("failed to back up stored settings")?;
+	if !yes && !confirm(opts, &serial)? {
+		opts.print(json!({ "reset": false, "backup": backup }), || {
+			"aborted".to_owned()
+		});
+		return Ok(());
+	}
+	let forgotten = headsets::remove(&serial)?;
+	dev.restart()?;
+	opts.print(
+		json!({ "reset": true, "serial": serial, "backup": backup, "forgotten": forgotten }),
+		|| {
+			let mut out = format!("headset {serial} was restarted and will reconnect");
+			match &backup {
+				Some(backup) => out.push_str(&format!(
+					", forgotten settings are backed up to {}",
+					backup.display()
+				)),
+				None => out.push_str(", there were no stored settings to forget"),
+			}
+			out
+		},
+	);
+	Ok(())
+}
@@ -0,0 +1,400 @@
+use std::{env, fs, path::Path, process::Command};
+
+use anyhow::{bail, Context, Result};
+use serde_json::json;
+use vive_display::{
+	drm::{find_connector, Connector},
+	edid::EdidId,
+	wayland::{run_command, Compositor},
+	x11,
+};
+use vive_hid::{power, SteamDevice, STEAM_PID, STEAM_VID, VIVE_PID, VIVE_VID};
+use vive_ipc::STEAM_FLATPAK_ID;
+
+use crate::{lens_server, usage, Opts};
+
+/// Should sort before 73-seat-late.rules, which applies uaccess tags
+pub const UDEV_RULES_PATH: &str = "/etc/udev/rules.d/70-vivepro2.rules";
+
+/// Group, which may power headset cameras down. Unlike device nodes, sysfs attributes get no
+/// uaccess ACLs, and `video` already grants camera access
+const CAMERA_GROUP: &str = "video";
+
+/// `cameras` are USB ids of headset cameras, see [`vive_hid::headset_camera_ids`]
+pub fn udev_rules(cameras: &[(u16, u16)]) -> String {
+	let mut out =
+		"# Vive Pro 2 access for the logged in user, generated by vivectl setup udev\n".to_owned();
+	for (what, vid, pid) in [
+		("HTC mainboard (display/audio control)", VIVE_VID, VIVE_PID),
+		("Valve tracking board", STEAM_VID, STEAM_PID),
+	] {
+		out.push_str(&format!("# {what}\n"));
+		out.push_str(&format!(
+			"KERNEL==\"hidraw*\", ATTRS{{idVendor}}==\"{vid:04x}\", ATTRS{{idProduct}}==\"{pid:04x}\", MODE=\"0660\", TAG+=\"uaccess\"\n"
+		));
+		out.push_str(&format!(
+			"SUBSYSTEM==\"usb\", ATTRS{{idVendor}}==\"{vid:04x}\", ATTRS{{idProduct}}==\"{pid:04x}\", MODE=\"0660\", TAG+=\"uaccess\"\n"
+		));
+	}
+	if cameras.is_empty() {
+		out.push_str(
+			"# Headset cameras were not found, connect headset and rerun vivectl setup udev, so they can be powered down without root\n",
+		);
+	} else {
+		out.push_str(&format!(
+			"# Headset cameras, so {CAMERA_GROUP} group can power them down without root\n"
+		));
+	}
+	for (vid, pid) in cameras {
+		out.push_str(&format!(
+			"SUBSYSTEM==\"usb\", ENV{{DEVTYPE}}==\"usb_device\", ATTR{{idVendor}}==\"{vid:04x}\", ATTR{{idProduct}}==\"{pid:04x}\", RUN+=\"/bin/chgrp {CAMERA_GROUP} /sys%p/authorized\", RUN+=\"/bin/chmod 0660 /sys%p/authorized\"\n"
+		));
+	}
+	out.push_str("# Keep headset devices awake, autosuspended ones don't always come back\n");
+	for vid in [VIVE_VID, STEAM_VID] {
+		out.push_str(&format!(
+			"ACTION==\"add\", SUBSYSTEM==\"usb\", ENV{{DEVTYPE}}==\"usb_device\", ATTR{{idVendor}}==\"{vid:04x}\", TEST==\"power/control\", ATTR{{power/control}}=\"on\"\n"
+		));
+	}
+	out
+}
+
+fn reload() -> Result<()> {
+	for args in [&["control", "--reload-rules"][..], &["trigger"]] {
+		let status = Command::new("udevadm")
+			.args(args)
+			.status()
+			.context("failed to run udevadm")?;
+		if !status.success() {
+			bail!("udevadm {} failed: {status}", args.join(" "));
+		}
+	}
+	Ok(())
+}
+
+fn udev(opts: Opts, args: &[&str]) -> Result<()> {
+	let mut dry_run = false;
+	let mut path = UDEV_RULES_PATH;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match *arg {
+			"--dry-run" => dry_run = true,
+			"--output" => path = args.next().unwrap_or_else(|| usage()),
+			_ => usage(),
+		}
+	}
+	let cameras = vive_hid::headset_camera_ids().context("failed to scan usb devices")?;
+	if cameras.is_empty() {
+		eprintln!(
+			"headset cameras were not found, rules won't let them be powered down without root"
+		);
+	}
+	let rules = udev_rules(&cameras);
+	if dry_run {
+		opts.print(json!({ "path": path, "rules": rules }), || {
+			format!("# {path}\n{}", rules.trim_end())
+		});
+		return Ok(());
+	}
+	fs::write(Path::new(path), &rules).with_context(|| {
+		format!("failed to write {path}, try running with sudo, or use --dry-run")
+	})?;
+	// Custom output path is most likely a packaging step, udev doesn't need to know
+	let reloaded = path == UDEV_RULES_PATH;
+	if reloaded {
+		reload()?;
+	}
+	opts.print(json!({ "path": path, "reloaded": reloaded }), || {
+		if reloaded {
+			format!("installed {path}, reconnect headset to apply")
+		} else {
+			format!("written {path}")
+		}
+	});
+	Ok(())
+}
+
+/// Same as udev rules do on plug, for the already plugged in headset
+fn autosuspend(opts: Opts, args: &[&str]) -> Result<()> {
+	let dry_run = match args {
+		[] => false,
+		["--dry-run"] => true,
+		_ => usage(),
+	};
+	let devices = power::headset_usb_power().context("failed to scan usb devices")?;
+	if devices.is_empty() {
+		bail!("headset is not connected to usb");
+	}
+	let changed = if dry_run {
+		devices.iter().filter(|d| d.autosuspend).cloned().collect()
+	} else {
+		power::disable_autosuspend(&devices)
+			.context("failed to write power/control, try running with sudo")?
+	};
+	let names = changed.iter().map(|d| d.label()).collect::<Vec<_>>();
+	opts.print(json!({ "dry_run": dry_run, "devices": names }), || {
+		if changed.is_empty() {
+			"autosuspend is already disabled for every headset device".to_owned()
+		} else if dry_run {
+			format!("would disable autosuspend for:\n  {}", names.join("\n  "))
+		} else {
+			format!(
+				"disabled autosuspend for:\n  {}\nrun vivectl setup udev to keep it disabled after reconnect",
+				names.join("\n  ")
+			)
+		}
+	});
+	Ok(())
+}
+
+/// Display is only identifiable by EDID, which ids are stored in tracking board config
+pub fn headset_edid() -> Result<EdidId> {
+	let config = SteamDevice::open_first()
+		.and_then(|dev| dev.read_config())
+		.context("failed to read tracking board config, to identify headset display")?;
+	let (vid, pid) = config
+		.direct_mode_edid()
+		.context("tracking board config has no display EDID ids, to identify headset display")?;
+	Ok(EdidId::from_config(vid, pid))
+}
+
+pub fn headset_connector() -> Result<Connector> {
+	let id = headset_edid()?;
+	find_connector(id)?.with_context(|| {
+		format!(
+			"no DRM connector with {} {:04x} EDID",
+			id.pnp_id(),
+			id.product
+		)
+	})
+}
+
+fn wayland(opts: Opts, args: &[&str]) -> Result<()> {
+	let dry_run = match args {
+		[] => false,
+		["--dry-run"] => true,
+		_ => usage(),
+	};
+	let connector = headset_connector()?;
+	if !connector.connected {
+		bail!("headset display {} is disconnected", connector.name);
+	}
+	let non_desktop = connector
+		.is_non_desktop()
+		.with_context(|| format!("failed to query {}", connector.card_path().display()))?;
+	let Some(compositor) = Compositor::detect() else {
+		bail!("not a Wayland session");
+	};
+	let enabled = compositor.output_enabled(&connector.name)?;
+
+	let mut notes = Vec::new();
+	if !non_desktop {
+		notes.push(
+			"kernel doesn't mark headset as non-desktop, kernel 5.18+ is required".to_owned(),
+		);
+	}
+	let command = match enabled {
+		Some(true) => compositor.disable_command(&connector.name),
+		_ => None,
+	};
+	let mut applied = false;
+	match (enabled, &command) {
+		(Some(false), _) => {}
+		(Some(true), Some(command)) if !dry_run => {
+			run_command(command)?;
+			applied = true;
+			notes.push(format!(
+				"{} was used as desktop output, disabled it with `{}`, {}",
+				connector.name,
+				command.join(" "),
+				compositor.advice()
+			));
+		}
+		(Some(true), Some(command)) => notes.push(format!(
+			"{} is used as desktop output, run `{}`",
+			connector.name,
+			command.join(" ")
+		)),
+		(Some(true), None) => notes.push(format!(
+			"{} is used as desktop output, {}",
+			connector.name,
+			compositor.advice()
+		)),
+		(None, _) => notes.push(format!(
+			"can't query {} outputs, if headset extends the desktop: {}",
+			compositor.name(),
+			compositor.advice()
+		)),
+	}
+
+	opts.print(
+		json!({
+			"connector": connector.name,
+			"non_desktop": non_desktop,
+			"compositor": compositor.name(),
+			"desktop_output": enabled,
+			"command": command,
+			"applied": applied,
+			"notes": notes,
+		}),
+		|| {
+			let mut out = format!(
+				"{} on {} ({}non-desktop), compositor: {}",
+				connector.name,
+				connector.card,
+				if non_desktop { "" } else { "not " },
+				compositor.name()
+			);
+			if notes.is_empty() {
+				out.push_str("\nheadset is kept out of the desktop");
+			}
+			for note in &notes {
+				out.push_str(&format!("\n{note}"));
+			}
+			out
+		},
+	);
+	Ok(())
+}
+
+fn x11(opts: Opts, args: &[&str]) -> Result<()> {
+	let dry_run = match args {
+		[] => false,
+		["--dry-run"] => true,
+		_ => usage(),
+	};
+	if env::var_os("DISPLAY").is_none() {
+		bail!("not an X11 session");
+	}
+	let id = headset_edid()?;
+	let Some(output) = x11::find_output(id)? else {
+		bail!(
+			"no connected RandR output with {} {:04x} EDID",
+			id.pnp_id(),
+			id.product
+		);
+	};
+
+	let mut actions = Vec::new();
+	if output.non_desktop == Some(false) {
+		if !dry_run {
+			output.set_non_desktop()?;
+		}
+		actions.push(format!(
+			"xrandr --output {} --set non-desktop 1",
+			output.name
+		));
+	}
+	if output.active {
+		if !dry_run {
+			output.disable()?;
+		}
+		actions.push(format!("xrandr --output {} --off", output.name));
+	}
+
+	opts.print(
+		json!({
+			"output": output.name,
+			"non_desktop": output.non_desktop,
+			"active": output.active,
+			"actions": actions,
+			"applied": !dry_run && !actions.is_empty(),
+		}),
+		|| {
+			let mut out = format!("headset output: {}", output.name);
+			if output.non_desktop.is_none() {
+				out.push_str(
+					"\nX server doesn't support non-desktop property, RandR 1.6 is required",
+				);
+			}
+			if actions.is_empty() {
+				out.push_str("\nheadset is kept out of the desktop");
+			}
+			for action in &actions {
+				if dry_run {
+					out.push_str(&format!("\nwould run: {action}"));
+				} else {
+					out.push_str(&format!("\nran: {action}"));
+				}
+			}
+			out
+		},
+	);
+	Ok(())
+}
+
+/// Driver inside of Flatpak Steam needs host settings directory, and hidraw devices, which are
+/// given by `--device=all` by default, but may have been revoked by the user
+pub const FLATPAK_OVERRIDES: &[&str] = &[
+	"--device=all",
+	"--filesystem=xdg-config/vivepro2:create",
+	// Lens server prefix, see `vivectl setup lens-server`
+	"--filesystem=xdg-data/vivepro2:create",
+];
+
+/// Whether overrides from [`FLATPAK_OVERRIDES`] are applied, `None` if Flatpak Steam isn't installed
+pub fn flatpak_overridden() -> Option<bool> {
+	let installed = Command::new("flatpak")
+		.args(["info", STEAM_FLATPAK_ID])
+		.output()
+		.ok()?;
+	if !installed.status.success() {
+		return None;
+	}
+	let shown = Command::new("flatpak")
+		.args(["override", "--user", "--show", STEAM_FLATPAK_ID])
+		.output()
+		.ok()?;
+	Some(String::from_utf8_lossy(&shown.stdout).contains("xdg-config/vivepro2"))
+}
+
+fn flatpak(opts: Opts, args: &[&str]) -> Result<()> {
+	let dry_run = match args {
+		[] => false,
+		["--dry-run"] => true,
+		_ => usage(),
+	};
+	let Some(overridden) = flatpak_overridden() else {
+		bail!("{STEAM_FLATPAK_ID} flatpak is not installed");
+	};
+	let command = ["flatpak", "override", "--user"]
+		.iter()
+		.chain(FLATPAK_OVERRIDES)
+		.chain([&STEAM_FLATPAK_ID])
+		.map(|arg| arg.to_string())
+		.collect::<Vec<_>>();
+	if !dry_run {
+		run_command(&command)?;
+	}
+	opts.print(
+		json!({
+			"already_applied": overridden,
+			"command": command.join(" "),
+			"applied": !dry_run,
+		}),
+		|| {
+			let ran = if dry_run { "would run" } else { "ran" };
+			let mut out = format!("{ran}: {}", command.join(" "));
+			if !dry_run {
+				out.push_str(
+					"
+restart Steam to apply, and install driver into its SteamVR with install.sh",
+				);
+			}
+			out
+		},
+	);
+	Ok(())
+}
+
+pub fn run(opts: Opts, args: &[&str]) -> Result<()> {
+	match args {
+		["autosuspend", rest @ ..] => autosuspend(opts, rest),
+		["flatpak", rest @ ..] => flatpak(opts, rest),
+		["lens-server", rest @ ..] => lens_server::setup(opts, rest),
+		["udev", rest @ ..] => udev(opts, rest),
+		["wayland", rest @ ..] => wayland(opts, rest),
+		["x11", rest @ ..] => x11(opts, rest),
+		_ => usage(),
+	}
+}
@@ -0,0 +1,158 @@
+//! Locating SteamVR installations: native, Flatpak and Snap Steam, their custom library folders,
+//! and runtime registered in `openvrpaths.vrpath` (the file `vrpathreg` maintains)
+use std::{
+	env, fs, io,
+	path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use vive_ipc::STEAM_FLATPAK_ID;
+
+/// Relative to `$HOME`
+const STEAM_ROOTS: &[&str] = &[
+	".local/share/Steam",
+	".steam/steam",
+	".var/app/com.valvesoftware.Steam/.local/share/Steam",
+	".var/app/com.valvesoftware.Steam/data/Steam",
+	"snap/steam/common/.local/share/Steam",
+];
+
+fn home() -> PathBuf {
+	PathBuf::from(env::var_os("HOME").unwrap_or_default())
+}
+
+/// `$XDG_CONFIG_HOME/openvr/openvrpaths.vrpath`, Flatpak Steam has its own one
+pub fn openvrpaths_path(steamvr: &Path) -> PathBuf {
+	let flatpak = home().join(".var/app").join(STEAM_FLATPAK_ID);
+	let config = if steamvr.starts_with(&flatpak) {
+		flatpak.join(".config")
+	} else {
+		match env::var_os("XDG_CONFIG_HOME") {
+			Some(dir) => PathBuf::from(dir),
+			None => home().join(".config"),
+		}
+	};
+	config.join("openvr/openvrpaths.vrpath")
+}
+
+pub fn is_flatpak(steamvr: &Path) -> bool {
+	steamvr.starts_with(home().join(".var/app"))
+}
+
+/// Library paths from `libraryfolders.vdf`, only `"path"` keys are interesting, so there is no
+/// need for a full KeyValues parser
+fn library_folders(root: &Path) -> Vec<PathBuf> {
+	let Ok(vdf) = fs::read_to_string(root.join("steamapps/libraryfolders.vdf")) else {
+		return Vec::new();
+	};
+	vdf.lines()
+		.filter_map(|line| {
+			let mut parts = line.trim().splitn(2, char::is_whitespace);
+			if parts.next()? != "\"path\"" {
+				return None;
+			}
+			let value = parts.next()?.trim().strip_prefix('"')?.strip_suffix('"')?;
+			Some(PathBuf::from(value.replace("\\\\", "\\")))
+		})
+		.collect()
+}
+
+fn read_openvrpaths(path: &Path) -> Result<Value> {
+	match fs::read_to_string(path) {
+		Ok(data) => {
+			serde_json::from_str(&data).with_context(|| format!("failed to parse {path:?}"))
+		}
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(json!({})),
+		Err(e) => Err(e).with_context(|| format!("failed to read {path:?}")),
+	}
+}
+
+/// Runtimes registered in `openvrpaths.vrpath`, first one is started by clients
+pub fn registered_runtimes(openvrpaths: &Path) -> Vec<PathBuf> {
+	read_openvrpaths(openvrpaths)
+		.ok()
+		.and_then(|v| {
+			Some(
+				v.get("runtime")?
+					.as_array()?
+					.iter()
+					.filter_map(|p| Some(PathBuf::from(p.as_str()?)))
+					.collect(),
+			)
+		})
+		.unwrap_or_default()
+}
+
+/// Same as `vrpathreg setruntime`, makes `steamvr` first registered runtime
+pub fn register_runtime(steamvr: &Path) -> Result<PathBuf> {
+	let path = openvrpaths_path(steamvr);
+	let mut value = read_openvrpaths(&path)?;
+	let Some(object) = value.as_object_mut() else {
+		anyhow::bail!("{path:?} is not an object");
+	};
+	let steamvr = steamvr.to_string_lossy().into_owned();
+	let mut runtimes = object
+		.get("runtime")
+		.and_then(Value::as_array)
+		.cloned()
+		.unwrap_or_default();
+	runtimes.retain(|r| r.as_str() != Some(&steamvr));
+	runtimes.insert(0, steamvr.into());
+	object.insert("runtime".to_owned(), runtimes.into());
+	object.entry("version").or_insert(1.into());
+	object.entry("jsonid").or_insert("vrpathreg".into());
+
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent)?;
+	}
+	let tmp = path.with_extension("vrpath.tmp");
+	fs::write(&tmp, serde_json::to_string_pretty(&value)? + "\n")?;
+	fs::rename(&tmp, &path).with_context(|| format!("failed to write {path:?}"))?;
+	Ok(path)
+}
+
+/// Every found SteamVR installation, `$STEAMVR` and registered runtime go first
+pub fn find_steamvr() -> Vec<PathBuf> {
+	let mut candidates = Vec::new();
+	if let Some(path) = env::var_os("STEAMVR") {
+		candidates.push(PathBuf::from(path));
+	}
+	let home = home();
+	let roots = STEAM_ROOTS
+		.iter()
+		.map(|root| home.join(root))
+		.collect::<Vec<_>>();
+	for root in &roots {
+		candidates.extend(registered_runtimes(&openvrpaths_path(root)));
+	}
+	for root in &roots {
+		candidates.push(root.clone());
+		candidates.extend(library_folders(root));
+	}
+	let mut out: Vec<PathBuf> = Vec::new();
+	for candidate in candidates {
+		let steamvr = if candidate.ends_with("SteamVR") {
+			candidate
+		} else {
+			candidate.join("steamapps/common/SteamVR")
+		};
+		// .steam/steam is usually a symlink to one of other roots
+		let Ok(steamvr) = steamvr.canonicalize() else {
+			continue;
+		};
+		if steamvr.is_dir() && !out.contains(&steamvr) {
+			out.push(steamvr);
+		}
+	}
+	out
+}
+
+pub fn steamvr_path() -> Option<PathBuf> {
+	find_steamvr().into_iter().next()
+}
+
+/// `steamapps/common/SteamVR` -> Steam root, where its `config` and `logs` are
+pub fn steam_root(steamvr: &Path) -> Option<PathBuf> {
+	steamvr.ancestors().nth(3).map(Path::to_path_buf)
+}
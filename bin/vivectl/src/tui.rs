@@ -0,0 +1,270 @@
+//! Minimal terminal UI, drawn with plain ANSI escapes, as there is not much to draw
+use std::{
+	io::{self, Read, Write},
+	mem::MaybeUninit,
+	time::Duration,
+};
+
+use anyhow::{bail, Result};
+use vive_hid::{MainboardStatus, Resolution, ViveDevice};
+use vive_ipc::{Client, Request};
+
+use crate::usage;
+
+const STATUS_POLL: Duration = Duration::from_millis(50);
+const BRIGHTNESS_STEP: u8 = 10;
+
+/// Raw mode + alternate screen, restored on drop, even on panic
+struct Terminal {
+	original: libc::termios,
+}
+impl Terminal {
+	fn enter() -> Result<Self> {
+		let original = unsafe {
+			let mut termios = MaybeUninit::<libc::termios>::uninit();
+			if libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) != 0 {
+				bail!("stdin is not a terminal");
+			}
+			termios.assume_init()
+		};
+		let mut raw = original;
+		unsafe { libc::cfmakeraw(&mut raw) };
+		if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+			bail!("failed to switch terminal to raw mode");
+		}
+		print!("\x1b[?1049h\x1b[?25l");
+		io::stdout().flush()?;
+		Ok(Self { original })
+	}
+	/// Waits for input up to `timeout`
+	fn read_key(&self, timeout: Duration) -> Result<Option<Key>> {
+		let mut fd = libc::pollfd {
+			fd: libc::STDIN_FILENO,
+			events: libc::POLLIN,
+			revents: 0,
+		};
+		if unsafe { libc::poll(&mut fd, 1, timeout.as_millis() as i32) } <= 0 {
+			return Ok(None);
+		}
+		let mut buf = [0u8; 8];
+		let len = io::stdin().read(&mut buf)?;
+		Ok(Some(match &buf[..len] {
+			b"\x1b[C" => Key::Right,
+			b"\x1b[D" => Key::Left,
+			b"\r" | b"\n" => Key::Enter,
+			[c, ..] => Key::Char(*c as char),
+			[] => return Ok(None),
+		}))
+	}
+}
+impl Drop for Terminal {
+	fn drop(&mut self) {
+		print!("\x1b[?25h\x1b[?1049l");
+		let _ = io::stdout().flush();
+		unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+	}
+}
+
+enum Key {
+	Left,
+	Right,
+	Enter,
+	Char(char),
+}
+
+struct State {
+	device: Option<ViveDevice>,
+	/// SteamVR has the headset, and it can't be opened
+	held: bool,
+	serial: Option<String>,
+	status: Option<MainboardStatus>,
+	/// Mode, which will be applied on Enter
+	selected: usize,
+	/// Device has no way to read these back, so only values set here are known
+	brightness: Option<u8>,
+	noise_cancel: Option<bool>,
+	message: String,
+}
+impl State {
+	fn reconnect(&mut self) {
+		if self.device.is_some() {
+			return;
+		}
+		match ViveDevice::open_first() {
+			Ok(dev) => {
+				self.serial = dev.read_devsn().ok();
+				self.device = Some(dev);
+				self.held = false;
+				self.message = "connected".to_owned();
+			}
+			Err(vive_hid::Error::HeldBySteamVr { .. }) => self.held = true,
+			Err(_) => self.held = false,
+		}
+	}
+	/// Runs command on device, drops device if it fails, as it is most likely gone
+	///
+	/// During SteamVR session `request` is sent to driver-proxy instead, as it owns the headset
+	fn apply(
+		&mut self,
+		what: &str,
+		request: Request,
+		f: impl FnOnce(&ViveDevice) -> Result<(), vive_hid::Error>,
+	) {
+		if let Some(mut driver) = Client::connect_driver() {
+			self.message = match driver.request(&request) {
+				Ok(_) => format!("{what}: done, through SteamVR driver"),
+				Err(e) => format!("{what}: {e}"),
+			};
+			return;
+		}
+		let Some(dev) = &self.device else {
+			self.message = format!("can't {what}: headset is not connected");
+			return;
+		};
+		match f(dev) {
+			Ok(()) => self.message = format!("{what}: done"),
+			Err(e) => {
+				self.message = format!("{what}: {e}");
+				self.device = None;
+			}
+		}
+	}
+	fn draw(&self) -> io::Result<()> {
+		let mut out = String::from("\x1b[H\x1b[2J");
+		let mut line = |s: String| {
+			out.push_str(&s);
+			out.push_str("\r\n");
+		};
+		let unknown = || "unknown".to_owned();
+		line("vivectl - Vive Pro 2 control panel".to_owned());
+		line(String::new());
+		line(format!(
+			"headset:        {}",
+			match (&self.device, &self.serial) {
+				(Some(_), Some(sn)) => format!("connected ({sn})"),
+				(Some(_), None) => "connected".to_owned(),
+				(None, _) if self.held => "held by SteamVR".to_owned(),
+				(None, _) => "disconnected".to_owned(),
+			}
+		));
+		line(format!(
+			"ipd:            {}",
+			self.status.map_or_else(unknown, |s| s.ipd.to_string())
+		));
+		line(format!(
+			"proximity:      {}",
+			self.status
+				.map_or_else(unknown, |s| if s.proximity_change != 0 {
+					"near".to_owned()
+				} else {
+					"far".to_owned()
+				})
+		));
+		line(String::new());
+		line(format!(
+			"resolution:     < {} >",
+			Resolution::ALL[self.selected]
+		));
+		line(format!(
+			"brightness:     {}",
+			self.brightness.map_or_else(unknown, |b| b.to_string())
+		));
+		line(format!(
+			"noise cancel:   {}",
+			self.noise_cancel.map_or_else(unknown, |n| if n {
+				"on".to_owned()
+			} else {
+				"off".to_owned()
+			})
+		));
+		line(String::new());
+		line("left/right: choose resolution, enter: apply, +/-: brightness".to_owned());
+		line("n: toggle noise cancel, q: quit".to_owned());
+		line(String::new());
+		line(self.message.clone());
+		let mut stdout = io::stdout();
+		stdout.write_all(out.as_bytes())?;
+		stdout.flush()
+	}
+}
+
+pub fn run(args: &[&str]) -> Result<()> {
+	if !args.is_empty() {
+		usage()
+	}
+	let term = Terminal::enter()?;
+	let mut state = State {
+		device: None,
+		held: false,
+		serial: None,
+		status: None,
+		selected: 0,
+		brightness: None,
+		noise_cancel: None,
+		message: "waiting for headset".to_owned(),
+	};
+	loop {
+		state.reconnect();
+		if let Some(dev) = &state.device {
+			match dev.read_status(STATUS_POLL.as_millis() as i32) {
+				Ok(Some(status)) => state.status = Some(status),
+				Ok(None) => {}
+				Err(_) => {
+					state.device = None;
+					state.message = "disconnected".to_owned();
+				}
+			}
+		}
+		state.draw()?;
+		let Some(key) = term.read_key(STATUS_POLL)? else {
+			continue;
+		};
+		match key {
+			Key::Char('q') | Key::Char('\x03') => break,
+			Key::Left => {
+				state.selected = state
+					.selected
+					.checked_sub(1)
+					.unwrap_or(Resolution::ALL.len() - 1)
+			}
+			Key::Right => state.selected = (state.selected + 1) % Resolution::ALL.len(),
+			Key::Enter => {
+				let res = Resolution::ALL[state.selected];
+				let resolution = res as u8;
+				state.apply(
+					"switch resolution",
+					Request::SetResolution { resolution },
+					|dev| dev.set_mode(resolution),
+				);
+				// Headset reconnects after mode switch
+				state.device = None;
+			}
+			Key::Char(c @ ('+' | '=' | '-')) => {
+				let current = state.brightness.unwrap_or(130);
+				let brightness = if c == '-' {
+					current.saturating_sub(BRIGHTNESS_STEP).max(1)
+				} else {
+					current.saturating_add(BRIGHTNESS_STEP).min(130)
+				};
+				state.apply(
+					"set brightness",
+					Request::SetBrightness { brightness },
+					|dev| dev.set_brightness(brightness),
+				);
+				state.brightness = Some(brightness);
+			}
+			Key::Char('n') => {
+				let enabled = !state.noise_cancel.unwrap_or(false);
+				state.apply(
+					"toggle noise cancel",
+					Request::SetNoiseCancel { enabled },
+					|dev| dev.toggle_noise_canceling(enabled),
+				);
+				state.noise_cancel = Some(enabled);
+			}
+			_ => {}
+		}
+	}
+	drop(term);
+	Ok(())
+}
@@ -0,0 +1,83 @@
+use std::{sync::mpsc::Receiver, thread::sleep, time::Duration};
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use vive_hid::{MainboardStatus, StateChange, ViveContext, ViveDevice};
+
+use crate::{usage, Opts};
+
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+fn emit(opts: Opts, event: &str, value: Value) {
+	// Line per event in both modes, so output may be piped (json-lines with --json)
+	opts.print(json!({ "event": event, "value": value }), || {
+		if value.is_null() {
+			event.to_owned()
+		} else {
+			format!("{event}: {value}")
+		}
+	});
+}
+
+/// Reports only fields, which were changed since last report
+fn emit_changes(opts: Opts, old: Option<MainboardStatus>, new: MainboardStatus) {
+	let changed = |f: fn(&MainboardStatus) -> u16| old.map(|o| f(&o)) != Some(f(&new));
+	if changed(|s| s.ipd) {
+		emit(opts, "ipd", new.ipd.into());
+	}
+	if changed(|s| s.lens_separation) {
+		emit(opts, "lens_separation", new.lens_separation.into());
+	}
+	// Raw proximity is noisy, only report when sensor state flips
+	if changed(|s| s.proximity_change as u16) {
+		emit(
+			opts,
+			"proximity",
+			json!({ "near": new.proximity_change != 0, "raw": new.proximity }),
+		);
+	}
+	if changed(|s| s.button as u16) {
+		emit(opts, "button", new.button.into());
+	}
+}
+
+/// Lifecycle changes, `connected` and `disconnected` are still reported for older scripts
+fn emit_states(opts: Opts, changes: &Receiver<StateChange>) {
+	for change in changes.try_iter() {
+		emit(
+			opts,
+			"state",
+			json!({ "state": change.to.name(), "reason": change.reason }),
+		);
+		if change.to.is_ready() {
+			emit(opts, "connected", Value::Null);
+		} else if change.from.is_ready() {
+			emit(opts, "disconnected", Value::Null);
+		}
+	}
+}
+
+pub fn run(opts: Opts, args: &[&str]) -> Result<()> {
+	if !args.is_empty() {
+		usage()
+	}
+	let changes = ViveContext::global().connection().subscribe();
+	loop {
+		let dev = ViveDevice::open_first();
+		emit_states(opts, &changes);
+		let Ok(dev) = dev else {
+			sleep(RECONNECT_INTERVAL);
+			continue;
+		};
+		let mut last = None;
+		// Read fails once headset is unplugged (or reconnects after mode switch)
+		while let Ok(status) = dev.read_status(500) {
+			let Some(status) = status else {
+				continue;
+			};
+			emit_changes(opts, last, status);
+			last = Some(status);
+		}
+		emit_states(opts, &changes);
+	}
+}
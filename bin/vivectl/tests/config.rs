@@ -0,0 +1,277 @@
+//! `vivectl config` over config fixtures of vive-protocol, no headset is needed for dumped ones
+use std::{
+	env, fs,
+	path::{Path, PathBuf},
+	process::{self, Command, Output},
+};
+
+use serde_json::{json, Value};
+use vive_protocol::{parse_config_dump, ConfigHeader};
+
+fn fixture(name: &str) -> PathBuf {
+	Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("../../crates/vive-protocol/tests/fixtures")
+		.join(name)
+}
+
+/// For configs the test makes of fixtures
+fn temp(name: &str) -> PathBuf {
+	let dir = env::temp_dir().join(format!("vivectl-config-{}", process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	dir.join(name)
+}
+
+fn vivectl(args: &[&str]) -> Output {
+	Command::new(env!("CARGO_BIN_EXE_vivectl"))
+		.args(args)
+		// Fixtures are read, so no headset is opened, but make sure no simulated one is either
+		.env_remove("VIVEPRO2_SIMULATE")
+		.output()
+		.expect("failed to run vivectl")
+}
+
+fn path(path: &Path) -> &str {
+	path.to_str().unwrap()
+}
+
+/// `--json` output, and exit code
+fn json(args: &[&str]) -> (Value, Option<i32>) {
+	let out = vivectl(&[&["--json"], args].concat());
+	let stdout = String::from_utf8(out.stdout).unwrap();
+	let value = serde_json::from_str(&stdout)
+		.unwrap_or_else(|e| panic!("{args:?} printed {stdout:?}: {e}"));
+	(value, out.status.code())
+}
+
+fn text(args: &[&str]) -> (String, String, Option<i32>) {
+	let out = vivectl(args);
+	(
+		String::from_utf8(out.stdout).unwrap(),
+		String::from_utf8(out.stderr).unwrap(),
+		out.status.code(),
+	)
+}
+
+#[test]
+fn diff_identical() {
+	let config = fixture("vive-v1-padded.bin");
+	let (stdout, stderr, code) = text(&["config", "diff", path(&config), path(&config)]);
+	assert_eq!(code, Some(0));
+	assert_eq!(stdout, "");
+	assert_eq!(stderr.trim(), "configs are identical");
+
+	let (changes, code) = json(&["config", "diff", path(&config), path(&config)]);
+	assert_eq!(code, Some(0));
+	assert_eq!(changes, json!([]));
+}
+
+#[test]
+fn diff_vive() {
+	let a = fixture("vive-unversioned.bin");
+	let b = fixture("vive-no-hash.bin");
+	let (changes, code) = json(&["config", "diff", path(&a), path(&b)]);
+	assert_eq!(code, Some(0));
+	assert_eq!(
+		changes,
+		json!([
+			{"op": "removed", "path": "$.direct_mode_edid_pid", "value": 2},
+			{"op": "removed", "path": "$.direct_mode_edid_vid", "value": 53006},
+			{"op": "removed", "path": "$.seconds_from_photons_to_vblank", "value": 0.0},
+			{"op": "removed", "path": "$.seconds_from_vsync_to_photons", "value": 0.0111},
+			{"op": "added", "path": "$.panel", "value": {"vendor": "SANITIZED"}},
+			{"op": "added", "path": "$.version", "value": 1},
+		])
+	);
+
+	let (stdout, _, code) = text(&["config", "diff", path(&a), path(&b)]);
+	assert_eq!(code, Some(0));
+	let lines = stdout.lines().collect::<Vec<_>>();
+	assert_eq!(lines.len(), 6);
+	assert_eq!(lines[0], "- $.direct_mode_edid_pid: 2");
+	assert_eq!(lines[5], "+ $.version: 1");
+}
+
+#[test]
+fn diff_changed() {
+	let a = fixture("vive-unversioned.bin");
+	let mut config = parse_config_dump(&fs::read(&a).unwrap()).unwrap();
+	config["direct_mode_edid_pid"] = json!(3);
+	let b = temp("diff-changed.json");
+	fs::write(&b, config.to_string()).unwrap();
+
+	let (changes, _) = json(&["config", "diff", path(&a), path(&b)]);
+	assert_eq!(
+		changes,
+		json!([{"op": "changed", "path": "$.direct_mode_edid_pid", "from": 2, "to": 3}])
+	);
+	let (stdout, _, _) = text(&["config", "diff", path(&a), path(&b)]);
+	assert_eq!(stdout, "~ $.direct_mode_edid_pid: 2 -> 3\n");
+}
+
+/// Steam configs are dumped compressed
+#[test]
+fn diff_steam() {
+	let a = fixture("steam-minimal.bin");
+	let b = fixture("steam-v1-tracking.bin");
+	let (changes, code) = json(&["config", "diff", path(&a), path(&b)]);
+	assert_eq!(code, Some(0));
+	let changes = changes.as_array().unwrap();
+	assert!(changes
+		.iter()
+		.any(|c| c["op"] == "added" && c["path"] == "$.imu"));
+	assert!(changes.iter().all(|c| c["op"] != "removed"), "{changes:?}");
+}
+
+#[test]
+fn diff_usage() {
+	let config = fixture("vive-v1-padded.bin");
+	let (_, stderr, code) = text(&["config", "diff", path(&config)]);
+	assert_eq!(code, Some(2));
+	assert!(stderr.contains("config diff <a> <b>"));
+}
+
+/// Raw dumps have factory config header, which is skipped, whatever its layout
+#[test]
+fn header_is_skipped() {
+	for name in [
+		"vive-unversioned.bin",
+		"vive-v1-padded.bin",
+		"vive-no-hash.bin",
+		"vive-v2-unknown-fields.bin",
+	] {
+		let raw = fixture(name);
+		let blob = fs::read(&raw).unwrap();
+		let (header, body) = ConfigHeader::parse(&blob).unwrap();
+		assert!(!header.is_empty(), "{name}");
+
+		let plain = temp(&format!("plain-{name}.json"));
+		fs::write(&plain, body).unwrap();
+		let reencoded = temp(&format!("reencoded-{name}"));
+		fs::write(&reencoded, ConfigHeader::encode("OTHER-HEADER", body)).unwrap();
+		for other in [&plain, &reencoded] {
+			let (changes, code) = json(&["config", "diff", path(&raw), path(other)]);
+			assert_eq!((changes, code), (json!([]), Some(0)), "{name}");
+		}
+	}
+}
+
+/// Sanitized dumps don't match their hash, which is fine
+#[test]
+fn header_hash_mismatch() {
+	let raw = fixture("vive-v1-padded.bin");
+	let mut blob = fs::read(&raw).unwrap();
+	let at = blob
+		.windows(12)
+		.position(|w| w == b"XXXXXXXXXXXX")
+		.expect("sanitized serial");
+	blob[at..at + 12].copy_from_slice(b"YYYYYYYYYYYY");
+	assert!(!ConfigHeader::parse(&blob).unwrap().0.hash_verified());
+	let edited = temp("hash-mismatch.bin");
+	fs::write(&edited, &blob).unwrap();
+
+	let (changes, code) = json(&["config", "diff", path(&raw), path(&edited)]);
+	assert_eq!(code, Some(0));
+	assert_eq!(
+		changes,
+		json!([{
+			"op": "changed",
+			"path": "$.panel.serial_number",
+			"from": "XXXXXXXXXXXX",
+			"to": "YYYYYYYYYYYY",
+		}])
+	);
+}
+
+#[test]
+fn header_without_body() {
+	let truncated = fixture("vive-truncated.bin");
+	let (_, stderr, code) = text(&["config", "diff", path(&truncated), path(&truncated)]);
+	assert_eq!(code, Some(1));
+	assert!(stderr.contains("failed to parse"), "{stderr}");
+
+	// Erased region, JSON isn't found
+	let erased = temp("erased.bin");
+	fs::write(&erased, [0xff; 4096]).unwrap();
+	let (_, stderr, code) = text(&["config", "diff", path(&erased), path(&truncated)]);
+	assert_eq!(code, Some(1));
+	assert!(stderr.contains("config size mismatch"), "{stderr}");
+}
+
+#[test]
+fn validate_fixtures() {
+	for (name, args) in [
+		("vive-unversioned.bin", &[][..]),
+		("vive-v1-padded.bin", &[]),
+		("vive-no-hash.bin", &[]),
+		("vive-v2-unknown-fields.bin", &[]),
+		("steam-minimal.bin", &["--steam"]),
+		("steam-v1-tracking.bin", &["--steam"]),
+		("steam-config-version-alias.bin", &["--steam"]),
+	] {
+		let config = fixture(name);
+		let args = [&["config", "validate"], args, &[path(&config)]].concat();
+		let (result, code) = json(&args);
+		assert_eq!(
+			(result, code),
+			(json!({"valid": true, "issues": []}), Some(0)),
+			"{name}"
+		);
+		let (stdout, _, code) = text(&args);
+		assert_eq!((stdout.as_str(), code), ("config is valid\n", Some(0)));
+	}
+}
+
+#[test]
+fn validate_issues() {
+	let mut config = parse_config_dump(&fs::read(fixture("vive-no-hash.bin")).unwrap()).unwrap();
+	config["direct_mode_edid_pid"] = json!("2");
+	config.as_object_mut().unwrap().remove("device");
+	let broken = temp("validate-issues.json");
+	fs::write(&broken, config.to_string()).unwrap();
+
+	let (result, code) = json(&["config", "validate", path(&broken)]);
+	assert_eq!(code, Some(1));
+	assert_eq!(
+		result,
+		json!({
+			"valid": false,
+			"issues": [
+				"$.device: missing, expected object",
+				"$.direct_mode_edid_pid: expected integer, got string",
+			],
+		})
+	);
+	let (stdout, _, code) = text(&["config", "validate", path(&broken)]);
+	assert_eq!(code, Some(1));
+	assert_eq!(
+		stdout,
+		"$.device: missing, expected object\n$.direct_mode_edid_pid: expected integer, got string\n"
+	);
+}
+
+/// Headset config isn't a steam one
+#[test]
+fn validate_wrong_schema() {
+	let config = fixture("vive-unversioned.bin");
+	let (result, code) = json(&["config", "validate", "--steam", path(&config)]);
+	assert_eq!(code, Some(1));
+	assert_eq!(result["valid"], false);
+	assert!(
+		result["issues"]
+			.as_array()
+			.unwrap()
+			.contains(&json!("$.mb_serial_number: missing, expected string")),
+		"{result}"
+	);
+}
+
+#[test]
+fn validate_unparsable() {
+	let config = fixture("vive-truncated.bin");
+	let (_, stderr, code) = text(&["config", "validate", path(&config)]);
+	assert_eq!(code, Some(1));
+	assert!(stderr.contains("failed to parse"), "{stderr}");
+
+	let (_, _, code) = text(&["config", "validate", path(&config), path(&config)]);
+	assert_eq!(code, Some(2));
+}
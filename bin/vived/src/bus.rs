@@ -0,0 +1,216 @@
+//! Same control as socket provides, for desktop environments and scripts, which speak D-Bus
+//!
+//! ```sh
+//! busctl --user call org.vivepro2.Daemon /org/vivepro2/Headset org.vivepro2.Headset SetBrightness y 100
+//! busctl --user call org.vivepro2.Daemon /org/vivepro2/Headset org.vivepro2.Headset SetProfile s cinema
+//! ```
+use std::{ffi::CString, sync::Arc, time::Duration};
+
+use anyhow::{bail, Result};
+use dbus::{
+	blocking::{stdintf::org_freedesktop_dbus::RequestNameReply, Connection},
+	channel::{MatchingReceiver, Sender},
+	message::MatchRule,
+	strings::{ErrorName, Interface, Member, Path},
+	Message,
+};
+use vive_ipc::{Event, Request, Response};
+
+use crate::daemon::Daemon;
+
+pub const BUS_NAME: &str = "org.vivepro2.Daemon";
+pub const OBJECT_PATH: &str = "/org/vivepro2/Headset";
+pub const INTERFACE: &str = "org.vivepro2.Headset";
+const INTROSPECTABLE: &str = "org.freedesktop.DBus.Introspectable";
+
+const PROCESS_INTERVAL: Duration = Duration::from_millis(100);
+
+const INTROSPECTION: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="org.vivepro2.Headset">
+    <method name="GetStatus">
+      <arg name="connected" type="b" direction="out"/>
+      <arg name="serial" type="s" direction="out"/>
+    </method>
+    <method name="SetResolution">
+      <arg name="resolution" type="y" direction="in"/>
+    </method>
+    <method name="SetBrightness">
+      <arg name="brightness" type="y" direction="in"/>
+    </method>
+    <method name="SetNoiseCancel">
+      <arg name="enabled" type="b" direction="in"/>
+    </method>
+    <method name="SetBaseStations">
+      <arg name="on" type="b" direction="in"/>
+    </method>
+    <method name="GetProfiles">
+      <arg name="profiles" type="as" direction="out"/>
+      <arg name="active" type="s" direction="out"/>
+    </method>
+    <method name="SetProfile">
+      <arg name="name" type="s" direction="in"/>
+    </method>
+    <signal name="Connected">
+      <arg name="serial" type="s"/>
+    </signal>
+    <signal name="Disconnected"/>
+    <signal name="IpdChanged">
+      <arg name="ipd" type="q"/>
+    </signal>
+    <signal name="ProximityChanged">
+      <arg name="near" type="b"/>
+    </signal>
+    <signal name="ModeChanged">
+      <arg name="resolution" type="y"/>
+    </signal>
+    <signal name="SessionChanged">
+      <arg name="running" type="b"/>
+    </signal>
+    <signal name="BaseStationsChanged">
+      <arg name="on" type="b"/>
+    </signal>
+    <signal name="ModeApplied">
+      <arg name="resolution" type="y"/>
+    </signal>
+    <signal name="ModeFallback">
+      <arg name="requested" type="y"/>
+      <arg name="resolution" type="y"/>
+      <arg name="reason" type="s"/>
+    </signal>
+    <signal name="LensServerRestarted"/>
+    <signal name="StateChanged">
+      <arg name="state" type="s"/>
+      <arg name="reason" type="s"/>
+    </signal>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg name="xml" type="s" direction="out"/>
+    </method>
+  </interface>
+</node>
+"#;
+
+fn error(msg: &Message, name: &'static str, message: &str) -> Message {
+	let message = CString::new(message.replace('\0', "")).expect("nul bytes are removed");
+	msg.error(&ErrorName::from(name), &message)
+}
+
+fn call(daemon: &Daemon, msg: &Message, request: Request) -> Message {
+	match daemon.handle(request) {
+		Response::Ok | Response::FrameTiming(_) | Response::Debug { .. } => msg.method_return(),
+		Response::Status(status) => msg
+			.method_return()
+			.append2(status.connected, status.serial.unwrap_or_default()),
+		Response::Profiles { active, profiles } => msg.method_return().append2(
+			profiles.into_keys().collect::<Vec<_>>(),
+			active.unwrap_or_default(),
+		),
+		Response::Error { message } => error(msg, "org.vivepro2.Error.Failed", &message),
+	}
+}
+
+fn handle(daemon: &Daemon, msg: &Message) -> Message {
+	if msg.path().as_deref() != Some(OBJECT_PATH) {
+		return error(
+			msg,
+			"org.freedesktop.DBus.Error.UnknownObject",
+			"unknown object",
+		);
+	}
+	let interface = msg.interface();
+	let member = msg.member();
+	let res = match (interface.as_deref(), member.as_deref()) {
+		(Some(INTROSPECTABLE), Some("Introspect")) => {
+			return msg.method_return().append1(INTROSPECTION)
+		}
+		(Some(INTERFACE) | None, Some("GetStatus")) => Ok(Request::Status),
+		(Some(INTERFACE) | None, Some("SetResolution")) => msg
+			.read1()
+			.map(|resolution| Request::SetResolution { resolution }),
+		(Some(INTERFACE) | None, Some("SetBrightness")) => msg
+			.read1()
+			.map(|brightness| Request::SetBrightness { brightness }),
+		(Some(INTERFACE) | None, Some("SetNoiseCancel")) => msg
+			.read1()
+			.map(|enabled| Request::SetNoiseCancel { enabled }),
+		(Some(INTERFACE) | None, Some("SetBaseStations")) => {
+			msg.read1().map(|on| Request::SetBaseStations { on })
+		}
+		(Some(INTERFACE) | None, Some("GetProfiles")) => Ok(Request::Profiles),
+		(Some(INTERFACE) | None, Some("SetProfile")) => {
+			msg.read1().map(|name| Request::SetProfile { name })
+		}
+		_ => {
+			return error(
+				msg,
+				"org.freedesktop.DBus.Error.UnknownMethod",
+				"unknown method",
+			)
+		}
+	};
+	match res {
+		Ok(request) => call(daemon, msg, request),
+		Err(e) => error(
+			msg,
+			"org.freedesktop.DBus.Error.InvalidArgs",
+			&e.to_string(),
+		),
+	}
+}
+
+fn signal(event: Event) -> Message {
+	let signal = |name: &'static str| {
+		Message::signal(
+			&Path::from(OBJECT_PATH),
+			&Interface::from(INTERFACE),
+			&Member::from(name),
+		)
+	};
+	match event {
+		Event::Connected { serial } => signal("Connected").append1(serial.unwrap_or_default()),
+		Event::Disconnected => signal("Disconnected"),
+		Event::Ipd { ipd } => signal("IpdChanged").append1(ipd),
+		Event::Proximity { near } => signal("ProximityChanged").append1(near),
+		Event::Mode { resolution } => signal("ModeChanged").append1(resolution),
+		Event::Session { running } => signal("SessionChanged").append1(running),
+		Event::BaseStations { on } => signal("BaseStationsChanged").append1(on),
+		Event::ModeApplied { resolution } => signal("ModeApplied").append1(resolution),
+		Event::ModeFallback {
+			requested,
+			resolution,
+			reason,
+		} => signal("ModeFallback").append3(requested, resolution, reason),
+		Event::LensServerRestarted => signal("LensServerRestarted"),
+		Event::State { state, reason } => {
+			signal("StateChanged").append2(state, reason.unwrap_or_default())
+		}
+	}
+}
+
+/// Serves headset object on session bus, until connection fails
+pub fn run(daemon: Arc<Daemon>) -> Result<()> {
+	let conn = Connection::new_session()?;
+	if conn.request_name(BUS_NAME, false, true, true)? != RequestNameReply::PrimaryOwner {
+		bail!("{BUS_NAME} is already owned by another process");
+	}
+	let events = daemon.subscribe();
+	conn.start_receive(
+		MatchRule::new_method_call(),
+		Box::new(move |msg, conn| {
+			let reply = handle(&daemon, &msg);
+			if !msg.get_no_reply() {
+				let _ = conn.send(reply);
+			}
+			true
+		}),
+	);
+	loop {
+		conn.process(PROCESS_INTERVAL)?;
+		while let Ok(event) = events.try_recv() {
+			let _ = conn.send(signal(event));
+		}
+	}
+}
@@ -0,0 +1,170 @@
+use std::{
+	collections::BTreeMap,
+	fs, io,
+	net::SocketAddr,
+	path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use vive_hid::Resolution;
+use vive_ipc::{headsets, toml, Settings};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+	/// Last chosen settings, applied to headsets, which have no own settings stored yet
+	#[serde(flatten)]
+	pub settings: Settings,
+	/// Base station names (`LHB-XXXXXXXX`), which are kept powered on while headset is connected
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub base_stations: Vec<String>,
+	/// Address of REST API, i.e `127.0.0.1:8370`, disabled if unset
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub http: Option<SocketAddr>,
+	/// Bearer token, required by REST API, see [`crate::http`], no authentication if unset
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub http_token: Option<String>,
+	/// User profiles, override built-in ones with the same name
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub profiles: BTreeMap<String, Settings>,
+	/// Last applied profile
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub profile: Option<String>,
+	#[serde(default, skip_serializing_if = "Hooks::is_empty")]
+	pub hooks: Hooks,
+	/// Make headset default PipeWire sink/source while it is worn
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub switch_audio: bool,
+	/// Show desktop notifications on connection, mode and base station changes
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub notifications: bool,
+	/// Blank display when headset is taken off, disabled if unset
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub standby: Option<Standby>,
+	/// Blank display and put base stations to sleep after SteamVR exits, disabled if unset
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub power_down: Option<PowerDown>,
+	/// SCHED_FIFO priority (1-99) of headset polling and base station keepalive threads, normal
+	/// scheduling if unset
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub realtime: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Standby {
+	/// Seconds since proximity sensor reported headset was taken off
+	pub delay: u64,
+	/// Also switch to the lowest mode, only if resolution is configured, so it can be restored
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub lower_mode: bool,
+}
+
+/// Delays in seconds since SteamVR exit, unset part isn't powered down
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PowerDown {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub display: Option<u64>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub base_stations: Option<u64>,
+}
+
+/// Shell commands, which are run on headset events, with event JSON in `VIVEPRO2_EVENT`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hooks {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub connected: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub disconnected: Option<String>,
+	/// Proximity sensor reports headset is put on
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub worn: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub removed: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub session_started: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub session_stopped: Option<String>,
+}
+impl Hooks {
+	pub fn is_empty(&self) -> bool {
+		*self == Self::default()
+	}
+}
+
+fn builtin_profiles() -> BTreeMap<String, Settings> {
+	[
+		(
+			"performance",
+			Settings {
+				resolution: Some(Resolution::R2448x1224f120 as u8),
+				..Settings::default()
+			},
+		),
+		(
+			"quality",
+			Settings {
+				resolution: Some(Resolution::R4896x2448f90 as u8),
+				..Settings::default()
+			},
+		),
+		(
+			"cinema",
+			Settings {
+				resolution: Some(Resolution::R4896x2448f90 as u8),
+				brightness: Some(60),
+				noise_cancel: Some(true),
+				..Settings::default()
+			},
+		),
+	]
+	.into_iter()
+	.map(|(name, settings)| (name.to_owned(), settings))
+	.collect()
+}
+
+/// `$XDG_CONFIG_HOME/vivepro2/vived.toml`
+pub fn config_path() -> PathBuf {
+	headsets::config_dir().join("vived.toml")
+}
+
+impl Config {
+	/// Built-in profiles, merged with user ones
+	pub fn profiles(&self) -> BTreeMap<String, Settings> {
+		let mut profiles = builtin_profiles();
+		profiles.extend(self.profiles.clone());
+		profiles
+	}
+	/// Missing file is the same as empty config, `vived.json` of older versions is still read
+	/// in place of missing `vived.toml`, and replaced by it on the next save
+	pub fn load(path: &Path) -> Result<Self> {
+		match fs::read_to_string(path) {
+			Ok(data) => toml::from_str(&data)
+				.map_err(anyhow::Error::msg)
+				.with_context(|| format!("failed to parse {path:?}")),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {
+				let legacy = path.with_extension("json");
+				match fs::read_to_string(&legacy) {
+					Ok(data) if legacy != path => {
+						info!("reading legacy config {legacy:?}, it will be saved to {path:?}");
+						serde_json::from_str(&data)
+							.with_context(|| format!("failed to parse {legacy:?}"))
+					}
+					_ => Ok(Self::default()),
+				}
+			}
+			Err(e) => Err(e).with_context(|| format!("failed to read {path:?}")),
+		}
+	}
+	pub fn save(&self, path: &Path) -> Result<()> {
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent)?;
+		}
+		// Written via rename, so crash never leaves half-written config
+		let tmp = path.with_extension("toml.tmp");
+		fs::write(&tmp, toml::to_string(self).map_err(anyhow::Error::msg)?)?;
+		fs::rename(&tmp, path).with_context(|| format!("failed to write {path:?}"))
+	}
+}
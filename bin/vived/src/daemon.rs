@@ -0,0 +1,591 @@
+use std::{
+	io, mem,
+	os::fd::AsRawFd,
+	path::PathBuf,
+	sync::{
+		mpsc::{self, Receiver, Sender},
+		Mutex,
+	},
+	thread::{self, sleep},
+	time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use tracing::{info, warn};
+use vive_hid::{MainboardStatus, Resolution, ViveContext, ViveDevice};
+use vive_ipc::{
+	debug::{DebugRequest, DebugResponse},
+	headsets, Client, Event, Request, Response, Settings, Status,
+};
+
+use crate::{config::Config, keepalive::Keepalive};
+
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+const STATUS_POLL_MS: i32 = 100;
+/// Headset streams status reports, quiet second means it may be gone, device handle is asked
+const LIVENESS_PROBE: Duration = Duration::from_secs(1);
+const SESSION_POLL: Duration = Duration::from_secs(2);
+/// Headset may reconnect after mode switch, it shouldn't cause another switch
+const MODE_SWITCH_SETTLE: Duration = Duration::from_secs(10);
+/// Used on wake, if brightness was never configured, same as driver-proxy default
+const DEFAULT_BRIGHTNESS: u8 = 130;
+
+/// Counters since daemon start, exported as metrics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+	pub connects: u64,
+	pub disconnects: u64,
+	pub mode_switches: u64,
+	pub connected_since: Option<Instant>,
+	pub last_status: Option<MainboardStatus>,
+}
+
+/// Takes stations over from exited driver-proxy, once the delay has passed
+fn sleep_base_stations(names: Vec<String>, after: u64) {
+	info!("base stations will be put to sleep in {after}s");
+	thread::spawn(move || {
+		let deadline = Instant::now() + Duration::from_secs(after);
+		while Instant::now() < deadline {
+			sleep(SESSION_POLL.min(deadline.saturating_duration_since(Instant::now())));
+			if Client::connect_driver().is_some() {
+				info!("SteamVR is started again, base stations are kept on");
+				return;
+			}
+		}
+		// Stations are put to sleep on connection, and connections are closed after that
+		match Keepalive::start(&names, None) {
+			Ok(keepalive) => keepalive.finish(),
+			Err(e) => warn!("failed to put base stations to sleep: {e:#}"),
+		}
+	});
+}
+
+enum Wait {
+	Readable,
+	Timeout,
+	/// Node was removed, or fd broke otherwise
+	Hangup,
+}
+/// Waits for `fd` without holding anything, so clients may use the device meanwhile
+fn wait_readable(fd: &impl AsRawFd, timeout_ms: i32) -> Wait {
+	let mut pfd = libc::pollfd {
+		fd: fd.as_raw_fd(),
+		events: libc::POLLIN,
+		revents: 0,
+	};
+	match unsafe { libc::poll(&mut pfd, 1, timeout_ms) } {
+		0 => Wait::Timeout,
+		n if n < 0 => {
+			// Signal, caller polls again
+			if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+				Wait::Timeout
+			} else {
+				Wait::Hangup
+			}
+		}
+		_ if pfd.revents & (libc::POLLHUP | libc::POLLERR | libc::POLLNVAL) != 0 => Wait::Hangup,
+		_ => Wait::Readable,
+	}
+}
+
+/// State shared between hotplug loop and socket clients
+pub struct Daemon {
+	config_path: PathBuf,
+	config: Mutex<Config>,
+	/// Only hidraw handle for the headset, every command goes through it
+	device: Mutex<Option<ViveDevice>>,
+	serial: Mutex<Option<String>>,
+	usb_path: Mutex<Option<PathBuf>>,
+	last_mode_switch: Mutex<Option<Instant>>,
+	keepalive: Mutex<Option<Keepalive>>,
+	subscribers: Mutex<Vec<Sender<Event>>>,
+	stats: Mutex<Stats>,
+	/// Display is blanked, with whether mode was lowered, kept over reconnects caused by
+	/// standby mode switch
+	standby: Mutex<Option<bool>>,
+}
+impl Daemon {
+	pub fn new(config_path: PathBuf, config: Config, keepalive: Option<Keepalive>) -> Self {
+		Self {
+			config_path,
+			config: Mutex::new(config),
+			device: Mutex::new(None),
+			serial: Mutex::new(None),
+			usb_path: Mutex::new(None),
+			last_mode_switch: Mutex::new(None),
+			keepalive: Mutex::new(keepalive),
+			subscribers: Mutex::new(Vec::new()),
+			stats: Mutex::new(Stats::default()),
+			standby: Mutex::new(None),
+		}
+	}
+
+	/// Receives every event since subscription, dropping receiver unsubscribes
+	pub fn subscribe(&self) -> Receiver<Event> {
+		let (tx, rx) = mpsc::channel();
+		self.subscribers.lock().expect("lock").push(tx);
+		rx
+	}
+	fn emit(&self, event: Event) {
+		self.subscribers
+			.lock()
+			.expect("lock")
+			.retain(|tx| tx.send(event.clone()).is_ok());
+	}
+	fn emit_changes(&self, old: Option<MainboardStatus>, new: MainboardStatus) {
+		if old.map(|o| o.ipd) != Some(new.ipd) {
+			self.emit(Event::Ipd { ipd: new.ipd });
+		}
+		if old.map(|o| o.proximity_change) != Some(new.proximity_change) {
+			self.emit(Event::Proximity {
+				near: new.proximity_change != 0,
+			});
+		}
+	}
+
+	fn set_mode(&self, dev: &ViveDevice, resolution: u8) -> Result<()> {
+		dev.set_mode(resolution)?;
+		*self.last_mode_switch.lock().expect("lock") = Some(Instant::now());
+		self.stats.lock().expect("lock").mode_switches += 1;
+		self.emit(Event::Mode { resolution });
+		Ok(())
+	}
+
+	/// Settings stored for this headset override the global ones
+	fn effective_settings(&self, serial: Option<&str>) -> Settings {
+		let mut settings = self.config.lock().expect("lock").settings.clone();
+		if let Some(serial) = serial {
+			match headsets::load(serial) {
+				Ok(Some(stored)) => settings.merge(&stored),
+				Ok(None) => {}
+				Err(e) => warn!("failed to load stored headset settings: {e}"),
+			}
+		}
+		settings
+	}
+
+	fn apply_settings(&self, dev: &ViveDevice, serial: Option<&str>) -> Result<()> {
+		let settings = self.effective_settings(serial);
+		if self.standby.lock().expect("lock").is_some() {
+			info!("headset is in standby, keeping display blanked");
+			dev.set_brightness(0)?;
+			return Ok(());
+		}
+		if let Some(resolution) = settings.resolution {
+			let last_switch = *self.last_mode_switch.lock().expect("lock");
+			if last_switch.map(|t| t.elapsed() < MODE_SWITCH_SETTLE) == Some(true) {
+				info!("skipping mode switch, headset reconnected after previous one");
+			} else {
+				self.set_mode(dev, resolution)?;
+			}
+		}
+		if let Some(enabled) = settings.noise_cancel {
+			dev.toggle_noise_canceling(enabled)?;
+		}
+		if let Some(brightness) = settings.brightness {
+			dev.set_brightness(brightness)?;
+		}
+		if let Some(enabled) = settings.cameras {
+			// Needs udev rules, shouldn't prevent anything else from working
+			if let Err(e) = dev.set_cameras_enabled(enabled) {
+				warn!("failed to toggle cameras: {e}");
+			}
+		}
+		Ok(())
+	}
+
+	/// Has no effect if there is no base stations configured
+	pub fn set_keepalive(&self, active: bool) {
+		{
+			let mut keepalive = self.keepalive.lock().expect("lock");
+			let Some(keepalive) = &mut *keepalive else {
+				return;
+			};
+			keepalive.set_active(active);
+		}
+		self.emit(Event::BaseStations { on: active });
+	}
+
+	/// Waits for headset, applies settings, and holds it until disconnect, forever
+	pub fn run_hotplug(&self) -> ! {
+		// Open keeps failing the same way until session ends, or udev rules are fixed
+		let mut reported = None;
+		loop {
+			let dev = match ViveDevice::open_first() {
+				Ok(dev) => dev,
+				Err(e) => {
+					let kind = mem::discriminant(&e);
+					if reported != Some(kind) {
+						match e {
+							// Requests are forwarded to driver-proxy during session anyway
+							vive_hid::Error::HeldBySteamVr { .. } => {
+								info!("{e}, requests go through its driver until session ends")
+							}
+							vive_hid::Error::PermissionDenied(_) => warn!("{e}"),
+							_ => {}
+						}
+					}
+					reported = Some(kind);
+					sleep(RECONNECT_INTERVAL);
+					continue;
+				}
+			};
+			reported = None;
+			// Separate fd gets its own copy of every report, so status is read without the
+			// device lock, which leaves it to clients
+			let mut events = match dev.status_events() {
+				Ok(events) => events,
+				Err(e) => {
+					warn!("failed to listen for headset status: {e}");
+					sleep(RECONNECT_INTERVAL);
+					continue;
+				}
+			};
+			let serial = dev.read_devsn().ok();
+			info!(
+				"headset connected: {}",
+				serial.as_deref().unwrap_or("unknown")
+			);
+			if let Err(e) = self.apply_settings(&dev, serial.as_deref()) {
+				warn!("failed to apply settings: {e}");
+			}
+			*self.serial.lock().expect("lock") = serial.clone();
+			*self.usb_path.lock().expect("lock") = dev.usb_path().ok();
+			*self.device.lock().expect("lock") = Some(dev);
+			self.set_keepalive(true);
+			{
+				let mut stats = self.stats.lock().expect("lock");
+				stats.connects += 1;
+				stats.connected_since = Some(Instant::now());
+			}
+			self.emit(Event::Connected { serial });
+
+			let mut last = None;
+			let mut heard = Instant::now();
+			loop {
+				let res = match wait_readable(&events, STATUS_POLL_MS) {
+					Wait::Readable => events.latest(),
+					Wait::Timeout => Ok(None),
+					Wait::Hangup => Err(vive_hid::Error::DeviceNotFound),
+				};
+				let res = match res {
+					Ok(Some(status)) => {
+						heard = Instant::now();
+						Ok(Some(status))
+					}
+					Ok(None) if heard.elapsed() < LIVENESS_PROBE => Ok(None),
+					// Failed read of device handle is what tells connection headset is lost, it
+					// doesn't wait, so lock is only held for a moment
+					res => {
+						heard = Instant::now();
+						match &*self.device.lock().expect("lock") {
+							Some(dev) => dev.read_status(0).and(res),
+							None => break,
+						}
+					}
+				};
+				match res {
+					Ok(Some(status)) => {
+						self.emit_changes(last, status);
+						self.stats.lock().expect("lock").last_status = Some(status);
+						last = Some(status);
+					}
+					Ok(None) => {}
+					Err(e) => {
+						info!("headset disconnected: {e}");
+						break;
+					}
+				}
+			}
+
+			*self.device.lock().expect("lock") = None;
+			*self.serial.lock().expect("lock") = None;
+			*self.usb_path.lock().expect("lock") = None;
+			self.set_keepalive(false);
+			{
+				let mut stats = self.stats.lock().expect("lock");
+				stats.disconnects += 1;
+				stats.connected_since = None;
+				stats.last_status = None;
+			}
+			self.emit(Event::Disconnected);
+		}
+	}
+
+	/// Passes headset lifecycle changes to subscribers, forever
+	pub fn run_state_watch(&self) -> ! {
+		let changes = ViveContext::global().connection().subscribe();
+		for change in changes {
+			info!("headset is {}", change.to);
+			self.emit(Event::State {
+				state: change.to.name().to_owned(),
+				reason: change.reason,
+			});
+		}
+		unreachable!("connection is never dropped")
+	}
+
+	/// Watches for driver-proxy control socket, forever, driver events are passed to subscribers
+	/// during session
+	pub fn run_session_watch(&self) -> ! {
+		let mut running = false;
+		loop {
+			let client = Client::connect_driver();
+			let now = client.is_some();
+			if now != running {
+				info!(
+					"SteamVR session {}",
+					if now { "started" } else { "stopped" }
+				);
+				running = now;
+				self.emit(Event::Session { running });
+			}
+			let Some(client) = client else {
+				sleep(SESSION_POLL);
+				continue;
+			};
+			match client.subscribe() {
+				// Stream ends with the session, which is checked right away
+				Ok(events) => {
+					for event in events {
+						match event {
+							Ok(event) => self.emit(event),
+							// Newer driver
+							Err(vive_ipc::Error::Json(e)) => warn!("unknown driver event: {e}"),
+							Err(_) => break,
+						}
+					}
+				}
+				// Older driver, only session itself is seen
+				Err(_) => sleep(SESSION_POLL),
+			}
+		}
+	}
+
+	/// Blanks display, or restores settings, there is no known panel power command, so
+	/// backlight is turned off instead
+	///
+	/// `lower_mode` is only used on entering, mode is restored on leaving if it was lowered
+	///
+	/// Display is left alone during SteamVR session, driver-proxy applies settings on start
+	pub fn set_standby(&self, on: bool, lower_mode: bool) -> Result<()> {
+		let mut standby = self.standby.lock().expect("lock");
+		if standby.is_some() == on {
+			return Ok(());
+		}
+		if Client::connect_driver().is_some() {
+			if !on {
+				*standby = None;
+			}
+			return Ok(());
+		}
+		let serial = self.serial.lock().expect("lock").clone();
+		let settings = self.effective_settings(serial.as_deref());
+		let device = self.device.lock().expect("lock");
+		let Some(dev) = &*device else {
+			bail!("headset is not connected");
+		};
+		let lowest = Resolution::R2448x1224f90 as u8;
+		if on {
+			info!("entering standby");
+			dev.set_brightness(0)?;
+			let lower = lower_mode && settings.resolution.map(|r| r != lowest) == Some(true);
+			*standby = Some(lower);
+			if lower {
+				self.set_mode(dev, lowest)?;
+			}
+		} else {
+			info!("leaving standby");
+			let lowered = standby.take() == Some(true);
+			dev.set_brightness(settings.brightness.unwrap_or(DEFAULT_BRIGHTNESS))?;
+			if let (true, Some(resolution)) = (lowered, settings.resolution) {
+				self.set_mode(dev, resolution)?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Sysfs path of connected headset, see [`ViveDevice::usb_path`]
+	pub fn usb_path(&self) -> Option<PathBuf> {
+		self.usb_path.lock().expect("lock").clone()
+	}
+
+	pub fn stats(&self) -> Stats {
+		*self.stats.lock().expect("lock")
+	}
+
+	pub fn status(&self) -> Status {
+		let driver = Client::connect_driver().map(|mut driver| driver.status().ok());
+		let driver_status = driver.as_ref().and_then(Option::as_ref);
+		let state = ViveContext::global().connection().state();
+		Status {
+			// Headset may be held by driver-proxy only, if daemon failed to open it during session
+			connected: state.is_ready() || driver_status.is_some_and(|s| s.connected),
+			state: match driver_status {
+				Some(driver) if !state.is_ready() => driver
+					.state
+					.clone()
+					// Older driver
+					.or_else(|| driver.connected.then(|| "ready".to_owned())),
+				_ => Some(state.name().to_owned()),
+			},
+			serial: self
+				.serial
+				.lock()
+				.expect("lock")
+				.clone()
+				.or_else(|| driver_status.and_then(|s| s.serial.clone())),
+			settings: self.config.lock().expect("lock").settings.clone(),
+			lens_fallback: false,
+			standby: match driver {
+				Some(status) => status.is_some_and(|s| s.standby),
+				None => self.standby.lock().expect("lock").is_some(),
+			},
+		}
+	}
+
+	/// Persists setting, both globally and for connected headset, and applies it immediately
+	/// if headset is connected, otherwise it will be applied on connect
+	///
+	/// During SteamVR session request is forwarded to driver-proxy instead, as it owns the headset
+	fn update(
+		&self,
+		request: &Request,
+		change: Settings,
+		apply: impl FnOnce(&Self, &ViveDevice) -> Result<()>,
+	) -> Result<()> {
+		{
+			let mut config = self.config.lock().expect("lock");
+			config.settings.merge(&change);
+			config.save(&self.config_path)?;
+		}
+		if let Some(serial) = &*self.serial.lock().expect("lock") {
+			headsets::update(serial, &change)?;
+		}
+		if let Some(mut driver) = Client::connect_driver() {
+			driver.request(request)?;
+			return Ok(());
+		}
+		if let Some(dev) = &*self.device.lock().expect("lock") {
+			apply(self, dev)?;
+		}
+		Ok(())
+	}
+
+	fn try_handle(&self, request: Request) -> Result<Response> {
+		match &request {
+			Request::Status => return Ok(Response::Status(self.status())),
+			&Request::SetResolution { resolution } => {
+				Resolution::try_from(resolution)?;
+				self.update(
+					&request,
+					Settings {
+						resolution: Some(resolution),
+						..Settings::default()
+					},
+					|this, dev| this.set_mode(dev, resolution),
+				)?;
+			}
+			&Request::SetBrightness { brightness } => {
+				if !(1..=130).contains(&brightness) {
+					bail!("brightness should be in 1-130 range");
+				}
+				self.update(
+					&request,
+					Settings {
+						brightness: Some(brightness),
+						..Settings::default()
+					},
+					|_, dev| Ok(dev.set_brightness(brightness)?),
+				)?;
+			}
+			Request::Profiles => {
+				let config = self.config.lock().expect("lock");
+				return Ok(Response::Profiles {
+					active: config.profile.clone(),
+					profiles: config.profiles(),
+				});
+			}
+			Request::SetProfile { name } => {
+				let profile = self.config.lock().expect("lock").profiles().remove(name);
+				let Some(profile) = profile else {
+					bail!("unknown profile: {name}");
+				};
+				// Resolution is last, as it can't be switched during SteamVR session
+				if let Some(brightness) = profile.brightness {
+					self.try_handle(Request::SetBrightness { brightness })?;
+				}
+				if let Some(enabled) = profile.noise_cancel {
+					self.try_handle(Request::SetNoiseCancel { enabled })?;
+				}
+				if let Some(resolution) = profile.resolution {
+					self.try_handle(Request::SetResolution { resolution })?;
+				}
+				let mut config = self.config.lock().expect("lock");
+				config.profile = Some(name.clone());
+				config.save(&self.config_path)?;
+			}
+			Request::FrameTiming => match Client::connect_driver() {
+				Some(mut driver) => return Ok(Response::FrameTiming(driver.frame_timing()?)),
+				None => bail!("frame timing is only available during SteamVR session"),
+			},
+			Request::Subscribe => bail!("events are only streamed over socket"),
+			Request::Debug {
+				request: DebugRequest::RecentReports,
+			} => match &*self.device.lock().expect("lock") {
+				Some(dev) => {
+					return Ok(Response::Debug {
+						response: DebugResponse::RecentReports {
+							reports: dev.dump_recent(),
+						},
+					})
+				}
+				None => bail!("headset is not connected"),
+			},
+			Request::Debug { request } => match Client::connect_driver() {
+				Some(mut driver) => {
+					return Ok(Response::Debug {
+						response: driver.debug(request.clone())?,
+					})
+				}
+				None => bail!("debug requests are only served during SteamVR session"),
+			},
+			&Request::SetBaseStations { on } => {
+				if self.keepalive.lock().expect("lock").is_none() {
+					bail!("no base stations are configured");
+				}
+				self.set_keepalive(on);
+			}
+			Request::SleepBaseStations { names, after } => {
+				// Own stations follow power_down policy, which knows about session already
+				if self.keepalive.lock().expect("lock").is_none() {
+					sleep_base_stations(names.clone(), *after);
+				}
+			}
+			&Request::SetNoiseCancel { enabled } => {
+				self.update(
+					&request,
+					Settings {
+						noise_cancel: Some(enabled),
+						..Settings::default()
+					},
+					|_, dev| Ok(dev.toggle_noise_canceling(enabled)?),
+				)?;
+			}
+		}
+		Ok(Response::Ok)
+	}
+	pub fn handle(&self, request: Request) -> Response {
+		self.try_handle(request)
+			.unwrap_or_else(|e| Response::Error {
+				message: format!("{e:#}"),
+			})
+	}
+
+	/// Puts base stations to sleep
+	pub fn shutdown(&self) {
+		if let Some(keepalive) = self.keepalive.lock().expect("lock").take() {
+			keepalive.finish();
+		}
+	}
+}
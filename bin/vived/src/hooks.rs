@@ -0,0 +1,52 @@
+//! User commands, run on headset events, i.e to pause music when headset is removed
+//!
+//! ```json
+//! { "hooks": { "removed": "playerctl pause", "session_started": "pactl set-card-profile ..." } }
+//! ```
+use std::{process::Command, sync::mpsc::Receiver, thread};
+
+use tracing::{info, warn};
+use vive_ipc::Event;
+
+use crate::config::Hooks;
+
+fn hook(hooks: &Hooks, event: &Event) -> Option<String> {
+	match event {
+		Event::Connected { .. } => hooks.connected.clone(),
+		Event::Disconnected => hooks.disconnected.clone(),
+		Event::Proximity { near: true } => hooks.worn.clone(),
+		Event::Proximity { near: false } => hooks.removed.clone(),
+		Event::Session { running: true } => hooks.session_started.clone(),
+		Event::Session { running: false } => hooks.session_stopped.clone(),
+		Event::Ipd { .. }
+		| Event::Mode { .. }
+		| Event::BaseStations { .. }
+		| Event::ModeApplied { .. }
+		| Event::ModeFallback { .. }
+		| Event::LensServerRestarted
+		| Event::State { .. } => None,
+	}
+}
+
+/// Runs hooks for events until daemon exits, slow hook doesn't delay the next ones
+pub fn run(hooks: Hooks, events: Receiver<Event>) {
+	for event in events {
+		let Some(command) = hook(&hooks, &event) else {
+			continue;
+		};
+		let json = serde_json::to_string(&event).expect("serializable");
+		thread::spawn(move || {
+			info!("running hook: {command}");
+			match Command::new("sh")
+				.arg("-c")
+				.arg(&command)
+				.env("VIVEPRO2_EVENT", json)
+				.status()
+			{
+				Ok(status) if status.success() => {}
+				Ok(status) => warn!("hook {command:?} failed: {status}"),
+				Err(e) => warn!("failed to run hook {command:?}: {e}"),
+			}
+		});
+	}
+}
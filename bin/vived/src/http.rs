@@ -0,0 +1,506 @@
+//! Minimal REST API, for home automation and web dashboards
+//!
+//! ```sh
+//! curl http://127.0.0.1:8370/status
+//! curl -X PUT -H 'Content-Type: application/json' -d '{"on": false}' http://127.0.0.1:8370/base-stations
+//! websocat ws://127.0.0.1:8370/events
+//! curl http://127.0.0.1:8370/metrics
+//! ```
+//!
+//! Control panel page is served on `/`, for users who don't want to touch a terminal
+//!
+//! Web pages can reach it through the browser of the user, so requests are only served for
+//! `Host` naming this machine (loopback, or the address API is bound to), with no `Origin` of
+//! other sites, and commands need JSON content type, which pages can't send cross-origin
+//! without preflight. With `http_token` configured, every request but the panel page needs
+//! `Authorization: Bearer <token>`, or `access_token` query parameter, for WebSocket in browsers.
+//! Without it, API is only bound to loopback
+use std::{
+	io::{self, BufRead, BufReader, Write},
+	net::{IpAddr, SocketAddr, TcpListener, TcpStream},
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
+	thread,
+	time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+use vive_ipc::{Request, Response};
+
+use crate::{daemon::Daemon, metrics, websocket};
+
+const PANEL: &str = include_str!("panel.html");
+const MAX_BODY_SIZE: usize = 64 * 1024;
+const MAX_HEADERS: usize = 64;
+/// Applies to every read and write, so stalled clients don't keep their threads forever
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+/// Every connection has its thread, WebSocket ones stay open, the rest are refused with 503
+const MAX_CONNECTIONS: usize = 32;
+
+pub struct HttpRequest {
+	pub method: String,
+	/// Without query string
+	pub path: String,
+	pub query: String,
+	pub headers: Vec<(String, String)>,
+	pub body: Vec<u8>,
+}
+impl HttpRequest {
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self.headers
+			.iter()
+			.find(|(n, _)| n.eq_ignore_ascii_case(name))
+			.map(|(_, v)| v.as_str())
+	}
+	/// Not percent-decoded
+	pub fn query_param(&self, name: &str) -> Option<&str> {
+		self.query
+			.split('&')
+			.filter_map(|pair| pair.split_once('='))
+			.find(|(n, _)| *n == name)
+			.map(|(_, v)| v)
+	}
+}
+
+fn bad_request(msg: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// `None` if connection was closed before request
+fn read_request(read: &mut impl BufRead) -> io::Result<Option<HttpRequest>> {
+	let mut line = String::new();
+	if read.read_line(&mut line)? == 0 {
+		return Ok(None);
+	}
+	let mut parts = line.split_whitespace();
+	let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+		return Err(bad_request("malformed request line"));
+	};
+	let method = method.to_owned();
+	let (path, query) = target.split_once('?').unwrap_or((target, ""));
+	let (path, query) = (path.to_owned(), query.to_owned());
+
+	let mut headers = Vec::new();
+	loop {
+		line.clear();
+		read.read_line(&mut line)?;
+		let header = line.trim_end();
+		if header.is_empty() {
+			break;
+		}
+		if headers.len() == MAX_HEADERS {
+			return Err(bad_request("too many headers"));
+		}
+		let Some((name, value)) = header.split_once(':') else {
+			return Err(bad_request("malformed header"));
+		};
+		headers.push((name.trim().to_owned(), value.trim().to_owned()));
+	}
+	let mut req = HttpRequest {
+		method,
+		path,
+		query,
+		headers,
+		body: Vec::new(),
+	};
+	let len = match req.header("content-length") {
+		Some(len) => len
+			.parse::<usize>()
+			.map_err(|_| bad_request("malformed content-length"))?,
+		None => 0,
+	};
+	if len > MAX_BODY_SIZE {
+		return Err(bad_request("body is too large"));
+	}
+	req.body.resize(len, 0);
+	read.read_exact(&mut req.body)?;
+	Ok(Some(req))
+}
+
+fn write_body(
+	write: &mut impl Write,
+	status: u16,
+	content_type: &str,
+	body: &str,
+) -> io::Result<()> {
+	let reason = match status {
+		200 => "OK",
+		400 => "Bad Request",
+		401 => "Unauthorized",
+		403 => "Forbidden",
+		404 => "Not Found",
+		405 => "Method Not Allowed",
+		415 => "Unsupported Media Type",
+		503 => "Service Unavailable",
+		_ => "Error",
+	};
+	write!(
+		write,
+		"HTTP/1.1 {status} {reason}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+		body.len()
+	)?;
+	write.flush()
+}
+pub fn write_response(write: &mut impl Write, status: u16, body: &Value) -> io::Result<()> {
+	write_body(write, status, "application/json", &body.to_string())
+}
+
+fn error(status: u16, message: impl Into<String>) -> (u16, Value) {
+	(status, json!({ "error": message.into() }))
+}
+
+/// Body is a request of the socket protocol, without `command` field, which is taken from path
+fn command(name: &str, body: &[u8]) -> Result<Request, String> {
+	let mut value: Value = if body.is_empty() {
+		json!({})
+	} else {
+		serde_json::from_slice(body).map_err(|e| format!("malformed body: {e}"))?
+	};
+	let Some(object) = value.as_object_mut() else {
+		return Err("body should be an object".to_owned());
+	};
+	object.insert("command".to_owned(), name.into());
+	serde_json::from_value(value).map_err(|e| format!("malformed body: {e}"))
+}
+
+fn route(daemon: &Daemon, req: &HttpRequest) -> (u16, Value) {
+	let request = match (req.method.as_str(), req.path.as_str()) {
+		("GET", "/status") => Request::Status,
+		("GET", "/profiles") => Request::Profiles,
+		("PUT" | "POST", path) => {
+			let name = match path {
+				"/resolution" => "set_resolution",
+				"/brightness" => "set_brightness",
+				"/noise-cancel" => "set_noise_cancel",
+				"/base-stations" => "set_base_stations",
+				"/profile" => "set_profile",
+				_ => return error(404, "not found"),
+			};
+			match command(name, &req.body) {
+				Ok(request) => request,
+				Err(e) => return error(400, e),
+			}
+		}
+		(
+			_,
+			"/status" | "/resolution" | "/brightness" | "/noise-cancel" | "/base-stations"
+			| "/profiles" | "/profile",
+		) => return error(405, "method not allowed"),
+		_ => return error(404, "not found"),
+	};
+	match daemon.handle(request) {
+		Response::Ok => (200, json!({ "ok": true })),
+		Response::Status(status) => (200, serde_json::to_value(status).expect("serializable")),
+		Response::Profiles { active, profiles } => {
+			(200, json!({ "active": active, "profiles": profiles }))
+		}
+		Response::FrameTiming(timing) => (200, serde_json::to_value(timing).expect("serializable")),
+		Response::Debug { response } => {
+			(200, serde_json::to_value(response).expect("serializable"))
+		}
+		Response::Error { message } => error(400, message),
+	}
+}
+
+/// Whether `host` names this machine, `local` is the address connection was accepted on. Other
+/// names may resolve to it too, but then page of any site could, through DNS rebinding
+fn is_local_host(host: &str, local: IpAddr) -> bool {
+	let name = match host.strip_prefix('[') {
+		Some(v6) => v6.split(']').next().unwrap_or(v6),
+		None => host.split(':').next().unwrap_or(host),
+	};
+	if name.eq_ignore_ascii_case("localhost") {
+		return true;
+	}
+	name.parse::<IpAddr>()
+		.is_ok_and(|ip| ip.is_loopback() || ip == local)
+}
+
+/// Doesn't leak token length or prefix through timing
+fn token_matches(given: &str, token: &str) -> bool {
+	given.len() == token.len()
+		&& given
+			.bytes()
+			.zip(token.bytes())
+			.fold(0, |acc, (a, b)| acc | (a ^ b))
+			== 0
+}
+
+/// Error response, if request shouldn't be served
+fn check_access(req: &HttpRequest, token: Option<&str>, local: IpAddr) -> Result<(), (u16, Value)> {
+	let Some(host) = req.header("host").filter(|h| is_local_host(h, local)) else {
+		return Err(error(403, "host is not allowed"));
+	};
+	// Browsers send it with cross-origin and non-GET requests, same origin is the panel page
+	if let Some(origin) = req.header("origin") {
+		if !origin.eq_ignore_ascii_case(&format!("http://{host}")) {
+			return Err(error(403, "cross-origin requests are not allowed"));
+		}
+	}
+	if matches!(req.method.as_str(), "PUT" | "POST") {
+		let json = req.header("content-type").is_some_and(|t| {
+			let media = t.split(';').next().unwrap_or(t).trim();
+			media.eq_ignore_ascii_case("application/json")
+		});
+		if !json {
+			return Err(error(415, "content type should be application/json"));
+		}
+	}
+	// Panel is static, it asks for token itself
+	if let Some(token) = token.filter(|_| !(req.method == "GET" && req.path == "/")) {
+		let given = req
+			.header("authorization")
+			.and_then(|v| v.strip_prefix("Bearer "))
+			.or_else(|| req.query_param("access_token"));
+		if !given.is_some_and(|given| token_matches(given.trim(), token)) {
+			return Err(error(401, "missing or wrong bearer token"));
+		}
+	}
+	Ok(())
+}
+
+fn handle_client(daemon: &Daemon, stream: TcpStream, token: Option<&str>) -> io::Result<()> {
+	stream.set_read_timeout(Some(IO_TIMEOUT))?;
+	stream.set_write_timeout(Some(IO_TIMEOUT))?;
+	let local = stream.local_addr()?.ip();
+	let mut read = BufReader::new(stream.try_clone()?);
+	let mut write = stream;
+	let request = match read_request(&mut read) {
+		Ok(Some(req)) => match check_access(&req, token, local) {
+			Ok(()) => Ok(Some(req)),
+			Err((status, body)) => return write_response(&mut write, status, &body),
+		},
+		other => other,
+	};
+	let (status, body) = match request {
+		Ok(Some(req)) if req.path == "/events" => {
+			if req.method == "GET" && websocket::is_upgrade(&req) {
+				return websocket::serve(daemon, &req, read, write);
+			}
+			error(400, "websocket upgrade expected")
+		}
+		Ok(Some(req)) if req.method == "GET" && req.path == "/" => {
+			return write_body(&mut write, 200, "text/html; charset=utf-8", PANEL);
+		}
+		Ok(Some(req)) if req.method == "GET" && req.path == "/metrics" => {
+			return write_body(
+				&mut write,
+				200,
+				"text/plain; version=0.0.4",
+				&metrics::render(daemon),
+			);
+		}
+		Ok(Some(req)) => route(daemon, &req),
+		Ok(None) => return Ok(()),
+		Err(e) if e.kind() == io::ErrorKind::InvalidData => error(400, e.to_string()),
+		Err(e) => return Err(e),
+	};
+	write_response(&mut write, status, &body)
+}
+
+/// Slot of [`MAX_CONNECTIONS`], released with the connection thread
+struct Slot(Arc<AtomicUsize>);
+impl Slot {
+	fn take(open: &Arc<AtomicUsize>) -> Option<Self> {
+		open.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+			(n < MAX_CONNECTIONS).then_some(n + 1)
+		})
+		.ok()
+		.map(|_| Self(open.clone()))
+	}
+}
+impl Drop for Slot {
+	fn drop(&mut self) {
+		self.0.fetch_sub(1, Ordering::AcqRel);
+	}
+}
+
+/// Anyone who reaches non-loopback address could control the headset, token is required then
+fn check_bind(addr: SocketAddr, token: Option<&str>) -> Result<()> {
+	if !addr.ip().is_loopback() && token.is_none() {
+		bail!("refusing to listen on {addr} without http_token, which is required for addresses other than loopback");
+	}
+	Ok(())
+}
+
+/// `token` is `http_token` of config
+pub fn serve(addr: SocketAddr, token: Option<String>, daemon: Arc<Daemon>) -> Result<()> {
+	check_bind(addr, token.as_deref())?;
+	let listener = TcpListener::bind(addr).with_context(|| format!("failed to bind {addr}"))?;
+	info!("http api listening on {addr}");
+	let token: Option<Arc<str>> = token.map(Into::into);
+	let open = Arc::new(AtomicUsize::new(0));
+	for stream in listener.incoming() {
+		let Ok(mut stream) = stream else {
+			continue;
+		};
+		let Some(slot) = Slot::take(&open) else {
+			warn!("http client refused, {MAX_CONNECTIONS} connections are open already");
+			// Small enough for socket buffer, doesn't block accepting
+			let _ = write_response(&mut stream, 503, &error(503, "too many connections").1);
+			continue;
+		};
+		let daemon = daemon.clone();
+		let token = token.clone();
+		thread::spawn(move || {
+			let _slot = slot;
+			if let Err(e) = handle_client(&daemon, stream, token.as_deref()) {
+				warn!("http client failed: {e}");
+			}
+		});
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::net::Ipv4Addr;
+
+	use super::*;
+
+	const LOCAL: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+	const LAN: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10));
+
+	fn request(method: &str, path: &str, headers: &[(&str, &str)]) -> HttpRequest {
+		let (path, query) = path.split_once('?').unwrap_or((path, ""));
+		HttpRequest {
+			method: method.to_owned(),
+			path: path.to_owned(),
+			query: query.to_owned(),
+			headers: headers
+				.iter()
+				.map(|(n, v)| (n.to_string(), v.to_string()))
+				.collect(),
+			body: Vec::new(),
+		}
+	}
+	fn status(req: &HttpRequest, token: Option<&str>, local: IpAddr) -> u16 {
+		check_access(req, token, local).map_or_else(|(status, _)| status, |()| 200)
+	}
+
+	#[test]
+	fn host() {
+		for host in [
+			"127.0.0.1:8370",
+			"localhost:8370",
+			"LOCALHOST",
+			"[::1]:8370",
+		] {
+			let req = request("GET", "/status", &[("Host", host)]);
+			assert_eq!(status(&req, None, LOCAL), 200, "{host}");
+		}
+		// Bound address is fine, any other name could be rebound by DNS
+		let req = request("GET", "/status", &[("Host", "192.168.1.10:8370")]);
+		assert_eq!(status(&req, None, LAN), 200);
+		assert_eq!(status(&req, None, LOCAL), 403);
+		for host in ["evil.example:8370", "127.0.0.1.evil.example"] {
+			let req = request("GET", "/status", &[("Host", host)]);
+			assert_eq!(status(&req, None, LOCAL), 403, "{host}");
+		}
+		assert_eq!(status(&request("GET", "/status", &[]), None, LOCAL), 403);
+	}
+
+	#[test]
+	fn origin() {
+		let same = request(
+			"GET",
+			"/status",
+			&[
+				("Host", "127.0.0.1:8370"),
+				("Origin", "http://127.0.0.1:8370"),
+			],
+		);
+		assert_eq!(status(&same, None, LOCAL), 200);
+		let other = request(
+			"GET",
+			"/status",
+			&[
+				("Host", "127.0.0.1:8370"),
+				("Origin", "http://evil.example"),
+			],
+		);
+		assert_eq!(status(&other, None, LOCAL), 403);
+	}
+
+	#[test]
+	fn commands_need_json() {
+		let host = ("Host", "127.0.0.1:8370");
+		let form = request(
+			"PUT",
+			"/brightness",
+			&[host, ("Content-Type", "application/x-www-form-urlencoded")],
+		);
+		assert_eq!(status(&form, None, LOCAL), 415);
+		assert_eq!(
+			status(&request("POST", "/profile", &[host]), None, LOCAL),
+			415
+		);
+		let json = request(
+			"PUT",
+			"/brightness",
+			&[host, ("Content-Type", "Application/JSON; charset=utf-8")],
+		);
+		assert_eq!(status(&json, None, LOCAL), 200);
+	}
+
+	#[test]
+	fn token() {
+		let host = ("Host", "127.0.0.1:8370");
+		let token = Some("secret");
+		assert_eq!(
+			status(&request("GET", "/status", &[host]), token, LOCAL),
+			401
+		);
+		let bearer = request(
+			"GET",
+			"/status",
+			&[host, ("Authorization", "Bearer secret")],
+		);
+		assert_eq!(status(&bearer, token, LOCAL), 200);
+		let wrong = request(
+			"GET",
+			"/status",
+			&[host, ("Authorization", "Bearer secreT")],
+		);
+		assert_eq!(status(&wrong, token, LOCAL), 401);
+		let prefix = request("GET", "/status", &[host, ("Authorization", "Bearer secre")]);
+		assert_eq!(status(&prefix, token, LOCAL), 401);
+		let query = request("GET", "/events?access_token=secret", &[host]);
+		assert_eq!(status(&query, token, LOCAL), 200);
+		// Panel asks for token itself
+		assert_eq!(status(&request("GET", "/", &[host]), token, LOCAL), 200);
+		// Token doesn't lift host check
+		let foreign = request(
+			"GET",
+			"/status",
+			&[("Host", "evil.example"), ("Authorization", "Bearer secret")],
+		);
+		assert_eq!(status(&foreign, token, LOCAL), 403);
+	}
+
+	#[test]
+	fn bind() {
+		let loopback = "127.0.0.1:8370".parse().unwrap();
+		let any = "0.0.0.0:8370".parse().unwrap();
+		assert!(check_bind(loopback, None).is_ok());
+		assert!(check_bind("[::1]:8370".parse().unwrap(), None).is_ok());
+		assert!(check_bind(any, None).is_err());
+		assert!(check_bind(any, Some("secret")).is_ok());
+	}
+
+	#[test]
+	fn connection_limit() {
+		let open = Arc::new(AtomicUsize::new(0));
+		let slots = (0..MAX_CONNECTIONS)
+			.map(|_| Slot::take(&open).expect("free slot"))
+			.collect::<Vec<_>>();
+		assert!(Slot::take(&open).is_none());
+		drop(slots);
+		assert_eq!(open.load(Ordering::Acquire), 0);
+		assert!(Slot::take(&open).is_some());
+	}
+}
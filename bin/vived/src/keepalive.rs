@@ -0,0 +1,52 @@
+//! Keeps base stations powered on while headset is connected, same as driver-proxy does
+//! during SteamVR session, but also outside of it
+use anyhow::{anyhow, Result};
+use tokio::{runtime::Runtime, task::LocalSet};
+use tracing::info;
+use valve_pm::{start_manager, StationCommand, StationControl, StationState};
+
+use crate::realtime;
+
+pub struct Keepalive {
+	runtime: Runtime,
+	stations: Vec<StationControl>,
+}
+impl Keepalive {
+	pub fn start(names: &[String], realtime: Option<u32>) -> Result<Self> {
+		let runtime = tokio::runtime::Builder::new_multi_thread()
+			.enable_all()
+			.on_thread_start(move || realtime::enter(realtime, "base station keepalive"))
+			.build()?;
+		let manager = runtime
+			.block_on(start_manager())
+			.map_err(|()| anyhow!("bluetooth is not available"))?;
+		let _runtime = runtime.enter();
+		// Stations are only woken up once headset is connected
+		let stations = names
+			.iter()
+			.map(|name| StationControl::new(manager.clone(), name.clone(), StationState::Sleeping))
+			.collect();
+		info!("managing {} base stations", names.len());
+		Ok(Self { runtime, stations })
+	}
+	pub fn set_active(&mut self, active: bool) {
+		let state = if active {
+			StationState::On
+		} else {
+			StationState::Sleeping
+		};
+		for station in &mut self.stations {
+			station.send(StationCommand::SetState(state));
+		}
+	}
+	/// Disconnects from stations, putting them to sleep
+	pub fn finish(self) {
+		info!("disconnecting from base stations");
+		let _runtime = self.runtime.enter();
+		let localset = LocalSet::new();
+		for station in self.stations {
+			localset.spawn_local(station.finish());
+		}
+		self.runtime.block_on(localset);
+	}
+}
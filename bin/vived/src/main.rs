@@ -0,0 +1,188 @@
+//! Headset control daemon
+//!
+//! Owns hidraw handle, so multiple clients don't fight over it, and applies
+//! persisted settings every time headset is connected
+use std::{
+	env::{self, args},
+	fs,
+	mem::MaybeUninit,
+	process::exit,
+	sync::Arc,
+	thread,
+};
+
+use anyhow::Result;
+use tracing::{info, level_filters::LevelFilter, warn};
+
+use crate::{
+	config::{config_path, Config},
+	daemon::Daemon,
+	keepalive::Keepalive,
+};
+
+mod bus;
+mod config;
+mod daemon;
+mod hooks;
+mod http;
+mod keepalive;
+mod metrics;
+mod notifications;
+mod pipewire;
+mod realtime;
+mod server;
+mod standby;
+mod websocket;
+
+const USAGE: &str = "\
+Usage: vived [--config <path>]
+
+Options:
+  --config <path>               Settings file, $XDG_CONFIG_HOME/vivepro2/vived.toml by default
+";
+
+/// Blocks termination signals in every thread, returns set to wait on
+///
+/// Should be called before any thread is spawned, so signal is only received by [`wait_signal`]
+fn block_signals() -> libc::sigset_t {
+	unsafe {
+		let mut set = MaybeUninit::<libc::sigset_t>::uninit();
+		libc::sigemptyset(set.as_mut_ptr());
+		let mut set = set.assume_init();
+		libc::sigaddset(&mut set, libc::SIGINT);
+		libc::sigaddset(&mut set, libc::SIGTERM);
+		libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+		set
+	}
+}
+fn wait_signal(set: &libc::sigset_t) -> i32 {
+	let mut sig = 0;
+	unsafe { libc::sigwait(set, &mut sig) };
+	sig
+}
+
+/// `VIVEPRO2_LOG=debug`, `trace` also shows every HID report with `trace-hid` feature
+fn log_level() -> LevelFilter {
+	env::var("VIVEPRO2_LOG")
+		.ok()
+		.and_then(|level| level.parse().ok())
+		.unwrap_or(LevelFilter::INFO)
+}
+
+fn main() -> Result<()> {
+	tracing_subscriber::fmt()
+		.without_time()
+		.with_max_level(log_level())
+		.init();
+	let args = args().skip(1).collect::<Vec<_>>();
+	let config_path = match args
+		.iter()
+		.map(String::as_str)
+		.collect::<Vec<_>>()
+		.as_slice()
+	{
+		[] => config_path(),
+		["--config", path] => path.into(),
+		_ => {
+			eprint!("{USAGE}");
+			exit(2)
+		}
+	};
+	let config = Config::load(&config_path)?;
+	let http = config.http;
+	let http_token = config.http_token.clone();
+	let hooks = config.hooks.clone();
+	let switch_audio = config.switch_audio;
+	let notifications = config.notifications;
+	let standby = config.standby;
+	let power_down = config.power_down;
+	let realtime = config.realtime;
+	let signals = block_signals();
+
+	let keepalive = if config.base_stations.is_empty() {
+		None
+	} else {
+		Keepalive::start(&config.base_stations, realtime)
+			.map_err(|e| warn!("base station keepalive disabled: {e:#}"))
+			.ok()
+	};
+	let daemon = Arc::new(Daemon::new(config_path, config, keepalive));
+	let (listener, socket) = server::listen()?;
+
+	if !hooks.is_empty() {
+		// Subscribed before hotplug starts, so the first connection isn't missed
+		let events = daemon.subscribe();
+		thread::spawn(move || hooks::run(hooks, events));
+	}
+	if switch_audio {
+		let events = daemon.subscribe();
+		let daemon = daemon.clone();
+		thread::spawn(move || pipewire::run(daemon, events));
+	}
+	if notifications {
+		let events = daemon.subscribe();
+		thread::spawn(move || {
+			if let Err(e) = notifications::run(events) {
+				warn!("notifications disabled: {e}");
+			}
+		});
+	}
+	if let Some(standby) = standby {
+		let events = daemon.subscribe();
+		let daemon = daemon.clone();
+		thread::spawn(move || standby::run(daemon, standby, events));
+	}
+	if let Some(power_down) = power_down {
+		let events = daemon.subscribe();
+		let daemon = daemon.clone();
+		thread::spawn(move || standby::run_power_down(daemon, power_down, events));
+	}
+	{
+		let daemon = daemon.clone();
+		thread::spawn(move || {
+			realtime::enter(realtime, "headset polling");
+			daemon.run_hotplug()
+		});
+	}
+	{
+		let daemon = daemon.clone();
+		thread::spawn(move || daemon.run_session_watch());
+	}
+	{
+		let daemon = daemon.clone();
+		thread::spawn(move || daemon.run_state_watch());
+	}
+	{
+		let daemon = daemon.clone();
+		thread::spawn(move || {
+			if let Err(e) = bus::run(daemon) {
+				warn!("dbus interface disabled: {e}");
+			}
+		});
+	}
+	{
+		let daemon = daemon.clone();
+		thread::spawn(move || {
+			if let Err(e) = server::serve(listener, daemon) {
+				warn!("socket failed: {e}");
+			}
+		});
+	}
+
+	if let Some(addr) = http {
+		let daemon = daemon.clone();
+		thread::spawn(move || {
+			if let Err(e) = http::serve(addr, http_token, daemon) {
+				warn!("http api disabled: {e:#}");
+			}
+		});
+	}
+
+	let sig = wait_signal(&signals);
+	info!("received signal {sig}, exiting");
+	daemon.shutdown();
+	if let Some(socket) = socket {
+		let _ = fs::remove_file(socket);
+	}
+	Ok(())
+}
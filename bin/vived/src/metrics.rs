@@ -0,0 +1,123 @@
+//! Prometheus text exposition of daemon state, served on `/metrics` of REST API
+//!
+//! Frame timing is only exported during SteamVR session, it is relayed from driver-proxy.
+//! Headset temperatures and DisplayPort link errors aren't exported, as neither is known
+//! to be reported by the headset
+use std::fmt::Write;
+
+use vive_ipc::Client;
+
+use crate::daemon::Daemon;
+
+struct Exposition(String);
+impl Exposition {
+	fn metric(&mut self, name: &str, kind: &str, help: &str, value: impl Into<f64>) {
+		let _ = write!(
+			self.0,
+			"# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {}\n",
+			value.into()
+		);
+	}
+	fn gauge(&mut self, name: &str, help: &str, value: impl Into<f64>) {
+		self.metric(name, "gauge", help, value)
+	}
+	fn counter(&mut self, name: &str, help: &str, value: u64) {
+		self.metric(name, "counter", help, value as f64)
+	}
+}
+
+pub fn render(daemon: &Daemon) -> String {
+	let stats = daemon.stats();
+	let status = daemon.status();
+	let mut out = Exposition(String::new());
+
+	out.gauge(
+		"vivepro2_headset_connected",
+		"Whether headset is connected",
+		u8::from(status.connected),
+	);
+	out.gauge(
+		"vivepro2_session_duration_seconds",
+		"Time since headset was connected",
+		stats
+			.connected_since
+			.map(|t| t.elapsed().as_secs_f64())
+			.unwrap_or_default(),
+	);
+	out.counter(
+		"vivepro2_connects_total",
+		"Headset connections since daemon start",
+		stats.connects,
+	);
+	out.counter(
+		"vivepro2_disconnects_total",
+		"Headset disconnections since daemon start",
+		stats.disconnects,
+	);
+	out.counter(
+		"vivepro2_mode_switches_total",
+		"Display mode switches requested by daemon",
+		stats.mode_switches,
+	);
+	if let Some(brightness) = status.settings.brightness {
+		out.gauge("vivepro2_brightness", "Configured brightness", brightness);
+	}
+	if let Some(sensors) = stats.last_status {
+		out.gauge("vivepro2_ipd_raw", "Raw IPD knob position", sensors.ipd);
+		out.gauge(
+			"vivepro2_lens_separation_raw",
+			"Raw lens separation sensor value",
+			sensors.lens_separation,
+		);
+		out.gauge(
+			"vivepro2_proximity_raw",
+			"Raw proximity sensor value",
+			sensors.proximity,
+		);
+	}
+
+	let timing = Client::connect_driver().and_then(|mut driver| driver.frame_timing().ok());
+	out.gauge(
+		"vivepro2_steamvr_session",
+		"Whether driver-proxy is running",
+		u8::from(timing.is_some()),
+	);
+	if let Some(timing) = timing {
+		out.gauge(
+			"vivepro2_frames",
+			"Frames in timing sample window",
+			timing.frames,
+		);
+		out.gauge(
+			"vivepro2_frames_dropped",
+			"Dropped frames in sample window",
+			timing.dropped_frames,
+		);
+		out.gauge(
+			"vivepro2_frames_mispresented",
+			"Mispresented frames in sample window",
+			timing.mispresented_frames,
+		);
+		out.gauge(
+			"vivepro2_frames_reprojected",
+			"Reprojected frames in sample window",
+			timing.reprojected_frames,
+		);
+		out.gauge(
+			"vivepro2_frame_gpu_milliseconds",
+			"Average GPU render time of application and compositor",
+			timing.gpu_ms,
+		);
+		out.gauge(
+			"vivepro2_frame_compositor_cpu_milliseconds",
+			"Average compositor CPU render time",
+			timing.compositor_cpu_ms,
+		);
+		out.gauge(
+			"vivepro2_frame_interval_milliseconds",
+			"Average application frame interval",
+			timing.frame_interval_ms,
+		);
+	}
+	out.0
+}
@@ -0,0 +1,77 @@
+//! Desktop notifications for headset events, over freedesktop notification spec
+use std::{collections::HashMap, sync::mpsc::Receiver, time::Duration};
+
+use anyhow::Result;
+use dbus::{
+	arg::{RefArg, Variant},
+	blocking::Connection,
+};
+use tracing::warn;
+use vive_hid::Resolution;
+use vive_ipc::Event;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn message(event: &Event) -> Option<(&'static str, String)> {
+	Some(match event {
+		Event::Connected { serial } => ("Headset connected", serial.clone().unwrap_or_default()),
+		Event::Disconnected => ("Headset disconnected", String::new()),
+		Event::Mode { resolution } => (
+			"Display mode switched",
+			Resolution::try_from(*resolution)
+				.map(|r| format!("{r}, headset will reconnect"))
+				.unwrap_or_default(),
+		),
+		Event::BaseStations { on: false } => ("Base stations powered down", String::new()),
+		Event::BaseStations { on: true } => ("Base stations powered on", String::new()),
+		Event::State { state, reason } if state == "failed" => {
+			("Headset is unreachable", reason.clone().unwrap_or_default())
+		}
+		// Driver shows its own notifications during session
+		Event::Ipd { .. }
+		| Event::Proximity { .. }
+		| Event::Session { .. }
+		| Event::ModeApplied { .. }
+		| Event::ModeFallback { .. }
+		| Event::LensServerRestarted
+		| Event::State { .. } => return None,
+	})
+}
+
+fn notify(conn: &Connection, summary: &str, body: &str) -> Result<()> {
+	let proxy = conn.with_proxy(
+		"org.freedesktop.Notifications",
+		"/org/freedesktop/Notifications",
+		TIMEOUT,
+	);
+	let hints: HashMap<&str, Variant<Box<dyn RefArg>>> = HashMap::new();
+	let _: (u32,) = proxy.method_call(
+		"org.freedesktop.Notifications",
+		"Notify",
+		(
+			"vived",
+			0u32,
+			"input-gaming",
+			summary,
+			body,
+			Vec::<&str>::new(),
+			hints,
+			-1i32,
+		),
+	)?;
+	Ok(())
+}
+
+/// Shows notification for every important event, until daemon exits
+pub fn run(events: Receiver<Event>) -> Result<()> {
+	let conn = Connection::new_session()?;
+	for event in events {
+		let Some((summary, body)) = message(&event) else {
+			continue;
+		};
+		if let Err(e) = notify(&conn, summary, &body) {
+			warn!("failed to show notification: {e}");
+		}
+	}
+	Ok(())
+}
@@ -0,0 +1,157 @@
+//! Switches default PipeWire sink/source to headset while it is worn,
+//! and restores previous ones when it is removed
+//!
+//! Only needs `pw-dump` and `pw-metadata` tools, headset audio nodes are matched by USB topology
+use std::{
+	path::{Path, PathBuf},
+	process::Command,
+	sync::{mpsc::Receiver, Arc},
+};
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Map, Value};
+use tracing::{info, warn};
+use vive_ipc::Event;
+
+use crate::daemon::Daemon;
+
+const SINK_KEY: &str = "default.configured.audio.sink";
+const SOURCE_KEY: &str = "default.configured.audio.source";
+
+fn dump() -> Result<Vec<Value>> {
+	let out = Command::new("pw-dump")
+		.output()
+		.context("failed to run pw-dump")?;
+	if !out.status.success() {
+		bail!("pw-dump failed: {}", out.status);
+	}
+	Ok(serde_json::from_slice(&out.stdout)?)
+}
+
+fn props(object: &Value) -> Option<&Map<String, Value>> {
+	object["info"]["props"].as_object()
+}
+fn of_type<'a>(objects: &'a [Value], ty: &'a str) -> impl Iterator<Item = &'a Value> {
+	objects.iter().filter(move |o| o["type"] == ty)
+}
+
+/// Sink and source node names, of devices under headset hub
+fn headset_nodes(objects: &[Value], hub: &Path) -> (Option<String>, Option<String>) {
+	let devices = of_type(objects, "PipeWire:Interface:Device")
+		.filter(|o| {
+			let sysfs = props(o).and_then(|p| p.get("sysfs.path")?.as_str());
+			sysfs.map(|p| {
+				Path::new("/sys")
+					.join(p.trim_start_matches('/'))
+					.starts_with(hub)
+			}) == Some(true)
+		})
+		.filter_map(|o| o["id"].as_u64())
+		.collect::<Vec<_>>();
+	let (mut sink, mut source) = (None, None);
+	for node in of_type(objects, "PipeWire:Interface:Node") {
+		let Some(props) = props(node) else {
+			continue;
+		};
+		let device = props.get("device.id").and_then(Value::as_u64);
+		if device.map(|d| devices.contains(&d)) != Some(true) {
+			continue;
+		}
+		let name = props
+			.get("node.name")
+			.and_then(Value::as_str)
+			.map(str::to_owned);
+		match props.get("media.class").and_then(Value::as_str) {
+			Some("Audio/Sink") => sink = sink.or(name),
+			Some("Audio/Source") => source = source.or(name),
+			_ => {}
+		}
+	}
+	(sink, source)
+}
+
+fn default_node(objects: &[Value], key: &str) -> Option<String> {
+	let metadata = of_type(objects, "PipeWire:Interface:Metadata")
+		.find(|o| props(o).and_then(|p| p.get("metadata.name")) == Some(&json!("default")))?;
+	let value = &metadata["metadata"]
+		.as_array()?
+		.iter()
+		.find(|e| e["key"] == key)?["value"];
+	// Older versions of pw-dump print JSON values as strings
+	let value = match value {
+		Value::String(s) => serde_json::from_str(s).ok()?,
+		v => v.clone(),
+	};
+	value["name"].as_str().map(str::to_owned)
+}
+
+/// Previous default is removed, if `name` is `None`
+fn set_default(key: &str, name: Option<&str>) -> Result<()> {
+	let mut cmd = Command::new("pw-metadata");
+	cmd.args(["-n", "default"]);
+	match name {
+		Some(name) => cmd.args(["0", key, &json!({ "name": name }).to_string()]),
+		None => cmd.args(["-d", "0", key]),
+	};
+	let status = cmd.status().context("failed to run pw-metadata")?;
+	if !status.success() {
+		bail!("pw-metadata failed: {status}");
+	}
+	Ok(())
+}
+
+/// Returns previous defaults of switched keys
+fn switch(hub: &Path) -> Result<Vec<(&'static str, Option<String>)>> {
+	let objects = dump()?;
+	let (sink, source) = headset_nodes(&objects, hub);
+	if sink.is_none() && source.is_none() {
+		bail!("no headset audio nodes found under {hub:?}");
+	}
+	let mut previous = Vec::new();
+	for (key, name) in [(SINK_KEY, sink), (SOURCE_KEY, source)] {
+		let Some(name) = name else {
+			continue;
+		};
+		info!("switching {key} to {name}");
+		previous.push((key, default_node(&objects, key)));
+		set_default(key, Some(&name))?;
+	}
+	Ok(previous)
+}
+
+fn restore(previous: Vec<(&'static str, Option<String>)>) {
+	for (key, name) in previous {
+		info!("restoring {key}");
+		if let Err(e) = set_default(key, name.as_deref()) {
+			warn!("failed to restore {key}: {e:#}");
+		}
+	}
+}
+
+fn hub(daemon: &Daemon) -> Option<PathBuf> {
+	Some(daemon.usb_path()?.parent()?.to_owned())
+}
+
+/// Follows headset proximity until daemon exits
+pub fn run(daemon: Arc<Daemon>, events: Receiver<Event>) {
+	let mut previous = None;
+	for event in events {
+		match event {
+			Event::Proximity { near: true } if previous.is_none() => {
+				let Some(hub) = hub(&daemon) else {
+					continue;
+				};
+				match switch(&hub) {
+					Ok(p) => previous = Some(p),
+					Err(e) => warn!("failed to switch audio to headset: {e:#}"),
+				}
+			}
+			Event::Proximity { near: false } | Event::Disconnected => {
+				if let Some(previous) = previous.take() {
+					restore(previous);
+				}
+			}
+			_ => {}
+		}
+	}
+}
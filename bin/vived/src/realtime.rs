@@ -0,0 +1,83 @@
+//! SCHED_FIFO for threads, which talk to headset and base stations, so they aren't delayed by
+//! compositor and game load
+//!
+//! Directly if process is allowed to (`CAP_SYS_NICE`, or `RLIMIT_RTPRIO` from limits.conf),
+//! otherwise thread is handed to rtkit, which lets desktop sessions have it without privileges
+use std::{
+	io,
+	sync::atomic::{AtomicBool, Ordering},
+	time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use dbus::blocking::{stdintf::org_freedesktop_dbus::Properties, Connection};
+use tracing::{debug, info, warn};
+
+const RTKIT: &str = "org.freedesktop.RealtimeKit1";
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// So a runtime with thread per core doesn't log for each of them, outcome is the same for all
+static REPORTED: AtomicBool = AtomicBool::new(false);
+
+fn set_fifo(priority: u32) -> io::Result<()> {
+	let param = libc::sched_param {
+		sched_priority: priority as i32,
+	};
+	// Pid 0 is calling thread, hooks spawned from it shouldn't inherit realtime
+	if unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO | libc::SCHED_RESET_ON_FORK, &param) }
+		< 0
+	{
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+fn rtkit(priority: u32) -> Result<u32> {
+	let conn = Connection::new_system()?;
+	let proxy = conn.with_proxy(RTKIT, "/org/freedesktop/RealtimeKit1", TIMEOUT);
+	let max: i32 = proxy.get(RTKIT, "MaxRealtimePriority")?;
+	if max < 1 {
+		bail!("rtkit doesn't allow realtime priority");
+	}
+	let priority = priority.min(max as u32);
+	// Refused unless the process limits its realtime CPU time to what rtkit allows, thread gets
+	// SIGXCPU once it spins for longer than that without blocking
+	let rttime: i64 = proxy.get(RTKIT, "RTTimeUSecMax")?;
+	let limit = libc::rlimit {
+		rlim_cur: rttime as libc::rlim_t,
+		rlim_max: rttime as libc::rlim_t,
+	};
+	if unsafe { libc::setrlimit(libc::RLIMIT_RTTIME, &limit) } < 0 {
+		return Err(io::Error::last_os_error()).context("failed to limit realtime cpu time");
+	}
+	let tid = unsafe { libc::gettid() } as u64;
+	proxy.method_call::<(), _, _, _>(RTKIT, "MakeThreadRealtime", (tid, priority))?;
+	Ok(priority)
+}
+
+/// Makes calling thread realtime
+pub fn make_realtime(priority: u32) -> Result<u32> {
+	match set_fifo(priority) {
+		Ok(()) => Ok(priority),
+		Err(e) if e.raw_os_error() == Some(libc::EPERM) => {
+			debug!("no permission for SCHED_FIFO, asking rtkit");
+			rtkit(priority).context("rtkit failed")
+		}
+		Err(e) => Err(e).context("failed to set SCHED_FIFO"),
+	}
+}
+
+/// [`make_realtime`], if configured, failure only warns, as thread works the same without it
+pub fn enter(priority: Option<u32>, name: &str) {
+	let Some(priority) = priority else {
+		return;
+	};
+	let res = make_realtime(priority);
+	match res {
+		_ if REPORTED.swap(true, Ordering::Relaxed) => {
+			debug!("{name} thread realtime: {res:?}")
+		}
+		Ok(priority) => info!("{name} thread is realtime, priority {priority}"),
+		Err(e) => warn!("failed to make {name} thread realtime: {e:#}"),
+	}
+}
@@ -0,0 +1,95 @@
+use std::{
+	env, fs,
+	io::{BufReader, ErrorKind},
+	os::{
+		fd::{FromRawFd, RawFd},
+		unix::net::{UnixListener, UnixStream},
+	},
+	path::PathBuf,
+	process,
+	sync::Arc,
+	thread,
+};
+
+use anyhow::{bail, Context, Result};
+use tracing::{info, warn};
+use vive_ipc::{read_message, socket_path, write_message, Error, Request, Response};
+
+use crate::daemon::Daemon;
+
+/// First fd passed by systemd, see sd_listen_fds(3)
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// Socket passed by systemd, if daemon was started by socket unit
+fn activated_listener() -> Option<UnixListener> {
+	let pid = env::var("LISTEN_PID").ok()?.parse::<u32>().ok()?;
+	let fds = env::var("LISTEN_FDS").ok()?.parse::<u32>().ok()?;
+	// Variables are inherited by children otherwise
+	env::remove_var("LISTEN_PID");
+	env::remove_var("LISTEN_FDS");
+	env::remove_var("LISTEN_FDNAMES");
+	if pid != process::id() || fds == 0 {
+		return None;
+	}
+	if fds > 1 {
+		warn!("only first of {fds} passed sockets is used");
+	}
+	unsafe { libc::fcntl(SD_LISTEN_FDS_START, libc::F_SETFD, libc::FD_CLOEXEC) };
+	Some(unsafe { UnixListener::from_raw_fd(SD_LISTEN_FDS_START) })
+}
+
+/// Returns listener, and socket path, which should be removed on exit, if daemon created it
+pub fn listen() -> Result<(UnixListener, Option<PathBuf>)> {
+	if let Some(listener) = activated_listener() {
+		info!("using socket passed by systemd");
+		return Ok((listener, None));
+	}
+	let path = socket_path();
+	match UnixStream::connect(&path) {
+		Ok(_) => bail!("another instance is already listening on {path:?}"),
+		// Left after crash
+		Err(e) if e.kind() == ErrorKind::ConnectionRefused => fs::remove_file(&path)?,
+		Err(_) => {}
+	}
+	let listener = UnixListener::bind(&path).with_context(|| format!("failed to bind {path:?}"))?;
+	info!("listening on {path:?}");
+	Ok((listener, Some(path)))
+}
+
+fn handle_client(daemon: &Daemon, stream: UnixStream) -> Result<(), Error> {
+	let mut read = BufReader::new(stream.try_clone()?);
+	let mut write = stream;
+	loop {
+		let response = match read_message::<Request>(&mut read) {
+			Ok(Request::Subscribe) => {
+				let events = daemon.subscribe();
+				write_message(&mut write, &Response::Ok)?;
+				for event in events {
+					write_message(&mut write, &event)?;
+				}
+				return Ok(());
+			}
+			Ok(request) => daemon.handle(request),
+			Err(Error::Closed) => return Ok(()),
+			// Line is consumed, client may send next one
+			Err(Error::Json(e)) => Response::Error {
+				message: format!("malformed request: {e}"),
+			},
+			Err(e) => return Err(e),
+		};
+		write_message(&mut write, &response)?;
+	}
+}
+
+pub fn serve(listener: UnixListener, daemon: Arc<Daemon>) -> Result<()> {
+	for stream in listener.incoming() {
+		let stream = stream?;
+		let daemon = daemon.clone();
+		thread::spawn(move || {
+			if let Err(e) = handle_client(&daemon, stream) {
+				warn!("client failed: {e}");
+			}
+		});
+	}
+	Ok(())
+}
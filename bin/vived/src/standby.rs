@@ -0,0 +1,100 @@
+//! Standby policies: blanking display after headset is taken off, and powering down after
+//! SteamVR exit, the same way Vive Console does. Both wake up when headset is put back on
+use std::{
+	sync::{
+		mpsc::{Receiver, RecvTimeoutError},
+		Arc,
+	},
+	time::{Duration, Instant},
+};
+
+use tracing::warn;
+use vive_ipc::Event;
+
+use crate::{
+	config::{PowerDown, Standby},
+	daemon::Daemon,
+};
+
+fn recv(events: &Receiver<Event>, deadline: Option<Instant>) -> Result<Event, RecvTimeoutError> {
+	match deadline {
+		Some(at) => events.recv_timeout(at.saturating_duration_since(Instant::now())),
+		None => events.recv().map_err(|_| RecvTimeoutError::Disconnected),
+	}
+}
+
+fn wake(daemon: &Daemon) {
+	if let Err(e) = daemon.set_standby(false, false) {
+		warn!("failed to leave standby: {e:#}");
+	}
+}
+
+/// Applies proximity policy until daemon exits
+pub fn run(daemon: Arc<Daemon>, config: Standby, events: Receiver<Event>) {
+	let delay = Duration::from_secs(config.delay);
+	// Moment, when headset should enter standby, if it is still not worn
+	let mut deadline: Option<Instant> = None;
+	loop {
+		match recv(&events, deadline) {
+			Ok(Event::Proximity { near: false }) => {
+				deadline.get_or_insert_with(|| Instant::now() + delay);
+			}
+			Ok(Event::Proximity { near: true }) => {
+				deadline = None;
+				wake(&daemon);
+			}
+			// Standby state survives reconnect, new proximity event will be received after it
+			Ok(Event::Disconnected) => deadline = None,
+			Ok(_) => {}
+			Err(RecvTimeoutError::Timeout) => {
+				deadline = None;
+				if let Err(e) = daemon.set_standby(true, config.lower_mode) {
+					warn!("failed to enter standby: {e:#}");
+				}
+			}
+			Err(RecvTimeoutError::Disconnected) => return,
+		}
+	}
+}
+
+/// Applies SteamVR exit policy until daemon exits
+pub fn run_power_down(daemon: Arc<Daemon>, config: PowerDown, events: Receiver<Event>) {
+	let mut display_at: Option<Instant> = None;
+	let mut base_stations_at: Option<Instant> = None;
+	loop {
+		let deadline = display_at.into_iter().chain(base_stations_at).min();
+		match recv(&events, deadline) {
+			Ok(Event::Session { running: false }) => {
+				let after =
+					|delay: Option<u64>| delay.map(|d| Instant::now() + Duration::from_secs(d));
+				display_at = after(config.display);
+				base_stations_at = after(config.base_stations);
+			}
+			Ok(Event::Session { running: true }) => {
+				display_at = None;
+				base_stations_at = None;
+				wake(&daemon);
+				daemon.set_keepalive(true);
+			}
+			Ok(Event::Proximity { near: true }) => {
+				display_at = None;
+				wake(&daemon);
+			}
+			Ok(_) => {}
+			Err(RecvTimeoutError::Timeout) => {
+				let now = Instant::now();
+				if display_at.map(|at| at <= now) == Some(true) {
+					display_at = None;
+					if let Err(e) = daemon.set_standby(true, false) {
+						warn!("failed to enter standby: {e:#}");
+					}
+				}
+				if base_stations_at.map(|at| at <= now) == Some(true) {
+					base_stations_at = None;
+					daemon.set_keepalive(false);
+				}
+			}
+			Err(RecvTimeoutError::Disconnected) => return,
+		}
+	}
+}
@@ -0,0 +1,357 @@
+//! Event stream over WebSocket (RFC 6455), for live dashboards and OBS overlays
+//!
+//! Every event is sent as text frame, containing the same JSON as [`vive_ipc::Event`],
+//! messages from client are ignored
+use std::{
+	io::{self, BufRead, Read, Write},
+	net::{Shutdown, TcpStream},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		mpsc::RecvTimeoutError,
+		Arc,
+	},
+	thread,
+	time::Duration,
+};
+
+use serde_json::json;
+
+use crate::{
+	daemon::Daemon,
+	http::{write_response, HttpRequest},
+};
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// How often sender checks for closed connection, while there is no events
+const CLOSE_POLL: Duration = Duration::from_secs(1);
+const MAX_FRAME_SIZE: u64 = 64 * 1024;
+
+const OP_TEXT: u8 = 0x1;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+/// Only used for handshake, not worth a dependency
+fn sha1(data: &[u8]) -> [u8; 20] {
+	let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+	let mut msg = data.to_vec();
+	msg.push(0x80);
+	while msg.len() % 64 != 56 {
+		msg.push(0);
+	}
+	msg.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+	for chunk in msg.chunks(64) {
+		let mut w = [0u32; 80];
+		for (i, word) in chunk.chunks(4).enumerate() {
+			w[i] = u32::from_be_bytes(word.try_into().expect("chunk of 4"));
+		}
+		for i in 16..80 {
+			w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+		}
+		let [mut a, mut b, mut c, mut d, mut e] = h;
+		for (i, w) in w.iter().enumerate() {
+			let (f, k) = match i {
+				0..=19 => ((b & c) | (!b & d), 0x5a827999),
+				20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+				40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+				_ => (b ^ c ^ d, 0xca62c1d6),
+			};
+			let temp = a
+				.rotate_left(5)
+				.wrapping_add(f)
+				.wrapping_add(e)
+				.wrapping_add(k)
+				.wrapping_add(*w);
+			e = d;
+			d = c;
+			c = b.rotate_left(30);
+			b = a;
+			a = temp;
+		}
+		for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+			*h = h.wrapping_add(v);
+		}
+	}
+	let mut out = [0; 20];
+	for (out, h) in out.chunks_mut(4).zip(h) {
+		out.copy_from_slice(&h.to_be_bytes());
+	}
+	out
+}
+
+fn base64(data: &[u8]) -> String {
+	const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+	let mut out = String::new();
+	for chunk in data.chunks(3) {
+		let b = [
+			chunk[0],
+			*chunk.get(1).unwrap_or(&0),
+			*chunk.get(2).unwrap_or(&0),
+		];
+		let n = u32::from_be_bytes([0, b[0], b[1], b[2]]);
+		for i in 0..4 {
+			if i <= chunk.len() {
+				out.push(ALPHABET[(n >> (18 - i * 6)) as usize & 0x3f] as char);
+			} else {
+				out.push('=');
+			}
+		}
+	}
+	out
+}
+
+fn accept_key(key: &str) -> String {
+	base64(&sha1(format!("{key}{ACCEPT_GUID}").as_bytes()))
+}
+
+fn write_frame(mut write: impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+	let mut frame = vec![0x80 | opcode];
+	match payload.len() {
+		len @ 0..=125 => frame.push(len as u8),
+		len @ 126..=0xffff => {
+			frame.push(126);
+			frame.extend_from_slice(&(len as u16).to_be_bytes());
+		}
+		len => {
+			frame.push(127);
+			frame.extend_from_slice(&(len as u64).to_be_bytes());
+		}
+	}
+	frame.extend_from_slice(payload);
+	write.write_all(&frame)?;
+	write.flush()
+}
+
+/// Returns opcode and unmasked payload
+fn read_frame(read: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+	let mut head = [0; 2];
+	read.read_exact(&mut head)?;
+	let opcode = head[0] & 0x0f;
+	let masked = head[1] & 0x80 != 0;
+	let len = match head[1] & 0x7f {
+		126 => {
+			let mut len = [0; 2];
+			read.read_exact(&mut len)?;
+			u16::from_be_bytes(len) as u64
+		}
+		127 => {
+			let mut len = [0; 8];
+			read.read_exact(&mut len)?;
+			u64::from_be_bytes(len)
+		}
+		len => len as u64,
+	};
+	if len > MAX_FRAME_SIZE {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidData,
+			"frame is too large",
+		));
+	}
+	let mut mask = [0; 4];
+	if masked {
+		read.read_exact(&mut mask)?;
+	}
+	let mut payload = vec![0; len as usize];
+	read.read_exact(&mut payload)?;
+	for (i, b) in payload.iter_mut().enumerate() {
+		*b ^= mask[i % 4];
+	}
+	Ok((opcode, payload))
+}
+
+pub fn is_upgrade(req: &HttpRequest) -> bool {
+	req.header("upgrade")
+		.map(|v| v.eq_ignore_ascii_case("websocket"))
+		== Some(true)
+}
+
+/// Takes over connection after upgrade request, until client disconnects
+pub fn serve(
+	daemon: &Daemon,
+	req: &HttpRequest,
+	mut read: impl BufRead + Send + 'static,
+	stream: TcpStream,
+) -> io::Result<()> {
+	let Some(key) = req.header("sec-websocket-key") else {
+		return write_response(
+			&mut &stream,
+			400,
+			&json!({ "error": "missing sec-websocket-key" }),
+		);
+	};
+	write!(
+		&stream,
+		"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+		accept_key(key)
+	)?;
+	// Subscribed after handshake, so nothing is queued for failed connections
+	let events = daemon.subscribe();
+	// Listening clients don't have to send anything, dead ones are noticed once event write
+	// fails or times out, which also stops reader
+	stream.set_read_timeout(None)?;
+
+	let closed = Arc::new(AtomicBool::new(false));
+	{
+		let closed = closed.clone();
+		let stream = stream.try_clone()?;
+		thread::spawn(move || {
+			while let Ok((opcode, payload)) = read_frame(&mut read) {
+				match opcode {
+					OP_PING => {
+						if write_frame(&stream, OP_PONG, &payload).is_err() {
+							break;
+						}
+					}
+					OP_CLOSE => {
+						let _ = write_frame(&stream, OP_CLOSE, &payload);
+						break;
+					}
+					_ => {}
+				}
+			}
+			closed.store(true, Ordering::Relaxed);
+			let _ = stream.shutdown(Shutdown::Both);
+		});
+	}
+
+	while !closed.load(Ordering::Relaxed) {
+		match events.recv_timeout(CLOSE_POLL) {
+			Ok(event) => {
+				let payload = serde_json::to_vec(&event).expect("serializable");
+				write_frame(&stream, OP_TEXT, &payload)?;
+			}
+			Err(RecvTimeoutError::Timeout) => {}
+			Err(RecvTimeoutError::Disconnected) => break,
+		}
+	}
+	let _ = stream.shutdown(Shutdown::Both);
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		io::{BufReader, Cursor},
+		net::TcpListener,
+	};
+
+	use super::*;
+	use crate::config::Config;
+
+	/// RFC 6455 section 5.7, single-frame masked text message
+	const MASKED_HELLO: [u8; 11] = [
+		0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+	];
+
+	fn mask(opcode: u8, payload: &[u8], key: [u8; 4]) -> Vec<u8> {
+		let mut frame = vec![0x80 | opcode];
+		match payload.len() {
+			len @ 0..=125 => frame.push(0x80 | len as u8),
+			len => {
+				frame.push(0x80 | 126);
+				frame.extend_from_slice(&(len as u16).to_be_bytes());
+			}
+		}
+		frame.extend_from_slice(&key);
+		frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ key[i % 4]));
+		frame
+	}
+
+	#[test]
+	fn accept_key_of_rfc_example() {
+		assert_eq!(
+			accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+			"s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+		);
+		assert_eq!(base64(b"ab"), "YWI=");
+		assert_eq!(base64(b"a"), "YQ==");
+	}
+
+	#[test]
+	fn masked_frames() {
+		let (opcode, payload) = read_frame(&mut Cursor::new(MASKED_HELLO)).unwrap();
+		assert_eq!((opcode, payload.as_slice()), (OP_TEXT, &b"Hello"[..]));
+
+		// 16 bit length, mask offset continues over 4 byte boundary
+		let long = (0..300).map(|i| i as u8).collect::<Vec<_>>();
+		let frame = mask(OP_PING, &long, [1, 2, 3, 4]);
+		assert_eq!(
+			read_frame(&mut Cursor::new(frame)).unwrap(),
+			(OP_PING, long)
+		);
+
+		let mut oversize = vec![0x82, 0xff];
+		oversize.extend_from_slice(&(MAX_FRAME_SIZE + 1).to_be_bytes());
+		let e = read_frame(&mut Cursor::new(oversize)).unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+		// Cut in the middle of payload
+		assert!(read_frame(&mut Cursor::new(&MASKED_HELLO[..8])).is_err());
+	}
+
+	#[test]
+	fn unmasked_server_frames() {
+		let mut out = Vec::new();
+		write_frame(&mut out, OP_TEXT, b"{}").unwrap();
+		assert_eq!(out, [0x81, 2, b'{', b'}']);
+		let mut out = Vec::new();
+		write_frame(&mut out, OP_TEXT, &[0; 200]).unwrap();
+		assert_eq!(out[..4], [0x81, 126, 0, 200]);
+		assert_eq!(
+			read_frame(&mut Cursor::new(out)).unwrap(),
+			(OP_TEXT, vec![0; 200])
+		);
+	}
+
+	#[test]
+	fn handshake_ping_and_close() {
+		let config_path =
+			std::env::temp_dir().join(format!("vived-ws-{}.toml", std::process::id()));
+		let daemon = Arc::new(Daemon::new(config_path, Config::default(), None));
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap();
+		let server = thread::spawn(move || {
+			let (stream, _) = listener.accept().unwrap();
+			let req = HttpRequest {
+				method: "GET".to_owned(),
+				path: "/events".to_owned(),
+				query: String::new(),
+				headers: vec![
+					("Upgrade".to_owned(), "websocket".to_owned()),
+					(
+						"Sec-WebSocket-Key".to_owned(),
+						"dGhlIHNhbXBsZSBub25jZQ==".to_owned(),
+					),
+				],
+				body: Vec::new(),
+			};
+			assert!(is_upgrade(&req));
+			let read = BufReader::new(stream.try_clone().unwrap());
+			serve(&daemon, &req, read, stream)
+		});
+
+		let mut client = TcpStream::connect(addr).unwrap();
+		client
+			.set_read_timeout(Some(Duration::from_secs(5)))
+			.unwrap();
+		let mut read = BufReader::new(client.try_clone().unwrap());
+		let mut response = String::new();
+		while !response.ends_with("\r\n\r\n") {
+			assert_ne!(read.read_line(&mut response).unwrap(), 0, "{response:?}");
+		}
+		assert!(response.starts_with("HTTP/1.1 101 "), "{response:?}");
+		assert!(
+			response.contains("\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"),
+			"{response:?}"
+		);
+
+		client
+			.write_all(&mask(OP_PING, b"ping", [0xde, 0xad, 0xbe, 0xef]))
+			.unwrap();
+		assert_eq!(read_frame(&mut read).unwrap(), (OP_PONG, b"ping".to_vec()));
+		client
+			.write_all(&mask(OP_CLOSE, &[0x03, 0xe8], [9, 8, 7, 6]))
+			.unwrap();
+		assert_eq!(read_frame(&mut read).unwrap(), (OP_CLOSE, vec![0x03, 0xe8]));
+		server.join().unwrap().unwrap();
+	}
+}
@@ -0,0 +1,277 @@
+//! Lens server takes seconds to start under wine, while its answers only depend on lens config.
+//! Everything driver asks for is stored on disk, and next sessions with the same headset, mode
+//! and IPD get it from there, without starting lens server at all. IPD change mid-session
+//! switches to the entry of the new IPD
+//!
+//! Cache lives in `$XDG_CACHE_HOME/vivepro2/distortion`, and can be removed at any time
+use std::{
+	cell::{Cell, OnceCell, RefCell},
+	env, fs, io,
+	path::{Path, PathBuf},
+	rc::Rc,
+	result,
+};
+
+use lens_protocol::{DistortOutput, Error, Eye, LeftRightTopBottom, LensClient};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, warn};
+
+type Result<T> = result::Result<T, Error>;
+
+/// Bumped when stored data changes its meaning
+const FORMAT_VERSION: u32 = 1;
+/// IPD is rounded to this, in mm
+const IPD_BUCKET_MM: f32 = 1.0;
+
+/// What distortion depends on, stored results are only reused when all of it matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct CacheKey {
+	/// CRC32 of lens config
+	pub config: u32,
+	pub mode: u8,
+	/// 0 when IPD is not known
+	pub ipd_bucket: u16,
+}
+impl CacheKey {
+	pub fn new(config: &Value, mode: u8, ipd_meters: Option<f32>) -> Self {
+		Self {
+			config: crc32fast::hash(config.to_string().as_bytes()),
+			mode,
+			ipd_bucket: ipd_meters.map(ipd_bucket).unwrap_or_default(),
+		}
+	}
+	fn path(&self) -> Option<PathBuf> {
+		let base = match env::var_os("XDG_CACHE_HOME") {
+			Some(dir) => PathBuf::from(dir),
+			None => Path::new(&env::var_os("HOME")?).join(".cache"),
+		};
+		Some(base.join("vivepro2/distortion").join(format!(
+			"{:08x}-{}-{}.bin",
+			self.config, self.mode, self.ipd_bucket
+		)))
+	}
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Entry {
+	version: u32,
+	key: CacheKey,
+	projection: [Option<LeftRightTopBottom>; 2],
+	matrix_needs_inversion: Option<bool>,
+	/// By CRC32 of requested points, see [`points_hash`]
+	batches: Vec<(u32, Vec<DistortOutput>)>,
+}
+impl Entry {
+	fn empty(key: CacheKey) -> Self {
+		Self {
+			version: FORMAT_VERSION,
+			key,
+			..Self::default()
+		}
+	}
+	fn load(key: CacheKey, path: &Path) -> io::Result<Option<Self>> {
+		let data = match fs::read(path) {
+			Ok(data) => data,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+			Err(e) => return Err(e),
+		};
+		let entry: Self = postcard::from_bytes(&data)
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+		Ok(Some(entry).filter(|e| e.version == FORMAT_VERSION && e.key == key))
+	}
+	fn store(&self, path: &Path) -> io::Result<()> {
+		let data =
+			postcard::to_stdvec(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		// Other session may be reading it
+		let tmp = path.with_extension("tmp");
+		fs::write(&tmp, data)?;
+		fs::rename(tmp, path)
+	}
+}
+
+fn ipd_bucket(ipd_meters: f32) -> u16 {
+	(ipd_meters * 1000.0 / IPD_BUCKET_MM).round() as u16
+}
+
+fn points_hash(points: &[(Eye, [f32; 2])]) -> u32 {
+	let mut hasher = crc32fast::Hasher::new();
+	for &(eye, [u, v]) in points {
+		hasher.update(&[eye as u8]);
+		hasher.update(&u.to_le_bytes());
+		hasher.update(&v.to_le_bytes());
+	}
+	hasher.finalize()
+}
+
+type Start = Box<dyn FnOnce() -> crate::Result<Rc<dyn LensClient>>>;
+type Fallback = Box<dyn FnOnce(crate::Error) -> Rc<dyn LensClient>>;
+
+struct Backend {
+	client: Rc<dyn LensClient>,
+	/// Fallback output is not worth keeping
+	store: bool,
+}
+
+/// Answers from cache, lens server is only started on first miss
+pub struct CachedClient {
+	path: RefCell<Option<PathBuf>>,
+	entry: RefCell<Entry>,
+	start: Cell<Option<(Start, Fallback)>>,
+	backend: OnceCell<Backend>,
+}
+impl CachedClient {
+	/// `fallback` is used when `start` fails, its results are not stored
+	pub fn new(
+		key: CacheKey,
+		start: impl FnOnce() -> crate::Result<Rc<dyn LensClient>> + 'static,
+		fallback: impl FnOnce(crate::Error) -> Rc<dyn LensClient> + 'static,
+	) -> Self {
+		let (path, entry) = Self::load(key);
+		Self {
+			path: RefCell::new(path),
+			entry: RefCell::new(entry),
+			start: Cell::new(Some((Box::new(start), Box::new(fallback)))),
+			backend: OnceCell::new(),
+		}
+	}
+
+	fn load(key: CacheKey) -> (Option<PathBuf>, Entry) {
+		let path = key.path();
+		let entry = match path.as_deref().map(|path| Entry::load(key, path)) {
+			Some(Ok(Some(entry))) => {
+				info!(
+					"using cached lens distortion from {}",
+					path.as_ref().expect("loaded").display()
+				);
+				entry
+			}
+			Some(Err(e)) => {
+				warn!("failed to read lens distortion cache: {e}");
+				Entry::empty(key)
+			}
+			_ => Entry::empty(key),
+		};
+		(path, entry)
+	}
+
+	fn backend(&self) -> &Backend {
+		self.backend.get_or_init(|| {
+			info!("lens distortion is not cached, starting lens server");
+			let (start, fallback) = self.start.take().expect("backend is started once");
+			match start() {
+				Ok(client) => Backend {
+					client,
+					store: true,
+				},
+				Err(e) => Backend {
+					client: fallback(e),
+					store: false,
+				},
+			}
+		})
+	}
+	/// Asks backend on miss, and stores its answer
+	fn cached<T: Clone>(
+		&self,
+		get: impl FnOnce(&Entry) -> Option<T>,
+		request: impl FnOnce(&dyn LensClient) -> Result<T>,
+		put: impl FnOnce(&mut Entry, T),
+	) -> Result<T> {
+		if let Some(v) = get(&self.entry.borrow()) {
+			return Ok(v);
+		}
+		let backend = self.backend();
+		let v = request(&*backend.client)?;
+		if backend.store {
+			put(&mut self.entry.borrow_mut(), v.clone());
+			if let Some(path) = &*self.path.borrow() {
+				if let Err(e) = self.entry.borrow().store(path) {
+					warn!("failed to store lens distortion cache: {e}");
+				}
+			}
+		}
+		Ok(v)
+	}
+}
+impl LensClient for CachedClient {
+	fn ping(&self, v: u32) -> Result<u32> {
+		match self.backend.get() {
+			Some(backend) => backend.client.ping(v),
+			None => Ok(v),
+		}
+	}
+
+	fn project(&self, eye: Eye) -> Result<LeftRightTopBottom> {
+		self.cached(
+			|e| e.projection[eye as usize],
+			|c| c.project(eye),
+			|e, v| e.projection[eye as usize] = Some(v),
+		)
+	}
+
+	fn matrix_needs_inversion(&self) -> Result<bool> {
+		self.cached(
+			|e| e.matrix_needs_inversion,
+			|c| c.matrix_needs_inversion(),
+			|e, v| e.matrix_needs_inversion = Some(v),
+		)
+	}
+
+	/// Single points are only requested when batch has failed, they aren't cached
+	fn distort(&self, eye: Eye, uv: [f32; 2]) -> Result<DistortOutput> {
+		self.backend().client.distort(eye, uv)
+	}
+	fn distort_batch(&self, points: &[(Eye, [f32; 2])]) -> Result<Vec<DistortOutput>> {
+		let hash = points_hash(points);
+		self.cached(
+			|e| {
+				e.batches
+					.iter()
+					.find(|(h, out)| *h == hash && out.len() == points.len())
+					.map(|(_, out)| out.clone())
+			},
+			|c| c.distort_batch(points),
+			|e, v| e.batches.push((hash, v)),
+		)
+	}
+
+	fn set_config(&self, config: Value) -> Result<()> {
+		let key = {
+			let entry = self.entry.borrow();
+			CacheKey {
+				config: CacheKey::new(&config, 0, None).config,
+				..entry.key
+			}
+		};
+		self.backend().client.set_config(config)?;
+		*self.path.borrow_mut() = key.path();
+		*self.entry.borrow_mut() = Entry::empty(key);
+		Ok(())
+	}
+
+	/// Cached entry of new IPD bucket is used if there is one, lens server is asked otherwise
+	fn set_ipd(&self, ipd_meters: f32) -> Result<()> {
+		let key = CacheKey {
+			ipd_bucket: ipd_bucket(ipd_meters),
+			..self.entry.borrow().key
+		};
+		if key == self.entry.borrow().key {
+			return Ok(());
+		}
+		let (path, entry) = Self::load(key);
+		*self.path.borrow_mut() = path;
+		*self.entry.borrow_mut() = entry;
+		Ok(())
+	}
+
+	fn exit(&self) -> Result<()> {
+		match self.backend.get() {
+			Some(backend) => backend.client.exit(),
+			None => Ok(()),
+		}
+	}
+}
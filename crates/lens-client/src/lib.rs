@@ -1,16 +1,28 @@
 use std::{
 	env::{self, vars_os},
 	ffi::{OsStr, OsString},
-	io,
+	fs, io,
 	path::{Path, PathBuf},
 	process::{Command, Stdio},
 	result,
+	time::Duration,
 };
 
-use lens_protocol::ServerClient;
+use lens_protocol::{Address, Negotiated, ServerClient, Timeouts};
 use serde_json::Value;
 use tracing::{info, warn};
 
+mod cache;
+mod proton;
+mod record;
+mod supervisor;
+mod worker;
+pub use cache::{CacheKey, CachedClient};
+pub use proton::{default_proton, find_protons, select_proton, steam_root, Proton};
+pub use record::{serve_replay, Call, Recording, RecordingClient, ReplayClient};
+pub use supervisor::SupervisedClient;
+pub use worker::{AsyncClient, Pending};
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
 	#[error("io error: {0}")]
@@ -27,8 +39,28 @@ pub enum Error {
 
 type Result<T> = result::Result<T, Error>;
 
+/// Proton may be creating its prefix, which takes a while on first start
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(180);
+/// Full distortion batch is answered in well under a second
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Server started elsewhere, with `lens-server.exe --listen <address>`
+pub const ADDRESS_ENV: &str = "LENS_SERVER_ADDRESS";
+
+const TIMEOUTS: Timeouts = Timeouts {
+	handshake: Some(HANDSHAKE_TIMEOUT),
+	request: Some(REQUEST_TIMEOUT),
+};
+
 // TODO: Pass config on startup
 pub fn start_lens_server(config: Value) -> Result<ServerClient> {
+	if let Some(address) = env::var_os(ADDRESS_ENV) {
+		let address: Address = address.to_string_lossy().parse()?;
+		info!("connecting to lens server at {address}");
+		let client = ServerClient::connect_to(&address, config, TIMEOUTS)?;
+		log_negotiated(&client);
+		return Ok(client);
+	}
 	let server = find_server().ok_or(Error::CantFindServer)?;
 	info!("using lens server at {server:?}");
 
@@ -37,60 +69,32 @@ pub fn start_lens_server(config: Value) -> Result<ServerClient> {
 		info!("server is working");
 		Ok(res)
 	} else {
-		'proton: {
-			if let Some(steamvr_path) = env::var_os("STEAM_COMPAT_INSTALL_PATH") {
-				let mut path = PathBuf::from(steamvr_path);
-				// Drop /SteamVR
-				path.pop();
-				let mut name = env::var_os("PROTON_VERSION").map(|v| {
-					let mut full = OsString::from("Proton ");
-					full.push(v.as_os_str());
-					full
-				});
-				if name.is_none() {
-					let readdir = std::fs::read_dir(&path)
-						.expect("parent exists, there should be no other reason to fail");
-					let mut candidates = Vec::new();
-					for item in readdir {
-						let Ok(entry) = item else {
-							continue;
-						};
-						// Proton is ascii name
-						let file_name = entry.file_name();
-						let Some(name) = file_name.to_str() else {
-							continue;
-						};
-						if !name.starts_with("Proton ") {
-							continue;
-						}
-						candidates.push((name.to_owned(), file_name))
-					}
-					// Try to find latest version by semverish comparison
-					candidates.sort_unstable_by(|a, b| a.0.cmp(&b.0));
-					if let Some(version) = candidates.into_iter().last() {
-						name = Some(version.1)
-					}
+		let proton = match env::var_os("PROTON").or_else(|| {
+			env::var_os("PROTON_VERSION").map(|v| {
+				let mut full = OsString::from("Proton ");
+				full.push(v.as_os_str());
+				full
+			})
+		}) {
+			Some(selected) => {
+				let proton = select_proton(&selected);
+				if proton.is_none() {
+					warn!("selected proton {selected:?} is not found");
 				}
-				if let Some(name) = name {
-					path.push(name);
-					path.push("proton");
-					info!("trying {} as proton", path.display());
-					let res = start_lens_server_with(path, true, &server, config.clone());
-					match res {
-						Err(Error::Io(io)) if io.kind() == io::ErrorKind::NotFound => {
-							// Only possible if wine not exists
-							break 'proton;
-						}
-						_ => {}
-					}
-					let res = res?;
-					return Ok(res);
-				} else {
-					warn!("failed to find proton")
+				proton
+			}
+			None => default_proton(),
+		};
+		match proton {
+			Some(proton) => {
+				info!("trying {} as proton", proton.script.display());
+				match start_lens_server_with(&proton.script, true, &server, config.clone()) {
+					// Only possible if proton not exists
+					Err(Error::Io(io)) if io.kind() == io::ErrorKind::NotFound => {}
+					res => return res,
 				}
-				// .unwrap_or_else(|| {
-				// });
 			}
+			None => warn!("failed to find proton"),
 		}
 		for wine in ["wine64", "wine"] {
 			info!("trying {wine} as wine");
@@ -122,6 +126,17 @@ pub fn start_lens_server_with(
 
 	let mut child = Command::new(wine);
 	if is_proton {
+		// Set by Steam for SteamVR, but not when proton was selected outside of it
+		if env::var_os("STEAM_COMPAT_DATA_PATH").is_none() {
+			let data = proton_data_path();
+			fs::create_dir_all(&data)?;
+			child.env("STEAM_COMPAT_DATA_PATH", data);
+		}
+		if env::var_os("STEAM_COMPAT_CLIENT_INSTALL_PATH").is_none() {
+			if let Some(root) = steam_root() {
+				child.env("STEAM_COMPAT_CLIENT_INSTALL_PATH", root);
+			}
+		}
 		child
 			.arg("runinprefix")
 			.arg("start.exe")
@@ -139,7 +154,36 @@ pub fn start_lens_server_with(
 		.stderr(Stdio::inherit());
 	let child = child.spawn()?;
 
-	Ok(ServerClient::open(child, config)?)
+	let client = ServerClient::open(child, config, TIMEOUTS)?;
+	log_negotiated(&client);
+	Ok(client)
+}
+
+fn log_negotiated(client: &ServerClient) {
+	let negotiated = client.negotiated();
+	if negotiated == Negotiated::LEGACY {
+		info!("lens server has no protocol handshake, assuming version 0");
+	} else {
+		info!(
+			"lens server protocol version {}, capabilities {:#x}, batches go through {}",
+			negotiated.version,
+			negotiated.capabilities.0,
+			if client.shared_memory() {
+				"shared memory"
+			} else {
+				"pipe"
+			}
+		);
+	}
+}
+
+/// Prefix of proton, when it is not started by Steam: `$XDG_DATA_HOME/vivepro2/proton`
+fn proton_data_path() -> PathBuf {
+	let base = match env::var_os("XDG_DATA_HOME") {
+		Some(dir) => PathBuf::from(dir),
+		None => Path::new(&env::var_os("HOME").unwrap_or_default()).join(".local/share"),
+	};
+	base.join("vivepro2/proton")
 }
 
 pub fn find_server() -> Option<PathBuf> {
@@ -0,0 +1,124 @@
+//! Proton builds, which lens server may run under: official ones from `steamapps/common`, and
+//! custom ones (Proton-GE and alike) from `compatibilitytools.d`
+use std::{
+	env,
+	ffi::OsStr,
+	fs,
+	path::{Path, PathBuf},
+};
+
+/// Relative to `$HOME`
+const STEAM_ROOTS: &[&str] = &[
+	".local/share/Steam",
+	".steam/root",
+	".var/app/com.valvesoftware.Steam/.local/share/Steam",
+];
+
+#[derive(Debug, Clone)]
+pub struct Proton {
+	pub name: String,
+	/// `proton` script of the build
+	pub script: PathBuf,
+	/// Installed by user into `compatibilitytools.d`
+	pub custom: bool,
+}
+
+fn home() -> PathBuf {
+	PathBuf::from(env::var_os("HOME").unwrap_or_default())
+}
+
+/// Steam installation, which is running SteamVR, or the first existing one
+pub fn steam_root() -> Option<PathBuf> {
+	if let Some(path) = env::var_os("STEAM_COMPAT_CLIENT_INSTALL_PATH") {
+		return Some(PathBuf::from(path));
+	}
+	let home = home();
+	STEAM_ROOTS
+		.iter()
+		.map(|root| home.join(root))
+		.find(|root| root.is_dir())
+}
+
+fn scan(dir: &Path, custom: bool, out: &mut Vec<Proton>) {
+	let Ok(entries) = fs::read_dir(dir) else {
+		return;
+	};
+	for entry in entries.flatten() {
+		let script = entry.path().join("proton");
+		// Proton is ascii name
+		let Some(name) = entry.file_name().to_str().map(str::to_owned) else {
+			continue;
+		};
+		if !script.is_file() || (!custom && !name.starts_with("Proton ")) {
+			continue;
+		}
+		if out.iter().any(|p| p.script == script) {
+			continue;
+		}
+		out.push(Proton {
+			name,
+			script,
+			custom,
+		});
+	}
+}
+
+/// Every found build, official ones first, both groups are sorted by name, so latest version
+/// is the last one of its group
+pub fn find_protons() -> Vec<Proton> {
+	let home = home();
+	let mut roots = steam_root().into_iter().collect::<Vec<_>>();
+	roots.extend(STEAM_ROOTS.iter().map(|root| home.join(root)));
+
+	let mut official = Vec::new();
+	// SteamVR library, which might not be the default one
+	if let Some(steamvr) = env::var_os("STEAM_COMPAT_INSTALL_PATH") {
+		if let Some(common) = Path::new(&steamvr).parent() {
+			scan(common, false, &mut official);
+		}
+	}
+	for root in &roots {
+		scan(&root.join("steamapps/common"), false, &mut official);
+	}
+	let mut custom = Vec::new();
+	for root in &roots {
+		scan(&root.join("compatibilitytools.d"), true, &mut custom);
+	}
+	// Try to find latest version by semverish comparison
+	official.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+	custom.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+	official.extend(custom);
+	official
+}
+
+/// Latest official build, or latest custom one, if there is no official
+pub fn default_proton() -> Option<Proton> {
+	let protons = find_protons();
+	protons
+		.iter()
+		.rfind(|p| !p.custom)
+		.or_else(|| protons.last())
+		.cloned()
+}
+
+/// By path to build directory (or its `proton` script), or by name, where `Proton ` prefix of
+/// official builds may be omitted
+pub fn select_proton(selected: &OsStr) -> Option<Proton> {
+	let path = Path::new(selected);
+	if path.components().count() > 1 {
+		let script = if path.is_dir() {
+			path.join("proton")
+		} else {
+			path.to_owned()
+		};
+		return script.is_file().then(|| Proton {
+			name: path.to_string_lossy().into_owned(),
+			script,
+			custom: true,
+		});
+	}
+	let name = selected.to_str()?;
+	find_protons()
+		.into_iter()
+		.find(|p| p.name == name || p.name.strip_prefix("Proton ") == Some(name))
+}
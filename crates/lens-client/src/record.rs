@@ -0,0 +1,248 @@
+//! Lens server answers, recorded once with the vendor library, and replayed later without wine.
+//! Replay is possible in-process, with [`ReplayClient`], or over the pipe, with [`serve_replay`]
+//! standing in for lens server, so lens protocol is exercised the same way it is with wine
+//!
+//! Points are matched bit-for-bit, anything not recorded fails with [`Error::NotRecorded`]
+use std::{cell::RefCell, fs, io, path::Path, rc::Rc, result};
+
+use lens_protocol::{DistortOutput, Error, Eye, LeftRightTopBottom, LensClient, Request, Server};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+type Result<T> = result::Result<T, Error>;
+
+/// Bumped when stored data changes its meaning
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Call {
+	Project {
+		eye: Eye,
+		out: LeftRightTopBottom,
+	},
+	Distort {
+		eye: Eye,
+		uv: [f32; 2],
+		out: DistortOutput,
+	},
+	DistortBatch {
+		points: Vec<(Eye, [f32; 2])>,
+		out: Vec<DistortOutput>,
+	},
+}
+
+fn same_point(a: (Eye, [f32; 2]), b: (Eye, [f32; 2])) -> bool {
+	a.0 as u32 == b.0 as u32 && a.1.map(f32::to_bits) == b.1.map(f32::to_bits)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+	version: u32,
+	/// Lens config, which was given to lens server, as JSON
+	config: String,
+	pub calls: Vec<Call>,
+}
+impl Recording {
+	pub fn new(config: &Value) -> Self {
+		Self {
+			version: FORMAT_VERSION,
+			config: config.to_string(),
+			calls: Vec::new(),
+		}
+	}
+	pub fn load(path: &Path) -> io::Result<Self> {
+		let recording: Self = postcard::from_bytes(&fs::read(path)?)
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+		if recording.version != FORMAT_VERSION {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("recording format {} is not supported", recording.version),
+			));
+		}
+		Ok(recording)
+	}
+	pub fn store(&self, path: &Path) -> io::Result<()> {
+		let data =
+			postcard::to_stdvec(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+		fs::write(path, data)
+	}
+	pub fn config(&self) -> Value {
+		serde_json::from_str(&self.config).unwrap_or_default()
+	}
+
+	pub fn project(&self, eye: Eye) -> Result<LeftRightTopBottom> {
+		self.calls
+			.iter()
+			.find_map(|call| match call {
+				Call::Project { eye: e, out } if *e as u32 == eye as u32 => Some(*out),
+				_ => None,
+			})
+			.ok_or_else(|| Error::NotRecorded(format!("projection of {eye:?} eye")))
+	}
+	/// Points of recorded batches are used too
+	pub fn distort(&self, eye: Eye, uv: [f32; 2]) -> Result<DistortOutput> {
+		self.calls
+			.iter()
+			.find_map(|call| match call {
+				Call::Distort { eye: e, uv: p, out } if same_point((*e, *p), (eye, uv)) => {
+					Some(*out)
+				}
+				Call::DistortBatch { points, out } => points
+					.iter()
+					.position(|&p| same_point(p, (eye, uv)))
+					.and_then(|i| out.get(i).copied()),
+				_ => None,
+			})
+			.ok_or_else(|| Error::NotRecorded(format!("distortion of {uv:?} for {eye:?} eye")))
+	}
+	pub fn distort_batch(&self, points: &[(Eye, [f32; 2])]) -> Result<Vec<DistortOutput>> {
+		let batch = self.calls.iter().find_map(|call| match call {
+			Call::DistortBatch { points: p, out }
+				if p.len() == points.len()
+					&& p.iter().zip(points).all(|(&a, &b)| same_point(a, b)) =>
+			{
+				Some(out.clone())
+			}
+			_ => None,
+		});
+		match batch {
+			Some(out) => Ok(out),
+			None => points
+				.iter()
+				.map(|&(eye, uv)| self.distort(eye, uv))
+				.collect(),
+		}
+	}
+}
+
+/// Passes everything to `inner`, and keeps its answers
+pub struct RecordingClient {
+	inner: Rc<dyn LensClient>,
+	recording: RefCell<Recording>,
+}
+impl RecordingClient {
+	pub fn new(inner: Rc<dyn LensClient>, config: &Value) -> Self {
+		Self {
+			inner,
+			recording: RefCell::new(Recording::new(config)),
+		}
+	}
+	pub fn recording(&self) -> Recording {
+		self.recording.borrow().clone()
+	}
+	fn record(&self, call: Call) {
+		self.recording.borrow_mut().calls.push(call);
+	}
+}
+impl LensClient for RecordingClient {
+	fn ping(&self, v: u32) -> Result<u32> {
+		self.inner.ping(v)
+	}
+
+	fn project(&self, eye: Eye) -> Result<LeftRightTopBottom> {
+		let out = self.inner.project(eye)?;
+		self.record(Call::Project { eye, out });
+		Ok(out)
+	}
+
+	/// Lens server derives it from left eye projection, replay does the same
+	fn matrix_needs_inversion(&self) -> Result<bool> {
+		let v = self.project(Eye::Left)?;
+		Ok(v.top > v.bottom)
+	}
+
+	fn distort(&self, eye: Eye, uv: [f32; 2]) -> Result<DistortOutput> {
+		let out = self.inner.distort(eye, uv)?;
+		self.record(Call::Distort { eye, uv, out });
+		Ok(out)
+	}
+	fn distort_batch(&self, points: &[(Eye, [f32; 2])]) -> Result<Vec<DistortOutput>> {
+		let out = self.inner.distort_batch(points)?;
+		self.record(Call::DistortBatch {
+			points: points.to_vec(),
+			out: out.clone(),
+		});
+		Ok(out)
+	}
+
+	/// Recording is only valid for a single config
+	fn set_config(&self, config: Value) -> Result<()> {
+		self.inner.set_config(config.clone())?;
+		*self.recording.borrow_mut() = Recording::new(&config);
+		Ok(())
+	}
+
+	fn set_ipd(&self, ipd_meters: f32) -> Result<()> {
+		self.inner.set_ipd(ipd_meters)
+	}
+
+	fn exit(&self) -> Result<()> {
+		self.inner.exit()
+	}
+}
+
+/// Answers from recording, same as lens server would
+pub struct ReplayClient {
+	recording: Recording,
+}
+impl ReplayClient {
+	pub fn new(recording: Recording) -> Self {
+		Self { recording }
+	}
+}
+impl LensClient for ReplayClient {
+	fn ping(&self, v: u32) -> Result<u32> {
+		Ok(v)
+	}
+
+	fn project(&self, eye: Eye) -> Result<LeftRightTopBottom> {
+		self.recording.project(eye)
+	}
+
+	fn matrix_needs_inversion(&self) -> Result<bool> {
+		let v = self.project(Eye::Left)?;
+		Ok(v.top > v.bottom)
+	}
+
+	fn distort(&self, eye: Eye, uv: [f32; 2]) -> Result<DistortOutput> {
+		self.recording.distort(eye, uv)
+	}
+	fn distort_batch(&self, points: &[(Eye, [f32; 2])]) -> Result<Vec<DistortOutput>> {
+		self.recording.distort_batch(points)
+	}
+
+	fn set_config(&self, config: Value) -> Result<()> {
+		if config != self.recording.config() {
+			return Err(Error::NotRecorded("different lens config".to_owned()));
+		}
+		Ok(())
+	}
+
+	fn exit(&self) -> Result<()> {
+		Ok(())
+	}
+}
+
+/// Lens server main loop, with recording in place of the vendor library, on stdin/stdout.
+/// Unrecorded request stops it, same as library error stops lens server
+pub fn serve_replay(recording: &Recording) -> Result<()> {
+	let mut server = Server::listen();
+	loop {
+		match server.recv()? {
+			Request::Init(config) => {
+				if config != recording.config() {
+					warn!("lens config differs from the recorded one");
+				}
+			}
+			Request::Ping(v) => server.send(&v)?,
+			Request::ProjectionRaw(eye) => server.send(&recording.project(eye)?)?,
+			Request::Distort(eye, uv) => server.send(&recording.distort(eye, uv)?)?,
+			Request::DistortBatch(points) => server.send(&recording.distort_batch(&points)?)?,
+			Request::Exit => return Ok(()),
+			Request::Hello(_) | Request::AttachShm(_) | Request::DistortBatchShm(_) => {
+				unreachable!("answered by server")
+			}
+		}
+	}
+}
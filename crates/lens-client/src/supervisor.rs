@@ -0,0 +1,170 @@
+//! Lens server runs under wine, which may crash mid-session. Crashed server is restarted, and
+//! the request which has failed with it is resent to the new process, so distortion keeps coming
+//! from LibLensDistortion, callers only see errors while server can't be started at all
+use std::{
+	cell::{Cell, RefCell},
+	result,
+	time::{Duration, Instant},
+};
+
+use lens_protocol::{DistortOutput, Error, Eye, LeftRightTopBottom, LensClient, ServerClient};
+use serde_json::Value;
+use tracing::{error, info, warn};
+
+use crate::start_lens_server;
+
+type Result<T> = result::Result<T, Error>;
+
+/// Delay before the second restart in a row, doubled for each next one
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// Server which crashed after working this long is restarted right away
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+struct Running {
+	client: ServerClient,
+	started_at: Instant,
+}
+
+pub struct SupervisedClient {
+	/// Latest config, passed to restarted servers
+	config: RefCell<Value>,
+	running: RefCell<Option<Running>>,
+	backoff: Cell<Duration>,
+	retry_at: Cell<Option<Instant>>,
+	exited: Cell<bool>,
+	on_restart: Option<Box<dyn Fn()>>,
+}
+impl SupervisedClient {
+	/// Only the first start fails, later ones are retried with backoff
+	pub fn start(config: Value) -> crate::Result<Self> {
+		let client = start_lens_server(config.clone())?;
+		Ok(Self {
+			config: RefCell::new(config),
+			running: RefCell::new(Some(Running {
+				client,
+				started_at: Instant::now(),
+			})),
+			backoff: Cell::new(MIN_BACKOFF),
+			retry_at: Cell::new(None),
+			exited: Cell::new(false),
+			on_restart: None,
+		})
+	}
+	/// Called after every successful restart, i.e to tell interested parties that the server has
+	/// crashed
+	pub fn on_restart(self, hook: impl Fn() + 'static) -> Self {
+		Self {
+			on_restart: Some(Box::new(hook)),
+			..self
+		}
+	}
+
+	fn restart(&self) -> Result<()> {
+		if self.exited.get() {
+			return Err(Error::Unavailable);
+		}
+		let now = Instant::now();
+		if self.retry_at.get().map(|at| now < at) == Some(true) {
+			return Err(Error::Unavailable);
+		}
+		info!("restarting lens server");
+		match start_lens_server(self.config.borrow().clone()) {
+			Ok(client) => {
+				*self.running.borrow_mut() = Some(Running {
+					client,
+					started_at: now,
+				});
+				if let Some(hook) = &self.on_restart {
+					hook();
+				}
+				Ok(())
+			}
+			Err(e) => {
+				error!("lens server restart failed: {e}");
+				self.delay_next(now);
+				Err(Error::Unavailable)
+			}
+		}
+	}
+	fn delay_next(&self, now: Instant) {
+		let delay = self.backoff.get();
+		self.retry_at.set(Some(now + delay));
+		self.backoff.set((delay * 2).min(MAX_BACKOFF));
+	}
+	fn crashed(&self, e: &Error) {
+		let Some(running) = self.running.borrow_mut().take() else {
+			return;
+		};
+		warn!("lens server has crashed: {e}");
+		let now = Instant::now();
+		if now - running.started_at >= STABLE_AFTER {
+			self.backoff.set(MIN_BACKOFF);
+			self.retry_at.set(None);
+		} else {
+			self.delay_next(now);
+		}
+		running.client.kill();
+	}
+
+	/// Request is sent at most twice, second time to the restarted server
+	fn with_client<R>(&self, request: impl Fn(&ServerClient) -> Result<R>) -> Result<R> {
+		let mut resent = false;
+		loop {
+			if self.running.borrow().is_none() {
+				self.restart()?;
+			}
+			let res = {
+				let running = self.running.borrow();
+				let running = running.as_ref().expect("server is running");
+				request(&running.client)
+			};
+			match res {
+				// Broken pipe, pipe closed mid-message, garbage from a dying process, or a hung one
+				Err(
+					e @ (Error::Io(_) | Error::Truncated | Error::Corrupted(_) | Error::Timeout),
+				) => {
+					self.crashed(&e);
+					if resent {
+						return Err(e);
+					}
+					resent = true;
+				}
+				res => return res,
+			}
+		}
+	}
+}
+impl LensClient for SupervisedClient {
+	fn ping(&self, v: u32) -> Result<u32> {
+		self.with_client(|c| c.ping(v))
+	}
+
+	fn project(&self, eye: Eye) -> Result<LeftRightTopBottom> {
+		self.with_client(|c| c.project(eye))
+	}
+
+	fn matrix_needs_inversion(&self) -> Result<bool> {
+		self.with_client(|c| c.matrix_needs_inversion())
+	}
+
+	fn distort(&self, eye: Eye, uv: [f32; 2]) -> Result<DistortOutput> {
+		self.with_client(|c| c.distort(eye, uv))
+	}
+	fn distort_batch(&self, points: &[(Eye, [f32; 2])]) -> Result<Vec<DistortOutput>> {
+		self.with_client(|c| c.distort_batch(points))
+	}
+
+	fn set_config(&self, config: Value) -> Result<()> {
+		*self.config.borrow_mut() = config.clone();
+		self.with_client(|c| c.set_config(config.clone()))
+	}
+
+	fn exit(&self) -> Result<()> {
+		self.exited.set(true);
+		match self.running.borrow_mut().take() {
+			Some(running) => running.client.exit(),
+			None => Ok(()),
+		}
+	}
+}
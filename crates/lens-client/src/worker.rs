@@ -0,0 +1,131 @@
+//! Lens client on its own thread, so lens server startup and distortion computation don't hold
+//! up whoever asked for them. Requests are queued, and answered with [`Pending`], which can be
+//! awaited, or waited for from sync code
+use std::{
+	future::Future,
+	pin::Pin,
+	rc::Rc,
+	result,
+	sync::mpsc,
+	task::{Context, Poll},
+	thread,
+};
+
+use lens_protocol::{DistortOutput, Error, Eye, LeftRightTopBottom, LensClient};
+use serde_json::Value;
+use tokio::sync::oneshot;
+use tracing::error;
+
+type Result<T> = result::Result<T, Error>;
+
+type Job = Box<dyn FnOnce(&dyn LensClient) + Send>;
+
+/// Answer to a queued request, [`Error::Unavailable`] if worker is gone
+pub struct Pending<R>(oneshot::Receiver<Result<R>>);
+impl<R> Pending<R> {
+	/// Blocks, must not be called from async context
+	pub fn wait(self) -> Result<R> {
+		self.0.blocking_recv().unwrap_or(Err(Error::Unavailable))
+	}
+	/// `None` while it is still being computed
+	pub fn try_wait(&mut self) -> Option<Result<R>> {
+		match self.0.try_recv() {
+			Ok(res) => Some(res),
+			Err(oneshot::error::TryRecvError::Empty) => None,
+			Err(oneshot::error::TryRecvError::Closed) => Some(Err(Error::Unavailable)),
+		}
+	}
+}
+impl<R> Future for Pending<R> {
+	type Output = Result<R>;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		Pin::new(&mut self.0)
+			.poll(cx)
+			.map(|res| res.unwrap_or(Err(Error::Unavailable)))
+	}
+}
+
+/// Implements [`LensClient`] too, every call then waits for its answer
+pub struct AsyncClient {
+	jobs: mpsc::Sender<Job>,
+}
+impl AsyncClient {
+	/// Client is created by `start` on worker thread, and never leaves it, requests queued
+	/// meanwhile are answered once it is ready
+	pub fn start(start: impl FnOnce() -> Rc<dyn LensClient> + Send + 'static) -> Self {
+		let (jobs, rx) = mpsc::channel::<Job>();
+		let res = thread::Builder::new()
+			.name("lens-client".to_owned())
+			.spawn(move || {
+				let client = start();
+				for job in rx {
+					job(&*client);
+				}
+			});
+		if let Err(e) = res {
+			// Requests are then answered with Unavailable
+			error!("failed to start lens client thread: {e}");
+		}
+		Self { jobs }
+	}
+
+	/// Runs `request` with the client, after everything queued before
+	pub fn request<R: Send + 'static>(
+		&self,
+		request: impl FnOnce(&dyn LensClient) -> Result<R> + Send + 'static,
+	) -> Pending<R> {
+		let (tx, rx) = oneshot::channel();
+		// Dropped sender makes receiver fail, no need to handle it here
+		let _ = self.jobs.send(Box::new(move |client| {
+			let _ = tx.send(request(client));
+		}));
+		Pending(rx)
+	}
+
+	pub async fn project_async(&self, eye: Eye) -> Result<LeftRightTopBottom> {
+		self.request(move |c| c.project(eye)).await
+	}
+	pub async fn distort_batch_async(
+		&self,
+		points: Vec<(Eye, [f32; 2])>,
+	) -> Result<Vec<DistortOutput>> {
+		self.request(move |c| c.distort_batch(&points)).await
+	}
+	pub async fn set_config_async(&self, config: Value) -> Result<()> {
+		self.request(move |c| c.set_config(config)).await
+	}
+}
+impl LensClient for AsyncClient {
+	fn ping(&self, v: u32) -> Result<u32> {
+		self.request(move |c| c.ping(v)).wait()
+	}
+
+	fn project(&self, eye: Eye) -> Result<LeftRightTopBottom> {
+		self.request(move |c| c.project(eye)).wait()
+	}
+
+	fn matrix_needs_inversion(&self) -> Result<bool> {
+		self.request(|c| c.matrix_needs_inversion()).wait()
+	}
+
+	fn distort(&self, eye: Eye, uv: [f32; 2]) -> Result<DistortOutput> {
+		self.request(move |c| c.distort(eye, uv)).wait()
+	}
+	fn distort_batch(&self, points: &[(Eye, [f32; 2])]) -> Result<Vec<DistortOutput>> {
+		let points = points.to_vec();
+		self.request(move |c| c.distort_batch(&points)).wait()
+	}
+
+	fn set_config(&self, config: Value) -> Result<()> {
+		self.request(move |c| c.set_config(config)).wait()
+	}
+
+	fn set_ipd(&self, ipd_meters: f32) -> Result<()> {
+		self.request(move |c| c.set_ipd(ipd_meters)).wait()
+	}
+
+	fn exit(&self) -> Result<()> {
+		self.request(|c| c.exit()).wait()
+	}
+}
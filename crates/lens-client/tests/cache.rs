@@ -0,0 +1,259 @@
+use std::{cell::Cell, env, fs, path::PathBuf, process, rc::Rc, result, sync::Once};
+
+use lens_client::{CacheKey, CachedClient, Error};
+use lens_protocol::{DistortOutput, Eye, LeftRightTopBottom, LensClient, StubClient};
+use serde_json::{json, Value};
+
+type Result<T> = result::Result<T, lens_protocol::Error>;
+
+/// Cache location comes from environment, which is shared by all tests of this file, so they
+/// get the same directory, and different configs to not see entries of each other
+fn cache_dir() -> PathBuf {
+	static INIT: Once = Once::new();
+	let dir = env::temp_dir().join(format!("lens-client-cache-{}", process::id()));
+	INIT.call_once(|| {
+		let _ = fs::remove_dir_all(&dir);
+		env::set_var("XDG_CACHE_HOME", &dir);
+	});
+	dir.join("vivepro2/distortion")
+}
+
+fn path(key: CacheKey) -> PathBuf {
+	cache_dir().join(format!(
+		"{:08x}-{}-{}.bin",
+		key.config, key.mode, key.ipd_bucket
+	))
+}
+
+fn config(test: &str) -> Value {
+	json!({ "test": test })
+}
+
+/// Stub lens server, which counts what it was asked
+#[derive(Default)]
+struct Counting {
+	starts: Cell<usize>,
+	requests: Cell<usize>,
+}
+impl Counting {
+	fn request(&self) {
+		self.requests.set(self.requests.get() + 1);
+	}
+}
+impl LensClient for Counting {
+	fn ping(&self, v: u32) -> Result<u32> {
+		Ok(v)
+	}
+	fn project(&self, eye: Eye) -> Result<LeftRightTopBottom> {
+		self.request();
+		StubClient.project(eye)
+	}
+	fn matrix_needs_inversion(&self) -> Result<bool> {
+		self.request();
+		StubClient.matrix_needs_inversion()
+	}
+	fn distort(&self, eye: Eye, uv: [f32; 2]) -> Result<DistortOutput> {
+		self.request();
+		StubClient.distort(eye, uv)
+	}
+	fn distort_batch(&self, points: &[(Eye, [f32; 2])]) -> Result<Vec<DistortOutput>> {
+		self.request();
+		StubClient.distort_batch(points)
+	}
+	fn set_config(&self, _config: Value) -> Result<()> {
+		Ok(())
+	}
+	fn exit(&self) -> Result<()> {
+		Ok(())
+	}
+}
+
+fn session(key: CacheKey) -> (CachedClient, Rc<Counting>) {
+	cache_dir();
+	let server = Rc::new(Counting::default());
+	let started = server.clone();
+	let client = CachedClient::new(
+		key,
+		move || {
+			started.starts.set(started.starts.get() + 1);
+			Ok(started)
+		},
+		|e| panic!("lens server should start: {e}"),
+	);
+	(client, server)
+}
+
+const POINTS: [(Eye, [f32; 2]); 3] = [
+	(Eye::Left, [0.0, 0.0]),
+	(Eye::Left, [0.5, 0.25]),
+	(Eye::Right, [1.0, 0.75]),
+];
+
+/// Everything driver asks for on startup, as JSON, since protocol types can't be compared
+fn ask(client: &dyn LensClient) -> Value {
+	json!([
+		client.project(Eye::Left).unwrap(),
+		client.project(Eye::Right).unwrap(),
+		client.matrix_needs_inversion().unwrap(),
+		client.distort_batch(&POINTS).unwrap(),
+	])
+}
+
+fn expected() -> Value {
+	ask(&StubClient)
+}
+
+/// Stores answers of a server session, and checks they are there
+fn fill(key: CacheKey) {
+	let (client, server) = session(key);
+	assert_eq!(ask(&client), expected());
+	assert_eq!(server.starts.get(), 1);
+	assert_eq!(server.requests.get(), 4);
+	assert!(path(key).is_file());
+}
+
+fn assert_cached(key: CacheKey) {
+	let (client, server) = session(key);
+	assert_eq!(ask(&client), expected());
+	assert_eq!(server.starts.get(), 0, "lens server was started");
+}
+
+fn assert_not_cached(key: CacheKey) {
+	let (client, server) = session(key);
+	assert_eq!(ask(&client), expected());
+	assert_eq!(server.starts.get(), 1);
+	assert_eq!(server.requests.get(), 4, "some answers came from cache");
+}
+
+#[test]
+fn next_session_is_cached() {
+	let key = CacheKey::new(&config("next_session"), 0, Some(0.063));
+	fill(key);
+	assert_cached(key);
+	// Nothing is stored or sent to server for cached answers
+	assert_cached(key);
+}
+
+#[test]
+fn same_ipd_bucket() {
+	fill(CacheKey::new(&config("same_ipd_bucket"), 0, Some(0.0632)));
+	assert_cached(CacheKey::new(&config("same_ipd_bucket"), 0, Some(0.0628)));
+}
+
+#[test]
+fn key_invalidation() {
+	let key = CacheKey::new(&config("key_invalidation"), 1, Some(0.063));
+	fill(key);
+	assert_not_cached(CacheKey::new(
+		&config("key_invalidation_other"),
+		1,
+		Some(0.063),
+	));
+	assert_not_cached(CacheKey::new(&config("key_invalidation"), 2, Some(0.063)));
+	assert_not_cached(CacheKey::new(&config("key_invalidation"), 1, Some(0.065)));
+	assert_not_cached(CacheKey::new(&config("key_invalidation"), 1, None));
+	// Other entries didn't replace it
+	assert_cached(key);
+}
+
+#[test]
+fn other_points_are_requested() {
+	let key = CacheKey::new(&config("other_points"), 0, None);
+	fill(key);
+	let (client, server) = session(key);
+	let points = &POINTS[..2];
+	assert_eq!(
+		json!(client.distort_batch(points).unwrap()),
+		json!(StubClient.distort_batch(points).unwrap())
+	);
+	assert_eq!(server.requests.get(), 1);
+	// Both batches are kept
+	assert_cached(key);
+	let (client, server) = session(key);
+	client.distort_batch(points).unwrap();
+	assert_eq!(server.starts.get(), 0);
+}
+
+#[test]
+fn set_ipd_switches_entry() {
+	let config = config("set_ipd");
+	let near = CacheKey::new(&config, 0, Some(0.060));
+	let far = CacheKey::new(&config, 0, Some(0.070));
+	fill(near);
+
+	let (client, server) = session(far);
+	client.set_ipd(0.060).unwrap();
+	assert_eq!(ask(&client), expected());
+	assert_eq!(server.starts.get(), 0);
+	// Back to the one which has nothing stored
+	client.set_ipd(0.070).unwrap();
+	assert_eq!(ask(&client), expected());
+	assert_eq!(server.starts.get(), 1);
+	assert_eq!(server.requests.get(), 4);
+	assert_cached(far);
+}
+
+#[test]
+fn set_config_invalidates() {
+	let old = CacheKey::new(&config("set_config_old"), 3, Some(0.063));
+	let new = CacheKey::new(&config("set_config_new"), 3, Some(0.063));
+	fill(old);
+
+	let (client, server) = session(old);
+	client.set_config(config("set_config_new")).unwrap();
+	assert_eq!(ask(&client), expected());
+	assert_eq!(server.requests.get(), 4);
+	assert_cached(new);
+	assert_cached(old);
+}
+
+#[test]
+fn fallback_is_not_stored() {
+	let key = CacheKey::new(&config("fallback"), 0, None);
+	let client = CachedClient::new(key, || Err(Error::WineNotFound), |_| Rc::new(StubClient));
+	assert_eq!(ask(&client), expected());
+	assert!(!path(key).exists());
+	assert_not_cached(key);
+}
+
+#[test]
+fn corrupt_file() {
+	let key = CacheKey::new(&config("corrupt"), 0, None);
+	fs::create_dir_all(cache_dir()).unwrap();
+	fs::write(
+		path(key),
+		b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff not an entry",
+	)
+	.unwrap();
+	assert_not_cached(key);
+	// And is replaced with a good one
+	assert_cached(key);
+
+	fs::write(path(key), b"").unwrap();
+	assert_not_cached(key);
+	assert_cached(key);
+}
+
+#[test]
+fn partial_file() {
+	let key = CacheKey::new(&config("partial"), 0, None);
+	fill(key);
+	let data = fs::read(path(key)).unwrap();
+	for len in [1, data.len() / 2, data.len() - 1] {
+		fs::write(path(key), &data[..len]).unwrap();
+		assert_not_cached(key);
+		assert_eq!(fs::read(path(key)).unwrap(), data);
+	}
+}
+
+/// Entry knows its key, so one copied or renamed over another isn't used
+#[test]
+fn file_of_other_key() {
+	let stored = CacheKey::new(&config("other_key_stored"), 0, None);
+	let replaced = CacheKey::new(&config("other_key_replaced"), 0, None);
+	fill(stored);
+	fs::copy(path(stored), path(replaced)).unwrap();
+	assert_not_cached(replaced);
+	assert_cached(replaced);
+	assert_cached(stored);
+}
@@ -0,0 +1,71 @@
+use std::{fs, path::Path, rc::Rc};
+
+use lens_client::{Recording, RecordingClient, ReplayClient};
+use lens_geometry::{DistortionMesh, FallbackLens};
+use lens_protocol::{Error, Eye, LensClient};
+use serde_json::Value;
+
+fn fixture(name: &str) -> (Recording, Value) {
+	let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("tests/fixtures")
+		.join(name);
+	let recording = Recording::load(&dir.join("responses.bin")).expect("recording");
+	let golden = serde_json::from_slice(&fs::read(dir.join("golden.json")).unwrap()).unwrap();
+	(recording, golden)
+}
+
+/// Parsed back from text, same as golden ones, since float parsing of serde_json isn't exact
+fn meshes(lens: &dyn LensClient, grid: usize) -> Value {
+	// Same calls as `vivectl lens record` makes
+	let meshes = [Eye::Left, Eye::Right]
+		.into_iter()
+		.map(|eye| {
+			DistortionMesh::compute(lens, eye, grid)
+				.expect("mesh")
+				.to_json()
+		})
+		.collect::<Value>();
+	serde_json::from_str(&meshes.to_string()).unwrap()
+}
+
+fn grid(golden: &Value) -> usize {
+	golden["meshes"][0]["grid"].as_u64().unwrap() as usize
+}
+
+#[test]
+fn replay_matches_golden() {
+	let (recording, golden) = fixture("approximate");
+	let grid = grid(&golden);
+	let lens = ReplayClient::new(recording);
+	assert_eq!(meshes(&lens, grid), golden["meshes"]);
+}
+
+/// Fails once approximation or mesh computation changes, fixture should be recorded again then
+#[test]
+fn approximation_matches_recording() {
+	let (recording, golden) = fixture("approximate");
+	let config = recording.config();
+	let lens = RecordingClient::new(Rc::new(FallbackLens::new(&config)), &config);
+	assert_eq!(meshes(&lens, grid(&golden)), golden["meshes"]);
+	assert_eq!(
+		serde_json::to_value(&lens.recording().calls).unwrap(),
+		serde_json::to_value(&recording.calls).unwrap(),
+	);
+}
+
+#[test]
+fn unrecorded_fails() {
+	let (recording, golden) = fixture("approximate");
+	let config = recording.config();
+	let lens = ReplayClient::new(recording);
+	assert!(matches!(
+		lens.distort(Eye::Left, [0.123, 0.456]),
+		Err(Error::NotRecorded(_))
+	));
+	assert!(matches!(
+		lens.set_config(Value::Null),
+		Err(Error::NotRecorded(_))
+	));
+	lens.set_config(config).unwrap();
+	assert!(DistortionMesh::compute(&lens, Eye::Left, grid(&golden) + 1).is_err());
+}
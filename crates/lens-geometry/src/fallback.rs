@@ -0,0 +1,91 @@
+use std::{cell::RefCell, result};
+
+use lens_protocol::{DistortOutput, Error, Eye, LeftRightTopBottom, LensClient, StubClient};
+use serde_json::Value;
+
+use crate::LensIntrinsics;
+
+type Result<T> = result::Result<T, Error>;
+
+/// Radial coefficients, `r * (1 + K1 * r^2 + K2 * r^4)`, where `r` is the distance from the optical
+/// center normalized to the closest viewport edge.
+///
+/// Rough values for lenses of this FOV, image is usable, but straight lines bend near the edges
+const K1: f32 = 0.24;
+const K2: f32 = 0.12;
+/// Blue is refracted more than red, so it is sampled farther from the center.
+/// Red, green, blue
+const CHROMATIC_SCALE: [f32; 3] = [0.994, 1.0, 1.012];
+
+/// Approximate distortion model, for when lens server (and LibLensDistortion with it) is not
+/// available
+///
+/// Projection is still exact for configs with precomputed intrinsics
+#[derive(Default)]
+pub struct FallbackLens {
+	intrinsics: RefCell<Option<[LensIntrinsics; 2]>>,
+}
+impl FallbackLens {
+	pub fn new(config: &Value) -> Self {
+		let out = Self::default();
+		out.load(config);
+		out
+	}
+	fn load(&self, config: &Value) {
+		let left = LensIntrinsics::from_config(config, Eye::Left);
+		let right = LensIntrinsics::from_config(config, Eye::Right);
+		*self.intrinsics.borrow_mut() = left.zip(right).map(|(l, r)| [l, r]);
+	}
+}
+impl LensClient for FallbackLens {
+	fn ping(&self, v: u32) -> Result<u32> {
+		Ok(v)
+	}
+
+	fn project(&self, eye: Eye) -> Result<LeftRightTopBottom> {
+		match &*self.intrinsics.borrow() {
+			Some(intrinsics) => Ok(intrinsics[eye as usize].projection_raw()),
+			None => StubClient.project(eye),
+		}
+	}
+
+	fn matrix_needs_inversion(&self) -> Result<bool> {
+		let v = self.project(Eye::Left)?;
+		Ok(v.top > v.bottom)
+	}
+
+	fn distort(&self, eye: Eye, uv: [f32; 2]) -> Result<DistortOutput> {
+		let projection = self.project(eye)?;
+		// Optical axis, in uv space
+		let center = [
+			-projection.left / (projection.right - projection.left),
+			-projection.top / (projection.bottom - projection.top),
+		];
+		let radius = center
+			.iter()
+			.flat_map(|c| [*c, 1.0 - c])
+			.fold(f32::INFINITY, f32::min)
+			.max(f32::EPSILON);
+		let d = [uv[0] - center[0], uv[1] - center[1]];
+		let r2 = (d[0] * d[0] + d[1] * d[1]) / (radius * radius);
+		let scale = 1.0 + K1 * r2 + K2 * r2 * r2;
+		let channel = |chromatic: f32| {
+			let s = scale * chromatic;
+			[center[0] + d[0] * s, center[1] + d[1] * s]
+		};
+		Ok(DistortOutput {
+			red: channel(CHROMATIC_SCALE[0]),
+			green: channel(CHROMATIC_SCALE[1]),
+			blue: channel(CHROMATIC_SCALE[2]),
+		})
+	}
+
+	fn set_config(&self, config: Value) -> Result<()> {
+		self.load(&config);
+		Ok(())
+	}
+
+	fn exit(&self) -> Result<()> {
+		Ok(())
+	}
+}
@@ -0,0 +1,123 @@
+use std::f32::consts::TAU;
+
+use lens_protocol::LeftRightTopBottom;
+
+/// Triangle list in eye viewport uv space (0,0 is top-left), covering pixels which are
+/// not visible through the lens.
+#[derive(Debug, Clone, Default)]
+pub struct HiddenAreaMesh {
+	pub triangles: Vec<[[f32; 2]; 3]>,
+}
+impl HiddenAreaMesh {
+	/// Flattened vertex list, in format expected by `IVRCompositor::GetHiddenAreaMesh`
+	pub fn vertices(&self) -> impl Iterator<Item = [f32; 2]> + '_ {
+		self.triangles.iter().flatten().copied()
+	}
+}
+
+fn to_uv(projection: &LeftRightTopBottom, p: [f32; 2]) -> [f32; 2] {
+	[
+		(p[0] - projection.left) / (projection.right - projection.left),
+		(p[1] - projection.top) / (projection.bottom - projection.top),
+	]
+}
+
+/// Distance from the optical center to the viewport edge, in direction of `angle`
+fn edge_distance(projection: &LeftRightTopBottom, angle: f32) -> f32 {
+	let (sin, cos) = angle.sin_cos();
+	let mut t = f32::INFINITY;
+	if cos > f32::EPSILON {
+		t = t.min(projection.right.max(projection.left) / cos);
+	} else if cos < -f32::EPSILON {
+		t = t.min(projection.left.min(projection.right) / cos);
+	}
+	if sin > f32::EPSILON {
+		t = t.min(projection.bottom.max(projection.top) / sin);
+	} else if sin < -f32::EPSILON {
+		t = t.min(projection.top.min(projection.bottom) / sin);
+	}
+	t
+}
+
+/// Lens aperture is assumed to be circular and centered on the optical axis.
+///
+/// `projection` contains raw projection tangents, as returned by [`lens_protocol::LensClient::project`],
+/// `aperture` is the visible radius in the same tangent space, when `None` - circle is fit to the
+/// farthest viewport edge, so only corners are hidden.
+pub fn hidden_area_mesh(
+	projection: &LeftRightTopBottom,
+	aperture: Option<f32>,
+	segments: usize,
+) -> HiddenAreaMesh {
+	let aperture = aperture.unwrap_or_else(|| {
+		[
+			projection.left,
+			projection.right,
+			projection.top,
+			projection.bottom,
+		]
+		.into_iter()
+		.map(f32::abs)
+		.fold(0.0, f32::max)
+	});
+
+	// Viewport corners should be sampled exactly, otherwise chords between samples cut them off,
+	// and so should be points where aperture crosses viewport edges, otherwise chord from edge to
+	// aperture hides some of visible pixels
+	let mut angles: Vec<f32> = (0..segments.max(4))
+		.map(|i| TAU * i as f32 / segments.max(4) as f32)
+		.collect();
+	let (min_x, max_x) = (
+		projection.left.min(projection.right),
+		projection.left.max(projection.right),
+	);
+	let (min_y, max_y) = (
+		projection.top.min(projection.bottom),
+		projection.top.max(projection.bottom),
+	);
+	for x in [projection.left, projection.right] {
+		for y in [projection.top, projection.bottom] {
+			angles.push(y.atan2(x).rem_euclid(TAU));
+		}
+		if x.abs() < aperture {
+			let y = (aperture * aperture - x * x).sqrt();
+			for y in [y, -y].into_iter().filter(|y| (min_y..=max_y).contains(y)) {
+				angles.push(y.atan2(x).rem_euclid(TAU));
+			}
+		}
+	}
+	for y in [projection.top, projection.bottom] {
+		if y.abs() < aperture {
+			let x = (aperture * aperture - y * y).sqrt();
+			for x in [x, -x].into_iter().filter(|x| (min_x..=max_x).contains(x)) {
+				angles.push(y.atan2(x).rem_euclid(TAU));
+			}
+		}
+	}
+	angles.sort_unstable_by(f32::total_cmp);
+	angles.dedup_by(|a, b| (*a - *b).abs() < 1e-6);
+
+	let points: Vec<([f32; 2], [f32; 2])> = angles
+		.iter()
+		.map(|&angle| {
+			let (sin, cos) = angle.sin_cos();
+			let edge = edge_distance(projection, angle);
+			let inner = aperture.min(edge);
+			(
+				to_uv(projection, [cos * inner, sin * inner]),
+				to_uv(projection, [cos * edge, sin * edge]),
+			)
+		})
+		.collect();
+
+	let mut triangles = Vec::new();
+	for i in 0..points.len() {
+		let (inner_a, outer_a) = points[i];
+		let (inner_b, outer_b) = points[(i + 1) % points.len()];
+		if inner_a != outer_a || inner_b != outer_b {
+			triangles.push([inner_a, outer_a, outer_b]);
+			triangles.push([inner_a, outer_b, inner_b]);
+		}
+	}
+	HiddenAreaMesh { triangles }
+}
@@ -0,0 +1,17 @@
+//! Geometry derived from lens parameters, shared between driver-proxy and standalone runtimes
+
+mod fallback;
+mod hidden_area;
+mod mesh;
+#[cfg(feature = "native")]
+mod native;
+mod openhmd;
+mod projection;
+
+pub use fallback::FallbackLens;
+pub use hidden_area::{hidden_area_mesh, HiddenAreaMesh};
+pub use mesh::{write_obj, DistortionMesh, MeshVertex};
+#[cfg(feature = "native")]
+pub use native::{Compare, Difference, NativeLens};
+pub use openhmd::OpenHmdProfile;
+pub use projection::LensIntrinsics;
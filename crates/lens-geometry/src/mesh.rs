@@ -0,0 +1,226 @@
+use std::io::{self, Write};
+
+use lens_protocol::{
+	ChromaticAberration, DistortOutput, Error, Eye, LeftRightTopBottom, LensClient,
+};
+use serde_json::{json, Value};
+
+/// Grid vertex, with uv in eye viewport space (0,0 is top-left), and where each color channel
+/// should be sampled from in the rendered eye image
+#[derive(Debug, Clone, Copy)]
+pub struct MeshVertex {
+	pub position: [f32; 2],
+	pub red: [f32; 2],
+	pub green: [f32; 2],
+	pub blue: [f32; 2],
+}
+impl MeshVertex {
+	pub fn chromatic_aberration(&self) -> ChromaticAberration {
+		DistortOutput {
+			red: self.red,
+			green: self.green,
+			blue: self.blue,
+		}
+		.chromatic_aberration()
+	}
+}
+
+type Channel = fn(&MeshVertex) -> [f32; 2];
+const CHANNELS: [(&str, Channel); 3] = [
+	("red", |v| v.red),
+	("green", |v| v.green),
+	("blue", |v| v.blue),
+];
+
+/// Distortion sampled on a regular grid, in the form compositors usually apply it: render eye
+/// image with `projection`, then draw mesh with per-channel texture coordinates
+#[derive(Debug, Clone)]
+pub struct DistortionMesh {
+	pub eye: Eye,
+	/// Cells per side, there are `(grid + 1)^2` vertices, row by row
+	pub grid: usize,
+	/// Tangents of eye frustum, as reported to SteamVR
+	pub projection: LeftRightTopBottom,
+	pub vertices: Vec<MeshVertex>,
+}
+fn grid_positions(grid: usize) -> impl Iterator<Item = [f32; 2]> {
+	(0..=grid).flat_map(move |y| {
+		(0..=grid).map(move |x| [x as f32 / grid as f32, y as f32 / grid as f32])
+	})
+}
+
+impl DistortionMesh {
+	pub fn compute(lens: &dyn LensClient, eye: Eye, grid: usize) -> Result<Self, Error> {
+		let [mesh] = Self::compute_all(lens, [eye], grid)?;
+		Ok(mesh)
+	}
+	/// Both eyes, in a single batch, so remote lens server is only asked once
+	pub fn compute_both(lens: &dyn LensClient, grid: usize) -> Result<[Self; 2], Error> {
+		Self::compute_all(lens, [Eye::Left, Eye::Right], grid)
+	}
+	fn compute_all<const N: usize>(
+		lens: &dyn LensClient,
+		eyes: [Eye; N],
+		grid: usize,
+	) -> Result<[Self; N], Error> {
+		let grid = grid.max(1);
+		let points = eyes
+			.iter()
+			.flat_map(|&eye| grid_positions(grid).map(move |p| (eye, p)))
+			.collect::<Vec<_>>();
+		let outputs = lens.distort_batch(&points)?;
+		if outputs.len() != points.len() {
+			return Err(Error::Corrupted(format!(
+				"{} distortion results for {} points",
+				outputs.len(),
+				points.len()
+			)));
+		}
+		let inverse = lens.matrix_needs_inversion()?;
+		let per_eye = (grid + 1) * (grid + 1);
+		let mut meshes = Vec::with_capacity(N);
+		for (i, &eye) in eyes.iter().enumerate() {
+			let range = i * per_eye..(i + 1) * per_eye;
+			let vertices = points[range.clone()]
+				.iter()
+				.zip(&outputs[range])
+				.map(|(&(_, position), out)| MeshVertex {
+					position,
+					red: out.red,
+					green: out.green,
+					blue: out.blue,
+				})
+				.collect();
+			let mut projection = lens.project(eye)?;
+			if inverse {
+				(projection.top, projection.bottom) = (projection.bottom, projection.top);
+			}
+			meshes.push(Self {
+				eye,
+				grid,
+				projection,
+				vertices,
+			});
+		}
+		Ok(meshes
+			.try_into()
+			.unwrap_or_else(|_| unreachable!("mesh per eye")))
+	}
+
+	/// Bilinear interpolation between grid vertices, `uv` is clamped to the viewport
+	pub fn sample(&self, uv: [f32; 2]) -> DistortOutput {
+		let row = self.grid + 1;
+		let cell = |v: f32| {
+			let v = v.clamp(0.0, 1.0) * self.grid as f32;
+			let i = (v as usize).min(self.grid - 1);
+			(i, v - i as f32)
+		};
+		let (x, fx) = cell(uv[0]);
+		let (y, fy) = cell(uv[1]);
+		let at = |x: usize, y: usize| &self.vertices[y * row + x];
+		let (a, b, c, d) = (at(x, y), at(x + 1, y), at(x, y + 1), at(x + 1, y + 1));
+		let lerp = |f: Channel| {
+			let (a, b, c, d) = (f(a), f(b), f(c), f(d));
+			let top = [a[0] + (b[0] - a[0]) * fx, a[1] + (b[1] - a[1]) * fx];
+			let bottom = [c[0] + (d[0] - c[0]) * fx, c[1] + (d[1] - c[1]) * fx];
+			[
+				top[0] + (bottom[0] - top[0]) * fy,
+				top[1] + (bottom[1] - top[1]) * fy,
+			]
+		};
+		DistortOutput {
+			red: lerp(CHANNELS[0].1),
+			green: lerp(CHANNELS[1].1),
+			blue: lerp(CHANNELS[2].1),
+		}
+	}
+
+	/// Two triangles per cell, counter-clockwise with y pointing up, indices into `vertices`
+	pub fn triangles(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+		let row = self.grid as u32 + 1;
+		(0..self.grid as u32).flat_map(move |y| {
+			(0..self.grid as u32).flat_map(move |x| {
+				let i = y * row + x;
+				[[i, i + row, i + 1], [i + 1, i + row, i + row + 1]]
+			})
+		})
+	}
+
+	pub fn to_json(&self) -> Value {
+		let channel = |f: Channel| self.vertices.iter().map(f).collect::<Vec<_>>();
+		let mut out = json!({
+			"eye": match self.eye {
+				Eye::Left => "left",
+				Eye::Right => "right",
+			},
+			"grid": self.grid,
+			"projection": {
+				"left": self.projection.left,
+				"right": self.projection.right,
+				"top": self.projection.top,
+				"bottom": self.projection.bottom,
+			},
+			"positions": channel(|v| v.position),
+			"triangles": self.triangles().collect::<Vec<_>>(),
+		});
+		for (name, f) in CHANNELS {
+			out[name] = json!(channel(f));
+		}
+		let ca = self
+			.vertices
+			.iter()
+			.map(MeshVertex::chromatic_aberration)
+			.collect::<Vec<_>>();
+		out["chromatic_aberration"] = json!({
+			"red": ca.iter().map(|v| v.red).collect::<Vec<_>>(),
+			"blue": ca.iter().map(|v| v.blue).collect::<Vec<_>>(),
+		});
+		out
+	}
+}
+
+/// Wavefront OBJ, with an object per eye and channel. Vertices are grid positions (z = 0), texture
+/// coordinates are distorted uv, with v flipped, as OBJ has its origin at bottom-left
+pub fn write_obj(meshes: &[DistortionMesh], out: &mut impl Write) -> io::Result<()> {
+	writeln!(out, "# VIVE Pro 2 lens distortion")?;
+	let mut vertex_base = 1;
+	let mut uv_base = 1;
+	for mesh in meshes {
+		let eye = match mesh.eye {
+			Eye::Left => "left",
+			Eye::Right => "right",
+		};
+		let p = &mesh.projection;
+		writeln!(
+			out,
+			"# {eye} eye projection: left {} right {} top {} bottom {}",
+			p.left, p.right, p.top, p.bottom
+		)?;
+		for v in &mesh.vertices {
+			writeln!(out, "v {} {} 0", v.position[0], 1.0 - v.position[1])?;
+		}
+		for (name, channel) in CHANNELS {
+			writeln!(out, "o {eye}_{name}")?;
+			for v in &mesh.vertices {
+				let uv = channel(v);
+				writeln!(out, "vt {} {}", uv[0], 1.0 - uv[1])?;
+			}
+			for t in mesh.triangles() {
+				let [a, b, c] = t.map(|i| i as usize);
+				writeln!(
+					out,
+					"f {}/{} {}/{} {}/{}",
+					vertex_base + a,
+					uv_base + a,
+					vertex_base + b,
+					uv_base + b,
+					vertex_base + c,
+					uv_base + c
+				)?;
+			}
+			uv_base += mesh.vertices.len();
+		}
+		vertex_base += mesh.vertices.len();
+	}
+	Ok(())
+}
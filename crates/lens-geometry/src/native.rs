@@ -0,0 +1,280 @@
+//! Lens distortion, evaluated from `inhouse_lens_correction` without LibLensDistortion
+//!
+//! Per eye, config is expected to carry lighthouse-style polynomial per color channel, next to the
+//! precomputed intrinsics:
+//!
+//! ```json
+//! "left": {
+//!   "intrinsic": [...], "grow_for_undistort": [...],
+//!   "distortion": { "center_x": 0.0, "center_y": 0.0, "coeffs": [k1, k2, k3], "type": "DISTORT_DPOLY3" },
+//!   "distortion_red": { ... },
+//!   "distortion_blue": { ... },
+//!   "undistort_r2_cutoff": 1.5
+//! }
+//! ```
+//!
+//! Configs without it are rejected, lens server stays the only option for them. [`Compare`] runs
+//! this model next to lens server, to see how far off it is for a given headset
+use std::{cell::RefCell, rc::Rc, result};
+
+use lens_protocol::{DistortOutput, Error, Eye, LeftRightTopBottom, LensClient};
+use serde_json::Value;
+
+use crate::LensIntrinsics;
+
+type Result<T> = result::Result<T, Error>;
+
+/// `uv / (1 + k1 * r^2 + k2 * r^4 + k3 * r^6)`, around the channel center, in `-1..1` eye space
+#[derive(Debug, Clone, Copy)]
+struct Polynomial {
+	center: [f32; 2],
+	k: [f32; 3],
+}
+impl Polynomial {
+	fn from_config(v: &Value) -> Result<Self> {
+		let unsupported = |what: &str| Error::UnsupportedConfig(what.to_owned());
+		match v["type"].as_str() {
+			Some("DISTORT_DPOLY3") => {}
+			Some(other) => return Err(unsupported(&format!("distortion type {other}"))),
+			None => return Err(unsupported("no distortion polynomial")),
+		}
+		let coeffs = v["coeffs"]
+			.as_array()
+			.filter(|c| !c.is_empty())
+			.ok_or_else(|| unsupported("no distortion coeffs"))?;
+		let mut k = [0.0; 3];
+		for (k, c) in k.iter_mut().zip(coeffs) {
+			*k = c.as_f64().ok_or_else(|| unsupported("distortion coeffs"))? as f32;
+		}
+		Ok(Self {
+			center: [
+				v["center_x"].as_f64().unwrap_or_default() as f32,
+				v["center_y"].as_f64().unwrap_or_default() as f32,
+			],
+			k,
+		})
+	}
+	fn apply(&self, p: [f32; 2], r2_cutoff: f32) -> [f32; 2] {
+		let d = [p[0] - self.center[0], p[1] - self.center[1]];
+		// Polynomial diverges past the lens edge, nothing is visible there anyway
+		let r2 = (d[0] * d[0] + d[1] * d[1]).min(r2_cutoff);
+		let [k1, k2, k3] = self.k;
+		let scale = 1.0 / (1.0 + r2 * (k1 + r2 * (k2 + r2 * k3)));
+		[self.center[0] + d[0] * scale, self.center[1] + d[1] * scale]
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+struct EyeModel {
+	intrinsics: LensIntrinsics,
+	/// Red, green, blue
+	channels: [Polynomial; 3],
+	r2_cutoff: f32,
+}
+impl EyeModel {
+	fn from_config(config: &Value, eye: Eye) -> Result<Self> {
+		let intrinsics = LensIntrinsics::from_config(config, eye)
+			.ok_or_else(|| Error::UnsupportedConfig("no precomputed intrinsics".to_owned()))?;
+		let v = &config[eye_key(eye)];
+		Ok(Self {
+			intrinsics,
+			channels: [
+				Polynomial::from_config(&v["distortion_red"])?,
+				Polynomial::from_config(&v["distortion"])?,
+				Polynomial::from_config(&v["distortion_blue"])?,
+			],
+			r2_cutoff: v["undistort_r2_cutoff"]
+				.as_f64()
+				.map(|v| v as f32)
+				.unwrap_or(f32::INFINITY),
+		})
+	}
+	fn distort(&self, uv: [f32; 2]) -> DistortOutput {
+		let grow = self.intrinsics.grow_for_undistort.map(|v| v + 1.0);
+		let p = [uv[0] * 2.0 - 1.0, uv[1] * 2.0 - 1.0];
+		let channel = |poly: &Polynomial| {
+			let d = poly.apply(p, self.r2_cutoff);
+			// Render target covers more than the panel, by grow on each side
+			let gx = if d[0] < 0.0 { grow[0] } else { grow[1] };
+			let gy = if d[1] < 0.0 { grow[2] } else { grow[3] };
+			[0.5 + d[0] / gx * 0.5, 0.5 + d[1] / gy * 0.5]
+		};
+		DistortOutput {
+			red: channel(&self.channels[0]),
+			green: channel(&self.channels[1]),
+			blue: channel(&self.channels[2]),
+		}
+	}
+}
+
+fn eye_key(eye: Eye) -> &'static str {
+	match eye {
+		Eye::Left => "left",
+		Eye::Right => "right",
+	}
+}
+
+/// Drop-in replacement for lens server client
+pub struct NativeLens {
+	eyes: RefCell<[EyeModel; 2]>,
+}
+impl NativeLens {
+	pub fn new(config: &Value) -> Result<Self> {
+		Ok(Self {
+			eyes: RefCell::new(Self::load(config)?),
+		})
+	}
+	fn load(config: &Value) -> Result<[EyeModel; 2]> {
+		Ok([
+			EyeModel::from_config(config, Eye::Left)?,
+			EyeModel::from_config(config, Eye::Right)?,
+		])
+	}
+}
+impl LensClient for NativeLens {
+	fn ping(&self, v: u32) -> Result<u32> {
+		Ok(v)
+	}
+
+	fn project(&self, eye: Eye) -> Result<LeftRightTopBottom> {
+		Ok(self.eyes.borrow()[eye as usize].intrinsics.projection_raw())
+	}
+
+	fn matrix_needs_inversion(&self) -> Result<bool> {
+		let v = self.project(Eye::Left)?;
+		Ok(v.top > v.bottom)
+	}
+
+	fn distort(&self, eye: Eye, uv: [f32; 2]) -> Result<DistortOutput> {
+		Ok(self.eyes.borrow()[eye as usize].distort(uv))
+	}
+
+	fn set_config(&self, config: Value) -> Result<()> {
+		*self.eyes.borrow_mut() = Self::load(&config)?;
+		Ok(())
+	}
+
+	fn exit(&self) -> Result<()> {
+		Ok(())
+	}
+}
+
+/// Difference between native and reference output, in uv units, over every channel
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Difference {
+	pub samples: u64,
+	pub max: f32,
+	sum: f64,
+	/// Where `max` was reached
+	pub worst: Option<(Eye, [f32; 2])>,
+}
+impl Difference {
+	pub fn mean(&self) -> f32 {
+		if self.samples == 0 {
+			return 0.0;
+		}
+		(self.sum / self.samples as f64) as f32
+	}
+	fn add(&mut self, eye: Eye, uv: [f32; 2], reference: &DistortOutput, native: &DistortOutput) {
+		let dist =
+			|a: [f32; 2], b: [f32; 2]| ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt();
+		let diff = dist(reference.red, native.red)
+			.max(dist(reference.green, native.green))
+			.max(dist(reference.blue, native.blue));
+		self.samples += 1;
+		self.sum += diff as f64;
+		if diff > self.max || self.worst.is_none() {
+			self.max = diff;
+			self.worst = Some((eye, uv));
+		}
+	}
+}
+
+type ReportHook = Box<dyn Fn(&Difference)>;
+
+/// Reference client output is used, native model is evaluated for the same input, and the
+/// difference is accumulated
+pub struct Compare {
+	reference: Rc<dyn LensClient>,
+	native: NativeLens,
+	difference: RefCell<Difference>,
+	report_every: u64,
+	on_report: Option<ReportHook>,
+}
+impl Compare {
+	pub fn new(reference: Rc<dyn LensClient>, native: NativeLens) -> Self {
+		Self {
+			reference,
+			native,
+			difference: RefCell::default(),
+			report_every: 1,
+			on_report: None,
+		}
+	}
+	/// Called with accumulated difference after every `every` distort calls
+	pub fn on_report(self, every: u64, hook: impl Fn(&Difference) + 'static) -> Self {
+		Self {
+			report_every: every.max(1),
+			on_report: Some(Box::new(hook)),
+			..self
+		}
+	}
+	pub fn difference(&self) -> Difference {
+		*self.difference.borrow()
+	}
+}
+impl LensClient for Compare {
+	fn ping(&self, v: u32) -> Result<u32> {
+		self.reference.ping(v)
+	}
+
+	fn project(&self, eye: Eye) -> Result<LeftRightTopBottom> {
+		self.reference.project(eye)
+	}
+
+	fn matrix_needs_inversion(&self) -> Result<bool> {
+		self.reference.matrix_needs_inversion()
+	}
+
+	fn distort(&self, eye: Eye, uv: [f32; 2]) -> Result<DistortOutput> {
+		let reference = self.reference.distort(eye, uv)?;
+		let native = self.native.distort(eye, uv)?;
+		let difference = {
+			let mut difference = self.difference.borrow_mut();
+			difference.add(eye, uv, &reference, &native);
+			*difference
+		};
+		if let Some(hook) = &self.on_report {
+			if difference.samples % self.report_every == 0 {
+				hook(&difference);
+			}
+		}
+		Ok(reference)
+	}
+
+	fn distort_batch(&self, points: &[(Eye, [f32; 2])]) -> Result<Vec<DistortOutput>> {
+		let reference = self.reference.distort_batch(points)?;
+		let mut difference = self.difference.borrow_mut();
+		for (&(eye, uv), reference) in points.iter().zip(&reference) {
+			difference.add(eye, uv, reference, &self.native.distort(eye, uv)?);
+		}
+		if let Some(hook) = &self.on_report {
+			hook(&difference);
+		}
+		Ok(reference)
+	}
+
+	fn set_config(&self, config: Value) -> Result<()> {
+		self.native.set_config(config.clone())?;
+		*self.difference.borrow_mut() = Difference::default();
+		self.reference.set_config(config)
+	}
+
+	fn set_ipd(&self, ipd_meters: f32) -> Result<()> {
+		self.reference.set_ipd(ipd_meters)
+	}
+
+	fn exit(&self) -> Result<()> {
+		self.reference.exit()
+	}
+}
@@ -0,0 +1,186 @@
+//! Headset, as OpenHMD describes HMDs: panel size in meters, symmetric vertical fov, and the
+//! universal (PanoTools-like) radial distortion, with per-channel scale for chromatic aberration
+//!
+//! Panel size is not in the config, it is derived from lens separation instead, which follows
+//! IPD, and from where optical axes are on the panel
+use lens_protocol::{Error, Eye, LensClient};
+use serde_json::{json, Value};
+
+use crate::DistortionMesh;
+
+/// A few hundred points per eye is plenty for a 4 coefficient fit
+const GRID: usize = 32;
+
+#[derive(Debug, Clone, Copy)]
+pub struct OpenHmdProfile {
+	pub horizontal_resolution: u32,
+	pub vertical_resolution: u32,
+	/// Both eyes, meters
+	pub horizontal_size: f32,
+	pub vertical_size: f32,
+	pub lens_horizontal_separation: f32,
+	/// From the bottom of the panel
+	pub lens_vertical_position: f32,
+	/// Vertical, radians
+	pub fov: f32,
+	/// Of a single eye viewport
+	pub aspect_ratio: f32,
+	pub ipd: f32,
+	/// `a, b, c, d`, sampled radius is `r * (a r^3 + b r^2 + c r + d)`
+	pub universal_distortion_k: [f32; 4],
+	/// Red, green, blue
+	pub universal_aberration_k: [f32; 3],
+}
+
+impl OpenHmdProfile {
+	/// `width` and `height` are of the whole panel, as in headset modes
+	pub fn compute(
+		lens: &dyn LensClient,
+		width: u32,
+		height: u32,
+		ipd: f32,
+	) -> Result<Self, Error> {
+		let meshes = DistortionMesh::compute_both(lens, GRID)?;
+		// Optical axis of each eye, in its viewport uv
+		let centers = [&meshes[0], &meshes[1]].map(|mesh| {
+			let p = &mesh.projection;
+			[-p.left / (p.right - p.left), -p.top / (p.bottom - p.top)]
+		});
+		let [left, right] = centers;
+		let view_width = ipd / (1.0 - left[0] + right[0]);
+		let view_height = view_width * height as f32 / (width as f32 / 2.0);
+		let lens_vertical_position = (1.0 - (left[1] + right[1]) / 2.0) * view_height;
+
+		// Same normalization OpenHMD shaders do, both lens centers are placed symmetrically
+		let half_separation = ipd / 2.0;
+		let warp_scale = half_separation.max(view_width - half_separation);
+		let fit = Fit::new(&meshes, |eye, uv| {
+			let center = match eye {
+				Eye::Left => view_width - half_separation,
+				Eye::Right => half_separation,
+			};
+			[
+				(uv[0] * view_width - center) / warp_scale,
+				((1.0 - uv[1]) * view_height - lens_vertical_position) / warp_scale,
+			]
+		});
+
+		let vertical = |mesh: &DistortionMesh| {
+			(mesh.projection.bottom.atan() - mesh.projection.top.atan()).abs()
+		};
+		Ok(Self {
+			horizontal_resolution: width,
+			vertical_resolution: height,
+			horizontal_size: view_width * 2.0,
+			vertical_size: view_height,
+			lens_horizontal_separation: ipd,
+			lens_vertical_position,
+			fov: (vertical(&meshes[0]) + vertical(&meshes[1])) / 2.0,
+			aspect_ratio: width as f32 / 2.0 / height as f32,
+			ipd,
+			universal_distortion_k: fit.distortion(),
+			universal_aberration_k: fit.aberration(),
+		})
+	}
+
+	/// Keyed by OpenHMD property names, the way they are returned by `ohmd_device_getf`/`geti`
+	pub fn to_json(&self) -> Value {
+		json!({
+			"OHMD_SCREEN_HORIZONTAL_RESOLUTION": self.horizontal_resolution,
+			"OHMD_SCREEN_VERTICAL_RESOLUTION": self.vertical_resolution,
+			"OHMD_SCREEN_HORIZONTAL_SIZE": self.horizontal_size,
+			"OHMD_SCREEN_VERTICAL_SIZE": self.vertical_size,
+			"OHMD_LENS_HORIZONTAL_SEPARATION": self.lens_horizontal_separation,
+			"OHMD_LENS_VERTICAL_POSITION": self.lens_vertical_position,
+			"OHMD_LEFT_EYE_FOV": self.fov,
+			"OHMD_RIGHT_EYE_FOV": self.fov,
+			"OHMD_LEFT_EYE_ASPECT_RATIO": self.aspect_ratio,
+			"OHMD_RIGHT_EYE_ASPECT_RATIO": self.aspect_ratio,
+			"OHMD_EYE_IPD": self.ipd,
+			"OHMD_UNIVERSAL_DISTORTION_K": self.universal_distortion_k,
+			"OHMD_UNIVERSAL_ABERRATION_K": self.universal_aberration_k,
+		})
+	}
+}
+
+/// Radial samples of every mesh vertex, normalized by warp scale
+struct Fit {
+	/// Distance from the lens center, and how much green is scaled at it
+	green: Vec<(f64, f64)>,
+	/// Red and blue scale, relative to green
+	aberration: Vec<[f64; 2]>,
+}
+impl Fit {
+	/// `normalize` maps viewport uv of an eye to offset from its lens center
+	fn new(meshes: &[DistortionMesh], normalize: impl Fn(Eye, [f32; 2]) -> [f32; 2]) -> Self {
+		let mut out = Self {
+			green: Vec::new(),
+			aberration: Vec::new(),
+		};
+		let dot = |a: [f64; 2], b: [f64; 2]| a[0] * b[0] + a[1] * b[1];
+		for mesh in meshes {
+			let rel = |uv| normalize(mesh.eye, uv).map(f64::from);
+			for v in &mesh.vertices {
+				let r = rel(v.position);
+				let g = rel(v.green);
+				let (r2, g2) = (dot(r, r), dot(g, g));
+				// Optical center itself says nothing about scale
+				if r2 < 1e-6 || g2 < 1e-6 {
+					continue;
+				}
+				out.green.push((r2.sqrt(), dot(g, r) / r2));
+				out.aberration
+					.push([rel(v.red), rel(v.blue)].map(|c| dot(c, g) / g2));
+			}
+		}
+		out
+	}
+
+	/// Least squares fit of `d + c r + b r^2 + a r^3` to green scale
+	fn distortion(&self) -> [f32; 4] {
+		let mut m = [[0.0f64; 5]; 4];
+		for &(r, s) in &self.green {
+			let powers = [1.0, r, r * r, r * r * r];
+			for (row, &pi) in m.iter_mut().zip(&powers) {
+				for (cell, &pj) in row.iter_mut().zip(&powers) {
+					*cell += pi * pj;
+				}
+				row[4] += pi * s;
+			}
+		}
+		let [d, c, b, a] = solve(m).unwrap_or([1.0, 0.0, 0.0, 0.0]);
+		[a, b, c, d].map(|v| v as f32)
+	}
+	fn aberration(&self) -> [f32; 3] {
+		if self.aberration.is_empty() {
+			return [1.0; 3];
+		}
+		let n = self.aberration.len() as f64;
+		let sum = self
+			.aberration
+			.iter()
+			.fold([0.0; 2], |acc, v| [acc[0] + v[0], acc[1] + v[1]]);
+		[(sum[0] / n) as f32, 1.0, (sum[1] / n) as f32]
+	}
+}
+
+/// Gaussian elimination with partial pivoting, of the augmented matrix
+fn solve<const N: usize, const M: usize>(mut m: [[f64; M]; N]) -> Option<[f64; N]> {
+	for col in 0..N {
+		let pivot = (col..N).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+		if m[pivot][col].abs() < 1e-12 {
+			return None;
+		}
+		m.swap(col, pivot);
+		let pivot = m[col];
+		for (i, row) in m.iter_mut().enumerate() {
+			if i != col {
+				let f = row[col] / pivot[col];
+				for (cell, p) in row.iter_mut().zip(pivot).skip(col) {
+					*cell -= f * p;
+				}
+			}
+		}
+	}
+	Some(std::array::from_fn(|i| m[i][N] / m[i][i]))
+}
@@ -0,0 +1,61 @@
+use lens_protocol::{Eye, LeftRightTopBottom};
+use serde_json::Value;
+
+/// Per-eye values, which LibLensDistortion derives from the lens config
+#[derive(Debug, Clone, Copy)]
+pub struct LensIntrinsics {
+	/// Row-major camera matrix, only `[0]` (fx), `[2]` (cx), `[4]` (fy) and `[5]` (cy) are used
+	pub intrinsic: [f32; 8],
+	/// How much image should be grown to fill the panel after undistortion, left/right/top/bottom
+	pub grow_for_undistort: [f32; 4],
+}
+impl LensIntrinsics {
+	/// Not every config carries precomputed values, lens-server should be asked in that case
+	pub fn from_config(config: &Value, eye: Eye) -> Option<Self> {
+		let eye = &config[match eye {
+			Eye::Left => "left",
+			Eye::Right => "right",
+		}];
+		fn floats<const N: usize>(v: &Value) -> Option<[f32; N]> {
+			let arr = v.as_array()?;
+			if arr.len() != N {
+				return None;
+			}
+			let mut out = [0.0; N];
+			for (o, v) in out.iter_mut().zip(arr) {
+				*o = v.as_f64()? as f32;
+			}
+			Some(out)
+		}
+		Some(Self {
+			intrinsic: floats(&eye["intrinsic"])?,
+			grow_for_undistort: floats(&eye["grow_for_undistort"])?,
+		})
+	}
+
+	/// Raw projection tangents, in the same form as `IVRDisplayComponent::GetProjectionRaw`
+	/// (before inversion)
+	pub fn projection_raw(&self) -> LeftRightTopBottom {
+		let g = self.grow_for_undistort.map(|v| v + 1.0);
+		let i = &self.intrinsic;
+		LeftRightTopBottom {
+			left: (-1.0 - i[2]) * g[0] / i[0],
+			right: (1.0 - i[2]) * g[1] / i[0],
+			top: (1.0 - i[4 + 1]) * g[2] / i[4],
+			bottom: (-1.0 - i[4 + 1]) * g[3] / i[4],
+		}
+	}
+
+	/// Size of render target (both eyes side by side), which keeps panel pixel density in the
+	/// lens center after undistortion
+	pub fn recommended_render_target_size(&self, mode_width: u32, mode_height: u32) -> (u32, u32) {
+		let g = self.grow_for_undistort.map(|v| v + 1.0);
+		let scale_x = (g[0] + g[1]) / 2.0;
+		let scale_y = (g[2] + g[3]) / 2.0;
+		let round = |v: f32| (v.ceil() as u32 + 3) & !3;
+		(
+			round(mode_width as f32 / 2.0 * scale_x) * 2,
+			round(mode_height as f32 * scale_y),
+		)
+	}
+}
@@ -0,0 +1,151 @@
+//! Hidden area against lens projections, which are checked by sampling viewport, independently of
+//! how mesh is built
+use lens_geometry::{hidden_area_mesh, HiddenAreaMesh};
+use lens_protocol::{Eye, LeftRightTopBottom, LensClient, StubClient};
+
+/// What driver asks for, see `HIDDEN_AREA_SEGMENTS` of driver-proxy
+const SEGMENTS: usize = 64;
+
+fn projection(eye: Eye) -> LeftRightTopBottom {
+	StubClient.project(eye).unwrap()
+}
+
+/// Point of viewport in raw tangent space
+fn tangent(projection: &LeftRightTopBottom, [u, v]: [f32; 2]) -> [f32; 2] {
+	[
+		projection.left + u * (projection.right - projection.left),
+		projection.top + v * (projection.bottom - projection.top),
+	]
+}
+
+fn cross(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+	(b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+fn covered(mesh: &HiddenAreaMesh, p: [f32; 2]) -> bool {
+	mesh.triangles.iter().any(|&[a, b, c]| {
+		let d = [cross(a, b, p), cross(b, c, p), cross(c, a, p)];
+		d.iter().all(|&d| d >= -1e-6) || d.iter().all(|&d| d <= 1e-6)
+	})
+}
+
+/// In uv space, where whole viewport is 1
+fn area(mesh: &HiddenAreaMesh) -> f32 {
+	mesh.triangles
+		.iter()
+		.map(|&[a, b, c]| cross(a, b, c).abs() / 2.0)
+		.sum()
+}
+
+/// Fraction of viewport outside of aperture, by its grid of `n * n` points
+fn sampled_hidden(projection: &LeftRightTopBottom, aperture: f32, n: usize) -> f32 {
+	let mut hidden = 0;
+	for y in 0..n {
+		for x in 0..n {
+			let uv = [(x as f32 + 0.5) / n as f32, (y as f32 + 0.5) / n as f32];
+			let [tx, ty] = tangent(projection, uv);
+			if tx.hypot(ty) > aperture {
+				hidden += 1;
+			}
+		}
+	}
+	hidden as f32 / (n * n) as f32
+}
+
+fn check(projection: &LeftRightTopBottom, aperture: f32, mesh: &HiddenAreaMesh) {
+	for v in mesh.vertices() {
+		assert!(
+			(-1e-6..=1.0 + 1e-6).contains(&v[0]) && (-1e-6..=1.0 + 1e-6).contains(&v[1]),
+			"{v:?} is outside of viewport"
+		);
+	}
+	assert_eq!(mesh.vertices().count(), mesh.triangles.len() * 3);
+	// Chords of 64-gon are inside of aperture, so mesh covers a bit more than it should
+	let expected = sampled_hidden(projection, aperture, 1000);
+	let area = area(mesh);
+	assert!(
+		area >= expected - 0.001 && area <= expected + 0.01,
+		"mesh covers {area} of viewport, while {expected} is outside of aperture"
+	);
+	for y in 0..50 {
+		for x in 0..50 {
+			let uv = [(x as f32 + 0.5) / 50.0, (y as f32 + 0.5) / 50.0];
+			let [tx, ty] = tangent(projection, uv);
+			let r = tx.hypot(ty);
+			// Chord sag of 64-gon is ~0.12% of radius
+			if r > aperture * 1.001 {
+				assert!(
+					covered(mesh, uv),
+					"{uv:?} is visible, while outside of aperture"
+				);
+			} else if r < aperture * 0.998 {
+				assert!(
+					!covered(mesh, uv),
+					"{uv:?} is hidden, while inside of aperture"
+				);
+			}
+		}
+	}
+}
+
+/// Aperture fit to the farthest edge hides corners only
+#[test]
+fn corners() {
+	let left = projection(Eye::Left);
+	let mesh = hidden_area_mesh(&left, None, SEGMENTS);
+	// Left edge is the farthest one
+	check(&left, 1.667393, &mesh);
+	// Outer corners are hidden, inner ones are visible through the lens
+	assert!(covered(&mesh, [0.0, 0.0]));
+	assert!(covered(&mesh, [0.0, 1.0]));
+	assert!(!covered(&mesh, [0.999, 0.001]));
+	assert!(!covered(&mesh, [0.999, 0.999]));
+	assert!(area(&mesh) > 0.05);
+
+	let right = projection(Eye::Right);
+	let mesh = hidden_area_mesh(&right, None, SEGMENTS);
+	check(&right, 1.635135, &mesh);
+	assert!(covered(&mesh, [1.0, 0.0]));
+	assert!(!covered(&mesh, [0.001, 0.999]));
+}
+
+#[test]
+fn explicit_aperture() {
+	for eye in [Eye::Left, Eye::Right] {
+		let projection = projection(eye);
+		for aperture in [0.9, 1.2, 1.5] {
+			let mesh = hidden_area_mesh(&projection, Some(aperture), SEGMENTS);
+			check(&projection, aperture, &mesh);
+		}
+	}
+	// Optical center is visible no matter how small aperture is
+	let left = projection(Eye::Left);
+	let mesh = hidden_area_mesh(&left, Some(0.1), SEGMENTS);
+	let center = [
+		-left.left / (left.right - left.left),
+		-left.top / (left.bottom - left.top),
+	];
+	assert!(!covered(&mesh, center));
+	assert!(covered(&mesh, [center[0] + 0.05, center[1]]));
+}
+
+#[test]
+fn nothing_hidden() {
+	let left = projection(Eye::Left);
+	// Viewport corner is the farthest point
+	let corner = left.left.hypot(left.top);
+	let mesh = hidden_area_mesh(&left, Some(corner + 0.01), SEGMENTS);
+	assert!(mesh.triangles.is_empty());
+}
+
+/// Same corners are hidden with too few segments, since they are always sampled
+#[test]
+fn few_segments() {
+	let left = projection(Eye::Left);
+	for segments in [0, 3, 4, 8] {
+		let mesh = hidden_area_mesh(&left, None, segments);
+		assert!(covered(&mesh, [0.0, 0.0]), "{segments} segments");
+		assert!(covered(&mesh, [0.0, 1.0]), "{segments} segments");
+		assert!(!covered(&mesh, [0.999, 0.999]), "{segments} segments");
+	}
+}
@@ -0,0 +1,130 @@
+//! Projection from precomputed intrinsics, expected values are worked out by hand
+use lens_geometry::{FallbackLens, LensIntrinsics};
+use lens_protocol::{Eye, LeftRightTopBottom, LensClient, StubClient};
+use serde_json::{json, Value};
+
+/// `inhouse_lens_correction` of `vive-v1-padded.bin` fixture of vive-protocol
+fn fixture_config() -> Value {
+	json!({
+		"left": {"intrinsic": [0.6, 0, -0.02, 0, 0.6, 0, 0, 0], "grow_for_undistort": [0, 0, 0, 0]},
+		"right": {"intrinsic": [0.6, 0, 0.02, 0, 0.6, 0, 0, 0], "grow_for_undistort": [0, 0, 0, 0]},
+	})
+}
+
+#[track_caller]
+fn assert_close(actual: LeftRightTopBottom, expected: [f32; 4]) {
+	let actual = [actual.left, actual.right, actual.top, actual.bottom];
+	for (a, e) in actual.iter().zip(expected) {
+		assert!((a - e).abs() < 1e-5, "{actual:?} != {expected:?}");
+	}
+}
+
+#[test]
+fn fixture_projection() {
+	let config = fixture_config();
+	let left = LensIntrinsics::from_config(&config, Eye::Left).expect("left");
+	let right = LensIntrinsics::from_config(&config, Eye::Right).expect("right");
+	// (-1 - cx) / fx, (1 - cx) / fx, (1 - cy) / fy, (-1 - cy) / fy
+	assert_close(
+		left.projection_raw(),
+		[-0.98 / 0.6, 1.02 / 0.6, 1.0 / 0.6, -1.0 / 0.6],
+	);
+	// Fixture eyes have mirrored cx, so are their projections
+	assert_close(
+		right.projection_raw(),
+		[-1.02 / 0.6, 0.98 / 0.6, 1.0 / 0.6, -1.0 / 0.6],
+	);
+}
+
+#[test]
+fn grow_for_undistort() {
+	let intrinsics = LensIntrinsics {
+		intrinsic: [0.6, 0.0, -0.02, 0.0, 0.5, 0.1, 0.0, 0.0],
+		grow_for_undistort: [0.1, 0.2, 0.05, 0.0],
+	};
+	assert_close(intrinsics.projection_raw(), [-1.796667, 2.04, 1.89, -2.2]);
+}
+
+/// Intrinsics for tangents StubClient answers with, fx and cx from left/right, fy and cy from
+/// top/bottom, fy being negative, as top is above bottom there
+#[test]
+fn stub_projection() {
+	for eye in [Eye::Left, Eye::Right] {
+		let p = StubClient.project(eye).unwrap();
+		let fx = 2.0 / (p.right - p.left);
+		let cx = -(p.right + p.left) / (p.right - p.left);
+		let fy = 2.0 / (p.top - p.bottom);
+		let cy = -(p.top + p.bottom) / (p.top - p.bottom);
+		let intrinsics = LensIntrinsics {
+			intrinsic: [fx, 0.0, cx, 0.0, fy, cy, 0.0, 0.0],
+			grow_for_undistort: [0.0; 4],
+		};
+		assert!(fy < 0.0);
+		assert_close(
+			intrinsics.projection_raw(),
+			[p.left, p.right, p.top, p.bottom],
+		);
+	}
+}
+
+#[test]
+fn fallback_uses_intrinsics() {
+	let config = fixture_config();
+	let lens = FallbackLens::new(&config);
+	for eye in [Eye::Left, Eye::Right] {
+		let expected = LensIntrinsics::from_config(&config, eye)
+			.unwrap()
+			.projection_raw();
+		assert_close(
+			lens.project(eye).unwrap(),
+			[expected.left, expected.right, expected.top, expected.bottom],
+		);
+	}
+}
+
+#[test]
+fn incomplete_config() {
+	let mut config = fixture_config();
+	config["right"]["intrinsic"] = json!([0.6, 0, 0.02, 0, 0.6, 0, 0]);
+	assert!(LensIntrinsics::from_config(&config, Eye::Left).is_some());
+	assert!(LensIntrinsics::from_config(&config, Eye::Right).is_none());
+	config["right"]["intrinsic"] = json!([0.6, 0, 0.02, 0, "0.6", 0, 0, 0]);
+	assert!(LensIntrinsics::from_config(&config, Eye::Right).is_none());
+	config["right"] = json!({"intrinsic": [0.6, 0, 0.02, 0, 0.6, 0, 0, 0]});
+	assert!(LensIntrinsics::from_config(&config, Eye::Right).is_none());
+	assert!(LensIntrinsics::from_config(&json!({}), Eye::Left).is_none());
+}
+
+#[test]
+fn render_target_size() {
+	let config = fixture_config();
+	let left = LensIntrinsics::from_config(&config, Eye::Left).unwrap();
+	// Nothing to grow, panel resolution as is
+	assert_eq!(
+		left.recommended_render_target_size(4896, 2448),
+		(4896, 2448)
+	);
+	assert_eq!(
+		left.recommended_render_target_size(2448, 1224),
+		(2448, 1224)
+	);
+
+	let grown = LensIntrinsics {
+		grow_for_undistort: [0.1, 0.1, 0.05, 0.05],
+		..left
+	};
+	// Eye is 2448 * 1.1 = 2692.8 wide, and 2448 * 1.05 = 2570.4 tall, rounded up to multiple of 4
+	assert_eq!(
+		grown.recommended_render_target_size(4896, 2448),
+		(5392, 2572)
+	);
+	// Average of left and right growth
+	let uneven = LensIntrinsics {
+		grow_for_undistort: [0.0, 0.2, 0.1, 0.0],
+		..left
+	};
+	assert_eq!(
+		uneven.recommended_render_target_size(4896, 2448),
+		(5392, 2572)
+	);
+}
@@ -1,13 +1,20 @@
 use std::{
 	cell::RefCell,
-	io::{self, Read, StdinLock, StdoutLock, Write},
-	process::{Child, ChildStdin, ChildStdout},
+	fs::File,
+	io::{self, Read, Seek, SeekFrom, Write},
+	ops::BitOr,
+	process::Child,
 	result,
+	time::{Duration, Instant},
 };
 
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
 
+mod transport;
+pub use transport::{Address, ChildTransport, Listener, SocketTransport, Transport};
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
 	#[error("postcard error: {0}")]
@@ -18,17 +25,56 @@ pub enum Error {
 	MissingPipe,
 	#[error("failed to ping server")]
 	PingFailed,
+	#[error("lens server is not running")]
+	Unavailable,
+	#[error("lens server pipe was closed mid-message")]
+	Truncated,
+	#[error("lens server did not answer in time")]
+	Timeout,
+	#[error("lens protocol message is corrupted: {0}")]
+	Corrupted(String),
+	#[error("lens config is not supported: {0}")]
+	UnsupportedConfig(String),
+	#[error("not in lens server recording: {0}")]
+	NotRecorded(String),
+	#[error("invalid lens server address {0:?}, expected unix:<path> or tcp:<host>:<port>")]
+	InvalidAddress(String),
+	#[error("lens server speaks protocol {theirs} (down to {theirs_min}), client speaks {ours} (down to {ours_min}), update both from the same release")]
+	Incompatible {
+		ours: u32,
+		ours_min: u32,
+		theirs: u32,
+		theirs_min: u32,
+	},
 }
 type Result<T, E = Error> = result::Result<T, E>;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct DistortOutput {
 	pub red: [f32; 2],
 	pub green: [f32; 2],
 	pub blue: [f32; 2],
 }
+impl DistortOutput {
+	/// Green is the geometric part, red and blue are apart from it by chromatic aberration
+	pub fn chromatic_aberration(&self) -> ChromaticAberration {
+		let offset = |c: [f32; 2]| [c[0] - self.green[0], c[1] - self.green[1]];
+		ChromaticAberration {
+			red: offset(self.red),
+			blue: offset(self.blue),
+		}
+	}
+}
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Offsets of red and blue sampling positions from green one, in uv units, for compositors which
+/// correct chromatic aberration on their own, and only need geometric distortion (green) as a mesh
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChromaticAberration {
+	pub red: [f32; 2],
+	pub blue: [f32; 2],
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct LeftRightTopBottom {
 	pub left: f32,
 	pub right: f32,
@@ -63,6 +109,99 @@ mod json {
 	}
 }
 
+/// Bumped on every change of message format, servers without handshake are version 0
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Oldest version, which is still spoken
+pub const MIN_PROTOCOL_VERSION: u32 = 0;
+/// Handshake starts with ping of this value. Servers without handshake just echo it, newer ones
+/// reply with its complement, and then expect [`Request::Hello`]
+pub const HANDSHAKE_PING: u32 = 0x5650_3248;
+
+/// Optional protocol features, only used when both sides have them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Capabilities(pub u32);
+impl Capabilities {
+	pub const NONE: Self = Self(0);
+	/// Every message after handshake carries CRC32 of its payload
+	pub const CHECKSUM: Self = Self(1);
+	/// [`Request::DistortBatch`] is understood
+	pub const BATCH: Self = Self(2);
+	/// Big payloads are zlib-compressed, see [`read_message`]
+	pub const COMPRESSION: Self = Self(4);
+	/// [`Request::AttachShm`] is understood, batches may go through shared memory
+	pub const SHARED_MEMORY: Self = Self(8);
+
+	pub fn contains(self, other: Self) -> bool {
+		self.0 & other.0 == other.0
+	}
+	pub fn intersection(self, other: Self) -> Self {
+		Self(self.0 & other.0)
+	}
+}
+impl BitOr for Capabilities {
+	type Output = Self;
+	fn bitor(self, rhs: Self) -> Self {
+		Self(self.0 | rhs.0)
+	}
+}
+
+/// What one side supports, exchanged in both directions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hello {
+	pub version: u32,
+	pub min_version: u32,
+	pub capabilities: Capabilities,
+}
+
+/// Version and features, both sides have agreed on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Negotiated {
+	pub version: u32,
+	pub capabilities: Capabilities,
+}
+impl Negotiated {
+	/// Server without handshake
+	pub const LEGACY: Self = Self {
+		version: 0,
+		capabilities: Capabilities::NONE,
+	};
+
+	/// Newest version both sides speak
+	pub fn new(ours: Hello, theirs: Hello) -> Result<Self> {
+		let version = ours.version.min(theirs.version);
+		if version < ours.min_version.max(theirs.min_version) {
+			return Err(Error::Incompatible {
+				ours: ours.version,
+				ours_min: ours.min_version,
+				theirs: theirs.version,
+				theirs_min: theirs.min_version,
+			});
+		}
+		Ok(Self {
+			version,
+			capabilities: ours.capabilities.intersection(theirs.capabilities),
+		})
+	}
+	pub fn supports(&self, capabilities: Capabilities) -> bool {
+		self.capabilities.contains(capabilities)
+	}
+	pub fn framing(&self) -> Framing {
+		Framing {
+			checksum: self.supports(Capabilities::CHECKSUM),
+			compression: self.supports(Capabilities::COMPRESSION),
+		}
+	}
+}
+
+/// How messages are wrapped, handshake messages have neither
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Framing {
+	pub checksum: bool,
+	pub compression: bool,
+}
+
+/// New variants only go to the end, postcard encodes variant by its index
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Request {
 	Init(#[serde(with = "json")] Value),
@@ -70,6 +209,74 @@ pub enum Request {
 	Distort(Eye, [f32; 2]),
 	ProjectionRaw(Eye),
 	Exit,
+	/// Since version 1, only sent after [`HANDSHAKE_PING`] is answered with its complement
+	Hello(Hello),
+	/// With [`Capabilities::BATCH`], answered with output for every point, in the same order
+	DistortBatch(Vec<(Eye, [f32; 2])>),
+	/// With [`Capabilities::SHARED_MEMORY`], path of client memfd (`/proc/<pid>/fd/<fd>`), answered
+	/// with whether server has opened it. Answered by server itself
+	AttachShm(String),
+	/// Same as [`Request::DistortBatch`], but points are in attached shared memory, and output
+	/// is put there too, answered with its [`ShmPayload`]
+	DistortBatchShm(ShmPayload),
+}
+
+/// Where payload in shared memory ends, it always starts at offset 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShmPayload {
+	pub len: u32,
+	pub crc: u32,
+}
+
+fn write_shm(file: &mut File, data: &[u8]) -> Result<ShmPayload> {
+	if data.len() > MAX_MESSAGE_SIZE as usize {
+		return Err(Error::Corrupted(format!(
+			"shared payload is {} bytes",
+			data.len()
+		)));
+	}
+	file.seek(SeekFrom::Start(0))?;
+	file.write_all(data)?;
+	Ok(ShmPayload {
+		len: data.len() as u32,
+		crc: crc32fast::hash(data),
+	})
+}
+fn read_shm(file: &mut File, payload: ShmPayload) -> Result<Vec<u8>> {
+	if payload.len > MAX_MESSAGE_SIZE {
+		return Err(Error::Corrupted(format!(
+			"shared payload is {} bytes",
+			payload.len
+		)));
+	}
+	file.seek(SeekFrom::Start(0))?;
+	let mut data = vec![0; payload.len as usize];
+	read_exact(file, &mut data)?;
+	let actual = crc32fast::hash(&data);
+	if actual != payload.crc {
+		return Err(Error::Corrupted(format!(
+			"shared payload checksum is {actual:08x}, expected {:08x}",
+			payload.crc
+		)));
+	}
+	Ok(data)
+}
+
+/// Anonymous file, which lens server opens through `/proc`, wine sees unix filesystem as `Z:`
+#[cfg(target_os = "linux")]
+fn create_shm() -> io::Result<(File, String)> {
+	use std::os::fd::{AsRawFd, FromRawFd};
+	let fd = unsafe { libc::memfd_create(b"lens-protocol\0".as_ptr().cast(), libc::MFD_CLOEXEC) };
+	if fd == -1 {
+		return Err(io::Error::last_os_error());
+	}
+	let file = unsafe { File::from_raw_fd(fd) };
+	let path = format!("/proc/{}/fd/{}", std::process::id(), file.as_raw_fd());
+	Ok((file, path))
+}
+#[cfg(not(target_os = "linux"))]
+fn create_shm() -> io::Result<(File, String)> {
+	Err(io::ErrorKind::Unsupported.into())
 }
 
 pub trait LensClient {
@@ -77,7 +284,18 @@ pub trait LensClient {
 	fn project(&self, eye: Eye) -> Result<LeftRightTopBottom>;
 	fn matrix_needs_inversion(&self) -> Result<bool>;
 	fn distort(&self, eye: Eye, uv: [f32; 2]) -> Result<DistortOutput>;
+	/// Many points at once, for both eyes, clients with costly round trips do it in one
+	fn distort_batch(&self, points: &[(Eye, [f32; 2])]) -> Result<Vec<DistortOutput>> {
+		points
+			.iter()
+			.map(|&(eye, uv)| self.distort(eye, uv))
+			.collect()
+	}
 	fn set_config(&self, config: Value) -> Result<()>;
+	/// Lens server output doesn't depend on IPD, only clients which keep results per IPD care
+	fn set_ipd(&self, _ipd_meters: f32) -> Result<()> {
+		Ok(())
+	}
 	fn exit(&self) -> Result<()>;
 }
 
@@ -125,21 +343,65 @@ impl LensClient for StubClient {
 	}
 }
 
+/// How long server may take to answer, `None` waits forever
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timeouts {
+	/// Includes wine startup, which may create prefix on first run
+	pub handshake: Option<Duration>,
+	pub request: Option<Duration>,
+}
+
+/// Fails reads with [`io::ErrorKind::TimedOut`] after deadline
+struct Deadline<'a> {
+	read: &'a mut dyn Transport,
+	until: Option<Instant>,
+}
+impl Read for Deadline<'_> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		if let Some(until) = self.until {
+			self.read.wait_readable(until)?;
+		}
+		self.read.read(buf)
+	}
+}
+
 pub struct ServerClientInner {
-	stdin: ChildStdin,
-	stdout: ChildStdout,
-	child: Child,
+	transport: Box<dyn Transport>,
+	negotiated: Negotiated,
+	timeouts: Timeouts,
+	/// Attached to server, batches go through it
+	shm: Option<File>,
 }
 impl ServerClientInner {
+	fn framing(&self) -> Framing {
+		self.negotiated.framing()
+	}
 	fn request<R: DeserializeOwned>(&mut self, request: &Request) -> Result<R> {
+		let timeout = match request {
+			Request::Ping(HANDSHAKE_PING) | Request::Hello(_) => self.timeouts.handshake,
+			_ => self.timeouts.request,
+		};
 		self.send(request)?;
-		let data = read_message(&mut self.stdout)?;
+		let framing = self.framing();
+		let mut read = Deadline {
+			read: &mut *self.transport,
+			until: timeout.map(|t| Instant::now() + t),
+		};
+		let data = match read_message(&mut read, framing) {
+			Ok(data) => data,
+			Err(Error::Timeout) => {
+				self.transport.abort();
+				return Err(Error::Timeout);
+			}
+			Err(e) => return Err(e),
+		};
 		Ok(postcard::from_bytes(&data)?)
 	}
 	pub fn send(&mut self, request: &Request) -> Result<()> {
 		let data = postcard::to_stdvec(&request)?;
-		write_message(&mut self.stdin, &data)?;
-		self.stdin.flush()?;
+		let framing = self.framing();
+		write_message(&mut self.transport, &data, framing)?;
+		self.transport.flush()?;
 		Ok(())
 	}
 }
@@ -158,6 +420,25 @@ impl LensClient for ServerClient {
 	fn distort(&self, eye: Eye, uv: [f32; 2]) -> Result<DistortOutput> {
 		self.0.borrow_mut().request(&Request::Distort(eye, uv))
 	}
+	fn distort_batch(&self, points: &[(Eye, [f32; 2])]) -> Result<Vec<DistortOutput>> {
+		let mut inner = self.0.borrow_mut();
+		if !inner.negotiated.supports(Capabilities::BATCH) {
+			return points
+				.iter()
+				.map(|&(eye, uv)| inner.request(&Request::Distort(eye, uv)))
+				.collect();
+		}
+		if let Some(mut shm) = inner.shm.take() {
+			let res: Result<_> = (|| {
+				let request = write_shm(&mut shm, &postcard::to_stdvec(points)?)?;
+				let reply = inner.request(&Request::DistortBatchShm(request))?;
+				Ok(postcard::from_bytes(&read_shm(&mut shm, reply)?)?)
+			})();
+			inner.shm = Some(shm);
+			return res;
+		}
+		inner.request(&Request::DistortBatch(points.to_vec()))
+	}
 
 	fn set_config(&self, config: Value) -> Result<()> {
 		self.0.borrow_mut().send(&Request::Init(config))?;
@@ -167,26 +448,85 @@ impl LensClient for ServerClient {
 	fn exit(&self) -> Result<()> {
 		// Flush may fail in case if exit succeeded
 		let _ = self.0.borrow_mut().send(&Request::Exit);
-		self.0.borrow_mut().child.wait().unwrap();
+		self.0.borrow_mut().transport.finish()?;
 		Ok(())
 	}
 }
 impl ServerClient {
-	pub fn open(mut child: Child, config: Value) -> Result<Self> {
-		let res = Self(RefCell::new(ServerClientInner {
-			stdin: child.stdin.take().ok_or(Error::MissingPipe)?,
-			stdout: child.stdout.take().ok_or(Error::MissingPipe)?,
-			child,
-		}));
-
-		if res.ping(0x12345678)? != 0x12345678 {
-			return Err(Error::MissingPipe);
+	/// Child is killed if it doesn't answer handshake
+	pub fn open(child: Child, config: Value, timeouts: Timeouts) -> Result<Self> {
+		Self::connect(Box::new(ChildTransport::new(child)?), config, timeouts)
+	}
+	/// Server listening on a socket, see [`Listener`]
+	pub fn connect_to(address: &Address, config: Value, timeouts: Timeouts) -> Result<Self> {
+		Self::connect(
+			Box::new(SocketTransport::connect(address)?),
+			config,
+			timeouts,
+		)
+	}
+	pub fn connect(
+		transport: Box<dyn Transport>,
+		config: Value,
+		timeouts: Timeouts,
+	) -> Result<Self> {
+		let mut inner = ServerClientInner {
+			transport,
+			negotiated: Negotiated::LEGACY,
+			timeouts,
+			shm: None,
+		};
+		inner.negotiated = match Self::handshake(&mut inner) {
+			Ok(negotiated) => negotiated,
+			Err(e) => {
+				inner.transport.abort();
+				return Err(e);
+			}
+		};
+		if inner.negotiated.supports(Capabilities::SHARED_MEMORY) && inner.transport.local() {
+			inner.shm = Self::attach_shm(&mut inner)?;
 		}
+		let res = Self(RefCell::new(inner));
 		res.set_config(config)?;
 
 		Ok(res)
 	}
+	fn handshake(inner: &mut ServerClientInner) -> Result<Negotiated> {
+		let reply: u32 = inner.request(&Request::Ping(HANDSHAKE_PING))?;
+		if reply == HANDSHAKE_PING {
+			return Ok(Negotiated::LEGACY);
+		}
+		if reply != !HANDSHAKE_PING {
+			return Err(Error::PingFailed);
+		}
+		let ours = Hello {
+			version: PROTOCOL_VERSION,
+			min_version: MIN_PROTOCOL_VERSION,
+			capabilities: CLIENT_CAPABILITIES,
+		};
+		let theirs: Hello = inner.request(&Request::Hello(ours))?;
+		Negotiated::new(ours, theirs)
+	}
+	/// Batches stay on the pipe, if either side can't have it
+	fn attach_shm(inner: &mut ServerClientInner) -> Result<Option<File>> {
+		let Ok((file, path)) = create_shm() else {
+			return Ok(None);
+		};
+		let attached: bool = inner.request(&Request::AttachShm(path))?;
+		Ok(attached.then_some(file))
+	}
+	pub fn negotiated(&self) -> Negotiated {
+		self.0.borrow().negotiated
+	}
+	/// Whether batches go through shared memory
+	pub fn shared_memory(&self) -> bool {
+		self.0.borrow().shm.is_some()
+	}
 	pub fn exit(&mut self) {}
+	/// For server which stopped responding, pipes are closed with it
+	pub fn kill(self) {
+		self.0.borrow_mut().transport.abort();
+	}
 }
 impl Drop for ServerClient {
 	fn drop(&mut self) {
@@ -200,26 +540,117 @@ extern "C" {
 	fn _setmode(fd: i32, mode: i32) -> i32;
 }
 
-pub fn read_message(read: &mut impl Read) -> Result<Vec<u8>> {
+/// Smaller payloads aren't worth compressing, only distortion batches are this big
+const COMPRESS_FROM: usize = 4096;
+const ENCODING_RAW: u8 = 0;
+const ENCODING_ZLIB: u8 = 1;
+
+/// Largest message, anything longer is a garbage length from a pipe that went out of sync
+const MAX_MESSAGE_SIZE: u32 = 64 << 20;
+
+fn read_exact(read: &mut impl Read, buf: &mut [u8]) -> Result<()> {
+	read.read_exact(buf).map_err(|e| match e.kind() {
+		io::ErrorKind::UnexpectedEof => Error::Truncated,
+		io::ErrorKind::TimedOut => Error::Timeout,
+		_ => Error::Io(e),
+	})
+}
+
+/// Big-endian length, payload, then big-endian CRC32 of payload with checksum. With compression,
+/// payload starts with encoding byte, and big ones are zlib-compressed
+pub fn read_message(read: &mut impl Read, framing: Framing) -> Result<Vec<u8>> {
 	let mut len_buf = [0; 4];
-	read.read_exact(&mut len_buf)?;
+	read_exact(read, &mut len_buf)?;
 	let len = u32::from_be_bytes(len_buf);
+	if len > MAX_MESSAGE_SIZE {
+		return Err(Error::Corrupted(format!("message length is {len}")));
+	}
 	// This protocol isn't talkative, its ok to allocate here.
 	let mut data = vec![0; len as usize];
-	read.read_exact(&mut data)?;
-	Ok(data)
+	read_exact(read, &mut data)?;
+	if framing.checksum {
+		let mut crc_buf = [0; 4];
+		read_exact(read, &mut crc_buf)?;
+		let expected = u32::from_be_bytes(crc_buf);
+		let actual = crc32fast::hash(&data);
+		if actual != expected {
+			return Err(Error::Corrupted(format!(
+				"checksum is {actual:08x}, expected {expected:08x}"
+			)));
+		}
+	}
+	if !framing.compression {
+		return Ok(data);
+	}
+	match data.first() {
+		Some(&ENCODING_RAW) => {
+			data.remove(0);
+			Ok(data)
+		}
+		Some(&ENCODING_ZLIB) => {
+			let mut out = Vec::new();
+			ZlibDecoder::new(&data[1..])
+				.take(MAX_MESSAGE_SIZE as u64 + 1)
+				.read_to_end(&mut out)
+				.map_err(|e| Error::Corrupted(format!("compressed payload: {e}")))?;
+			if out.len() > MAX_MESSAGE_SIZE as usize {
+				return Err(Error::Corrupted(
+					"compressed payload is too large".to_owned(),
+				));
+			}
+			Ok(out)
+		}
+		encoding => Err(Error::Corrupted(format!("payload encoding {encoding:?}"))),
+	}
 }
-pub fn write_message(write: &mut impl Write, v: &[u8]) -> Result<()> {
+pub fn write_message(write: &mut impl Write, v: &[u8], framing: Framing) -> Result<()> {
+	let encoded;
+	let v = if !framing.compression {
+		v
+	} else if v.len() < COMPRESS_FROM {
+		encoded = [&[ENCODING_RAW], v].concat();
+		&encoded
+	} else {
+		let mut encoder = ZlibEncoder::new(vec![ENCODING_ZLIB], Compression::fast());
+		encoder.write_all(v)?;
+		encoded = encoder.finish()?;
+		&encoded
+	};
 	write.write_all(&u32::to_be_bytes(v.len() as u32))?;
 	write.write_all(v)?;
+	if framing.checksum {
+		write.write_all(&crc32fast::hash(v).to_be_bytes())?;
+	}
 	Ok(())
 }
 
+/// Everything client is able to use
+const CLIENT_CAPABILITIES: Capabilities = Capabilities(
+	Capabilities::CHECKSUM.0
+		| Capabilities::BATCH.0
+		| Capabilities::COMPRESSION.0
+		| Capabilities::SHARED_MEMORY.0,
+);
+/// Everything server is able to serve
+const SERVER_CAPABILITIES: Capabilities = Capabilities(
+	Capabilities::CHECKSUM.0
+		| Capabilities::BATCH.0
+		| Capabilities::COMPRESSION.0
+		| Capabilities::SHARED_MEMORY.0,
+);
+
+/// Handshake is answered by the server itself, [`Server::recv`] only returns requests which need
+/// the library
 pub struct Server {
-	stdin: StdinLock<'static>,
-	stdout: StdoutLock<'static>,
+	read: Box<dyn Read>,
+	write: Box<dyn Write>,
+	negotiated: Negotiated,
+	shm: Option<File>,
+	/// Next reply goes to shared memory, as the request came from there
+	reply_in_shm: bool,
+	/// Stdio modes to restore
 	#[cfg(target_os = "windows")]
-	modes: (i32, i32),
+	modes: Option<(i32, i32)>,
 }
 impl Server {
 	pub fn listen() -> Self {
@@ -234,33 +665,98 @@ impl Server {
 			(stdout, stdin)
 		};
 
-		let stdin = io::stdin().lock();
-		let stdout = io::stdout().lock();
-
 		Self {
-			stdin,
-			stdout,
+			read: Box::new(io::stdin().lock()),
+			write: Box::new(io::stdout().lock()),
+			negotiated: Negotiated::LEGACY,
+			shm: None,
+			reply_in_shm: false,
 			#[cfg(target_os = "windows")]
-			modes,
+			modes: Some(modes),
+		}
+	}
+	/// Waits for the next client, see [`SocketTransport`]
+	pub fn accept(listener: &Listener) -> Result<Self> {
+		let (read, write) = listener.accept()?;
+		Ok(Self::new(read, write))
+	}
+	fn new(read: Box<dyn Read>, write: Box<dyn Write>) -> Self {
+		Self {
+			read,
+			write,
+			negotiated: Negotiated::LEGACY,
+			shm: None,
+			reply_in_shm: false,
+			#[cfg(target_os = "windows")]
+			modes: None,
 		}
 	}
 	pub fn recv(&mut self) -> Result<Request> {
-		let data = read_message(&mut self.stdin)?;
-		Ok(postcard::from_bytes(&data)?)
+		loop {
+			let framing = self.framing();
+			let data = read_message(&mut self.read, framing)?;
+			match postcard::from_bytes(&data)? {
+				Request::Ping(HANDSHAKE_PING) => self.send(&!HANDSHAKE_PING)?,
+				Request::Hello(theirs) => {
+					let ours = Hello {
+						version: PROTOCOL_VERSION,
+						min_version: MIN_PROTOCOL_VERSION,
+						capabilities: SERVER_CAPABILITIES,
+					};
+					self.send(&ours)?;
+					// Incompatible client will see that from our hello too, and stop. Hello
+					// itself is still sent unchecked
+					if let Ok(negotiated) = Negotiated::new(ours, theirs) {
+						self.negotiated = negotiated;
+					}
+				}
+				Request::AttachShm(path) => {
+					self.shm = Self::open_shm(&path);
+					self.send(&self.shm.is_some())?;
+				}
+				Request::DistortBatchShm(payload) => {
+					let shm = self
+						.shm
+						.as_mut()
+						.ok_or_else(|| Error::Corrupted("no shared memory attached".to_owned()))?;
+					let points = postcard::from_bytes(&read_shm(shm, payload)?)?;
+					self.reply_in_shm = true;
+					return Ok(Request::DistortBatch(points));
+				}
+				request => return Ok(request),
+			}
+		}
+	}
+	fn open_shm(path: &str) -> Option<File> {
+		// Unix root is Z: drive in default prefixes
+		#[cfg(target_os = "windows")]
+		let path = format!("Z:{}", path.replace('/', "\\"));
+		File::options().read(true).write(true).open(path).ok()
+	}
+	pub fn negotiated(&self) -> Negotiated {
+		self.negotiated
+	}
+	fn framing(&self) -> Framing {
+		self.negotiated.framing()
 	}
 	pub fn send(&mut self, v: &impl Serialize) -> Result<()> {
-		let data = postcard::to_stdvec(&v)?;
-		write_message(&mut self.stdout, &data)?;
-		self.stdout.flush()?;
+		let mut data = postcard::to_stdvec(&v)?;
+		if std::mem::take(&mut self.reply_in_shm) {
+			let shm = self.shm.as_mut().expect("request came from shared memory");
+			data = postcard::to_stdvec(&write_shm(shm, &data)?)?;
+		}
+		let framing = self.framing();
+		write_message(&mut self.write, &data, framing)?;
+		self.write.flush()?;
 		Ok(())
 	}
 }
 impl Drop for Server {
 	fn drop(&mut self) {
 		#[cfg(target_os = "windows")]
-		{
-			let stdout = unsafe { _setmode(0, self.modes.0) };
-			let stdin = unsafe { _setmode(1, self.modes.1) };
+		if let Some(modes) = self.modes {
+			let stdout = unsafe { _setmode(0, modes.0) };
+			let stdin = unsafe { _setmode(1, modes.1) };
 			assert!(
 				stdout != -1 && stdin != -1,
 				"previous mode and fds should be correct"
@@ -0,0 +1,288 @@
+//! Where lens protocol messages go. Lens server is usually a child process, talking over its
+//! stdio, but it may also listen on a socket, to run on another machine, or inside of a container
+use std::{
+	fmt,
+	io::{self, BufReader, BufWriter, Read, Write},
+	net::{Shutdown, TcpListener, TcpStream},
+	process::{Child, ChildStdin, ChildStdout},
+	str::FromStr,
+	time::Instant,
+};
+#[cfg(unix)]
+use std::{
+	fs,
+	os::unix::{
+		fs::FileTypeExt,
+		net::{UnixListener, UnixStream},
+	},
+	path::PathBuf,
+};
+
+use crate::{Error, Result};
+
+/// `unix:<path>` or `tcp:<host>:<port>`, unix sockets are not available on windows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+	#[cfg(unix)]
+	Unix(PathBuf),
+	Tcp(String),
+}
+impl FromStr for Address {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self> {
+		match s.split_once(':') {
+			#[cfg(unix)]
+			Some(("unix", path)) if !path.is_empty() => Ok(Self::Unix(PathBuf::from(path))),
+			Some(("tcp", addr)) if addr.contains(':') => Ok(Self::Tcp(addr.to_owned())),
+			_ => Err(Error::InvalidAddress(s.to_owned())),
+		}
+	}
+}
+impl fmt::Display for Address {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			#[cfg(unix)]
+			Self::Unix(path) => write!(f, "unix:{}", path.display()),
+			Self::Tcp(addr) => write!(f, "tcp:{addr}"),
+		}
+	}
+}
+
+/// Client side of connection to lens server
+pub trait Transport: Read + Write {
+	/// Next read fails with [`io::ErrorKind::TimedOut`], if nothing arrives until `until`
+	fn wait_readable(&self, until: Instant) -> io::Result<()>;
+	/// Whether server sees the same filesystem and processes, shared memory is only offered then
+	fn local(&self) -> bool {
+		false
+	}
+	/// Server is not answering, its late answers would be taken for the next ones
+	fn abort(&mut self);
+	/// Called after [`crate::Request::Exit`] is sent
+	fn finish(&mut self) -> io::Result<()>;
+}
+
+/// Lens server as a child process, over its stdin/stdout
+pub struct ChildTransport {
+	stdin: ChildStdin,
+	stdout: ChildStdout,
+	child: Child,
+}
+impl ChildTransport {
+	pub fn new(mut child: Child) -> Result<Self> {
+		Ok(Self {
+			stdin: child.stdin.take().ok_or(Error::MissingPipe)?,
+			stdout: child.stdout.take().ok_or(Error::MissingPipe)?,
+			child,
+		})
+	}
+}
+impl Read for ChildTransport {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.stdout.read(buf)
+	}
+}
+impl Write for ChildTransport {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.stdin.write(buf)
+	}
+	fn flush(&mut self) -> io::Result<()> {
+		self.stdin.flush()
+	}
+}
+impl Transport for ChildTransport {
+	fn wait_readable(&self, until: Instant) -> io::Result<()> {
+		#[cfg(unix)]
+		{
+			use std::os::fd::AsRawFd;
+			wait_readable(self.stdout.as_raw_fd(), until)
+		}
+		#[cfg(not(unix))]
+		{
+			let _ = until;
+			Ok(())
+		}
+	}
+	fn local(&self) -> bool {
+		true
+	}
+	fn abort(&mut self) {
+		let _ = self.child.kill();
+		let _ = self.child.wait();
+	}
+	fn finish(&mut self) -> io::Result<()> {
+		self.child.wait()?;
+		Ok(())
+	}
+}
+
+enum Stream {
+	#[cfg(unix)]
+	Unix(UnixStream),
+	Tcp(TcpStream),
+}
+impl Stream {
+	fn connect(address: &Address) -> io::Result<Self> {
+		Ok(match address {
+			#[cfg(unix)]
+			Address::Unix(path) => Self::Unix(UnixStream::connect(path)?),
+			Address::Tcp(addr) => {
+				let stream = TcpStream::connect(addr)?;
+				// Messages are written in pieces, and answered right away
+				stream.set_nodelay(true)?;
+				Self::Tcp(stream)
+			}
+		})
+	}
+	fn try_clone(&self) -> io::Result<Self> {
+		Ok(match self {
+			#[cfg(unix)]
+			Self::Unix(s) => Self::Unix(s.try_clone()?),
+			Self::Tcp(s) => Self::Tcp(s.try_clone()?),
+		})
+	}
+	fn shutdown(&self) -> io::Result<()> {
+		match self {
+			#[cfg(unix)]
+			Self::Unix(s) => s.shutdown(Shutdown::Both),
+			Self::Tcp(s) => s.shutdown(Shutdown::Both),
+		}
+	}
+}
+impl Read for Stream {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		match self {
+			#[cfg(unix)]
+			Self::Unix(s) => s.read(buf),
+			Self::Tcp(s) => s.read(buf),
+		}
+	}
+}
+impl Write for Stream {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		match self {
+			#[cfg(unix)]
+			Self::Unix(s) => s.write(buf),
+			Self::Tcp(s) => s.write(buf),
+		}
+	}
+	fn flush(&mut self) -> io::Result<()> {
+		match self {
+			#[cfg(unix)]
+			Self::Unix(s) => s.flush(),
+			Self::Tcp(s) => s.flush(),
+		}
+	}
+}
+
+/// Lens server listening on a socket, see [`Listener`]. Reads are not buffered, so
+/// [`Transport::wait_readable`] sees everything which has not been read yet
+pub struct SocketTransport {
+	read: Stream,
+	write: BufWriter<Stream>,
+}
+impl SocketTransport {
+	pub fn connect(address: &Address) -> io::Result<Self> {
+		let read = Stream::connect(address)?;
+		let write = BufWriter::new(read.try_clone()?);
+		Ok(Self { read, write })
+	}
+}
+impl Read for SocketTransport {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.read.read(buf)
+	}
+}
+impl Write for SocketTransport {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.write.write(buf)
+	}
+	fn flush(&mut self) -> io::Result<()> {
+		self.write.flush()
+	}
+}
+impl Transport for SocketTransport {
+	fn wait_readable(&self, until: Instant) -> io::Result<()> {
+		#[cfg(unix)]
+		{
+			use std::os::fd::AsRawFd;
+			let fd = match &self.read {
+				Stream::Unix(s) => s.as_raw_fd(),
+				Stream::Tcp(s) => s.as_raw_fd(),
+			};
+			wait_readable(fd, until)
+		}
+		#[cfg(not(unix))]
+		{
+			let _ = until;
+			Ok(())
+		}
+	}
+	fn abort(&mut self) {
+		let _ = self.read.shutdown();
+	}
+	/// Server keeps listening for the next client
+	fn finish(&mut self) -> io::Result<()> {
+		self.read.shutdown()
+	}
+}
+
+/// Server side of [`SocketTransport`], clients are served one at a time
+pub enum Listener {
+	#[cfg(unix)]
+	Unix(UnixListener),
+	Tcp(TcpListener),
+}
+impl Listener {
+	pub fn bind(address: &Address) -> io::Result<Self> {
+		Ok(match address {
+			#[cfg(unix)]
+			Address::Unix(path) => {
+				// Left by previous run, anything else at the path is kept
+				if fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+					fs::remove_file(path)?;
+				}
+				Self::Unix(UnixListener::bind(path)?)
+			}
+			Address::Tcp(addr) => Self::Tcp(TcpListener::bind(addr)?),
+		})
+	}
+	/// Read and write halves of the next connection
+	pub(crate) fn accept(&self) -> io::Result<(Box<dyn Read>, Box<dyn Write>)> {
+		let stream = match self {
+			#[cfg(unix)]
+			Self::Unix(l) => Stream::Unix(l.accept()?.0),
+			Self::Tcp(l) => {
+				let stream = l.accept()?.0;
+				stream.set_nodelay(true)?;
+				Stream::Tcp(stream)
+			}
+		};
+		let write = BufWriter::new(stream.try_clone()?);
+		Ok((Box::new(BufReader::new(stream)), Box::new(write)))
+	}
+}
+
+#[cfg(unix)]
+fn wait_readable(fd: std::os::fd::RawFd, until: Instant) -> io::Result<()> {
+	loop {
+		let left = until.saturating_duration_since(Instant::now());
+		let mut fd = libc::pollfd {
+			fd,
+			events: libc::POLLIN,
+			revents: 0,
+		};
+		let timeout = left.as_millis().min(i32::MAX as u128) as i32;
+		match unsafe { libc::poll(&mut fd, 1, timeout) } {
+			0 => return Err(io::ErrorKind::TimedOut.into()),
+			-1 => {
+				let e = io::Error::last_os_error();
+				if e.kind() != io::ErrorKind::Interrupted {
+					return Err(e);
+				}
+			}
+			_ => return Ok(()),
+		}
+	}
+}
@@ -0,0 +1,463 @@
+//! Message framing, handshake with current and legacy servers, and transports. Servers run on
+//! threads of the test, over unix sockets, as lens server would over its stdio
+use std::{
+	env, fs,
+	io::{self, Cursor, Read, Write},
+	net::TcpListener,
+	os::unix::net::UnixStream,
+	path::PathBuf,
+	process,
+	sync::mpsc,
+	thread::{self, JoinHandle},
+	time::{Duration, Instant},
+};
+
+use lens_protocol::{
+	read_message, write_message, Address, Capabilities, DistortOutput, Error, Eye, Framing, Hello,
+	LensClient, Listener, Negotiated, Request, Server, ServerClient, SocketTransport, StubClient,
+	Timeouts, Transport, HANDSHAKE_PING, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+use serde_json::json;
+
+const FRAMINGS: [Framing; 4] = [
+	Framing {
+		checksum: false,
+		compression: false,
+	},
+	Framing {
+		checksum: true,
+		compression: false,
+	},
+	Framing {
+		checksum: false,
+		compression: true,
+	},
+	Framing {
+		checksum: true,
+		compression: true,
+	},
+];
+/// Same as in lens-protocol, longer messages are taken for a pipe out of sync
+const MAX_MESSAGE_SIZE: u32 = 64 << 20;
+const TIMEOUTS: Timeouts = Timeouts {
+	handshake: Some(Duration::from_secs(10)),
+	request: Some(Duration::from_secs(10)),
+};
+
+fn socket(name: &str) -> (Address, PathBuf) {
+	let path = env::temp_dir().join(format!("lens-protocol-{}-{name}.sock", process::id()));
+	(Address::Unix(path.clone()), path)
+}
+
+fn encoded(payload: &[u8], framing: Framing) -> Vec<u8> {
+	let mut out = Vec::new();
+	write_message(&mut out, payload, framing).expect("write");
+	out
+}
+
+fn same(a: &DistortOutput, b: &DistortOutput) -> bool {
+	a.red == b.red && a.green == b.green && a.blue == b.blue
+}
+
+fn points() -> Vec<(Eye, [f32; 2])> {
+	(0..600)
+		.map(|i| {
+			let eye = if i % 2 == 0 { Eye::Left } else { Eye::Right };
+			(eye, [i as f32 / 600.0, 1.0 - i as f32 / 600.0])
+		})
+		.collect()
+}
+
+#[test]
+fn framing_round_trip() {
+	let small = b"lens".to_vec();
+	// Over compression threshold, and compresses well
+	let big = (0..20_000).map(|i| (i % 7) as u8).collect::<Vec<_>>();
+	for framing in FRAMINGS {
+		for payload in [Vec::new(), small.clone(), big.clone()] {
+			let message = encoded(&payload, framing);
+			let mut read = Cursor::new(&message);
+			assert_eq!(read_message(&mut read, framing).expect("read"), payload);
+			assert_eq!(read.position() as usize, message.len(), "{framing:?}");
+		}
+		let size = encoded(&big, framing).len();
+		match framing.compression {
+			true => assert!(size < big.len() / 4, "{framing:?} is {size} bytes"),
+			false => assert!(size > big.len(), "{framing:?} is {size} bytes"),
+		}
+	}
+}
+
+#[test]
+fn bad_checksum() {
+	for framing in FRAMINGS.into_iter().filter(|f| f.checksum) {
+		let mut message = encoded(b"distortion", framing);
+		message[6] ^= 0x10;
+		assert!(matches!(
+			read_message(&mut Cursor::new(&message), framing),
+			Err(Error::Corrupted(e)) if e.starts_with("checksum is")
+		));
+		// Checksum itself
+		let mut message = encoded(b"distortion", framing);
+		*message.last_mut().unwrap() ^= 1;
+		assert!(matches!(
+			read_message(&mut Cursor::new(&message), framing),
+			Err(Error::Corrupted(_))
+		));
+	}
+}
+
+#[test]
+fn message_size_limit() {
+	for framing in FRAMINGS {
+		// Nothing is allocated, or read, for length past the limit
+		let mut message = (MAX_MESSAGE_SIZE + 1).to_be_bytes().to_vec();
+		message.extend([0; 16]);
+		assert!(matches!(
+			read_message(&mut Cursor::new(&message), framing),
+			Err(Error::Corrupted(e)) if e == format!("message length is {}", MAX_MESSAGE_SIZE + 1)
+		));
+		// Limit itself is allowed, message is only short
+		let message = MAX_MESSAGE_SIZE.to_be_bytes();
+		assert!(matches!(
+			read_message(&mut Cursor::new(&message), framing),
+			Err(Error::Truncated)
+		));
+	}
+}
+
+#[test]
+fn truncated_and_unknown_encoding() {
+	for framing in FRAMINGS {
+		let message = encoded(b"projection", framing);
+		for len in 0..message.len() {
+			assert!(
+				matches!(
+					read_message(&mut Cursor::new(&message[..len]), framing),
+					Err(Error::Truncated)
+				),
+				"{framing:?} cut at {len}"
+			);
+		}
+	}
+	let framing = Framing {
+		checksum: false,
+		compression: true,
+	};
+	let message = encoded(&[7, 1, 2], Framing::default());
+	assert!(matches!(
+		read_message(&mut Cursor::new(&message), framing),
+		Err(Error::Corrupted(e)) if e == "payload encoding Some(7)"
+	));
+	let message = encoded(&[1, 0xde, 0xad], Framing::default());
+	assert!(matches!(
+		read_message(&mut Cursor::new(&message), framing),
+		Err(Error::Corrupted(e)) if e.starts_with("compressed payload")
+	));
+}
+
+#[test]
+fn negotiation() {
+	let ours = Hello {
+		version: PROTOCOL_VERSION,
+		min_version: MIN_PROTOCOL_VERSION,
+		capabilities: Capabilities::CHECKSUM | Capabilities::BATCH | Capabilities::COMPRESSION,
+	};
+	let older = Hello {
+		version: 0,
+		min_version: 0,
+		capabilities: Capabilities::CHECKSUM,
+	};
+	let negotiated = Negotiated::new(ours, older).expect("compatible");
+	assert_eq!(negotiated.version, 0);
+	assert_eq!(negotiated.capabilities, Capabilities::CHECKSUM);
+	assert_eq!(
+		negotiated.framing(),
+		Framing {
+			checksum: true,
+			compression: false
+		}
+	);
+	let newer = Hello {
+		version: PROTOCOL_VERSION + 2,
+		min_version: PROTOCOL_VERSION + 1,
+		capabilities: Capabilities::BATCH,
+	};
+	assert!(matches!(
+		Negotiated::new(ours, newer),
+		Err(Error::Incompatible { theirs, theirs_min, .. })
+			if theirs == PROTOCOL_VERSION + 2 && theirs_min == PROTOCOL_VERSION + 1
+	));
+	assert_eq!(Negotiated::LEGACY.framing(), Framing::default());
+}
+
+/// Lens server with [`StubClient`] in place of the library, sends every request it got once the
+/// client is gone
+fn serve(listener: Listener) -> JoinHandle<Vec<String>> {
+	thread::spawn(move || {
+		let mut server = Server::accept(&listener).expect("accept");
+		let mut seen = Vec::new();
+		loop {
+			let request = match server.recv() {
+				Ok(request) => request,
+				Err(Error::Truncated) => return seen,
+				Err(e) => panic!("server failed: {e}"),
+			};
+			seen.push(format!("{request:?}").split('(').next().unwrap().to_owned());
+			let lens = StubClient;
+			match request {
+				Request::Init(_) => {}
+				Request::Ping(v) => server.send(&v).unwrap(),
+				Request::ProjectionRaw(eye) => server.send(&lens.project(eye).unwrap()).unwrap(),
+				Request::Distort(eye, uv) => server.send(&lens.distort(eye, uv).unwrap()).unwrap(),
+				Request::DistortBatch(points) => {
+					server.send(&lens.distort_batch(&points).unwrap()).unwrap()
+				}
+				Request::Exit => return seen,
+				other => panic!("answered by server: {other:?}"),
+			}
+		}
+	})
+}
+
+fn check_answers(client: &ServerClient) {
+	assert_eq!(client.ping(42).expect("ping"), 42);
+	let projection = client.project(Eye::Right).expect("project");
+	assert_eq!(
+		projection.left,
+		StubClient.project(Eye::Right).unwrap().left
+	);
+	let points = points();
+	let batch = client.distort_batch(&points).expect("batch");
+	let expected = StubClient.distort_batch(&points).unwrap();
+	assert_eq!(batch.len(), points.len());
+	assert!(batch.iter().zip(&expected).all(|(a, b)| same(a, b)));
+}
+
+#[test]
+fn current_server() {
+	let (address, path) = socket("current");
+	let server = serve(Listener::bind(&address).expect("bind"));
+	let client = ServerClient::connect_to(&address, json!({"lens": 1}), TIMEOUTS).expect("connect");
+	assert_eq!(
+		client.negotiated(),
+		Negotiated {
+			version: PROTOCOL_VERSION,
+			capabilities: Capabilities::CHECKSUM
+				| Capabilities::BATCH
+				| Capabilities::COMPRESSION
+				| Capabilities::SHARED_MEMORY,
+		}
+	);
+	// Server may be on another machine
+	assert!(!client.shared_memory());
+	check_answers(&client);
+	client.exit().expect("exit");
+	let seen = server.join().expect("server");
+	assert_eq!(
+		seen,
+		["Init", "Ping", "ProjectionRaw", "DistortBatch", "Exit"]
+	);
+	fs::remove_file(path).ok();
+}
+
+/// Server from before handshake existed echoes every ping, and knows nothing of batches
+#[test]
+fn legacy_server() {
+	let (address, path) = socket("legacy");
+	let Listener::Unix(listener) = Listener::bind(&address).expect("bind") else {
+		unreachable!()
+	};
+	let server = thread::spawn(move || {
+		let (mut stream, _) = listener.accept().expect("accept");
+		let mut distorts = 0;
+		loop {
+			let data = match read_message(&mut stream, Framing::default()) {
+				Ok(data) => data,
+				Err(Error::Truncated) => return distorts,
+				Err(e) => panic!("legacy server failed: {e}"),
+			};
+			let reply = match postcard::from_bytes(&data).expect("request") {
+				Request::Ping(v) => postcard::to_stdvec(&v),
+				Request::Distort(eye, uv) => {
+					distorts += 1;
+					postcard::to_stdvec(&StubClient.distort(eye, uv).unwrap())
+				}
+				Request::ProjectionRaw(eye) => {
+					postcard::to_stdvec(&StubClient.project(eye).unwrap())
+				}
+				Request::Init(_) => continue,
+				Request::Exit => return distorts,
+				other => panic!("legacy server doesn't know {other:?}"),
+			}
+			.unwrap();
+			write_message(&mut stream, &reply, Framing::default()).unwrap();
+		}
+	});
+	let client = ServerClient::connect_to(&address, json!({}), TIMEOUTS).expect("connect");
+	assert_eq!(client.negotiated(), Negotiated::LEGACY);
+	check_answers(&client);
+	client.exit().expect("exit");
+	assert_eq!(server.join().expect("server"), points().len());
+	fs::remove_file(path).ok();
+}
+
+/// Reply to handshake ping, which is neither echo nor complement
+#[test]
+fn foreign_server() {
+	let (address, path) = socket("foreign");
+	let Listener::Unix(listener) = Listener::bind(&address).expect("bind") else {
+		unreachable!()
+	};
+	let server = thread::spawn(move || {
+		let (mut stream, _) = listener.accept().expect("accept");
+		read_message(&mut stream, Framing::default()).expect("ping");
+		let reply = postcard::to_stdvec(&(HANDSHAKE_PING ^ 1)).unwrap();
+		write_message(&mut stream, &reply, Framing::default()).unwrap();
+		// Until client hangs up
+		let _ = stream.read_to_end(&mut Vec::new());
+	});
+	assert!(matches!(
+		ServerClient::connect_to(&address, json!({}), TIMEOUTS),
+		Err(Error::PingFailed)
+	));
+	server.join().expect("server");
+	fs::remove_file(path).ok();
+}
+
+#[test]
+fn handshake_timeout() {
+	let (address, path) = socket("timeout");
+	let Listener::Unix(listener) = Listener::bind(&address).expect("bind") else {
+		unreachable!()
+	};
+	let (stop, stopped) = mpsc::channel();
+	let server = thread::spawn(move || {
+		let (_stream, _) = listener.accept().expect("accept");
+		// Never answers
+		stopped.recv().ok();
+	});
+	let timeouts = Timeouts {
+		handshake: Some(Duration::from_millis(200)),
+		request: None,
+	};
+	let start = Instant::now();
+	assert!(matches!(
+		ServerClient::connect_to(&address, json!({}), timeouts),
+		Err(Error::Timeout)
+	));
+	assert!(start.elapsed() < Duration::from_secs(5));
+	stop.send(()).unwrap();
+	server.join().expect("server");
+	fs::remove_file(path).ok();
+}
+
+/// Unix socket to a server in this process, which is local, so shared memory is offered
+struct LocalTransport(UnixStream);
+impl Read for LocalTransport {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.0.read(buf)
+	}
+}
+impl Write for LocalTransport {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.0.write(buf)
+	}
+	fn flush(&mut self) -> io::Result<()> {
+		self.0.flush()
+	}
+}
+impl Transport for LocalTransport {
+	fn wait_readable(&self, _until: Instant) -> io::Result<()> {
+		Ok(())
+	}
+	fn local(&self) -> bool {
+		true
+	}
+	fn abort(&mut self) {
+		let _ = self.0.shutdown(std::net::Shutdown::Both);
+	}
+	fn finish(&mut self) -> io::Result<()> {
+		self.0.shutdown(std::net::Shutdown::Both)
+	}
+}
+
+#[test]
+fn shared_memory() {
+	let (address, path) = socket("shm");
+	let server = serve(Listener::bind(&address).expect("bind"));
+	let stream = UnixStream::connect(&path).expect("connect");
+	let client = ServerClient::connect(Box::new(LocalTransport(stream)), json!({}), TIMEOUTS)
+		.expect("handshake");
+	assert!(client.shared_memory());
+	check_answers(&client);
+	// Batch of another size reuses the same memory
+	let points = &points()[..3];
+	let batch = client.distort_batch(points).expect("small batch");
+	assert_eq!(batch.len(), 3);
+	assert_eq!(batch[2].green, points[2].1);
+	client.exit().expect("exit");
+	let seen = server.join().expect("server");
+	// Shared memory batches reach server loop as plain ones
+	assert_eq!(
+		seen,
+		[
+			"Init",
+			"Ping",
+			"ProjectionRaw",
+			"DistortBatch",
+			"DistortBatch",
+			"Exit"
+		]
+	);
+	fs::remove_file(path).ok();
+}
+
+#[test]
+fn tcp_transport() {
+	let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+	let address: Address = format!("tcp:{}", listener.local_addr().unwrap())
+		.parse()
+		.expect("address");
+	let server = serve(Listener::Tcp(listener));
+	let transport = SocketTransport::connect(&address).expect("connect");
+	assert!(!transport.local());
+	let client =
+		ServerClient::connect(Box::new(transport), json!({}), TIMEOUTS).expect("handshake");
+	check_answers(&client);
+	client.exit().expect("exit");
+	server.join().expect("server");
+}
+
+#[test]
+fn addresses() {
+	for text in ["unix:/run/lens.sock", "tcp:127.0.0.1:7070", "tcp:host:1"] {
+		let address: Address = text.parse().expect(text);
+		assert_eq!(address.to_string(), text);
+	}
+	for text in ["unix:", "tcp:7070", "udp:host:1", "/run/lens.sock", ""] {
+		assert!(
+			matches!(text.parse::<Address>(), Err(Error::InvalidAddress(a)) if a == text),
+			"{text}"
+		);
+	}
+}
+
+#[test]
+fn stale_socket_is_replaced() {
+	let (address, path) = socket("stale");
+	drop(Listener::bind(&address).expect("bind"));
+	assert!(path.exists());
+	// Left by previous run
+	let server = serve(Listener::bind(&address).expect("bind again"));
+	let client = ServerClient::connect_to(&address, json!({}), TIMEOUTS).expect("connect");
+	client.exit().expect("exit");
+	server.join().expect("server");
+	fs::remove_file(&path).ok();
+
+	// Anything else is kept
+	fs::write(&path, b"not a socket").unwrap();
+	assert!(Listener::bind(&address).is_err());
+	assert_eq!(fs::read(&path).unwrap(), b"not a socket");
+	fs::remove_file(path).ok();
+}
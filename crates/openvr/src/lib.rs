@@ -19,35 +19,35 @@ type VkPhysicalDevice_T = ();
 
 #[derive(Clone, Copy)]
 pub union Union0 {
-	reserved: VREvent_Reserved_t,
-	controller: VREvent_Controller_t,
-	mouse: VREvent_Mouse_t,
-	scroll: VREvent_Scroll_t,
-	process: VREvent_Process_t,
-	notification: VREvent_Notification_t,
-	overlay: VREvent_Overlay_t,
-	status: VREvent_Status_t,
-	keyboard: VREvent_Keyboard_t,
-	ipd: VREvent_Ipd_t,
-	chaperone: VREvent_Chaperone_t,
-	performanceTest: VREvent_PerformanceTest_t,
-	touchPadMove: VREvent_TouchPadMove_t,
-	seatedZeroPoseReset: VREvent_SeatedZeroPoseReset_t,
-	screenshot: VREvent_Screenshot_t,
-	screenshotProgress: VREvent_ScreenshotProgress_t,
-	applicationLaunch: VREvent_ApplicationLaunch_t,
-	cameraSurface: VREvent_EditingCameraSurface_t,
-	messageOverlay: VREvent_MessageOverlay_t,
-	property: VREvent_Property_t,
-	hapticVibration: VREvent_HapticVibration_t,
-	webConsole: VREvent_WebConsole_t,
-	inputBinding: VREvent_InputBindingLoad_t,
-	actionManifest: VREvent_InputActionManifestLoad_t,
-	spatialAnchor: VREvent_SpatialAnchor_t,
-	progressUpdate: VREvent_ProgressUpdate_t,
-	showUi: VREvent_ShowUI_t,
-	showDevTools: VREvent_ShowDevTools_t,
-	hdcpError: VREvent_HDCPError_t,
+	pub reserved: VREvent_Reserved_t,
+	pub controller: VREvent_Controller_t,
+	pub mouse: VREvent_Mouse_t,
+	pub scroll: VREvent_Scroll_t,
+	pub process: VREvent_Process_t,
+	pub notification: VREvent_Notification_t,
+	pub overlay: VREvent_Overlay_t,
+	pub status: VREvent_Status_t,
+	pub keyboard: VREvent_Keyboard_t,
+	pub ipd: VREvent_Ipd_t,
+	pub chaperone: VREvent_Chaperone_t,
+	pub performanceTest: VREvent_PerformanceTest_t,
+	pub touchPadMove: VREvent_TouchPadMove_t,
+	pub seatedZeroPoseReset: VREvent_SeatedZeroPoseReset_t,
+	pub screenshot: VREvent_Screenshot_t,
+	pub screenshotProgress: VREvent_ScreenshotProgress_t,
+	pub applicationLaunch: VREvent_ApplicationLaunch_t,
+	pub cameraSurface: VREvent_EditingCameraSurface_t,
+	pub messageOverlay: VREvent_MessageOverlay_t,
+	pub property: VREvent_Property_t,
+	pub hapticVibration: VREvent_HapticVibration_t,
+	pub webConsole: VREvent_WebConsole_t,
+	pub inputBinding: VREvent_InputBindingLoad_t,
+	pub actionManifest: VREvent_InputActionManifestLoad_t,
+	pub spatialAnchor: VREvent_SpatialAnchor_t,
+	pub progressUpdate: VREvent_ProgressUpdate_t,
+	pub showUi: VREvent_ShowUI_t,
+	pub showDevTools: VREvent_ShowDevTools_t,
+	pub hdcpError: VREvent_HDCPError_t,
 }
 pub type PropertyTypeTag_t = u32;
 pub type vrshared_uint64_t = u64;
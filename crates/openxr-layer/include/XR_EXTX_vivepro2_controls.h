@@ -0,0 +1,39 @@
+#ifndef XR_EXTX_VIVEPRO2_CONTROLS_H
+#define XR_EXTX_VIVEPRO2_CONTROLS_H
+
+/* Provided by vivepro2 API layer (XrApiLayer_vivepro2.json), not by runtimes */
+
+#include <openxr/openxr.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define XR_EXTX_vivepro2_controls 1
+#define XR_EXTX_vivepro2_controls_SPEC_VERSION 1
+#define XR_EXTX_VIVEPRO2_CONTROLS_EXTENSION_NAME "XR_EXTX_vivepro2_controls"
+
+/* Headset state, as known by vived (or SteamVR driver during session), unknown values are -1 */
+typedef struct XrVivePro2StateEXTX {
+  XrBool32 connected;
+  /* Mode id, as listed by `vivectl resolution` */
+  int32_t mode;
+  int32_t brightness;
+  /* 0 or 1 */
+  int32_t noiseCancel;
+} XrVivePro2StateEXTX;
+
+typedef XrResult (XRAPI_PTR *PFN_xrGetVivePro2StateEXTX)(XrInstance instance, XrVivePro2StateEXTX *state);
+/* 1-130 */
+typedef XrResult (XRAPI_PTR *PFN_xrSetVivePro2BrightnessEXTX)(XrInstance instance, uint32_t brightness);
+/* Headset reconnects to apply it, and session is lost with it */
+typedef XrResult (XRAPI_PTR *PFN_xrSetVivePro2ModeEXTX)(XrInstance instance, uint32_t mode);
+typedef XrResult (XRAPI_PTR *PFN_xrSetVivePro2NoiseCancelEXTX)(XrInstance instance, XrBool32 enabled);
+
+/* Every function fails with XR_ERROR_RUNTIME_FAILURE, if neither vived nor driver is reachable */
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* XR_EXTX_VIVEPRO2_CONTROLS_H */
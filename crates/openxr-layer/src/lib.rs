@@ -0,0 +1,275 @@
+//! OpenXR API layer, which brings headset controls (brightness, mode, noise cancelling) to apps
+//! on any runtime. Requests go to driver-proxy during SteamVR session, and to vived otherwise
+//!
+//! Apps enable `XR_EXTX_vivepro2_controls`, and get its functions from `xrGetInstanceProcAddr`,
+//! see `include/XR_EXTX_vivepro2_controls.h`. Extension is removed from create info before it
+//! reaches runtime, which doesn't know about it
+use std::{
+	collections::BTreeMap,
+	ffi::{c_char, CStr},
+	mem, slice,
+	sync::Mutex,
+};
+
+use vive_ipc::{Client, Request};
+use xr::*;
+
+mod xr;
+
+const EXTENSION_NAME: &CStr =
+	unsafe { CStr::from_bytes_with_nul_unchecked(b"XR_EXTX_vivepro2_controls\0") };
+const DESTROY_INSTANCE: &CStr =
+	unsafe { CStr::from_bytes_with_nul_unchecked(b"xrDestroyInstance\0") };
+/// Everything layer calls exists since 1.0
+const API_VERSION: XrVersion = xr_make_version(1, 0, 0);
+
+/// Created instances, with functions of the next layer (or runtime) for them
+struct Instance {
+	get_instance_proc_addr: PFN_xrGetInstanceProcAddr,
+	destroy_instance: PFN_xrDestroyInstance,
+	enabled: bool,
+}
+static INSTANCES: Mutex<BTreeMap<XrInstance, Instance>> = Mutex::new(BTreeMap::new());
+
+/// Function of the next layer, for anything layer doesn't handle itself
+fn next_proc_addr(instance: XrInstance) -> Option<PFN_xrGetInstanceProcAddr> {
+	let instances = INSTANCES.lock().expect("lock");
+	// Every instance has the same chain below the layer
+	instances
+		.get(&instance)
+		.or_else(|| instances.values().next())
+		.map(|i| i.get_instance_proc_addr)
+}
+fn enabled(instance: XrInstance) -> bool {
+	INSTANCES
+		.lock()
+		.expect("lock")
+		.get(&instance)
+		.is_some_and(|i| i.enabled)
+}
+
+/// Headset state, as known by vived or driver-proxy, unknown values are -1
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct XrVivePro2StateEXTX {
+	pub connected: XrBool32,
+	pub mode: i32,
+	pub brightness: i32,
+	pub noise_cancel: i32,
+}
+
+#[allow(non_camel_case_types)]
+type PFN_xrGetVivePro2StateEXTX =
+	unsafe extern "system" fn(XrInstance, *mut XrVivePro2StateEXTX) -> XrResult;
+/// Every setter takes a single value
+#[allow(non_camel_case_types)]
+type PFN_xrSetValueEXTX = unsafe extern "system" fn(XrInstance, u32) -> XrResult;
+const EXTENSION_FUNCTIONS: [&[u8]; 4] = [
+	b"xrGetVivePro2StateEXTX",
+	b"xrSetVivePro2BrightnessEXTX",
+	b"xrSetVivePro2ModeEXTX",
+	b"xrSetVivePro2NoiseCancelEXTX",
+];
+
+fn request(request: &Request) -> XrResult {
+	let res = match Client::connect_driver() {
+		Some(mut driver) => driver.request(request),
+		None => Client::connect_default().and_then(|mut vived| vived.request(request)),
+	};
+	match res {
+		Ok(_) => XR_SUCCESS,
+		Err(e) => {
+			eprintln!("vivepro2 layer: {request:?} failed: {e}");
+			XR_ERROR_RUNTIME_FAILURE
+		}
+	}
+}
+
+unsafe extern "system" fn get_state(
+	_instance: XrInstance,
+	state: *mut XrVivePro2StateEXTX,
+) -> XrResult {
+	if state.is_null() {
+		return XR_ERROR_VALIDATION_FAILURE;
+	}
+	let res = match Client::connect_driver() {
+		Some(mut driver) => driver.status(),
+		None => Client::connect_default().and_then(|mut vived| vived.status()),
+	};
+	let status = match res {
+		Ok(status) => status,
+		Err(e) => {
+			eprintln!("vivepro2 layer: status failed: {e}");
+			return XR_ERROR_RUNTIME_FAILURE;
+		}
+	};
+	let s = &status.settings;
+	*state = XrVivePro2StateEXTX {
+		connected: status.connected.into(),
+		mode: s.resolution.map_or(-1, i32::from),
+		brightness: s.brightness.map_or(-1, i32::from),
+		noise_cancel: s.noise_cancel.map_or(-1, i32::from),
+	};
+	XR_SUCCESS
+}
+
+unsafe extern "system" fn set_brightness(_instance: XrInstance, brightness: u32) -> XrResult {
+	match u8::try_from(brightness) {
+		Ok(brightness) => request(&Request::SetBrightness { brightness }),
+		Err(_) => XR_ERROR_VALIDATION_FAILURE,
+	}
+}
+
+/// Headset reconnects to apply it, so does the session of app
+unsafe extern "system" fn set_mode(_instance: XrInstance, mode: u32) -> XrResult {
+	match u8::try_from(mode) {
+		Ok(resolution) => request(&Request::SetResolution { resolution }),
+		Err(_) => XR_ERROR_VALIDATION_FAILURE,
+	}
+}
+
+unsafe extern "system" fn set_noise_cancel(_instance: XrInstance, enabled: XrBool32) -> XrResult {
+	request(&Request::SetNoiseCancel {
+		enabled: enabled != 0,
+	})
+}
+
+unsafe extern "system" fn destroy_instance(instance: XrInstance) -> XrResult {
+	let removed = INSTANCES.lock().expect("lock").remove(&instance);
+	match removed {
+		Some(i) => (i.destroy_instance)(instance),
+		None => XR_ERROR_HANDLE_INVALID,
+	}
+}
+
+unsafe extern "system" fn get_instance_proc_addr(
+	instance: XrInstance,
+	name: *const c_char,
+	function: *mut PFN_xrVoidFunction,
+) -> XrResult {
+	if name.is_null() || function.is_null() {
+		return XR_ERROR_VALIDATION_FAILURE;
+	}
+	let name_bytes = CStr::from_ptr(name).to_bytes();
+	if EXTENSION_FUNCTIONS.contains(&name_bytes) && !enabled(instance) {
+		*function = None;
+		return XR_ERROR_FUNCTION_UNSUPPORTED;
+	}
+	// SAFETY: callers cast them back to the types of these names
+	let ours: PFN_xrVoidFunction = match name_bytes {
+		b"xrGetInstanceProcAddr" => Some(
+			mem::transmute::<PFN_xrGetInstanceProcAddr, VoidFunction>(get_instance_proc_addr),
+		),
+		b"xrDestroyInstance" => Some(mem::transmute::<PFN_xrDestroyInstance, VoidFunction>(
+			destroy_instance,
+		)),
+		b"xrGetVivePro2StateEXTX" => Some(
+			mem::transmute::<PFN_xrGetVivePro2StateEXTX, VoidFunction>(get_state),
+		),
+		b"xrSetVivePro2BrightnessEXTX" => Some(mem::transmute::<PFN_xrSetValueEXTX, VoidFunction>(
+			set_brightness,
+		)),
+		b"xrSetVivePro2ModeEXTX" => {
+			Some(mem::transmute::<PFN_xrSetValueEXTX, VoidFunction>(set_mode))
+		}
+		b"xrSetVivePro2NoiseCancelEXTX" => Some(
+			mem::transmute::<PFN_xrSetValueEXTX, VoidFunction>(set_noise_cancel),
+		),
+		_ => None,
+	};
+	if let Some(ours) = ours {
+		*function = Some(ours);
+		return XR_SUCCESS;
+	}
+	match next_proc_addr(instance) {
+		Some(next) => next(instance, name, function),
+		None => {
+			*function = None;
+			XR_ERROR_HANDLE_INVALID
+		}
+	}
+}
+
+unsafe extern "system" fn create_api_layer_instance(
+	info: *const XrInstanceCreateInfo,
+	layer_info: *const XrApiLayerCreateInfo,
+	instance: *mut XrInstance,
+) -> XrResult {
+	let (Some(info), Some(layer_info)) = (info.as_ref(), layer_info.as_ref()) else {
+		return XR_ERROR_VALIDATION_FAILURE;
+	};
+	let Some(next) = layer_info.next_info.as_ref() else {
+		return XR_ERROR_INITIALIZATION_FAILED;
+	};
+	let names = match info.enabled_extension_names.is_null() {
+		true => &[][..],
+		false => slice::from_raw_parts(
+			info.enabled_extension_names,
+			info.enabled_extension_count as usize,
+		),
+	};
+	let is_ours = |&name: &*const c_char| !name.is_null() && CStr::from_ptr(name) == EXTENSION_NAME;
+	let enabled = names.iter().any(is_ours);
+	let names = names
+		.iter()
+		.copied()
+		.filter(|name| !is_ours(name))
+		.collect::<Vec<_>>();
+
+	let mut info = *info;
+	info.enabled_extension_count = names.len() as u32;
+	info.enabled_extension_names = names.as_ptr();
+	let mut layer_info = *layer_info;
+	layer_info.next_info = next.next;
+	let res = (next.next_create_api_layer_instance)(&info, &layer_info, instance);
+	if res < XR_SUCCESS {
+		return res;
+	}
+
+	let mut destroy: PFN_xrVoidFunction = None;
+	let res =
+		(next.next_get_instance_proc_addr)(*instance, DESTROY_INSTANCE.as_ptr(), &mut destroy);
+	let Some(destroy) = destroy.filter(|_| res >= XR_SUCCESS) else {
+		return XR_ERROR_INITIALIZATION_FAILED;
+	};
+	INSTANCES.lock().expect("lock").insert(
+		*instance,
+		Instance {
+			get_instance_proc_addr: next.next_get_instance_proc_addr,
+			// SAFETY: returned for this name, so it has this signature
+			destroy_instance: mem::transmute::<VoidFunction, PFN_xrDestroyInstance>(destroy),
+			enabled,
+		},
+	);
+	XR_SUCCESS
+}
+
+/// Entry point, called by OpenXR loader
+///
+/// # Safety
+/// Pointers are valid, as loader passes them
+#[no_mangle]
+pub unsafe extern "system" fn xrNegotiateLoaderApiLayerInterface(
+	loader_info: *const XrNegotiateLoaderInfo,
+	_layer_name: *const c_char,
+	request: *mut XrNegotiateApiLayerRequest,
+) -> XrResult {
+	let (Some(loader_info), Some(request)) = (loader_info.as_ref(), request.as_mut()) else {
+		return XR_ERROR_INITIALIZATION_FAILED;
+	};
+	if loader_info.struct_type != XR_LOADER_INTERFACE_STRUCT_LOADER_INFO
+		|| loader_info.struct_version != XR_LOADER_INFO_STRUCT_VERSION
+		|| request.struct_type != XR_LOADER_INTERFACE_STRUCT_API_LAYER_REQUEST
+		|| request.struct_version != XR_API_LAYER_INFO_STRUCT_VERSION
+		|| !(loader_info.min_interface_version..=loader_info.max_interface_version)
+			.contains(&XR_CURRENT_LOADER_API_LAYER_VERSION)
+		|| loader_info.max_api_version < API_VERSION
+	{
+		return XR_ERROR_INITIALIZATION_FAILED;
+	}
+	request.layer_interface_version = XR_CURRENT_LOADER_API_LAYER_VERSION;
+	request.layer_api_version = API_VERSION;
+	request.get_instance_proc_addr = Some(get_instance_proc_addr);
+	request.create_api_layer_instance = Some(create_api_layer_instance);
+	XR_SUCCESS
+}
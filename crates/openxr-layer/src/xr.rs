@@ -0,0 +1,120 @@
+//! Parts of `openxr.h` and `openxr_loader_negotiation.h`, which layer needs
+#![allow(non_camel_case_types, dead_code)]
+
+use std::ffi::{c_char, c_void};
+
+pub type XrResult = i32;
+pub const XR_SUCCESS: XrResult = 0;
+pub const XR_ERROR_VALIDATION_FAILURE: XrResult = -1;
+pub const XR_ERROR_RUNTIME_FAILURE: XrResult = -2;
+pub const XR_ERROR_INITIALIZATION_FAILED: XrResult = -6;
+pub const XR_ERROR_FUNCTION_UNSUPPORTED: XrResult = -7;
+pub const XR_ERROR_HANDLE_INVALID: XrResult = -12;
+
+pub type XrVersion = u64;
+pub const fn xr_make_version(major: u64, minor: u64, patch: u64) -> XrVersion {
+	(major << 48) | (minor << 32) | patch
+}
+pub type XrBool32 = u32;
+/// Handles are pointers on 64-bit platforms, and `uint64_t` elsewhere
+pub type XrInstance = u64;
+pub const XR_NULL_HANDLE: XrInstance = 0;
+
+pub type VoidFunction = unsafe extern "system" fn();
+pub type PFN_xrVoidFunction = Option<VoidFunction>;
+pub type PFN_xrGetInstanceProcAddr = unsafe extern "system" fn(
+	instance: XrInstance,
+	name: *const c_char,
+	function: *mut PFN_xrVoidFunction,
+) -> XrResult;
+pub type PFN_xrCreateApiLayerInstance = unsafe extern "system" fn(
+	info: *const XrInstanceCreateInfo,
+	layer_info: *const XrApiLayerCreateInfo,
+	instance: *mut XrInstance,
+) -> XrResult;
+pub type PFN_xrDestroyInstance = unsafe extern "system" fn(instance: XrInstance) -> XrResult;
+
+pub const XR_MAX_APPLICATION_NAME_SIZE: usize = 128;
+pub const XR_MAX_ENGINE_NAME_SIZE: usize = 128;
+pub const XR_MAX_API_LAYER_NAME_SIZE: usize = 256;
+pub const XR_API_LAYER_MAX_SETTINGS_PATH_SIZE: usize = 512;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct XrApplicationInfo {
+	pub application_name: [c_char; XR_MAX_APPLICATION_NAME_SIZE],
+	pub application_version: u32,
+	pub engine_name: [c_char; XR_MAX_ENGINE_NAME_SIZE],
+	pub engine_version: u32,
+	pub api_version: XrVersion,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct XrInstanceCreateInfo {
+	pub ty: i32,
+	pub next: *const c_void,
+	pub create_flags: u64,
+	pub application_info: XrApplicationInfo,
+	pub enabled_api_layer_count: u32,
+	pub enabled_api_layer_names: *const *const c_char,
+	pub enabled_extension_count: u32,
+	pub enabled_extension_names: *const *const c_char,
+}
+
+/// `XrLoaderInterfaceStructs`
+pub type XrLoaderInterfaceStructs = u32;
+pub const XR_LOADER_INTERFACE_STRUCT_LOADER_INFO: XrLoaderInterfaceStructs = 1;
+pub const XR_LOADER_INTERFACE_STRUCT_API_LAYER_REQUEST: XrLoaderInterfaceStructs = 2;
+pub const XR_LOADER_INTERFACE_STRUCT_API_LAYER_CREATE_INFO: XrLoaderInterfaceStructs = 4;
+pub const XR_LOADER_INTERFACE_STRUCT_API_LAYER_NEXT_INFO: XrLoaderInterfaceStructs = 5;
+
+pub const XR_LOADER_INFO_STRUCT_VERSION: u32 = 1;
+pub const XR_API_LAYER_INFO_STRUCT_VERSION: u32 = 1;
+pub const XR_API_LAYER_CREATE_INFO_STRUCT_VERSION: u32 = 1;
+pub const XR_API_LAYER_NEXT_INFO_STRUCT_VERSION: u32 = 1;
+pub const XR_CURRENT_LOADER_API_LAYER_VERSION: u32 = 1;
+
+#[repr(C)]
+pub struct XrNegotiateLoaderInfo {
+	pub struct_type: XrLoaderInterfaceStructs,
+	pub struct_version: u32,
+	pub struct_size: usize,
+	pub min_interface_version: u32,
+	pub max_interface_version: u32,
+	pub min_api_version: XrVersion,
+	pub max_api_version: XrVersion,
+}
+
+#[repr(C)]
+pub struct XrNegotiateApiLayerRequest {
+	pub struct_type: XrLoaderInterfaceStructs,
+	pub struct_version: u32,
+	pub struct_size: usize,
+	pub layer_interface_version: u32,
+	pub layer_api_version: XrVersion,
+	pub get_instance_proc_addr: Option<PFN_xrGetInstanceProcAddr>,
+	pub create_api_layer_instance: Option<PFN_xrCreateApiLayerInstance>,
+}
+
+#[repr(C)]
+pub struct XrApiLayerNextInfo {
+	pub struct_type: XrLoaderInterfaceStructs,
+	pub struct_version: u32,
+	pub struct_size: usize,
+	pub layer_name: [c_char; XR_MAX_API_LAYER_NAME_SIZE],
+	pub next_get_instance_proc_addr: PFN_xrGetInstanceProcAddr,
+	pub next_create_api_layer_instance: PFN_xrCreateApiLayerInstance,
+	pub next: *mut XrApiLayerNextInfo,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct XrApiLayerCreateInfo {
+	pub struct_type: XrLoaderInterfaceStructs,
+	pub struct_version: u32,
+	pub struct_size: usize,
+	pub loader_instance: *mut c_void,
+	pub settings_file_location: [c_char; XR_API_LAYER_MAX_SETTINGS_PATH_SIZE],
+	pub next_info: *mut XrApiLayerNextInfo,
+}
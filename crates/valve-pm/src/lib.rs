@@ -28,6 +28,13 @@ type Result<T, E = Error> = result::Result<T, E>;
 const MODE_CHARACTERISTIC_ID: Lazy<Uuid> =
 	Lazy::new(|| Uuid::from_str("00001525-1212-efde-1523-785feabcd124").expect("uuid is valid"));
 
+/// Standard Device Information service characteristic
+static FIRMWARE_REVISION_CHARACTERISTIC_ID: Lazy<Uuid> =
+	Lazy::new(|| Uuid::from_str("00002a26-0000-1000-8000-00805f9b34fb").expect("uuid is valid"));
+
+/// Lighthouse v2 advertise themselves as LHB-XXXXXXXX
+const STATION_NAME_PREFIX: &str = "LHB-";
+
 #[allow(dead_code)]
 const HTC_MAC_PREFIX: [u8; 3] = [0x74, 0xf6, 0x1c];
 
@@ -110,6 +117,65 @@ pub async fn start_manager() -> Result<Arc<(Manager, Adapter)>, ()> {
 	Ok(Arc::new((manager, adapter)))
 }
 
+#[derive(Debug, Clone)]
+pub struct StationInfo {
+	pub name: String,
+	/// `None` if station was found, but firmware revision can't be read
+	pub firmware: Option<String>,
+}
+
+/// Scans for `scan_time`, then connects to every found base station to read its firmware
+/// revision
+pub async fn station_info(adapter: &Adapter, scan_time: Duration) -> Result<Vec<StationInfo>> {
+	adapter.start_scan(ScanFilter::default()).await?;
+	sleep(scan_time).await;
+	let mut out = Vec::new();
+	for peripheral in adapter.peripherals().await? {
+		let Some(name) = peripheral
+			.properties()
+			.await
+			.ok()
+			.flatten()
+			.and_then(|p| p.local_name)
+		else {
+			continue;
+		};
+		if !name.starts_with(STATION_NAME_PREFIX) {
+			continue;
+		}
+		let firmware: Result<Option<String>> = try {
+			if !peripheral.is_connected().await? {
+				peripheral.connect().await?;
+			}
+			if peripheral.characteristics().is_empty() {
+				peripheral.discover_services().await?;
+			}
+			let characteristic = peripheral
+				.characteristics()
+				.into_iter()
+				.find(|c| c.uuid == *FIRMWARE_REVISION_CHARACTERISTIC_ID);
+			match characteristic {
+				Some(c) => {
+					let value = peripheral.read(&c).await?;
+					Some(
+						String::from_utf8_lossy(&value)
+							.trim_end_matches('\0')
+							.to_owned(),
+					)
+				}
+				None => None,
+			}
+		};
+		let _ = peripheral.disconnect().await;
+		let firmware = firmware.unwrap_or_else(|e| {
+			warn!("failed to read {name} firmware: {e}");
+			None
+		});
+		out.push(StationInfo { name, firmware });
+	}
+	Ok(out)
+}
+
 pub struct StationControl {
 	handle: Option<tokio::task::JoinHandle<()>>,
 	ctx: Option<mpsc::UnboundedSender<StationCommand>>,
@@ -225,6 +291,14 @@ impl StationControl {
 			ctx: Some(ctx),
 		}
 	}
+	/// Stops managing station, leaving it in its current state, for another process to take it
+	pub fn release(mut self) {
+		drop(self.ctx.take());
+		self.handle
+			.take()
+			.expect("finish can only be called once")
+			.abort();
+	}
 	pub fn send(&mut self, command: StationCommand) {
 		self.ctx
 			.as_mut()
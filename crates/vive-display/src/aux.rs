@@ -0,0 +1,107 @@
+//! DisplayPort side channel to the headset, for when its USB side is unreachable
+//!
+//! Config and commands only exist on USB HID, they are served by headset mainboard, while
+//! DisplayPort ends at the panel bridge, which has no known vendor channel: AUX carries standard
+//! DPCD registers only, and DDC carries EDID, DDC/CI isn't known to be answered. So what is
+//! available here is identification, enough to tell that headset is connected and to which GPU
+//! port, see [`AuxInfo`]. EDID is readable by anyone, DPCD needs access to `/dev/drm_dp_aux*`,
+//! which is root-only by default
+
+use std::{
+	fs::{self, File},
+	os::unix::fs::FileExt,
+	path::{Path, PathBuf},
+};
+
+use crate::{drm::Connector, Error, Result};
+
+const DPCD_REV: usize = 0x000;
+const DPCD_DOWNSTREAM_PORT_PRESENT: usize = 0x005;
+const DPCD_SINK_ID: u64 = 0x400;
+const DPCD_BRANCH_ID: u64 = 0x500;
+/// OUI, device id string, hardware and firmware revision
+const ID_LEN: usize = 12;
+
+/// Contents of DPCD identification block, of sink (headset) or branch device (link box, if it
+/// presents itself as one)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceId {
+	/// IEEE OUI of the chip vendor, in `xx-xx-xx` form
+	pub oui: Option<String>,
+	/// Up to 6 characters, picked by chip vendor
+	pub device: String,
+	pub hardware_rev: u8,
+	pub firmware_rev: (u8, u8),
+}
+impl DeviceId {
+	/// Parses block at 0x400 (or 0x500), all zeroes is how unimplemented one reads
+	pub fn parse(block: &[u8]) -> Option<Self> {
+		let block = block.get(..ID_LEN)?;
+		if block.iter().all(|&b| b == 0) {
+			return None;
+		}
+		let oui = (block[..3] != [0; 3])
+			.then(|| format!("{:02x}-{:02x}-{:02x}", block[0], block[1], block[2]));
+		let device = block[3..9]
+			.iter()
+			.take_while(|&&c| c != 0)
+			.map(|&c| c as char)
+			.collect::<String>();
+		Some(Self {
+			oui,
+			device: device.trim().to_owned(),
+			hardware_rev: block[9],
+			firmware_rev: (block[10], block[11]),
+		})
+	}
+}
+
+/// Everything DisplayPort side tells about the headset
+#[derive(Debug, Clone)]
+pub struct AuxInfo {
+	/// i.e `0x14` for DP 1.4
+	pub dpcd_rev: u8,
+	pub sink: Option<DeviceId>,
+	pub branch: Option<DeviceId>,
+}
+
+/// `/dev/drm_dp_auxN` of connector, it is the only `drm_dp_aux*` entry in its sysfs directory
+pub fn aux_node(sysfs_path: &Path) -> Option<PathBuf> {
+	let aux = fs::read_dir(sysfs_path)
+		.ok()?
+		.flatten()
+		.map(|e| e.file_name())
+		.find(|n| n.to_str().is_some_and(|n| n.starts_with("drm_dp_aux")))?;
+	Some(Path::new("/dev").join(aux))
+}
+
+/// Reads `len` DPCD bytes at `offset`, every read is an AUX transaction, which wakes sink up
+pub fn read_dpcd(node: &Path, offset: u64, len: usize) -> Result<Vec<u8>> {
+	let mut out = vec![0; len];
+	File::open(node)?.read_exact_at(&mut out, offset)?;
+	Ok(out)
+}
+
+impl Connector {
+	/// DPCD node, fails with [`Error::ConnectorNotFound`] for non-DP connectors
+	pub fn aux_node(&self) -> Result<PathBuf> {
+		aux_node(&self.sysfs_path)
+			.ok_or_else(|| Error::ConnectorNotFound(format!("{}-{} aux", self.card, self.name)))
+	}
+	pub fn aux_info(&self) -> Result<AuxInfo> {
+		let node = self.aux_node()?;
+		let caps = read_dpcd(&node, 0, DPCD_DOWNSTREAM_PORT_PRESENT + 1)?;
+		let id = |offset| {
+			read_dpcd(&node, offset, ID_LEN)
+				.ok()
+				.and_then(|b| DeviceId::parse(&b))
+		};
+		Ok(AuxInfo {
+			dpcd_rev: caps[DPCD_REV],
+			sink: id(DPCD_SINK_ID),
+			branch: (caps[DPCD_DOWNSTREAM_PORT_PRESENT] & 1 != 0)
+				.then(|| id(DPCD_BRANCH_ID))
+				.flatten(),
+		})
+	}
+}
@@ -0,0 +1,120 @@
+//! Connector discovery through sysfs, so no DRM master is needed just to find the headset
+
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use crate::{
+	edid::{Edid, EdidId},
+	Error, Result,
+};
+
+pub const SYSFS_DRM: &str = "/sys/class/drm";
+/// `HVR`, EDID manufacturer of every HTC headset
+pub const HTC_VENDOR: u16 = 0x22d2;
+pub const DEBUGFS_DRI: &str = "/sys/kernel/debug/dri";
+
+#[derive(Debug, Clone)]
+pub struct Connector {
+	/// i.e `card0`
+	pub card: String,
+	/// i.e `DP-1`, as named by the kernel (and by most compositors)
+	pub name: String,
+	pub sysfs_path: PathBuf,
+	pub connected: bool,
+	pub edid: Edid,
+}
+impl Connector {
+	/// Device node, which should be opened for leasing/modesetting
+	pub fn card_path(&self) -> PathBuf {
+		Path::new("/dev/dri").join(&self.card)
+	}
+	/// Video memory of the GPU connector is on, only amdgpu exposes it in sysfs
+	pub fn vram_total(&self) -> Option<u64> {
+		let path = Path::new(SYSFS_DRM)
+			.join(&self.card)
+			.join("device/mem_info_vram_total");
+		fs::read_to_string(path).ok()?.trim().parse().ok()
+	}
+}
+
+/// Returns all connectors, which have EDID matching `id`, connected ones first
+pub fn find_connectors(id: EdidId) -> Result<Vec<Connector>> {
+	find_connectors_in(Path::new(SYSFS_DRM), id)
+}
+pub fn find_connectors_in(root: &Path, id: EdidId) -> Result<Vec<Connector>> {
+	find_matching(root, |edid| edid.matches(id))
+}
+/// Connectors with any HTC display, for when exact id is unknown, as it is only stored in
+/// tracking board config
+pub fn find_htc_connectors() -> Result<Vec<Connector>> {
+	find_matching(Path::new(SYSFS_DRM), |edid| edid.id.vendor == HTC_VENDOR)
+}
+
+fn find_matching(root: &Path, matches: impl Fn(&Edid) -> bool) -> Result<Vec<Connector>> {
+	let mut out = Vec::new();
+	for entry in fs::read_dir(root)? {
+		let entry = entry?;
+		let file_name = entry.file_name();
+		let Some(file_name) = file_name.to_str() else {
+			continue;
+		};
+		// Connectors are named `card<N>-<CONNECTOR>`, cards itself and render nodes have no dash
+		let Some((card, name)) = file_name.split_once('-') else {
+			continue;
+		};
+		if !card.starts_with("card") {
+			continue;
+		}
+		let path = entry.path();
+		let Ok(edid) = fs::read(path.join("edid")) else {
+			continue;
+		};
+		// Empty for disconnected connectors
+		let Ok(edid) = Edid::parse(&edid) else {
+			continue;
+		};
+		if !matches(&edid) {
+			continue;
+		}
+		let connected = fs::read_to_string(path.join("status"))
+			.map(|s| s.trim() == "connected")
+			.unwrap_or(false);
+		out.push(Connector {
+			card: card.to_owned(),
+			name: name.to_owned(),
+			sysfs_path: path,
+			connected,
+			edid,
+		});
+	}
+	out.sort_by_key(|c| !c.connected);
+	Ok(out)
+}
+pub fn find_connector(id: EdidId) -> Result<Option<Connector>> {
+	Ok(find_connectors(id)?.into_iter().next())
+}
+
+/// Makes kernel report `edid` for connector (`card0-DP-1`, as in sysfs) instead of the one read
+/// from display, through debugfs, so needs root. Override is kept until reboot
+///
+/// `drm.edid_firmware` kernel parameter does the same persistently
+pub fn override_edid(connector: &str, edid: &[u8]) -> Result<()> {
+	Edid::parse(edid)?;
+	let Some((card, name)) = connector.split_once('-') else {
+		return Err(Error::ConnectorNotFound(connector.to_owned()));
+	};
+	// Debugfs directories are named by card minor
+	let Some(minor) = card.strip_prefix("card") else {
+		return Err(Error::ConnectorNotFound(connector.to_owned()));
+	};
+	let dir = Path::new(DEBUGFS_DRI).join(minor).join(name);
+	if !dir.is_dir() {
+		return Err(Error::ConnectorNotFound(connector.to_owned()));
+	}
+	fs::write(dir.join("edid_override"), edid)?;
+	// Compositor only sees the new EDID after hotplug
+	fs::write(dir.join("trigger_hotplug"), "1")?;
+	Ok(())
+}
@@ -0,0 +1,117 @@
+//! Only the base block is parsed, this is enough to identify the panel
+
+use crate::{Error, Result};
+
+const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+const BLOCK_SIZE: usize = 128;
+
+/// Vendor and product, as SteamVR config stores them (`direct_mode_edid_vid`/`pid`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdidId {
+	/// Manufacturer id in EDID byte order (big-endian)
+	pub vendor: u16,
+	pub product: u16,
+}
+impl EdidId {
+	/// Config stores manufacturer id as it is laid out in memory (bytes are swapped)
+	pub fn from_config(vid: u32, pid: u32) -> Self {
+		Self {
+			vendor: (vid as u16).swap_bytes(),
+			product: pid as u16,
+		}
+	}
+	/// Inverse of [`EdidId::from_config`]
+	pub fn to_config(self) -> (u32, u32) {
+		(self.vendor.swap_bytes() as u32, self.product as u32)
+	}
+	/// Three-letter PNP id, i.e `HVR` for HTC
+	pub fn pnp_id(&self) -> String {
+		pnp_id(self.vendor)
+	}
+}
+
+/// Manufacturer id in EDID byte order, from three-letter PNP id
+pub fn parse_pnp_id(id: &str) -> Option<u16> {
+	let bytes = id.as_bytes();
+	if bytes.len() != 3 || !bytes.iter().all(u8::is_ascii_uppercase) {
+		return None;
+	}
+	Some(
+		bytes
+			.iter()
+			.fold(0, |out, c| (out << 5) | (c - b'A' + 1) as u16),
+	)
+}
+
+pub fn pnp_id(vendor: u16) -> String {
+	[10, 5, 0]
+		.into_iter()
+		.map(|shift| {
+			let c = ((vendor >> shift) & 0x1f) as u8;
+			if (1..=26).contains(&c) {
+				(b'A' + c - 1) as char
+			} else {
+				'?'
+			}
+		})
+		.collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct Edid {
+	pub id: EdidId,
+	pub serial: u32,
+	pub week: u8,
+	pub year: u16,
+	/// Display product name descriptor
+	pub name: Option<String>,
+	/// Display product serial number descriptor
+	pub serial_string: Option<String>,
+}
+impl Edid {
+	pub fn parse(data: &[u8]) -> Result<Self> {
+		if data.len() < BLOCK_SIZE {
+			return Err(Error::EdidTooShort(data.len()));
+		}
+		let block = &data[..BLOCK_SIZE];
+		if block[..8] != HEADER {
+			return Err(Error::EdidBadHeader);
+		}
+		if block.iter().fold(0u8, |a, b| a.wrapping_add(*b)) != 0 {
+			return Err(Error::EdidBadChecksum);
+		}
+		let mut name = None;
+		let mut serial_string = None;
+		for descriptor in block[54..126].chunks_exact(18) {
+			// Display descriptors have zero pixel clock
+			if descriptor[0] != 0 || descriptor[1] != 0 {
+				continue;
+			}
+			let text = || {
+				let text = &descriptor[5..];
+				let end = text.iter().position(|&c| c == b'\n').unwrap_or(text.len());
+				String::from_utf8_lossy(&text[..end]).trim_end().to_owned()
+			};
+			match descriptor[3] {
+				0xfc => name = Some(text()),
+				0xff => serial_string = Some(text()),
+				_ => {}
+			}
+		}
+		Ok(Self {
+			id: EdidId {
+				vendor: u16::from_be_bytes([block[8], block[9]]),
+				product: u16::from_le_bytes([block[10], block[11]]),
+			},
+			serial: u32::from_le_bytes([block[12], block[13], block[14], block[15]]),
+			week: block[16],
+			year: 1990 + block[17] as u16,
+			name,
+			serial_string,
+		})
+	}
+	/// Is this EDID belongs to the headset, described by config
+	pub fn matches(&self, id: EdidId) -> bool {
+		self.id == id
+	}
+}
@@ -0,0 +1,459 @@
+//! DRM leasing of the headset connector
+//!
+//! Kernel marks HMD panels as `non-desktop`, so compositors leave them alone, and whoever holds
+//! DRM master on the card may lease connector+crtc to a client, which then owns the panel
+//! exclusively. Under a running compositor the master fd should come from its lease protocol
+//! (i.e `wp_drm_lease_device_v1`), this module only implements the kernel side.
+
+use std::{
+	fs::{File, OpenOptions},
+	io,
+	mem::size_of,
+	os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+	path::Path,
+	time::Duration,
+};
+
+use crate::{drm::Connector, Error, Result};
+
+#[repr(C)]
+#[derive(Default)]
+struct CardRes {
+	fb_id_ptr: u64,
+	crtc_id_ptr: u64,
+	connector_id_ptr: u64,
+	encoder_id_ptr: u64,
+	count_fbs: u32,
+	count_crtcs: u32,
+	count_connectors: u32,
+	count_encoders: u32,
+	min_width: u32,
+	max_width: u32,
+	min_height: u32,
+	max_height: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct GetConnector {
+	encoders_ptr: u64,
+	modes_ptr: u64,
+	props_ptr: u64,
+	prop_values_ptr: u64,
+	count_modes: u32,
+	count_props: u32,
+	count_encoders: u32,
+	encoder_id: u32,
+	connector_id: u32,
+	connector_type: u32,
+	connector_type_id: u32,
+	connection: u32,
+	mm_width: u32,
+	mm_height: u32,
+	subpixel: u32,
+	pad: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct GetEncoder {
+	encoder_id: u32,
+	encoder_type: u32,
+	crtc_id: u32,
+	possible_crtcs: u32,
+	possible_clones: u32,
+}
+
+#[repr(C)]
+struct GetProperty {
+	values_ptr: u64,
+	enum_blob_ptr: u64,
+	prop_id: u32,
+	flags: u32,
+	name: [u8; 32],
+	count_values: u32,
+	count_enum_blobs: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CreateLease {
+	object_ids: u64,
+	object_count: u32,
+	flags: u32,
+	lessee_id: u32,
+	fd: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct RevokeLease {
+	lessee_id: u32,
+}
+
+/// Reply layout of `drm_wait_vblank` union, request one (`type`, `sequence`, `signal`) fits in it
+#[repr(C)]
+#[derive(Default)]
+struct WaitVblank {
+	kind: u32,
+	sequence: u32,
+	tval_sec: i64,
+	tval_usec: i64,
+}
+const VBLANK_RELATIVE: u32 = 0x1;
+const VBLANK_SECONDARY: u32 = 0x20000000;
+const VBLANK_HIGH_CRTC_SHIFT: u32 = 1;
+const VBLANK_HIGH_CRTC_MASK: u32 = 0x3e;
+
+const fn iowr<T>(nr: u64) -> u64 {
+	(3 << 30) | ((size_of::<T>() as u64) << 16) | ((b'd' as u64) << 8) | nr
+}
+const IOCTL_WAIT_VBLANK: u64 = iowr::<WaitVblank>(0x3a);
+const IOCTL_MODE_GETRESOURCES: u64 = iowr::<CardRes>(0xa0);
+const IOCTL_MODE_GETENCODER: u64 = iowr::<GetEncoder>(0xa6);
+const IOCTL_MODE_GETCONNECTOR: u64 = iowr::<GetConnector>(0xa7);
+const IOCTL_MODE_GETPROPERTY: u64 = iowr::<GetProperty>(0xaa);
+const IOCTL_MODE_CREATE_LEASE: u64 = iowr::<CreateLease>(0xc6);
+const IOCTL_MODE_REVOKE_LEASE: u64 = iowr::<RevokeLease>(0xc9);
+
+/// Same names, as used by kernel in sysfs (`drm_connector_enum_list`)
+const CONNECTOR_TYPES: &[&str] = &[
+	"Unknown",
+	"VGA",
+	"DVI-I",
+	"DVI-D",
+	"DVI-A",
+	"Composite",
+	"SVIDEO",
+	"LVDS",
+	"Component",
+	"DIN",
+	"DP",
+	"HDMI-A",
+	"HDMI-B",
+	"TV",
+	"eDP",
+	"Virtual",
+	"DSI",
+	"DPI",
+	"Writeback",
+	"SPI",
+	"USB",
+];
+
+fn ioctl<T>(fd: RawFd, request: u64, arg: &mut T) -> io::Result<()> {
+	loop {
+		// Safety: every request here is defined with size of T
+		let res = unsafe { libc::ioctl(fd, request as _, arg as *mut T) };
+		if res == 0 {
+			return Ok(());
+		}
+		let err = io::Error::last_os_error();
+		if err.kind() != io::ErrorKind::Interrupted {
+			return Err(err);
+		}
+	}
+}
+
+fn ptr<T>(v: &mut [T]) -> u64 {
+	v.as_mut_ptr() as u64
+}
+
+struct ConnectorInfo {
+	id: u32,
+	name: String,
+	encoders: Vec<u32>,
+	current_encoder: u32,
+	props: Vec<u32>,
+	prop_values: Vec<u64>,
+}
+
+fn resources(fd: RawFd) -> io::Result<(Vec<u32>, Vec<u32>)> {
+	loop {
+		let mut res = CardRes::default();
+		ioctl(fd, IOCTL_MODE_GETRESOURCES, &mut res)?;
+		let mut crtcs = vec![0u32; res.count_crtcs as usize];
+		let mut connectors = vec![0u32; res.count_connectors as usize];
+		let (count_crtcs, count_connectors) = (res.count_crtcs, res.count_connectors);
+		let mut res = CardRes {
+			crtc_id_ptr: ptr(&mut crtcs),
+			connector_id_ptr: ptr(&mut connectors),
+			count_crtcs,
+			count_connectors,
+			..Default::default()
+		};
+		ioctl(fd, IOCTL_MODE_GETRESOURCES, &mut res)?;
+		// Hotplug between calls, retry
+		if res.count_crtcs != count_crtcs || res.count_connectors != count_connectors {
+			continue;
+		}
+		return Ok((crtcs, connectors));
+	}
+}
+
+fn connector_info(fd: RawFd, id: u32) -> io::Result<ConnectorInfo> {
+	loop {
+		let mut conn = GetConnector {
+			connector_id: id,
+			..Default::default()
+		};
+		ioctl(fd, IOCTL_MODE_GETCONNECTOR, &mut conn)?;
+		let (count_encoders, count_props) = (conn.count_encoders, conn.count_props);
+		let mut encoders = vec![0u32; count_encoders as usize];
+		let mut props = vec![0u32; count_props as usize];
+		let mut prop_values = vec![0u64; count_props as usize];
+		let mut conn = GetConnector {
+			encoders_ptr: ptr(&mut encoders),
+			props_ptr: ptr(&mut props),
+			prop_values_ptr: ptr(&mut prop_values),
+			count_encoders,
+			count_props,
+			connector_id: id,
+			..Default::default()
+		};
+		ioctl(fd, IOCTL_MODE_GETCONNECTOR, &mut conn)?;
+		if conn.count_encoders != count_encoders || conn.count_props != count_props {
+			continue;
+		}
+		let type_name = CONNECTOR_TYPES
+			.get(conn.connector_type as usize)
+			.copied()
+			.unwrap_or("Unknown");
+		return Ok(ConnectorInfo {
+			id,
+			name: format!("{type_name}-{}", conn.connector_type_id),
+			encoders,
+			current_encoder: conn.encoder_id,
+			props,
+			prop_values,
+		});
+	}
+}
+
+fn find_connector_info(fd: RawFd, name: &str) -> Result<ConnectorInfo> {
+	let (_, connectors) = resources(fd)?;
+	for id in connectors {
+		let conn = connector_info(fd, id)?;
+		if conn.name == name {
+			return Ok(conn);
+		}
+	}
+	Err(Error::ConnectorNotFound(name.to_owned()))
+}
+
+/// Whether kernel marked connector as `non-desktop`, doesn't need DRM master
+pub fn is_non_desktop(card: &File, name: &str) -> Result<bool> {
+	let fd = card.as_raw_fd();
+	Ok(find_connector_info(fd, name)?.non_desktop(fd)?)
+}
+
+/// Current values of connector properties, by name, doesn't need DRM master
+pub fn connector_properties(card: &File, name: &str) -> Result<Vec<(String, u64)>> {
+	let fd = card.as_raw_fd();
+	let info = find_connector_info(fd, name)?;
+	let mut out = Vec::with_capacity(info.props.len());
+	for (&prop, &value) in info.props.iter().zip(&info.prop_values) {
+		out.push((property_name(fd, prop)?, value));
+	}
+	Ok(out)
+}
+
+/// KMS object id of connector with kernel name `name`
+pub fn connector_id(card: &File, name: &str) -> Result<u32> {
+	Ok(find_connector_info(card.as_raw_fd(), name)?.id)
+}
+
+/// Waits for `count` consecutive vblanks of crtc, which currently drives connector `name`,
+/// and returns their `CLOCK_MONOTONIC` timestamps, doesn't need DRM master
+pub fn vblank_timestamps(card: &File, name: &str, count: usize) -> Result<Vec<Duration>> {
+	let fd = card.as_raw_fd();
+	let (crtcs, _) = resources(fd)?;
+	let info = find_connector_info(fd, name)?;
+	let no_crtc = || Error::NoCrtc(name.to_owned());
+	if info.current_encoder == 0 {
+		return Err(no_crtc());
+	}
+	let crtc_id = encoder(fd, info.current_encoder)?.crtc_id;
+	let pipe = crtcs
+		.iter()
+		.position(|&id| id == crtc_id && id != 0)
+		.ok_or_else(no_crtc)? as u32;
+	// Same encoding as libdrm drmWaitVBlank
+	let kind = match pipe {
+		0 => VBLANK_RELATIVE,
+		1 => VBLANK_RELATIVE | VBLANK_SECONDARY,
+		_ => VBLANK_RELATIVE | ((pipe << VBLANK_HIGH_CRTC_SHIFT) & VBLANK_HIGH_CRTC_MASK),
+	};
+	let mut timestamps = Vec::with_capacity(count);
+	for _ in 0..count {
+		let mut wait = WaitVblank {
+			kind,
+			sequence: 1,
+			..Default::default()
+		};
+		ioctl(fd, IOCTL_WAIT_VBLANK, &mut wait)?;
+		timestamps.push(
+			Duration::from_secs(wait.tval_sec as u64)
+				+ Duration::from_micros(wait.tval_usec as u64),
+		);
+	}
+	Ok(timestamps)
+}
+
+fn property_name(fd: RawFd, id: u32) -> io::Result<String> {
+	let mut prop = GetProperty {
+		values_ptr: 0,
+		enum_blob_ptr: 0,
+		prop_id: id,
+		flags: 0,
+		name: [0; 32],
+		count_values: 0,
+		count_enum_blobs: 0,
+	};
+	ioctl(fd, IOCTL_MODE_GETPROPERTY, &mut prop)?;
+	let len = prop.name.iter().position(|&c| c == 0).unwrap_or(32);
+	Ok(String::from_utf8_lossy(&prop.name[..len]).into_owned())
+}
+
+fn encoder(fd: RawFd, id: u32) -> io::Result<GetEncoder> {
+	let mut enc = GetEncoder {
+		encoder_id: id,
+		..Default::default()
+	};
+	ioctl(fd, IOCTL_MODE_GETENCODER, &mut enc)?;
+	Ok(enc)
+}
+
+impl ConnectorInfo {
+	fn non_desktop(&self, fd: RawFd) -> io::Result<bool> {
+		for (&prop, &value) in self.props.iter().zip(&self.prop_values) {
+			if property_name(fd, prop)? == "non-desktop" {
+				return Ok(value != 0);
+			}
+		}
+		Ok(false)
+	}
+	/// Prefers crtc, which already drives this connector
+	fn crtc(&self, fd: RawFd, crtcs: &[u32]) -> io::Result<Option<u32>> {
+		if self.current_encoder != 0 {
+			let enc = encoder(fd, self.current_encoder)?;
+			if enc.crtc_id != 0 {
+				return Ok(Some(enc.crtc_id));
+			}
+		}
+		for &id in &self.encoders {
+			let enc = encoder(fd, id)?;
+			if let Some((_, &crtc)) = crtcs
+				.iter()
+				.enumerate()
+				.find(|(i, _)| enc.possible_crtcs & (1 << i) != 0)
+			{
+				return Ok(Some(crtc));
+			}
+		}
+		Ok(None)
+	}
+}
+
+/// Active lease, revoked on drop
+///
+/// `fd` is a DRM fd with only leased objects visible, it may be passed to any renderer, which
+/// accepts a DRM fd (i.e `VK_EXT_acquire_drm_display`)
+#[derive(Debug)]
+pub struct Lease {
+	master: File,
+	/// None after lease was detached
+	fd: Option<OwnedFd>,
+	pub lessee_id: u32,
+	pub connector_id: u32,
+	pub crtc_id: u32,
+}
+impl Lease {
+	pub fn fd(&self) -> RawFd {
+		self.fd.as_ref().expect("lease is not detached").as_raw_fd()
+	}
+	/// Detaches lease from this object, it will now live until returned fd is closed
+	pub fn into_fd(mut self) -> OwnedFd {
+		self.fd.take().expect("lease is not detached")
+	}
+	pub fn revoke(mut self) -> Result<()> {
+		self.fd = None;
+		let mut revoke = RevokeLease {
+			lessee_id: self.lessee_id,
+		};
+		ioctl(
+			self.master.as_raw_fd(),
+			IOCTL_MODE_REVOKE_LEASE,
+			&mut revoke,
+		)?;
+		Ok(())
+	}
+}
+impl Drop for Lease {
+	fn drop(&mut self) {
+		if self.fd.is_none() {
+			return;
+		}
+		let mut revoke = RevokeLease {
+			lessee_id: self.lessee_id,
+		};
+		let _ = ioctl(
+			self.master.as_raw_fd(),
+			IOCTL_MODE_REVOKE_LEASE,
+			&mut revoke,
+		);
+	}
+}
+
+/// Leases connector with kernel name `name` (i.e `DP-1`) from `master`
+///
+/// `master` should hold DRM master, i.e be opened with no compositor running on this card.
+/// Refuses to lease connectors without `non-desktop` flag, unless `force` is set, as they are
+/// most likely used by compositor
+pub fn lease_connector(master: File, name: &str, force: bool) -> Result<Lease> {
+	let fd = master.as_raw_fd();
+	let (crtcs, _) = resources(fd)?;
+	let info = find_connector_info(fd, name)?;
+	if !force && !info.non_desktop(fd)? {
+		return Err(Error::NotNonDesktop(name.to_owned()));
+	}
+	let crtc_id = info
+		.crtc(fd, &crtcs)?
+		.ok_or_else(|| Error::NoCrtc(name.to_owned()))?;
+
+	let mut objects = [info.id, crtc_id];
+	let mut lease = CreateLease {
+		object_ids: ptr(&mut objects),
+		object_count: objects.len() as u32,
+		flags: (libc::O_CLOEXEC | libc::O_NONBLOCK) as u32,
+		..Default::default()
+	};
+	ioctl(fd, IOCTL_MODE_CREATE_LEASE, &mut lease)?;
+	// Safety: kernel returned newly allocated fd
+	let lease_fd = unsafe { OwnedFd::from_raw_fd(lease.fd as RawFd) };
+	Ok(Lease {
+		master,
+		fd: Some(lease_fd),
+		lessee_id: lease.lessee_id,
+		connector_id: info.id,
+		crtc_id,
+	})
+}
+
+pub fn open_card(path: &Path) -> Result<File> {
+	Ok(OpenOptions::new().read(true).write(true).open(path)?)
+}
+
+impl Connector {
+	pub fn is_non_desktop(&self) -> Result<bool> {
+		is_non_desktop(&open_card(&self.card_path())?, &self.name)
+	}
+	/// See [`vblank_timestamps`]
+	pub fn vblank_timestamps(&self, count: usize) -> Result<Vec<Duration>> {
+		vblank_timestamps(&open_card(&self.card_path())?, &self.name, count)
+	}
+	/// Opens card of this connector and leases it
+	pub fn lease(&self, force: bool) -> Result<Lease> {
+		lease_connector(open_card(&self.card_path())?, &self.name, force)
+	}
+}
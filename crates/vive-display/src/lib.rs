@@ -0,0 +1,40 @@
+//! Helpers for finding and driving the headset panel outside of SteamVR
+use std::{io, result};
+
+pub mod aux;
+pub mod drm;
+pub mod edid;
+pub mod lease;
+pub mod link;
+#[cfg(feature = "vulkan")]
+pub mod vulkan;
+pub mod wayland;
+pub mod x11;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("io error: {0}")]
+	Io(#[from] io::Error),
+	#[error("edid is too short: {0} bytes")]
+	EdidTooShort(usize),
+	#[error("edid header is invalid")]
+	EdidBadHeader,
+	#[error("edid checksum mismatch")]
+	EdidBadChecksum,
+	#[error("drm connector {0} not found")]
+	ConnectorNotFound(String),
+	#[error("drm connector {0} is not marked as non-desktop")]
+	NotNonDesktop(String),
+	#[error("no crtc available for drm connector {0}")]
+	NoCrtc(String),
+	#[error("{0}")]
+	Command(String),
+	#[cfg(feature = "vulkan")]
+	#[error("vulkan function {0} is not available")]
+	VulkanMissingFunction(String),
+	#[cfg(feature = "vulkan")]
+	#[error("vulkan error: {0}")]
+	Vulkan(i32),
+}
+
+pub type Result<T, E = Error> = result::Result<T, E>;
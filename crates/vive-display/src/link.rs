@@ -0,0 +1,221 @@
+//! DisplayPort link capabilities of headset connector, to tell whether the highest modes can be
+//! driven on this GPU and cable at all, before headset is asked to switch to them
+//!
+//! Headset side comes from its DPCD, through `drm_dp_aux` node kernel creates for every DP
+//! connector, link driver verified GPU, cable and headset are able to train to, and DSC state
+//! come from driver debugfs (amdgpu and i915 only), both need root. `link-status` and `max bpc`
+//! are connector properties, readable by anyone with card access. Whatever is unreadable is None
+
+use std::{fmt, fs, path::Path};
+
+use crate::{
+	aux::{aux_node, read_dpcd},
+	drm::{Connector, DEBUGFS_DRI},
+	lease::{connector_properties, open_card},
+};
+
+/// DPCD registers, which are read, from DPCD_REV up to DSC_SUPPORT
+const DPCD_LEN: usize = 0x61;
+const DPCD_MAX_LINK_RATE: usize = 0x001;
+const DPCD_MAX_LANE_COUNT: usize = 0x002;
+const DPCD_DSC_SUPPORT: usize = 0x060;
+
+/// Bits per pixel, which DSC compresses 8bpc RGB to without visible loss
+const DSC_BPP: u64 = 8;
+const BPP: u64 = 24;
+
+/// Lane count and per-lane rate, rate is in DPCD units of 0.27 Gbps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkConfig {
+	pub lanes: u8,
+	pub rate: u8,
+}
+impl LinkConfig {
+	pub fn lane_mbps(self) -> u64 {
+		self.rate as u64 * 270
+	}
+	/// Bandwidth left for pixels, after 8b/10b channel coding
+	pub fn payload_mbps(self) -> u64 {
+		self.lanes as u64 * self.lane_mbps() * 8 / 10
+	}
+	pub fn rate_name(self) -> Option<&'static str> {
+		Some(match self.rate {
+			0x06 => "RBR",
+			0x0a => "HBR",
+			0x14 => "HBR2",
+			0x1e => "HBR3",
+			_ => return None,
+		})
+	}
+	/// Zero lanes or rate is how drivers report unknown link
+	fn known(lanes: u8, rate: u8) -> Option<Self> {
+		(lanes != 0 && rate != 0).then_some(Self { lanes, rate })
+	}
+}
+impl fmt::Display for LinkConfig {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.rate_name() {
+			Some(name) => write!(f, "{name} x{}", self.lanes),
+			None => write!(f, "{} Mbps x{}", self.lane_mbps(), self.lanes),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModeFit {
+	/// Fits uncompressed
+	Fits,
+	/// Only fits with DSC, which headset supports (or is not known not to), GPU side of it
+	/// is only known once DSC is active, see [`LinkCaps::dsc_enabled`]
+	NeedsDsc,
+	/// Doesn't fit even compressed
+	TooFast,
+	/// Link is unknown
+	Unknown,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LinkCaps {
+	/// Highest link headset (link box) reports in DPCD
+	pub sink_max: Option<LinkConfig>,
+	/// Highest link driver verified GPU, cable and headset can train to (amdgpu)
+	pub verified: Option<LinkConfig>,
+	/// Link as currently trained (amdgpu)
+	pub current: Option<LinkConfig>,
+	pub sink_dsc: Option<bool>,
+	/// Whether DSC is in use right now, which also means GPU supports it
+	pub dsc_enabled: Option<bool>,
+	/// `link-status` property, false after driver gave up on training the link
+	pub link_good: Option<bool>,
+	/// `max bpc` property
+	pub max_bpc: Option<u64>,
+}
+
+impl LinkCaps {
+	/// Reads everything readable for connector, never fails
+	pub fn read(connector: &Connector) -> Self {
+		let mut caps = Self::default();
+		if let Ok(card) = open_card(&connector.card_path()) {
+			if let Ok(props) = connector_properties(&card, &connector.name) {
+				caps.apply_properties(&props);
+			}
+		}
+		if let Some(dpcd) =
+			aux_node(&connector.sysfs_path).and_then(|node| read_dpcd(&node, 0, DPCD_LEN).ok())
+		{
+			caps.apply_dpcd(&dpcd);
+		}
+		// Debugfs directories are named by card minor
+		if let Some(minor) = connector.card.strip_prefix("card") {
+			caps.apply_debugfs(&Path::new(DEBUGFS_DRI).join(minor).join(&connector.name));
+		}
+		caps
+	}
+
+	/// Takes `link-status` and `max bpc` from connector property values
+	pub fn apply_properties(&mut self, props: &[(String, u64)]) {
+		for (name, value) in props {
+			match name.as_str() {
+				// DRM_MODE_LINK_STATUS_GOOD is 0
+				"link-status" => self.link_good = Some(*value == 0),
+				"max bpc" => self.max_bpc = Some(*value),
+				_ => {}
+			}
+		}
+	}
+	/// Takes headset capabilities from DPCD, starting at address 0
+	pub fn apply_dpcd(&mut self, dpcd: &[u8]) {
+		if dpcd.len() > DPCD_MAX_LANE_COUNT {
+			self.sink_max =
+				LinkConfig::known(dpcd[DPCD_MAX_LANE_COUNT] & 0x1f, dpcd[DPCD_MAX_LINK_RATE]);
+		}
+		if let Some(dsc) = dpcd.get(DPCD_DSC_SUPPORT) {
+			self.sink_dsc = Some(dsc & 1 != 0);
+		}
+	}
+	/// Takes link and DSC state from connector debugfs directory, files of both amdgpu and
+	/// i915 are understood, missing ones are skipped
+	pub fn apply_debugfs(&mut self, dir: &Path) {
+		// amdgpu: `Current:  4  0x1e  0  Verified:  4  0x1e  0  Reported: ...`
+		if let Ok(settings) = fs::read_to_string(dir.join("link_settings")) {
+			let words = settings.split_whitespace().collect::<Vec<_>>();
+			let link = |label: &str| {
+				let at = words.iter().position(|&w| w == label)?;
+				let lanes = words.get(at + 1)?.parse().ok()?;
+				let rate = u8::from_str_radix(words.get(at + 2)?.strip_prefix("0x")?, 16).ok()?;
+				LinkConfig::known(lanes, rate)
+			};
+			self.current = link("Current:");
+			self.verified = link("Verified:");
+		}
+		// amdgpu: `FEC_Sink_Support: yes`, i915: `DSC_Enabled: no`, `DSC_Sink_Support: yes`
+		for file in ["dp_dsc_fec_support", "i915_dsc_fec_support"] {
+			let Ok(support) = fs::read_to_string(dir.join(file)) else {
+				continue;
+			};
+			for line in support.lines() {
+				let Some((key, value)) = line.split_once(':') else {
+					continue;
+				};
+				let value = value.trim() == "yes";
+				match key.trim() {
+					"DSC_Sink_Support" => self.sink_dsc = Some(value),
+					"DSC_Enabled" => self.dsc_enabled = Some(value),
+					_ => {}
+				}
+			}
+		}
+		// amdgpu: whether DSC clock of the pipe is on
+		if let Ok(enabled) = fs::read_to_string(dir.join("dsc_clock_en")) {
+			if let Ok(enabled) = enabled.trim().parse::<u8>() {
+				self.dsc_enabled = Some(enabled != 0);
+			}
+		}
+	}
+
+	/// Best known upper bound of the link, headset maximum says nothing about GPU and cable,
+	/// so verified link is preferred
+	pub fn max_link(&self) -> Option<LinkConfig> {
+		self.verified.or(self.sink_max)
+	}
+
+	/// Whether `width`x`height`@`refresh` fits into [`LinkCaps::max_link`]
+	pub fn fit(&self, width: u32, height: u32, refresh: f32) -> ModeFit {
+		let Some(link) = self.max_link() else {
+			return ModeFit::Unknown;
+		};
+		let payload = link.payload_mbps();
+		if pixel_rate_mhz(width, height, refresh) * BPP <= payload {
+			ModeFit::Fits
+		} else if self.min_required_mbps(width, height, refresh) <= payload {
+			ModeFit::NeedsDsc
+		} else {
+			ModeFit::TooFast
+		}
+	}
+	/// Least bandwidth mode can be sent with, compressed, unless headset is known to lack DSC
+	pub fn min_required_mbps(&self, width: u32, height: u32, refresh: f32) -> u64 {
+		let bpp = if self.sink_dsc == Some(false) {
+			BPP
+		} else {
+			DSC_BPP
+		};
+		pixel_rate_mhz(width, height, refresh) * bpp
+	}
+}
+
+impl Connector {
+	/// See [`LinkCaps::read`]
+	pub fn link_caps(&self) -> LinkCaps {
+		LinkCaps::read(self)
+	}
+}
+
+/// Pixel clock estimate, in MHz, with CVT reduced blanking v2 (80 pixels of horizontal blank,
+/// at least 460us of vertical one), exact headset timings are in DisplayID block
+fn pixel_rate_mhz(width: u32, height: u32, refresh: f32) -> u64 {
+	let refresh = refresh as f64;
+	let h_total = width as f64 + 80.0;
+	let v_total = height as f64 / (1.0 - 460e-6 * refresh);
+	(h_total * v_total.ceil() * refresh / 1e6).ceil() as u64
+}
@@ -0,0 +1,174 @@
+//! Locating headset `VkDisplayKHR` for direct-mode presentation
+//!
+//! No Vulkan bindings are pulled in, caller passes its `vkGetInstanceProcAddr` and raw handles
+//! (i.e from ash: `entry.static_fn().get_instance_proc_addr`, `instance.handle().as_raw()`).
+//! Instance should be created with `VK_KHR_display`, and `VK_EXT_acquire_drm_display` for
+//! [`acquire_drm_display`].
+
+use std::{
+	ffi::{c_char, c_void, CStr},
+	fs::File,
+	mem,
+	os::fd::{AsRawFd, RawFd},
+	ptr,
+};
+
+use crate::{drm::Connector, lease, Error, Result};
+
+pub type VkInstance = *mut c_void;
+pub type VkPhysicalDevice = *mut c_void;
+pub type VkDisplayKHR = u64;
+pub type PfnVoidFunction = unsafe extern "system" fn();
+pub type PfnGetInstanceProcAddr =
+	unsafe extern "system" fn(VkInstance, *const c_char) -> Option<PfnVoidFunction>;
+
+type VkResult = i32;
+const VK_SUCCESS: VkResult = 0;
+const VK_INCOMPLETE: VkResult = 5;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct VkExtent2D {
+	width: u32,
+	height: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct VkDisplayPropertiesKHR {
+	display: VkDisplayKHR,
+	display_name: *const c_char,
+	physical_dimensions: VkExtent2D,
+	physical_resolution: VkExtent2D,
+	supported_transforms: u32,
+	plane_reorder_possible: u32,
+	persistent_content: u32,
+}
+
+type PfnGetPhysicalDeviceDisplayProperties =
+	unsafe extern "system" fn(VkPhysicalDevice, *mut u32, *mut VkDisplayPropertiesKHR) -> VkResult;
+type PfnGetDrmDisplay =
+	unsafe extern "system" fn(VkPhysicalDevice, i32, u32, *mut VkDisplayKHR) -> VkResult;
+type PfnAcquireDrmDisplay =
+	unsafe extern "system" fn(VkPhysicalDevice, i32, VkDisplayKHR) -> VkResult;
+
+/// Raw handles of an already created instance
+pub struct Instance {
+	pub get_instance_proc_addr: PfnGetInstanceProcAddr,
+	pub instance: VkInstance,
+	pub physical_device: VkPhysicalDevice,
+}
+impl Instance {
+	/// `name` should be nul-terminated
+	unsafe fn load<F>(&self, name: &'static [u8]) -> Result<F> {
+		let name = CStr::from_bytes_with_nul(name).expect("nul-terminated name");
+		let f = (self.get_instance_proc_addr)(self.instance, name.as_ptr())
+			.ok_or_else(|| Error::VulkanMissingFunction(name.to_string_lossy().into_owned()))?;
+		Ok(mem::transmute_copy::<PfnVoidFunction, F>(&f))
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Display {
+	pub display: VkDisplayKHR,
+	pub name: Option<String>,
+	pub width: u32,
+	pub height: u32,
+}
+
+fn check(res: VkResult) -> Result<()> {
+	if res == VK_SUCCESS {
+		Ok(())
+	} else {
+		Err(Error::Vulkan(res))
+	}
+}
+
+/// Lists displays, visible through `VK_KHR_display`
+///
+/// # Safety
+///
+/// Handles should be valid, and instance should have `VK_KHR_display` enabled
+pub unsafe fn displays(instance: &Instance) -> Result<Vec<Display>> {
+	let get: PfnGetPhysicalDeviceDisplayProperties =
+		instance.load(b"vkGetPhysicalDeviceDisplayPropertiesKHR\0")?;
+	loop {
+		let mut count = 0;
+		check(get(instance.physical_device, &mut count, ptr::null_mut()))?;
+		let mut props = Vec::with_capacity(count as usize);
+		let res = get(instance.physical_device, &mut count, props.as_mut_ptr());
+		if res == VK_INCOMPLETE {
+			continue;
+		}
+		check(res)?;
+		props.set_len(count as usize);
+		return Ok(props
+			.into_iter()
+			.map(|p: VkDisplayPropertiesKHR| Display {
+				display: p.display,
+				name: (!p.display_name.is_null()).then(|| {
+					CStr::from_ptr(p.display_name)
+						.to_string_lossy()
+						.into_owned()
+				}),
+				width: p.physical_resolution.width,
+				height: p.physical_resolution.height,
+			})
+			.collect());
+	}
+}
+
+/// Finds headset between displays, which are exposed by `VK_KHR_display` (i.e non-desktop
+/// displays are not taken by X server, or already acquired through `VK_EXT_direct_mode_display`)
+///
+/// Drivers name displays after EDID monitor name, so it is matched against connector EDID
+///
+/// # Safety
+///
+/// Same as [`displays`]
+pub unsafe fn find_display(instance: &Instance, connector: &Connector) -> Result<Option<Display>> {
+	let Some(name) = &connector.edid.name else {
+		return Ok(None);
+	};
+	Ok(displays(instance)?.into_iter().find(|d| {
+		d.name
+			.as_deref()
+			.is_some_and(|n| n.starts_with(name.as_str()))
+	}))
+}
+
+/// Gets display for connector on `card` (either DRM master, or lease fd from [`lease`]), and
+/// acquires it through `VK_EXT_acquire_drm_display`
+///
+/// # Safety
+///
+/// Handles should be valid, and instance should have `VK_EXT_acquire_drm_display` enabled
+pub unsafe fn acquire_drm_display(
+	instance: &Instance,
+	card: &File,
+	connector: &Connector,
+) -> Result<VkDisplayKHR> {
+	let connector_id = lease::connector_id(card, &connector.name)?;
+	acquire_drm_display_fd(instance, card.as_raw_fd(), connector_id)
+}
+
+/// # Safety
+///
+/// Same as [`acquire_drm_display`], `fd` should be a valid DRM fd
+pub unsafe fn acquire_drm_display_fd(
+	instance: &Instance,
+	fd: RawFd,
+	connector_id: u32,
+) -> Result<VkDisplayKHR> {
+	let get: PfnGetDrmDisplay = instance.load(b"vkGetDrmDisplayEXT\0")?;
+	let acquire: PfnAcquireDrmDisplay = instance.load(b"vkAcquireDrmDisplayEXT\0")?;
+	let mut display = 0;
+	check(get(
+		instance.physical_device,
+		fd,
+		connector_id,
+		&mut display,
+	))?;
+	check(acquire(instance.physical_device, fd, display))?;
+	Ok(display)
+}
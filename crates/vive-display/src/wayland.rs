@@ -0,0 +1,122 @@
+//! Checks, whether Wayland compositor keeps headset out of the desktop
+//!
+//! Kernel `non-desktop` flag is honored by every major compositor, but older versions (or
+//! missing kernel patches) make the headset extend the desktop, and then SteamVR can't get it.
+//! Compositor state is queried through its own CLI tools, so no Wayland connection is needed.
+use std::{env, process::Command};
+
+use serde_json::Value;
+
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Compositor {
+	Sway,
+	Kwin,
+	Gnome,
+	/// Value of `XDG_CURRENT_DESKTOP`, if any
+	Other(String),
+}
+impl Compositor {
+	/// `None` if session is not a Wayland one
+	pub fn detect() -> Option<Self> {
+		env::var_os("WAYLAND_DISPLAY")?;
+		if env::var_os("SWAYSOCK").is_some() {
+			return Some(Self::Sway);
+		}
+		let desktop = env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+		let is = |name: &str| desktop.split(':').any(|d| d.eq_ignore_ascii_case(name));
+		Some(if is("sway") {
+			Self::Sway
+		} else if is("kde") {
+			Self::Kwin
+		} else if is("gnome") {
+			Self::Gnome
+		} else {
+			Self::Other(desktop)
+		})
+	}
+	pub fn name(&self) -> &str {
+		match self {
+			Self::Sway => "sway",
+			Self::Kwin => "KWin",
+			Self::Gnome => "GNOME",
+			Self::Other(name) if !name.is_empty() => name,
+			Self::Other(_) => "unknown compositor",
+		}
+	}
+
+	/// Whether connector `name` (i.e `DP-1`) is used as a desktop output, `None` if
+	/// there is no way to query this compositor
+	pub fn output_enabled(&self, name: &str) -> Result<Option<bool>> {
+		match self {
+			// Non-desktop outputs are not listed at all
+			Self::Sway => {
+				let outputs = json_output("swaymsg", &["-r", "-t", "get_outputs"])?;
+				Ok(Some(
+					outputs
+						.as_array()
+						.into_iter()
+						.flatten()
+						.any(|o| o["name"] == name && o["active"] == true),
+				))
+			}
+			Self::Kwin => {
+				let state = json_output("kscreen-doctor", &["-j"])?;
+				Ok(Some(
+					state["outputs"]
+						.as_array()
+						.into_iter()
+						.flatten()
+						.any(|o| o["name"] == name && o["enabled"] == true),
+				))
+			}
+			Self::Gnome | Self::Other(_) => Ok(None),
+		}
+	}
+
+	/// Command, which removes output from the desktop until it is reconnected
+	pub fn disable_command(&self, name: &str) -> Option<Vec<String>> {
+		match self {
+			Self::Sway => Some(vec![
+				"swaymsg".to_owned(),
+				"output".to_owned(),
+				name.to_owned(),
+				"disable".to_owned(),
+			]),
+			Self::Kwin => Some(vec![
+				"kscreen-doctor".to_owned(),
+				format!("output.{name}.disable"),
+			]),
+			Self::Gnome | Self::Other(_) => None,
+		}
+	}
+
+	/// Manual advice, for compositors without [`Self::disable_command`]
+	pub fn advice(&self) -> &'static str {
+		match self {
+			Self::Sway => "update sway to 1.7+, which supports DRM leasing",
+			Self::Kwin => "update Plasma to 5.24+, which supports DRM leasing",
+			Self::Gnome => "GNOME 44+ supports DRM leasing, on older versions disable headset in Settings > Displays",
+			Self::Other(_) => "disable headset output in compositor settings",
+		}
+	}
+}
+
+fn json_output(program: &str, args: &[&str]) -> Result<Value> {
+	let out = Command::new(program).args(args).output()?;
+	if !out.status.success() {
+		return Err(Error::Command(format!("{program} failed: {}", out.status)));
+	}
+	serde_json::from_slice(&out.stdout)
+		.map_err(|e| Error::Command(format!("{program} printed malformed json: {e}")))
+}
+
+pub fn run_command(args: &[String]) -> Result<()> {
+	let (program, args) = args.split_first().expect("command is not empty");
+	let status = Command::new(program).args(args).status()?;
+	if !status.success() {
+		return Err(Error::Command(format!("{program} failed: {status}")));
+	}
+	Ok(())
+}
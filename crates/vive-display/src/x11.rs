@@ -0,0 +1,119 @@
+//! RandR side of non-desktop handling, for X11 sessions
+//!
+//! Output names differ from kernel connector names between DDX drivers (`DP-1` vs
+//! `DisplayPort-0`), so headset output is found by EDID. Everything goes through `xrandr`,
+//! to not depend on Xlib.
+use std::process::Command;
+
+use crate::{
+	edid::{Edid, EdidId},
+	Error, Result,
+};
+
+#[derive(Debug, Clone)]
+pub struct Output {
+	/// RandR output name, as accepted by `xrandr --output`
+	pub name: String,
+	pub connected: bool,
+	/// Has a CRTC assigned, i.e is a part of the desktop
+	pub active: bool,
+	/// `None` if server doesn't expose the property (RandR < 1.6)
+	pub non_desktop: Option<bool>,
+	pub edid: Option<Edid>,
+}
+
+fn hex(data: &str) -> Option<Vec<u8>> {
+	(0..data.len())
+		.step_by(2)
+		.map(|i| u8::from_str_radix(data.get(i..i + 2)?, 16).ok())
+		.collect()
+}
+
+/// Parses `xrandr --props` output
+pub fn parse_outputs(text: &str) -> Vec<Output> {
+	let mut outputs: Vec<Output> = Vec::new();
+	let mut edid: Option<String> = None;
+	let finish_edid = |outputs: &mut Vec<Output>, edid: &mut Option<String>| {
+		if let (Some(output), Some(data)) = (outputs.last_mut(), edid.take()) {
+			output.edid = hex(&data).and_then(|data| Edid::parse(&data).ok());
+		}
+	};
+	for line in text.lines() {
+		if !line.starts_with(char::is_whitespace) {
+			finish_edid(&mut outputs, &mut edid);
+			let mut words = line.split_whitespace();
+			let (Some(name), Some(state)) = (words.next(), words.next()) else {
+				continue;
+			};
+			if state != "connected" && state != "disconnected" {
+				// `Screen 0: ...` header
+				continue;
+			}
+			// Geometry (`WxH+X+Y`) is only printed for outputs with CRTC
+			let active = words
+				.take_while(|w| !w.starts_with('('))
+				.any(|w| w.contains('x') && w.contains('+'));
+			outputs.push(Output {
+				name: name.to_owned(),
+				connected: state == "connected",
+				active,
+				non_desktop: None,
+				edid: None,
+			});
+			continue;
+		}
+		let trimmed = line.trim();
+		if let Some(data) = &mut edid {
+			if trimmed.bytes().all(|b| b.is_ascii_hexdigit()) && !trimmed.is_empty() {
+				data.push_str(trimmed);
+				continue;
+			}
+			finish_edid(&mut outputs, &mut edid);
+		}
+		if trimmed == "EDID:" {
+			edid = Some(String::new());
+		} else if let Some(value) = trimmed.strip_prefix("non-desktop:") {
+			if let Some(output) = outputs.last_mut() {
+				output.non_desktop = value.split_whitespace().next().map(|v| v == "1");
+			}
+		}
+	}
+	finish_edid(&mut outputs, &mut edid);
+	outputs
+}
+
+fn xrandr(args: &[&str]) -> Result<String> {
+	let out = Command::new("xrandr").args(args).output()?;
+	if !out.status.success() {
+		return Err(Error::Command(format!(
+			"xrandr failed: {}",
+			String::from_utf8_lossy(&out.stderr).trim()
+		)));
+	}
+	Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+pub fn outputs() -> Result<Vec<Output>> {
+	Ok(parse_outputs(&xrandr(&["--props"])?))
+}
+
+/// Connected output with EDID matching `id`
+pub fn find_output(id: EdidId) -> Result<Option<Output>> {
+	Ok(outputs()?
+		.into_iter()
+		.filter(|o| o.connected)
+		.find(|o| o.edid.as_ref().map(|e| e.matches(id)) == Some(true)))
+}
+
+impl Output {
+	/// Marks output as non-desktop, so window managers (and RandR-aware compositors) skip it
+	pub fn set_non_desktop(&self) -> Result<()> {
+		xrandr(&["--output", &self.name, "--set", "non-desktop", "1"])?;
+		Ok(())
+	}
+	/// Removes output from the desktop, SteamVR may still lease it
+	pub fn disable(&self) -> Result<()> {
+		xrandr(&["--output", &self.name, "--off"])?;
+		Ok(())
+	}
+}
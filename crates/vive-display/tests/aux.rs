@@ -0,0 +1,29 @@
+//! DPCD identification blocks, as sink and branch devices fill them
+use vive_display::aux::DeviceId;
+
+#[test]
+fn device_id() {
+	let mut block = [0u8; 12];
+	assert_eq!(DeviceId::parse(&block), None);
+	assert_eq!(DeviceId::parse(&block[..4]), None);
+
+	block[..3].copy_from_slice(&[0x00, 0x1c, 0xf8]);
+	block[3..8].copy_from_slice(b"HMD01");
+	block[9] = 0x10;
+	block[10..].copy_from_slice(&[2, 7]);
+	assert_eq!(
+		DeviceId::parse(&block),
+		Some(DeviceId {
+			oui: Some("00-1c-f8".to_owned()),
+			device: "HMD01".to_owned(),
+			hardware_rev: 0x10,
+			firmware_rev: (2, 7),
+		})
+	);
+
+	// Vendors without OUI still fill the rest
+	block[..3].fill(0);
+	let id = DeviceId::parse(&block).expect("id");
+	assert_eq!(id.oui, None);
+	assert_eq!(id.device, "HMD01");
+}
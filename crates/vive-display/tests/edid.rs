@@ -0,0 +1,132 @@
+//! EDID blobs in `tests/fixtures/edid`, see README there
+use std::{env, fs, path::Path, process};
+
+use vive_display::{
+	drm::{find_connectors_in, HTC_VENDOR},
+	edid::{parse_pnp_id, pnp_id, Edid, EdidId},
+	Error,
+};
+
+const VIVE_PRO2: EdidId = EdidId {
+	vendor: HTC_VENDOR,
+	product: 0x0906,
+};
+
+fn fixture(name: &str) -> Vec<u8> {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("tests/fixtures/edid")
+		.join(name);
+	fs::read(&path).unwrap_or_else(|e| panic!("failed to read {path:?}: {e}"))
+}
+
+#[test]
+fn vive_pro2_reconstructed() {
+	let blob = fixture("vive-pro2-reconstructed.bin");
+	// Extension blocks are there, but only base one is parsed
+	assert_eq!(blob.len(), 256);
+	let edid = Edid::parse(&blob).expect("edid");
+	assert_eq!(edid.id, VIVE_PRO2);
+	assert_eq!(edid.id.pnp_id(), "HVR");
+	assert_eq!(edid.serial, 0);
+	assert_eq!((edid.week, edid.year), (20, 2021));
+	assert_eq!(edid.name.as_deref(), Some("VIVE Pro 2"));
+	assert_eq!(edid.serial_string.as_deref(), Some("XXXXXXXXXXXX"));
+	assert!(edid.matches(VIVE_PRO2));
+	assert!(!edid.matches(EdidId {
+		product: 0x0907,
+		..VIVE_PRO2
+	}));
+
+	let base = Edid::parse(&blob[..128]).expect("base block");
+	assert_eq!(base.id, edid.id);
+}
+
+/// SteamVR config and `Prop_EdidVendorID_Int32` have manufacturer bytes swapped
+#[test]
+fn config_ids() {
+	let edid = Edid::parse(&fixture("vive-pro2-reconstructed.bin")).expect("edid");
+	let (vid, pid) = edid.id.to_config();
+	assert_eq!((vid, pid), (0xd222, 0x0906));
+	assert_eq!(EdidId::from_config(vid, pid), edid.id);
+	// Upper bits of config values are not a part of id
+	assert_eq!(EdidId::from_config(vid | 0x10000, pid | 0x10000), edid.id);
+}
+
+#[test]
+fn pnp_ids() {
+	assert_eq!(parse_pnp_id("HVR"), Some(HTC_VENDOR));
+	assert_eq!(pnp_id(HTC_VENDOR), "HVR");
+	for id in ["AAA", "ZZZ", "DEL"] {
+		assert_eq!(parse_pnp_id(id).map(pnp_id).as_deref(), Some(id));
+	}
+	assert_eq!(parse_pnp_id("hvr"), None);
+	assert_eq!(parse_pnp_id("HV"), None);
+	assert_eq!(parse_pnp_id("HVRX"), None);
+	assert_eq!(pnp_id(0), "???");
+}
+
+#[test]
+fn damaged() {
+	let blob = fixture("vive-pro2-reconstructed.bin");
+	assert!(matches!(Edid::parse(&[]), Err(Error::EdidTooShort(0))));
+	assert!(matches!(
+		Edid::parse(&blob[..127]),
+		Err(Error::EdidTooShort(127))
+	));
+
+	let mut header = blob.clone();
+	header[0] = 0xff;
+	// Checksum is fixed, so only header is wrong
+	header[127] = header[127].wrapping_sub(0xff);
+	assert!(matches!(Edid::parse(&header), Err(Error::EdidBadHeader)));
+
+	let mut product = blob.clone();
+	product[10] ^= 1;
+	assert!(matches!(Edid::parse(&product), Err(Error::EdidBadChecksum)));
+
+	// Extension block has its own checksum, which isn't checked
+	let mut extension = blob;
+	extension[200] ^= 1;
+	assert!(Edid::parse(&extension).is_ok());
+}
+
+/// Sysfs connector directories, with fixture as EDID of some of them
+#[test]
+fn connectors() {
+	let root = env::temp_dir().join(format!("vive-display-drm-{}", process::id()));
+	let _ = fs::remove_dir_all(&root);
+	let blob = fixture("vive-pro2-reconstructed.bin");
+	let mut other = blob[..128].to_vec();
+	other[8..10].copy_from_slice(&parse_pnp_id("DEL").unwrap().to_be_bytes());
+	other[127] = 0;
+	other[127] = 0u8.wrapping_sub(other.iter().fold(0u8, |a, b| a.wrapping_add(*b)));
+	for (name, edid, status) in [
+		("card0-DP-1", &other[..], "connected"),
+		("card0-DP-2", &blob[..], "disconnected"),
+		("card1-DP-3", &blob[..], "connected"),
+		("card1-HDMI-A-1", &[][..], "disconnected"),
+		// Not a connector
+		("card1", &blob[..], "connected"),
+	] {
+		let dir = root.join(name);
+		fs::create_dir_all(&dir).unwrap();
+		fs::write(dir.join("edid"), edid).unwrap();
+		fs::write(dir.join("status"), format!("{status}\n")).unwrap();
+	}
+
+	let found = find_connectors_in(&root, VIVE_PRO2).expect("connectors");
+	let found = found
+		.iter()
+		.map(|c| (c.card.as_str(), c.name.as_str(), c.connected))
+		.collect::<Vec<_>>();
+	// Connected first
+	assert_eq!(found, [("card1", "DP-3", true), ("card0", "DP-2", false)]);
+
+	let other = Edid::parse(&other).unwrap().id;
+	let found = find_connectors_in(&root, other).expect("connectors");
+	assert_eq!(found.len(), 1);
+	assert_eq!(found[0].name, "DP-1");
+	assert_eq!(found[0].card_path(), Path::new("/dev/dri/card0"));
+
+	let _ = fs::remove_dir_all(&root);
+}
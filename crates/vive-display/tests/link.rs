@@ -0,0 +1,83 @@
+//! Link capabilities from fixture debugfs directories, see README in `tests/fixtures`
+use std::path::{Path, PathBuf};
+
+use vive_display::link::{LinkCaps, LinkConfig, ModeFit};
+
+const HBR2_X4: LinkConfig = LinkConfig {
+	lanes: 4,
+	rate: 0x14,
+};
+const HBR3_X4: LinkConfig = LinkConfig {
+	lanes: 4,
+	rate: 0x1e,
+};
+
+fn fixture(name: &str) -> PathBuf {
+	Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("tests/fixtures")
+		.join(name)
+}
+
+fn dpcd(rate: u8, lanes: u8, dsc: bool) -> Vec<u8> {
+	let mut dpcd = vec![0; 0x61];
+	dpcd[0] = 0x14;
+	dpcd[1] = rate;
+	// Enhanced framing and TPS3 bits are above lane count
+	dpcd[2] = 0xc0 | lanes;
+	dpcd[0x60] = dsc as u8;
+	dpcd
+}
+
+#[test]
+fn amdgpu_verified_link_wins() {
+	let mut caps = LinkCaps::default();
+	caps.apply_dpcd(&dpcd(0x1e, 4, true));
+	caps.apply_debugfs(&fixture("amdgpu"));
+	assert_eq!(caps.sink_max, Some(HBR3_X4));
+	assert_eq!(caps.verified, Some(HBR2_X4));
+	assert_eq!(caps.current, Some(HBR2_X4));
+	assert_eq!(caps.sink_dsc, Some(true));
+	assert_eq!(caps.dsc_enabled, Some(false));
+	assert_eq!(caps.max_link(), Some(HBR2_X4));
+	assert_eq!(HBR2_X4.to_string(), "HBR2 x4");
+	assert_eq!(HBR2_X4.payload_mbps(), 17280);
+
+	assert_eq!(caps.fit(2448, 1224, 120.0), ModeFit::Fits);
+	assert_eq!(caps.fit(3672, 1836, 90.0), ModeFit::Fits);
+	assert_eq!(caps.fit(4896, 2448, 90.0), ModeFit::NeedsDsc);
+	assert_eq!(caps.fit(4896, 2448, 120.0), ModeFit::NeedsDsc);
+
+	// Link trained down to a single lane at lowest rate
+	caps.verified = Some(LinkConfig {
+		lanes: 1,
+		rate: 0x06,
+	});
+	assert_eq!(caps.fit(2448, 1224, 90.0), ModeFit::TooFast);
+}
+
+#[test]
+fn i915_dsc_state() {
+	let mut caps = LinkCaps::default();
+	caps.apply_debugfs(&fixture("i915"));
+	assert_eq!(caps.dsc_enabled, Some(true));
+	assert_eq!(caps.sink_dsc, Some(true));
+	// Nothing tells link rate
+	assert_eq!(caps.fit(2448, 1224, 90.0), ModeFit::Unknown);
+
+	caps.apply_dpcd(&dpcd(0x1e, 4, true));
+	assert_eq!(caps.fit(4896, 2448, 120.0), ModeFit::NeedsDsc);
+	caps.apply_dpcd(&dpcd(0x1e, 4, false));
+	assert_eq!(caps.fit(4896, 2448, 120.0), ModeFit::TooFast);
+}
+
+#[test]
+fn link_properties() {
+	let mut caps = LinkCaps::default();
+	caps.apply_properties(&[
+		("EDID".to_owned(), 42),
+		("link-status".to_owned(), 1),
+		("max bpc".to_owned(), 8),
+	]);
+	assert_eq!(caps.link_good, Some(false));
+	assert_eq!(caps.max_bpc, Some(8));
+}
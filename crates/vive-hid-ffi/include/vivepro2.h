@@ -0,0 +1,168 @@
+#ifndef VIVEPRO2_H
+#define VIVEPRO2_H
+
+/* Written by hand as cbindgen would generate it from crates/vive-hid-ffi, checked by its tests/header.rs */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum VivePro2Status {
+  VIVE_PRO2_STATUS_OK = 0,
+  /**
+   * [`vivepro2_poll_status`] got no status report in time
+   */
+  VIVE_PRO2_STATUS_NO_EVENT = 1,
+  VIVE_PRO2_STATUS_NOT_FOUND = 2,
+  /**
+   * Null pointer, or string which is not UTF-8
+   */
+  VIVE_PRO2_STATUS_INVALID_ARGUMENT = 3,
+  /**
+   * Previous mode switch is still in progress, or SteamVR holds the headset
+   */
+  VIVE_PRO2_STATUS_BUSY = 4,
+  VIVE_PRO2_STATUS_HID = 5,
+  VIVE_PRO2_STATUS_OTHER = 6,
+  /**
+   * Rust side has panicked, handle should not be used anymore
+   */
+  VIVE_PRO2_STATUS_PANIC = 7,
+} VivePro2Status;
+
+/**
+ * Opened headset, from [`vivepro2_open`], released with [`vivepro2_close`]
+ */
+typedef struct VivePro2Device VivePro2Device;
+
+typedef struct VivePro2Mode {
+  /**
+   * Passed to [`vivepro2_set_mode`]
+   */
+  uint8_t id;
+  uint32_t width;
+  uint32_t height;
+  float frame_rate;
+  float extra_photon_vsync;
+} VivePro2Mode;
+
+/**
+ * Sensor state, sent by headset continuously
+ */
+typedef struct VivePro2HeadsetStatus {
+  /**
+   * Raw IPD knob position, hundredths of millimeter
+   */
+  uint16_t ipd;
+  float ipd_meters;
+  /**
+   * Raw value, grows with distance between lenses
+   */
+  uint16_t lens_separation;
+  uint8_t button;
+  /**
+   * Raw sensor value, larger means closer
+   */
+  uint16_t proximity;
+  uint8_t proximity_change;
+} VivePro2HeadsetStatus;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Message of the last failed call on this thread, or null. Valid until the next failed call
+ */
+const char *vivepro2_last_error(void);
+
+/**
+ * Opens headset by its serial (devsn, as shown by `vivectl info`), or the first one found, if
+ * `serial` is null. `VIVEPRO2_SIMULATE=1` opens a simulated headset
+ *
+ * # Safety
+ * `serial` is null or a NUL-terminated string, `out` is valid for writes
+ */
+VivePro2Status vivepro2_open(const char *serial, VivePro2Device **out);
+
+/**
+ * # Safety
+ * `device` is null, or a handle from [`vivepro2_open`], which is not used afterwards
+ */
+void vivepro2_close(VivePro2Device *device);
+
+/**
+ * Factory config as JSON, released with [`vivepro2_free_string`]. Reading takes a few seconds
+ *
+ * # Safety
+ * `device` is a valid handle, `out` is valid for writes
+ */
+VivePro2Status vivepro2_read_config(VivePro2Device *device, char **out);
+
+/**
+ * # Safety
+ * `s` is null, or a string returned by this library, which is not used afterwards
+ */
+void vivepro2_free_string(char *s);
+
+/**
+ * Number of modes, at least one
+ *
+ * # Safety
+ * `device` is a valid handle
+ */
+size_t vivepro2_mode_count(VivePro2Device *device);
+
+/**
+ * # Safety
+ * `device` is a valid handle, `out` is valid for writes
+ */
+VivePro2Status vivepro2_get_mode(VivePro2Device *device, size_t index, VivePro2Mode *out);
+
+/**
+ * Headset disconnects to apply the mode, close the handle, and open it again with
+ * [`vivepro2_wait_reconnect`]
+ *
+ * # Safety
+ * `device` is a valid handle
+ */
+VivePro2Status vivepro2_set_mode(VivePro2Device *device, uint8_t mode);
+
+/**
+ * Opens the first headset, once it is back after mode switch
+ *
+ * # Safety
+ * `out` is valid for writes
+ */
+VivePro2Status vivepro2_wait_reconnect(uint32_t timeout_ms, VivePro2Device **out);
+
+/**
+ * # Safety
+ * `device` is a valid handle
+ */
+VivePro2Status vivepro2_set_brightness(VivePro2Device *device, uint8_t brightness);
+
+/**
+ * # Safety
+ * `device` is a valid handle
+ */
+VivePro2Status vivepro2_set_noise_cancel(VivePro2Device *device, bool enabled);
+
+/**
+ * Waits up to `timeout_ms` for the next status report, [`VivePro2Status::NoEvent`] if there is
+ * none
+ *
+ * # Safety
+ * `device` is a valid handle, `out` is valid for writes
+ */
+VivePro2Status vivepro2_poll_status(VivePro2Device *device,
+                                    int32_t timeout_ms,
+                                    VivePro2HeadsetStatus *out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* VIVEPRO2_H */
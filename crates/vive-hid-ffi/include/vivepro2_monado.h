@@ -0,0 +1,122 @@
+#ifndef VIVEPRO2_MONADO_H
+#define VIVEPRO2_MONADO_H
+
+/* Written by hand as cbindgen would generate it from crates/vive-hid-ffi, with `monado` feature, checked by its tests/header.rs */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include "vivepro2.h"
+
+/**
+ * Created with [`vivepro2_monado_open`], released with [`vivepro2_monado_close`]
+ */
+typedef struct VivePro2MonadoHmd VivePro2MonadoHmd;
+
+/**
+ * `xrt_hmd_parts::screens[0]`, and view size, both views are side by side
+ */
+typedef struct VivePro2MonadoDisplay {
+  uint32_t w_pixels;
+  uint32_t h_pixels;
+  uint64_t nominal_frame_interval_ns;
+  /**
+   * From vsync to the moment image is lit, for prediction
+   */
+  uint64_t vsync_to_photons_ns;
+  uint32_t view_w_pixels;
+  uint32_t view_h_pixels;
+} VivePro2MonadoDisplay;
+
+/**
+ * `xrt_fov`, radians, left and down are negative
+ */
+typedef struct VivePro2Fov {
+  float angle_left;
+  float angle_right;
+  float angle_up;
+  float angle_down;
+} VivePro2Fov;
+
+/**
+ * `xrt_vec2`
+ */
+typedef struct VivePro2Vec2 {
+  float x;
+  float y;
+} VivePro2Vec2;
+
+/**
+ * `xrt_uv_triplet`, where each channel is sampled in the rendered view, 0,0 is top-left
+ */
+typedef struct VivePro2UvTriplet {
+  VivePro2Vec2 r;
+  VivePro2Vec2 g;
+  VivePro2Vec2 b;
+} VivePro2UvTriplet;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Reads config, and computes distortion with lens server, or approximation, if `approximate` is
+ * set. Takes a few seconds, more if lens server is not cached yet. `mode` is the one headset is
+ * in, as set by [`crate::vivepro2_set_mode`], or by SteamVR driver
+ *
+ * # Safety
+ * `device` is a valid handle, `out` is valid for writes
+ */
+VivePro2Status vivepro2_monado_open(VivePro2Device *device,
+                                    uint8_t mode,
+                                    bool approximate,
+                                    VivePro2MonadoHmd **out);
+
+/**
+ * # Safety
+ * `hmd` is null, or a handle from [`vivepro2_monado_open`], which is not used afterwards
+ */
+void vivepro2_monado_close(VivePro2MonadoHmd *hmd);
+
+/**
+ * # Safety
+ * `hmd` is a valid handle, `out` is valid for writes
+ */
+VivePro2Status vivepro2_monado_display(const VivePro2MonadoHmd *hmd, VivePro2MonadoDisplay *out);
+
+/**
+ * Raw factory config JSON, as `vive_config_parse` expects it. Owned by `hmd`
+ *
+ * # Safety
+ * `hmd` is a valid handle
+ */
+const char *vivepro2_monado_config_json(const VivePro2MonadoHmd *hmd);
+
+/**
+ * `view` is 0 for left eye, 1 for right
+ *
+ * # Safety
+ * `hmd` is a valid handle, `out` is valid for writes
+ */
+VivePro2Status vivepro2_monado_fov(const VivePro2MonadoHmd *hmd, uint32_t view, VivePro2Fov *out);
+
+/**
+ * Same contract as `xrt_device::compute_distortion`, `u` and `v` are in view space, 0,0 is
+ * top-left. Interpolated from the grid, so it is cheap enough to be called per vertex
+ *
+ * # Safety
+ * `hmd` is a valid handle, `out` is valid for writes
+ */
+bool vivepro2_monado_compute_distortion(const VivePro2MonadoHmd *hmd,
+                                        uint32_t view,
+                                        float u,
+                                        float v,
+                                        VivePro2UvTriplet *out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* VIVEPRO2_MONADO_H */
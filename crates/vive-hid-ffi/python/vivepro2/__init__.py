@@ -0,0 +1,196 @@
+"""VIVE Pro 2 headset control, for scripting and lab automation
+
+Thin wrapper over libvivepro2 (crates/vive-hid-ffi), same calls as its C API. Library is looked up
+in VIVEPRO2_LIB, next to this module, then in the system library path.
+
+    import vivepro2
+    with vivepro2.Device() as hmd:
+        print([m.width for m in hmd.modes()])
+        hmd.set_brightness(100)
+        print(hmd.poll_status())
+"""
+import ctypes
+import ctypes.util
+import json
+import os
+from dataclasses import dataclass
+from typing import List, Optional
+
+__all__ = ["Error", "NotFound", "Busy", "Mode", "Status", "Device"]
+
+_OK = 0
+_NO_EVENT = 1
+_NOT_FOUND = 2
+_BUSY = 4
+
+
+class Error(Exception):
+    def __init__(self, status: int, message: str):
+        super().__init__(message)
+        self.status = status
+
+
+class NotFound(Error):
+    pass
+
+
+class Busy(Error):
+    """Previous mode switch is still in progress, or SteamVR holds the headset"""
+
+
+@dataclass
+class Mode:
+    id: int
+    width: int
+    height: int
+    frame_rate: float
+    extra_photon_vsync: float
+
+
+@dataclass
+class Status:
+    """Sensor state, sent by headset continuously"""
+
+    ipd: int
+    ipd_meters: float
+    lens_separation: int
+    button: int
+    proximity: int
+    proximity_change: int
+
+
+class _Mode(ctypes.Structure):
+    _fields_ = [
+        ("id", ctypes.c_uint8),
+        ("width", ctypes.c_uint32),
+        ("height", ctypes.c_uint32),
+        ("frame_rate", ctypes.c_float),
+        ("extra_photon_vsync", ctypes.c_float),
+    ]
+
+
+class _Status(ctypes.Structure):
+    _fields_ = [
+        ("ipd", ctypes.c_uint16),
+        ("ipd_meters", ctypes.c_float),
+        ("lens_separation", ctypes.c_uint16),
+        ("button", ctypes.c_uint8),
+        ("proximity", ctypes.c_uint16),
+        ("proximity_change", ctypes.c_uint8),
+    ]
+
+
+def _load():
+    path = os.environ.get("VIVEPRO2_LIB")
+    if path is None:
+        bundled = os.path.join(os.path.dirname(__file__), "libvivepro2.so")
+        path = bundled if os.path.exists(bundled) else ctypes.util.find_library("vivepro2")
+    if path is None:
+        raise ImportError("libvivepro2 is not found, build vive-hid-ffi, or set VIVEPRO2_LIB")
+    lib = ctypes.CDLL(path)
+    handle = ctypes.c_void_p
+    status = ctypes.c_int
+    for name, args, res in [
+        ("vivepro2_last_error", [], ctypes.c_char_p),
+        ("vivepro2_open", [ctypes.c_char_p, ctypes.POINTER(handle)], status),
+        ("vivepro2_close", [handle], None),
+        ("vivepro2_read_config", [handle, ctypes.POINTER(ctypes.c_void_p)], status),
+        ("vivepro2_free_string", [ctypes.c_void_p], None),
+        ("vivepro2_mode_count", [handle], ctypes.c_size_t),
+        ("vivepro2_get_mode", [handle, ctypes.c_size_t, ctypes.POINTER(_Mode)], status),
+        ("vivepro2_set_mode", [handle, ctypes.c_uint8], status),
+        ("vivepro2_wait_reconnect", [ctypes.c_uint32, ctypes.POINTER(handle)], status),
+        ("vivepro2_set_brightness", [handle, ctypes.c_uint8], status),
+        ("vivepro2_set_noise_cancel", [handle, ctypes.c_bool], status),
+        ("vivepro2_poll_status", [handle, ctypes.c_int32, ctypes.POINTER(_Status)], status),
+    ]:
+        f = getattr(lib, name)
+        f.argtypes = args
+        f.restype = res
+    return lib
+
+
+_lib = _load()
+
+
+def _check(status: int) -> int:
+    if status in (_OK, _NO_EVENT):
+        return status
+    message = (_lib.vivepro2_last_error() or b"unknown error").decode(errors="replace")
+    cls = {_NOT_FOUND: NotFound, _BUSY: Busy}.get(status, Error)
+    raise cls(status, message)
+
+
+class Device:
+    """Opened headset, by devsn (as shown by `vivectl info`), or the first one found"""
+
+    def __init__(self, serial: Optional[str] = None, *, _handle=None):
+        self._handle = None
+        if _handle is None:
+            _handle = ctypes.c_void_p()
+            arg = serial.encode() if serial is not None else None
+            _check(_lib.vivepro2_open(arg, ctypes.byref(_handle)))
+        self._handle = _handle
+
+    @classmethod
+    def wait_reconnect(cls, timeout: float = 10.0) -> "Device":
+        """First headset, once it is back after `set_mode`"""
+        handle = ctypes.c_void_p()
+        _check(_lib.vivepro2_wait_reconnect(int(timeout * 1000), ctypes.byref(handle)))
+        return cls(_handle=handle)
+
+    def close(self):
+        if self._handle:
+            _lib.vivepro2_close(self._handle)
+            self._handle = None
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, *_):
+        self.close()
+
+    def __del__(self):
+        self.close()
+
+    def _device(self):
+        if not self._handle:
+            raise Error(3, "device is closed")
+        return self._handle
+
+    def config(self) -> dict:
+        """Factory config, reading takes a few seconds"""
+        out = ctypes.c_void_p()
+        _check(_lib.vivepro2_read_config(self._device(), ctypes.byref(out)))
+        try:
+            return json.loads(ctypes.string_at(out.value))
+        finally:
+            _lib.vivepro2_free_string(out)
+
+    def modes(self) -> List[Mode]:
+        out = []
+        for i in range(_lib.vivepro2_mode_count(self._device())):
+            mode = _Mode()
+            _check(_lib.vivepro2_get_mode(self._device(), i, ctypes.byref(mode)))
+            out.append(Mode(mode.id, mode.width, mode.height, mode.frame_rate, mode.extra_photon_vsync))
+        return out
+
+    def set_mode(self, mode: int):
+        """Headset disconnects to apply it, this handle is closed, use `Device.wait_reconnect`"""
+        try:
+            _check(_lib.vivepro2_set_mode(self._device(), mode))
+        finally:
+            self.close()
+
+    def set_brightness(self, brightness: int):
+        _check(_lib.vivepro2_set_brightness(self._device(), brightness))
+
+    def set_noise_cancel(self, enabled: bool):
+        _check(_lib.vivepro2_set_noise_cancel(self._device(), enabled))
+
+    def poll_status(self, timeout: float = 1.0) -> Optional[Status]:
+        """Next status report, or None if there was none in time"""
+        s = _Status()
+        if _check(_lib.vivepro2_poll_status(self._device(), int(timeout * 1000), ctypes.byref(s))) == _NO_EVENT:
+            return None
+        return Status(s.ipd, s.ipd_meters, s.lens_separation, s.button, s.proximity, s.proximity_change)
@@ -0,0 +1,334 @@
+//! C API of vive-hid, for projects which can't use Rust crates directly (Monado, standalone
+//! tools). Header is in `include/vivepro2.h`, in the form cbindgen produces with `cbindgen.toml`,
+//! `tests/header.rs` checks it against this file
+//!
+//! Every call returns [`VivePro2Status`], and on failure, message of the error is available
+//! from [`vivepro2_last_error`] on the same thread. Handles may be used from any thread, but not
+//! from several at once
+use std::{
+	cell::RefCell,
+	ffi::{c_char, CStr, CString},
+	panic::{catch_unwind, AssertUnwindSafe},
+	ptr,
+	time::Duration,
+};
+
+use vive_hid::{Error, ViveDevice};
+
+#[cfg(feature = "monado")]
+pub mod monado;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VivePro2Status {
+	Ok = 0,
+	/// [`vivepro2_poll_status`] got no status report in time
+	NoEvent = 1,
+	NotFound = 2,
+	/// Null pointer, or string which is not UTF-8
+	InvalidArgument = 3,
+	/// Previous mode switch is still in progress, or SteamVR holds the headset
+	Busy = 4,
+	Hid = 5,
+	Other = 6,
+	/// Rust side has panicked, handle should not be used anymore
+	Panic = 7,
+}
+
+/// Opened headset, from [`vivepro2_open`], released with [`vivepro2_close`]
+pub struct VivePro2Device(ViveDevice);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VivePro2Mode {
+	/// Passed to [`vivepro2_set_mode`]
+	pub id: u8,
+	pub width: u32,
+	pub height: u32,
+	pub frame_rate: f32,
+	pub extra_photon_vsync: f32,
+}
+
+/// Sensor state, sent by headset continuously
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VivePro2HeadsetStatus {
+	/// Raw IPD knob position, hundredths of millimeter
+	pub ipd: u16,
+	pub ipd_meters: f32,
+	/// Raw value, grows with distance between lenses
+	pub lens_separation: u16,
+	pub button: u8,
+	/// Raw sensor value, larger means closer
+	pub proximity: u16,
+	pub proximity_change: u8,
+}
+
+thread_local! {
+	static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(message: String) {
+	let message = CString::new(message).unwrap_or_else(|e| {
+		let mut bytes = e.into_vec();
+		bytes.retain(|b| *b != 0);
+		CString::new(bytes).expect("NULs are removed")
+	});
+	LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+fn status(e: Error) -> VivePro2Status {
+	let status = match e.root() {
+		Error::DeviceNotFound | Error::NotAVive | Error::Disconnected(_) => {
+			VivePro2Status::NotFound
+		}
+		Error::ModeSwitchInProgress | Error::HeldBySteamVr { .. } => VivePro2Status::Busy,
+		Error::Hid(_) | Error::PermissionDenied(_) => VivePro2Status::Hid,
+		_ => VivePro2Status::Other,
+	};
+	set_error(e.to_string());
+	status
+}
+
+fn invalid(what: &str) -> VivePro2Status {
+	set_error(format!("invalid argument: {what}"));
+	VivePro2Status::InvalidArgument
+}
+
+/// Panics must not cross FFI boundary
+fn guard(f: impl FnOnce() -> VivePro2Status) -> VivePro2Status {
+	catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+		set_error("vive-hid has panicked".to_owned());
+		VivePro2Status::Panic
+	})
+}
+
+/// Runs `f` with device behind handle
+unsafe fn with_device(
+	device: *mut VivePro2Device,
+	f: impl FnOnce(&ViveDevice) -> VivePro2Status,
+) -> VivePro2Status {
+	match device.as_ref() {
+		Some(device) => guard(|| f(&device.0)),
+		None => invalid("device is null"),
+	}
+}
+
+fn result(res: Result<(), Error>) -> VivePro2Status {
+	match res {
+		Ok(()) => VivePro2Status::Ok,
+		Err(e) => status(e),
+	}
+}
+
+/// Message of the last failed call on this thread, or null. Valid until the next failed call
+#[no_mangle]
+pub extern "C" fn vivepro2_last_error() -> *const c_char {
+	LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Opens headset by its serial (devsn, as shown by `vivectl info`), or the first one found, if
+/// `serial` is null. `VIVEPRO2_SIMULATE=1` opens a simulated headset
+///
+/// # Safety
+/// `serial` is null or a NUL-terminated string, `out` is valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn vivepro2_open(
+	serial: *const c_char,
+	out: *mut *mut VivePro2Device,
+) -> VivePro2Status {
+	if out.is_null() {
+		return invalid("out is null");
+	}
+	let serial = match serial.is_null() {
+		true => None,
+		false => match CStr::from_ptr(serial).to_str() {
+			Ok(serial) => Some(serial.to_owned()),
+			Err(_) => return invalid("serial is not UTF-8"),
+		},
+	};
+	guard(|| {
+		let res = match &serial {
+			Some(serial) => ViveDevice::open(serial),
+			None => ViveDevice::open_first(),
+		};
+		match res {
+			Ok(device) => {
+				*out = Box::into_raw(Box::new(VivePro2Device(device)));
+				VivePro2Status::Ok
+			}
+			Err(e) => status(e),
+		}
+	})
+}
+
+/// # Safety
+/// `device` is null, or a handle from [`vivepro2_open`], which is not used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn vivepro2_close(device: *mut VivePro2Device) {
+	if !device.is_null() {
+		drop(Box::from_raw(device));
+	}
+}
+
+/// Factory config as JSON, released with [`vivepro2_free_string`]. Reading takes a few seconds
+///
+/// # Safety
+/// `device` is a valid handle, `out` is valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn vivepro2_read_config(
+	device: *mut VivePro2Device,
+	out: *mut *mut c_char,
+) -> VivePro2Status {
+	if out.is_null() {
+		return invalid("out is null");
+	}
+	with_device(device, |device| {
+		let config = match device.read_config() {
+			Ok(config) => config,
+			Err(e) => return status(e),
+		};
+		let json = match serde_json::to_string(&config).map(CString::new) {
+			Ok(Ok(json)) => json,
+			_ => {
+				set_error("failed to serialize config".to_owned());
+				return VivePro2Status::Other;
+			}
+		};
+		*out = json.into_raw();
+		VivePro2Status::Ok
+	})
+}
+
+/// # Safety
+/// `s` is null, or a string returned by this library, which is not used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn vivepro2_free_string(s: *mut c_char) {
+	if !s.is_null() {
+		drop(CString::from_raw(s));
+	}
+}
+
+/// Number of modes, at least one
+///
+/// # Safety
+/// `device` is a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn vivepro2_mode_count(device: *mut VivePro2Device) -> usize {
+	device.as_ref().map_or(0, |d| d.0.query_modes().len())
+}
+
+/// # Safety
+/// `device` is a valid handle, `out` is valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn vivepro2_get_mode(
+	device: *mut VivePro2Device,
+	index: usize,
+	out: *mut VivePro2Mode,
+) -> VivePro2Status {
+	if out.is_null() {
+		return invalid("out is null");
+	}
+	with_device(device, |device| {
+		let Some(mode) = device.query_modes().get(index).copied() else {
+			return invalid("mode index is out of range");
+		};
+		*out = VivePro2Mode {
+			id: mode.id,
+			width: mode.width,
+			height: mode.height,
+			frame_rate: mode.frame_rate,
+			extra_photon_vsync: mode.extra_photon_vsync,
+		};
+		VivePro2Status::Ok
+	})
+}
+
+/// Headset disconnects to apply the mode, close the handle, and open it again with
+/// [`vivepro2_wait_reconnect`]
+///
+/// # Safety
+/// `device` is a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn vivepro2_set_mode(
+	device: *mut VivePro2Device,
+	mode: u8,
+) -> VivePro2Status {
+	with_device(device, |device| result(device.set_mode(mode)))
+}
+
+/// Opens the first headset, once it is back after mode switch
+///
+/// # Safety
+/// `out` is valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn vivepro2_wait_reconnect(
+	timeout_ms: u32,
+	out: *mut *mut VivePro2Device,
+) -> VivePro2Status {
+	if out.is_null() {
+		return invalid("out is null");
+	}
+	guard(
+		|| match ViveDevice::wait_reconnect(Duration::from_millis(timeout_ms.into())) {
+			Ok(device) => {
+				*out = Box::into_raw(Box::new(VivePro2Device(device)));
+				VivePro2Status::Ok
+			}
+			Err(e) => status(e),
+		},
+	)
+}
+
+/// # Safety
+/// `device` is a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn vivepro2_set_brightness(
+	device: *mut VivePro2Device,
+	brightness: u8,
+) -> VivePro2Status {
+	with_device(device, |device| result(device.set_brightness(brightness)))
+}
+
+/// # Safety
+/// `device` is a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn vivepro2_set_noise_cancel(
+	device: *mut VivePro2Device,
+	enabled: bool,
+) -> VivePro2Status {
+	with_device(device, |device| {
+		result(device.toggle_noise_canceling(enabled))
+	})
+}
+
+/// Waits up to `timeout_ms` for the next status report, [`VivePro2Status::NoEvent`] if there is
+/// none
+///
+/// # Safety
+/// `device` is a valid handle, `out` is valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn vivepro2_poll_status(
+	device: *mut VivePro2Device,
+	timeout_ms: i32,
+	out: *mut VivePro2HeadsetStatus,
+) -> VivePro2Status {
+	if out.is_null() {
+		return invalid("out is null");
+	}
+	with_device(device, |device| match device.read_status(timeout_ms) {
+		Ok(Some(s)) => {
+			*out = VivePro2HeadsetStatus {
+				ipd: s.ipd,
+				ipd_meters: s.ipd_meters(),
+				lens_separation: s.lens_separation,
+				button: s.button,
+				proximity: s.proximity,
+				proximity_change: s.proximity_change,
+			};
+			VivePro2Status::Ok
+		}
+		Ok(None) => VivePro2Status::NoEvent,
+		Err(e) => status(e),
+	})
+}
@@ -0,0 +1,269 @@
+//! Everything Monado needs for a Pro 2 HMD, in the form of its `xrt_hmd_parts`: screen and view
+//! sizes, frame interval, per-view fov, and `compute_distortion` callback. Raw config is there
+//! too, for `vive_config_parse` of its vive driver, which takes the rest of it (lens separation,
+//! IMU calibration) from there. Header is `include/vivepro2_monado.h`
+//!
+//! Distortion comes from lens server (started the same way the SteamVR driver starts it, and
+//! cached the same way), or from built-in approximation, sampled on a grid once, on open
+use std::{
+	ffi::{c_char, CString},
+	rc::Rc,
+};
+
+use lens_client::{start_lens_server, CacheKey, CachedClient};
+use lens_geometry::{DistortionMesh, FallbackLens};
+use lens_protocol::LensClient;
+use vive_hid::{Mode, ViveDevice};
+use vive_protocol::parse_vive_config_json;
+
+use crate::{guard, invalid, set_error, status, VivePro2Device, VivePro2Status};
+
+/// Same density SteamVR driver asks for
+const GRID: usize = 64;
+const STATUS_TIMEOUT_MS: i32 = 500;
+
+/// `xrt_fov`, radians, left and down are negative
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VivePro2Fov {
+	pub angle_left: f32,
+	pub angle_right: f32,
+	pub angle_up: f32,
+	pub angle_down: f32,
+}
+
+/// `xrt_vec2`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VivePro2Vec2 {
+	pub x: f32,
+	pub y: f32,
+}
+
+/// `xrt_uv_triplet`, where each channel is sampled in the rendered view, 0,0 is top-left
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VivePro2UvTriplet {
+	pub r: VivePro2Vec2,
+	pub g: VivePro2Vec2,
+	pub b: VivePro2Vec2,
+}
+
+/// `xrt_hmd_parts::screens[0]`, and view size, both views are side by side
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VivePro2MonadoDisplay {
+	pub w_pixels: u32,
+	pub h_pixels: u32,
+	pub nominal_frame_interval_ns: u64,
+	/// From vsync to the moment image is lit, for prediction
+	pub vsync_to_photons_ns: u64,
+	pub view_w_pixels: u32,
+	pub view_h_pixels: u32,
+}
+impl VivePro2MonadoDisplay {
+	fn new(mode: &Mode) -> Self {
+		let interval = 1.0 / mode.frame_rate;
+		Self {
+			w_pixels: mode.width,
+			h_pixels: mode.height,
+			nominal_frame_interval_ns: (interval as f64 * 1e9) as u64,
+			vsync_to_photons_ns: ((interval + mode.extra_photon_vsync) as f64 * 1e9) as u64,
+			view_w_pixels: mode.width / 2,
+			view_h_pixels: mode.height,
+		}
+	}
+}
+
+/// Created with [`vivepro2_monado_open`], released with [`vivepro2_monado_close`]
+pub struct VivePro2MonadoHmd {
+	display: VivePro2MonadoDisplay,
+	meshes: [DistortionMesh; 2],
+	config: CString,
+}
+
+fn lens(correction: &serde_json::Value, mode: u8, ipd: Option<f32>) -> Rc<dyn LensClient> {
+	let fallback = {
+		let correction = correction.clone();
+		move |e| {
+			eprintln!("vivepro2: lens server start failed, using built-in distortion: {e}");
+			Rc::new(FallbackLens::new(&correction)) as Rc<dyn LensClient>
+		}
+	};
+	let start = {
+		let correction = correction.clone();
+		move || Ok(Rc::new(start_lens_server(correction)?) as Rc<dyn LensClient>)
+	};
+	Rc::new(CachedClient::new(
+		CacheKey::new(correction, mode, ipd),
+		start,
+		fallback,
+	))
+}
+
+unsafe fn hmd<'h>(hmd: *const VivePro2MonadoHmd) -> Option<&'h VivePro2MonadoHmd> {
+	hmd.as_ref()
+}
+
+/// Reads config, and computes distortion with lens server, or approximation, if `approximate` is
+/// set. Takes a few seconds, more if lens server is not cached yet. `mode` is the one headset is
+/// in, as set by [`crate::vivepro2_set_mode`], or by SteamVR driver
+///
+/// # Safety
+/// `device` is a valid handle, `out` is valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn vivepro2_monado_open(
+	device: *mut VivePro2Device,
+	mode: u8,
+	approximate: bool,
+	out: *mut *mut VivePro2MonadoHmd,
+) -> VivePro2Status {
+	if out.is_null() {
+		return invalid("out is null");
+	}
+	let Some(device) = device.as_ref() else {
+		return invalid("device is null");
+	};
+	let device: &ViveDevice = &device.0;
+	let Some(mode) = device.query_modes().into_iter().find(|m| m.id == mode) else {
+		return invalid("unknown mode");
+	};
+	guard(|| {
+		let raw = match device.read_config_raw() {
+			Ok(raw) => raw,
+			Err(e) => return status(e),
+		};
+		let Some(json) = raw.json else {
+			set_error("config is not UTF-8".to_owned());
+			return VivePro2Status::Other;
+		};
+		let config = match parse_vive_config_json(&json) {
+			Ok(config) => config,
+			Err(e) => {
+				set_error(format!("failed to parse config: {e}"));
+				return VivePro2Status::Other;
+			}
+		};
+		let correction = &config.inhouse_lens_correction;
+		let lens = if approximate {
+			Rc::new(FallbackLens::new(correction)) as Rc<dyn LensClient>
+		} else {
+			let ipd = match device.read_status(STATUS_TIMEOUT_MS) {
+				Ok(status) => status.map(|s| s.ipd_meters()),
+				Err(e) => return status(e),
+			};
+			lens(correction, mode.id, ipd)
+		};
+		let meshes = DistortionMesh::compute_both(&*lens, GRID);
+		let _ = lens.exit();
+		let meshes = match meshes {
+			Ok(meshes) => meshes,
+			Err(e) => {
+				set_error(format!("failed to compute distortion: {e}"));
+				return VivePro2Status::Other;
+			}
+		};
+		let Ok(config) = CString::new(json) else {
+			set_error("config has NUL".to_owned());
+			return VivePro2Status::Other;
+		};
+		*out = Box::into_raw(Box::new(VivePro2MonadoHmd {
+			display: VivePro2MonadoDisplay::new(&mode),
+			meshes,
+			config,
+		}));
+		VivePro2Status::Ok
+	})
+}
+
+/// # Safety
+/// `hmd` is null, or a handle from [`vivepro2_monado_open`], which is not used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn vivepro2_monado_close(hmd: *mut VivePro2MonadoHmd) {
+	if !hmd.is_null() {
+		drop(Box::from_raw(hmd));
+	}
+}
+
+/// # Safety
+/// `hmd` is a valid handle, `out` is valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn vivepro2_monado_display(
+	hmd: *const VivePro2MonadoHmd,
+	out: *mut VivePro2MonadoDisplay,
+) -> VivePro2Status {
+	match (self::hmd(hmd), out.is_null()) {
+		(Some(hmd), false) => {
+			*out = hmd.display;
+			VivePro2Status::Ok
+		}
+		_ => invalid("hmd or out is null"),
+	}
+}
+
+/// Raw factory config JSON, as `vive_config_parse` expects it. Owned by `hmd`
+///
+/// # Safety
+/// `hmd` is a valid handle
+#[no_mangle]
+pub unsafe extern "C" fn vivepro2_monado_config_json(
+	hmd: *const VivePro2MonadoHmd,
+) -> *const c_char {
+	self::hmd(hmd).map_or(std::ptr::null(), |hmd| hmd.config.as_ptr())
+}
+
+/// `view` is 0 for left eye, 1 for right
+///
+/// # Safety
+/// `hmd` is a valid handle, `out` is valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn vivepro2_monado_fov(
+	hmd: *const VivePro2MonadoHmd,
+	view: u32,
+	out: *mut VivePro2Fov,
+) -> VivePro2Status {
+	let (Some(hmd), false) = (self::hmd(hmd), out.is_null()) else {
+		return invalid("hmd or out is null");
+	};
+	let Some(mesh) = hmd.meshes.get(view as usize) else {
+		return invalid("view is out of range");
+	};
+	// Tangents are as SteamVR takes them, with y pointing down
+	let p = &mesh.projection;
+	*out = VivePro2Fov {
+		angle_left: p.left.atan(),
+		angle_right: p.right.atan(),
+		angle_up: (-p.top).atan(),
+		angle_down: (-p.bottom).atan(),
+	};
+	VivePro2Status::Ok
+}
+
+/// Same contract as `xrt_device::compute_distortion`, `u` and `v` are in view space, 0,0 is
+/// top-left. Interpolated from the grid, so it is cheap enough to be called per vertex
+///
+/// # Safety
+/// `hmd` is a valid handle, `out` is valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn vivepro2_monado_compute_distortion(
+	hmd: *const VivePro2MonadoHmd,
+	view: u32,
+	u: f32,
+	v: f32,
+	out: *mut VivePro2UvTriplet,
+) -> bool {
+	let (Some(hmd), false) = (self::hmd(hmd), out.is_null()) else {
+		return false;
+	};
+	let Some(mesh) = hmd.meshes.get(view as usize) else {
+		return false;
+	};
+	let s = mesh.sample([u, v]);
+	let vec = |[x, y]: [f32; 2]| VivePro2Vec2 { x, y };
+	*out = VivePro2UvTriplet {
+		r: vec(s.red),
+		g: vec(s.green),
+		b: vec(s.blue),
+	};
+	true
+}
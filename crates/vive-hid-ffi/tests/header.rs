@@ -0,0 +1,218 @@
+//! cbindgen isn't run as part of the build, so headers are checked here against declarations of
+//! the sources they come from, rendered the way cbindgen.toml has it. Comments and line wrapping
+//! of the header don't matter, everything else does
+use std::collections::BTreeSet;
+
+/// `VivePro2Status`/`NoEvent` -> `VIVE_PRO2_STATUS`/`NO_EVENT`
+fn screaming_snake(name: &str) -> String {
+	let mut out = String::new();
+	let mut prev = None::<char>;
+	for c in name.chars() {
+		if c.is_ascii_uppercase()
+			&& prev.is_some_and(|p| p.is_ascii_lowercase() || p.is_ascii_digit())
+		{
+			out.push('_');
+		}
+		out.push(c.to_ascii_uppercase());
+		prev = Some(c);
+	}
+	out
+}
+
+fn c_type(ty: &str) -> String {
+	let ty = ty.trim();
+	if let Some(inner) = ty.strip_prefix("*const ") {
+		return format!("const {}*", pointee(inner));
+	}
+	if let Some(inner) = ty.strip_prefix("*mut ") {
+		return format!("{}*", pointee(inner));
+	}
+	match ty {
+		"u8" => "uint8_t",
+		"u16" => "uint16_t",
+		"u32" => "uint32_t",
+		"u64" => "uint64_t",
+		"i32" => "int32_t",
+		"f32" => "float",
+		"bool" => "bool",
+		"usize" => "size_t",
+		"c_char" => "char",
+		other => other,
+	}
+	.to_owned()
+}
+/// Nested pointers go without space, `VivePro2Device **out`
+fn pointee(ty: &str) -> String {
+	let ty = c_type(ty);
+	match ty.ends_with('*') {
+		true => ty,
+		false => format!("{ty} "),
+	}
+}
+
+fn declaration(ty: &str, name: &str) -> String {
+	let ty = c_type(ty);
+	match ty.ends_with('*') {
+		true => format!("{ty}{name}"),
+		false => format!("{ty} {name}"),
+	}
+}
+
+/// Rust sources are formatted with rustfmt, so items are recognized by their lines
+fn expected(source: &str) -> BTreeSet<String> {
+	let mut items = BTreeSet::new();
+	let mut lines = source.lines().map(str::trim);
+	let mut repr_c = false;
+	while let Some(line) = lines.next() {
+		if line == "#[repr(C)]" {
+			repr_c = true;
+			continue;
+		}
+		if line.starts_with("#[") || line.starts_with("///") {
+			continue;
+		}
+		let body = |lines: &mut dyn Iterator<Item = &str>| {
+			lines
+				.take_while(|l| *l != "}")
+				.filter(|l| !l.starts_with("///"))
+				.map(|l| l.trim_end_matches(',').to_owned())
+				.collect::<Vec<_>>()
+		};
+		if let Some(name) = line
+			.strip_prefix("pub enum ")
+			.and_then(|l| l.strip_suffix(" {"))
+		{
+			assert!(repr_c, "{name} is not repr(C)");
+			let prefix = screaming_snake(name);
+			let variants = body(&mut lines)
+				.iter()
+				.map(|v| {
+					let (variant, value) = v.split_once(" = ").expect("explicit discriminant");
+					format!("{prefix}_{} = {value},", screaming_snake(variant))
+				})
+				.collect::<Vec<_>>()
+				.join(" ");
+			items.insert(format!("typedef enum {name} {{ {variants} }} {name};"));
+		} else if let Some(rest) = line.strip_prefix("pub struct ") {
+			let name = rest.split(['(', ' ', ';']).next().unwrap();
+			if !repr_c {
+				if rest.ends_with('{') {
+					body(&mut lines);
+				}
+				items.insert(format!("typedef struct {name} {name};"));
+			} else {
+				let fields = body(&mut lines)
+					.iter()
+					.map(|f| {
+						let (field, ty) = f
+							.strip_prefix("pub ")
+							.and_then(|f| f.split_once(": "))
+							.expect("public field");
+						format!("{};", declaration(ty, field))
+					})
+					.collect::<Vec<_>>()
+					.join(" ");
+				items.insert(format!("typedef struct {name} {{ {fields} }} {name};"));
+			}
+		} else if let Some(rest) = line
+			.strip_prefix("pub unsafe extern \"C\" fn ")
+			.or_else(|| line.strip_prefix("pub extern \"C\" fn "))
+		{
+			let mut signature = rest.to_owned();
+			while !signature.ends_with('{') {
+				signature.push_str(lines.next().expect("function body"));
+			}
+			let (name, rest) = signature.split_once('(').unwrap();
+			let (params, rest) = rest.split_once(')').unwrap();
+			let ret = rest
+				.trim_end_matches('{')
+				.trim()
+				.strip_prefix("-> ")
+				.map_or("void".to_owned(), c_type);
+			let params = params
+				.split(',')
+				.filter(|p| !p.trim().is_empty())
+				.map(|p| {
+					let (name, ty) = p.split_once(':').unwrap();
+					declaration(ty, name.trim())
+				})
+				.collect::<Vec<_>>();
+			let params = match params.is_empty() {
+				true => "void".to_owned(),
+				false => params.join(", "),
+			};
+			let ret = match ret.ends_with('*') {
+				true => ret,
+				false => format!("{ret} "),
+			};
+			items.insert(format!("{ret}{name}({params});"));
+		}
+		repr_c = false;
+	}
+	items
+}
+
+/// Header without comments, preprocessor lines and `extern "C"` block, whitespace collapsed
+fn header(text: &str) -> String {
+	let mut out = String::new();
+	let mut rest = text;
+	while let Some(start) = rest.find("/*") {
+		out.push_str(&rest[..start]);
+		let end = rest[start..].find("*/").expect("closed comment");
+		rest = &rest[start + end + 2..];
+	}
+	out.push_str(rest);
+	out.lines()
+		.map(|l| l.split("//").next().unwrap().trim())
+		.filter(|l| !l.starts_with('#') && *l != "extern \"C\" {" && *l != "}")
+		.collect::<Vec<_>>()
+		.join(" ")
+		.split_whitespace()
+		.collect::<Vec<_>>()
+		.join(" ")
+		// Wrapped parameter lists
+		.replace("( ", "(")
+}
+
+fn check(header_text: &str, source: &str) {
+	let mut header = header(header_text);
+	let mut missing = Vec::new();
+	for item in expected(source) {
+		match header.find(&item) {
+			Some(at) => header.replace_range(at..at + item.len(), ""),
+			None => missing.push(item),
+		}
+	}
+	let extra = header.split_whitespace().collect::<Vec<_>>().join(" ");
+	assert!(
+		missing.is_empty() && extra.is_empty(),
+		"header is out of date\nmissing: {missing:#?}\nnot in sources: {extra}"
+	);
+}
+
+#[test]
+fn vivepro2_h() {
+	check(
+		include_str!("../include/vivepro2.h"),
+		include_str!("../src/lib.rs"),
+	);
+}
+
+#[test]
+fn vivepro2_monado_h() {
+	check(
+		include_str!("../include/vivepro2_monado.h"),
+		include_str!("../src/monado.rs"),
+	);
+}
+
+#[test]
+fn renders_like_cbindgen() {
+	assert_eq!(screaming_snake("VivePro2Status"), "VIVE_PRO2_STATUS");
+	assert_eq!(
+		declaration("*mut *mut VivePro2Device", "out"),
+		"VivePro2Device **out"
+	);
+	assert_eq!(declaration("*const c_char", "serial"), "const char *serial");
+	assert_eq!(declaration("u8", "mode"), "uint8_t mode");
+}
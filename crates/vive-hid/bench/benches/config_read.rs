@@ -0,0 +1,85 @@
+//! Device open and config read, against recorded traces replayed through `MockTransport`, so
+//! numbers measure protocol code only, not USB latency
+//!
+//! Run with `cargo bench` in this directory, criterion keeps previous run in `target/criterion`,
+//! and reports the change against it
+use std::{fs, path::Path};
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use lens_geometry::{FallbackLens, LensIntrinsics};
+use lens_protocol::Eye;
+use vive_hid::{replay::Trace, SteamDevice, ViveDevice};
+
+fn trace(name: &str) -> Trace {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("../tests/traces")
+		.join(name);
+	Trace::load(&path).unwrap_or_else(|e| panic!("failed to load {path:?}: {e}"))
+}
+
+fn fixture(name: &str) -> Vec<u8> {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("../../vive-protocol/tests/fixtures")
+		.join(name);
+	fs::read(&path).unwrap_or_else(|e| panic!("failed to read {path:?}: {e}"))
+}
+
+fn config_read(c: &mut Criterion) {
+	let vive = trace("vive-read_config.json");
+	let steam = trace("steam-read_config.json");
+	let mut group = c.benchmark_group("read");
+	// Mock is filled in setup, outside of measurement
+	group.bench_function("vive_open_read_config", |b| {
+		b.iter_batched(
+			|| vive.mock("vive"),
+			|mock| {
+				let dev = ViveDevice::with_transport(mock);
+				black_box(dev.read_config().expect("config"))
+			},
+			BatchSize::SmallInput,
+		)
+	});
+	group.bench_function("steam_open_read_config", |b| {
+		b.iter_batched(
+			|| steam.mock("steam"),
+			|mock| {
+				let dev = SteamDevice::with_transport(mock);
+				black_box(dev.read_config().expect("config"))
+			},
+			BatchSize::SmallInput,
+		)
+	});
+	group.finish();
+}
+
+fn config_parse(c: &mut Criterion) {
+	let vive = fixture("vive-v1-padded.bin");
+	let steam = fixture("steam-v1-tracking.bin");
+	let mut group = c.benchmark_group("parse");
+	group.bench_function("vive_config", |b| {
+		b.iter(|| vive_protocol::parse_vive_config(black_box(&vive)).expect("config"))
+	});
+	group.bench_function("steam_config", |b| {
+		b.iter(|| vive_protocol::parse_steam_config(black_box(&steam)).expect("config"))
+	});
+
+	let lens = vive_protocol::parse_vive_config(&vive)
+		.expect("config")
+		.inhouse_lens_correction;
+	group.bench_function("lens_intrinsics", |b| {
+		b.iter(|| {
+			let lens = black_box(&lens);
+			(
+				LensIntrinsics::from_config(lens, Eye::Left).expect("left"),
+				LensIntrinsics::from_config(lens, Eye::Right).expect("right"),
+			)
+		})
+	});
+	group.bench_function("fallback_lens", |b| {
+		b.iter(|| FallbackLens::new(black_box(&lens)))
+	});
+	group.finish();
+}
+
+criterion_group!(benches, config_read, config_parse);
+criterion_main!(benches);
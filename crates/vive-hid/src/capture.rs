@@ -0,0 +1,355 @@
+//! Recording of every exchanged HID report, for protocol reverse-engineering and bug reports
+//!
+//! Enabled with `VIVEPRO2_HID_CAPTURE=<path>` before the first device is opened, or with
+//! [`start`]. Files ending with `.json` get one JSON object per line, everything else is
+//! written as pcap-ng, which opens in Wireshark (reports are raw `USER0` packets, with direction
+//! in packet flags and report kind in packet comment)
+//!
+//! Independently of that, every handle keeps its last [`RECENT_REPORTS`] reports in memory, for
+//! diagnostics of failures nobody was capturing
+use std::{
+	collections::VecDeque,
+	env,
+	ffi::CString,
+	fs::File,
+	io::{self, Write},
+	path::Path,
+	sync::{Mutex, Once},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hidapi::{HidError, HidResult};
+use tracing::{info, warn};
+
+use crate::transport::{HidTransport, InputEvents};
+
+pub const CAPTURE_ENV: &str = "VIVEPRO2_HID_CAPTURE";
+/// Kept by every handle, config read is the longest operation, and takes less than that
+pub const RECENT_REPORTS: usize = 256;
+
+#[derive(Clone, Copy, Debug)]
+enum Kind {
+	Output,
+	Input,
+	SetFeature,
+	GetFeature,
+}
+impl Kind {
+	fn name(self) -> &'static str {
+		match self {
+			Self::Output => "output",
+			Self::Input => "input",
+			Self::SetFeature => "set_feature",
+			Self::GetFeature => "get_feature",
+		}
+	}
+	fn inbound(self) -> bool {
+		matches!(self, Self::Input | Self::GetFeature)
+	}
+}
+
+const BLOCK_SECTION_HEADER: u32 = 0x0a0d0d0a;
+const BLOCK_INTERFACE: u32 = 1;
+const BLOCK_ENHANCED_PACKET: u32 = 6;
+const LINKTYPE_USER0: u16 = 147;
+const OPT_END: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const OPT_IF_NAME: u16 = 2;
+const OPT_EPB_FLAGS: u16 = 2;
+const EPB_INBOUND: u32 = 1;
+const EPB_OUTBOUND: u32 = 2;
+
+fn push_option(out: &mut Vec<u8>, code: u16, value: &[u8]) {
+	out.extend_from_slice(&code.to_le_bytes());
+	out.extend_from_slice(&(value.len() as u16).to_le_bytes());
+	out.extend_from_slice(value);
+	pad(out);
+}
+fn pad(out: &mut Vec<u8>) {
+	while out.len() & 3 != 0 {
+		out.push(0);
+	}
+}
+fn now() -> Duration {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or_default()
+}
+
+/// Line of JSON capture, failed reports also have `error`, and are skipped by replay
+fn json_record(
+	time: Duration,
+	device: &str,
+	kind: Kind,
+	data: &[u8],
+	error: Option<&str>,
+) -> String {
+	let hex = data.iter().map(|b| format!("{b:02x}")).collect::<String>();
+	let error = error
+		.map(|e| format!(",\"error\":{}", serde_json::Value::from(e)))
+		.unwrap_or_default();
+	format!(
+		"{{\"time\":{:.6},\"device\":\"{device}\",\"kind\":\"{}\",\"direction\":\"{}\",\"data\":\"{hex}\"{error}}}\n",
+		time.as_secs_f64(),
+		kind.name(),
+		if kind.inbound() { "in" } else { "out" },
+	)
+}
+
+/// Wraps body with type and both length fields
+fn block(kind: u32, body: &[u8]) -> Vec<u8> {
+	let len = (body.len() + 12) as u32;
+	let mut out = Vec::with_capacity(len as usize);
+	out.extend_from_slice(&kind.to_le_bytes());
+	out.extend_from_slice(&len.to_le_bytes());
+	out.extend_from_slice(body);
+	out.extend_from_slice(&len.to_le_bytes());
+	out
+}
+
+enum Format {
+	Json,
+	/// Interface per device, in order of appearance
+	Pcapng {
+		interfaces: Vec<&'static str>,
+	},
+}
+
+struct Capture {
+	file: File,
+	format: Format,
+}
+impl Capture {
+	fn create(path: &Path) -> io::Result<Self> {
+		let mut file = File::create(path)?;
+		let format = if path.extension().and_then(|e| e.to_str()) == Some("json") {
+			Format::Json
+		} else {
+			let mut body = Vec::new();
+			body.extend_from_slice(&0x1a2b3c4du32.to_le_bytes());
+			body.extend_from_slice(&1u16.to_le_bytes());
+			body.extend_from_slice(&0u16.to_le_bytes());
+			// Unknown section length
+			body.extend_from_slice(&(-1i64).to_le_bytes());
+			file.write_all(&block(BLOCK_SECTION_HEADER, &body))?;
+			Format::Pcapng {
+				interfaces: Vec::new(),
+			}
+		};
+		Ok(Self { file, format })
+	}
+	fn record(&mut self, device: &'static str, kind: Kind, data: &[u8]) -> io::Result<()> {
+		let time = now();
+		// Every record is written at once, so capture survives crash of the process
+		let out = match &mut self.format {
+			Format::Json => json_record(time, device, kind, data, None).into_bytes(),
+			Format::Pcapng { interfaces } => {
+				let mut out = Vec::new();
+				let interface = match interfaces.iter().position(|i| *i == device) {
+					Some(i) => i,
+					None => {
+						let mut body = Vec::new();
+						body.extend_from_slice(&LINKTYPE_USER0.to_le_bytes());
+						body.extend_from_slice(&0u16.to_le_bytes());
+						// No snapshot length limit
+						body.extend_from_slice(&0u32.to_le_bytes());
+						push_option(&mut body, OPT_IF_NAME, device.as_bytes());
+						push_option(&mut body, OPT_END, &[]);
+						out.extend(block(BLOCK_INTERFACE, &body));
+						interfaces.push(device);
+						interfaces.len() - 1
+					}
+				};
+				// Default interface timestamp resolution is microseconds
+				let micros = time.as_micros() as u64;
+				let mut body = Vec::new();
+				body.extend_from_slice(&(interface as u32).to_le_bytes());
+				body.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+				body.extend_from_slice(&(micros as u32).to_le_bytes());
+				body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+				body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+				body.extend_from_slice(data);
+				pad(&mut body);
+				let flags = if kind.inbound() {
+					EPB_INBOUND
+				} else {
+					EPB_OUTBOUND
+				};
+				push_option(&mut body, OPT_EPB_FLAGS, &flags.to_le_bytes());
+				push_option(&mut body, OPT_COMMENT, kind.name().as_bytes());
+				push_option(&mut body, OPT_END, &[]);
+				out.extend(block(BLOCK_ENHANCED_PACKET, &body));
+				out
+			}
+		};
+		self.file.write_all(&out)
+	}
+}
+
+static CAPTURE: Mutex<Option<Capture>> = Mutex::new(None);
+static FROM_ENV: Once = Once::new();
+
+/// Starts recording to `path`, replacing previous capture
+pub fn start(path: &Path) -> io::Result<()> {
+	let capture = Capture::create(path)?;
+	info!("capturing hid traffic to {path:?}");
+	*CAPTURE.lock().expect("lock") = Some(capture);
+	Ok(())
+}
+
+/// Stops recording, file is complete after every report, so there is nothing to finalize
+pub fn stop() {
+	CAPTURE.lock().expect("lock").take();
+}
+
+pub fn is_active() -> bool {
+	CAPTURE.lock().expect("lock").is_some()
+}
+
+fn start_from_env() {
+	FROM_ENV.call_once(|| {
+		let Some(path) = env::var_os(CAPTURE_ENV) else {
+			return;
+		};
+		if let Err(e) = start(Path::new(&path)) {
+			warn!("failed to start hid capture: {e}");
+		}
+	});
+}
+
+/// Report, which has passed through device, also logged with `trace-hid`, which doesn't need to
+/// be set up in advance, unlike capture
+fn observe(device: &'static str, kind: Kind, data: &[u8]) {
+	trace_hid!(
+		device,
+		kind = kind.name(),
+		id = data.first().copied(),
+		size = data.len(),
+		"report"
+	);
+	record(device, kind, data);
+}
+
+fn record(device: &'static str, kind: Kind, data: &[u8]) {
+	let mut capture = CAPTURE.lock().expect("lock");
+	let Some(active) = &mut *capture else {
+		return;
+	};
+	if let Err(e) = active.record(device, kind, data) {
+		warn!("hid capture stopped: {e}");
+		*capture = None;
+	}
+}
+
+struct Recent {
+	time: Duration,
+	kind: Kind,
+	data: Vec<u8>,
+	error: Option<String>,
+}
+
+/// HID handle, which records everything passing through it
+pub(crate) struct Device<T> {
+	dev: T,
+	/// Name of capture interface
+	name: &'static str,
+	recent: Mutex<VecDeque<Recent>>,
+}
+impl<T: HidTransport> Device<T> {
+	pub fn new(dev: T, name: &'static str) -> Self {
+		start_from_env();
+		Self {
+			dev,
+			name,
+			recent: Mutex::new(VecDeque::with_capacity(RECENT_REPORTS)),
+		}
+	}
+	/// Status is polled continuously, so buffer of the oldest report is reused
+	fn remember(&self, kind: Kind, data: &[u8], error: Option<&HidError>) {
+		let mut recent = self.recent.lock().expect("lock");
+		let mut buf = if recent.len() >= RECENT_REPORTS {
+			recent.pop_front().map(|r| r.data).unwrap_or_default()
+		} else {
+			Vec::new()
+		};
+		buf.clear();
+		buf.extend_from_slice(data);
+		recent.push_back(Recent {
+			time: now(),
+			kind,
+			data: buf,
+			error: error.map(ToString::to_string),
+		});
+	}
+	fn done<R>(&self, kind: Kind, data: &[u8], res: HidResult<R>) -> HidResult<R> {
+		match &res {
+			Ok(_) => self.remember(kind, data, None),
+			Err(e) => {
+				trace_hid!(device = self.name, kind = kind.name(), id = data.first().copied(), error = %e, "report failed");
+				self.remember(kind, data, Some(e));
+			}
+		}
+		res
+	}
+	/// Recent reports, oldest first, as lines of JSON capture
+	pub fn dump_recent(&self) -> String {
+		self.recent
+			.lock()
+			.expect("lock")
+			.iter()
+			.map(|r| json_record(r.time, self.name, r.kind, &r.data, r.error.as_deref()))
+			.collect()
+	}
+}
+impl<T: HidTransport> HidTransport for Device<T> {
+	fn write(&self, data: &[u8]) -> HidResult<usize> {
+		observe(self.name, Kind::Output, data);
+		self.done(Kind::Output, data, self.dev.write(data))
+	}
+	fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> HidResult<usize> {
+		let size = match self.dev.read_timeout(buf, timeout_ms) {
+			Ok(size) => size,
+			Err(e) => return self.done(Kind::Input, &[], Err(e)),
+		};
+		if size != 0 {
+			observe(self.name, Kind::Input, &buf[..size]);
+			self.remember(Kind::Input, &buf[..size], None);
+		} else {
+			trace_hid!(device = self.name, timeout_ms, "read timed out");
+		}
+		Ok(size)
+	}
+	fn send_feature_report(&self, data: &[u8]) -> HidResult<()> {
+		observe(self.name, Kind::SetFeature, data);
+		self.done(Kind::SetFeature, data, self.dev.send_feature_report(data))
+	}
+	fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize> {
+		let id = buf.first().copied();
+		let size = match self.dev.get_feature_report(buf) {
+			Ok(size) => size,
+			// Report id is all there is of the request
+			Err(e) => return self.done(Kind::GetFeature, id.as_slice(), Err(e)),
+		};
+		observe(self.name, Kind::GetFeature, &buf[..size]);
+		self.remember(Kind::GetFeature, &buf[..size], None);
+		Ok(size)
+	}
+	/// Dropped reports are traced, but not captured, replay has nothing to answer with them
+	fn discard_input(&self) -> HidResult<usize> {
+		let count = self.dev.discard_input()?;
+		if count != 0 {
+			trace_hid!(device = self.name, count, "discarded pending input");
+		}
+		Ok(count)
+	}
+	fn hidraw_path(&self) -> HidResult<CString> {
+		self.dev.hidraw_path()
+	}
+	/// Reports read from it don't pass through here, so they are neither captured nor kept
+	fn input_events(&self) -> HidResult<InputEvents> {
+		self.dev.input_events()
+	}
+	fn serial_number(&self) -> HidResult<String> {
+		self.dev.serial_number()
+	}
+}
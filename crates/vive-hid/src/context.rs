@@ -0,0 +1,269 @@
+//! Owner of the hidapi instance, which devices are opened through
+//!
+//! hidapi enumerates devices once, when instance is created, and only again on
+//! [`ViveContext::refresh`], opening by serial and pairing refresh it themselves, as they
+//! go by enumerated list, first device is opened by hid_open, which always enumerates.
+//!
+//! Most callers only need [`ViveContext::global`], which `ViveDevice::open*` and
+//! `SteamDevice::open*` use, separate contexts have their own instance and their own list,
+//! and [`ViveContext::simulated`] opens simulated headset without enabling it for the whole
+//! process
+//!
+//! Every context tracks lifecycle of the headset it opens, see [`ViveContext::connection`]
+use std::{
+	ffi::{CStr, OsStr},
+	fs,
+	os::unix::ffi::OsStrExt,
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex, MutexGuard},
+};
+
+use hidapi::{DeviceInfo, HidApi, HidError};
+use once_cell::sync::OnceCell;
+
+use crate::{
+	power, retryable, sim, state::Connection, DefaultTransport, Error, Result, SteamDevice,
+	ViveDevice, STEAM_PID, STEAM_VID, VIVE_PID, VIVE_VID,
+};
+
+static GLOBAL: ViveContext = ViveContext::new();
+
+pub struct ViveContext {
+	/// Created on first use, so simulated contexts don't need hidraw at all
+	api: OnceCell<Mutex<HidApi>>,
+	connection: OnceCell<Arc<Connection>>,
+	simulated: bool,
+}
+
+impl Default for ViveContext {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl ViveContext {
+	/// Context with its own hidapi instance, separate from the global one
+	pub const fn new() -> Self {
+		Self {
+			api: OnceCell::new(),
+			connection: OnceCell::new(),
+			simulated: false,
+		}
+	}
+	/// Every device is opened as [`crate::sim`] fake, headset state is still per process, so
+	/// simulated contexts see each other's mode switches
+	pub fn simulated() -> Self {
+		sim::load();
+		Self {
+			api: OnceCell::new(),
+			connection: OnceCell::new(),
+			simulated: true,
+		}
+	}
+	/// Shared context, simulated once [`sim::enabled`]
+	pub fn global() -> &'static Self {
+		&GLOBAL
+	}
+
+	pub fn is_simulated(&self) -> bool {
+		self.simulated || sim::enabled()
+	}
+
+	/// Lifecycle of headset, as seen by handles opened through this context
+	pub fn connection(&self) -> Arc<Connection> {
+		self.connection.get_or_init(Default::default).clone()
+	}
+	/// Reports open attempt to [`ViveContext::connection`], opened handle reports the rest
+	fn track(&self, open: impl FnOnce() -> Result<ViveDevice>) -> Result<ViveDevice> {
+		let connection = self.connection();
+		let present =
+			self.is_simulated() || power::headset_hubs().is_ok_and(|hubs| !hubs.is_empty());
+		connection.opening(present);
+		match open() {
+			Ok(dev) => {
+				connection.opened();
+				Ok(dev.with_connection(connection))
+			}
+			Err(e) => {
+				connection.open_failed(&e);
+				Err(e)
+			}
+		}
+	}
+
+	fn api(&self) -> Result<MutexGuard<'_, HidApi>> {
+		let api = self.api.get_or_try_init(|| HidApi::new().map(Mutex::new))?;
+		Ok(api.lock().expect("lock"))
+	}
+
+	/// Enumerates devices again, for [`ViveContext::devices`], which otherwise lists what was
+	/// plugged in when context was first used
+	pub fn refresh(&self) -> Result<()> {
+		self.api()?.refresh_devices()?;
+		Ok(())
+	}
+	/// Every hidraw device, as of the last [`ViveContext::refresh`], simulated ones are not
+	/// listed
+	pub fn devices(&self) -> Result<Vec<DeviceInfo>> {
+		Ok(self.api()?.device_list().cloned().collect())
+	}
+
+	pub fn open_steam(&self) -> Result<SteamDevice> {
+		if self.is_simulated() {
+			return SteamDevice::simulated();
+		}
+		let mut api = self.api()?;
+		let device = api
+			.open(STEAM_VID, STEAM_PID)
+			.map_err(|e| open_error(&mut api, STEAM_VID, STEAM_PID, e))?;
+		Ok(SteamDevice::with_transport(DefaultTransport::hid(device)))
+	}
+	pub fn open_steam_serial(&self, sn: &str) -> Result<SteamDevice> {
+		if self.is_simulated() {
+			if sn != sim::STEAM_SERIAL {
+				return Err(Error::DeviceNotFound);
+			}
+			return SteamDevice::simulated();
+		}
+		let mut api = self.api()?;
+		let device = open_serial(&mut api, STEAM_VID, STEAM_PID, sn)?;
+		Ok(SteamDevice::with_transport(DefaultTransport::hid(device)))
+	}
+
+	pub fn open_vive(&self) -> Result<ViveDevice> {
+		self.track(|| {
+			if self.is_simulated() {
+				return ViveDevice::simulated();
+			}
+			let mut api = self.api()?;
+			let device = api
+				.open(VIVE_VID, VIVE_PID)
+				.map_err(|e| open_error(&mut api, VIVE_VID, VIVE_PID, e))?;
+			Ok(ViveDevice::with_transport(DefaultTransport::hid(device)))
+		})
+	}
+	pub fn open_vive_serial(&self, sn: &str) -> Result<ViveDevice> {
+		self.track(|| {
+			if self.is_simulated() {
+				if sn != sim::DEVSN {
+					return Err(Error::DeviceNotFound);
+				}
+				return ViveDevice::simulated();
+			}
+			let mut api = self.api()?;
+			let device = open_serial(&mut api, VIVE_VID, VIVE_PID, sn)?;
+			Ok(ViveDevice::with_transport(DefaultTransport::hid(device)))
+		})
+	}
+	/// See [`ViveDevice::open_paired`]
+	pub fn open_vive_paired(&self, steam_sn: &str) -> Result<ViveDevice> {
+		self.track(|| self.open_vive_paired_untracked(steam_sn))
+	}
+	fn open_vive_paired_untracked(&self, steam_sn: &str) -> Result<ViveDevice> {
+		if self.is_simulated() {
+			if steam_sn != sim::STEAM_SERIAL {
+				return Err(Error::DeviceNotFound);
+			}
+			return ViveDevice::simulated();
+		}
+		let mut api = self.api()?;
+		// Headset may be just reconnected
+		api.refresh_devices()?;
+		let steam = api
+			.device_list()
+			.find(|dev| {
+				dev.vendor_id() == STEAM_VID
+					&& dev.product_id() == STEAM_PID
+					&& dev.serial_number() == Some(steam_sn)
+			})
+			.ok_or(Error::DeviceNotFound)?;
+		let steam_path = sysfs_device(steam.path());
+		let mut best = None;
+		let mut best_shared = 0;
+		for dev in api
+			.device_list()
+			.filter(|dev| dev.vendor_id() == VIVE_VID && dev.product_id() == VIVE_PID)
+		{
+			let shared = steam_path
+				.as_ref()
+				.zip(sysfs_device(dev.path()))
+				.map(|(a, b)| {
+					a.components()
+						.zip(b.components())
+						.take_while(|(a, b)| a == b)
+						.count()
+				})
+				.unwrap_or(0);
+			if best.is_none() || shared > best_shared {
+				best = Some(dev);
+				best_shared = shared;
+			}
+		}
+		let open = best.ok_or(Error::DeviceNotFound)?.open_device(&api)?;
+		Ok(ViveDevice::with_transport(DefaultTransport::hid(open)))
+	}
+}
+
+fn open_serial(api: &mut HidApi, vid: u16, pid: u16, sn: &str) -> Result<hidapi::HidDevice> {
+	api.refresh_devices()?;
+	let device = api
+		.device_list()
+		.find(|dev| dev.serial_number() == Some(sn))
+		.ok_or(Error::DeviceNotFound)?;
+	if device.vendor_id() != vid || device.product_id() != pid {
+		return Err(Error::NotAVive);
+	}
+	api.open_serial(vid, pid, sn)
+		.map_err(|e| open_error(api, vid, pid, e))
+}
+
+/// Sysfs device behind hidraw node, its ancestors are the usb hubs and ports it is attached to
+fn sysfs_device(path: &CStr) -> Option<PathBuf> {
+	let node = Path::new(OsStr::from_bytes(path.to_bytes())).file_name()?;
+	fs::canonicalize(Path::new("/sys/class/hidraw").join(node).join("device")).ok()
+}
+
+/// SteamVR server process, which has hidraw node open, driver-proxy runs inside of it.
+/// Only processes of the same user can be inspected, which SteamVR is
+fn steamvr_holder(node: &CStr) -> Option<u32> {
+	let node = Path::new(OsStr::from_bytes(node.to_bytes()));
+	let own = std::process::id();
+	fs::read_dir("/proc")
+		.ok()?
+		.flatten()
+		.filter_map(|e| e.file_name().to_str()?.parse::<u32>().ok())
+		.filter(|&pid| pid != own)
+		.filter(|pid| {
+			fs::read_to_string(format!("/proc/{pid}/comm"))
+				.is_ok_and(|c| c.trim_end() == "vrserver")
+		})
+		.find(|pid| {
+			fs::read_dir(format!("/proc/{pid}/fd")).is_ok_and(|mut fds| {
+				fds.any(|fd| {
+					fd.and_then(|fd| fs::read_link(fd.path()))
+						.is_ok_and(|l| l == node)
+				})
+			})
+		})
+}
+
+/// Open failure, with bare hidapi error replaced if SteamVR has the device, so callers can go
+/// through driver-proxy, or at least tell what is wrong
+fn open_error(api: &mut HidApi, vid: u16, pid: u16, e: HidError) -> Error {
+	// Listed nodes may be from before the device was reconnected
+	let _ = api.refresh_devices();
+	let held = api
+		.device_list()
+		.filter(|dev| dev.vendor_id() == vid && dev.product_id() == pid)
+		.find_map(|dev| steamvr_holder(dev.path()));
+	// hid_open has no errno when nothing matches, only this
+	let not_found = matches!(
+		&e,
+		HidError::HidApiError { message } if message.contains("requested VID/PID")
+	);
+	match held {
+		Some(pid) => Error::HeldBySteamVr { pid },
+		None if not_found => Error::DeviceNotFound,
+		None => retryable(e).map_or_else(|e| e, Error::Hid),
+	}
+}
@@ -1,11 +1,66 @@
-use std::{io::Read, result};
+use std::{
+	collections::BTreeMap,
+	ffi::OsStr,
+	fmt, fs, io,
+	os::{
+		fd::{AsFd, AsRawFd, BorrowedFd, RawFd},
+		unix::ffi::OsStrExt,
+	},
+	path::{Path, PathBuf},
+	result,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+	thread,
+	time::{Duration, Instant},
+};
 
-use flate2::read::ZlibDecoder;
-use hidapi::{HidApi, HidDevice, HidError};
-use once_cell::sync::OnceCell;
-use serde::Deserialize;
-use serde_json::Value;
-use tracing::error;
+use hidapi::HidError;
+use tracing::{debug, info, warn};
+use vive_display::link::{LinkCaps, LinkConfig, ModeFit};
+use vive_protocol::{
+	decode_mainboard_status, decode_steam_config_chunk, hexdump, noise_canceling_commands,
+	parse_region_size, parse_steam_config_reader, parse_user_config, parse_vive_config,
+	raw_steam_config, raw_vive_config, set_brightness_command, set_mode_command, FeatureReport04,
+	Report01Request, Report02Command, FACTORY_CONFIG_REGION, REPORT_SIZE, USER_CONFIG_REGION,
+	VIVE_PRO_2_MODES,
+};
+#[cfg(feature = "unverified-mura")]
+use vive_protocol::{extract_mura_correction, MURA_REGION};
+#[cfg(feature = "unverified-flash-write")]
+use vive_protocol::{serialize_user_config, REGION_CHUNK_SIZE};
+
+/// `tracing::trace!`, which is only enabled with `trace-hid` feature, otherwise compiled out
+macro_rules! trace_hid {
+	($($arg:tt)*) => {
+		if cfg!(feature = "trace-hid") {
+			tracing::trace!($($arg)*);
+		}
+	};
+}
+
+pub mod capture;
+mod context;
+pub mod power;
+pub mod replay;
+pub mod sim;
+pub mod state;
+pub mod transport;
+
+pub use context::ViveContext;
+pub use state::{Connection, ConnectionState, StateChange};
+pub use transport::{DefaultTransport, HidTransport, InputEvents, MockTransport, Sent};
+
+pub use vive_protocol::{color_temperature_gain, combined_gain, NATIVE_COLOR_TEMPERATURE};
+pub use vive_protocol::{
+	ConfigAudio, ConfigCamera, ConfigCameraBlock, ConfigCameraIntrinsics, ConfigDevice,
+	ConfigEyeTransform, ConfigHeader, ConfigImu, ConfigLighthouse, ConfigPose, ConfigVersion,
+	DisplayMode, DistortType, GpuTier, IntrinsicsDistort, LensMetadata, MainboardStatus, Mode,
+	PanelInfo, RawConfig, Resolution, SteamConfig, UserConfig, ViveConfig,
+};
+#[cfg(feature = "unverified-mura")]
+pub use vive_protocol::{MuraCorrection, MuraCorrectionData, MuraData, MuraReference};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -15,324 +70,1199 @@ pub enum Error {
 	DeviceNotFound,
 	#[error("device is not a vive device")]
 	NotAVive,
-	#[error("config size mismatch")]
-	ConfigSizeMismatch,
-	#[error("failed to read config")]
-	ConfigReadFailed,
-	#[error("protocol error: {0}")]
-	ProtocolError(&'static str),
+	#[error("failed to read config after {attempts} attempts: {source}")]
+	ConfigReadFailed {
+		attempts: usize,
+		#[source]
+		source: HidError,
+	},
+	#[error("operation was cancelled")]
+	Cancelled,
+	#[error(transparent)]
+	Protocol(#[from] vive_protocol::Error),
+	#[error("io error: {0}")]
+	Io(#[from] io::Error),
+	#[error("headset is still reconnecting after previous mode switch")]
+	ModeSwitchInProgress,
+	#[error("mode switch has run out of time while {stage}")]
+	SwitchTimeout { stage: SwitchStage },
+	/// Mode doesn't fit into DisplayPort link even with DSC, in Mbps, see
+	/// [`ViveDevice::with_link_check`]
+	#[error("mode needs {required} Mbps, DisplayPort link only has {available} Mbps")]
+	ModeNotAchievable { required: u64, available: u64 },
+	#[error("headset is held by SteamVR (vrserver, pid {pid})")]
+	HeldBySteamVr { pid: u32 },
+	#[error("headset is disconnected: {0}")]
+	Disconnected(#[source] HidError),
+	#[error(
+		"no permission to access headset, udev rules are installed with `vivectl setup udev`: {0}"
+	)]
+	PermissionDenied(#[source] HidError),
+	/// What was being done, i.e `reading region b1 chunk 14/52`, see [`Error::root`]
+	#[error("while {operation}: {source}")]
+	Context {
+		operation: String,
+		#[source]
+		source: Box<Error>,
+	},
+}
+impl Error {
+	fn context(self, operation: impl Into<String>) -> Self {
+		Self::Context {
+			operation: operation.into(),
+			source: Box::new(self),
+		}
+	}
+	/// Error itself, without [`Error::Context`] around it, for matching
+	pub fn root(&self) -> &Self {
+		match self {
+			Self::Context { source, .. } => source.root(),
+			e => e,
+		}
+	}
 }
 
 type Result<T, E = Error> = result::Result<T, E>;
 
-static HIDAPI: OnceCell<HidApi> = OnceCell::new();
-pub fn get_hidapi() -> Result<&'static HidApi> {
-	HIDAPI.get_or_try_init(HidApi::new).map_err(From::from)
+/// Fails with [`Error::Disconnected`] or [`Error::PermissionDenied`], which retrying won't fix,
+/// anything else (EPIPE of busy device, timeouts, unknown errors) is given back to be retried
+///
+/// hidraw backend only keeps strerror of errno, so that is what is matched, for glibc and musl
+fn retryable(e: HidError) -> Result<HidError> {
+	const GONE: &[&str] = &[
+		"No such device",
+		"No such file or directory",
+		"device disconnected",
+	];
+	const DENIED: &[&str] = &["Permission denied", "Operation not permitted"];
+	let is = |messages: &[&str], errnos: &[i32]| match &e {
+		HidError::HidApiError { message } => messages.iter().any(|s| message.ends_with(s)),
+		HidError::IoError { error } => error.raw_os_error().is_some_and(|n| errnos.contains(&n)),
+		_ => false,
+	};
+	// ENODEV, ENOENT
+	if is(GONE, &[19, 2]) {
+		return Err(Error::Disconnected(e));
+	}
+	// EACCES, EPERM
+	if is(DENIED, &[13, 1]) {
+		return Err(Error::PermissionDenied(e));
+	}
+	Ok(e)
 }
 
-const STEAM_VID: u16 = 0x28de;
-const STEAM_PID: u16 = 0x2300;
-
-#[derive(Deserialize, Debug)]
-pub struct ConfigDevice {
-	pub eye_target_height_in_pixels: u32,
-	pub eye_target_width_in_pixels: u32,
+/// Structured error only tells what didn't match, whole report is dumped at debug level, so
+/// bug reports about malformed replies show what the device actually sent
+fn check_report<T>(report: &[u8], res: result::Result<T, vive_protocol::Error>) -> Result<T> {
+	res.map_err(|e| {
+		debug!("malformed report, {e}:\n{}", hexdump(report));
+		e.into()
+	})
 }
-#[derive(Deserialize, Debug)]
-pub enum DistortType {
-	#[serde(rename = "DISTORT_FTHETA")]
-	DistortFtheta,
+
+/// Fails with [`Error::ModeNotAchievable`] if mode with id `resolution` doesn't fit into `link`
+/// even compressed, unknown links and modes pass
+pub fn check_link(link: &LinkCaps, resolution: u8) -> Result<()> {
+	let (Some(mode), Some(max)) = (VIVE_PRO_2_MODES.get(resolution as usize), link.max_link())
+	else {
+		return Ok(());
+	};
+	if link.fit(mode.width, mode.height, mode.frame_rate) != ModeFit::TooFast {
+		return Ok(());
+	}
+	Err(Error::ModeNotAchievable {
+		required: link.min_required_mbps(mode.width, mode.height, mode.frame_rate),
+		available: max.payload_mbps(),
+	})
 }
-#[derive(Deserialize, Debug)]
-pub struct IntrinsicsDistort {
-	pub center_x: f32,
-	pub center_y: f32,
-	pub coeffs: Vec<f64>,
-	pub r#type: DistortType,
+
+/// Highest mode, which fits into `link` and GPU renders at full rate. Link of unknown rate is
+/// taken as HBR3 x4, and modes which need DSC are only picked once it is known to be active,
+/// as it also needs GPU (and kernel) support
+pub fn recommend_resolution(link: &LinkCaps, gpu: GpuTier) -> Resolution {
+	let link = LinkCaps {
+		verified: link.max_link().or(Some(LinkConfig {
+			lanes: 4,
+			rate: 0x1e,
+		})),
+		..link.clone()
+	};
+	let dsc = link.dsc_enabled == Some(true);
+	Resolution::ALL
+		.into_iter()
+		.rev()
+		.find(|r| {
+			let mode = r.mode();
+			let fits = match link.fit(mode.width, mode.height, mode.frame_rate) {
+				ModeFit::Fits => true,
+				ModeFit::NeedsDsc => dsc,
+				ModeFit::TooFast | ModeFit::Unknown => false,
+			};
+			fits && mode.width as f32 * mode.height as f32 * mode.frame_rate <= gpu.pixel_rate()
+		})
+		.unwrap_or(Resolution::R2448x1224f90)
 }
-#[derive(Deserialize, Debug)]
-pub struct ConfigCameraIntrinsics {
-	pub center_x: f32,
-	pub center_y: f32,
-	pub distort: IntrinsicsDistort,
-	pub focal_x: f32,
-	pub focal_y: f32,
-	pub width: u32,
-	pub height: u32,
+
+/// Part of [`ViveDevice::set_resolution`], which didn't fit into its deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwitchStage {
+	/// Feature reports of the switch itself, before chip reset
+	Write,
+	/// Old handle still works after chip reset, mode is not applied until it goes
+	DropOff,
+	/// Headset is gone, but is not back with the new mode yet
+	Reconnect,
 }
-#[derive(Deserialize, Debug)]
-pub struct ConfigCamera {
-	pub name: String,
-	pub intrinsics: ConfigCameraIntrinsics,
-	pub extrinsics: Vec<u8>,
+impl fmt::Display for SwitchStage {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::Write => "writing mode",
+			Self::DropOff => "waiting for headset to disconnect",
+			Self::Reconnect => "waiting for headset to reconnect",
+		})
+	}
 }
-#[derive(Deserialize, Debug)]
-pub struct SteamConfig {
-	pub device: ConfigDevice,
-	pub tracked_cameras: Vec<ConfigCamera>,
-	pub direct_mode_edid_pid: u32,
-	pub direct_mode_edid_vid: u32,
-	pub seconds_from_photons_to_vblank: f64,
-	pub seconds_from_vsync_to_photons: f64,
-	/// SN of ViveDevice
-	pub mb_serial_number: String,
+
+/// Shared flag for aborting long operations from another thread
+///
+/// Checked between HID transactions, and while waiting for replies, so blocked thread
+/// returns [`Error::Cancelled`] shortly after [`CancellationToken::cancel`]
+#[derive(Clone, Default, Debug)]
+pub struct CancellationToken(Arc<AtomicBool>);
+impl CancellationToken {
+	pub fn new() -> Self {
+		Self::default()
+	}
+	pub fn cancel(&self) {
+		self.0.store(true, Ordering::Relaxed)
+	}
+	pub fn is_cancelled(&self) -> bool {
+		self.0.load(Ordering::Relaxed)
+	}
+	pub fn check(&self) -> Result<()> {
+		if self.is_cancelled() {
+			return Err(Error::Cancelled);
+		}
+		Ok(())
+	}
 }
 
-pub struct SteamDevice(HidDevice);
+/// How often blocked read wakes up to check for cancellation
+const CANCEL_POLL_MS: i32 = 100;
+/// Known commands are answered right away
+const OPTIONAL_REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+
+pub const STEAM_VID: u16 = 0x28de;
+pub const STEAM_PID: u16 = 0x2300;
+
+pub struct SteamDevice<T = DefaultTransport>(capture::Device<T>, CancellationToken);
 impl SteamDevice {
+	fn simulated() -> Result<Self> {
+		Ok(Self::with_transport(DefaultTransport::simulated(
+			sim::open(sim::Part::Steam)?,
+		)))
+	}
 	pub fn open_first() -> Result<Self> {
-		let api = get_hidapi()?;
-		let device = api.open(STEAM_VID, STEAM_PID)?;
-		Ok(Self(device))
+		ViveContext::global().open_steam()
 	}
 	pub fn open(sn: &str) -> Result<Self> {
-		let api = get_hidapi()?;
-		let device = api
-			.device_list()
-			.find(|dev| dev.serial_number() == Some(sn))
-			.ok_or(Error::DeviceNotFound)?;
-		if device.vendor_id() != STEAM_VID || device.product_id() != STEAM_PID {
-			return Err(Error::NotAVive);
-		}
-		let open = api.open_serial(device.vendor_id(), device.product_id(), sn)?;
-		Ok(Self(open))
+		ViveContext::global().open_steam_serial(sn)
+	}
+}
+impl<T: HidTransport> SteamDevice<T> {
+	/// Device over any transport, i.e [`MockTransport`]
+	pub fn with_transport(transport: T) -> Self {
+		Self(
+			capture::Device::new(transport, "steam"),
+			CancellationToken::new(),
+		)
+	}
+	/// Replaces token, which is checked by long operations of this device
+	pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+		self.1 = token;
+		self
+	}
+	pub fn cancellation_token(&self) -> CancellationToken {
+		self.1.clone()
+	}
+	/// Last [`capture::RECENT_REPORTS`] reports of this handle, see [`ViveDevice::dump_recent`]
+	pub fn dump_recent(&self) -> String {
+		self.0.dump_recent()
+	}
+	#[cfg_attr(feature = "trace-hid", tracing::instrument(level = "trace", skip_all))]
+	fn read_config_blob(&self) -> Result<Vec<u8>> {
+		let mut progress = |_, _| {};
+		let mut reader = SteamConfigReader::new(self, &mut progress)?;
+		let mut out = Vec::new();
+		// Reader only fails with its own errors, which are returned by finish
+		let res = io::Read::read_to_end(&mut reader, &mut out);
+		reader.finish(res)?;
+		Ok(out)
 	}
 	pub fn read_config(&self) -> Result<SteamConfig> {
-		let mut report = [0u8; 64];
+		self.read_config_with_progress(|_, _| {})
+	}
+	/// Total size isn't known in advance for steam config, so it is always reported as `None`
+	#[cfg_attr(feature = "trace-hid", tracing::instrument(level = "trace", skip_all))]
+	pub fn read_config_with_progress(
+		&self,
+		mut progress: impl FnMut(usize, Option<usize>),
+	) -> Result<SteamConfig> {
+		let mut reader = SteamConfigReader::new(self, &mut progress)?;
+		let res = parse_steam_config_reader(&mut reader);
+		let config = reader.finish(res)?;
+		if !config.version.is_known() {
+			warn!(
+				"steam config version {} is newer than known",
+				config.version.0
+			);
+		}
+		Ok(config)
+	}
+	/// Unparsed config, available even if typed parsing fails
+	pub fn read_config_raw(&self) -> Result<RawConfig> {
+		Ok(raw_steam_config(&self.read_config_blob()?))
+	}
+}
+
+/// Streams config chunks straight from feature reports, reusing a single report buffer
+struct SteamConfigReader<'d, T> {
+	device: &'d SteamDevice<T>,
+	progress: &'d mut dyn FnMut(usize, Option<usize>),
+	read: usize,
+	chunks: usize,
+	report: [u8; REPORT_SIZE],
+	/// Range of unread chunk data in report
+	pos: usize,
+	end: usize,
+	done: bool,
+	/// Real error, which is hidden behind io::Error while inside of decoder
+	error: Option<Error>,
+}
+impl<'d, T: HidTransport> SteamConfigReader<'d, T> {
+	fn new(
+		device: &'d SteamDevice<T>,
+		progress: &'d mut dyn FnMut(usize, Option<usize>),
+	) -> Result<Self> {
+		let mut report = [0u8; REPORT_SIZE];
 		report[0] = 16;
 		let mut read_retries = 0;
-		while self.0.get_feature_report(&mut report).is_err() {
+		while let Err(e) = device.0.get_feature_report(&mut report) {
+			let e = retryable(e)?;
+			trace_hid!(id = 16, attempt = read_retries, error = %e, "config start failed");
 			if read_retries > 5 {
-				return Err(Error::ConfigReadFailed);
+				return Err(Error::ConfigReadFailed {
+					attempts: read_retries + 1,
+					source: e,
+				});
 			}
 			read_retries += 1;
 		}
-		read_retries = 0;
-		let mut out = Vec::new();
+		Ok(Self {
+			device,
+			progress,
+			read: 0,
+			chunks: 0,
+			report,
+			pos: 0,
+			end: 0,
+			done: false,
+			error: None,
+		})
+	}
+	fn next_chunk(&mut self) -> Result<()> {
+		self.device.1.check()?;
+		let mut read_retries = 0;
 		loop {
-			report[0] = 17;
-			if self.0.get_feature_report(&mut report).is_err() {
+			self.report[0] = 17;
+			if let Err(e) = self.device.0.get_feature_report(&mut self.report) {
+				let e = retryable(e)?;
+				trace_hid!(id = 17, attempt = read_retries, error = %e, "config chunk failed");
 				if read_retries > 5 {
-					return Err(Error::ConfigReadFailed);
+					return Err(Error::ConfigReadFailed {
+						attempts: read_retries + 1,
+						source: e,
+					});
 				}
 				read_retries += 1;
 				continue;
 			}
-			read_retries = 0;
-			if report[1] == 0 {
-				break;
+			break;
+		}
+		match check_report(&self.report, decode_steam_config_chunk(&self.report))? {
+			Some(chunk) => {
+				// Chunk data starts after id and size
+				self.pos = 2;
+				self.end = 2 + chunk.len();
+				self.read += chunk.len();
+				self.chunks += 1;
+				(self.progress)(self.read, None);
+			}
+			None => {
+				trace_hid!(size = self.read, "config read done");
+				self.done = true
+			}
+		}
+		Ok(())
+	}
+	/// Prefers error which happened during transfer over whatever consumer made of it
+	fn finish<R, E: Into<Error>>(self, res: Result<R, E>) -> Result<R> {
+		if let Some(e) = self.error {
+			return Err(e);
+		}
+		res.map_err(Into::into)
+	}
+}
+impl<T: HidTransport> io::Read for SteamConfigReader<'_, T> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		while self.pos == self.end && !self.done {
+			if let Err(e) = self.next_chunk() {
+				// Total is not known in advance
+				let e = e.context(format!("reading steam config chunk {}", self.chunks + 1));
+				let msg = e.to_string();
+				self.error = Some(e);
+				return Err(io::Error::other(msg));
 			}
-			out.extend_from_slice(&report[2..2 + report[1] as usize])
 		}
-		let mut dec = ZlibDecoder::new(out.as_slice());
-		let mut out = String::new();
-		dec.read_to_string(&mut out)
-			.map_err(|_| Error::ConfigReadFailed)?;
+		let len = buf.len().min(self.end - self.pos);
+		buf[..len].copy_from_slice(&self.report[self.pos..self.pos + len]);
+		self.pos += len;
+		Ok(len)
+	}
+}
+
+/// From [`ViveDevice::identity`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+	/// Same as [`ViveDevice::read_devsn`]
+	pub device_sn: String,
+	/// Mainboard serial
+	pub board_sn: Option<String>,
+	pub model: Option<String>,
+	/// Manufacturing region, market headset was made for
+	pub region: Option<String>,
+	/// Display panel, for tracking down panel defects and warranty, factory config may have
+	/// them in [`PanelInfo`] as well. Read is unverified, see
+	/// [`vive_protocol::Report02Command::PANEL_SN`], so answer may be something else entirely
+	pub panel_sn: Option<String>,
+	/// As firmware reports it, format is not known to be the same everywhere, unverified as
+	/// [`Self::panel_sn`]
+	pub panel_date: Option<String>,
+}
 
-		serde_json::from_str(&out).map_err(|_| Error::ConfigReadFailed)
+/// From [`ViveDevice::usage_stats`], counters firmware doesn't answer are left out. Every read
+/// is unverified, see [`vive_protocol::Report02Command::UPTIME`], answers may mean something else
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UsageStats {
+	/// Since power on, chip reset (i.e mode switch) starts it over
+	pub uptime: Option<Duration>,
+	/// Over the whole life of the headset
+	pub power_on_hours: Option<u64>,
+	pub power_cycles: Option<u64>,
+}
+
+/// From [`ViveDevice::status_events`]
+pub struct StatusEvents {
+	events: InputEvents,
+	cancel: CancellationToken,
+}
+impl StatusEvents {
+	/// Next status, other reports are skipped, `None` once everything that arrived is read,
+	/// then it is time to wait for fd again
+	pub fn try_next(&mut self) -> Result<Option<MainboardStatus>> {
+		self.cancel.check()?;
+		let mut data = [0u8; REPORT_SIZE];
+		while let Some(size) = self.events.try_read(&mut data)? {
+			if let Some(status) = decode_mainboard_status(&data[..size]) {
+				return Ok(Some(status));
+			}
+		}
+		Ok(None)
+	}
+	/// Only the latest status, for callers which are woken up less often than reports come
+	pub fn latest(&mut self) -> Result<Option<MainboardStatus>> {
+		let mut latest = None;
+		while let Some(status) = self.try_next()? {
+			latest = Some(status);
+		}
+		Ok(latest)
+	}
+}
+impl AsFd for StatusEvents {
+	fn as_fd(&self) -> BorrowedFd<'_> {
+		self.events.as_fd()
+	}
+}
+impl AsRawFd for StatusEvents {
+	fn as_raw_fd(&self) -> RawFd {
+		self.events.as_raw_fd()
 	}
 }
 
-const VIVE_VID: u16 = 0x0bb4;
-const VIVE_PID: u16 = 0x0342;
+/// ALSA sound card of headset audio codec
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioCard {
+	/// `N` of `hw:N`, also equal to `alsa.card` property of PulseAudio/PipeWire sinks and sources
+	pub index: u32,
+	/// Stable name, as in `hw:CARD=<id>`
+	pub id: String,
+	/// Sysfs directory of card
+	pub path: PathBuf,
+}
+impl AudioCard {
+	/// ALSA device string, which doesn't change between reboots
+	pub fn alsa_device(&self) -> String {
+		format!("hw:CARD={}", self.id)
+	}
+}
 
-#[derive(Deserialize, Debug)]
-pub struct ViveConfig {
-	pub device: ConfigDevice,
-	pub direct_mode_edid_pid: u32,
-	pub direct_mode_edid_vid: u32,
-	pub seconds_from_photons_to_vblank: f64,
-	pub seconds_from_vsync_to_photons: f64,
-	/// Lets threat it as something opaque, anyway we directly feed this to lens-client
-	pub inhouse_lens_correction: Value,
+/// Every sound card of USB devices under `hub`
+fn audio_cards_under(hub: &Path) -> Result<Vec<AudioCard>> {
+	let mut cards = Vec::new();
+	for entry in fs::read_dir("/sys/class/sound")? {
+		let entry = entry?;
+		let name = entry.file_name();
+		let Some(index) = name
+			.to_str()
+			.and_then(|n| n.strip_prefix("card"))
+			.and_then(|n| n.parse().ok())
+		else {
+			continue;
+		};
+		let path = fs::canonicalize(entry.path())?;
+		if !path.starts_with(hub) {
+			continue;
+		}
+		let id = fs::read_to_string(path.join("id"))?.trim().to_owned();
+		cards.push(AudioCard { index, id, path });
+	}
+	cards.sort_by_key(|c| c.index);
+	Ok(cards)
 }
 
-#[derive(Clone, Copy)]
-pub struct Mode {
-	pub id: u8,
+/// USB interface class of UVC cameras
+const VIDEO_INTERFACE_CLASS: &str = "0e";
 
-	pub width: u32,
-	pub height: u32,
-	pub frame_rate: f32,
-	pub extra_photon_vsync: f32,
+/// Every USB device under `hub`, which has a video interface
+fn cameras_under(hub: &Path) -> Result<Vec<PathBuf>> {
+	let mut cameras = Vec::new();
+	for entry in fs::read_dir("/sys/bus/usb/devices")? {
+		let path = fs::canonicalize(entry?.path())?;
+		// Interfaces are listed here too, they have no idVendor
+		if !path.starts_with(hub) || path == hub || !path.join("idVendor").exists() {
+			continue;
+		}
+		let video = fs::read_dir(&path)?.flatten().any(|interface| {
+			fs::read_to_string(interface.path().join("bInterfaceClass"))
+				.map(|c| c.trim() == VIDEO_INTERFACE_CLASS)
+				.unwrap_or(false)
+		});
+		if video {
+			cameras.push(path);
+		}
+	}
+	cameras.sort();
+	Ok(cameras)
 }
-impl Mode {
-	const fn new(
-		id: u8,
-		width: u32,
-		height: u32,
-		frame_rate: f32,
-		extra_photon_vsync: f32,
-	) -> Self {
-		Self {
-			id,
-			width,
-			height,
-			frame_rate,
-			extra_photon_vsync,
+
+/// USB vendor and product ids of cameras of every plugged in headset, found through sysfs, so
+/// udev rules can be limited to them. Camera ids are not documented, so they are only known
+/// with headset connected
+pub fn headset_camera_ids() -> Result<Vec<(u16, u16)>> {
+	let mut ids = Vec::new();
+	for hub in power::headset_hubs()? {
+		for camera in cameras_under(&hub)? {
+			let id = |name| -> Option<u16> {
+				let value = fs::read_to_string(camera.join(name)).ok()?;
+				u16::from_str_radix(value.trim(), 16).ok()
+			};
+			if let (Some(vid), Some(pid)) = (id("idVendor"), id("idProduct")) {
+				ids.push((vid, pid));
+			}
 		}
 	}
+	ids.sort();
+	ids.dedup();
+	Ok(ids)
 }
 
-const VIVE_PRO_2_MODES: [Mode; 6] = [
-	Mode::new(0, 2448, 1224, 90.0, 0.0),
-	Mode::new(1, 2448, 1224, 120.0, 0.0),
-	Mode::new(2, 3264, 1632, 90.0, 0.00297),
-	Mode::new(3, 3672, 1836, 90.0, 0.00332),
-	Mode::new(4, 4896, 2448, 90.0, 0.0),
-	Mode::new(5, 4896, 2448, 120.0, 0.0),
-];
+/// `/dev/videoN` capture nodes of camera USB device. UVC also creates a metadata node per
+/// camera, it has `index` 1 and is skipped
+fn video_nodes_under(camera: &Path) -> Result<Vec<PathBuf>> {
+	let mut nodes = Vec::new();
+	for interface in fs::read_dir(camera)?.flatten() {
+		let Ok(videos) = fs::read_dir(interface.path().join("video4linux")) else {
+			continue;
+		};
+		for video in videos.flatten() {
+			let index = fs::read_to_string(video.path().join("index")).unwrap_or_default();
+			if index.trim() == "0" {
+				nodes.push(Path::new("/dev").join(video.file_name()));
+			}
+		}
+	}
+	nodes.sort();
+	Ok(nodes)
+}
+
+/// Headset re-enumerates after mode switch, and sending more dtd commands meanwhile
+/// may wedge the firmware
+const MODE_SWITCH_COOLDOWN: Duration = Duration::from_secs(5);
+/// Per headset serial, shared by every handle, as the handle which switched the mode is gone after
+/// re-enumeration. Handles without usb serial share `None`
+static LAST_MODE_SWITCH: Mutex<BTreeMap<Option<String>, (Instant, u8)>> =
+	Mutex::new(BTreeMap::new());
+/// Old device stays listed for a moment after reset, it shouldn't be mistaken for reconnected one
+const RECONNECT_GRACE: Duration = Duration::from_secs(1);
+const RECONNECT_POLL: Duration = Duration::from_millis(250);
+
+pub const VIVE_VID: u16 = 0x0bb4;
+pub const VIVE_PID: u16 = 0x0342;
 
-pub struct ViveDevice(HidDevice);
+pub struct ViveDevice<T = DefaultTransport> {
+	device: capture::Device<T>,
+	cancel: CancellationToken,
+	/// See [`ViveDevice::with_link_check`]
+	link: Option<LinkCaps>,
+	/// See [`ViveDevice::with_connection`]
+	connection: Option<Arc<Connection>>,
+}
 impl ViveDevice {
+	fn simulated() -> Result<Self> {
+		Ok(Self::with_transport(DefaultTransport::simulated(
+			sim::open(sim::Part::Vive)?,
+		)))
+	}
 	pub fn open_first() -> Result<Self> {
-		let api = get_hidapi()?;
-		let device = api.open(VIVE_VID, VIVE_PID)?;
-		Ok(Self(device))
+		ViveContext::global().open_vive()
 	}
 	pub fn open(sn: &str) -> Result<Self> {
-		let api = get_hidapi()?;
-		let device = api
-			.device_list()
-			.find(|dev| dev.serial_number() == Some(sn))
-			.ok_or(Error::DeviceNotFound)?;
-		if device.vendor_id() != VIVE_VID || device.product_id() != VIVE_PID {
-			return Err(Error::NotAVive);
-		}
-		let open = api.open_serial(device.vendor_id(), device.product_id(), sn)?;
-		Ok(Self(open))
-	}
-	fn write(&self, id: u8, data: &[u8]) -> Result<()> {
-		let mut report = [0u8; 64];
-		report[0] = id;
-		report[1..1 + data.len()].copy_from_slice(data);
-		self.0.write(&report)?;
-		Ok(())
+		ViveContext::global().open_vive_serial(sn)
 	}
-	fn write_feature(&self, id: u8, sub_id: u16, data: &[u8]) -> Result<()> {
-		let mut report = [0u8; 64];
-		report[0] = id;
-		report[1] = (sub_id & 0xff) as u8;
-		report[2] = (sub_id >> 8) as u8;
-		report[3] = data.len() as u8;
-		report[4..][..data.len()].copy_from_slice(data);
-		self.0.send_feature_report(&report)?;
-		Ok(())
+	/// Headset of tracking board with this serial, for when there is more than one plugged in.
+	/// Both are behind the hub inside the headset, so the one closest to it in usb topology is
+	/// picked, same as [`ViveDevice::open_first`] when topology is not known
+	pub fn open_paired(steam_sn: &str) -> Result<Self> {
+		ViveContext::global().open_vive_paired(steam_sn)
 	}
-	fn read(&self, id: u8, strip_prefix: &[u8], out: &mut [u8]) -> Result<usize> {
-		let mut data = [0u8; 64];
-		self.0.read(&mut data)?;
-		if data[0] != id {
-			error!("expected {id} but got {}\n{:02x?}", data[0], data);
-			return Err(Error::ProtocolError("wrong report id"));
-		}
-		if &data[1..1 + strip_prefix.len()] != strip_prefix {
-			error!(
-				"expected {strip_prefix:x?}, got {:x?}",
-				&data[1..1 + strip_prefix.len()]
-			);
-			return Err(Error::ProtocolError("wrong prefix"));
+	/// Opens headset again, once it is back after [`ViveDevice::set_mode`] or reset, headset
+	/// which didn't come back in time is Failed in [`ViveContext::connection`]
+	pub fn wait_reconnect(timeout: Duration) -> Result<Self> {
+		Self::wait_reconnect_with(timeout, Self::open_first)
+	}
+	/// Same as [`ViveDevice::wait_reconnect`], with [`ViveDevice::open_paired`]
+	pub fn wait_reconnect_paired(steam_sn: &str, timeout: Duration) -> Result<Self> {
+		Self::wait_reconnect_with(timeout, || Self::open_paired(steam_sn))
+	}
+	/// [`ViveDevice::set_resolution`], then waits for the headset to be back, `budget` covers
+	/// all of it, and whatever is late fails with [`Error::SwitchTimeout`]
+	///
+	/// `None` if nothing was switched, as the same mode is already being switched to, then
+	/// there is nothing to wait for, and this handle is left as it is
+	pub fn set_resolution_and_wait(
+		&self,
+		resolution: u8,
+		budget: Duration,
+	) -> Result<Option<Self>> {
+		self.switch_and_reconnect(resolution, budget, Self::open_first)
+	}
+	/// Same as [`ViveDevice::set_resolution_and_wait`], with [`ViveDevice::open_paired`]
+	pub fn set_resolution_and_wait_paired(
+		&self,
+		resolution: u8,
+		steam_sn: &str,
+		budget: Duration,
+	) -> Result<Option<Self>> {
+		self.switch_and_reconnect(resolution, budget, || Self::open_paired(steam_sn))
+	}
+	fn switch_and_reconnect(
+		&self,
+		resolution: u8,
+		budget: Duration,
+		open: impl Fn() -> Result<Self>,
+	) -> Result<Option<Self>> {
+		let deadline = Instant::now() + budget;
+		if !self.switch_mode(resolution, Some(deadline))? {
+			return Ok(None);
+		}
+		let reconnect = || {
+			self.wait_drop_off(deadline)?;
+			Self::reconnect_until(deadline, &self.cancel, open)
+		};
+		match reconnect() {
+			Ok(dev) => Ok(Some(dev.keep_link(self))),
+			Err(e @ Error::SwitchTimeout { .. }) => {
+				self.report(|c| c.reboot_failed(&e));
+				Err(e)
+			}
+			Err(e) => Err(e),
+		}
+	}
+	/// Handle after reconnect checks modes the same way old one did
+	fn keep_link(mut self, old: &Self) -> Self {
+		self.link = old.link.clone();
+		self
+	}
+	/// Old handle is known to be gone, so there is no grace period, unlike
+	/// [`ViveDevice::wait_reconnect`]
+	fn reconnect_until(
+		deadline: Instant,
+		cancel: &CancellationToken,
+		open: impl Fn() -> Result<Self>,
+	) -> Result<Self> {
+		loop {
+			cancel.check()?;
+			match open() {
+				Ok(dev) => return Ok(dev.with_cancellation(cancel.clone())),
+				Err(e) if Instant::now() + RECONNECT_POLL > deadline => {
+					debug!("headset is not back after mode switch: {e}");
+					return Err(Error::SwitchTimeout {
+						stage: SwitchStage::Reconnect,
+					});
+				}
+				Err(e) => {
+					trace_hid!(error = %e, "headset is not back yet");
+					thread::sleep(RECONNECT_POLL)
+				}
+			}
 		}
-		let size = data[1 + strip_prefix.len()] as usize;
-		if size > 62 {
-			return Err(Error::ProtocolError("wrong size"));
+	}
+	#[cfg_attr(
+		feature = "trace-hid",
+		tracing::instrument(level = "trace", skip_all, fields(timeout_ms = timeout.as_millis() as u64))
+	)]
+	fn wait_reconnect_with(timeout: Duration, open: impl Fn() -> Result<Self>) -> Result<Self> {
+		let start = Instant::now();
+		thread::sleep(RECONNECT_GRACE);
+		loop {
+			match open() {
+				Ok(dev) => {
+					trace_hid!(
+						elapsed_ms = start.elapsed().as_millis() as u64,
+						"headset is back"
+					);
+					return Ok(dev);
+				}
+				Err(e) if start.elapsed() >= timeout => {
+					ViveContext::global().connection().reboot_failed(&e);
+					return Err(e);
+				}
+				Err(e) => {
+					trace_hid!(error = %e, "headset is not back yet");
+					thread::sleep(RECONNECT_POLL)
+				}
+			}
 		}
-		out[..size].copy_from_slice(&data[strip_prefix.len() + 2..strip_prefix.len() + 2 + size]);
-		Ok(size)
+	}
+}
+impl<T: HidTransport> ViveDevice<T> {
+	/// Device over any transport, i.e [`MockTransport`]
+	pub fn with_transport(transport: T) -> Self {
+		Self {
+			device: capture::Device::new(transport, "vive"),
+			cancel: CancellationToken::new(),
+			link: None,
+			connection: None,
+		}
+	}
+	/// Replaces token, which is checked by long operations of this device
+	pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+		self.cancel = token;
+		self
+	}
+	pub fn cancellation_token(&self) -> CancellationToken {
+		self.cancel.clone()
+	}
+	/// Makes mode switches of this handle fail with [`Error::ModeNotAchievable`] before
+	/// anything is written, if mode doesn't fit into `link`, instead of leaving panel black.
+	/// Link of unknown rate lets every mode through
+	pub fn with_link_check(mut self, link: LinkCaps) -> Self {
+		self.link = Some(link);
+		self
+	}
+	/// Makes this handle report to `connection`, devices opened through [`ViveContext`] report
+	/// to [`ViveContext::connection`]
+	pub fn with_connection(mut self, connection: Arc<Connection>) -> Self {
+		self.connection = Some(connection);
+		self
+	}
+	pub fn connection(&self) -> Option<Arc<Connection>> {
+		self.connection.clone()
+	}
+	fn report(&self, change: impl FnOnce(&Connection)) {
+		if let Some(connection) = &self.connection {
+			change(connection);
+		}
+	}
+	/// Last [`capture::RECENT_REPORTS`] reports of this handle, oldest first, in JSON capture
+	/// format, which [`replay::Trace`] reads. Failed ones have `error` field
+	pub fn dump_recent(&self) -> String {
+		self.device.dump_recent()
+	}
+	fn read_reply(&self) -> Result<[u8; REPORT_SIZE]> {
+		let mut data = [0u8; REPORT_SIZE];
+		loop {
+			self.cancel.check()?;
+			if self.device.read_timeout(&mut data, CANCEL_POLL_MS)? != 0 {
+				return Ok(data);
+			}
+		}
+	}
+	#[cfg_attr(
+		feature = "trace-hid",
+		tracing::instrument(
+			level = "trace",
+			skip_all,
+			fields(op = req.reply_prefix()[0], region = req.reply_prefix()[1])
+		)
+	)]
+	fn transact(&self, req: Report01Request<'_>) -> Result<[u8; REPORT_SIZE]> {
+		self.cancel.check()?;
+		self.device.write(&req.encode()?)?;
+		self.read_reply()
+	}
+	/// Sends request and copies reply payload to `out`
+	#[cfg(feature = "unverified-flash-write")]
+	fn request(&self, req: Report01Request<'_>, out: &mut [u8]) -> Result<usize> {
+		let reply = self.transact(req)?;
+		let payload = check_report(&reply, req.decode_reply(&reply))?;
+		out[..payload.len()].copy_from_slice(payload);
+		Ok(payload.len())
+	}
+	#[cfg_attr(
+		feature = "trace-hid",
+		tracing::instrument(level = "trace", skip_all, fields(what = what))
+	)]
+	fn command(&self, cmd: Report02Command<'_>, what: &'static str) -> Result<String> {
+		self.device.discard_input()?;
+		self.device.write(&cmd.encode()?)?;
+		let reply = self.read_reply()?;
+		Self::decode_command(cmd, what, &reply)
+	}
+	/// Command, which firmware may not know, and then doesn't answer, `None` if it doesn't, or
+	/// reply doesn't decode
+	///
+	/// Reply may still come after we stopped waiting, it is dropped before the next command is
+	/// sent. Replies don't echo command text, so report id is all they are matched by, other
+	/// reports (status) are skipped
+	fn optional_command(
+		&self,
+		cmd: Report02Command<'_>,
+		what: &'static str,
+	) -> Result<Option<String>> {
+		self.device.discard_input()?;
+		self.device.write(&cmd.encode()?)?;
+		let start = Instant::now();
+		let mut reply = [0u8; REPORT_SIZE];
+		loop {
+			if self.device.read_timeout(&mut reply, CANCEL_POLL_MS)? != 0 {
+				if reply[0] == Report02Command::ID {
+					break;
+				}
+				trace_hid!(id = reply[0], "skipped report, while waiting for {what}");
+			}
+			self.cancel.check()?;
+			if start.elapsed() >= OPTIONAL_REPLY_TIMEOUT {
+				debug!("headset has not answered {what} read");
+				return Ok(None);
+			}
+		}
+		match Self::decode_command(cmd, what, &reply) {
+			Ok(value) => {
+				let value = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+				Ok((!value.is_empty()).then(|| value.to_owned()))
+			}
+			Err(Error::Protocol(e)) => {
+				debug!("headset has no {what}: {e}");
+				Ok(None)
+			}
+			Err(e) => Err(e),
+		}
+	}
+	fn decode_command(
+		cmd: Report02Command<'_>,
+		what: &'static str,
+		reply: &[u8; REPORT_SIZE],
+	) -> Result<String> {
+		let text = cmd.decode_reply(reply).and_then(|payload| {
+			std::str::from_utf8(payload).map_err(|_| vive_protocol::Error::NotUtf8 {
+				what,
+				bytes: payload.to_vec(),
+			})
+		});
+		Ok(check_report(reply, text)?.to_string())
+	}
+	#[cfg_attr(
+		feature = "trace-hid",
+		tracing::instrument(
+			level = "trace",
+			skip_all,
+			fields(sub_id = report.sub_id, size = report.data.len())
+		)
+	)]
+	fn write_feature(&self, report: FeatureReport04<'_>) -> Result<()> {
+		self.device.send_feature_report(&report.encode()?)?;
+		Ok(())
 	}
 	pub fn read_devsn(&self) -> Result<String> {
-		self.write(0x02, b"mfg-r-devsn")?;
-		let mut out = [0u8; 62];
-		let size = self.read(0x02, &[], &mut out)?;
-		Ok(std::str::from_utf8(&out[..size])
-			.map_err(|_| Error::ProtocolError("devsn is not a string"))?
-			.to_string())
+		self.command(Report02Command::DEVSN, "devsn")
 	}
-	pub fn read_reg(&self, reg: &str) -> Result<String> {
-		self.write(0x02, reg.as_bytes())?;
-		let mut out = [0u8; 62];
-		let size = self.read(0x02, &[], &mut out)?;
-		Ok(std::str::from_utf8(&out[..size])
-			.map_err(|_| Error::ProtocolError("result is not a string"))?
-			.to_string())
+	/// Every identity field at once, only device serial is required, other reads are left out
+	/// if firmware doesn't answer them properly
+	pub fn identity(&self) -> Result<Identity> {
+		Ok(Identity {
+			device_sn: self.read_devsn()?,
+			board_sn: self.optional_command(Report02Command::PCBSN, "board serial")?,
+			model: self.optional_command(Report02Command::MODEL, "model")?,
+			region: self.optional_command(Report02Command::REGION, "region")?,
+			panel_sn: self.optional_command(Report02Command::PANEL_SN, "panel serial")?,
+			panel_date: self
+				.optional_command(Report02Command::PANEL_DATE, "panel manufacture date")?,
+		})
 	}
-	pub fn read_config(&self) -> Result<ViveConfig> {
-		let mut buf = [0u8; 62];
-		// Request size
-		let total_len = {
-			self.write(0x01, &[0xea, 0xb1])?;
-			let size = self.read(0x01, &[0xea, 0xb1], &mut buf)?;
-			if size != 4 {
-				return Err(Error::ProtocolError("config length has 4 bytes"));
+	/// Counters, which firmware provides, for telling how much headset was used
+	pub fn usage_stats(&self) -> Result<UsageStats> {
+		let counter = |cmd, what| -> Result<Option<u64>> {
+			let Some(value) = self.optional_command(cmd, what)? else {
+				return Ok(None);
+			};
+			let parsed = value.parse().ok();
+			if parsed.is_none() {
+				debug!("headset {what} is not a number: {value:?}");
 			}
-			let mut total_len = [0u8; 4];
-			total_len.copy_from_slice(&buf[0..4]);
-			u32::from_le_bytes(total_len) as usize
+			Ok(parsed)
 		};
-		let mut read = 0;
+		Ok(UsageStats {
+			uptime: counter(Report02Command::UPTIME, "uptime")?.map(Duration::from_secs),
+			power_on_hours: counter(Report02Command::ONTIME, "power on hours")?,
+			power_cycles: counter(Report02Command::BOOTCNT, "power cycles")?,
+		})
+	}
+	pub fn read_reg(&self, reg: &str) -> Result<String> {
+		self.command(Report02Command::new(reg.as_bytes())?, "register value")
+	}
+	fn region_size(&self, region: u8) -> Result<usize> {
+		let req = Report01Request::RegionSize { region };
+		let reply = self.transact(req)?;
+		let size = req
+			.decode_reply(&reply)
+			.and_then(|payload| parse_region_size(region, payload));
+		check_report(&reply, size)
+	}
+	#[cfg_attr(
+		feature = "trace-hid",
+		tracing::instrument(level = "trace", skip_all, fields(region = region))
+	)]
+	fn read_region(
+		&self,
+		region: u8,
+		progress: &mut dyn FnMut(usize, Option<usize>),
+	) -> Result<Vec<u8>> {
+		let total_len = self
+			.region_size(region)
+			.map_err(|e| e.context(format!("reading region {region:02x} size")))?;
+		trace_hid!(size = total_len, "region size");
+		// Device replies with full reports, except for the last one
+		let chunks = total_len.div_ceil(REPORT_SIZE - 4);
 		let mut out = Vec::<u8>::with_capacity(total_len);
-		while read < total_len {
-			let mut req = [0; 63];
-			req[0] = 0xeb;
-			req[1] = 0xb1;
-			req[2] = 0x04;
-			req[3..7].copy_from_slice(&u32::to_le_bytes(read as u32));
-
-			self.write(0x01, &req)?;
-			let size = self.read(0x01, &[0xeb, 0xb1], &mut buf)?;
-			read += size;
-			out.extend_from_slice(&buf[0..size]);
+		let mut chunk = 0;
+		while out.len() < total_len {
+			chunk += 1;
+			let req = Report01Request::RegionRead {
+				region,
+				offset: out.len() as u32,
+			};
+			let what = || format!("reading region {region:02x} chunk {chunk}/{chunks}");
+			// Payload is appended directly from reply, without intermediate buffer
+			let reply = self.transact(req).map_err(|e| e.context(what()))?;
+			let payload =
+				check_report(&reply, req.decode_reply(&reply)).map_err(|e| e.context(what()))?;
+			if payload.is_empty() || out.len() + payload.len() > total_len {
+				trace_hid!(
+					read = out.len(),
+					size = payload.len(),
+					"region read stopped early"
+				);
+				break;
+			}
+			out.extend_from_slice(payload);
+			progress(out.len(), Some(total_len));
 		}
+		let read = out.len();
 		if read != total_len {
-			return Err(Error::ProtocolError("config size mismatch"));
+			return Err(vive_protocol::Error::RegionSizeMismatch {
+				region,
+				expected: total_len,
+				actual: read,
+			}
+			.into());
 		}
-
-		// First 128 bytes - something i can't decipher + sha256 hash (why?)
-		let string = std::str::from_utf8(&out[128..])
-			.map_err(|_| Error::ProtocolError("config is not utf-8"))?;
-
-		serde_json::from_str(string).map_err(|_| Error::ConfigReadFailed)
+		Ok(out)
+	}
+	/// Write protocol was never seen in a capture, see [`vive_protocol::REGION_WRITE`]
+	#[cfg(feature = "unverified-flash-write")]
+	#[cfg_attr(
+		feature = "trace-hid",
+		tracing::instrument(level = "trace", skip_all, fields(region = region, size = data.len()))
+	)]
+	fn write_region(&self, region: u8, data: &[u8]) -> Result<()> {
+		let mut buf = [0u8; 62];
+		let chunks = data.len().div_ceil(REGION_CHUNK_SIZE);
+		for (i, chunk) in data.chunks(REGION_CHUNK_SIZE).enumerate() {
+			let offset = (i * REGION_CHUNK_SIZE) as u32;
+			Report01Request::region_write(region, offset, chunk)
+				.map_err(Error::from)
+				.and_then(|req| self.request(req, &mut buf))
+				.map_err(|e| {
+					e.context(format!(
+						"writing region {region:02x} chunk {}/{chunks}",
+						i + 1
+					))
+				})?;
+		}
+		self.request(
+			Report01Request::RegionCommit {
+				region,
+				len: data.len() as u32,
+			},
+			&mut buf,
+		)
+		.map_err(|e| e.context(format!("committing region {region:02x}")))?;
+		Ok(())
+	}
+	pub fn read_config(&self) -> Result<ViveConfig> {
+		self.read_config_with_progress(|_, _| {})
+	}
+	/// Config read takes many transactions, `progress` receives bytes read and total size
+	#[cfg_attr(feature = "trace-hid", tracing::instrument(level = "trace", skip_all))]
+	pub fn read_config_with_progress(
+		&self,
+		mut progress: impl FnMut(usize, Option<usize>),
+	) -> Result<ViveConfig> {
+		let out = self.read_region(FACTORY_CONFIG_REGION, &mut progress)?;
+		let config = parse_vive_config(&out)?;
+		if !config.version.is_known() {
+			warn!(
+				"vive config version {} is newer than known",
+				config.version.0
+			);
+		}
+		Ok(config)
+	}
+	/// Unparsed config, available even if typed parsing fails
+	pub fn read_config_raw(&self) -> Result<RawConfig> {
+		Ok(raw_vive_config(
+			&self.read_region(FACTORY_CONFIG_REGION, &mut |_, _| {})?,
+		))
+	}
+	/// Returns `None` if config has no mura correction info. Guessed, see
+	/// [`vive_protocol::MURA_REGION`]
+	#[cfg(feature = "unverified-mura")]
+	pub fn read_mura_correction(&self, config: &ViveConfig) -> Result<Option<MuraCorrectionData>> {
+		let Some(correction) = &config.mura_correction else {
+			return Ok(None);
+		};
+		let region = self.read_region(MURA_REGION, &mut |_, _| {})?;
+		Ok(Some(extract_mura_correction(&region, correction)?))
+	}
+	/// Returns default config if nothing was written to the user region yet
+	pub fn read_user_config(&self) -> Result<UserConfig> {
+		let out = self.read_region(USER_CONFIG_REGION, &mut |_, _| {})?;
+		Ok(parse_user_config(&out)?)
+	}
+	/// Only with `unverified-flash-write` feature, write opcodes are guessed, see
+	/// [`vive_protocol::REGION_WRITE`]
+	#[cfg(feature = "unverified-flash-write")]
+	pub fn write_user_config(&self, config: &UserConfig) -> Result<()> {
+		let data = serialize_user_config(config)?;
+		self.write_region(USER_CONFIG_REGION, &data)
+	}
+	/// Waits up to `timeout_ms` for sensor state report, other reports are skipped
+	pub fn read_status(&self, timeout_ms: i32) -> Result<Option<MainboardStatus>> {
+		self.cancel.check()?;
+		let mut data = [0u8; REPORT_SIZE];
+		let size = match self.device.read_timeout(&mut data, timeout_ms) {
+			Ok(size) => size,
+			Err(e) => {
+				let e = Error::from(e);
+				self.report(|c| c.lost(&e));
+				return Err(e);
+			}
+		};
+		Ok(decode_mainboard_status(&data[..size]))
+	}
+	/// Sensor state reports, for event loops waiting on many devices: fd becomes readable once
+	/// there are reports, which [`StatusEvents::try_next`] then reads without blocking
+	pub fn status_events(&self) -> Result<StatusEvents> {
+		Ok(StatusEvents {
+			events: self.device.input_events()?,
+			cancel: self.cancel.clone(),
+		})
+	}
+	/// Sysfs directory of USB device, which provides this HID interface,
+	/// i.e `/sys/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2.1`
+	///
+	/// Headset is a hub, so other headset functions (audio, camera) are its siblings
+	pub fn usb_path(&self) -> Result<PathBuf> {
+		let path = self.device.hidraw_path()?;
+		// Always /dev/hidrawN with hidraw backend
+		let node = Path::new(OsStr::from_bytes(path.to_bytes()));
+		let name = node.file_name().ok_or(Error::DeviceNotFound)?;
+		let mut path = fs::canonicalize(Path::new("/sys/class/hidraw").join(name).join("device"))?;
+		// hid device -> usb interface -> usb device
+		while !path.join("idVendor").exists() {
+			if !path.pop() {
+				return Err(Error::DeviceNotFound);
+			}
+		}
+		Ok(path)
+	}
+	/// Sound cards, which belong to this headset (normally just one, with both speakers and microphone)
+	pub fn audio_cards(&self) -> Result<Vec<AudioCard>> {
+		let usb = self.usb_path()?;
+		let hub = usb.parent().ok_or(Error::DeviceNotFound)?;
+		audio_cards_under(hub)
+	}
+	/// Autosuspend state of every USB device of this headset, see [`power`]
+	pub fn usb_power(&self) -> Result<Vec<power::UsbPower>> {
+		let usb = self.usb_path()?;
+		let hub = usb.parent().ok_or(Error::DeviceNotFound)?;
+		power::usb_power_in(Path::new(power::SYSFS_USB_DEVICES), hub)
+	}
+	/// Sysfs directories of headset camera USB devices
+	pub fn cameras(&self) -> Result<Vec<PathBuf>> {
+		let usb = self.usb_path()?;
+		let hub = usb.parent().ok_or(Error::DeviceNotFound)?;
+		cameras_under(hub)
+	}
+	/// What headset cameras stream through, for standard V4L2 consumers (GStreamer `v4l2src`,
+	/// ffmpeg). Empty when cameras are disabled
+	pub fn camera_nodes(&self) -> Result<Vec<PathBuf>> {
+		let mut nodes = Vec::new();
+		for camera in self.cameras()? {
+			nodes.extend(video_nodes_under(&camera)?);
+		}
+		Ok(nodes)
+	}
+	/// Disabled cameras are deauthorized, so they are gone from the bus together with the
+	/// bandwidth they reserve, until enabled again or replugged.
+	///
+	/// Needs write access to `authorized` attribute, which `vivectl setup udev` rules grant to
+	/// `video` group
+	pub fn set_cameras_enabled(&self, enabled: bool) -> Result<()> {
+		let value = if enabled { "1" } else { "0" };
+		for camera in self.cameras()? {
+			let authorized = camera.join("authorized");
+			// Needs no access at all, if there is nothing to change
+			if fs::read_to_string(&authorized)?.trim() != value {
+				fs::write(authorized, value)?;
+			}
+		}
+		Ok(())
 	}
 	/// Always returns at least one mode
 	pub fn query_modes(&self) -> Vec<Mode> {
 		VIVE_PRO_2_MODES.into_iter().collect()
 	}
+	/// Repeated request for the same mode during [`MODE_SWITCH_COOLDOWN`] is ignored,
+	/// and request for other mode fails with [`Error::ModeSwitchInProgress`]
+	#[cfg_attr(
+		feature = "trace-hid",
+		tracing::instrument(level = "trace", skip_all, fields(resolution = resolution))
+	)]
 	pub fn set_mode(&self, resolution: u8) -> Result<(), Error> {
-		self.write_feature(0x04, 0x2970, b"wireless,0")?;
-		self.write_feature(0x04, 0x2970, format!("dtd,{}", resolution).as_bytes())?;
-		self.write_feature(0x04, 0x2970, b"chipreset")?;
-		// TODO: wait for reconnection
+		self.switch_mode(resolution, None)?;
+		Ok(())
+	}
+	/// [`ViveDevice::set_mode`], which only returns once the old handle is gone, so the mode is
+	/// being applied, instead of once reports are written. Fails with [`Error::SwitchTimeout`]
+	/// if that takes longer than `budget`
+	pub fn set_resolution(&self, resolution: u8, budget: Duration) -> Result<()> {
+		let deadline = Instant::now() + budget;
+		if self.switch_mode(resolution, Some(deadline))? {
+			self.wait_drop_off(deadline)?;
+		}
+		Ok(())
+	}
+	/// Returns whether reports were written, they aren't if the same switch is still going on
+	fn switch_mode(&self, resolution: u8, deadline: Option<Instant>) -> Result<bool> {
+		if let Some(link) = &self.link {
+			check_link(link, resolution)?;
+		}
+		let serial = self.device.serial_number().ok();
+		let mut last = LAST_MODE_SWITCH.lock().expect("lock");
+		last.retain(|_, (at, _)| at.elapsed() < MODE_SWITCH_COOLDOWN);
+		if let Some(&(_, mode)) = last.get(&serial) {
+			if mode == resolution {
+				info!("mode {resolution} is already being switched to");
+				return Ok(false);
+			}
+			trace_hid!(switching_to = mode, "mode switch in progress");
+			return Err(Error::ModeSwitchInProgress);
+		}
+		// Feature reports can't be interrupted, so late ones are only caught before they start
+		let write = |report: FeatureReport04<'_>| {
+			if deadline.is_some_and(|d| Instant::now() >= d) {
+				return Err(Error::SwitchTimeout {
+					stage: SwitchStage::Write,
+				});
+			}
+			self.write_feature(report)
+		};
+		write(FeatureReport04::control(b"wireless,0")?)?;
+		write(FeatureReport04::control(
+			set_mode_command(resolution).as_bytes(),
+		)?)?;
+		// Reset below may fail as device is already gone, it still counts as a switch
+		last.insert(serial, (Instant::now(), resolution));
+		self.report(Connection::rebooting);
+		trace_hid!("mode switch cooldown started, resetting chip");
+		write(FeatureReport04::control(b"chipreset")?)?;
+		Ok(true)
+	}
+	/// Reads fail once hidraw node is removed, reports until then are status stream
+	fn wait_drop_off(&self, deadline: Instant) -> Result<()> {
+		let mut data = [0u8; REPORT_SIZE];
+		loop {
+			self.cancel.check()?;
+			let left = deadline.saturating_duration_since(Instant::now());
+			if left.is_zero() {
+				return Err(Error::SwitchTimeout {
+					stage: SwitchStage::DropOff,
+				});
+			}
+			let timeout = left.min(RECONNECT_POLL).as_millis() as i32;
+			if let Err(e) = self.device.read_timeout(&mut data, timeout) {
+				trace_hid!(error = %e, "headset is gone");
+				return Ok(());
+			}
+		}
+	}
+	/// Restarts headset chip, which makes it reconnect, nothing stored on it is changed. There
+	/// is no known vendor command for factory reset
+	#[cfg_attr(feature = "trace-hid", tracing::instrument(level = "trace", skip_all))]
+	pub fn restart(&self) -> Result<()> {
+		self.report(Connection::rebooting);
+		// May fail as device is already gone
+		let _ = self.write_feature(FeatureReport04::control(b"chipreset")?);
 		Ok(())
 	}
+	#[cfg_attr(
+		feature = "trace-hid",
+		tracing::instrument(level = "trace", skip_all, fields(brightness = brightness))
+	)]
 	pub fn set_brightness(&self, brightness: u8) -> Result<(), Error> {
-		self.write_feature(
-			0x04,
-			0x2970,
-			format!("setbrightness,{}", brightness.min(130)).as_bytes(),
-		)
+		self.write_feature(FeatureReport04::control(
+			set_brightness_command(brightness).as_bytes(),
+		)?)
 	}
+	#[cfg_attr(
+		feature = "trace-hid",
+		tracing::instrument(level = "trace", skip_all, fields(enabled = enabled))
+	)]
 	pub fn toggle_noise_canceling(&self, enabled: bool) -> Result<(), Error> {
-		const ENABLE: &[&[u8]] = &[
-			b"codecreg=9c9,80".as_slice(),
-			b"codecreg=9c8,a5",
-			b"codecreg=9d0,a4",
-			b"codecreg=1c008f,1",
-			b"codecreg=1c0005,9",
-			b"codecreg=1c0005,8000",
-		];
-		const DISABLE: &[&[u8]] = &[
-			b"codecreg=9c9,8c".as_slice(),
-			b"codecreg=9c8,a4",
-			b"codecreg=9d0,0",
-			b"codecreg=1c008f,0",
-			b"codecreg=1c0005,9",
-			b"codecreg=1c0005,8000",
-		];
-		// I have no idea what those values mean, this is straight
-		// copy-pasta from what original vive console sends
-		let lines: &'static [&'static [u8]] = if enabled { ENABLE } else { DISABLE };
-		for line in lines {
-			self.write_feature(0x04, 0x2971, line)?;
+		for line in noise_canceling_commands(enabled) {
+			self.write_feature(FeatureReport04::codec(line)?)?;
 		}
 		Ok(())
 	}
@@ -0,0 +1,125 @@
+//! USB runtime power management of headset devices
+//!
+//! Kernel suspends idle USB devices which have `power/control` set to `auto`. That's the
+//! default with powersave tuning (TLP, `powertop --auto-tune`) and on some distributions, and
+//! headset functions nothing has open (cameras, audio while muted, tracking board between
+//! sessions) don't always come back from it, so headset drops off the bus. Hubs only suspend
+//! once every device under them did, so keeping devices of the headset itself awake is enough
+//!
+//! Writing `power/control` needs root, `vivectl setup udev` rules set it on every plug
+
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use crate::{Result, VIVE_PID, VIVE_VID};
+
+pub const SYSFS_USB_DEVICES: &str = "/sys/bus/usb/devices";
+const USBCORE_AUTOSUSPEND: &str = "/sys/module/usbcore/parameters/autosuspend";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbPower {
+	/// Sysfs directory of USB device
+	pub path: PathBuf,
+	/// Bus path, i.e `1-2.1`
+	pub name: String,
+	pub product: Option<String>,
+	/// `power/control` is `auto`
+	pub autosuspend: bool,
+}
+impl UsbPower {
+	/// Product name, or bus path for devices without one
+	pub fn label(&self) -> String {
+		match &self.product {
+			Some(product) => format!("{} ({product})", self.name),
+			None => self.name.clone(),
+		}
+	}
+}
+
+/// Every USB device under `hub`, including the hub itself, `devices` is usually
+/// [`SYSFS_USB_DEVICES`]
+pub fn usb_power_in(devices: &Path, hub: &Path) -> Result<Vec<UsbPower>> {
+	let mut out = Vec::new();
+	for entry in fs::read_dir(devices)? {
+		let path = fs::canonicalize(entry?.path())?;
+		// Interfaces are listed here too, they have no idVendor
+		if !path.starts_with(hub) || !path.join("idVendor").exists() {
+			continue;
+		}
+		let Ok(control) = fs::read_to_string(path.join("power/control")) else {
+			continue;
+		};
+		let read = |name| Some(fs::read_to_string(path.join(name)).ok()?.trim().to_owned());
+		out.push(UsbPower {
+			name: path
+				.file_name()
+				.map(|n| n.to_string_lossy().into_owned())
+				.unwrap_or_default(),
+			product: read("product"),
+			autosuspend: control.trim() == "auto",
+			path,
+		});
+	}
+	out.sort_by(|a, b| a.path.cmp(&b.path));
+	Ok(out)
+}
+
+/// Hubs inside of every plugged in headset, found through sysfs, so headset doesn't need to be
+/// accessible, or even free of SteamVR
+pub fn headset_hubs() -> Result<Vec<PathBuf>> {
+	let mut hubs = Vec::new();
+	// Missing without USB host controllers
+	let Ok(devices) = fs::read_dir(SYSFS_USB_DEVICES) else {
+		return Ok(hubs);
+	};
+	for entry in devices {
+		let path = fs::canonicalize(entry?.path())?;
+		let id = |name| fs::read_to_string(path.join(name)).ok();
+		let matches = |name, value: u16| {
+			id(name).is_some_and(|v| u16::from_str_radix(v.trim(), 16) == Ok(value))
+		};
+		if !matches("idVendor", VIVE_VID) || !matches("idProduct", VIVE_PID) {
+			continue;
+		}
+		if let Some(hub) = path.parent() {
+			hubs.push(hub.to_owned());
+		}
+	}
+	hubs.sort();
+	hubs.dedup();
+	Ok(hubs)
+}
+
+/// [`usb_power_in`] of every [`headset_hubs`] entry
+pub fn headset_usb_power() -> Result<Vec<UsbPower>> {
+	let mut out = Vec::new();
+	for hub in headset_hubs()? {
+		out.extend(usb_power_in(Path::new(SYSFS_USB_DEVICES), &hub)?);
+	}
+	Ok(out)
+}
+
+/// Keeps devices awake, returns ones which were changed
+pub fn disable_autosuspend(devices: &[UsbPower]) -> Result<Vec<UsbPower>> {
+	let mut changed = Vec::new();
+	for dev in devices.iter().filter(|d| d.autosuspend) {
+		fs::write(dev.path.join("power/control"), "on")?;
+		changed.push(UsbPower {
+			autosuspend: false,
+			..dev.clone()
+		});
+	}
+	Ok(changed)
+}
+
+/// `usbcore.autosuspend` delay in seconds, newly plugged devices start with it, negative means
+/// they are never suspended, unless something enables it for them
+pub fn default_autosuspend_delay() -> Option<i64> {
+	fs::read_to_string(USBCORE_AUTOSUSPEND)
+		.ok()?
+		.trim()
+		.parse()
+		.ok()
+}
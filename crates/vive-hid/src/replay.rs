@@ -0,0 +1,167 @@
+//! Replay of HID sessions captured to JSON (see [`crate::capture`]), through [`MockTransport`]
+//!
+//! Replies of one device are queued in recorded order, and once the same operation is done
+//! against the mock, reports host has sent are compared with recorded ones, so protocol changes
+//! which alter what `read_config`, `set_mode` and friends send are noticed without hardware
+use std::{fmt, fs, io, path::Path};
+
+use serde_json::Value;
+
+use crate::transport::{MockTransport, Sent};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReplayError {
+	#[error("failed to read trace: {0}")]
+	Io(#[from] io::Error),
+	#[error("trace line {line} is not a capture record")]
+	BadRecord { line: usize },
+	#[error("report {index} differs, recorded {expected}, sent {actual}")]
+	Mismatch {
+		index: usize,
+		expected: Report,
+		actual: Report,
+	},
+	#[error("{count} recorded reports were not sent, first is {first}")]
+	NotSent { count: usize, first: Report },
+	#[error("{count} reports were sent after the recorded ones, first is {first}")]
+	Unexpected { count: usize, first: Report },
+	#[error("{0} recorded replies were not asked for")]
+	Unconsumed(usize),
+}
+
+type Result<T, E = ReplayError> = std::result::Result<T, E>;
+
+/// Sent report, as shown in errors
+#[derive(Debug)]
+pub struct Report(pub Sent);
+impl fmt::Display for Report {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let (kind, data) = match &self.0 {
+			Sent::Output(data) => ("output", data),
+			Sent::Feature(data) => ("set_feature", data),
+		};
+		// Reports are zero padded, tail says nothing
+		let len = data.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+		write!(f, "{kind} ")?;
+		for b in &data[..len] {
+			write!(f, "{b:02x}")?;
+		}
+		Ok(())
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+	Output,
+	Input,
+	SetFeature,
+	GetFeature,
+}
+
+struct Record {
+	device: String,
+	kind: Kind,
+	data: Vec<u8>,
+}
+
+/// `Some(None)` for reports which have failed, and weren't exchanged
+fn parse_record(line: &str) -> Option<Option<Record>> {
+	let value: Value = serde_json::from_str(line).ok()?;
+	if value.get("error").is_some() {
+		return Some(None);
+	}
+	let kind = match value["kind"].as_str()? {
+		"output" => Kind::Output,
+		"input" => Kind::Input,
+		"set_feature" => Kind::SetFeature,
+		"get_feature" => Kind::GetFeature,
+		_ => return None,
+	};
+	let hex = value["data"].as_str()?;
+	if hex.len() % 2 != 0 {
+		return None;
+	}
+	let data = (0..hex.len())
+		.step_by(2)
+		.map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+		.collect::<Option<_>>()?;
+	Some(Some(Record {
+		device: value["device"].as_str()?.to_owned(),
+		kind,
+		data,
+	}))
+}
+
+/// Captured session, every device of it
+pub struct Trace(Vec<Record>);
+impl Trace {
+	/// Only JSON captures can be replayed, pcap-ng has no device names to tell parts apart
+	pub fn load(path: &Path) -> Result<Self> {
+		Self::parse(&fs::read_to_string(path)?)
+	}
+	pub fn parse(text: &str) -> Result<Self> {
+		text.lines()
+			.enumerate()
+			.filter(|(_, line)| !line.trim().is_empty())
+			.filter_map(|(i, line)| {
+				parse_record(line)
+					.ok_or(ReplayError::BadRecord { line: i + 1 })
+					.transpose()
+			})
+			.collect::<Result<_>>()
+			.map(Self)
+	}
+	fn of<'t>(&'t self, device: &'t str) -> impl Iterator<Item = &'t Record> + 't {
+		self.0.iter().filter(move |r| r.device == device)
+	}
+	/// Mock, which answers with recorded replies of `device` (`"vive"` or `"steam"`)
+	pub fn mock(&self, device: &str) -> MockTransport {
+		let mock = MockTransport::new();
+		for record in self.of(device) {
+			match record.kind {
+				Kind::Input => mock.push_input(record.data.clone()),
+				Kind::GetFeature => mock.push_feature(record.data.clone()),
+				Kind::Output | Kind::SetFeature => {}
+			}
+		}
+		mock
+	}
+	/// Compares what was sent to `mock` with what was recorded for `device`, and checks that every
+	/// recorded reply was used
+	pub fn check(&self, device: &str, mock: &MockTransport) -> Result<()> {
+		let expected = self
+			.of(device)
+			.filter_map(|r| match r.kind {
+				Kind::Output => Some(Sent::Output(r.data.clone())),
+				Kind::SetFeature => Some(Sent::Feature(r.data.clone())),
+				Kind::Input | Kind::GetFeature => None,
+			})
+			.collect::<Vec<_>>();
+		let actual = mock.take_sent();
+		for (index, (e, a)) in expected.iter().zip(&actual).enumerate() {
+			if e != a {
+				return Err(ReplayError::Mismatch {
+					index,
+					expected: Report(e.clone()),
+					actual: Report(a.clone()),
+				});
+			}
+		}
+		if let Some(first) = expected.get(actual.len()) {
+			return Err(ReplayError::NotSent {
+				count: expected.len() - actual.len(),
+				first: Report(first.clone()),
+			});
+		}
+		if let Some(first) = actual.get(expected.len()) {
+			return Err(ReplayError::Unexpected {
+				count: actual.len() - expected.len(),
+				first: Report(first.clone()),
+			});
+		}
+		match mock.pending() {
+			0 => Ok(()),
+			n => Err(ReplayError::Unconsumed(n)),
+		}
+	}
+}
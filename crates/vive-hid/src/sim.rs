@@ -0,0 +1,487 @@
+//! Simulated headset, for development without hardware
+//!
+//! Enabled with `VIVEPRO2_SIMULATE=1` (or with path to vive config JSON, i.e from
+//! `vivectl config dump`, to simulate that headset) before the first device is opened, or with
+//! [`enable`]. Both headset parts are then opened as in-process fakes, which speak the same
+//! reports real ones do, so everything above HID works unchanged, including capture.
+//!
+//! State is per process: mode switch makes the headset gone for a moment, and handles opened
+//! before it stop working, same as with the real one
+use std::{
+	collections::VecDeque,
+	env, fs, io,
+	os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+	path::Path,
+	sync::{Mutex, Once},
+	thread,
+	time::{Duration, Instant},
+};
+
+use hidapi::{HidError, HidResult};
+use tracing::{debug, info, warn};
+use vive_protocol::{
+	compress_steam_config, encode_mainboard_status, ConfigHeader, FeatureReport04, MainboardStatus,
+	Report, Report01Request, Report02Command, FACTORY_CONFIG_REGION, REGION_CHUNK_SIZE,
+	REPORT_SIZE, USER_CONFIG_REGION,
+};
+
+use crate::{transport::HidTransport, Error, Result};
+
+pub const SIMULATE_ENV: &str = "VIVEPRO2_SIMULATE";
+/// Tracking board serial, as lighthouse driver would report it
+pub const STEAM_SERIAL: &str = "LHR-00000000";
+pub const DEVSN: &str = "SIM000000000";
+pub const PCBSN: &str = "SIMPCB000000";
+pub const MODEL: &str = "VIVE Pro 2 (simulated)";
+pub const REGION: &str = "SIM";
+pub const PANEL_SN: &str = "SIMPANEL0000";
+pub const PANEL_DATE: &str = "2021-06-01";
+/// Simulated headset is barely used
+pub const ONTIME_HOURS: u64 = 12;
+
+/// Real headset takes a few seconds to re-enumerate
+const RECONNECT_DELAY: Duration = Duration::from_secs(3);
+/// Mainboard sends its status continuously
+const STATUS_INTERVAL: Duration = Duration::from_millis(100);
+/// Worn headset, with 63mm IPD
+const STATUS: MainboardStatus = MainboardStatus {
+	lens_separation: 6300,
+	button: 0,
+	proximity_change: 1,
+	proximity: 1000,
+	ipd: 6300,
+};
+
+/// Only what driver needs, lens has precomputed intrinsics, so projection doesn't need lens
+/// server
+const VIVE_CONFIG: &str = r#"{
+	"device": { "eye_target_height_in_pixels": 2448, "eye_target_width_in_pixels": 2448 },
+	"seconds_from_vsync_to_photons": 0.0111,
+	"inhouse_lens_correction": {
+		"left": {
+			"intrinsic": [0.6, 0.0, -0.02, 0.0, 0.6, 0.0, 0.0, 0.0],
+			"grow_for_undistort": [0.0, 0.0, 0.0, 0.0]
+		},
+		"right": {
+			"intrinsic": [0.6, 0.0, 0.02, 0.0, 0.6, 0.0, 0.0, 0.0],
+			"grow_for_undistort": [0.0, 0.0, 0.0, 0.0]
+		}
+	},
+	"panel": { "vendor": "simulated" }
+}"#;
+const STEAM_CONFIG: &str = r#"{
+	"device": { "eye_target_height_in_pixels": 2448, "eye_target_width_in_pixels": 2448 },
+	"mb_serial_number": "SIM000000000",
+	"device_serial_number": "LHR-00000000",
+	"device_class": "hmd",
+	"head": { "plus_x": [1, 0, 0], "plus_z": [0, 0, 1], "position": [0, 0, 0] },
+	"imu": {
+		"plus_x": [1, 0, 0], "plus_z": [0, 0, 1], "position": [0, 0.02, 0.03],
+		"acc_bias": [0, 0, 0], "acc_scale": [1, 1, 1], "gyro_bias": [0, 0, 0], "gyro_scale": [1, 1, 1]
+	},
+	"lighthouse_config": {
+		"modelPoints": [[0.06, 0.03, 0.04], [-0.06, 0.03, 0.04], [0.06, -0.03, 0.04], [-0.06, -0.03, 0.04]],
+		"modelNormals": [[0.6, 0, 0.8], [-0.6, 0, 0.8], [0.6, 0, 0.8], [-0.6, 0, 0.8]],
+		"channelMap": [0, 1, 2, 3]
+	}
+}"#;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Part {
+	Vive,
+	Steam,
+}
+
+struct State {
+	enabled: bool,
+	/// Factory region, with header
+	vive_config: Vec<u8>,
+	steam_config: Vec<u8>,
+	user_config: Vec<u8>,
+	mode: u8,
+	/// Written with `dtd`, applied on chip reset
+	pending_mode: Option<u8>,
+	brightness: u8,
+	gone_until: Option<Instant>,
+	/// Bumped on every reset
+	generation: u32,
+	/// Last reset, or first command, which is as good as power on for uptime
+	booted: Option<Instant>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+	enabled: false,
+	vive_config: Vec::new(),
+	steam_config: Vec::new(),
+	user_config: Vec::new(),
+	mode: 0,
+	pending_mode: None,
+	brightness: 130,
+	gone_until: None,
+	generation: 0,
+	booted: None,
+});
+static FROM_ENV: Once = Once::new();
+
+fn set_configs(state: &mut State, vive_config: &[u8]) {
+	state.vive_config = ConfigHeader::encode("VIVEPRO2SIM", vive_config);
+	state.steam_config = compress_steam_config(STEAM_CONFIG);
+}
+
+/// Built-in configs, unless [`enable`] already set them, for simulated
+/// [`crate::ViveContext`], which doesn't make the rest of the process simulated
+pub(crate) fn load() {
+	let mut state = STATE.lock().expect("lock");
+	if state.vive_config.is_empty() {
+		set_configs(&mut state, VIVE_CONFIG.as_bytes());
+	}
+}
+
+/// Every device opened after this is simulated, `vive_config` replaces built-in config
+pub fn enable(vive_config: Option<&Path>) -> io::Result<()> {
+	let body = match vive_config {
+		Some(path) => fs::read(path)?,
+		None => VIVE_CONFIG.as_bytes().to_vec(),
+	};
+	let mut state = STATE.lock().expect("lock");
+	set_configs(&mut state, &body);
+	state.enabled = true;
+	info!("headset is simulated, serial {STEAM_SERIAL}");
+	Ok(())
+}
+
+pub fn enabled() -> bool {
+	FROM_ENV.call_once(|| {
+		let Some(value) = env::var_os(SIMULATE_ENV) else {
+			return;
+		};
+		let path = (value != "1").then(|| Path::new(&value).to_owned());
+		if let Err(e) = enable(path.as_deref()) {
+			warn!("failed to read simulated headset config: {e}");
+		}
+	});
+	STATE.lock().expect("lock").enabled
+}
+
+fn gone() -> HidError {
+	HidError::HidApiError {
+		// Same errno as hidraw of unplugged device, so it is classified the same way
+		message: "simulated device is gone: No such device".to_owned(),
+	}
+}
+
+struct Inner {
+	replies: VecDeque<Report>,
+	/// Region write chunks, until commit
+	written: Vec<u8>,
+	next_status: Instant,
+	steam_config_offset: usize,
+}
+
+pub(crate) struct Device {
+	part: Part,
+	generation: u32,
+	inner: Mutex<Inner>,
+}
+
+/// Fails the same way real open does, while headset is reconnecting
+pub(crate) fn open(part: Part) -> Result<Device> {
+	let state = STATE.lock().expect("lock");
+	if state.gone_until.map(|t| Instant::now() < t) == Some(true) {
+		return Err(Error::DeviceNotFound);
+	}
+	Ok(Device {
+		part,
+		generation: state.generation,
+		inner: Mutex::new(Inner {
+			replies: VecDeque::new(),
+			written: Vec::new(),
+			next_status: Instant::now(),
+			steam_config_offset: 0,
+		}),
+	})
+}
+
+/// Status stream, timer fires every time the next status report is due, tracking board has no
+/// stream, so its timer is never armed
+pub(crate) struct Events {
+	timer: OwnedFd,
+	generation: u32,
+}
+impl Events {
+	pub fn try_read(&mut self, buf: &mut [u8]) -> HidResult<Option<usize>> {
+		check(self.generation)?;
+		let mut expirations = 0u64;
+		let res = unsafe {
+			libc::read(
+				self.timer.as_raw_fd(),
+				(&mut expirations as *mut u64).cast(),
+				8,
+			)
+		};
+		if res < 0 {
+			let error = io::Error::last_os_error();
+			if error.kind() == io::ErrorKind::WouldBlock {
+				return Ok(None);
+			}
+			return Err(HidError::IoError { error });
+		}
+		let report = encode_mainboard_status(&STATUS);
+		let len = buf.len().min(REPORT_SIZE);
+		buf[..len].copy_from_slice(&report[..len]);
+		Ok(Some(len))
+	}
+}
+impl AsFd for Events {
+	fn as_fd(&self) -> BorrowedFd<'_> {
+		self.timer.as_fd()
+	}
+}
+
+/// Handles opened before reset are gone, even once headset is back
+fn check(generation: u32) -> HidResult<()> {
+	if STATE.lock().expect("lock").generation != generation {
+		return Err(gone());
+	}
+	Ok(())
+}
+
+impl Device {
+	fn check(&self) -> HidResult<()> {
+		check(self.generation)
+	}
+
+	pub fn events(&self) -> HidResult<Events> {
+		self.check()?;
+		let io = |error| HidError::IoError { error };
+		let fd = unsafe {
+			libc::timerfd_create(
+				libc::CLOCK_MONOTONIC,
+				libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+			)
+		};
+		if fd < 0 {
+			return Err(io(io::Error::last_os_error()));
+		}
+		let timer = unsafe { OwnedFd::from_raw_fd(fd) };
+		if self.part == Part::Vive {
+			let interval = libc::timespec {
+				tv_sec: 0,
+				tv_nsec: STATUS_INTERVAL.as_nanos() as _,
+			};
+			let spec = libc::itimerspec {
+				it_interval: interval,
+				it_value: interval,
+			};
+			if unsafe { libc::timerfd_settime(fd, 0, &spec, std::ptr::null_mut()) } < 0 {
+				return Err(io(io::Error::last_os_error()));
+			}
+		}
+		Ok(Events {
+			timer,
+			generation: self.generation,
+		})
+	}
+
+	fn region(&self, report: &[u8]) {
+		let req = match Report01Request::decode(report) {
+			Ok(req) => req,
+			Err(e) => {
+				warn!("simulated headset: {e}");
+				return;
+			}
+		};
+		let mut inner = self.inner.lock().expect("lock");
+		let mut state = STATE.lock().expect("lock");
+		let data = |state: &State, region| -> Vec<u8> {
+			match region {
+				FACTORY_CONFIG_REGION => state.vive_config.clone(),
+				USER_CONFIG_REGION => state.user_config.clone(),
+				_ => vec![],
+			}
+		};
+		let payload = match req {
+			Report01Request::RegionSize { region } => {
+				(data(&state, region).len() as u32).to_le_bytes().to_vec()
+			}
+			Report01Request::RegionRead { region, offset } => data(&state, region)
+				.get(offset as usize..)
+				.unwrap_or_default()
+				.iter()
+				.copied()
+				.take(REGION_CHUNK_SIZE)
+				.collect(),
+			Report01Request::RegionWrite { offset, chunk, .. } => {
+				let offset = offset as usize;
+				if inner.written.len() < offset + chunk.len() {
+					inner.written.resize(offset + chunk.len(), 0xff);
+				}
+				inner.written[offset..offset + chunk.len()].copy_from_slice(chunk);
+				vec![]
+			}
+			Report01Request::RegionCommit { region, len } => {
+				let mut written = std::mem::take(&mut inner.written);
+				written.truncate(len as usize);
+				if region == USER_CONFIG_REGION {
+					state.user_config = written;
+				} else {
+					warn!("simulated headset: region {region:02x} is read-only");
+				}
+				vec![]
+			}
+		};
+		inner
+			.replies
+			.push_back(req.encode_reply(&payload).expect("chunk fits"));
+	}
+
+	fn command(&self, report: &[u8]) {
+		let Ok(Report02Command(text)) = Report02Command::decode(report) else {
+			return;
+		};
+		let counter;
+		let reply: &[u8] = if text == Report02Command::DEVSN.0 {
+			DEVSN.as_bytes()
+		} else if text == Report02Command::PCBSN.0 {
+			PCBSN.as_bytes()
+		} else if text == Report02Command::MODEL.0 {
+			MODEL.as_bytes()
+		} else if text == Report02Command::REGION.0 {
+			REGION.as_bytes()
+		} else if text == Report02Command::PANEL_SN.0 {
+			PANEL_SN.as_bytes()
+		} else if text == Report02Command::PANEL_DATE.0 {
+			PANEL_DATE.as_bytes()
+		} else if text == Report02Command::UPTIME.0 {
+			let mut state = STATE.lock().expect("lock");
+			let booted = *state.booted.get_or_insert_with(Instant::now);
+			counter = booted.elapsed().as_secs().to_string();
+			counter.as_bytes()
+		} else if text == Report02Command::ONTIME.0 {
+			counter = ONTIME_HOURS.to_string();
+			counter.as_bytes()
+		} else if text == Report02Command::BOOTCNT.0 {
+			counter = (STATE.lock().expect("lock").generation + 1).to_string();
+			counter.as_bytes()
+		} else {
+			debug!(
+				"simulated headset: register {}",
+				String::from_utf8_lossy(text)
+			);
+			b"0"
+		};
+		self.inner
+			.lock()
+			.expect("lock")
+			.replies
+			.push_back(Report02Command::encode_reply(reply).expect("reply fits"));
+	}
+
+	fn control(&self, text: &str) {
+		let mut state = STATE.lock().expect("lock");
+		if let Some(mode) = text.strip_prefix("dtd,") {
+			state.pending_mode = mode.parse().ok();
+		} else if let Some(brightness) = text.strip_prefix("setbrightness,") {
+			state.brightness = brightness.parse().unwrap_or(state.brightness);
+		} else if text == "chipreset" {
+			if let Some(mode) = state.pending_mode.take() {
+				info!("simulated headset: switched to mode {mode}, reconnecting");
+				state.mode = mode;
+			}
+			state.gone_until = Some(Instant::now() + RECONNECT_DELAY);
+			state.generation += 1;
+			state.booted = state.gone_until;
+		} else {
+			debug!("simulated headset: {text}");
+		}
+	}
+}
+impl HidTransport for Device {
+	fn write(&self, data: &[u8]) -> HidResult<usize> {
+		self.check()?;
+		if self.part == Part::Vive {
+			match data.first() {
+				Some(&Report01Request::ID) => self.region(data),
+				Some(&Report02Command::ID) => self.command(data),
+				_ => {}
+			}
+		}
+		Ok(data.len())
+	}
+	fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> HidResult<usize> {
+		self.check()?;
+		let mut inner = self.inner.lock().expect("lock");
+		let report = match inner.replies.pop_front() {
+			Some(reply) => reply,
+			None => {
+				let now = Instant::now();
+				// Negative timeout blocks
+				let timeout = u64::try_from(timeout_ms)
+					.map(Duration::from_millis)
+					.unwrap_or(STATUS_INTERVAL);
+				if self.part == Part::Steam {
+					drop(inner);
+					thread::sleep(timeout);
+					return Ok(0);
+				}
+				let wait = inner.next_status.saturating_duration_since(now);
+				if wait > timeout {
+					drop(inner);
+					thread::sleep(timeout);
+					return Ok(0);
+				}
+				thread::sleep(wait);
+				inner.next_status = Instant::now() + STATUS_INTERVAL;
+				encode_mainboard_status(&STATUS)
+			}
+		};
+		let len = buf.len().min(REPORT_SIZE);
+		buf[..len].copy_from_slice(&report[..len]);
+		Ok(len)
+	}
+	fn send_feature_report(&self, data: &[u8]) -> HidResult<()> {
+		self.check()?;
+		if self.part != Part::Vive {
+			return Ok(());
+		}
+		if let Ok(report) = FeatureReport04::decode(data) {
+			if report.sub_id == FeatureReport04::SUB_ID_CONTROL {
+				self.control(&String::from_utf8_lossy(report.data));
+			}
+		}
+		Ok(())
+	}
+	fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize> {
+		self.check()?;
+		if self.part != Part::Steam {
+			return Err(HidError::HidApiError {
+				message: "simulated device has no such report".to_owned(),
+			});
+		}
+		let mut inner = self.inner.lock().expect("lock");
+		match buf[0] {
+			// Starts config transfer
+			16 => inner.steam_config_offset = 0,
+			17 => {
+				let state = STATE.lock().expect("lock");
+				let chunk = state
+					.steam_config
+					.get(inner.steam_config_offset..)
+					.unwrap_or_default();
+				let len = chunk.len().min(buf.len() - 2);
+				buf[1] = len as u8;
+				buf[2..2 + len].copy_from_slice(&chunk[..len]);
+				inner.steam_config_offset += len;
+			}
+			_ => {}
+		}
+		Ok(buf.len())
+	}
+	fn serial_number(&self) -> HidResult<String> {
+		Ok(match self.part {
+			Part::Vive => DEVSN,
+			Part::Steam => STEAM_SERIAL,
+		}
+		.to_owned())
+	}
+}
@@ -0,0 +1,234 @@
+//! Headset lifecycle, the one answer to "is the headset up?" in a process
+//!
+//! Headsets opened through a [`crate::ViveContext`] report to its [`Connection`]: open
+//! attempts move it out of Disconnected once headset is on the bus, mode switches and resets
+//! move it to Rebooting until it is opened again, and failed status reads mean it is gone.
+//! Consumers query it, wait for it, or [`Connection::subscribe`] to its changes
+//!
+//! ```text
+//! Disconnected -> Enumerating -> Ready -> Rebooting -> Ready
+//!                      |                      |
+//!                      +-------> Failed <-----+
+//! ```
+//!
+//! Headset may go away in any state, and Failed one becomes Ready once open succeeds
+
+use std::{
+	fmt,
+	sync::{
+		mpsc::{self, Receiver, Sender},
+		Condvar, Mutex,
+	},
+	time::{Duration, Instant},
+};
+
+use crate::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+	/// Headset is not on the bus
+	Disconnected,
+	/// Headset is on the bus, but its hidraw node is not there yet
+	Enumerating,
+	/// Opened, and answering
+	Ready,
+	/// Mode switch or reset was sent, headset is expected to come back
+	Rebooting,
+	/// Headset is there, but can't be opened (permissions, SteamVR holds it), or didn't come
+	/// back after reboot, see [`Connection::reason`]
+	Failed,
+}
+impl ConnectionState {
+	pub const ALL: [Self; 5] = [
+		Self::Disconnected,
+		Self::Enumerating,
+		Self::Ready,
+		Self::Rebooting,
+		Self::Failed,
+	];
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Disconnected => "disconnected",
+			Self::Enumerating => "enumerating",
+			Self::Ready => "ready",
+			Self::Rebooting => "rebooting",
+			Self::Failed => "failed",
+		}
+	}
+	pub fn is_ready(self) -> bool {
+		self == Self::Ready
+	}
+	/// Whether lifecycle goes from `self` to `next`, see [module docs](self)
+	pub fn can_become(self, next: Self) -> bool {
+		use ConnectionState::*;
+		self != next
+			&& matches!(
+				(self, next),
+				(_, Disconnected)
+					| (Disconnected, Enumerating)
+					| (Enumerating | Rebooting | Failed, Ready)
+					| (Enumerating | Rebooting, Failed)
+					| (Ready, Rebooting)
+			)
+	}
+	/// Where failed open leaves headset, which is on the bus, missing hidraw node means it is
+	/// still being enumerated, anything else won't go away by itself
+	pub fn after_open_error(e: &Error) -> Self {
+		match e.root() {
+			Error::DeviceNotFound | Error::Disconnected(_) => Self::Enumerating,
+			_ => Self::Failed,
+		}
+	}
+}
+impl fmt::Display for ConnectionState {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateChange {
+	pub from: ConnectionState,
+	pub to: ConnectionState,
+	/// Error which caused it, for Failed and Disconnected
+	pub reason: Option<String>,
+}
+
+struct Inner {
+	state: ConnectionState,
+	since: Option<Instant>,
+	reason: Option<String>,
+	subscribers: Vec<Sender<StateChange>>,
+}
+
+/// Lifecycle of a headset, shared by every handle of it
+pub struct Connection {
+	inner: Mutex<Inner>,
+	changed: Condvar,
+}
+impl Default for Connection {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+impl Connection {
+	pub const fn new() -> Self {
+		Self {
+			inner: Mutex::new(Inner {
+				state: ConnectionState::Disconnected,
+				since: None,
+				reason: None,
+				subscribers: Vec::new(),
+			}),
+			changed: Condvar::new(),
+		}
+	}
+
+	pub fn state(&self) -> ConnectionState {
+		self.inner.lock().expect("lock").state
+	}
+	pub fn is_ready(&self) -> bool {
+		self.state().is_ready()
+	}
+	/// Why headset is Failed or Disconnected, if it is known
+	pub fn reason(&self) -> Option<String> {
+		self.inner.lock().expect("lock").reason.clone()
+	}
+	/// Time in current state, None if it was never changed
+	pub fn since(&self) -> Option<Duration> {
+		self.inner.lock().expect("lock").since.map(|t| t.elapsed())
+	}
+	/// Receives every change since subscription, dropping receiver unsubscribes
+	pub fn subscribe(&self) -> Receiver<StateChange> {
+		let (tx, rx) = mpsc::channel();
+		self.inner.lock().expect("lock").subscribers.push(tx);
+		rx
+	}
+	/// Blocks until state matches, returns it, or None after `timeout`
+	pub fn wait_for(
+		&self,
+		timeout: Duration,
+		matches: impl Fn(ConnectionState) -> bool,
+	) -> Option<ConnectionState> {
+		let inner = self.inner.lock().expect("lock");
+		let (inner, _) = self
+			.changed
+			.wait_timeout_while(inner, timeout, |i| !matches(i.state))
+			.expect("lock");
+		matches(inner.state).then_some(inner.state)
+	}
+
+	/// Moves to state `next` picks for the current one, if lifecycle allows it
+	fn transition(
+		&self,
+		next: impl FnOnce(ConnectionState) -> Option<ConnectionState>,
+		reason: Option<String>,
+	) {
+		let mut inner = self.inner.lock().expect("lock");
+		let from = inner.state;
+		let Some(to) = next(from).filter(|&to| from.can_become(to)) else {
+			return;
+		};
+		trace_hid!(%from, %to, reason = ?reason, "connection state changed");
+		inner.state = to;
+		inner.since = Some(Instant::now());
+		inner.reason = reason.clone();
+		let change = StateChange { from, to, reason };
+		inner
+			.subscribers
+			.retain(|tx| tx.send(change.clone()).is_ok());
+		self.changed.notify_all();
+	}
+
+	/// Before opening, `present` is whether headset is on the bus
+	pub(crate) fn opening(&self, present: bool) {
+		use ConnectionState::*;
+		self.transition(
+			|state| match (state, present) {
+				// Goes off the bus on its own
+				(Rebooting, _) => None,
+				(Disconnected, true) => Some(Enumerating),
+				(_, false) => Some(Disconnected),
+				_ => None,
+			},
+			None,
+		);
+	}
+	pub(crate) fn opened(&self) {
+		// Bus may be unreadable, while hidraw is
+		self.transition(
+			|state| {
+				(state == ConnectionState::Disconnected).then_some(ConnectionState::Enumerating)
+			},
+			None,
+		);
+		self.transition(|_| Some(ConnectionState::Ready), None);
+	}
+	pub(crate) fn open_failed(&self, e: &Error) {
+		// Reconnect attempts fail until headset is back, deadline is up to the waiting side
+		self.transition(
+			|state| {
+				(state == ConnectionState::Enumerating)
+					.then(|| ConnectionState::after_open_error(e))
+			},
+			Some(e.to_string()),
+		);
+	}
+	pub(crate) fn rebooting(&self) {
+		self.transition(|_| Some(ConnectionState::Rebooting), None);
+	}
+	/// Headset didn't come back in time
+	pub(crate) fn reboot_failed(&self, e: &Error) {
+		self.transition(
+			|state| (state == ConnectionState::Rebooting).then_some(ConnectionState::Failed),
+			Some(e.to_string()),
+		);
+	}
+	/// Open handle stopped working
+	pub(crate) fn lost(&self, e: &Error) {
+		self.transition(
+			|state| state.is_ready().then_some(ConnectionState::Disconnected),
+			Some(e.to_string()),
+		);
+	}
+}
@@ -0,0 +1,401 @@
+//! What devices exchange reports over: hidapi for real hardware, the in-process simulator, or
+//! [`MockTransport`], for testing protocol logic without either
+use std::{
+	collections::VecDeque,
+	ffi::{CString, OsStr},
+	fs::{File, OpenOptions},
+	io::{self, Read},
+	os::{
+		fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+		unix::{ffi::OsStrExt, fs::OpenOptionsExt},
+	},
+	sync::{Arc, Mutex},
+};
+
+use hidapi::{HidDevice, HidError, HidResult};
+
+use crate::sim;
+
+const MAX_DISCARDED: usize = 64;
+
+/// Report level HID operations, buffers start with report id, same as with hidapi
+pub trait HidTransport {
+	fn write(&self, data: &[u8]) -> HidResult<usize>;
+	/// Returns 0 on timeout
+	fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> HidResult<usize>;
+	fn send_feature_report(&self, data: &[u8]) -> HidResult<()>;
+	fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize>;
+	/// Drops input reports, which are already there, i.e late replies to requests caller gave up
+	/// waiting for, so they aren't taken for replies to the next ones. Returns dropped count
+	fn discard_input(&self) -> HidResult<usize> {
+		let mut buf = [0u8; 64];
+		let mut count = 0;
+		// Status reports keep coming, there is no point in draining them forever
+		while count < MAX_DISCARDED && self.read_timeout(&mut buf, 0)? != 0 {
+			count += 1;
+		}
+		Ok(count)
+	}
+	/// Hidraw node, i.e `/dev/hidraw3`, which leads to usb device in sysfs
+	fn hidraw_path(&self) -> HidResult<CString> {
+		Err(HidError::HidApiError {
+			message: "device is not on usb".to_owned(),
+		})
+	}
+	/// Separate handle for input reports, which can be waited on with poll/epoll
+	fn input_events(&self) -> HidResult<InputEvents> {
+		Err(HidError::HidApiError {
+			message: "transport has no pollable input".to_owned(),
+		})
+	}
+	/// USB serial, same as devsn for headset, read without sending any report
+	fn serial_number(&self) -> HidResult<String> {
+		Err(HidError::HidApiError {
+			message: "device has no usb serial".to_owned(),
+		})
+	}
+}
+
+/// Non-blocking input of a device, its fd becomes readable once there is a report to read, so
+/// one thread (or async runtime, via `AsyncFd`) can wait for many devices
+///
+/// For hidraw it is another descriptor of the same node, which gets its own copy of every input
+/// report, so reports read here are not taken from the device handle, and the other way around
+pub struct InputEvents(Source);
+enum Source {
+	Hid(File),
+	Sim(sim::Events),
+	Mock {
+		state: Arc<Mutex<MockState>>,
+		ready: OwnedFd,
+	},
+}
+impl InputEvents {
+	/// Next report, `None` once there is nothing more to read right now
+	pub fn try_read(&mut self, buf: &mut [u8]) -> HidResult<Option<usize>> {
+		match &mut self.0 {
+			Source::Hid(file) => match file.read(buf) {
+				Ok(size) => Ok(Some(size)),
+				Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+				Err(error) => Err(HidError::IoError { error }),
+			},
+			Source::Sim(events) => events.try_read(buf),
+			Source::Mock { state, .. } => {
+				let mut state = state.lock().expect("lock");
+				Ok(state.pop_input().map(|report| copy_report(&report, buf)))
+			}
+		}
+	}
+}
+impl AsFd for InputEvents {
+	fn as_fd(&self) -> BorrowedFd<'_> {
+		match &self.0 {
+			Source::Hid(file) => file.as_fd(),
+			Source::Sim(events) => events.as_fd(),
+			Source::Mock { ready, .. } => ready.as_fd(),
+		}
+	}
+}
+impl AsRawFd for InputEvents {
+	fn as_raw_fd(&self) -> RawFd {
+		self.as_fd().as_raw_fd()
+	}
+}
+
+/// Counts down by one on every read, and is readable while it is not zero
+fn eventfd() -> io::Result<OwnedFd> {
+	let fd = unsafe {
+		libc::eventfd(
+			0,
+			libc::EFD_NONBLOCK | libc::EFD_CLOEXEC | libc::EFD_SEMAPHORE,
+		)
+	};
+	if fd < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+fn eventfd_add(fd: &OwnedFd, n: u64) {
+	let res = unsafe { libc::write(fd.as_raw_fd(), (&n as *const u64).cast(), 8) };
+	debug_assert_eq!(res, 8, "eventfd write");
+}
+fn eventfd_take(fd: &OwnedFd) {
+	let mut n = 0u64;
+	unsafe { libc::read(fd.as_raw_fd(), (&mut n as *mut u64).cast(), 8) };
+}
+
+/// Lets caller keep [`MockTransport`], to inspect it while device uses it
+impl<T: HidTransport + ?Sized> HidTransport for &T {
+	fn write(&self, data: &[u8]) -> HidResult<usize> {
+		(**self).write(data)
+	}
+	fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> HidResult<usize> {
+		(**self).read_timeout(buf, timeout_ms)
+	}
+	fn send_feature_report(&self, data: &[u8]) -> HidResult<()> {
+		(**self).send_feature_report(data)
+	}
+	fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize> {
+		(**self).get_feature_report(buf)
+	}
+	fn discard_input(&self) -> HidResult<usize> {
+		(**self).discard_input()
+	}
+	fn hidraw_path(&self) -> HidResult<CString> {
+		(**self).hidraw_path()
+	}
+	fn input_events(&self) -> HidResult<InputEvents> {
+		(**self).input_events()
+	}
+	fn serial_number(&self) -> HidResult<String> {
+		(**self).serial_number()
+	}
+}
+
+impl HidTransport for HidDevice {
+	fn write(&self, data: &[u8]) -> HidResult<usize> {
+		HidDevice::write(self, data)
+	}
+	fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> HidResult<usize> {
+		HidDevice::read_timeout(self, buf, timeout_ms)
+	}
+	fn send_feature_report(&self, data: &[u8]) -> HidResult<()> {
+		HidDevice::send_feature_report(self, data)
+	}
+	fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize> {
+		HidDevice::get_feature_report(self, buf)
+	}
+	fn hidraw_path(&self) -> HidResult<CString> {
+		Ok(self.get_device_info()?.path().to_owned())
+	}
+	fn input_events(&self) -> HidResult<InputEvents> {
+		let path = self.hidraw_path()?;
+		let file = OpenOptions::new()
+			.read(true)
+			.custom_flags(libc::O_NONBLOCK)
+			.open(OsStr::from_bytes(path.to_bytes()))
+			.map_err(|error| HidError::IoError { error })?;
+		Ok(InputEvents(Source::Hid(file)))
+	}
+	fn serial_number(&self) -> HidResult<String> {
+		self.get_device_info()?
+			.serial_number()
+			.map(str::to_owned)
+			.ok_or_else(|| HidError::HidApiError {
+				message: "device has no usb serial".to_owned(),
+			})
+	}
+}
+
+enum Backend {
+	Hid(HidDevice),
+	Sim(sim::Device),
+}
+
+/// What `open*` functions return: real device, or simulated one with `VIVEPRO2_SIMULATE`
+pub struct DefaultTransport(Backend);
+impl DefaultTransport {
+	pub(crate) fn hid(dev: HidDevice) -> Self {
+		Self(Backend::Hid(dev))
+	}
+	pub(crate) fn simulated(dev: sim::Device) -> Self {
+		Self(Backend::Sim(dev))
+	}
+	fn get(&self) -> &dyn HidTransport {
+		match &self.0 {
+			Backend::Hid(dev) => dev,
+			Backend::Sim(dev) => dev,
+		}
+	}
+}
+impl HidTransport for DefaultTransport {
+	fn write(&self, data: &[u8]) -> HidResult<usize> {
+		self.get().write(data)
+	}
+	fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> HidResult<usize> {
+		self.get().read_timeout(buf, timeout_ms)
+	}
+	fn send_feature_report(&self, data: &[u8]) -> HidResult<()> {
+		self.get().send_feature_report(data)
+	}
+	fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize> {
+		self.get().get_feature_report(buf)
+	}
+	fn hidraw_path(&self) -> HidResult<CString> {
+		self.get().hidraw_path()
+	}
+	fn input_events(&self) -> HidResult<InputEvents> {
+		match &self.0 {
+			Backend::Hid(dev) => dev.input_events(),
+			Backend::Sim(dev) => Ok(InputEvents(Source::Sim(dev.events()?))),
+		}
+	}
+	fn serial_number(&self) -> HidResult<String> {
+		self.get().serial_number()
+	}
+}
+
+/// Report, which host sent to [`MockTransport`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sent {
+	Output(Vec<u8>),
+	Feature(Vec<u8>),
+}
+
+#[derive(Default)]
+struct MockState {
+	/// Arrived before host asked for anything, see [`MockTransport::push_stale_input`]
+	stale: VecDeque<Vec<u8>>,
+	input: VecDeque<Vec<u8>>,
+	features: VecDeque<Vec<u8>>,
+	sent: Vec<Sent>,
+	/// Queued input count, once [`MockTransport::input_events`] was asked for
+	ready: Option<OwnedFd>,
+	/// See [`MockTransport::with_serial`]
+	serial: Option<String>,
+}
+impl MockState {
+	fn pop_input(&mut self) -> Option<Vec<u8>> {
+		let report = self.stale.pop_front().or_else(|| self.input.pop_front())?;
+		if let Some(ready) = &self.ready {
+			eventfd_take(ready);
+		}
+		Some(report)
+	}
+}
+
+/// In-memory device: replies are queued in advance, and everything host sends is kept,
+/// to be checked afterwards
+///
+/// Running out of queued replies is an error, not a timeout, so protocol code waiting for a reply
+/// that will never come fails instead of hanging
+///
+/// Its [`InputEvents`] are readable while there are queued input reports
+#[derive(Default)]
+pub struct MockTransport(Arc<Mutex<MockState>>);
+impl MockTransport {
+	pub fn new() -> Self {
+		Self::default()
+	}
+	/// Mock with usb serial, it has none otherwise
+	pub fn with_serial(serial: &str) -> Self {
+		let out = Self::default();
+		out.0.lock().expect("lock").serial = Some(serial.to_owned());
+		out
+	}
+	/// Queues input report, returned by next read
+	pub fn push_input(&self, report: impl Into<Vec<u8>>) {
+		let mut state = self.0.lock().expect("lock");
+		state.input.push_back(report.into());
+		if let Some(ready) = &state.ready {
+			eventfd_add(ready, 1);
+		}
+	}
+	/// Queues input report, which is already there, before the next input, like late reply of
+	/// previous request, [`HidTransport::discard_input`] drops it
+	pub fn push_stale_input(&self, report: impl Into<Vec<u8>>) {
+		let mut state = self.0.lock().expect("lock");
+		state.stale.push_back(report.into());
+		if let Some(ready) = &state.ready {
+			eventfd_add(ready, 1);
+		}
+	}
+	/// Queues feature report, returned by next get, whichever id it asks for
+	pub fn push_feature(&self, report: impl Into<Vec<u8>>) {
+		self.0
+			.lock()
+			.expect("lock")
+			.features
+			.push_back(report.into());
+	}
+	/// Everything sent since previous call
+	pub fn take_sent(&self) -> Vec<Sent> {
+		std::mem::take(&mut self.0.lock().expect("lock").sent)
+	}
+	/// Queued replies, which weren't asked for yet
+	pub fn pending(&self) -> usize {
+		let state = self.0.lock().expect("lock");
+		state.stale.len() + state.input.len() + state.features.len()
+	}
+}
+
+fn exhausted(what: &str) -> HidError {
+	HidError::HidApiError {
+		message: format!("mock has no more {what} reports"),
+	}
+}
+/// Copies as much as fits, same as hidraw does with short buffers
+fn copy_report(report: &[u8], buf: &mut [u8]) -> usize {
+	let len = report.len().min(buf.len());
+	buf[..len].copy_from_slice(&report[..len]);
+	len
+}
+
+impl HidTransport for MockTransport {
+	fn write(&self, data: &[u8]) -> HidResult<usize> {
+		let mut state = self.0.lock().expect("lock");
+		state.sent.push(Sent::Output(data.to_vec()));
+		Ok(data.len())
+	}
+	fn read_timeout(&self, buf: &mut [u8], _timeout_ms: i32) -> HidResult<usize> {
+		let report = self
+			.0
+			.lock()
+			.expect("lock")
+			.pop_input()
+			.ok_or_else(|| exhausted("input"))?;
+		Ok(copy_report(&report, buf))
+	}
+	fn send_feature_report(&self, data: &[u8]) -> HidResult<()> {
+		let mut state = self.0.lock().expect("lock");
+		state.sent.push(Sent::Feature(data.to_vec()));
+		Ok(())
+	}
+	/// Only stale reports, queued ones are what is going to arrive after the next write
+	fn discard_input(&self) -> HidResult<usize> {
+		let mut state = self.0.lock().expect("lock");
+		let count = state.stale.len();
+		for _ in 0..count {
+			state.pop_input();
+		}
+		Ok(count)
+	}
+	fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize> {
+		let report = self
+			.0
+			.lock()
+			.expect("lock")
+			.features
+			.pop_front()
+			.ok_or_else(|| exhausted("feature"))?;
+		Ok(copy_report(&report, buf))
+	}
+	/// Shares queue with [`HidTransport::read_timeout`], unlike hidraw, so each report is only
+	/// read once, by either of them
+	fn input_events(&self) -> HidResult<InputEvents> {
+		let io = |error| HidError::IoError { error };
+		let mut state = self.0.lock().expect("lock");
+		let ready = match &state.ready {
+			Some(ready) => ready,
+			None => {
+				let ready = eventfd().map_err(io)?;
+				eventfd_add(&ready, state.input.len() as u64);
+				state.ready.insert(ready)
+			}
+		};
+		Ok(InputEvents(Source::Mock {
+			state: self.0.clone(),
+			ready: ready.try_clone().map_err(io)?,
+		}))
+	}
+	fn serial_number(&self) -> HidResult<String> {
+		self.0
+			.lock()
+			.expect("lock")
+			.serial
+			.clone()
+			.ok_or_else(|| HidError::HidApiError {
+				message: "mock has no usb serial".to_owned(),
+			})
+	}
+}
@@ -0,0 +1,35 @@
+//! Simulated context opens fakes on its own, without making the process simulated
+use vive_hid::{sim, Error, ViveContext};
+
+#[test]
+fn simulated_context_is_isolated() {
+	let ctx = ViveContext::simulated();
+	assert!(ctx.is_simulated());
+	let vive = ctx.open_vive().expect("open headset");
+	assert_eq!(vive.read_devsn().expect("devsn"), sim::DEVSN);
+	let steam = ctx
+		.open_steam_serial(sim::STEAM_SERIAL)
+		.expect("open tracking board");
+	let config = steam.read_config().expect("steam config");
+	assert_eq!(config.mb_serial_number, sim::DEVSN);
+	ctx.open_vive_paired(sim::STEAM_SERIAL)
+		.expect("open paired headset");
+
+	assert!(!sim::enabled());
+	assert!(!ViveContext::global().is_simulated());
+	assert!(!ViveContext::new().is_simulated());
+}
+
+#[test]
+fn simulated_context_serials() {
+	let ctx = ViveContext::simulated();
+	assert!(matches!(
+		ctx.open_vive_serial("SIM999999999"),
+		Err(Error::DeviceNotFound)
+	));
+	assert!(matches!(
+		ctx.open_vive_paired("LHR-99999999"),
+		Err(Error::DeviceNotFound)
+	));
+	assert!(ctx.open_vive_serial(sim::DEVSN).is_ok());
+}
@@ -0,0 +1,59 @@
+//! Status through pollable handles, as an event loop would wait for it
+use std::os::fd::{AsRawFd, RawFd};
+
+use vive_hid::{MainboardStatus, MockTransport, ViveContext, ViveDevice};
+use vive_protocol::encode_mainboard_status;
+
+const STATUS: MainboardStatus = MainboardStatus {
+	lens_separation: 6400,
+	button: 0,
+	proximity_change: 0,
+	proximity: 900,
+	ipd: 6400,
+};
+
+fn readable(fd: RawFd, timeout_ms: i32) -> bool {
+	let mut pfd = libc::pollfd {
+		fd,
+		events: libc::POLLIN,
+		revents: 0,
+	};
+	let res = unsafe { libc::poll(&mut pfd, 1, timeout_ms) };
+	assert!(res >= 0, "poll failed");
+	res == 1 && pfd.revents & libc::POLLIN != 0
+}
+
+#[test]
+fn mock_status_events() {
+	let mock = MockTransport::new();
+	mock.push_input(encode_mainboard_status(&STATUS));
+	let dev = ViveDevice::with_transport(&mock);
+	let mut events = dev.status_events().expect("events");
+	// Queued before events were asked for
+	assert!(readable(events.as_raw_fd(), 0));
+	assert_eq!(events.try_next().expect("read"), Some(STATUS));
+	assert!(!readable(events.as_raw_fd(), 0));
+	assert_eq!(events.try_next().expect("read"), None);
+
+	// Reply is skipped, status after it isn't
+	mock.push_input([0x02, 0x01, b'x']);
+	mock.push_input(encode_mainboard_status(&STATUS));
+	assert!(readable(events.as_raw_fd(), 0));
+	assert_eq!(events.latest().expect("read"), Some(STATUS));
+	assert!(!readable(events.as_raw_fd(), 0));
+
+	// Queue is shared, what blocking read took is not readable anymore
+	mock.push_input(encode_mainboard_status(&STATUS));
+	assert_eq!(dev.read_status(0).expect("read"), Some(STATUS));
+	assert!(!readable(events.as_raw_fd(), 0));
+	assert_eq!(mock.pending(), 0);
+}
+
+#[test]
+fn simulated_status_events() {
+	let dev = ViveContext::simulated().open_vive().expect("open");
+	let mut events = dev.status_events().expect("events");
+	assert!(readable(events.as_raw_fd(), 1000));
+	let status = events.try_next().expect("read").expect("status");
+	assert_eq!(status.ipd, 6300);
+}
@@ -0,0 +1,28 @@
+use std::{fs, path::Path};
+
+use vive_hid::power::usb_power_in;
+
+/// `devices` has symlinks into `pci`, same as /sys/bus/usb/devices
+fn fixture() -> std::path::PathBuf {
+	Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/usb")
+}
+
+#[test]
+fn devices_under_hub() {
+	let root = fixture();
+	let hub = fs::canonicalize(root.join("pci/usb1/1-2")).unwrap();
+	let devices = usb_power_in(&root.join("devices"), &hub).unwrap();
+	// Interface 1-2.1:1.0 is skipped, 1-3 is not under the hub
+	let names = devices.iter().map(|d| d.name.as_str()).collect::<Vec<_>>();
+	assert_eq!(names, ["1-2", "1-2.1", "1-2.2"]);
+	let enabled = devices
+		.iter()
+		.filter(|d| d.autosuspend)
+		.map(|d| d.label())
+		.collect::<Vec<_>>();
+	assert_eq!(
+		enabled,
+		["1-2 (USB2.0 Hub)", "1-2.1 (HTC Multimedia Device)"]
+	);
+	assert_eq!(devices[2].label(), "1-2.2");
+}
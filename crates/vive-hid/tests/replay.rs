@@ -0,0 +1,167 @@
+//! Replays every trace in `tests/traces` (or in `$VIVEPRO2_TRACES`, for local traces of real
+//! devices, which may contain serials and aren't committed)
+//!
+//! Traces are captures made with `VIVEPRO2_HID_CAPTURE=<device>-<operation>[-<arg>].json`, i.e
+//! `VIVEPRO2_HID_CAPTURE=vive-set_brightness-80.json vivectl brightness 80`
+use std::{
+	env,
+	path::{Path, PathBuf},
+};
+
+use vive_hid::{replay::Trace, HidTransport, MockTransport, SteamDevice, ViveDevice};
+use vive_protocol::Report02Command;
+
+fn traces() -> Vec<PathBuf> {
+	let dir = env::var_os("VIVEPRO2_TRACES")
+		.map(PathBuf::from)
+		.unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/traces"));
+	let mut out = dir
+		.read_dir()
+		.unwrap_or_else(|e| panic!("failed to list {dir:?}: {e}"))
+		.map(|e| e.expect("dir entry").path())
+		.filter(|p| p.extension().is_some_and(|e| e == "json"))
+		.collect::<Vec<_>>();
+	out.sort();
+	out
+}
+
+fn vive(op: &str, arg: Option<&str>, dev: &ViveDevice<impl HidTransport>) -> Result<(), String> {
+	let res = match (op, arg) {
+		("read_config", None) => dev.read_config().map(drop),
+		("read_config_raw", None) => dev.read_config_raw().map(drop),
+		("read_devsn", None) => dev.read_devsn().map(drop),
+		("identity", None) => dev.identity().map(drop),
+		("read_user_config", None) => dev.read_user_config().map(drop),
+		("set_brightness", Some(v)) => dev.set_brightness(v.parse().expect("brightness")),
+		("set_mode", Some(v)) => dev.set_mode(v.parse().expect("mode")),
+		("toggle_noise_canceling", Some(v)) => dev.toggle_noise_canceling(v == "on"),
+		_ => panic!("unknown vive operation {op}"),
+	};
+	res.map_err(|e| e.to_string())
+}
+
+fn steam(op: &str, dev: &SteamDevice<impl HidTransport>) -> Result<(), String> {
+	let res = match op {
+		"read_config" => dev.read_config().map(drop),
+		"read_config_raw" => dev.read_config_raw().map(drop),
+		_ => panic!("unknown steam operation {op}"),
+	};
+	res.map_err(|e| e.to_string())
+}
+
+#[test]
+fn replay_traces() {
+	let traces = traces();
+	assert!(!traces.is_empty(), "no traces to replay");
+	let mut failed = Vec::new();
+	for path in traces {
+		let name = path.file_stem().and_then(|s| s.to_str()).expect("name");
+		let mut parts = name.splitn(3, '-');
+		let (device, op, arg) = (
+			parts.next().expect("device"),
+			parts.next().expect("operation"),
+			parts.next(),
+		);
+		let trace = Trace::load(&path).expect("trace");
+		let mock: MockTransport = trace.mock(device);
+		let res = match device {
+			"vive" => vive(op, arg, &ViveDevice::with_transport(&mock)),
+			"steam" => steam(op, &SteamDevice::with_transport(&mock)),
+			_ => panic!("unknown device in {name}"),
+		}
+		.and_then(|()| trace.check(device, &mock).map_err(|e| e.to_string()));
+		if let Err(e) = res {
+			failed.push(format!("{name}: {e}"));
+		}
+	}
+	assert!(failed.is_empty(), "{}", failed.join("\n"));
+}
+
+/// Recent reports of a handle are a trace of their own, which replays the same way
+#[test]
+fn recent_reports_replay() {
+	let path =
+		Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/traces/vive-set_brightness-80.json");
+	let trace = Trace::load(&path).expect("trace");
+	let mock = trace.mock("vive");
+	let dev = ViveDevice::with_transport(&mock);
+	vive("set_brightness", Some("80"), &dev).expect("replay");
+
+	let recent = Trace::parse(&dev.dump_recent()).expect("recent reports");
+	let mock = recent.mock("vive");
+	vive(
+		"set_brightness",
+		Some("80"),
+		&ViveDevice::with_transport(&mock),
+	)
+	.expect("replay recent");
+	recent.check("vive", &mock).expect("same reports");
+}
+
+/// Failed reads are kept with their error, and skipped when replaying
+#[test]
+fn recent_reports_failed_read() {
+	let mock = MockTransport::new();
+	let dev = ViveDevice::with_transport(&mock);
+	assert!(dev.read_devsn().is_err());
+	let recent = dev.dump_recent();
+	let failed = recent.lines().last().expect("failed read");
+	assert!(
+		failed.contains("mock has no more input reports"),
+		"{failed}"
+	);
+	let trace = Trace::parse(&recent).expect("recent reports");
+	assert_eq!(trace.mock("vive").pending(), 0);
+}
+
+/// Optional identity reads, which firmware doesn't answer, are left out, required one is not
+#[test]
+fn identity_unanswered_reads() {
+	let mock = MockTransport::new();
+	mock.push_input(Report02Command::encode_reply(b"SN0000000000").expect("reply"));
+	// Other report before reply is skipped, then empty and padded replies
+	mock.push_input([0x01, 0x00]);
+	mock.push_input(Report02Command::encode_reply(b"").expect("reply"));
+	mock.push_input(Report02Command::encode_reply(b"").expect("reply"));
+	mock.push_input(Report02Command::encode_reply(b" EU\0").expect("reply"));
+	mock.push_input(Report02Command::encode_reply(b"PNL000000000").expect("reply"));
+	mock.push_input([0x02, 0x02, 0xff, 0xfe]);
+	let dev = ViveDevice::with_transport(&mock);
+	let identity = dev.identity().expect("identity");
+	assert_eq!(identity.device_sn, "SN0000000000");
+	assert_eq!(identity.board_sn, None);
+	assert_eq!(identity.model, None);
+	assert_eq!(identity.region.as_deref(), Some("EU"));
+	assert_eq!(identity.panel_sn.as_deref(), Some("PNL000000000"));
+	// Not UTF-8
+	assert_eq!(identity.panel_date, None);
+	assert_eq!(mock.pending(), 0);
+
+	mock.push_input([0x01, 0x00]);
+	assert!(dev.identity().is_err());
+}
+
+/// Late reply of a read, which was given up on, isn't taken for reply of the next one
+#[test]
+fn late_reply_is_dropped() {
+	let mock = MockTransport::new();
+	mock.push_stale_input(Report02Command::encode_reply(b"EU").expect("reply"));
+	mock.push_input(Report02Command::encode_reply(b"SN0000000000").expect("reply"));
+	let dev = ViveDevice::with_transport(&mock);
+	assert_eq!(dev.read_devsn().expect("devsn"), "SN0000000000");
+	assert_eq!(mock.pending(), 0);
+}
+
+/// Counters are decimal, anything else is left out
+#[test]
+fn usage_stats_counters() {
+	let mock = MockTransport::new();
+	for reply in [&b"3600"[..], b"12h", b""] {
+		mock.push_input(Report02Command::encode_reply(reply).expect("reply"));
+	}
+	let dev = ViveDevice::with_transport(&mock);
+	let stats = dev.usage_stats().expect("stats");
+	assert_eq!(stats.uptime, Some(std::time::Duration::from_secs(3600)));
+	assert_eq!(stats.power_on_hours, None);
+	assert_eq!(stats.power_cycles, None);
+}
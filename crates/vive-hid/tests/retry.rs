@@ -0,0 +1,95 @@
+//! Which failures config reads retry, which they give up on right away, and what is reported
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use hidapi::{HidError, HidResult};
+use vive_hid::{Error, HidTransport, MockTransport, SteamDevice, ViveDevice};
+use vive_protocol::{Report01Request, FACTORY_CONFIG_REGION};
+
+/// Fails every feature report read the same way, as hidraw backend reports it
+struct Failing {
+	message: &'static str,
+	reads: AtomicUsize,
+}
+impl Failing {
+	fn new(message: &'static str) -> Self {
+		Self {
+			message,
+			reads: AtomicUsize::new(0),
+		}
+	}
+}
+impl HidTransport for Failing {
+	fn write(&self, data: &[u8]) -> HidResult<usize> {
+		Ok(data.len())
+	}
+	fn read_timeout(&self, _buf: &mut [u8], _timeout_ms: i32) -> HidResult<usize> {
+		Ok(0)
+	}
+	fn send_feature_report(&self, _data: &[u8]) -> HidResult<()> {
+		Ok(())
+	}
+	fn get_feature_report(&self, _buf: &mut [u8]) -> HidResult<usize> {
+		self.reads.fetch_add(1, Ordering::Relaxed);
+		Err(HidError::HidApiError {
+			message: self.message.to_owned(),
+		})
+	}
+}
+
+fn read(message: &'static str) -> (Error, usize) {
+	let transport = Failing::new(message);
+	let e = SteamDevice::with_transport(&transport)
+		.read_config_raw()
+		.expect_err("read should fail");
+	(e, transport.reads.load(Ordering::Relaxed))
+}
+
+#[test]
+fn transient_errors_are_retried() {
+	for message in [
+		"ioctl (GFEATURE): Broken pipe",
+		"ioctl (GFEATURE): Connection timed out",
+	] {
+		let (e, reads) = read(message);
+		assert!(
+			matches!(e, Error::ConfigReadFailed { attempts: 7, .. }),
+			"{message}: {e}"
+		);
+		assert_eq!(reads, 7, "{message}");
+	}
+}
+
+#[test]
+fn missing_device_fails_right_away() {
+	let (e, reads) = read("ioctl (GFEATURE): No such device");
+	assert!(matches!(e, Error::Disconnected(_)), "{e}");
+	assert_eq!(reads, 1);
+}
+
+#[test]
+fn permission_problems_fail_right_away() {
+	let (e, reads) = read("ioctl (GFEATURE): Permission denied");
+	assert!(matches!(e, Error::PermissionDenied(_)), "{e}");
+	assert_eq!(reads, 1);
+	assert!(e.to_string().contains("udev rules"));
+}
+
+#[test]
+fn failed_chunk_is_named() {
+	let region = FACTORY_CONFIG_REGION;
+	let mock = MockTransport::new();
+	let size = Report01Request::RegionSize { region };
+	mock.push_input(size.encode_reply(&600u32.to_le_bytes()).expect("reply"));
+	let read = Report01Request::RegionRead { region, offset: 0 };
+	mock.push_input(read.encode_reply(&[0; 60]).expect("reply"));
+	let e = ViveDevice::with_transport(&mock)
+		.read_config()
+		.expect_err("replies run out");
+	assert!(
+		e.to_string()
+			.starts_with(&format!("while reading region {region:02x} chunk 2/10: ")),
+		"{e}"
+	);
+	assert!(matches!(e.root(), Error::Hid(_)), "{e}");
+	assert!(std::error::Error::source(&e).is_some());
+}
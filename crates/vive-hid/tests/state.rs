@@ -0,0 +1,68 @@
+//! Headset lifecycle, as handles report it, with simulated headset, which takes seconds to
+//! come back after mode switch
+use std::time::Duration;
+
+use vive_hid::{sim, ConnectionState, Resolution, ViveContext, ViveDevice};
+
+#[test]
+fn transitions() {
+	use ConnectionState::*;
+	let allowed = [
+		(Disconnected, Enumerating),
+		(Enumerating, Ready),
+		(Enumerating, Failed),
+		(Ready, Rebooting),
+		(Rebooting, Ready),
+		(Rebooting, Failed),
+		(Failed, Ready),
+	];
+	for from in ConnectionState::ALL {
+		for to in ConnectionState::ALL {
+			let expected = from != to && (to == Disconnected || allowed.contains(&(from, to)));
+			assert_eq!(from.can_become(to), expected, "{from} -> {to}");
+		}
+	}
+}
+
+#[test]
+fn switch_lifecycle() {
+	use ConnectionState::*;
+	sim::enable(None).expect("simulate");
+	let connection = ViveContext::global().connection();
+	let changes = connection.subscribe();
+	assert_eq!(connection.state(), Disconnected);
+
+	let dev = ViveDevice::open_first().expect("open");
+	assert!(connection.is_ready());
+	let mode = Resolution::R2448x1224f90 as u8;
+	assert!(dev
+		.set_resolution_and_wait(mode, Duration::from_secs(1))
+		.is_err());
+	assert_eq!(connection.state(), Failed);
+	assert!(connection.reason().is_some());
+	// Late headset is still picked up
+	assert!(dev
+		.set_resolution_and_wait(mode, Duration::from_secs(10))
+		.expect("same mode")
+		.is_none());
+	ViveDevice::wait_reconnect(Duration::from_secs(10)).expect("headset is back");
+	assert_eq!(
+		connection.wait_for(Duration::ZERO, ConnectionState::is_ready),
+		Some(Ready)
+	);
+
+	let seen = changes
+		.try_iter()
+		.map(|c| (c.from, c.to))
+		.collect::<Vec<_>>();
+	assert_eq!(
+		seen,
+		[
+			(Disconnected, Enumerating),
+			(Enumerating, Ready),
+			(Ready, Rebooting),
+			(Rebooting, Failed),
+			(Failed, Ready),
+		]
+	);
+}
@@ -0,0 +1,156 @@
+//! Mode switch deadlines, one test, as switch cooldown is shared by the whole process for each
+//! headset, cooldown of different headsets, and link checks, which go before cooldown, with mode
+//! picked by link for first-time users
+use std::time::{Duration, Instant};
+
+use vive_display::link::{LinkCaps, LinkConfig};
+use vive_hid::{
+	recommend_resolution, sim, Error, GpuTier, MockTransport, Resolution, SwitchStage, ViveDevice,
+};
+
+#[test]
+fn switch_deadlines() {
+	let mode = Resolution::R2448x1224f90 as u8;
+
+	// Nothing is written once the budget is gone
+	let mock = MockTransport::new();
+	let dev = ViveDevice::with_transport(&mock);
+	assert!(matches!(
+		dev.set_resolution(mode, Duration::ZERO),
+		Err(Error::SwitchTimeout {
+			stage: SwitchStage::Write
+		})
+	));
+	assert!(mock.take_sent().is_empty());
+
+	// Simulated headset takes seconds to come back
+	sim::enable(None).expect("simulate");
+	let dev = ViveDevice::open_first().expect("open");
+	let start = Instant::now();
+	assert!(matches!(
+		dev.set_resolution_and_wait(mode, Duration::from_secs(1)),
+		Err(Error::SwitchTimeout {
+			stage: SwitchStage::Reconnect
+		})
+	));
+	assert!(start.elapsed() < Duration::from_secs(2));
+
+	// Same switch is not repeated, and there is nothing to reconnect for
+	let start = Instant::now();
+	assert!(dev
+		.set_resolution_and_wait(mode, Duration::from_secs(10))
+		.expect("same mode")
+		.is_none());
+	assert!(start.elapsed() < Duration::from_secs(1));
+	let back = ViveDevice::wait_reconnect(Duration::from_secs(10)).expect("headset is back");
+	assert_eq!(back.read_devsn().expect("devsn"), sim::DEVSN);
+	// Handle which is back is kept as it is
+	assert!(back
+		.set_resolution_and_wait(mode, Duration::from_secs(10))
+		.expect("same mode")
+		.is_none());
+	assert_eq!(back.read_devsn().expect("devsn"), sim::DEVSN);
+	assert!(matches!(
+		back.set_resolution(Resolution::R4896x2448f120 as u8, Duration::from_secs(1)),
+		Err(Error::ModeSwitchInProgress)
+	));
+}
+
+/// Serials are only used here, so other tests don't share their cooldown
+#[test]
+fn cooldown_per_headset() {
+	let (a, b) = (
+		MockTransport::with_serial("COOLDOWN-A"),
+		MockTransport::with_serial("COOLDOWN-B"),
+	);
+	let (dev_a, dev_b) = (
+		ViveDevice::with_transport(&a),
+		ViveDevice::with_transport(&b),
+	);
+	let (low, high) = (
+		Resolution::R2448x1224f90 as u8,
+		Resolution::R4896x2448f120 as u8,
+	);
+	dev_a.set_mode(low).expect("switch a");
+	assert!(!a.take_sent().is_empty());
+	// Other headset isn't affected
+	dev_b.set_mode(high).expect("switch b");
+	assert!(!b.take_sent().is_empty());
+	// Same headset, through another handle, as after re-enumeration
+	let again = MockTransport::with_serial("COOLDOWN-A");
+	assert!(matches!(
+		ViveDevice::with_transport(&again).set_mode(high),
+		Err(Error::ModeSwitchInProgress)
+	));
+	ViveDevice::with_transport(&again)
+		.set_mode(low)
+		.expect("same mode");
+	assert!(again.take_sent().is_empty());
+}
+
+#[test]
+fn link_check() {
+	// HBR2 x2
+	let link = LinkCaps {
+		verified: Some(LinkConfig {
+			lanes: 2,
+			rate: 0x14,
+		}),
+		sink_dsc: Some(true),
+		..Default::default()
+	};
+	let mock = MockTransport::new();
+	let dev = ViveDevice::with_transport(&mock).with_link_check(link.clone());
+	assert!(matches!(
+		dev.set_resolution(Resolution::R4896x2448f120 as u8, Duration::from_secs(1)),
+		Err(Error::ModeNotAchievable {
+			available: 8640,
+			..
+		})
+	));
+	assert!(mock.take_sent().is_empty());
+
+	// Without DSC even the lowest mode doesn't fit
+	let link = LinkCaps {
+		sink_dsc: Some(false),
+		..link
+	};
+	assert!(vive_hid::check_link(&link, Resolution::R2448x1224f120 as u8).is_err());
+	assert!(vive_hid::check_link(&link, Resolution::R2448x1224f90 as u8).is_ok());
+	// Nothing is known
+	assert!(vive_hid::check_link(&LinkCaps::default(), Resolution::R4896x2448f120 as u8).is_ok());
+}
+
+fn link(lanes: u8, rate: u8) -> LinkCaps {
+	LinkCaps {
+		verified: Some(LinkConfig { lanes, rate }),
+		..Default::default()
+	}
+}
+
+#[test]
+fn recommend_by_link_and_gpu() {
+	use Resolution::*;
+	// Unknown link is taken as HBR3 x4 without DSC
+	let unknown = LinkCaps::default();
+	assert_eq!(recommend_resolution(&unknown, GpuTier::High), R3672x1836f90);
+	assert_eq!(recommend_resolution(&unknown, GpuTier::Mid), R3672x1836f90);
+	assert_eq!(recommend_resolution(&unknown, GpuTier::Low), R2448x1224f120);
+
+	let dsc = LinkCaps {
+		dsc_enabled: Some(true),
+		..link(4, 0x1e)
+	};
+	assert_eq!(recommend_resolution(&dsc, GpuTier::High), R4896x2448f120);
+	assert_eq!(recommend_resolution(&dsc, GpuTier::Mid), R3672x1836f90);
+
+	// HBR x2 fits nothing, lowest mode is still picked
+	assert_eq!(
+		recommend_resolution(&link(2, 0x0a), GpuTier::High),
+		R2448x1224f90
+	);
+	assert_eq!(
+		recommend_resolution(&link(4, 0x14), GpuTier::High),
+		R3672x1836f90
+	);
+}
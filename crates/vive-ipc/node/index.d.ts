@@ -0,0 +1,58 @@
+import { EventEmitter } from 'events';
+
+export interface Settings {
+	resolution?: number;
+	brightness?: number;
+	noise_cancel?: boolean;
+	cameras?: boolean;
+}
+
+export interface Status {
+	connected: boolean;
+	serial: string | null;
+	settings: Settings;
+	/** Driver uses approximate lens distortion, as lens server has failed to start */
+	lens_fallback: boolean;
+	/** Display is blanked by standby policy */
+	standby: boolean;
+}
+
+export interface FrameTiming {
+	[field: string]: number;
+}
+
+/** Tagged by `event`, i.e `{ event: 'ipd', ipd: 6300 }`, see `Event` of vive-ipc crate */
+export type Event =
+	| { event: 'connected'; serial: string | null }
+	| { event: 'disconnected' }
+	| { event: 'ipd'; ipd: number }
+	| { event: 'proximity'; near: boolean }
+	| { event: 'mode'; resolution: number }
+	| { event: 'session'; running: boolean }
+	| { event: 'base_stations'; on: boolean }
+	| { event: 'mode_applied'; resolution: number }
+	| { event: 'mode_fallback'; requested: number; resolution: number; reason: string }
+	| { event: 'lens_server_restarted' };
+
+export declare class RemoteError extends Error {}
+
+export declare class Client {
+	static connect(path?: string): Promise<Client>;
+	static connectDriver(): Promise<Client | null>;
+	static connectAny(): Promise<Client>;
+
+	request(request: { command: string; [field: string]: unknown }): Promise<{ result: string }>;
+	status(): Promise<Status>;
+	frameTiming(): Promise<FrameTiming>;
+	profiles(): Promise<{ active: string | null; profiles: Record<string, Settings> }>;
+	setProfile(name: string): Promise<void>;
+	setResolution(resolution: number): Promise<void>;
+	setBrightness(brightness: number): Promise<void>;
+	setNoiseCancel(enabled: boolean): Promise<void>;
+	setBaseStations(on: boolean): Promise<void>;
+	subscribe(): Promise<EventEmitter>;
+	close(): void;
+}
+
+export declare function socketPath(): string;
+export declare function driverSocketPaths(): string[];
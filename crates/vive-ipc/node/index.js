@@ -0,0 +1,165 @@
+// Client of vived control socket, same protocol as crates/vive-ipc: a line of JSON per message.
+// Plain socket client, so there is no native module to rebuild for every Electron version
+'use strict';
+
+const net = require('net');
+const os = require('os');
+const path = require('path');
+const fs = require('fs');
+const { EventEmitter } = require('events');
+
+const SOCKET_NAME = 'vived.sock';
+const DRIVER_SOCKET_NAME = 'vivepro2-driver.sock';
+const STEAM_FLATPAK_ID = 'com.valvesoftware.Steam';
+
+function runtimePath(name) {
+	return path.join(process.env.XDG_RUNTIME_DIR || os.tmpdir(), name);
+}
+/** `$XDG_RUNTIME_DIR/vived.sock` */
+function socketPath() {
+	return runtimePath(SOCKET_NAME);
+}
+/** Every path, at which driver of native or Flatpak Steam may listen */
+function driverSocketPaths() {
+	return [
+		runtimePath(DRIVER_SOCKET_NAME),
+		runtimePath(path.join('app', STEAM_FLATPAK_ID, DRIVER_SOCKET_NAME)),
+	];
+}
+
+class RemoteError extends Error {}
+
+class Client {
+	constructor(socket) {
+		this._socket = socket;
+		this._buffer = '';
+		// Answers come in order of requests
+		this._pending = [];
+		this._events = null;
+		socket.setEncoding('utf8');
+		socket.on('data', (data) => this._data(data));
+		socket.on('error', (e) => this._fail(e));
+		socket.on('close', () => {
+			this._fail(new Error('connection closed'));
+			if (this._events) this._events.emit('close');
+		});
+	}
+
+	/** Connects to socket at `path`, vived one by default */
+	static connect(path = socketPath()) {
+		return new Promise((resolve, reject) => {
+			const socket = net.createConnection(path);
+			socket.once('connect', () => resolve(new Client(socket)));
+			socket.once('error', reject);
+		});
+	}
+	/** Driver is only there during SteamVR session, resolves to `null` otherwise */
+	static async connectDriver() {
+		for (const path of driverSocketPaths()) {
+			if (!fs.existsSync(path)) continue;
+			try {
+				return await Client.connect(path);
+			} catch (e) {
+				// Stale socket of crashed SteamVR
+			}
+		}
+		return null;
+	}
+	/** Driver during SteamVR session, as it holds the headset then, vived otherwise */
+	static async connectAny() {
+		return (await Client.connectDriver()) || Client.connect();
+	}
+
+	_data(data) {
+		this._buffer += data;
+		let end;
+		while ((end = this._buffer.indexOf('\n')) >= 0) {
+			const line = this._buffer.slice(0, end);
+			this._buffer = this._buffer.slice(end + 1);
+			let message;
+			try {
+				message = JSON.parse(line);
+			} catch (e) {
+				this._fail(new Error(`malformed message: ${e.message}`));
+				continue;
+			}
+			if (this._events && this._pending.length === 0) {
+				this._events.emit('event', message);
+			} else if (this._pending.length) {
+				this._pending.shift().resolve(message);
+			}
+		}
+	}
+	_fail(e) {
+		for (const { reject } of this._pending.splice(0)) reject(e);
+	}
+
+	/** Sends raw request, rejects with `RemoteError`, if daemon has reported failure */
+	request(request) {
+		if (this._events) return Promise.reject(new Error('connection is subscribed to events'));
+		return new Promise((resolve, reject) => {
+			this._pending.push({
+				resolve: (response) => {
+					if (response.result === 'error') reject(new RemoteError(response.message));
+					else resolve(response);
+				},
+				reject,
+			});
+			this._socket.write(JSON.stringify(request) + '\n');
+		});
+	}
+	async _expect(request, result) {
+		const response = await this.request(request);
+		if (response.result !== result) throw new Error('unexpected response');
+		return response;
+	}
+
+	async status() {
+		const { result, ...status } = await this._expect({ command: 'status' }, 'status');
+		return status;
+	}
+	/** Only served by driver */
+	async frameTiming() {
+		const { result, ...timing } = await this._expect({ command: 'frame_timing' }, 'frame_timing');
+		return timing;
+	}
+	async profiles() {
+		const { active, profiles } = await this._expect({ command: 'profiles' }, 'profiles');
+		return { active, profiles };
+	}
+	async setProfile(name) {
+		await this._expect({ command: 'set_profile', name }, 'ok');
+	}
+	/** Mode id, as listed by `vivectl resolution`, headset reconnects to apply it */
+	async setResolution(resolution) {
+		await this._expect({ command: 'set_resolution', resolution }, 'ok');
+	}
+	/** 1-130 */
+	async setBrightness(brightness) {
+		await this._expect({ command: 'set_brightness', brightness }, 'ok');
+	}
+	async setNoiseCancel(enabled) {
+		await this._expect({ command: 'set_noise_cancel', enabled: Boolean(enabled) }, 'ok');
+	}
+	/** Until headset is reconnected */
+	async setBaseStations(on) {
+		await this._expect({ command: 'set_base_stations', on: Boolean(on) }, 'ok');
+	}
+
+	/**
+	 * Emits `event` for every headset state change, and `close`, once daemon is gone.
+	 * Connection can't be used for requests afterwards
+	 */
+	async subscribe() {
+		await this._expect({ command: 'subscribe' }, 'ok');
+		this._events = new EventEmitter();
+		return this._events;
+	}
+
+	/** Subscribed connections are never closed by daemon */
+	close() {
+		this._socket.destroy();
+	}
+}
+
+module.exports = { Client, RemoteError, socketPath, driverSocketPaths };
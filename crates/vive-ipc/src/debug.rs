@@ -0,0 +1,79 @@
+//! Commands of driver-proxy debug handler, for introspecting a live session
+//!
+//! Served as `DebugRequest` of the HMD, so anything with SteamVR client API
+//! (`IVRSystem::DriverDebugRequest`) may use it, and through driver socket as
+//! [`crate::Request::Debug`], which is what `vivectl debug` does. Requests, which aren't
+//! understood, are passed to lighthouse driver
+use serde::{Deserialize, Serialize};
+
+use crate::{FrameTiming, Settings};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum DebugRequest {
+	State,
+	Counters,
+	/// Same as changing `vivepro2.resolution`, switch happens in background
+	SetMode {
+		mode: u8,
+	},
+	/// SteamVR performance graph in headset, toggled if `enabled` is not set
+	Hud {
+		#[serde(default)]
+		enabled: Option<bool>,
+	},
+	/// Last HID reports of headset handle. vived answers it for its own handle, instead of
+	/// passing it to driver
+	RecentReports,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum DebugResponse {
+	Ok,
+	State(DebugState),
+	Counters(Counters),
+	Hud {
+		enabled: bool,
+	},
+	/// Lines of JSON capture, same as `VIVEPRO2_HID_CAPTURE=<path>.json` writes
+	RecentReports {
+		reports: String,
+	},
+	Error {
+		message: String,
+	},
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DebugState {
+	/// Tracking board serial
+	pub serial: String,
+	pub devsn: Option<String>,
+	pub mode: u8,
+	pub width: u32,
+	pub height: u32,
+	pub frame_rate: f32,
+	pub switching: bool,
+	/// Name of `vive_hid::ConnectionState`
+	#[serde(default)]
+	pub state: String,
+	pub standby: bool,
+	/// HMD is activated by SteamVR
+	pub activated: bool,
+	/// Last applied to the headset
+	pub settings: Settings,
+}
+
+/// Since driver start
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Counters {
+	pub mode_switches: u64,
+	/// Mode has failed, and headset fell back to the safe one, or hasn't come back at all
+	pub failed_mode_switches: u64,
+	pub standby_entries: u64,
+	pub ipd_changes: u64,
+	pub debug_requests: u64,
+	/// Over last frames, not since start
+	pub frame_timing: FrameTiming,
+}
@@ -0,0 +1,98 @@
+//! Last used settings of every headset, by serial
+//!
+//! Shared between vived and driver-proxy, so settings follow the headset
+//! regardless of who had it when they were changed
+use std::{
+	collections::BTreeMap,
+	env, fs, io,
+	path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{in_flatpak, Result, Settings};
+
+/// `$XDG_CONFIG_HOME/vivepro2`
+///
+/// Flatpak points `XDG_CONFIG_HOME` to app's own directory, host one is used there instead,
+/// it is exposed by `vivectl setup flatpak`
+pub fn config_dir() -> PathBuf {
+	let home = || Path::new(&env::var_os("HOME").unwrap_or_default()).join(".config");
+	let base = match env::var_os("XDG_CONFIG_HOME") {
+		Some(_) if in_flatpak() => home(),
+		Some(dir) => PathBuf::from(dir),
+		None => home(),
+	};
+	base.join("vivepro2")
+}
+/// `$XDG_CONFIG_HOME/vivepro2/headsets.json`
+pub fn headsets_path() -> PathBuf {
+	config_dir().join("headsets.json")
+}
+/// `$XDG_CONFIG_HOME/vivepro2/good-modes.json`
+pub fn good_modes_path() -> PathBuf {
+	config_dir().join("good-modes.json")
+}
+
+fn load_all<T: DeserializeOwned>(path: &Path) -> Result<BTreeMap<String, T>> {
+	match fs::read_to_string(path) {
+		Ok(data) => Ok(serde_json::from_str(&data)?),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// `None` if this headset was never seen
+pub fn load(serial: &str) -> Result<Option<Settings>> {
+	Ok(load_all(&headsets_path())?.remove(serial))
+}
+
+/// Merges set fields of `settings` into stored ones
+pub fn update(serial: &str, settings: &Settings) -> Result<()> {
+	let path = headsets_path();
+	let mut all = load_all::<Settings>(&path)?;
+	all.entry(serial.to_owned()).or_default().merge(settings);
+	save_all(&path, &all)
+}
+
+/// Forgets headset, returns whether it was known
+pub fn remove(serial: &str) -> Result<bool> {
+	let path = headsets_path();
+	let mut all = load_all::<Settings>(&path)?;
+	if all.remove(serial).is_none() {
+		return Ok(false);
+	}
+	save_all(&path, &all)?;
+	let path = good_modes_path();
+	let mut modes = load_all::<u8>(&path)?;
+	if modes.remove(serial).is_some() {
+		save_all(&path, &modes)?;
+	}
+	Ok(true)
+}
+
+/// Last mode headset came back with after switching to it, which is where failed switches roll
+/// back to. Stored apart from settings, as their mode is the requested one, which may not work
+pub fn last_good_mode(serial: &str) -> Result<Option<u8>> {
+	Ok(load_all(&good_modes_path())?.remove(serial))
+}
+
+pub fn mark_good_mode(serial: &str, mode: u8) -> Result<()> {
+	let path = good_modes_path();
+	let mut all = load_all(&path)?;
+	if all.insert(serial.to_owned(), mode) == Some(mode) {
+		return Ok(());
+	}
+	save_all(&path, &all)
+}
+
+fn save_all<T: Serialize>(path: &Path, all: &BTreeMap<String, T>) -> Result<()> {
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent)?;
+	}
+	// Written via rename, so crash never leaves half-written file
+	let tmp = path.with_extension("json.tmp");
+	fs::write(&tmp, serde_json::to_string_pretty(all)?)?;
+	fs::rename(&tmp, path)?;
+	Ok(())
+}
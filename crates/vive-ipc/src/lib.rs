@@ -0,0 +1,328 @@
+//! Protocol of vived control socket
+//!
+//! Every message is a single line of JSON, so socket is also usable from scripts,
+//! i.e `echo '{"command":"status"}' | socat - UNIX-CONNECT:$XDG_RUNTIME_DIR/vived.sock`
+use std::{
+	collections::BTreeMap,
+	env,
+	io::{self, BufRead, BufReader, Write},
+	os::unix::net::UnixStream,
+	path::{Path, PathBuf},
+	result,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+pub mod debug;
+pub mod headsets;
+pub mod session;
+pub mod toml;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("io error: {0}")]
+	Io(#[from] io::Error),
+	#[error("malformed message: {0}")]
+	Json(#[from] serde_json::Error),
+	#[error("connection closed")]
+	Closed,
+	#[error("daemon error: {0}")]
+	Remote(String),
+	#[error("unexpected response")]
+	UnexpectedResponse,
+}
+type Result<T, E = Error> = result::Result<T, E>;
+
+pub const SOCKET_NAME: &str = "vived.sock";
+/// Served by driver-proxy during SteamVR session, speaks the same protocol as vived
+pub const DRIVER_SOCKET_NAME: &str = "vivepro2-driver.sock";
+
+fn runtime_path(name: &str) -> PathBuf {
+	match env::var_os("XDG_RUNTIME_DIR") {
+		Some(dir) => Path::new(&dir).join(name),
+		None => env::temp_dir().join(name),
+	}
+}
+/// `$XDG_RUNTIME_DIR/vived.sock`, same path is used by systemd socket unit
+pub fn socket_path() -> PathBuf {
+	runtime_path(SOCKET_NAME)
+}
+/// App id of Steam on Flathub
+pub const STEAM_FLATPAK_ID: &str = "com.valvesoftware.Steam";
+
+/// Whether this process runs inside of Flatpak sandbox, i.e it is driver loaded by Flatpak Steam
+pub fn in_flatpak() -> bool {
+	env::var_os("FLATPAK_ID").is_some() || Path::new("/.flatpak-info").exists()
+}
+/// Per-app runtime directory, which is visible both inside of sandbox and on the host
+fn flatpak_runtime_path(app: &str, name: &str) -> PathBuf {
+	runtime_path("app").join(app).join(name)
+}
+
+/// `$XDG_RUNTIME_DIR/vivepro2-driver.sock`, or `$XDG_RUNTIME_DIR/app/<id>/vivepro2-driver.sock`
+/// inside of Flatpak, as sandbox only shares that directory with the host
+pub fn driver_socket_path() -> PathBuf {
+	match env::var("FLATPAK_ID") {
+		Ok(app) if in_flatpak() => flatpak_runtime_path(&app, DRIVER_SOCKET_NAME),
+		_ => runtime_path(DRIVER_SOCKET_NAME),
+	}
+}
+/// Every path, at which driver-proxy of native or Flatpak Steam may listen, as seen from host
+pub fn driver_socket_paths() -> [PathBuf; 2] {
+	[
+		runtime_path(DRIVER_SOCKET_NAME),
+		flatpak_runtime_path(STEAM_FLATPAK_ID, DRIVER_SOCKET_NAME),
+	]
+}
+
+/// Settings, which are applied every time headset is connected
+///
+/// Unset fields are left as is
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub resolution: Option<u8>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub brightness: Option<u8>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub noise_cancel: Option<bool>,
+	/// Disabled cameras are powered down, which frees USB bandwidth
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub cameras: Option<bool>,
+}
+impl Settings {
+	/// Overrides fields, which are set in `other`
+	pub fn merge(&mut self, other: &Settings) {
+		if other.resolution.is_some() {
+			self.resolution = other.resolution;
+		}
+		if other.brightness.is_some() {
+			self.brightness = other.brightness;
+		}
+		if other.noise_cancel.is_some() {
+			self.noise_cancel = other.noise_cancel;
+		}
+		if other.cameras.is_some() {
+			self.cameras = other.cameras;
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+	pub connected: bool,
+	pub serial: Option<String>,
+	pub settings: Settings,
+	/// Driver uses approximate lens distortion, as lens server has failed to start
+	#[serde(default)]
+	pub lens_fallback: bool,
+	/// Display is blanked by standby policy, of vived, or of SteamVR during session
+	#[serde(default)]
+	pub standby: bool,
+	/// Headset lifecycle state, name of `vive_hid::ConnectionState`, of whoever holds it
+	#[serde(default)]
+	pub state: Option<String>,
+}
+
+/// Compositor statistics over last frames, averages are in milliseconds
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FrameTiming {
+	pub frames: u32,
+	pub dropped_frames: u32,
+	pub mispresented_frames: u32,
+	pub reprojected_frames: u32,
+	pub gpu_ms: f32,
+	pub compositor_cpu_ms: f32,
+	pub frame_interval_ms: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+	Status,
+	SetResolution {
+		resolution: u8,
+	},
+	SetBrightness {
+		brightness: u8,
+	},
+	SetNoiseCancel {
+		enabled: bool,
+	},
+	/// Manual override for base station keepalive, until headset is reconnected
+	SetBaseStations {
+		on: bool,
+	},
+	/// Sent by driver-proxy on SteamVR exit, stations are put to sleep after `after` seconds,
+	/// unless SteamVR is started again meanwhile
+	SleepBaseStations {
+		names: Vec<String>,
+		after: u64,
+	},
+	/// Only served by driver-proxy, as SteamVR session is required
+	FrameTiming,
+	Profiles,
+	/// Applies every setting of named profile, and persists it
+	SetProfile {
+		name: String,
+	},
+	/// Only served by driver-proxy, see [`debug`]
+	Debug {
+		request: debug::DebugRequest,
+	},
+	/// [`Response::Ok`] is followed by a stream of [`Event`], one per line. Driver-proxy only
+	/// sends its own events, until SteamVR exits, vived sends everything, including driver ones
+	Subscribe,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum Response {
+	Ok,
+	Status(Status),
+	FrameTiming(FrameTiming),
+	Profiles {
+		active: Option<String>,
+		profiles: BTreeMap<String, Settings>,
+	},
+	/// Struct variant, as debug responses are tagged with `result` too
+	Debug {
+		response: debug::DebugResponse,
+	},
+	Error {
+		message: String,
+	},
+}
+
+/// Headset state changes, as observed by daemon
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+	Connected {
+		serial: Option<String>,
+	},
+	Disconnected,
+	/// Raw sensor value, as reported by mainboard
+	Ipd {
+		ipd: u16,
+	},
+	Proximity {
+		near: bool,
+	},
+	/// Mode switch was requested by daemon
+	Mode {
+		resolution: u8,
+	},
+	/// SteamVR session started or stopped, as seen by driver control socket
+	Session {
+		running: bool,
+	},
+	/// Keepalive powered configured base stations on or down
+	BaseStations {
+		on: bool,
+	},
+	/// Reported by driver, headset is back in this mode after switch
+	ModeApplied {
+		resolution: u8,
+	},
+	/// Reported by driver, requested mode has left display dead, and safe mode is used instead
+	ModeFallback {
+		requested: u8,
+		resolution: u8,
+		reason: String,
+	},
+	/// Reported by driver, crashed lens server was replaced by a new process
+	LensServerRestarted,
+	/// Headset lifecycle state was changed, `state` is name of `vive_hid::ConnectionState`
+	State {
+		state: String,
+		reason: Option<String>,
+	},
+}
+
+pub fn read_message<T: DeserializeOwned>(read: &mut impl BufRead) -> Result<T> {
+	let mut line = String::new();
+	if read.read_line(&mut line)? == 0 {
+		return Err(Error::Closed);
+	}
+	Ok(serde_json::from_str(&line)?)
+}
+pub fn write_message(write: &mut impl Write, v: &impl Serialize) -> Result<()> {
+	let mut data = serde_json::to_vec(v)?;
+	data.push(b'\n');
+	write.write_all(&data)?;
+	write.flush()?;
+	Ok(())
+}
+
+pub struct Client {
+	read: BufReader<UnixStream>,
+	write: UnixStream,
+}
+impl Client {
+	pub fn connect(path: impl AsRef<Path>) -> Result<Self> {
+		let write = UnixStream::connect(path)?;
+		Ok(Self {
+			read: BufReader::new(write.try_clone()?),
+			write,
+		})
+	}
+	/// Connects to daemon at default [`socket_path`]
+	pub fn connect_default() -> Result<Self> {
+		Self::connect(socket_path())
+	}
+	/// Connects to driver-proxy of either native or Flatpak Steam, `None` if SteamVR isn't running
+	pub fn connect_driver() -> Option<Self> {
+		driver_socket_paths()
+			.into_iter()
+			.find_map(|path| Self::connect(path).ok())
+	}
+	/// Fails with [`Error::Remote`] if daemon reported failure
+	pub fn request(&mut self, request: &Request) -> Result<Response> {
+		write_message(&mut self.write, request)?;
+		match read_message(&mut self.read)? {
+			Response::Error { message } => Err(Error::Remote(message)),
+			res => Ok(res),
+		}
+	}
+	pub fn status(&mut self) -> Result<Status> {
+		match self.request(&Request::Status)? {
+			Response::Status(status) => Ok(status),
+			_ => Err(Error::UnexpectedResponse),
+		}
+	}
+	pub fn frame_timing(&mut self) -> Result<FrameTiming> {
+		match self.request(&Request::FrameTiming)? {
+			Response::FrameTiming(timing) => Ok(timing),
+			_ => Err(Error::UnexpectedResponse),
+		}
+	}
+	pub fn debug(&mut self, request: debug::DebugRequest) -> Result<debug::DebugResponse> {
+		match self.request(&Request::Debug { request })? {
+			Response::Debug {
+				response: debug::DebugResponse::Error { message },
+			} => Err(Error::Remote(message)),
+			Response::Debug { response } => Ok(response),
+			_ => Err(Error::UnexpectedResponse),
+		}
+	}
+	/// Connection can't be used for requests after this, see [`Request::Subscribe`]
+	pub fn subscribe(mut self) -> Result<Events> {
+		match self.request(&Request::Subscribe)? {
+			Response::Ok => Ok(Events(self.read)),
+			_ => Err(Error::UnexpectedResponse),
+		}
+	}
+}
+
+/// Ends once the other side closes connection
+pub struct Events(BufReader<UnixStream>);
+impl Iterator for Events {
+	type Item = Result<Event>;
+	fn next(&mut self) -> Option<Self::Item> {
+		match read_message(&mut self.0) {
+			Err(Error::Closed) => None,
+			res => Some(res),
+		}
+	}
+}
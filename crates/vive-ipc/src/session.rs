@@ -0,0 +1,61 @@
+//! Last state driver-proxy has applied to the headset, kept while SteamVR session runs
+//!
+//! File is removed on clean shutdown, so the one found on start is left by crashed vrserver.
+//! Restarted driver takes state from it, as SteamVR settings may have not been flushed, and
+//! headset may be left blanked by standby
+use std::{env, fs, io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{flatpak_runtime_path, in_flatpak, runtime_path, Result};
+
+pub const STATE_NAME: &str = "vivepro2-driver-state.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionState {
+	/// Tracking board serial, state is only restored for the same headset
+	pub serial: String,
+	pub mode: u8,
+	/// Last set by user, not 0 of standby
+	pub brightness: u8,
+	/// Display was blanked when state was written
+	#[serde(default)]
+	pub standby: bool,
+}
+
+/// Next to driver socket, see [`crate::driver_socket_path`]
+pub fn state_path() -> PathBuf {
+	match env::var("FLATPAK_ID") {
+		Ok(app) if in_flatpak() => flatpak_runtime_path(&app, STATE_NAME),
+		_ => runtime_path(STATE_NAME),
+	}
+}
+
+/// `None` if previous session has ended cleanly
+pub fn load() -> Result<Option<SessionState>> {
+	match fs::read_to_string(state_path()) {
+		Ok(data) => Ok(Some(serde_json::from_str(&data)?)),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+		Err(e) => Err(e.into()),
+	}
+}
+
+pub fn save(state: &SessionState) -> Result<()> {
+	let path = state_path();
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent)?;
+	}
+	// Written via rename, so crash never leaves half-written file
+	let tmp = path.with_extension("json.tmp");
+	fs::write(&tmp, serde_json::to_string(state)?)?;
+	fs::rename(&tmp, path)?;
+	Ok(())
+}
+
+/// Called on clean shutdown
+pub fn clear() -> Result<()> {
+	match fs::remove_file(state_path()) {
+		Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+		_ => Ok(()),
+	}
+}
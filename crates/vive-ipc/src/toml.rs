@@ -0,0 +1,814 @@
+//! Config files of driver and vived, in TOML 1.0 without dates and times (which are rejected,
+//! no config has them). toml crate is not worth a dependency for that
+//!
+//! Serde types go through [`serde_json::Value`], so the same derives read both TOML configs
+//! and JSON messages
+use std::{
+	collections::{btree_map, BTreeMap},
+	fmt::Write,
+	ops::Range,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+	String(String),
+	Integer(i64),
+	Float(f64),
+	Bool(bool),
+	Array(Vec<Value>),
+	Table(BTreeMap<String, Value>),
+}
+impl Value {
+	pub fn as_f64(&self) -> Option<f64> {
+		match self {
+			Self::Integer(v) => Some(*v as f64),
+			Self::Float(v) => Some(*v),
+			_ => None,
+		}
+	}
+	fn into_json(self) -> serde_json::Value {
+		match self {
+			Self::String(v) => v.into(),
+			Self::Integer(v) => v.into(),
+			Self::Float(v) => v.into(),
+			Self::Bool(v) => v.into(),
+			Self::Array(items) => items.into_iter().map(Self::into_json).collect(),
+			Self::Table(table) => table_to_json(table),
+		}
+	}
+}
+
+fn table_to_json(table: BTreeMap<String, Value>) -> serde_json::Value {
+	table
+		.into_iter()
+		.map(|(k, v)| (k, v.into_json()))
+		.collect::<serde_json::Map<_, _>>()
+		.into()
+}
+
+fn dotted(path: &[String]) -> String {
+	path.join(".")
+}
+
+/// How table came to be, which decides what may extend it later
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+	/// Parent of a header, may still get its own
+	Implicit,
+	Header,
+	/// Parent of dotted key, only other dotted keys of the same table may add to it
+	Dotted,
+}
+
+/// Table of the document, while it is parsed. Inline tables and arrays are plain values, as they
+/// can't be extended
+struct Table {
+	kind: Kind,
+	entries: BTreeMap<String, Entry>,
+}
+enum Entry {
+	Value(Value),
+	Table(Table),
+	/// `[[name]]`, never empty
+	Array(Vec<Table>),
+}
+impl Table {
+	fn new(kind: Kind) -> Self {
+		Self {
+			kind,
+			entries: BTreeMap::new(),
+		}
+	}
+	/// Table, which header path goes through, the last one for arrays of tables
+	fn child(&mut self, name: &str) -> Result<&mut Table, String> {
+		let entry = self
+			.entries
+			.entry(name.to_owned())
+			.or_insert_with(|| Entry::Table(Table::new(Kind::Implicit)));
+		match entry {
+			Entry::Table(table) => Ok(table),
+			Entry::Array(tables) => Ok(tables.last_mut().expect("not empty")),
+			Entry::Value(_) => Err(format!("{name:?} is not a table")),
+		}
+	}
+	fn path(&mut self, path: &[String]) -> Result<&mut Table, String> {
+		path.iter().try_fold(self, |table, name| table.child(name))
+	}
+	/// `[path]`
+	fn open(&mut self, path: &[String]) -> Result<(), String> {
+		let (name, parents) = path.split_last().expect("paths are not empty");
+		match self.path(parents)?.entries.entry(name.clone()) {
+			btree_map::Entry::Vacant(e) => {
+				e.insert(Entry::Table(Table::new(Kind::Header)));
+			}
+			btree_map::Entry::Occupied(e) => match e.into_mut() {
+				Entry::Table(table) if table.kind == Kind::Implicit => table.kind = Kind::Header,
+				_ => return Err(format!("{:?} is already defined", dotted(path))),
+			},
+		}
+		Ok(())
+	}
+	/// `[[path]]`
+	fn push(&mut self, path: &[String]) -> Result<(), String> {
+		let (name, parents) = path.split_last().expect("paths are not empty");
+		match self.path(parents)?.entries.entry(name.clone()) {
+			btree_map::Entry::Vacant(e) => {
+				e.insert(Entry::Array(vec![Table::new(Kind::Header)]));
+			}
+			btree_map::Entry::Occupied(e) => match e.into_mut() {
+				Entry::Array(tables) => tables.push(Table::new(Kind::Header)),
+				_ => return Err(format!("{:?} is not an array of tables", dotted(path))),
+			},
+		}
+		Ok(())
+	}
+	/// `key = value`, where key may be dotted
+	fn insert(&mut self, key: &[String], value: Value) -> Result<(), String> {
+		let (name, parents) = key.split_last().expect("keys are not empty");
+		let mut table = self;
+		for parent in parents {
+			let entry = table
+				.entries
+				.entry(parent.clone())
+				.or_insert_with(|| Entry::Table(Table::new(Kind::Dotted)));
+			table = match entry {
+				Entry::Table(table) if table.kind == Kind::Dotted => table,
+				_ => return Err(format!("{parent:?} is already defined")),
+			};
+		}
+		match table.entries.entry(name.clone()) {
+			btree_map::Entry::Vacant(e) => {
+				e.insert(Entry::Value(value));
+				Ok(())
+			}
+			btree_map::Entry::Occupied(_) => Err(format!("duplicate key {:?}", dotted(key))),
+		}
+	}
+	fn into_values(self) -> BTreeMap<String, Value> {
+		self.entries
+			.into_iter()
+			.map(|(name, entry)| {
+				let value = match entry {
+					Entry::Value(value) => value,
+					Entry::Table(table) => Value::Table(table.into_values()),
+					Entry::Array(tables) => Value::Array(
+						tables
+							.into_iter()
+							.map(|t| Value::Table(t.into_values()))
+							.collect(),
+					),
+				};
+				(name, value)
+			})
+			.collect()
+	}
+}
+
+/// Same rules as for [`Table::insert`], but inline table is only extended by itself
+fn insert_inline(
+	table: &mut BTreeMap<String, Value>,
+	key: &[String],
+	value: Value,
+) -> Result<(), String> {
+	let (name, parents) = key.split_last().expect("keys are not empty");
+	let mut table = table;
+	for parent in parents {
+		let entry = table
+			.entry(parent.clone())
+			.or_insert_with(|| Value::Table(BTreeMap::new()));
+		let Value::Table(next) = entry else {
+			return Err(format!("{parent:?} is not a table"));
+		};
+		table = next;
+	}
+	match table.entry(name.clone()) {
+		btree_map::Entry::Vacant(e) => {
+			e.insert(value);
+			Ok(())
+		}
+		btree_map::Entry::Occupied(_) => Err(format!("duplicate key {:?}", dotted(key))),
+	}
+}
+
+fn is_bare_key_char(c: char) -> bool {
+	c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+fn is_bare_key(key: &str) -> bool {
+	!key.is_empty() && key.chars().all(is_bare_key_char)
+}
+
+/// `1979-05-27`, `07:32:00`, and everything starting with them
+fn is_date_or_time(token: &str) -> bool {
+	let b = token.as_bytes();
+	let digits = |r: Range<usize>| b.get(r).is_some_and(|d| d.iter().all(u8::is_ascii_digit));
+	(digits(0..4) && b.get(4) == Some(&b'-') && digits(5..7))
+		|| (digits(0..2) && b.get(2) == Some(&b':'))
+}
+
+/// Digits with single underscores between them
+fn strip_underscores(digits: &str, radix: u32) -> Option<String> {
+	let valid = !digits.is_empty()
+		&& digits
+			.split('_')
+			.all(|group| !group.is_empty() && group.chars().all(|c| c.is_digit(radix)));
+	valid.then(|| digits.replace('_', ""))
+}
+
+fn parse_integer(token: &str) -> Option<i64> {
+	for (prefix, radix) in [("0x", 16), ("0o", 8), ("0b", 2)] {
+		if let Some(digits) = token.strip_prefix(prefix) {
+			return i64::from_str_radix(&strip_underscores(digits, radix)?, radix).ok();
+		}
+	}
+	let unsigned = token.strip_prefix(['+', '-']).unwrap_or(token);
+	if unsigned.len() > 1 && unsigned.starts_with('0') {
+		return None;
+	}
+	strip_underscores(unsigned, 10)?;
+	token.replace('_', "").parse().ok()
+}
+
+fn parse_float(token: &str) -> Option<f64> {
+	let unsigned = token.strip_prefix(['+', '-']).unwrap_or(token);
+	match unsigned {
+		"inf" => {
+			return Some(if token.starts_with('-') {
+				f64::NEG_INFINITY
+			} else {
+				f64::INFINITY
+			})
+		}
+		"nan" => return Some(f64::NAN),
+		_ => {}
+	}
+	let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+		Some((m, e)) => (m, Some(e)),
+		None => (unsigned, None),
+	};
+	let (int, fraction) = match mantissa.split_once('.') {
+		Some((i, f)) => (i, Some(f)),
+		None => (mantissa, None),
+	};
+	if (int.len() > 1 && int.starts_with('0'))
+		|| strip_underscores(int, 10).is_none()
+		|| fraction.is_some_and(|f| strip_underscores(f, 10).is_none())
+		|| exponent.is_some_and(|e| {
+			strip_underscores(e.strip_prefix(['+', '-']).unwrap_or(e), 10).is_none()
+		}) || (fraction.is_none() && exponent.is_none())
+	{
+		return None;
+	}
+	token.replace('_', "").parse().ok()
+}
+
+/// Top-level `key = value`, which [`set_keys`] may replace
+struct Statement {
+	key: Vec<String>,
+	/// Whole lines, with trailing comment and newline
+	span: Range<usize>,
+}
+
+struct Document {
+	root: Table,
+	/// Statements before the first table header
+	statements: Vec<Statement>,
+	/// Start of the line with the first table header
+	tables_at: Option<usize>,
+}
+
+/// Cursor over the document, errors tell line of the character it stopped at
+struct Parser<'d> {
+	data: &'d str,
+	pos: usize,
+}
+impl<'d> Parser<'d> {
+	fn new(data: &'d str) -> Self {
+		Self { data, pos: 0 }
+	}
+	fn rest(&self) -> &'d str {
+		&self.data[self.pos..]
+	}
+	fn peek(&self) -> Option<char> {
+		self.rest().chars().next()
+	}
+	fn bump(&mut self) -> Option<char> {
+		let c = self.peek()?;
+		self.pos += c.len_utf8();
+		Some(c)
+	}
+	fn eat(&mut self, s: &str) -> bool {
+		let found = self.rest().starts_with(s);
+		if found {
+			self.pos += s.len();
+		}
+		found
+	}
+	fn error_at(&self, pos: usize, message: impl std::fmt::Display) -> String {
+		let line = self.data[..pos].matches('\n').count() + 1;
+		format!("line {line}: {message}")
+	}
+	fn error(&self, message: impl std::fmt::Display) -> String {
+		self.error_at(self.pos, message)
+	}
+	fn unexpected(&self, expected: &str) -> String {
+		match self.peek() {
+			Some(c) => self.error(format!("expected {expected}, got {c:?}")),
+			None => self.error(format!("expected {expected}, got end of file")),
+		}
+	}
+	fn expect(&mut self, s: &str) -> Result<(), String> {
+		match self.eat(s) {
+			true => Ok(()),
+			false => Err(self.unexpected(s)),
+		}
+	}
+	fn line_start(&self, pos: usize) -> usize {
+		self.data[..pos].rfind('\n').map_or(0, |i| i + 1)
+	}
+
+	fn skip_whitespace(&mut self) {
+		while self.eat(" ") || self.eat("\t") {}
+	}
+	fn eat_newline(&mut self) -> bool {
+		self.eat("\n") || self.eat("\r\n")
+	}
+	fn skip_comment(&mut self) {
+		if self.eat("#") {
+			let len = self.rest().find('\n').unwrap_or(self.rest().len());
+			self.pos += len;
+			// \r of \r\n is left for eat_newline
+			if self.data[..self.pos].ends_with('\r') {
+				self.pos -= 1;
+			}
+		}
+	}
+	/// Between array items, and between statements
+	fn skip_blank(&mut self) {
+		loop {
+			self.skip_whitespace();
+			self.skip_comment();
+			if !self.eat_newline() {
+				return;
+			}
+		}
+	}
+	/// Statement is followed by comment or nothing until end of line
+	fn end_of_line(&mut self) -> Result<(), String> {
+		self.skip_whitespace();
+		self.skip_comment();
+		match self.eat_newline() || self.rest().is_empty() {
+			true => Ok(()),
+			false => Err(self.unexpected("end of line")),
+		}
+	}
+
+	fn escape(&mut self) -> Result<char, String> {
+		let start = self.pos - 1;
+		let hex = |p: &mut Self, len: usize| {
+			let digits = p
+				.rest()
+				.chars()
+				.take(len)
+				.take_while(char::is_ascii_hexdigit)
+				.count();
+			let c = u32::from_str_radix(&p.rest()[..digits], 16)
+				.ok()
+				.filter(|_| digits == len)
+				.and_then(char::from_u32);
+			p.pos += digits;
+			c.ok_or_else(|| {
+				p.error_at(
+					start,
+					format!("malformed escape: {:?}", &p.data[start..p.pos]),
+				)
+			})
+		};
+		Ok(match self.bump() {
+			Some('b') => '\u{8}',
+			Some('t') => '\t',
+			Some('n') => '\n',
+			Some('f') => '\u{c}',
+			Some('r') => '\r',
+			Some('"') => '"',
+			Some('\\') => '\\',
+			Some('u') => hex(self, 4)?,
+			Some('U') => hex(self, 8)?,
+			other => return Err(self.error_at(start, format!("unsupported escape: {other:?}"))),
+		})
+	}
+	/// After opening `"`
+	fn basic_string(&mut self) -> Result<String, String> {
+		let start = self.pos - 1;
+		let mut out = String::new();
+		loop {
+			match self.bump() {
+				Some('"') => return Ok(out),
+				Some('\\') => out.push(self.escape()?),
+				Some('\n') | None => return Err(self.error_at(start, "unterminated string")),
+				Some(c) => out.push(c),
+			}
+		}
+	}
+	/// After opening `'`
+	fn literal_string(&mut self) -> Result<String, String> {
+		let len = self
+			.rest()
+			.find(['\'', '\n'])
+			.filter(|&i| self.rest()[i..].starts_with('\''))
+			.ok_or_else(|| self.error("unterminated string"))?;
+		let out = self.rest()[..len].to_owned();
+		self.pos += len + 1;
+		Ok(out)
+	}
+	/// Closing delimiter of multi-line string, which may have up to two quotes of content before
+	/// it, as in `""""quoted""""`
+	fn multiline_end(&mut self, quote: char, out: &mut String) -> bool {
+		let quotes = self.rest().chars().take_while(|&c| c == quote).count();
+		if quotes < 3 {
+			return false;
+		}
+		let content = (quotes - 3).min(2);
+		out.extend(std::iter::repeat(quote).take(content));
+		self.pos += content + 3;
+		true
+	}
+	/// After opening `"""`
+	fn multiline_basic_string(&mut self) -> Result<String, String> {
+		let start = self.pos;
+		self.eat_newline();
+		let mut out = String::new();
+		loop {
+			if self.multiline_end('"', &mut out) {
+				return Ok(out);
+			}
+			match self.bump() {
+				// Line ending backslash trims whitespace up to the next content
+				Some('\\')
+					if self
+						.rest()
+						.trim_start_matches([' ', '\t'])
+						.starts_with(['\n', '\r']) =>
+				{
+					let len = self.rest().len() - self.rest().trim_start().len();
+					self.pos += len;
+				}
+				Some('\\') => out.push(self.escape()?),
+				Some(c) => out.push(c),
+				None => return Err(self.error_at(start, "unterminated string")),
+			}
+		}
+	}
+	/// After opening `'''`
+	fn multiline_literal_string(&mut self) -> Result<String, String> {
+		let start = self.pos;
+		self.eat_newline();
+		let mut out = String::new();
+		loop {
+			if self.multiline_end('\'', &mut out) {
+				return Ok(out);
+			}
+			match self.bump() {
+				Some(c) => out.push(c),
+				None => return Err(self.error_at(start, "unterminated string")),
+			}
+		}
+	}
+
+	fn key_part(&mut self) -> Result<String, String> {
+		if self.eat("\"") {
+			return self.basic_string();
+		}
+		if self.eat("'") {
+			return self.literal_string();
+		}
+		let len = self
+			.rest()
+			.find(|c| !is_bare_key_char(c))
+			.unwrap_or(self.rest().len());
+		if len == 0 {
+			return Err(self.unexpected("key"));
+		}
+		let key = self.rest()[..len].to_owned();
+		self.pos += len;
+		Ok(key)
+	}
+	/// `a."b c".d`
+	fn key(&mut self) -> Result<Vec<String>, String> {
+		let mut key = vec![self.key_part()?];
+		loop {
+			self.skip_whitespace();
+			if !self.eat(".") {
+				return Ok(key);
+			}
+			self.skip_whitespace();
+			key.push(self.key_part()?);
+		}
+	}
+
+	fn value(&mut self) -> Result<Value, String> {
+		if self.eat("\"\"\"") {
+			return self.multiline_basic_string().map(Value::String);
+		}
+		if self.eat("\"") {
+			return self.basic_string().map(Value::String);
+		}
+		if self.eat("'''") {
+			return self.multiline_literal_string().map(Value::String);
+		}
+		if self.eat("'") {
+			return self.literal_string().map(Value::String);
+		}
+		if self.eat("[") {
+			return self.array();
+		}
+		if self.eat("{") {
+			return self.inline_table();
+		}
+		let start = self.pos;
+		let len = self
+			.rest()
+			.find(|c: char| matches!(c, ',' | ']' | '}' | '#') || c.is_whitespace())
+			.unwrap_or(self.rest().len());
+		let token = &self.rest()[..len];
+		self.pos += len;
+		let value = match token {
+			"true" => Value::Bool(true),
+			"false" => Value::Bool(false),
+			"" => return Err(self.unexpected("value")),
+			_ if is_date_or_time(token) => {
+				return Err(self.error_at(start, "dates and times are not supported"))
+			}
+			_ => match parse_integer(token) {
+				Some(v) => Value::Integer(v),
+				None => Value::Float(
+					parse_float(token)
+						.ok_or_else(|| self.error_at(start, format!("invalid value: {token:?}")))?,
+				),
+			},
+		};
+		Ok(value)
+	}
+	/// After `[`, items may be on several lines, with comments and trailing comma
+	fn array(&mut self) -> Result<Value, String> {
+		let mut items = Vec::new();
+		loop {
+			self.skip_blank();
+			if self.eat("]") {
+				return Ok(Value::Array(items));
+			}
+			items.push(self.value()?);
+			self.skip_blank();
+			if !self.eat(",") && !self.rest().starts_with(']') {
+				return Err(self.unexpected(", or ] in array"));
+			}
+		}
+	}
+	/// After `{`, on a single line, without trailing comma
+	fn inline_table(&mut self) -> Result<Value, String> {
+		let mut table = BTreeMap::new();
+		self.skip_whitespace();
+		if self.eat("}") {
+			return Ok(Value::Table(table));
+		}
+		loop {
+			self.skip_whitespace();
+			let start = self.pos;
+			let key = self.key()?;
+			self.skip_whitespace();
+			self.expect("=")?;
+			self.skip_whitespace();
+			let value = self.value()?;
+			insert_inline(&mut table, &key, value).map_err(|e| self.error_at(start, e))?;
+			self.skip_whitespace();
+			if self.eat("}") {
+				return Ok(Value::Table(table));
+			}
+			if !self.eat(",") {
+				return Err(match self.peek() {
+					Some('\n' | '\r') => self.error("inline table should be on a single line"),
+					_ => self.unexpected(", or } in inline table"),
+				});
+			}
+		}
+	}
+	/// Header name after `[` or `[[`, up to `]` or `]]`
+	fn header(&mut self, close: &str) -> Result<Vec<String>, String> {
+		self.skip_whitespace();
+		let path = self.key()?;
+		self.expect(close)?;
+		Ok(path)
+	}
+
+	fn document(mut self) -> Result<Document, String> {
+		let mut doc = Document {
+			root: Table::new(Kind::Header),
+			statements: Vec::new(),
+			tables_at: None,
+		};
+		let mut table = Vec::new();
+		loop {
+			self.skip_blank();
+			if self.rest().is_empty() {
+				return Ok(doc);
+			}
+			let start = self.pos;
+			if self.eat("[[") {
+				let path = self.header("]]")?;
+				doc.root.push(&path).map_err(|e| self.error_at(start, e))?;
+				doc.tables_at.get_or_insert(self.line_start(start));
+				table = path;
+			} else if self.eat("[") {
+				let path = self.header("]")?;
+				doc.root.open(&path).map_err(|e| self.error_at(start, e))?;
+				doc.tables_at.get_or_insert(self.line_start(start));
+				table = path;
+			} else {
+				let key = self.key()?;
+				self.expect("=")?;
+				self.skip_whitespace();
+				let value = self.value()?;
+				doc.root
+					.path(&table)
+					.and_then(|t| t.insert(&key, value))
+					.map_err(|e| self.error_at(start, e))?;
+				self.end_of_line()?;
+				if doc.tables_at.is_none() {
+					doc.statements.push(Statement {
+						key,
+						span: self.line_start(start)..self.pos,
+					});
+				}
+				continue;
+			}
+			self.end_of_line()?;
+		}
+	}
+}
+
+pub fn parse(data: &str) -> Result<BTreeMap<String, Value>, String> {
+	Ok(Parser::new(data).document()?.root.into_values())
+}
+
+/// Parses TOML into anything, which deserializes from JSON
+pub fn from_str<T: DeserializeOwned>(data: &str) -> Result<T, String> {
+	serde_json::from_value(table_to_json(parse(data)?)).map_err(|e| e.to_string())
+}
+
+fn write_key(out: &mut String, key: &str) {
+	if is_bare_key(key) {
+		out.push_str(key);
+	} else {
+		write_string(out, key);
+	}
+}
+fn write_string(out: &mut String, s: &str) {
+	out.push('"');
+	for c in s.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\t' => out.push_str("\\t"),
+			c if c.is_control() => {
+				let _ = write!(out, "\\u{:04x}", c as u32);
+			}
+			c => out.push(c),
+		}
+	}
+	out.push('"');
+}
+fn write_value(out: &mut String, value: &serde_json::Value) -> Result<(), String> {
+	match value {
+		serde_json::Value::Bool(v) => out.push_str(if *v { "true" } else { "false" }),
+		serde_json::Value::Number(v) => {
+			if let Some(v) = v.as_i64() {
+				let _ = write!(out, "{v}");
+			} else if let Some(v) = v.as_f64().filter(|_| !v.is_u64()) {
+				let _ = write!(out, "{v:?}");
+			} else {
+				return Err(format!("{v} doesn't fit into TOML integer"));
+			}
+		}
+		serde_json::Value::String(v) => write_string(out, v),
+		serde_json::Value::Array(items) => {
+			out.push('[');
+			for (i, item) in items.iter().enumerate() {
+				if i != 0 {
+					out.push_str(", ");
+				}
+				if item.is_object() {
+					return Err("arrays of tables are not supported".to_owned());
+				}
+				write_value(out, item)?;
+			}
+			out.push(']');
+		}
+		serde_json::Value::Null | serde_json::Value::Object(_) => {
+			unreachable!("skipped by write_table")
+		}
+	}
+	Ok(())
+}
+/// Plain keys go first, as everything after a header belongs to its table. Header of table
+/// with nothing but tables in it is implied by theirs
+fn write_table(
+	out: &mut String,
+	path: &mut Vec<String>,
+	table: &serde_json::Map<String, serde_json::Value>,
+) -> Result<(), String> {
+	for (key, value) in table {
+		if value.is_null() || value.is_object() {
+			continue;
+		}
+		write_key(out, key);
+		out.push_str(" = ");
+		write_value(out, value)?;
+		out.push('\n');
+	}
+	for (key, value) in table {
+		let serde_json::Value::Object(inner) = value else {
+			continue;
+		};
+		path.push(key.clone());
+		let implied = inner.values().any(|v| v.is_object())
+			&& inner.values().all(|v| v.is_null() || v.is_object());
+		if !implied {
+			if !out.is_empty() {
+				out.push('\n');
+			}
+			out.push('[');
+			for (i, name) in path.iter().enumerate() {
+				if i != 0 {
+					out.push('.');
+				}
+				write_key(out, name);
+			}
+			out.push_str("]\n");
+		}
+		write_table(out, path, inner)?;
+		path.pop();
+	}
+	Ok(())
+}
+
+/// String value, quoted and escaped
+pub fn quote(s: &str) -> String {
+	let mut out = String::new();
+	write_string(&mut out, s);
+	out
+}
+
+/// Replaces (or removes, for `None`) top-level keys, new ones are added before the first table,
+/// the rest of the file is kept as written. Fails if `data` doesn't parse, as it would be unclear
+/// what to replace
+pub fn set_keys(data: &str, keys: &[(&str, Option<String>)]) -> Result<String, String> {
+	let doc = Parser::new(data).document()?;
+	let tables_at = doc.tables_at.unwrap_or(data.len());
+	let mut edits = Vec::new();
+	let mut missing = String::new();
+	if tables_at == data.len() && !data.is_empty() && !data.ends_with('\n') {
+		missing.push('\n');
+	}
+	for (key, value) in keys {
+		let line = value.as_ref().map(|value| {
+			let mut line = String::new();
+			write_key(&mut line, key);
+			let _ = writeln!(line, " = {value}");
+			line
+		});
+		let existing = doc
+			.statements
+			.iter()
+			.find(|s| matches!(s.key.as_slice(), [k] if k == key));
+		match (existing, line) {
+			(Some(s), line) => edits.push((s.span.clone(), line.unwrap_or_default())),
+			(None, Some(line)) => missing.push_str(&line),
+			(None, None) => {}
+		}
+	}
+	edits.push((tables_at..tables_at, missing));
+	edits.sort_by_key(|(span, _)| span.start);
+	let mut out = String::new();
+	let mut copied = 0;
+	for (span, text) in edits {
+		out.push_str(&data[copied..span.start]);
+		out.push_str(&text);
+		copied = span.end;
+	}
+	out.push_str(&data[copied..]);
+	Ok(out)
+}
+
+/// Writes anything, which serializes to JSON object, `null` fields are left out
+pub fn to_string<T: Serialize>(value: &T) -> Result<String, String> {
+	let serde_json::Value::Object(table) =
+		serde_json::to_value(value).map_err(|e| e.to_string())?
+	else {
+		return Err("only tables are written as TOML".to_owned());
+	};
+	let mut out = String::new();
+	write_table(&mut out, &mut Vec::new(), &table)?;
+	Ok(out)
+}
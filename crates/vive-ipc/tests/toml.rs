@@ -0,0 +1,328 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use vive_ipc::toml::{self, Value};
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Config {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	mode: Option<u8>,
+	#[serde(default)]
+	names: Vec<String>,
+	#[serde(default)]
+	profiles: BTreeMap<String, BTreeMap<String, f64>>,
+}
+
+#[test]
+fn nested_tables() {
+	let data = r#"
+		top = 1 # comment
+		[display]
+		"quoted key" = "a \"b\" é"
+		[profiles.cinema]
+		gain = [0.5, 1_000, 0x10]
+		[profiles."the other"]
+	"#;
+	let parsed = toml::parse(data).unwrap();
+	assert_eq!(parsed["top"], Value::Integer(1));
+	let Value::Table(display) = &parsed["display"] else {
+		panic!("not a table");
+	};
+	assert_eq!(display["quoted key"], Value::String("a \"b\" é".to_owned()));
+	let Value::Table(profiles) = &parsed["profiles"] else {
+		panic!("not a table");
+	};
+	assert_eq!(
+		profiles["cinema"],
+		Value::Table(BTreeMap::from([(
+			"gain".to_owned(),
+			Value::Array(vec![
+				Value::Float(0.5),
+				Value::Integer(1000),
+				Value::Integer(16)
+			])
+		)]))
+	);
+	assert_eq!(profiles["the other"], Value::Table(BTreeMap::new()));
+
+	assert!(toml::parse("[a]\n[a]").is_err());
+	assert!(toml::parse("a = 1\n[a.b]").is_err());
+	assert!(toml::parse("a = 1\na = 2").is_err());
+}
+
+#[test]
+fn serde_roundtrip() {
+	let config = Config {
+		mode: None,
+		names: vec!["LHB-1234\n".to_owned()],
+		profiles: BTreeMap::from([(
+			"night mode".to_owned(),
+			BTreeMap::from([("brightness".to_owned(), 0.25)]),
+		)]),
+	};
+	let data = toml::to_string(&config).unwrap();
+	assert_eq!(
+		data,
+		"names = [\"LHB-1234\\n\"]\n\n[profiles.\"night mode\"]\nbrightness = 0.25\n"
+	);
+	assert_eq!(toml::from_str::<Config>(&data).unwrap(), config);
+	assert_eq!(toml::from_str::<Config>("").unwrap(), Config::default());
+}
+
+fn table(entries: &[(&str, Value)]) -> Value {
+	Value::Table(
+		entries
+			.iter()
+			.map(|(k, v)| (k.to_string(), v.clone()))
+			.collect(),
+	)
+}
+fn string(s: &str) -> Value {
+	Value::String(s.to_owned())
+}
+
+#[test]
+fn multiline_arrays() {
+	let parsed = toml::parse(
+		"color = [\n\t1.0, # red\n\t0.5,\n\n\t0.25, # trailing comma\n]\r\nnested = [[1, 2], [\"a\"], []]\n",
+	)
+	.unwrap();
+	assert_eq!(
+		parsed["color"],
+		Value::Array(vec![
+			Value::Float(1.0),
+			Value::Float(0.5),
+			Value::Float(0.25)
+		])
+	);
+	assert_eq!(
+		parsed["nested"],
+		Value::Array(vec![
+			Value::Array(vec![Value::Integer(1), Value::Integer(2)]),
+			Value::Array(vec![string("a")]),
+			Value::Array(vec![]),
+		])
+	);
+}
+
+#[test]
+fn strings() {
+	let data = r#"
+		literal = 'C:\Users\wine'
+		'literal key' = 1
+		basic = "tab\tquote\"\u00e9\U0001F600"
+		multiline = """
+first \
+		  second
+"quoted""""
+		multiline_literal = '''
+no \escapes '' here'''
+	"#;
+	let parsed = toml::parse(data).unwrap();
+	assert_eq!(parsed["literal"], string(r"C:\Users\wine"));
+	assert_eq!(parsed["literal key"], Value::Integer(1));
+	assert_eq!(parsed["basic"], string("tab\tquote\"é😀"));
+	assert_eq!(parsed["multiline"], string("first second\n\"quoted\""));
+	assert_eq!(parsed["multiline_literal"], string("no \\escapes '' here"));
+}
+
+#[test]
+fn dotted_keys() {
+	let data = r#"
+		display.mode = 1
+		display."color gain".red = 0.5
+		[profiles.cinema]
+		brightness = 20
+		audio.noise_cancel = true
+		[profiles.cinema.audio.extra]
+	"#;
+	let parsed = toml::parse(data).unwrap();
+	assert_eq!(
+		parsed["display"],
+		table(&[
+			("mode", Value::Integer(1)),
+			("color gain", table(&[("red", Value::Float(0.5))])),
+		])
+	);
+	assert_eq!(
+		parsed["profiles"],
+		table(&[(
+			"cinema",
+			table(&[
+				("brightness", Value::Integer(20)),
+				(
+					"audio",
+					table(&[("noise_cancel", Value::Bool(true)), ("extra", table(&[]))])
+				),
+			])
+		)])
+	);
+}
+
+#[test]
+fn inline_tables_and_arrays_of_tables() {
+	let data = r#"
+		point = { x = 1, y.z = "deep" }
+		empty = {}
+		[[base_stations]]
+		serial = "LHB-1"
+		[base_stations.power]
+		sleep = true
+		[[base_stations]]
+		serial = "LHB-2"
+	"#;
+	let parsed = toml::parse(data).unwrap();
+	assert_eq!(
+		parsed["point"],
+		table(&[
+			("x", Value::Integer(1)),
+			("y", table(&[("z", string("deep"))]))
+		])
+	);
+	assert_eq!(parsed["empty"], table(&[]));
+	assert_eq!(
+		parsed["base_stations"],
+		Value::Array(vec![
+			table(&[
+				("serial", string("LHB-1")),
+				("power", table(&[("sleep", Value::Bool(true))])),
+			]),
+			table(&[("serial", string("LHB-2"))]),
+		])
+	);
+}
+
+#[test]
+fn numbers() {
+	let parsed = toml::parse(
+		"a = +42\nb = -1_000\nc = 0o17\nd = 0b101\ne = 6.626e-34\nf = -inf\ng = nan\nh = 1E6\ni = 0",
+	)
+	.unwrap();
+	assert_eq!(parsed["a"], Value::Integer(42));
+	assert_eq!(parsed["b"], Value::Integer(-1000));
+	assert_eq!(parsed["c"], Value::Integer(15));
+	assert_eq!(parsed["d"], Value::Integer(5));
+	assert_eq!(parsed["e"], Value::Float(6.626e-34));
+	assert_eq!(parsed["f"], Value::Float(f64::NEG_INFINITY));
+	assert!(matches!(parsed["g"], Value::Float(v) if v.is_nan()));
+	assert_eq!(parsed["h"], Value::Float(1e6));
+	assert_eq!(parsed["i"], Value::Integer(0));
+}
+
+/// Every rejection names the line, and what is wrong there
+#[track_caller]
+fn rejected(data: &str, error: &str) {
+	match toml::parse(data) {
+		Ok(v) => panic!("{data:?} is accepted as {v:?}"),
+		Err(e) => assert_eq!(e, error, "{data:?}"),
+	}
+}
+
+#[test]
+fn rejects_dates() {
+	rejected(
+		"ok = 1\nd = 1979-05-27",
+		"line 2: dates and times are not supported",
+	);
+	rejected(
+		"d = 1979-05-27T07:32:00Z",
+		"line 1: dates and times are not supported",
+	);
+	rejected("d = 07:32:00", "line 1: dates and times are not supported");
+	rejected(
+		"d = [1979-05-27]",
+		"line 1: dates and times are not supported",
+	);
+}
+
+#[test]
+fn rejects_malformed_values() {
+	rejected("a = 1__0", "line 1: invalid value: \"1__0\"");
+	rejected("a = _1", "line 1: invalid value: \"_1\"");
+	rejected("a = 01", "line 1: invalid value: \"01\"");
+	rejected("a = 1.", "line 1: invalid value: \"1.\"");
+	rejected("a = .5", "line 1: invalid value: \".5\"");
+	rejected("a = 0xG", "line 1: invalid value: \"0xG\"");
+	rejected(
+		"a = 99999999999999999999",
+		"line 1: invalid value: \"99999999999999999999\"",
+	);
+	rejected("a = yes", "line 1: invalid value: \"yes\"");
+	rejected("a =", "line 1: expected value, got end of file");
+	rejected("a = 1 2", "line 1: expected end of line, got '2'");
+}
+
+#[test]
+fn rejects_malformed_strings() {
+	rejected("a = \"open", "line 1: unterminated string");
+	rejected("a = \"two\nlines\"", "line 1: unterminated string");
+	rejected("a = 'open", "line 1: unterminated string");
+	rejected("a = \"\"\"open\n", "line 1: unterminated string");
+	rejected("a = '''open", "line 1: unterminated string");
+	rejected("a = \"\\x\"", "line 1: unsupported escape: Some('x')");
+	rejected("a = \"\\u12\"", "line 1: malformed escape: \"\\\\u12\"");
+}
+
+#[test]
+fn rejects_malformed_structure() {
+	rejected("a = [1 2]", "line 1: expected , or ] in array, got '2'");
+	rejected("a = [1,\n", "line 2: expected value, got end of file");
+	rejected("a = { x = 1,\n y = 2 }", "line 1: expected key, got '\\n'");
+	rejected(
+		"a = { x = 1\n}",
+		"line 1: inline table should be on a single line",
+	);
+	rejected("a = { x = 1, x = 2 }", "line 1: duplicate key \"x\"");
+	rejected("[a", "line 1: expected ], got end of file");
+	rejected("[[a]", "line 1: expected ]], got ']'");
+	rejected("[]", "line 1: expected key, got ']'");
+	rejected("a b = 1", "line 1: expected =, got 'b'");
+	rejected("[a] x = 1", "line 1: expected end of line, got 'x'");
+}
+
+#[test]
+fn rejects_redefinitions() {
+	rejected("a = 1\na = 2", "line 2: duplicate key \"a\"");
+	rejected("a.b = 1\na.b = 2", "line 2: duplicate key \"a.b\"");
+	rejected("[a]\n[a]", "line 2: \"a\" is already defined");
+	rejected("a = 1\n[a.b]", "line 2: \"a\" is not a table");
+	// Inline tables and arrays are complete as written
+	rejected("a = { x = 1 }\n[a]", "line 2: \"a\" is already defined");
+	rejected("a = { x = 1 }\na.y = 2", "line 2: \"a\" is already defined");
+	rejected("a = []\n[[a]]", "line 2: \"a\" is not an array of tables");
+	rejected("[[a]]\n[a]", "line 2: \"a\" is already defined");
+	// Dotted keys can't reopen a table defined elsewhere, and the other way around
+	rejected(
+		"[a.b]\nx = 1\n[a]\nb.y = 2",
+		"line 4: \"b\" is already defined",
+	);
+	rejected("[a]\nb.x = 1\n[a.b]", "line 3: \"a.b\" is already defined");
+}
+
+#[test]
+fn set_keys_keeps_the_rest() {
+	let data = "# header comment\nwine = \"old\" # comment\ncolor = [\n\t[1.0],\n]\nmode = 1\n\n[dynamic]\nkeep = true\n";
+	let out = toml::set_keys(
+		data,
+		&[
+			("wine", Some(toml::quote("/usr/bin/wine"))),
+			("color", None),
+			("proton", Some(toml::quote("p"))),
+			("absent", None),
+		],
+	)
+	.unwrap();
+	assert_eq!(
+		out,
+		"# header comment\nwine = \"/usr/bin/wine\"\nmode = 1\n\nproton = \"p\"\n[dynamic]\nkeep = true\n"
+	);
+	assert_eq!(
+		toml::set_keys("a = 1", &[("b", Some("2".to_owned()))]).unwrap(),
+		"a = 1\nb = 2\n"
+	);
+	assert_eq!(
+		toml::set_keys("", &[("b", Some("2".to_owned()))]).unwrap(),
+		"b = 2\n"
+	);
+	assert!(toml::set_keys("a = ", &[("b", None)]).is_err());
+}
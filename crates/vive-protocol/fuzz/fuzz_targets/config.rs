@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+	// zlib + json
+	let _ = vive_protocol::parse_steam_config(data);
+	// header + json
+	let _ = vive_protocol::parse_vive_config(data);
+	let _ = vive_protocol::parse_user_config(data);
+});
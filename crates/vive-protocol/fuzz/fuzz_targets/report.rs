@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use vive_protocol::{
+	decode_report, decode_steam_config_chunk, parse_region_size, FACTORY_CONFIG_REGION,
+	REGION_READ, REGION_SIZE,
+};
+
+fuzz_target!(|data: &[u8]| {
+	// Register reads
+	let _ = decode_report(data, 0x02, &[]);
+	// Config region reads
+	let _ = decode_report(data, 0x01, &[REGION_READ, FACTORY_CONFIG_REGION]);
+	if let Ok(payload) = decode_report(data, 0x01, &[REGION_SIZE, FACTORY_CONFIG_REGION]) {
+		let _ = parse_region_size(FACTORY_CONFIG_REGION, payload);
+	}
+	// Steam config feature reports
+	let _ = decode_steam_config_chunk(data);
+});
@@ -0,0 +1,95 @@
+//! Field-by-field comparison of configs, to see what firmware updates change
+use std::fmt;
+
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigChange {
+	Added {
+		path: String,
+		value: Value,
+	},
+	Removed {
+		path: String,
+		value: Value,
+	},
+	Changed {
+		path: String,
+		from: Value,
+		to: Value,
+	},
+}
+impl ConfigChange {
+	pub fn path(&self) -> &str {
+		match self {
+			Self::Added { path, .. } | Self::Removed { path, .. } | Self::Changed { path, .. } => {
+				path
+			}
+		}
+	}
+}
+impl fmt::Display for ConfigChange {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Added { path, value } => write!(f, "+ {path}: {value}"),
+			Self::Removed { path, value } => write!(f, "- {path}: {value}"),
+			Self::Changed { path, from, to } => write!(f, "~ {path}: {from} -> {to}"),
+		}
+	}
+}
+
+/// Only leaf values are reported, objects are descended into, arrays are compared by index
+pub fn config_diff(a: &Value, b: &Value) -> Vec<ConfigChange> {
+	let mut out = Vec::new();
+	diff_at("$".to_owned(), a, b, &mut out);
+	out
+}
+
+fn diff_at(path: String, a: &Value, b: &Value, out: &mut Vec<ConfigChange>) {
+	match (a, b) {
+		(Value::Object(a), Value::Object(b)) => {
+			for (key, av) in a {
+				let path = format!("{path}.{key}");
+				match b.get(key) {
+					Some(bv) => diff_at(path, av, bv, out),
+					None => out.push(ConfigChange::Removed {
+						path,
+						value: av.clone(),
+					}),
+				}
+			}
+			for (key, bv) in b {
+				if !a.contains_key(key) {
+					out.push(ConfigChange::Added {
+						path: format!("{path}.{key}"),
+						value: bv.clone(),
+					});
+				}
+			}
+		}
+		(Value::Array(a), Value::Array(b)) => {
+			for (i, av) in a.iter().enumerate() {
+				let path = format!("{path}[{i}]");
+				match b.get(i) {
+					Some(bv) => diff_at(path, av, bv, out),
+					None => out.push(ConfigChange::Removed {
+						path,
+						value: av.clone(),
+					}),
+				}
+			}
+			for (i, bv) in b.iter().enumerate().skip(a.len()) {
+				out.push(ConfigChange::Added {
+					path: format!("{path}[{i}]"),
+					value: bv.clone(),
+				});
+			}
+		}
+		(a, b) if a != b => out.push(ConfigChange::Changed {
+			path,
+			from: a.clone(),
+			to: b.clone(),
+		}),
+		_ => {}
+	}
+}
@@ -0,0 +1,101 @@
+//! Header, which precedes JSON in factory config region
+//!
+//! Layout isn't documented anywhere, so fields are located by content instead of fixed
+//! offsets: body starts at first `{`, length is a little-endian u32 equal to body length, and
+//! hash is a SHA-256 of body. Usually header is 128 bytes long.
+
+use crate::{sha256::sha256, Error, Result};
+
+/// Header is never seen to be longer, JSON might not be found at all with broken config
+const MAX_HEADER_SIZE: usize = 1024;
+/// Size of headers written by firmware
+const USUAL_HEADER_SIZE: usize = 128;
+
+#[derive(Debug, Clone)]
+pub struct ConfigHeader {
+	pub raw: Vec<u8>,
+	/// Leading printable ASCII, if there is any (i.e. format/model identifier)
+	pub identity: Option<String>,
+	/// Offset of `body_len` inside header, if it was found
+	pub body_len_offset: Option<usize>,
+	pub body_len: Option<u32>,
+	/// Offset of `sha256` inside header, if hash of body was found
+	pub sha256_offset: Option<usize>,
+	pub sha256: Option<[u8; 32]>,
+}
+impl ConfigHeader {
+	pub fn len(&self) -> usize {
+		self.raw.len()
+	}
+	pub fn is_empty(&self) -> bool {
+		self.raw.is_empty()
+	}
+	pub fn hash_verified(&self) -> bool {
+		self.sha256.is_some()
+	}
+
+	/// Region contents with header, which [`ConfigHeader::parse`] fully understands, for
+	/// simulated headset
+	pub fn encode(identity: &str, body: &[u8]) -> Vec<u8> {
+		let mut out = identity.as_bytes().to_vec();
+		out.push(0);
+		out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+		out.extend_from_slice(&sha256(body));
+		out.resize(out.len().max(USUAL_HEADER_SIZE), 0);
+		out.extend_from_slice(body);
+		out
+	}
+
+	/// Returns header and JSON body, trimmed to the length stored in header
+	pub fn parse(blob: &[u8]) -> Result<(Self, &[u8])> {
+		let body_offset = blob
+			.iter()
+			.take(MAX_HEADER_SIZE)
+			.enumerate()
+			.position(|(i, &b)| {
+				// Header is binary, so `{` alone might be a part of it
+				b == b'{' && matches!(blob.get(i + 1), Some(b'"' | b'\n' | b'\r' | b' ' | b'\t'))
+			})
+			.ok_or(Error::ConfigSizeMismatch)?;
+		let (raw, body) = blob.split_at(body_offset);
+
+		let body_len_offset = (0..raw.len().saturating_sub(3)).find(|&i| {
+			let len = u32::from_le_bytes([raw[i], raw[i + 1], raw[i + 2], raw[i + 3]]) as usize;
+			// Body might be padded up to region size
+			len > 0
+				&& len <= body.len()
+				&& body[len - 1] == b'}'
+				&& body[len..]
+					.iter()
+					.all(|b| matches!(b, 0 | 0xff) || b.is_ascii_whitespace())
+		});
+		let body_len = body_len_offset
+			.map(|i| u32::from_le_bytes([raw[i], raw[i + 1], raw[i + 2], raw[i + 3]]));
+		let body = match body_len {
+			Some(len) => &body[..len as usize],
+			None => body,
+		};
+
+		let hash = sha256(body);
+		let sha256_offset = raw.windows(32).position(|w| w == hash);
+
+		let identity_len = raw
+			.iter()
+			.position(|b| !b.is_ascii_graphic() && *b != b' ')
+			.unwrap_or(raw.len());
+		let identity =
+			(identity_len >= 4).then(|| String::from_utf8_lossy(&raw[..identity_len]).into_owned());
+
+		Ok((
+			Self {
+				raw: raw.to_vec(),
+				identity,
+				body_len_offset,
+				body_len,
+				sha256_offset,
+				sha256: sha256_offset.map(|_| hash),
+			},
+			body,
+		))
+	}
+}
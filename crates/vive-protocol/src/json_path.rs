@@ -0,0 +1,91 @@
+//! Recovers JSON path from the error position reported by serde_json, so config errors point
+//! to the field, and not only to a line/column in a blob the user has never seen.
+
+enum Frame {
+	Object { key: Option<String>, in_value: bool },
+	Array(usize),
+}
+
+fn offset_of(src: &str, line: usize, column: usize) -> usize {
+	let mut offset = 0;
+	for (i, l) in src.split_inclusive('\n').enumerate() {
+		if i + 1 == line {
+			return (offset + column.saturating_sub(1)).min(src.len());
+		}
+		offset += l.len();
+	}
+	src.len()
+}
+
+/// `line` and `column` are one-based, as in [`serde_json::Error`]
+pub fn json_path_at(src: &str, line: usize, column: usize) -> String {
+	let end = offset_of(src, line, column);
+	let bytes = &src.as_bytes()[..end];
+	let mut stack = Vec::new();
+	let mut i = 0;
+	while i < bytes.len() {
+		match bytes[i] {
+			b'{' => stack.push(Frame::Object {
+				key: None,
+				in_value: false,
+			}),
+			b'[' => stack.push(Frame::Array(0)),
+			b'}' | b']' => {
+				stack.pop();
+			}
+			b':' => {
+				if let Some(Frame::Object { in_value, .. }) = stack.last_mut() {
+					*in_value = true;
+				}
+			}
+			b',' => match stack.last_mut() {
+				Some(Frame::Object { key, in_value }) => {
+					*key = None;
+					*in_value = false;
+				}
+				Some(Frame::Array(idx)) => *idx += 1,
+				None => {}
+			},
+			b'"' => {
+				let start = i + 1;
+				i += 1;
+				while i < bytes.len() && bytes[i] != b'"' {
+					if bytes[i] == b'\\' {
+						i += 1;
+					}
+					i += 1;
+				}
+				if let Some(Frame::Object {
+					key,
+					in_value: false,
+				}) = stack.last_mut()
+				{
+					*key = Some(
+						String::from_utf8_lossy(&bytes[start..i.min(bytes.len())]).into_owned(),
+					);
+				}
+			}
+			_ => {}
+		}
+		i += 1;
+	}
+	// Errors about the object itself (i.e missing field) are reported at its closing brace
+	if src.as_bytes().get(end) == Some(&b'}') {
+		if let Some(Frame::Object { key, .. }) = stack.last_mut() {
+			*key = None;
+		}
+	}
+
+	let mut path = "$".to_owned();
+	for frame in stack {
+		match frame {
+			Frame::Object { key: Some(key), .. } => {
+				path.push('.');
+				path.push_str(&key);
+			}
+			Frame::Object { key: None, .. } => {}
+			Frame::Array(idx) => path.push_str(&format!("[{idx}]")),
+		}
+	}
+	path
+}
@@ -0,0 +1,1073 @@
+//! Report framing and config parsing for Vive Pro 2, without any I/O
+use std::{
+	fmt,
+	io::{self, Read, Write},
+	result,
+	str::FromStr,
+};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+mod diff;
+mod header;
+mod json_path;
+mod reports;
+mod sha256;
+mod survive;
+mod validate;
+
+pub use diff::{config_diff, ConfigChange};
+pub use header::ConfigHeader;
+pub use reports::{FeatureReport04, Report01Request, Report02Command};
+pub use survive::libsurvive_config;
+pub use validate::{validate_steam_config, validate_vive_config, ConfigIssue, FieldKind};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("config size mismatch")]
+	ConfigSizeMismatch,
+	#[error("failed to decompress config: {0}")]
+	Decompress(#[source] io::Error),
+	#[error("failed to parse config at {path}: {source}")]
+	ConfigParse {
+		path: String,
+		#[source]
+		source: serde_json::Error,
+	},
+	#[error("expected report {expected:02x}, got {actual:02x}: {report:02x?}")]
+	WrongReportId {
+		expected: u8,
+		actual: u8,
+		report: Vec<u8>,
+	},
+	#[error("report {id:02x}: expected prefix {expected:02x?}, got {actual:02x?}")]
+	WrongPrefix {
+		id: u8,
+		expected: Vec<u8>,
+		actual: Vec<u8>,
+	},
+	#[error("report {id:02x}: payload size {size} doesn't fit: {report:02x?}")]
+	WrongSize {
+		id: u8,
+		size: usize,
+		report: Vec<u8>,
+	},
+	#[error("report {id:02x}: payload of {size} bytes doesn't fit, at most {max} can be sent")]
+	PayloadTooLarge { id: u8, size: usize, max: usize },
+	#[error("report {id:02x}: unknown op {op:02x}")]
+	UnknownOp { id: u8, op: u8 },
+	#[error("region {region:02x}: expected 4 byte length, got {payload:02x?}")]
+	WrongRegionLength { region: u8, payload: Vec<u8> },
+	#[error("region {region:02x}: expected {expected} bytes, got {actual}")]
+	RegionSizeMismatch {
+		region: u8,
+		expected: usize,
+		actual: usize,
+	},
+	#[cfg(feature = "unverified-mura")]
+	#[error("mura data at {offset}+{size} is out of region bounds ({region_len})")]
+	MuraOutOfBounds {
+		offset: u32,
+		size: u32,
+		region_len: usize,
+	},
+	#[error("unknown resolution: {0}")]
+	UnknownResolution(u8),
+	#[error("invalid resolution {0:?}, expected WIDTHxHEIGHT@RATE")]
+	InvalidResolution(String),
+	#[error("invalid gpu tier {0:?}, expected low, mid or high")]
+	InvalidGpuTier(String),
+	#[error("{what} is not utf-8: {bytes:02x?}")]
+	NotUtf8 { what: &'static str, bytes: Vec<u8> },
+	#[error("user config is too large: {0} > {USER_CONFIG_MAX_SIZE}")]
+	UserConfigTooLarge(usize),
+	#[error("config has no {0}, tracking can't work without it")]
+	MissingCalibration(&'static str),
+}
+
+type Result<T, E = Error> = result::Result<T, E>;
+
+pub const REPORT_SIZE: usize = 64;
+pub type Report = [u8; REPORT_SIZE];
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConfigDevice {
+	pub eye_target_height_in_pixels: u32,
+	pub eye_target_width_in_pixels: u32,
+}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum DistortType {
+	#[serde(rename = "DISTORT_FTHETA")]
+	DistortFtheta,
+}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IntrinsicsDistort {
+	pub center_x: f32,
+	pub center_y: f32,
+	pub coeffs: Vec<f64>,
+	pub r#type: DistortType,
+}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfigCameraIntrinsics {
+	pub center_x: f32,
+	pub center_y: f32,
+	pub distort: IntrinsicsDistort,
+	pub focal_x: f32,
+	pub focal_y: f32,
+	pub width: u32,
+	pub height: u32,
+}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfigCamera {
+	pub name: String,
+	pub intrinsics: ConfigCameraIntrinsics,
+	pub extrinsics: Vec<u8>,
+}
+/// Lighthouse configs describe orientation by X and Z axis vectors, Y is implied
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct ConfigPose {
+	pub plus_x: [f64; 3],
+	pub plus_z: [f64; 3],
+	pub position: [f64; 3],
+}
+/// IMU mounting, and calibration of its sensors, if factory has written it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct ConfigImu {
+	#[serde(flatten)]
+	pub pose: ConfigPose,
+	#[serde(default)]
+	pub acc_bias: Option<[f64; 3]>,
+	#[serde(default)]
+	pub acc_scale: Option<[f64; 3]>,
+	#[serde(default)]
+	pub gyro_bias: Option<[f64; 3]>,
+	#[serde(default)]
+	pub gyro_scale: Option<[f64; 3]>,
+}
+/// Photodiodes, which base stations sweep, in tracking reference space
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfigLighthouse {
+	#[serde(rename = "modelPoints")]
+	pub model_points: Vec<[f64; 3]>,
+	#[serde(rename = "modelNormals")]
+	pub model_normals: Vec<[f64; 3]>,
+	/// Sensor index reported by hardware, for each model point
+	#[serde(default, rename = "channelMap")]
+	pub channel_map: Vec<u32>,
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct ConfigEyeTransform {
+	/// Row-major 3x4
+	pub eye_to_head: [[f64; 4]; 3],
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConfigAudio {
+	#[serde(default)]
+	pub device_name: Option<String>,
+	#[serde(default)]
+	pub microphone_gain: Option<f64>,
+	#[serde(flatten)]
+	pub extra: Map<String, Value>,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConfigCameraBlock {
+	#[serde(default)]
+	pub frame_rate: Option<u32>,
+	#[serde(default)]
+	pub exposure_time: Option<f64>,
+	#[serde(flatten)]
+	pub extra: Map<String, Value>,
+}
+/// Highest config schema version, for which all the fields are known
+pub const KNOWN_CONFIG_VERSION: ConfigVersion = ConfigVersion(1);
+
+/// Configs written by older firmware don't declare their version, those are treated as version 1
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct ConfigVersion(pub u32);
+impl Default for ConfigVersion {
+	fn default() -> Self {
+		Self(1)
+	}
+}
+impl ConfigVersion {
+	/// Newer schemas are still parsed, but fields added in them end up in `extra`
+	pub fn is_known(self) -> bool {
+		self <= KNOWN_CONFIG_VERSION
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SteamConfig {
+	#[serde(default, alias = "config_version")]
+	pub version: ConfigVersion,
+	pub device: ConfigDevice,
+	#[serde(default)]
+	pub tracked_cameras: Vec<ConfigCamera>,
+	/// Display EDID ids, missing in some configs, as are timings, see [`Self::direct_mode_edid`]
+	#[serde(default)]
+	pub direct_mode_edid_pid: Option<u32>,
+	#[serde(default)]
+	pub direct_mode_edid_vid: Option<u32>,
+	#[serde(default)]
+	pub seconds_from_photons_to_vblank: Option<f64>,
+	#[serde(default)]
+	pub seconds_from_vsync_to_photons: Option<f64>,
+	/// SN of ViveDevice
+	pub mb_serial_number: String,
+	/// SN of this device, the one shown by SteamVR
+	#[serde(default)]
+	pub device_serial_number: Option<String>,
+	#[serde(default)]
+	pub device_class: Option<String>,
+	/// Tracking reference (IMU) to head transform
+	#[serde(default)]
+	pub head: Option<ConfigPose>,
+	#[serde(default)]
+	pub imu: Option<ConfigImu>,
+	#[serde(default)]
+	pub lighthouse_config: Option<ConfigLighthouse>,
+	/// Display to head transforms, left eye first
+	#[serde(default)]
+	pub tracking_to_eye_transform: Vec<ConfigEyeTransform>,
+	#[serde(default)]
+	pub audio: Option<ConfigAudio>,
+	#[serde(default)]
+	pub camera: Option<ConfigCameraBlock>,
+}
+impl SteamConfig {
+	/// `(vid, pid)`, as [`Self::direct_mode_edid_vid`] and pid store them, if config has both
+	pub fn direct_mode_edid(&self) -> Option<(u32, u32)> {
+		Some((self.direct_mode_edid_vid?, self.direct_mode_edid_pid?))
+	}
+}
+
+/// Location of per-eye mura correction data, which is assumed to be stored outside of the config
+/// json. Unverified, see [`MURA_REGION`]
+#[cfg(feature = "unverified-mura")]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MuraReference {
+	/// Offset in the mura region
+	pub offset: u32,
+	pub size: u32,
+	#[serde(default)]
+	pub width: Option<u32>,
+	#[serde(default)]
+	pub height: Option<u32>,
+	#[serde(default)]
+	pub format: Option<String>,
+}
+#[cfg(feature = "unverified-mura")]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MuraCorrection {
+	#[serde(default)]
+	pub left: Option<MuraReference>,
+	#[serde(default)]
+	pub right: Option<MuraReference>,
+	#[serde(flatten)]
+	pub extra: Map<String, Value>,
+}
+/// Mura correction data for a single panel, as stored on device
+#[cfg(feature = "unverified-mura")]
+#[derive(Debug, Clone)]
+pub struct MuraData {
+	pub width: Option<u32>,
+	pub height: Option<u32>,
+	/// Sample format, as specified in config, data is passed as-is
+	pub format: Option<String>,
+	pub data: Vec<u8>,
+}
+#[cfg(feature = "unverified-mura")]
+#[derive(Debug, Clone, Default)]
+pub struct MuraCorrectionData {
+	pub left: Option<MuraData>,
+	pub right: Option<MuraData>,
+}
+
+#[cfg(feature = "unverified-mura")]
+pub fn extract_mura(region: &[u8], reference: &MuraReference) -> Result<MuraData> {
+	let data = region
+		.get(reference.offset as usize..)
+		.and_then(|r| r.get(..reference.size as usize))
+		.ok_or(Error::MuraOutOfBounds {
+			offset: reference.offset,
+			size: reference.size,
+			region_len: region.len(),
+		})?;
+	Ok(MuraData {
+		width: reference.width,
+		height: reference.height,
+		format: reference.format.clone(),
+		data: data.to_vec(),
+	})
+}
+#[cfg(feature = "unverified-mura")]
+pub fn extract_mura_correction(
+	region: &[u8],
+	correction: &MuraCorrection,
+) -> Result<MuraCorrectionData> {
+	Ok(MuraCorrectionData {
+		left: correction
+			.left
+			.as_ref()
+			.map(|r| extract_mura(region, r))
+			.transpose()?,
+		right: correction
+			.right
+			.as_ref()
+			.map(|r| extract_mura(region, r))
+			.transpose()?,
+	})
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PanelInfo {
+	#[serde(default)]
+	pub vendor: Option<String>,
+	#[serde(default)]
+	pub model: Option<String>,
+	#[serde(default)]
+	pub serial_number: Option<String>,
+	#[serde(default)]
+	pub manufacture_date: Option<String>,
+	#[serde(flatten)]
+	pub extra: Map<String, Value>,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LensMetadata {
+	#[serde(default)]
+	pub model: Option<String>,
+	#[serde(default)]
+	pub version: Option<String>,
+	#[serde(flatten)]
+	pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ViveConfig {
+	#[serde(default, alias = "config_version")]
+	pub version: ConfigVersion,
+	pub device: ConfigDevice,
+	/// Missing in some configs, as are timings
+	#[serde(default)]
+	pub direct_mode_edid_pid: Option<u32>,
+	#[serde(default)]
+	pub direct_mode_edid_vid: Option<u32>,
+	#[serde(default)]
+	pub seconds_from_photons_to_vblank: Option<f64>,
+	#[serde(default)]
+	pub seconds_from_vsync_to_photons: Option<f64>,
+	/// Lets threat it as something opaque, anyway we directly feed this to lens-client
+	pub inhouse_lens_correction: Value,
+	/// Without `unverified-mura` feature, it is kept in [`Self::extra`]
+	#[cfg(feature = "unverified-mura")]
+	#[serde(default)]
+	pub mura_correction: Option<MuraCorrection>,
+	#[serde(default)]
+	pub panel: Option<PanelInfo>,
+	#[serde(default)]
+	pub lens: Option<LensMetadata>,
+	/// Everything not known yet
+	#[serde(flatten)]
+	pub extra: Map<String, Value>,
+}
+
+/// Factory-written config, read-only
+pub const FACTORY_CONFIG_REGION: u8 = 0xb1;
+/// Region, which is assumed to be left for the vendor software to store its settings.
+///
+/// Unverified: it is the next id after [`FACTORY_CONFIG_REGION`], no capture shows VIVE Console
+/// reading or writing it
+pub const USER_CONFIG_REGION: u8 = 0xb2;
+/// Panel uniformity data, referenced by [`MuraCorrection`].
+///
+/// Unverified hypothesis, as is the layout of [`MuraCorrection`]: region id is the next one after
+/// [`USER_CONFIG_REGION`], `mura_correction` config key with per-panel offset and size is made
+/// up. No capture or config dump of a real headset shows either, so both
+/// are only built with `unverified-mura` feature. Would be confirmed by a factory config dump
+/// which has such key, and a region size request to this id returning a length which fits it
+#[cfg(feature = "unverified-mura")]
+pub const MURA_REGION: u8 = 0xb3;
+/// 63 byte request - 7 bytes of header
+pub const REGION_CHUNK_SIZE: usize = 56;
+pub const USER_CONFIG_MAX_SIZE: usize = 4096;
+
+pub const REGION_SIZE: u8 = 0xea;
+pub const REGION_READ: u8 = 0xeb;
+/// Unverified hypothesis, as is [`REGION_COMMIT`]: next opcodes after [`REGION_READ`], with the
+/// same header layout. Would be confirmed by a USB capture (i.e USBPcap) of VIVE Console on
+/// Windows saving a setting, showing report 0x01 requests with these opcodes to
+/// [`USER_CONFIG_REGION`], and a region read afterwards returning the written bytes. Only sent
+/// by vive-hid with `unverified-flash-write` feature
+pub const REGION_WRITE: u8 = 0xec;
+pub const REGION_COMMIT: u8 = 0xed;
+
+/// Settings, which are persisted on the device itself, and thus survive replugging
+/// and usage on other hosts.
+///
+/// Unset fields are left for the driver to decide.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct UserConfig {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub brightness: Option<u8>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub noise_canceling: Option<bool>,
+	/// Per-channel (r, g, b) display gain
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub color_gain: Option<[f32; 3]>,
+	/// White point in kelvin, applied on top of `color_gain`
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub color_temperature: Option<u32>,
+}
+impl UserConfig {
+	/// Gain for SteamVR to apply, `None` if color was never calibrated
+	pub fn display_gain(&self) -> Option<[f32; 3]> {
+		combined_gain(self.color_gain, self.color_temperature)
+	}
+}
+
+/// Panels are calibrated to D65
+pub const NATIVE_COLOR_TEMPERATURE: u32 = 6500;
+
+/// Planckian locus approximation (Tanner Helland), good enough for 1000-40000K
+fn blackbody(kelvin: u32) -> [f32; 3] {
+	let t = kelvin.clamp(1000, 40000) as f32 / 100.0;
+	let r = if t <= 66.0 {
+		255.0
+	} else {
+		329.69873 * (t - 60.0).powf(-0.13320476)
+	};
+	let g = if t <= 66.0 {
+		99.4708 * t.ln() - 161.11957
+	} else {
+		288.12216 * (t - 60.0).powf(-0.07551485)
+	};
+	let b = if t >= 66.0 {
+		255.0
+	} else if t <= 19.0 {
+		0.0
+	} else {
+		138.51773 * (t - 10.0).ln() - 305.0448
+	};
+	[r, g, b].map(|c| c.clamp(0.0, 255.0) / 255.0)
+}
+
+/// Gain, which moves panel white point to `kelvin`. Brightest channel is kept at 1, as gains
+/// above it clip
+pub fn color_temperature_gain(kelvin: u32) -> [f32; 3] {
+	let target = blackbody(kelvin);
+	let native = blackbody(NATIVE_COLOR_TEMPERATURE);
+	let gain = [0, 1, 2].map(|i| target[i] / native[i]);
+	let max = gain.into_iter().fold(f32::EPSILON, f32::max);
+	gain.map(|g| g / max)
+}
+
+/// Explicit gain, with white point shift applied on top
+pub fn combined_gain(gain: Option<[f32; 3]>, temperature: Option<u32>) -> Option<[f32; 3]> {
+	match (gain, temperature.map(color_temperature_gain)) {
+		(Some(gain), Some(shift)) => Some([0, 1, 2].map(|i| gain[i] * shift[i])),
+		(gain, shift) => gain.or(shift),
+	}
+}
+
+#[derive(Clone, Copy)]
+pub struct Mode {
+	pub id: u8,
+
+	pub width: u32,
+	pub height: u32,
+	pub frame_rate: f32,
+	pub extra_photon_vsync: f32,
+}
+impl Mode {
+	const fn new(
+		id: u8,
+		width: u32,
+		height: u32,
+		frame_rate: f32,
+		extra_photon_vsync: f32,
+	) -> Self {
+		Self {
+			id,
+			width,
+			height,
+			frame_rate,
+			extra_photon_vsync,
+		}
+	}
+}
+
+pub const VIVE_PRO_2_MODES: [Mode; 6] = [
+	Mode::new(0, 2448, 1224, 90.0, 0.0),
+	Mode::new(1, 2448, 1224, 120.0, 0.0),
+	Mode::new(2, 3264, 1632, 90.0, 0.00297),
+	Mode::new(3, 3672, 1836, 90.0, 0.00332),
+	Mode::new(4, 4896, 2448, 90.0, 0.0),
+	Mode::new(5, 4896, 2448, 120.0, 0.0),
+];
+
+/// Named version of [`Mode::id`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Resolution {
+	R2448x1224f90 = 0,
+	R2448x1224f120 = 1,
+	R3264x1632f90 = 2,
+	R3672x1836f90 = 3,
+	R4896x2448f90 = 4,
+	R4896x2448f120 = 5,
+}
+impl Resolution {
+	pub const ALL: [Self; 6] = [
+		Self::R2448x1224f90,
+		Self::R2448x1224f120,
+		Self::R3264x1632f90,
+		Self::R3672x1836f90,
+		Self::R4896x2448f90,
+		Self::R4896x2448f120,
+	];
+	pub fn mode(self) -> Mode {
+		VIVE_PRO_2_MODES[self as usize]
+	}
+}
+impl TryFrom<u8> for Resolution {
+	type Error = Error;
+
+	fn try_from(value: u8) -> Result<Self> {
+		Self::ALL
+			.get(value as usize)
+			.copied()
+			.ok_or(Error::UnknownResolution(value))
+	}
+}
+impl fmt::Display for Resolution {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let Mode {
+			width,
+			height,
+			frame_rate,
+			..
+		} = self.mode();
+		write!(f, "{width}x{height}@{frame_rate}")
+	}
+}
+/// `WxH` with optional `@RATE`
+fn parse_mode_spec(s: &str) -> Result<(u32, u32, Option<f32>)> {
+	let invalid = || Error::InvalidResolution(s.to_owned());
+	let (size, rate) = match s.split_once('@') {
+		Some((size, rate)) => (size, Some(rate.parse::<f32>().map_err(|_| invalid())?)),
+		None => (s, None),
+	};
+	let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+	let width: u32 = width.parse().map_err(|_| invalid())?;
+	let height: u32 = height.parse().map_err(|_| invalid())?;
+	Ok((width, height, rate))
+}
+
+impl Resolution {
+	/// Closest supported mode, every part of request is optional
+	///
+	/// Size and rate differences are compared relatively, so `3680x1836@100` picks
+	/// `3672x1836@90`, and `2448x1224@110` picks `2448x1224@120`
+	pub fn nearest(width: Option<u32>, height: Option<u32>, rate: Option<f32>) -> Self {
+		let diff = |a: f32, b: f32| (a / b).ln().abs();
+		let score = |r: &Self| {
+			let mode = r.mode();
+			let mut score = 0.0;
+			if let Some(width) = width {
+				score += diff(mode.width as f32, width as f32);
+			}
+			if let Some(height) = height {
+				score += diff(mode.height as f32, height as f32);
+			}
+			if let Some(rate) = rate {
+				score += diff(mode.frame_rate, rate);
+			}
+			score
+		};
+		Self::ALL
+			.into_iter()
+			.min_by(|a, b| score(a).total_cmp(&score(b)))
+			.expect("at least one resolution exists")
+	}
+	/// Same syntax as [`FromStr`], but doesn't require exact match
+	pub fn nearest_to(s: &str) -> Result<Self> {
+		if let Ok(id) = s.parse::<u8>() {
+			return Self::try_from(id);
+		}
+		let (width, height, rate) = parse_mode_spec(s)?;
+		Ok(Self::nearest(Some(width), Some(height), rate))
+	}
+}
+
+/// Rough GPU class, for picking a mode, headset refreshes at mode rate no matter how
+/// fast frames are rendered, and reprojected frames are worse than lower resolution
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GpuTier {
+	/// GTX 1060/RX 580 class
+	Low,
+	/// RTX 2070/RX 6600 class
+	Mid,
+	High,
+}
+impl GpuTier {
+	pub const ALL: [Self; 3] = [Self::Low, Self::Mid, Self::High];
+	/// Pixels per second rendered at stable rate, at 100% SteamVR resolution
+	pub fn pixel_rate(self) -> f32 {
+		match self {
+			Self::Low => 400e6,
+			Self::Mid => 700e6,
+			Self::High => f32::INFINITY,
+		}
+	}
+	/// Guess by video memory, for when nothing better is known
+	pub fn from_vram(bytes: u64) -> Self {
+		const GIB: u64 = 1 << 30;
+		if bytes <= 6 * GIB {
+			Self::Low
+		} else if bytes <= 10 * GIB {
+			Self::Mid
+		} else {
+			Self::High
+		}
+	}
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Low => "low",
+			Self::Mid => "mid",
+			Self::High => "high",
+		}
+	}
+}
+impl FromStr for GpuTier {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self> {
+		Self::ALL
+			.into_iter()
+			.find(|t| t.name() == s)
+			.ok_or_else(|| Error::InvalidGpuTier(s.to_owned()))
+	}
+}
+impl fmt::Display for GpuTier {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+/// Accepts `4896x2448@120`, `4896x2448` (lowest refresh rate), or raw mode index
+impl FromStr for Resolution {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self> {
+		let invalid = || Error::InvalidResolution(s.to_owned());
+		if let Ok(id) = s.parse::<u8>() {
+			return Self::try_from(id);
+		}
+		let (width, height, rate) = parse_mode_spec(s)?;
+		Self::ALL
+			.into_iter()
+			.find(|r| {
+				let mode = r.mode();
+				mode.width == width
+					&& mode.height == height
+					&& (rate.is_none() || rate == Some(mode.frame_rate))
+			})
+			.ok_or_else(invalid)
+	}
+}
+
+/// Mode as reported by device, future firmware may add modes not covered by [`Resolution`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplayMode {
+	Known(Resolution),
+	Unknown {
+		id: u8,
+		width: u32,
+		height: u32,
+		frame_rate: f32,
+	},
+}
+impl DisplayMode {
+	pub fn id(&self) -> u8 {
+		match self {
+			Self::Known(r) => *r as u8,
+			Self::Unknown { id, .. } => *id,
+		}
+	}
+}
+impl From<Mode> for DisplayMode {
+	fn from(mode: Mode) -> Self {
+		match Resolution::try_from(mode.id) {
+			Ok(r) if r.mode().width == mode.width && r.mode().height == mode.height => {
+				Self::Known(r)
+			}
+			_ => Self::Unknown {
+				id: mode.id,
+				width: mode.width,
+				height: mode.height,
+				frame_rate: mode.frame_rate,
+			},
+		}
+	}
+}
+impl fmt::Display for DisplayMode {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Known(r) => r.fmt(f),
+			Self::Unknown {
+				id,
+				width,
+				height,
+				frame_rate,
+			} => write!(f, "{width}x{height}@{frame_rate} (unknown mode {id})"),
+		}
+	}
+}
+impl Mode {
+	pub fn display_mode(self) -> DisplayMode {
+		self.into()
+	}
+}
+
+/// `xxd`-like dump, 16 bytes per line with offset and ascii, for logging reports which failed
+/// to decode
+pub fn hexdump(data: &[u8]) -> String {
+	let mut out = String::new();
+	for (i, line) in data.chunks(16).enumerate() {
+		if i != 0 {
+			out.push('\n');
+		}
+		let hex = line
+			.iter()
+			.map(|b| format!("{b:02x}"))
+			.collect::<Vec<_>>()
+			.join(" ");
+		let ascii = line
+			.iter()
+			.map(|&b| {
+				if b.is_ascii_graphic() || b == b' ' {
+					b as char
+				} else {
+					'.'
+				}
+			})
+			.collect::<String>();
+		out.push_str(&format!("{:04x}  {hex:<47}  |{ascii}|", i * 16));
+	}
+	out
+}
+
+pub(crate) fn check_payload(id: u8, data: &[u8], max: usize) -> Result<()> {
+	if data.len() > max {
+		return Err(Error::PayloadTooLarge {
+			id,
+			size: data.len(),
+			max,
+		});
+	}
+	Ok(())
+}
+/// `data` is at most 63 bytes
+pub fn encode_report(id: u8, data: &[u8]) -> Result<Report> {
+	check_payload(id, data, REPORT_SIZE - 1)?;
+	let mut report = [0u8; REPORT_SIZE];
+	report[0] = id;
+	report[1..1 + data.len()].copy_from_slice(data);
+	Ok(report)
+}
+/// `data` is at most 60 bytes
+pub fn encode_feature_report(id: u8, sub_id: u16, data: &[u8]) -> Result<Report> {
+	check_payload(id, data, REPORT_SIZE - 4)?;
+	let mut report = [0u8; REPORT_SIZE];
+	report[0] = id;
+	report[1] = (sub_id & 0xff) as u8;
+	report[2] = (sub_id >> 8) as u8;
+	report[3] = data.len() as u8;
+	report[4..][..data.len()].copy_from_slice(data);
+	Ok(report)
+}
+/// Validates report id and prefix of reply, and returns its payload
+pub fn decode_report<'r>(report: &'r [u8], id: u8, strip_prefix: &[u8]) -> Result<&'r [u8]> {
+	match report.first() {
+		Some(&actual) if actual == id => {}
+		actual => {
+			return Err(Error::WrongReportId {
+				expected: id,
+				actual: actual.copied().unwrap_or(0),
+				report: report.to_vec(),
+			})
+		}
+	}
+	let wrong_size = |size| Error::WrongSize {
+		id,
+		size,
+		report: report.to_vec(),
+	};
+	let prefix = report
+		.get(1..1 + strip_prefix.len())
+		.ok_or_else(|| wrong_size(0))?;
+	if prefix != strip_prefix {
+		return Err(Error::WrongPrefix {
+			id,
+			expected: strip_prefix.to_vec(),
+			actual: prefix.to_vec(),
+		});
+	}
+	let size = *report
+		.get(1 + strip_prefix.len())
+		.ok_or_else(|| wrong_size(0))? as usize;
+	report
+		.get(strip_prefix.len() + 2..strip_prefix.len() + 2 + size)
+		.ok_or_else(|| wrong_size(size))
+}
+/// Steam config is transferred in feature reports 17, returns `None` on the last one
+pub fn decode_steam_config_chunk(report: &[u8]) -> Result<Option<&[u8]>> {
+	let wrong_size = |size| Error::WrongSize {
+		id: 17,
+		size,
+		report: report.to_vec(),
+	};
+	let size = *report.get(1).ok_or_else(|| wrong_size(0))? as usize;
+	if size == 0 {
+		return Ok(None);
+	}
+	report
+		.get(2..2 + size)
+		.map(Some)
+		.ok_or_else(|| wrong_size(size))
+}
+
+/// Unsolicited input report with mainboard sensors state
+///
+/// Layout is the same as for original Vive/Vive Pro mainboard
+pub const MAINBOARD_STATUS_REPORT: u8 = 0x04;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MainboardStatus {
+	/// Raw value, grows with distance between lenses
+	pub lens_separation: u16,
+	pub button: u8,
+	pub proximity_change: u8,
+	/// Raw sensor value, larger means closer
+	pub proximity: u16,
+	/// Raw IPD knob position
+	pub ipd: u16,
+}
+impl MainboardStatus {
+	/// Knob position is in hundredths of millimeter, same as on original Vive
+	pub fn ipd_meters(&self) -> f32 {
+		self.ipd as f32 / 100_000.0
+	}
+}
+/// Returns `None` for any other report
+pub fn decode_mainboard_status(report: &[u8]) -> Option<MainboardStatus> {
+	if report.first() != Some(&MAINBOARD_STATUS_REPORT) || report.len() < 18 {
+		return None;
+	}
+	let u16_at = |i: usize| u16::from_le_bytes([report[i], report[i + 1]]);
+	Some(MainboardStatus {
+		lens_separation: u16_at(4),
+		button: report[8],
+		proximity_change: report[12],
+		proximity: u16_at(14),
+		ipd: u16_at(16),
+	})
+}
+
+/// Inverse of [`decode_mainboard_status`]
+pub fn encode_mainboard_status(status: &MainboardStatus) -> Report {
+	let mut report = [0u8; REPORT_SIZE];
+	report[0] = MAINBOARD_STATUS_REPORT;
+	report[4..6].copy_from_slice(&status.lens_separation.to_le_bytes());
+	report[8] = status.button;
+	report[12] = status.proximity_change;
+	report[14..16].copy_from_slice(&status.proximity.to_le_bytes());
+	report[16..18].copy_from_slice(&status.ipd.to_le_bytes());
+	report
+}
+
+pub fn region_size_request(region: u8) -> [u8; 2] {
+	[REGION_SIZE, region]
+}
+pub fn parse_region_size(region: u8, payload: &[u8]) -> Result<usize> {
+	if payload.len() != 4 {
+		return Err(Error::WrongRegionLength {
+			region,
+			payload: payload.to_vec(),
+		});
+	}
+	let mut total_len = [0u8; 4];
+	total_len.copy_from_slice(&payload[0..4]);
+	Ok(u32::from_le_bytes(total_len) as usize)
+}
+pub fn region_read_request(region: u8, offset: u32) -> [u8; 63] {
+	let mut req = [0; 63];
+	req[0] = REGION_READ;
+	req[1] = region;
+	req[2] = 0x04;
+	req[3..7].copy_from_slice(&u32::to_le_bytes(offset));
+	req
+}
+/// `chunk` is at most [`REGION_CHUNK_SIZE`] bytes, opcode is unverified, see [`REGION_WRITE`]
+pub fn region_write_request(region: u8, offset: u32, chunk: &[u8]) -> Result<[u8; 63]> {
+	check_payload(Report01Request::ID, chunk, REGION_CHUNK_SIZE)?;
+	let mut req = [0; 63];
+	req[0] = REGION_WRITE;
+	req[1] = region;
+	req[2] = chunk.len() as u8;
+	req[3..7].copy_from_slice(&u32::to_le_bytes(offset));
+	req[7..][..chunk.len()].copy_from_slice(chunk);
+	Ok(req)
+}
+/// Assumed to make device persist region once it receives final length, unverified, see
+/// [`REGION_WRITE`]
+pub fn region_commit_request(region: u8, len: u32) -> [u8; 7] {
+	let mut req = [0; 7];
+	req[0] = REGION_COMMIT;
+	req[1] = region;
+	req[2] = 0x04;
+	req[3..7].copy_from_slice(&u32::to_le_bytes(len));
+	req
+}
+
+fn parse_json<T: DeserializeOwned>(src: &str) -> Result<T> {
+	serde_json::from_str(src).map_err(|source| Error::ConfigParse {
+		path: json_path::json_path_at(src, source.line(), source.column()),
+		source,
+	})
+}
+fn parse_utf8<'b>(what: &'static str, bytes: &'b [u8]) -> Result<&'b str> {
+	std::str::from_utf8(bytes).map_err(|e| Error::NotUtf8 {
+		what,
+		bytes: bytes[e.valid_up_to()..].iter().copied().take(16).collect(),
+	})
+}
+
+/// Config, as stored on device, for archiving/bug reports
+#[derive(Debug, Clone)]
+pub struct RawConfig {
+	/// Empty for steam config
+	pub header: Vec<u8>,
+	/// Zlib-compressed for steam config, plain JSON bytes for vive config
+	pub blob: Vec<u8>,
+	/// `None` when blob can't be decompressed/isn't valid UTF-8
+	pub json: Option<String>,
+}
+
+fn decompress_steam_config(compressed: impl Read) -> Result<String> {
+	let mut dec = ZlibDecoder::new(compressed);
+	let mut out = String::new();
+	dec.read_to_string(&mut out).map_err(Error::Decompress)?;
+	Ok(out)
+}
+pub fn parse_steam_config(compressed: &[u8]) -> Result<SteamConfig> {
+	parse_steam_config_reader(compressed)
+}
+/// Decompresses while reading, so compressed config doesn't need to be collected first
+pub fn parse_steam_config_reader(compressed: impl Read) -> Result<SteamConfig> {
+	parse_json(&decompress_steam_config(compressed)?)
+}
+/// For already decompressed config, i.e from [`RawConfig::json`]
+pub fn parse_steam_config_json(json: &str) -> Result<SteamConfig> {
+	parse_json(json)
+}
+/// Inverse of [`parse_steam_config`], for simulated tracking board
+pub fn compress_steam_config(json: &str) -> Vec<u8> {
+	let mut enc = ZlibEncoder::new(Vec::new(), Compression::default());
+	enc.write_all(json.as_bytes())
+		.expect("writing to vec doesn't fail");
+	enc.finish().expect("writing to vec doesn't fail")
+}
+pub fn raw_steam_config(compressed: &[u8]) -> RawConfig {
+	RawConfig {
+		header: vec![],
+		blob: compressed.to_vec(),
+		json: decompress_steam_config(compressed).ok(),
+	}
+}
+pub fn parse_vive_config(blob: &[u8]) -> Result<ViveConfig> {
+	let (_, body) = ConfigHeader::parse(blob)?;
+	parse_json(parse_utf8("config", body)?)
+}
+/// For config without header, i.e from [`RawConfig::json`]
+pub fn parse_vive_config_json(json: &str) -> Result<ViveConfig> {
+	parse_json(json)
+}
+/// Doesn't fail, so config may be dumped even if it is not understood at all
+pub fn raw_vive_config(blob: &[u8]) -> RawConfig {
+	let (header, body) = match ConfigHeader::parse(blob) {
+		Ok((header, body)) => (header.raw, body),
+		// Header is usually this long
+		Err(_) => {
+			let split = blob.len().min(128);
+			(blob[..split].to_vec(), &blob[split..])
+		}
+	};
+	RawConfig {
+		header,
+		blob: body.to_vec(),
+		json: std::str::from_utf8(body).ok().map(ToOwned::to_owned),
+	}
+}
+/// Accepts any form config may be dumped in: plain JSON, vive config region (with header), or
+/// compressed steam config
+pub fn parse_config_dump(bytes: &[u8]) -> Result<Value> {
+	if bytes.first() == Some(&b'{') {
+		return parse_json(parse_utf8("config", bytes)?);
+	}
+	if let Ok(json) = decompress_steam_config(bytes) {
+		return parse_json(&json);
+	}
+	let (_, body) = ConfigHeader::parse(bytes)?;
+	parse_json(parse_utf8("config", body)?)
+}
+/// Returns default config if nothing was written to the user region yet
+pub fn parse_user_config(blob: &[u8]) -> Result<UserConfig> {
+	// Erased flash reads as 0xff
+	if blob.iter().all(|&b| b == 0 || b == 0xff) {
+		return Ok(UserConfig::default());
+	}
+	parse_json(parse_utf8("user config", blob)?)
+}
+pub fn serialize_user_config(config: &UserConfig) -> Result<Vec<u8>> {
+	let data = serde_json::to_vec(config).expect("user config is serializable");
+	if data.len() > USER_CONFIG_MAX_SIZE {
+		return Err(Error::UserConfigTooLarge(data.len()));
+	}
+	Ok(data)
+}
+
+pub fn set_brightness_command(brightness: u8) -> String {
+	format!("setbrightness,{}", brightness.min(130))
+}
+pub fn set_mode_command(resolution: u8) -> String {
+	format!("dtd,{}", resolution)
+}
+pub fn noise_canceling_commands(enabled: bool) -> &'static [&'static [u8]] {
+	const ENABLE: &[&[u8]] = &[
+		b"codecreg=9c9,80".as_slice(),
+		b"codecreg=9c8,a5",
+		b"codecreg=9d0,a4",
+		b"codecreg=1c008f,1",
+		b"codecreg=1c0005,9",
+		b"codecreg=1c0005,8000",
+	];
+	const DISABLE: &[&[u8]] = &[
+		b"codecreg=9c9,8c".as_slice(),
+		b"codecreg=9c8,a4",
+		b"codecreg=9d0,0",
+		b"codecreg=1c008f,0",
+		b"codecreg=1c0005,9",
+		b"codecreg=1c0005,8000",
+	];
+	// I have no idea what those values mean, this is straight
+	// copy-pasta from what original vive console sends
+	if enabled {
+		ENABLE
+	} else {
+		DISABLE
+	}
+}
@@ -0,0 +1,228 @@
+//! Typed requests, so device code doesn't need to know report ids, sub-ids and prefixes
+
+use crate::{
+	check_payload, decode_report, encode_feature_report, encode_report, region_commit_request,
+	region_read_request, region_size_request, region_write_request, Error, Report, Result,
+	REGION_CHUNK_SIZE, REGION_COMMIT, REGION_READ, REGION_SIZE, REGION_WRITE, REPORT_SIZE,
+};
+
+fn check_id(report: &[u8], id: u8) -> Result<()> {
+	match report.first() {
+		Some(&actual) if actual == id => Ok(()),
+		actual => Err(Error::WrongReportId {
+			expected: id,
+			actual: actual.copied().unwrap_or(0),
+			report: report.to_vec(),
+		}),
+	}
+}
+/// Reply is id, prefix, payload size, and payload
+fn encode_reply(id: u8, prefix: &[u8], payload: &[u8]) -> Result<Report> {
+	check_payload(id, payload, REPORT_SIZE - 2 - prefix.len())?;
+	let mut data = prefix.to_vec();
+	data.push(payload.len() as u8);
+	data.extend_from_slice(payload);
+	encode_report(id, &data)
+}
+
+/// Report 0x01, flash region access. Reply echoes op and region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Report01Request<'d> {
+	RegionSize {
+		region: u8,
+	},
+	RegionRead {
+		region: u8,
+		offset: u32,
+	},
+	/// `chunk` is at most [`crate::REGION_CHUNK_SIZE`] bytes, see [`Report01Request::region_write`]
+	RegionWrite {
+		region: u8,
+		offset: u32,
+		chunk: &'d [u8],
+	},
+	RegionCommit {
+		region: u8,
+		len: u32,
+	},
+}
+impl Report01Request<'_> {
+	pub const ID: u8 = 0x01;
+
+	/// Fails with [`Error::PayloadTooLarge`] if write chunk doesn't fit
+	pub fn encode(&self) -> Result<Report> {
+		match *self {
+			Self::RegionSize { region } => encode_report(Self::ID, &region_size_request(region)),
+			Self::RegionRead { region, offset } => {
+				encode_report(Self::ID, &region_read_request(region, offset))
+			}
+			Self::RegionWrite {
+				region,
+				offset,
+				chunk,
+			} => encode_report(Self::ID, &region_write_request(region, offset, chunk)?),
+			Self::RegionCommit { region, len } => {
+				encode_report(Self::ID, &region_commit_request(region, len))
+			}
+		}
+	}
+	pub fn reply_prefix(&self) -> [u8; 2] {
+		match *self {
+			Self::RegionSize { region } => [REGION_SIZE, region],
+			Self::RegionRead { region, .. } => [REGION_READ, region],
+			Self::RegionWrite { region, .. } => [REGION_WRITE, region],
+			Self::RegionCommit { region, .. } => [REGION_COMMIT, region],
+		}
+	}
+	pub fn decode_reply<'r>(&self, report: &'r [u8]) -> Result<&'r [u8]> {
+		decode_report(report, Self::ID, &self.reply_prefix())
+	}
+	/// Inverse of [`Report01Request::decode_reply`], for simulated headset.
+	/// Payload is at most 60 bytes
+	pub fn encode_reply(&self, payload: &[u8]) -> Result<Report> {
+		encode_reply(Self::ID, &self.reply_prefix(), payload)
+	}
+}
+impl<'d> Report01Request<'d> {
+	/// [`Report01Request::RegionWrite`], if `chunk` fits
+	pub fn region_write(region: u8, offset: u32, chunk: &'d [u8]) -> Result<Self> {
+		check_payload(Self::ID, chunk, REGION_CHUNK_SIZE)?;
+		Ok(Self::RegionWrite {
+			region,
+			offset,
+			chunk,
+		})
+	}
+	/// Inverse of [`Report01Request::encode`], what device does with request
+	pub fn decode(report: &'d [u8]) -> Result<Self> {
+		check_id(report, Self::ID)?;
+		let wrong_size = |size| Error::WrongSize {
+			id: Self::ID,
+			size,
+			report: report.to_vec(),
+		};
+		let header = report.get(1..8).ok_or_else(|| wrong_size(0))?;
+		let (op, region, len) = (header[0], header[1], header[2] as usize);
+		let value = u32::from_le_bytes(header[3..7].try_into().expect("4 bytes"));
+		Ok(match op {
+			REGION_SIZE => Self::RegionSize { region },
+			REGION_READ => Self::RegionRead {
+				region,
+				offset: value,
+			},
+			REGION_WRITE => Self::RegionWrite {
+				region,
+				offset: value,
+				chunk: report
+					.get(8..8 + len)
+					.filter(|_| len <= REGION_CHUNK_SIZE)
+					.ok_or_else(|| wrong_size(len))?,
+			},
+			REGION_COMMIT => Self::RegionCommit { region, len: value },
+			op => return Err(Error::UnknownOp { id: Self::ID, op }),
+		})
+	}
+}
+
+/// Report 0x02, textual commands/register reads, i.e `mfg-r-devsn`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report02Command<'d>(pub &'d [u8]);
+impl Report02Command<'_> {
+	pub const ID: u8 = 0x02;
+	/// The only `mfg-r-*` read seen in captures of VIVE Console
+	pub const DEVSN: Report02Command<'static> = Report02Command(b"mfg-r-devsn");
+	/// Mainboard serial. Unverified, as every read below: names are guessed after
+	/// [`Self::DEVSN`], and no capture shows them answered, firmware may not know them, or
+	/// answer them with something else
+	pub const PCBSN: Report02Command<'static> = Report02Command(b"mfg-r-pcbsn");
+	/// Unverified, see [`Self::PCBSN`]
+	pub const MODEL: Report02Command<'static> = Report02Command(b"mfg-r-model");
+	/// Market headset was made for, unverified, see [`Self::PCBSN`]
+	pub const REGION: Report02Command<'static> = Report02Command(b"mfg-r-region");
+	/// Unverified, see [`Self::PCBSN`]
+	pub const PANEL_SN: Report02Command<'static> = Report02Command(b"mfg-r-panelsn");
+	/// Unverified, see [`Self::PCBSN`]
+	pub const PANEL_DATE: Report02Command<'static> = Report02Command(b"mfg-r-paneldate");
+	/// Assumed to be seconds since power on, decimal, unverified, see [`Self::PCBSN`]
+	pub const UPTIME: Report02Command<'static> = Report02Command(b"mfg-r-uptime");
+	/// Assumed to be lifetime hours powered on, decimal, unverified, see [`Self::PCBSN`]
+	pub const ONTIME: Report02Command<'static> = Report02Command(b"mfg-r-ontime");
+	/// Assumed to be lifetime power on count, decimal, unverified, see [`Self::PCBSN`]
+	pub const BOOTCNT: Report02Command<'static> = Report02Command(b"mfg-r-bootcnt");
+	/// Everything after report id
+	pub const MAX_LEN: usize = REPORT_SIZE - 1;
+
+	/// Fails with [`Error::PayloadTooLarge`] if command is longer than [`Self::MAX_LEN`]
+	pub fn encode(&self) -> Result<Report> {
+		encode_report(Self::ID, self.0)
+	}
+	/// Reply has no prefix, only length
+	pub fn decode_reply<'r>(&self, report: &'r [u8]) -> Result<&'r [u8]> {
+		decode_report(report, Self::ID, &[])
+	}
+	/// Payload is at most 62 bytes
+	pub fn encode_reply(payload: &[u8]) -> Result<Report> {
+		encode_reply(Self::ID, &[], payload)
+	}
+}
+impl<'d> Report02Command<'d> {
+	/// Command, if it fits into report
+	pub fn new(text: &'d [u8]) -> Result<Self> {
+		check_payload(Self::ID, text, Self::MAX_LEN)?;
+		Ok(Self(text))
+	}
+	/// Command has no length, it ends at the first zero byte, or with the report
+	pub fn decode(report: &'d [u8]) -> Result<Self> {
+		check_id(report, Self::ID)?;
+		let text = &report[1..];
+		Ok(Self(
+			&text[..text.iter().position(|&b| b == 0).unwrap_or(text.len())],
+		))
+	}
+}
+
+/// Feature report 0x04, which carries textual commands for different subsystems
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeatureReport04<'d> {
+	pub sub_id: u16,
+	pub data: &'d [u8],
+}
+impl<'d> FeatureReport04<'d> {
+	pub const ID: u8 = 0x04;
+	/// Display/mainboard control: mode, brightness, resets
+	pub const SUB_ID_CONTROL: u16 = 0x2970;
+	/// Audio codec registers
+	pub const SUB_ID_CODEC: u16 = 0x2971;
+	/// Id, sub-id and size take 4 bytes
+	pub const MAX_LEN: usize = REPORT_SIZE - 4;
+
+	/// Report, if `data` is at most [`Self::MAX_LEN`] bytes
+	pub fn new(sub_id: u16, data: &'d [u8]) -> Result<Self> {
+		check_payload(Self::ID, data, Self::MAX_LEN)?;
+		Ok(Self { sub_id, data })
+	}
+	pub fn control(data: &'d [u8]) -> Result<Self> {
+		Self::new(Self::SUB_ID_CONTROL, data)
+	}
+	pub fn codec(data: &'d [u8]) -> Result<Self> {
+		Self::new(Self::SUB_ID_CODEC, data)
+	}
+	/// Fails with [`Error::PayloadTooLarge`] if `data` doesn't fit
+	pub fn encode(&self) -> Result<Report> {
+		encode_feature_report(Self::ID, self.sub_id, self.data)
+	}
+	pub fn decode(report: &'d [u8]) -> Result<Self> {
+		check_id(report, Self::ID)?;
+		let wrong_size = |size| Error::WrongSize {
+			id: Self::ID,
+			size,
+			report: report.to_vec(),
+		};
+		let header = report.get(1..4).ok_or_else(|| wrong_size(0))?;
+		let len = header[2] as usize;
+		Ok(Self {
+			sub_id: u16::from_le_bytes([header[0], header[1]]),
+			data: report.get(4..4 + len).ok_or_else(|| wrong_size(len))?,
+		})
+	}
+}
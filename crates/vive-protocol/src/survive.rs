@@ -0,0 +1,62 @@
+//! Tracking calibration, in the layout libsurvive reads from lighthouse devices, so headset may be
+//! tracked without SteamVR. Layout is the one Valve devices store, with everything display-related
+//! left out
+use serde_json::{json, Value};
+
+use crate::{ConfigPose, Error, Result, SteamConfig};
+
+fn pose(pose: &ConfigPose) -> Value {
+	json!({
+		"plus_x": pose.plus_x,
+		"plus_z": pose.plus_z,
+		"position": pose.position,
+	})
+}
+
+/// Fails if sensor positions or IMU mounting is missing, libsurvive can't track without them.
+/// IMU biases and scales are optional, libsurvive assumes ideal sensors otherwise
+pub fn libsurvive_config(config: &SteamConfig) -> Result<Value> {
+	let lighthouse = config
+		.lighthouse_config
+		.as_ref()
+		.ok_or(Error::MissingCalibration("lighthouse_config"))?;
+	if lighthouse.model_points.len() != lighthouse.model_normals.len() {
+		return Err(Error::MissingCalibration("normal for every sensor"));
+	}
+	let imu = config
+		.imu
+		.as_ref()
+		.ok_or(Error::MissingCalibration("imu"))?;
+
+	let mut imu_out = pose(&imu.pose);
+	for (key, value) in [
+		("acc_bias", imu.acc_bias),
+		("acc_scale", imu.acc_scale),
+		("gyro_bias", imu.gyro_bias),
+		("gyro_scale", imu.gyro_scale),
+	] {
+		if let Some(value) = value {
+			imu_out[key] = json!(value);
+		}
+	}
+	let mut lighthouse_out = json!({
+		"modelPoints": lighthouse.model_points,
+		"modelNormals": lighthouse.model_normals,
+	});
+	if !lighthouse.channel_map.is_empty() {
+		lighthouse_out["channelMap"] = json!(lighthouse.channel_map);
+	}
+	let mut out = json!({
+		"device_class": config.device_class.as_deref().unwrap_or("hmd"),
+		"mb_serial_number": config.mb_serial_number,
+		"lighthouse_config": lighthouse_out,
+		"imu": imu_out,
+	});
+	if let Some(serial) = &config.device_serial_number {
+		out["device_serial_number"] = json!(serial);
+	}
+	if let Some(head) = &config.head {
+		out["head"] = pose(head);
+	}
+	Ok(out)
+}
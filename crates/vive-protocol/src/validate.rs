@@ -0,0 +1,241 @@
+//! Schema check, which reports every problem at once, unlike serde, which stops at the first one
+
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::{parse_json, SteamConfig, ViveConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+	Object,
+	Array,
+	String,
+	Number,
+	Integer,
+	Bool,
+	Any,
+}
+impl FieldKind {
+	fn matches(self, v: &Value) -> bool {
+		match self {
+			Self::Object => v.is_object(),
+			Self::Array => v.is_array(),
+			Self::String => v.is_string(),
+			Self::Number => v.is_number(),
+			Self::Integer => v.is_u64() || v.is_i64(),
+			Self::Bool => v.is_boolean(),
+			Self::Any => true,
+		}
+	}
+	fn of(v: &Value) -> &'static str {
+		match v {
+			Value::Null => "null",
+			Value::Bool(_) => "bool",
+			Value::Number(n) if n.is_f64() => "number",
+			Value::Number(_) => "integer",
+			Value::String(_) => "string",
+			Value::Array(_) => "array",
+			Value::Object(_) => "object",
+		}
+	}
+}
+impl fmt::Display for FieldKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let name = match self {
+			Self::Object => "object",
+			Self::Array => "array",
+			Self::String => "string",
+			Self::Number => "number",
+			Self::Integer => "integer",
+			Self::Bool => "bool",
+			Self::Any => "any",
+		};
+		f.write_str(name)
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigIssue {
+	Missing {
+		path: String,
+		expected: FieldKind,
+	},
+	WrongType {
+		path: String,
+		expected: FieldKind,
+		actual: &'static str,
+	},
+	/// Problem, which isn't covered by schema, but found by typed parsing
+	Invalid {
+		message: String,
+	},
+}
+impl fmt::Display for ConfigIssue {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Missing { path, expected } => write!(f, "{path}: missing, expected {expected}"),
+			Self::WrongType {
+				path,
+				expected,
+				actual,
+			} => write!(f, "{path}: expected {expected}, got {actual}"),
+			Self::Invalid { message } => f.write_str(message),
+		}
+	}
+}
+
+/// `path` is dot-separated, `[]` suffix applies rest of the path to every array element.
+/// Fields are only checked if their parent is present
+struct Field(&'static str, FieldKind, bool);
+
+use FieldKind as K;
+const REQUIRED: bool = true;
+const OPTIONAL: bool = false;
+
+const VIVE_SCHEMA: &[Field] = &[
+	Field("device", K::Object, REQUIRED),
+	Field("device.eye_target_height_in_pixels", K::Integer, REQUIRED),
+	Field("device.eye_target_width_in_pixels", K::Integer, REQUIRED),
+	Field("direct_mode_edid_pid", K::Integer, OPTIONAL),
+	Field("direct_mode_edid_vid", K::Integer, OPTIONAL),
+	Field("seconds_from_photons_to_vblank", K::Number, OPTIONAL),
+	Field("seconds_from_vsync_to_photons", K::Number, OPTIONAL),
+	Field("inhouse_lens_correction", K::Object, REQUIRED),
+	Field("inhouse_lens_correction.left", K::Object, REQUIRED),
+	Field("inhouse_lens_correction.right", K::Object, REQUIRED),
+	Field("panel", K::Object, OPTIONAL),
+	Field("panel.vendor", K::String, OPTIONAL),
+	Field("panel.model", K::String, OPTIONAL),
+	Field("panel.serial_number", K::String, OPTIONAL),
+	Field("panel.manufacture_date", K::String, OPTIONAL),
+	Field("lens", K::Object, OPTIONAL),
+];
+
+const STEAM_SCHEMA: &[Field] = &[
+	Field("device", K::Object, REQUIRED),
+	Field("device.eye_target_height_in_pixels", K::Integer, REQUIRED),
+	Field("device.eye_target_width_in_pixels", K::Integer, REQUIRED),
+	Field("mb_serial_number", K::String, REQUIRED),
+	Field("direct_mode_edid_pid", K::Integer, OPTIONAL),
+	Field("direct_mode_edid_vid", K::Integer, OPTIONAL),
+	Field("seconds_from_photons_to_vblank", K::Number, OPTIONAL),
+	Field("seconds_from_vsync_to_photons", K::Number, OPTIONAL),
+	Field("tracked_cameras", K::Array, OPTIONAL),
+	Field("tracked_cameras[].name", K::String, REQUIRED),
+	Field("tracked_cameras[].intrinsics", K::Object, REQUIRED),
+	Field("tracked_cameras[].intrinsics.width", K::Integer, REQUIRED),
+	Field("tracked_cameras[].intrinsics.height", K::Integer, REQUIRED),
+	Field("tracked_cameras[].intrinsics.distort", K::Object, REQUIRED),
+	Field("tracked_cameras[].extrinsics", K::Array, REQUIRED),
+	Field("head", K::Object, OPTIONAL),
+	Field("head.plus_x", K::Array, REQUIRED),
+	Field("head.plus_z", K::Array, REQUIRED),
+	Field("head.position", K::Array, REQUIRED),
+	Field("imu", K::Object, OPTIONAL),
+	Field("imu.plus_x", K::Array, REQUIRED),
+	Field("imu.plus_z", K::Array, REQUIRED),
+	Field("imu.position", K::Array, REQUIRED),
+	Field("imu.acc_bias", K::Array, OPTIONAL),
+	Field("imu.acc_scale", K::Array, OPTIONAL),
+	Field("imu.gyro_bias", K::Array, OPTIONAL),
+	Field("imu.gyro_scale", K::Array, OPTIONAL),
+	Field("lighthouse_config", K::Object, OPTIONAL),
+	Field("lighthouse_config.modelPoints", K::Array, REQUIRED),
+	Field("lighthouse_config.modelNormals", K::Array, REQUIRED),
+	Field("lighthouse_config.channelMap", K::Array, OPTIONAL),
+	Field("tracking_to_eye_transform", K::Array, OPTIONAL),
+	Field(
+		"tracking_to_eye_transform[].eye_to_head",
+		K::Array,
+		REQUIRED,
+	),
+	Field("audio", K::Object, OPTIONAL),
+	Field("camera", K::Object, OPTIONAL),
+];
+
+fn check(root: &Value, schema: &[Field], out: &mut Vec<ConfigIssue>) {
+	for Field(path, kind, required) in schema {
+		// Parents of checked field
+		let mut parents = vec![("$".to_owned(), root)];
+		let mut segments = path.split('.').peekable();
+		while let Some(segment) = segments.next() {
+			let (name, each) = match segment.strip_suffix("[]") {
+				Some(name) => (name, true),
+				None => (segment, false),
+			};
+			if segments.peek().is_none() {
+				for (parent_path, parent) in &parents {
+					let Some(parent) = parent.as_object() else {
+						continue;
+					};
+					let path = format!("{parent_path}.{name}");
+					match parent.get(name) {
+						None if *required => out.push(ConfigIssue::Missing {
+							path,
+							expected: *kind,
+						}),
+						Some(v) if !kind.matches(v) => out.push(ConfigIssue::WrongType {
+							path,
+							expected: *kind,
+							actual: FieldKind::of(v),
+						}),
+						_ => {}
+					}
+				}
+				break;
+			}
+			let mut next = Vec::new();
+			for (parent_path, parent) in parents {
+				let Some(v) = parent.get(name) else {
+					continue;
+				};
+				let path = format!("{parent_path}.{name}");
+				match (each, v) {
+					(true, Value::Array(items)) => {
+						next.extend(
+							items
+								.iter()
+								.enumerate()
+								.map(|(i, item)| (format!("{path}[{i}]"), item)),
+						);
+					}
+					(false, v) => next.push((path, v)),
+					_ => {}
+				}
+			}
+			parents = next;
+		}
+	}
+}
+
+fn validate<T: serde::de::DeserializeOwned>(json: &str, schema: &[Field]) -> Vec<ConfigIssue> {
+	let root: Value = match serde_json::from_str(json) {
+		Ok(v) => v,
+		Err(e) => {
+			return vec![ConfigIssue::Invalid {
+				message: format!("not a valid JSON: {e}"),
+			}]
+		}
+	};
+	let mut out = Vec::new();
+	check(&root, schema, &mut out);
+	// Schema only covers fields driver relies on, typed parsing catches everything else
+	if out.is_empty() {
+		if let Err(e) = parse_json::<T>(json) {
+			out.push(ConfigIssue::Invalid {
+				message: e.to_string(),
+			});
+		}
+	}
+	out
+}
+
+/// Returns every problem in factory config JSON, empty if it is usable
+pub fn validate_vive_config(json: &str) -> Vec<ConfigIssue> {
+	validate::<ViveConfig>(json, VIVE_SCHEMA)
+}
+/// Returns every problem in steam config JSON, empty if it is usable
+pub fn validate_steam_config(json: &str) -> Vec<ConfigIssue> {
+	validate::<SteamConfig>(json, STEAM_SCHEMA)
+}
@@ -0,0 +1,151 @@
+//! Config blobs in `tests/fixtures`, as they are read from device regions, see README there
+use std::{fs, path::Path};
+
+use vive_protocol::{
+	parse_config_dump, parse_steam_config, parse_vive_config, validate_steam_config,
+	validate_vive_config, ConfigHeader, ConfigVersion, Error, SteamConfig, ViveConfig,
+	KNOWN_CONFIG_VERSION,
+};
+
+fn fixture(name: &str) -> Vec<u8> {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("tests/fixtures")
+		.join(name);
+	fs::read(&path).unwrap_or_else(|e| panic!("failed to read {path:?}: {e}"))
+}
+
+fn vive(name: &str) -> ViveConfig {
+	let blob = fixture(name);
+	let config = parse_vive_config(&blob).unwrap_or_else(|e| panic!("{name}: {e}"));
+	// Schema check should agree with typed parsing
+	let dump = parse_config_dump(&blob).expect("dump");
+	let issues = validate_vive_config(&dump.to_string());
+	assert!(issues.is_empty(), "{name}: {issues:?}");
+	assert_eq!(config.device.eye_target_width_in_pixels, 2448);
+	assert_eq!(config.device.eye_target_height_in_pixels, 2448);
+	assert!(config.inhouse_lens_correction["left"].is_object());
+	assert!(config.inhouse_lens_correction["right"].is_object());
+	config
+}
+
+fn steam(name: &str) -> SteamConfig {
+	let blob = fixture(name);
+	let config = parse_steam_config(&blob).unwrap_or_else(|e| panic!("{name}: {e}"));
+	let dump = parse_config_dump(&blob).expect("dump");
+	let issues = validate_steam_config(&dump.to_string());
+	assert!(issues.is_empty(), "{name}: {issues:?}");
+	assert_eq!(config.mb_serial_number, "XXXXXXXXXXXX");
+	config
+}
+
+#[test]
+fn vive_unversioned() {
+	let config = vive("vive-unversioned.bin");
+	// Treated as the first schema
+	assert_eq!(config.version, ConfigVersion(1));
+	assert_eq!(config.direct_mode_edid_vid, Some(53006));
+	assert_eq!(config.seconds_from_vsync_to_photons, Some(0.0111));
+	assert!(config.panel.is_none());
+	assert!(config.extra.is_empty());
+}
+
+#[test]
+fn vive_padded_crlf() {
+	let config = vive("vive-v1-padded.bin");
+	assert_eq!(config.version, KNOWN_CONFIG_VERSION);
+	#[cfg(feature = "unverified-mura")]
+	{
+		let mura = config.mura_correction.expect("mura correction");
+		let right = mura.right.expect("right panel");
+		assert_eq!((right.offset, right.size), (4096, 4096));
+		assert_eq!(right.format.as_deref(), Some("u8"));
+	}
+	#[cfg(not(feature = "unverified-mura"))]
+	assert!(config.extra.contains_key("mura_correction"));
+	let panel = config.panel.expect("panel");
+	assert_eq!(panel.model.as_deref(), Some("PANEL-2448"));
+	assert_eq!(config.lens.and_then(|l| l.version).as_deref(), Some("2"));
+
+	// Padding is not a part of body, and is not hashed
+	let blob = fixture("vive-v1-padded.bin");
+	let (header, body) = ConfigHeader::parse(&blob).expect("header");
+	assert_eq!(header.len(), 256);
+	assert_eq!(header.body_len_offset, Some(40));
+	assert!(header.hash_verified());
+	assert_eq!(body.last(), Some(&b'}'));
+}
+
+#[test]
+fn vive_without_hash() {
+	let config = vive("vive-no-hash.bin");
+	assert_eq!(config.version, ConfigVersion(1));
+	let (header, _) = ConfigHeader::parse(&fixture("vive-no-hash.bin")).expect("header");
+	assert!(!header.hash_verified());
+	assert!(header.body_len.is_some());
+	assert_eq!(header.identity.as_deref(), Some("VIVEPRO2CFG"));
+}
+
+#[test]
+fn vive_newer_version() {
+	let config = vive("vive-v2-unknown-fields.bin");
+	assert!(!config.version.is_known());
+	// Unknown fields are kept, so they survive dump and diff
+	assert!(config.extra.contains_key("eye_tracking"));
+	assert!(config.extra.contains_key("display_calibration"));
+}
+
+#[test]
+fn vive_truncated() {
+	let blob = fixture("vive-truncated.bin");
+	assert!(matches!(
+		parse_vive_config(&blob),
+		Err(Error::ConfigParse { .. })
+	));
+}
+
+#[test]
+fn steam_minimal() {
+	let config = steam("steam-minimal.bin");
+	assert_eq!(config.version, ConfigVersion(1));
+	assert!(config.tracked_cameras.is_empty());
+	assert!(config.imu.is_none() && config.lighthouse_config.is_none());
+	// Missing, not zero
+	assert_eq!(config.direct_mode_edid(), None);
+	assert_eq!(config.seconds_from_photons_to_vblank, None);
+}
+
+#[test]
+fn steam_tracking() {
+	let config = steam("steam-v1-tracking.bin");
+	assert_eq!(config.device_serial_number.as_deref(), Some("LHR-XXXXXXXX"));
+	assert_eq!(config.device_class.as_deref(), Some("hmd"));
+	assert!(config.direct_mode_edid().is_some());
+	assert_eq!(config.seconds_from_photons_to_vblank, None);
+	let imu = config.imu.expect("imu");
+	assert_eq!(imu.pose.position, [0.0, 0.02, 0.03]);
+	assert_eq!(imu.gyro_bias, Some([0.0, 0.0, 0.001]));
+	let lighthouse = config.lighthouse_config.expect("lighthouse config");
+	assert_eq!(lighthouse.model_points.len(), 3);
+	assert_eq!(lighthouse.model_normals.len(), 3);
+	assert_eq!(lighthouse.channel_map, [0, 1, 2]);
+	assert_eq!(config.tracking_to_eye_transform.len(), 2);
+	assert_eq!(
+		config.tracking_to_eye_transform[0].eye_to_head[0][3],
+		-0.032
+	);
+	assert_eq!(config.tracked_cameras.len(), 2);
+	assert_eq!(config.tracked_cameras[1].intrinsics.width, 640);
+	let camera = config.camera.expect("camera");
+	assert_eq!(camera.frame_rate, Some(60));
+	assert!(camera.extra.contains_key("auto_exposure"));
+	let audio = config.audio.expect("audio");
+	assert_eq!(audio.microphone_gain, Some(0.5));
+	assert!(audio.extra.contains_key("speaker_gain"));
+}
+
+#[test]
+fn steam_config_version_alias() {
+	let config = steam("steam-config-version-alias.bin");
+	assert_eq!(config.version, ConfigVersion(2));
+	assert!(!config.version.is_known());
+}
@@ -0,0 +1,12 @@
+//! GPU classes, which pick mode for first-time users, together with link, see
+//! `vive_hid::recommend_resolution`
+use vive_protocol::GpuTier;
+
+#[test]
+fn gpu_tiers() {
+	assert_eq!(GpuTier::from_vram(4 << 30), GpuTier::Low);
+	assert_eq!(GpuTier::from_vram(8 << 30), GpuTier::Mid);
+	assert_eq!(GpuTier::from_vram(16 << 30), GpuTier::High);
+	assert_eq!("mid".parse::<GpuTier>().expect("tier"), GpuTier::Mid);
+	assert!("huge".parse::<GpuTier>().is_err());
+}
@@ -0,0 +1,437 @@
+//! Round-trip properties of the report layer: what one side encodes, the other parses back
+//!
+//! Every payload size up to one past the limit is checked, as that's where slicing goes wrong,
+//! contents come from a seeded generator, so failures can be reproduced, and are shrunk
+use vive_protocol::{
+	decode_mainboard_status, decode_steam_config_chunk, encode_mainboard_status, hexdump, Error,
+	FeatureReport04, MainboardStatus, Report01Request, Report02Command, REGION_CHUNK_SIZE,
+	REPORT_SIZE,
+};
+
+/// Per payload size
+const CASES: usize = 64;
+/// Passes over draws of a failing case, each one lowering what it can
+const SHRINK_PASSES: usize = 4;
+
+/// Property test input, xorshift64* draws, or replayed ones while a failure is shrunk.
+///
+/// proptest and quickcheck aren't dependencies, this keeps the part of them that matters here:
+/// seeded cases, and failures shrunk to simpler inputs. Shrinking works on the draws, not on the
+/// values made of them, same as in hypothesis, so every generated value shrinks towards zero
+struct Gen {
+	state: u64,
+	replay: Option<Vec<u64>>,
+	draws: Vec<u64>,
+}
+impl Gen {
+	fn new(seed: u64) -> Self {
+		Self {
+			state: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1,
+			replay: None,
+			draws: Vec::new(),
+		}
+	}
+	/// Same draws, then zeroes once they run out
+	fn replay(draws: Vec<u64>) -> Self {
+		Self {
+			state: 0,
+			replay: Some(draws),
+			draws: Vec::new(),
+		}
+	}
+	/// Kept masked, so lower draw is lower value
+	fn draw(&mut self, mask: u64) -> u64 {
+		let v = match &self.replay {
+			Some(draws) => draws.get(self.draws.len()).copied().unwrap_or(0),
+			None => {
+				self.state ^= self.state >> 12;
+				self.state ^= self.state << 25;
+				self.state ^= self.state >> 27;
+				self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+			}
+		} & mask;
+		self.draws.push(v);
+		v
+	}
+	fn u8(&mut self) -> u8 {
+		self.draw(u8::MAX.into()) as u8
+	}
+	fn u16(&mut self) -> u16 {
+		self.draw(u16::MAX.into()) as u16
+	}
+	fn u32(&mut self) -> u32 {
+		self.draw(u32::MAX.into()) as u32
+	}
+	fn bytes(&mut self, len: usize) -> Vec<u8> {
+		(0..len).map(|_| self.u8()).collect()
+	}
+	/// Textual commands end at the first zero
+	fn text(&mut self, len: usize) -> Vec<u8> {
+		(0..len).map(|_| self.u8().max(1)).collect()
+	}
+}
+
+fn fails(check: &mut impl FnMut(&mut Gen, usize), gen: &mut Gen, size: usize) -> bool {
+	std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| check(gen, size))).is_err()
+}
+
+/// Draws which still fail, each one lowered by bisection, as far as it still fails
+fn shrink(check: &mut impl FnMut(&mut Gen, usize), size: usize, mut draws: Vec<u64>) -> Vec<u64> {
+	for _ in 0..SHRINK_PASSES {
+		let before = draws.clone();
+		let mut i = 0;
+		while i < draws.len() {
+			let (mut low, mut high) = (0, draws[i]);
+			while low < high {
+				let mid = low + (high - low) / 2;
+				let mut attempt = draws.clone();
+				attempt[i] = mid;
+				let mut gen = Gen::replay(attempt);
+				if fails(check, &mut gen, size) {
+					// Lower value may take a different path, with other draws after it
+					draws = gen.draws;
+					high = mid;
+				} else {
+					low = mid + 1;
+				}
+			}
+			i += 1;
+		}
+		if draws == before {
+			break;
+		}
+	}
+	draws
+}
+
+/// Runs `check` for every size in `sizes`, `CASES` times. Failing case is shrunk, and run once
+/// more, so the test fails with the simplest input found, seed is printed to reproduce the original
+fn for_sizes(sizes: impl Iterator<Item = usize>, mut check: impl FnMut(&mut Gen, usize)) {
+	for size in sizes {
+		for case in 0..CASES {
+			let seed = (size * CASES + case) as u64;
+			let mut gen = Gen::new(seed);
+			if !fails(&mut check, &mut gen, size) {
+				continue;
+			}
+			let draws = shrink(&mut check, size, gen.draws);
+			eprintln!("failed for size {size}, seed {seed}, shrunk to draws {draws:x?}");
+			check(&mut Gen::replay(draws), size);
+			panic!("size {size}, seed {seed} doesn't fail once shrunk");
+		}
+	}
+}
+
+fn requests(gen: &mut Gen, chunk: &[u8]) -> [Report01Request<'static>; 3] {
+	let region = gen.u8();
+	[
+		Report01Request::RegionSize { region },
+		Report01Request::RegionRead {
+			region,
+			offset: gen.u32(),
+		},
+		Report01Request::RegionCommit {
+			region,
+			len: gen.u32() ^ chunk.len() as u32,
+		},
+	]
+}
+
+fn too_large<T: std::fmt::Debug>(res: Result<T, Error>, id: u8, size: usize, max: usize) {
+	match res {
+		Err(Error::PayloadTooLarge {
+			id: actual_id,
+			size: actual_size,
+			max: actual_max,
+		}) => assert_eq!((actual_id, actual_size, actual_max), (id, size, max)),
+		other => panic!("expected payload to be too large, got {other:?}"),
+	}
+}
+
+#[test]
+fn region_requests() {
+	for_sizes(0..=REGION_CHUNK_SIZE + 1, |gen, size| {
+		let chunk = gen.bytes(size);
+		let (region, offset) = (gen.u8(), gen.u32());
+		// Constructed directly, so encode has to check it as well
+		let write = Report01Request::RegionWrite {
+			region,
+			offset,
+			chunk: &chunk,
+		};
+		if size > REGION_CHUNK_SIZE {
+			let id = Report01Request::ID;
+			too_large(write.encode(), id, size, REGION_CHUNK_SIZE);
+			too_large(
+				Report01Request::region_write(region, offset, &chunk),
+				id,
+				size,
+				REGION_CHUNK_SIZE,
+			);
+			return;
+		}
+		assert_eq!(
+			Report01Request::region_write(region, offset, &chunk).expect("chunk fits"),
+			write
+		);
+		for req in requests(gen, &chunk).into_iter().chain([write]) {
+			let report = req.encode().expect("encode");
+			assert_eq!(Report01Request::decode(&report).expect("decode"), req);
+		}
+	});
+}
+
+#[test]
+fn region_write_chunk_past_limit() {
+	let mut report = Report01Request::RegionWrite {
+		region: 0xb2,
+		offset: 0,
+		chunk: &[0xaa; REGION_CHUNK_SIZE],
+	}
+	.encode()
+	.expect("encode");
+	// Declared length, as if chunk was one byte longer
+	report[3] = REGION_CHUNK_SIZE as u8 + 1;
+	assert!(matches!(
+		Report01Request::decode(&report),
+		Err(Error::WrongSize { .. })
+	));
+}
+
+#[test]
+fn region_replies() {
+	// Id, op, region and size take 4 bytes
+	let max = REPORT_SIZE - 4;
+	for_sizes(0..=max + 1, |gen, size| {
+		let payload = gen.bytes(size);
+		for req in requests(gen, &payload) {
+			let reply = req.encode_reply(&payload);
+			if size > max {
+				too_large(reply, Report01Request::ID, size, max);
+				continue;
+			}
+			let reply = reply.expect("reply fits");
+			assert_eq!(req.decode_reply(&reply).expect("decode"), payload);
+		}
+	});
+}
+
+#[test]
+fn region_reply_from_other_request() {
+	let read = Report01Request::RegionRead {
+		region: 0xb1,
+		offset: 0,
+	};
+	let size = Report01Request::RegionSize { region: 0xb1 };
+	let reply = size.encode_reply(&[0, 1, 0, 0]).expect("reply");
+	assert!(matches!(
+		read.decode_reply(&reply),
+		Err(Error::WrongPrefix { .. })
+	));
+}
+
+#[test]
+fn commands() {
+	let max = Report02Command::MAX_LEN;
+	for_sizes(0..=max + 1, |gen, size| {
+		let text = gen.text(size);
+		if size > max {
+			too_large(
+				Report02Command(&text).encode(),
+				Report02Command::ID,
+				size,
+				max,
+			);
+			too_large(Report02Command::new(&text), Report02Command::ID, size, max);
+			return;
+		}
+		let report = Report02Command::new(&text)
+			.expect("command fits")
+			.encode()
+			.expect("encode");
+		assert_eq!(
+			Report02Command::decode(&report).expect("decode"),
+			Report02Command(&text)
+		);
+	});
+}
+
+#[test]
+fn command_replies() {
+	// Id and size
+	let max = REPORT_SIZE - 2;
+	for_sizes(0..=max + 1, |gen, size| {
+		let payload = gen.bytes(size);
+		let reply = Report02Command::encode_reply(&payload);
+		if size > max {
+			too_large(reply, Report02Command::ID, size, max);
+			return;
+		}
+		let reply = reply.expect("reply fits");
+		let decoded = Report02Command::DEVSN.decode_reply(&reply).expect("decode");
+		assert_eq!(decoded, payload);
+	});
+}
+
+#[test]
+fn feature_reports() {
+	let max = FeatureReport04::MAX_LEN;
+	for_sizes(0..=max + 1, |gen, size| {
+		let data = gen.bytes(size);
+		let report = FeatureReport04 {
+			sub_id: gen.u16(),
+			data: &data,
+		};
+		if size > max {
+			let id = FeatureReport04::ID;
+			too_large(report.encode(), id, size, max);
+			too_large(FeatureReport04::new(report.sub_id, &data), id, size, max);
+			too_large(FeatureReport04::control(&data), id, size, max);
+			return;
+		}
+		let encoded = report.encode().expect("encode");
+		assert_eq!(FeatureReport04::decode(&encoded).expect("decode"), report);
+	});
+}
+
+#[test]
+fn steam_config_chunks() {
+	let max = REPORT_SIZE - 2;
+	for_sizes(1..=max + 1, |gen, size| {
+		let mut report = [0u8; REPORT_SIZE];
+		report[0] = 17;
+		report[1] = size as u8;
+		let fits = size.min(max);
+		report[2..2 + fits].copy_from_slice(&gen.bytes(fits));
+		match decode_steam_config_chunk(&report) {
+			Ok(Some(chunk)) if size <= max => assert_eq!(chunk, &report[2..2 + size]),
+			Err(Error::WrongSize { size: actual, .. }) if size > max => assert_eq!(actual, size),
+			other => panic!("unexpected {other:?}"),
+		}
+	});
+	let mut last = [0u8; REPORT_SIZE];
+	last[0] = 17;
+	assert!(decode_steam_config_chunk(&last).expect("decode").is_none());
+}
+
+#[test]
+fn mainboard_status() {
+	for_sizes(0..1, |gen, _| {
+		let status = MainboardStatus {
+			lens_separation: gen.u16(),
+			button: gen.u8(),
+			proximity_change: gen.u8(),
+			proximity: gen.u16(),
+			ipd: gen.u16(),
+		};
+		let report = encode_mainboard_status(&status);
+		assert_eq!(decode_mainboard_status(&report), Some(status));
+	});
+}
+
+/// Short reads give short reports, none of them should make decoding panic
+#[test]
+fn truncated_reports() {
+	for_sizes(0..=REPORT_SIZE, |gen, len| {
+		let chunk = gen.bytes(REGION_CHUNK_SIZE);
+		let reports = [
+			Report01Request::RegionWrite {
+				region: gen.u8(),
+				offset: gen.u32(),
+				chunk: &chunk,
+			}
+			.encode()
+			.expect("encode"),
+			Report01Request::RegionSize { region: gen.u8() }
+				.encode_reply(&gen.bytes(4))
+				.expect("reply"),
+			Report02Command::encode_reply(&gen.bytes(REPORT_SIZE - 2)).expect("reply"),
+			FeatureReport04::control(&gen.bytes(FeatureReport04::MAX_LEN))
+				.and_then(|r| r.encode())
+				.expect("encode"),
+			encode_mainboard_status(&MainboardStatus {
+				lens_separation: gen.u16(),
+				button: 0,
+				proximity_change: 0,
+				proximity: gen.u16(),
+				ipd: gen.u16(),
+			}),
+		];
+		for report in &reports {
+			let report = &report[..len];
+			let _ = Report01Request::decode(report);
+			let _ = Report01Request::RegionSize { region: 0 }.decode_reply(report);
+			let _ = Report02Command::decode(report);
+			let _ = Report02Command::DEVSN.decode_reply(report);
+			let _ = FeatureReport04::decode(report);
+			let _ = decode_steam_config_chunk(report);
+			let _ = decode_mainboard_status(report);
+		}
+	});
+}
+
+/// Whatever headset sends, decoded reports encode back to reports which decode the same
+#[test]
+fn arbitrary_reports() {
+	let ops = [
+		Report01Request::RegionSize { region: 0 },
+		Report01Request::RegionRead {
+			region: 0,
+			offset: 0,
+		},
+		Report01Request::RegionWrite {
+			region: 0,
+			offset: 0,
+			chunk: &[],
+		},
+		Report01Request::RegionCommit { region: 0, len: 0 },
+	]
+	.map(|req| req.encode().expect("encode")[1]);
+	for_sizes(0..=REPORT_SIZE, |gen, len| {
+		let mut report = gen.bytes(len);
+		// Valid ids, ops and lengths are rare otherwise
+		if let Some(id) = report.first_mut() {
+			*id = [
+				Report01Request::ID,
+				Report02Command::ID,
+				FeatureReport04::ID,
+			][*id as usize % 3];
+		}
+		if let [Report01Request::ID, op, ..] = &mut report[..] {
+			*op = ops[*op as usize % ops.len()];
+		}
+		if let Some(size) = report.get_mut(3) {
+			*size %= REPORT_SIZE as u8;
+		}
+		if let Ok(req) = Report01Request::decode(&report) {
+			let encoded = req.encode().expect("decoded request encodes");
+			assert_eq!(Report01Request::decode(&encoded).expect("decode"), req);
+		}
+		if let Ok(command) = Report02Command::decode(&report) {
+			let encoded = command.encode().expect("decoded command encodes");
+			assert_eq!(Report02Command::decode(&encoded).expect("decode"), command);
+		}
+		if let Ok(feature) = FeatureReport04::decode(&report) {
+			let encoded = feature.encode().expect("decoded feature report encodes");
+			assert_eq!(FeatureReport04::decode(&encoded).expect("decode"), feature);
+		}
+	});
+}
+
+#[test]
+fn hexdump_of_reply() {
+	let reply = Report02Command::encode_reply(b"SIM000000000\x01").expect("reply");
+	let dump = hexdump(&reply);
+	let lines = dump.lines().collect::<Vec<_>>();
+	assert_eq!(lines.len(), REPORT_SIZE / 16);
+	assert_eq!(
+		lines[0],
+		"0000  02 0d 53 49 4d 30 30 30 30 30 30 30 30 30 01 00  |..SIM000000000..|"
+	);
+	assert!(lines[3].starts_with("0030  00 00"));
+	// Short last line is padded, so ascii column stays aligned
+	assert_eq!(
+		hexdump(&[0x41; 17]).lines().nth(1),
+		Some(format!("0010  41{}  |A|", " ".repeat(45)).as_str())
+	);
+}
@@ -12,7 +12,14 @@ echo "SteamVR proxy driver for Vive Pro 2"
 echo "Consider supporting developer on patreon: https://patreon.com/0lach"
 sleep 3
 
-STEAMVR="${STEAMVR:-$HOME/.local/share/Steam/steamapps/common/SteamVR}"
+if test -z "${STEAMVR:-}"; then
+	STEAMVR=$HOME/.local/share/Steam/steamapps/common/SteamVR
+	FLATPAK_STEAMVR=$HOME/.var/app/com.valvesoftware.Steam/.local/share/Steam/steamapps/common/SteamVR
+	if ! test -d "$STEAMVR" && test -d "$FLATPAK_STEAMVR"; then
+		echo "Using SteamVR of Flatpak Steam, run vivectl setup flatpak to let driver reach its settings"
+		STEAMVR=$FLATPAK_STEAMVR
+	fi
+fi
 if ! test -d "$STEAMVR"; then
 	echo "SteamVR not found at $STEAMVR (Set \$STEAMVR manually?)"
 	exit 1
@@ -46,4 +53,7 @@ rsync -a "$SCRIPTPATH/driver_lighthouse.so" "$LIGHTHOUSE_DRIVER/driver_lighthous
 echo "= Updating proxy server"
 rsync -ar "$SCRIPTPATH/lens-server/" "$LIGHTHOUSE_DRIVER/lens-server"
 
+echo "= Installing settings schema"
+rsync -a "$SCRIPTPATH/resources/settings/vivepro2.schema.json" "$STEAMVR/drivers/lighthouse/resources/settings/"
+
 echo "Installation finished, try to start SteamVR"